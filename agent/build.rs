@@ -20,6 +20,15 @@ fn generate_protobuf() -> Result<(), Box<dyn Error>> {
     tonic_build::configure()
         .build_server(false)
         .out_dir("src/proto")
+        // 用于dump-config输出控制器下发的配置
+        .type_attribute(
+            "trident.Config",
+            "#[derive(serde::Serialize)] #[serde(rename_all = \"kebab-case\")]",
+        )
+        .type_attribute(
+            "trident.ConfigLayer",
+            "#[derive(serde::Serialize)] #[serde(rename_all = \"kebab-case\")]",
+        )
         .compile(
             &[
                 "../message/common.proto",
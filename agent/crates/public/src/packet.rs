@@ -23,6 +23,8 @@ pub struct Packet<'a> {
     pub if_index: isize,
     pub capture_length: isize,
     pub data: &'a mut [u8],
+    // timestamp为网卡硬件时间戳
+    pub hw_timestamp: bool,
 }
 
 #[cfg(target_os = "windows")]
@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 从resources/test下的pcap生成初始corpus:
+//   cargo run --example seed_corpus
+// 所有帧写入corpus/meta_packet，协议目录下pcap的l4 payload写入corpus/<协议>
+use std::{
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use deepflow_agent::_meta_packet_update;
+
+const RESOURCES_DIR: &str = "../resources/test";
+const CORPUS_DIR: &str = "corpus";
+const META_PACKET_TARGET: &str = "meta_packet";
+// 与fuzz target同名的pcap目录
+const PROTOCOL_TARGETS: [&str; 7] = ["dns", "dubbo", "http", "kafka", "mqtt", "mysql", "redis"];
+
+const PCAP_GLOBAL_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+
+// 只支持以太网链路类型的pcap，不支持pcapng
+fn read_pcap(path: &Path) -> Result<Vec<Vec<u8>>> {
+    let data = fs::read(path)?;
+    if data.len() < PCAP_GLOBAL_HEADER_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, "pcap too short"));
+    }
+    let magic = u32::from_le_bytes(data[..4].try_into().unwrap());
+    let big_endian = match magic {
+        PCAP_MAGIC | PCAP_MAGIC_NS => false,
+        _ if magic.swap_bytes() == PCAP_MAGIC || magic.swap_bytes() == PCAP_MAGIC_NS => true,
+        _ => return Err(Error::new(ErrorKind::InvalidData, "not a pcap file")),
+    };
+    let read_u32 = |bs: &[u8]| {
+        let bs = bs[..4].try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(bs)
+        } else {
+            u32::from_le_bytes(bs)
+        }
+    };
+
+    let mut frames = vec![];
+    let mut offset = PCAP_GLOBAL_HEADER_LEN;
+    while offset + PCAP_RECORD_HEADER_LEN <= data.len() {
+        // ts_sec, ts_usec, incl_len, orig_len
+        let incl_len = read_u32(&data[offset + 8..]) as usize;
+        offset += PCAP_RECORD_HEADER_LEN;
+        if offset + incl_len > data.len() {
+            break;
+        }
+        frames.push(data[offset..offset + incl_len].to_vec());
+        offset += incl_len;
+    }
+    Ok(frames)
+}
+
+fn find_pcaps(dir: &Path, pcaps: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_pcaps(&path, pcaps)?;
+        } else if path.extension().map(|e| e == "pcap").unwrap_or_default() {
+            pcaps.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut pcaps = vec![];
+    find_pcaps(Path::new(RESOURCES_DIR), &mut pcaps)?;
+    pcaps.sort();
+
+    let mut count = 0;
+    for pcap in pcaps.iter() {
+        let frames = match read_pcap(pcap) {
+            Ok(frames) => frames,
+            Err(e) => {
+                eprintln!("skip {}: {}", pcap.display(), e);
+                continue;
+            }
+        };
+        let stem = pcap.file_stem().unwrap().to_string_lossy();
+        let protocol = pcap
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .filter(|n| PROTOCOL_TARGETS.contains(&n.as_str()));
+
+        for (i, frame) in frames.iter().enumerate() {
+            let name = format!("{}-{}", stem, i);
+            let dir = Path::new(CORPUS_DIR).join(META_PACKET_TARGET);
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(&name), frame)?;
+            count += 1;
+
+            let protocol = match protocol.as_ref() {
+                Some(p) => p,
+                None => continue,
+            };
+            if let Some(payload) = _meta_packet_update(frame).filter(|p| !p.is_empty()) {
+                let dir = Path::new(CORPUS_DIR).join(protocol);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join(&name), payload)?;
+                count += 1;
+            }
+        }
+    }
+    println!(
+        "{} seeds from {} pcaps written to {}",
+        count,
+        pcaps.len(),
+        CORPUS_DIR
+    );
+    Ok(())
+}
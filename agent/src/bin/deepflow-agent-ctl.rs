@@ -29,8 +29,9 @@ use clap::{ArgEnum, Parser, Subcommand};
 #[cfg(target_os = "linux")]
 use deepflow_agent::debug::PlatformMessage;
 use deepflow_agent::debug::{
-    Beacon, Client, Message, Module, QueueMessage, RpcMessage, BEACON_PORT,
-    DEBUG_QUEUE_IDLE_TIMEOUT, DEEPFLOW_AGENT_BEACON,
+    Beacon, Client, FlowMessage, FlowQuery, L7LogFilter, L7LogMessage, Message, Module,
+    QueueMessage, RpcMessage, BEACON_PORT, DEBUG_QUEUE_IDLE_TIMEOUT, DEEPFLOW_AGENT_BEACON,
+    FLOW_QUERY_DEFAULT_MAX_BYTES,
 };
 
 const ERR_PORT_MSG: &str = "error: The following required arguments were not provided:
@@ -60,6 +61,10 @@ enum ControllerCmd {
     Platform(PlatformCmd),
     /// monitor various queues of the selected deepflow-agent
     Queue(QueueCmd),
+    /// search recent l7 session logs cached by the selected deepflow-agent
+    L7Log(L7LogCmd),
+    /// export a snapshot of the flow tables of all dispatchers
+    Flow(FlowCmd),
     /// get connection information of all deepflow-agents managed under this controller
     List,
 }
@@ -95,6 +100,50 @@ struct QueueCmd {
     clear: bool,
 }
 
+#[derive(Parser)]
+struct L7LogCmd {
+    /// match logs whose client or server ip equals this ip
+    ///
+    /// eg: deepflow-agent-ctl -p 42700 l7-log --ip 10.1.2.3 --port 443
+    #[clap(long, parse(try_from_str))]
+    ip: Option<IpAddr>,
+    /// match logs whose client or server port equals this port
+    #[clap(long)]
+    port: Option<u16>,
+    /// match l7 protocol, case insensitive
+    ///
+    /// eg: http1, http2, dns, mysql, redis, dubbo, kafka, mqtt
+    #[clap(long)]
+    protocol: Option<String>,
+    /// match response status, case insensitive
+    ///
+    /// eg: ok, error, notexist, servererror, clienterror
+    #[clap(long)]
+    status: Option<String>,
+    /// max number of logs to return, newest first
+    #[clap(long)]
+    limit: Option<u32>,
+}
+
+#[derive(Parser)]
+struct FlowCmd {
+    /// output format
+    ///
+    /// eg: deepflow-agent-ctl -p 42700 flow --format json > flows.json
+    #[clap(long, arg_enum, default_value = "text")]
+    format: FlowFormat,
+    /// max size of the output in bytes, flows exceeding it are dropped
+    #[clap(long, default_value_t = FLOW_QUERY_DEFAULT_MAX_BYTES)]
+    max_bytes: u32,
+}
+
+#[derive(Clone, Copy, ArgEnum, Debug)]
+enum FlowFormat {
+    Text,
+    Json,
+    Csv,
+}
+
 #[cfg(target_os = "linux")]
 #[derive(Parser)]
 struct PlatformCmd {
@@ -219,6 +268,8 @@ impl Controller {
             ControllerCmd::Rpc(c) => self.rpc(c),
             ControllerCmd::List => self.list(),
             ControllerCmd::Queue(c) => self.queue(c),
+            ControllerCmd::L7Log(c) => self.l7_log(c),
+            ControllerCmd::Flow(c) => self.flow(c),
         }
     }
 
@@ -453,6 +504,77 @@ impl Controller {
         Ok(())
     }
 
+    fn l7_log(&self, c: L7LogCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+        let mut client = self.new_client()?;
+
+        let msg = Message {
+            module: Module::L7Log,
+            msg: L7LogMessage::Query(L7LogFilter {
+                ip: c.ip.map(|ip| ip.to_string()),
+                port: c.port,
+                protocol: c.protocol,
+                status: c.status,
+                limit: c.limit,
+            }),
+        };
+        client.send_to(msg)?;
+
+        let mut count = 0;
+        loop {
+            let res = client.recv::<L7LogMessage>()?;
+            match res {
+                L7LogMessage::Log(l) => {
+                    println!("LOG-{} {}", count, l);
+                    count += 1;
+                }
+                L7LogMessage::Fin => {
+                    if count == 0 {
+                        println!("no l7 log matched");
+                    }
+                    return Ok(());
+                }
+                L7LogMessage::Err(e) => return Err(anyhow!(e)),
+                L7LogMessage::Started(secs) => {
+                    // 随后返回开启后短时间内记录的日志
+                    println!(
+                        "l7 log recording started for {}s, query again to see more logs",
+                        secs
+                    );
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn flow(&self, c: FlowCmd) -> Result<()> {
+        if self.port.is_none() {
+            return Err(anyhow!(ERR_PORT_MSG));
+        }
+        let mut client = self.new_client()?;
+
+        let msg = Message {
+            module: Module::Flow,
+            msg: FlowMessage::Query(FlowQuery {
+                format: Some(format!("{:?}", c.format)),
+                max_bytes: Some(c.max_bytes),
+            }),
+        };
+        client.send_to(msg)?;
+
+        loop {
+            let res = client.recv::<FlowMessage>()?;
+            match res {
+                FlowMessage::Content(s) => print!("{}", s),
+                FlowMessage::Fin => return Ok(()),
+                FlowMessage::Err(e) => return Err(anyhow!(e)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
     #[cfg(target_os = "linux")]
     fn platform(&self, c: PlatformCmd) -> Result<()> {
         if self.port.is_none() {
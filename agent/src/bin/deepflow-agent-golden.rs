@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 维护协议解析的golden文件，在agent目录下运行:
+//   cargo run --bin deepflow-agent-golden -- update [--protocol mysql] [--diff] [--new]
+//   cargo run --bin deepflow-agent-golden -- anonymize <input.pcap> <output.pcap>
+// 新增用例时先anonymize用户提供的pcap，放入resources/test/flow_generator/<协议>/，
+// 再执行update --new生成同名的result文件，检查内容后一并提交
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+
+use deepflow_agent::{_Anonymizer, _GoldenParser, _GOLDEN_DIR, _GOLDEN_PARSERS};
+
+#[derive(Parser)]
+#[clap(name = "deepflow-agent-golden")]
+struct Cmd {
+    #[clap(subcommand)]
+    command: GoldenCmd,
+}
+
+#[derive(Subcommand)]
+enum GoldenCmd {
+    /// regenerate .result golden files from the pcaps of each protocol
+    Update(UpdateCmd),
+    /// replace mac and ip addresses in a user contributed pcap
+    ///
+    /// payloads are not modified and should be reviewed before committing
+    Anonymize { input: PathBuf, output: PathBuf },
+}
+
+#[derive(Parser)]
+struct UpdateCmd {
+    /// only update this protocol
+    ///
+    /// eg: dns, dubbo, http, kafka, mqtt, mysql, redis
+    #[clap(long)]
+    protocol: Option<String>,
+    /// print differences instead of writing files, exit with 1 if any
+    #[clap(long)]
+    diff: bool,
+    /// also generate results for pcaps without one
+    #[clap(long)]
+    new: bool,
+    /// directory containing one sub directory per protocol
+    #[clap(long, default_value = _GOLDEN_DIR)]
+    dir: PathBuf,
+}
+
+// 逐行比较，只输出不同的行
+fn print_diff(path: &Path, expected: &str, actual: &str) {
+    println!("--- {}", path.display());
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e == a {
+            continue;
+        }
+        println!("@@ line {}", i + 1);
+        if let Some(e) = e {
+            println!("-{}", e);
+        }
+        if let Some(a) = a {
+            println!("+{}", a);
+        }
+    }
+}
+
+fn update(cmd: UpdateCmd) -> Result<bool> {
+    let parsers: Vec<&_GoldenParser> =
+        match cmd.protocol.as_ref() {
+            Some(name) => vec![_GoldenParser::find(name)
+                .ok_or_else(|| anyhow!("unsupported protocol {}", name))?],
+            None => _GOLDEN_PARSERS.iter().collect(),
+        };
+    let mut changed = false;
+    for parser in parsers {
+        for (pcap, result) in parser.cases(&cmd.dir)? {
+            let expected = match fs::read_to_string(&result) {
+                Ok(s) => Some(s),
+                Err(_) if cmd.new => None,
+                Err(_) => continue,
+            };
+            let actual = parser
+                .run(&pcap)
+                .map_err(|e| anyhow!("parse {} failed: {}", pcap.display(), e))?;
+            if expected.as_ref() == Some(&actual) {
+                continue;
+            }
+            changed = true;
+            if cmd.diff {
+                print_diff(&result, expected.as_deref().unwrap_or_default(), &actual);
+            } else {
+                fs::write(&result, &actual)?;
+                println!("updated {}", result.display());
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn main() -> Result<()> {
+    match Cmd::parse().command {
+        GoldenCmd::Update(cmd) => {
+            let diff = cmd.diff;
+            if update(cmd)? && diff {
+                process::exit(1);
+            }
+        }
+        GoldenCmd::Anonymize { input, output } => {
+            let data = fs::read(&input)?;
+            let data = _Anonymizer::default().anonymize_pcap(&data)?;
+            fs::write(&output, data)?;
+        }
+    }
+    Ok(())
+}
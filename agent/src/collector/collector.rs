@@ -19,7 +19,7 @@ use std::{
     hash::Hash,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::{
-        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc, Mutex, Weak,
     },
     thread,
@@ -31,8 +31,8 @@ use arc_swap::access::Access;
 use log::{debug, info, warn};
 
 use super::{
-    acc_flow::AccumulatedFlow, MetricsType, FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC,
-    QUEUE_BATCH_SIZE, RCV_TIMEOUT,
+    acc_flow::AccumulatedFlow, l7_alert::L7AlertEvaluator, MetricsType, FLOW_METRICS_PEER_DST,
+    FLOW_METRICS_PEER_SRC, QUEUE_BATCH_SIZE, RCV_TIMEOUT,
 };
 use crate::{
     common::{
@@ -40,11 +40,12 @@ use crate::{
         flow::{get_direction, Flow, FlowSource, L7Protocol},
     },
     config::handler::CollectorAccess,
+    exception::ExceptionHandler,
     metric::{
         document::{Code, Direction, Document, DocumentFlag, TagType, Tagger, TapSide},
-        meter::{FlowMeter, Meter, UsageMeter},
+        meter::{FlowMeter, Meter, Traffic, UsageMeter},
     },
-    rpc::get_timestamp,
+    rpc::TimeSource,
     sender::SendItem,
     utils::{
         net::MacAddr,
@@ -108,6 +109,8 @@ struct StashKey {
     fast_id: u128,
     src_ip: IpAddr,
     dst_ip: IpAddr,
+    // 仅SNI统计时非空
+    server_name: String,
 }
 
 impl Default for StashKey {
@@ -116,6 +119,7 @@ impl Default for StashKey {
             fast_id: 0,
             src_ip: Ipv4Addr::UNSPECIFIED.into(),
             dst_ip: Ipv4Addr::UNSPECIFIED.into(),
+            server_name: String::new(),
         }
     }
 }
@@ -156,6 +160,15 @@ impl StashKey {
         .union(Code::SERVER_PORT)
         .union(Code::L7_PROTOCOL);
 
+    // 服务端连接数统计，不区分方向和采集位置
+    const SINGLE_IP_PORT_CONN: Code = Code::IP
+        .union(Code::L3_EPC_ID)
+        .union(Code::VTAP_ID)
+        .union(Code::PROTOCOL)
+        .union(Code::SERVER_PORT);
+    // 按TLS SNI区分同一服务端口后的不同域名
+    const SINGLE_IP_PORT_SNI: Code = Self::SINGLE_IP_PORT_CONN.union(Code::SERVER_NAME);
+
     const ACL: Code = Code::ACL_GID
         .union(Code::TAG_TYPE)
         .union(Code::TAG_VALUE)
@@ -247,6 +260,18 @@ impl StashKey {
                     << 64;
                 2
             }
+            Self::SINGLE_IP_PORT_CONN => {
+                fast_id |= ((tagger.l3_epc_id) as u16 as u128)
+                    | (tagger.protocol as u128) << 16
+                    | (tagger.server_port as u128) << 24;
+                3
+            }
+            Self::SINGLE_IP_PORT_SNI => {
+                fast_id |= ((tagger.l3_epc_id) as u16 as u128)
+                    | (tagger.protocol as u128) << 16
+                    | (tagger.server_port as u128) << 24;
+                4
+            }
             Self::ACL => {
                 fast_id |= tagger.acl_gid as u128
                     | (tagger.tag_type as u128) << 16
@@ -262,6 +287,7 @@ impl StashKey {
             fast_id,
             src_ip,
             dst_ip: dst_ip.unwrap_or(Ipv4Addr::UNSPECIFIED.into()),
+            server_name: tagger.server_name.clone(),
         }
     }
 }
@@ -275,6 +301,8 @@ struct Stash {
     global_thread_id: u8,
     doc_flag: DocumentFlag,
     context: Context,
+    // 仅分钟collector评估告警规则
+    alert: Option<L7AlertEvaluator>,
 }
 
 impl Stash {
@@ -283,18 +311,21 @@ impl Stash {
             MetricsType::SECOND => (1, DocumentFlag::PER_SECOND_METRICS),
             _ => (60, DocumentFlag::NONE),
         };
+        let alert = match ctx.metric_type {
+            MetricsType::MINUTE => Some(L7AlertEvaluator::new(ctx.exception_handler.clone())),
+            _ => None,
+        };
 
         Self {
             sender,
             counter,
-            start_time: Duration::from_secs(
-                get_timestamp(ctx.ntp_diff.load(Ordering::Relaxed)).as_secs() / MINUTE * MINUTE,
-            ),
+            start_time: Duration::from_secs(ctx.time_source.now().as_secs() / MINUTE * MINUTE),
             global_thread_id: ctx.id as u8 + 1,
             slot_interval,
             inner: HashMap::new(),
             doc_flag,
             context: ctx,
+            alert,
         }
     }
 
@@ -318,7 +349,7 @@ impl Stash {
         }
 
         time_in_second = time_in_second / self.slot_interval * self.slot_interval;
-        let timestamp = get_timestamp(self.context.ntp_diff.load(Ordering::Relaxed));
+        let timestamp = self.context.time_source.now();
 
         let start_time = self.start_time.as_secs();
         if time_in_second > start_time {
@@ -410,6 +441,16 @@ impl Stash {
         let directions = [src, dst];
 
         self.fill_stats(&acc_flow, directions, false, inactive_ip_enabeld);
+        if self.context.metric_type == MetricsType::MINUTE
+            && self.context.config.load().server_connection_metrics_enabled
+        {
+            self.fill_connection_stats(&acc_flow, directions, inactive_ip_enabeld);
+        }
+        if self.context.metric_type == MetricsType::MINUTE
+            && self.context.config.load().tls_sni_metrics_enabled
+        {
+            self.fill_server_name_stats(&acc_flow, directions, inactive_ip_enabeld);
+        }
         self.fill_tracing_stats(
             &acc_flow,
             directions,
@@ -565,6 +606,11 @@ impl Stash {
                 code
             },
             l7_protocol: acc_flow.l7_protocol,
+            service_id: if self.context.config.load().dual_stack_merge_enabled {
+                side.dual_stack_id
+            } else {
+                0
+            },
             ..Default::default()
         };
         if tagger.direction == Direction::ServerToClient
@@ -582,6 +628,86 @@ impl Stash {
         }
     }
 
+    // 服务端新建连接数和并发连接数，仅在服务端一侧采集的TCP流中统计一次
+    fn fill_connection_stats(
+        &mut self,
+        acc_flow: &AccumulatedFlow,
+        directions: [Direction; 2],
+        inactive_ip_enabeld: bool,
+    ) {
+        let flow = &acc_flow.tagged_flow.flow;
+        let flow_key = &flow.flow_key;
+        if flow_key.proto != IpProtocol::Tcp
+            || directions[FLOW_METRICS_PEER_DST] != Direction::ServerToClient
+            || (!acc_flow.is_active_host1 && !inactive_ip_enabeld)
+            || Self::ignore_server_port(
+                flow,
+                self.context.config.load().inactive_server_port_enabled,
+            )
+        {
+            return;
+        }
+        let side = &flow.flow_metrics_peers[FLOW_METRICS_PEER_DST];
+        let tagger = Tagger {
+            global_thread_id: self.global_thread_id,
+            vtap_id: self.context.config.load().vtap_id,
+            ip: flow_key.ip_dst,
+            l3_epc_id: side.l3_epc_id as i16,
+            protocol: flow_key.proto,
+            server_port: flow_key.port_dst,
+            is_ipv6: flow.eth_type == EthernetType::Ipv6,
+            code: StashKey::SINGLE_IP_PORT_CONN,
+            ..Default::default()
+        };
+        let traffic = &acc_flow.flow_meter.traffic;
+        // 并发连接数由FlowLoad在合并时根据new_flow和closed_flow计算
+        let meter = FlowMeter {
+            traffic: Traffic {
+                new_flow: traffic.new_flow,
+                closed_flow: traffic.closed_flow,
+                syn: traffic.syn,
+                synack: traffic.synack,
+                ..Default::default()
+            },
+            flow_load: acc_flow.flow_meter.flow_load,
+            ..Default::default()
+        };
+        let key = StashKey::new(&tagger, flow_key.ip_dst, None);
+        self.add(key, tagger, Meter::Flow(meter));
+    }
+
+    // 以服务端视角按SNI统计流量和时延，共享IP或负载均衡后的HTTPS服务可按域名区分
+    fn fill_server_name_stats(
+        &mut self,
+        acc_flow: &AccumulatedFlow,
+        directions: [Direction; 2],
+        inactive_ip_enabeld: bool,
+    ) {
+        let flow = &acc_flow.tagged_flow.flow;
+        let flow_key = &flow.flow_key;
+        if flow.tls_sni.is_empty()
+            || directions[FLOW_METRICS_PEER_DST] != Direction::ServerToClient
+            || (!acc_flow.is_active_host1 && !inactive_ip_enabeld)
+        {
+            return;
+        }
+        let side = &flow.flow_metrics_peers[FLOW_METRICS_PEER_DST];
+        let tagger = Tagger {
+            global_thread_id: self.global_thread_id,
+            vtap_id: self.context.config.load().vtap_id,
+            ip: flow_key.ip_dst,
+            l3_epc_id: side.l3_epc_id as i16,
+            protocol: flow_key.proto,
+            server_port: flow_key.port_dst,
+            is_ipv6: flow.eth_type == EthernetType::Ipv6,
+            code: StashKey::SINGLE_IP_PORT_SNI,
+            server_name: flow.tls_sni.clone(),
+            ..Default::default()
+        };
+        let key = StashKey::new(&tagger, flow_key.ip_dst, None);
+        self.add(key, tagger, Meter::Flow(acc_flow.flow_meter.to_reversed()));
+    }
+
     fn fill_edge_stats(
         &mut self,
         acc_flow: &AccumulatedFlow,
@@ -684,6 +810,10 @@ impl Stash {
             is_ipv6,
             ..Default::default()
         };
+        if self.context.config.load().dual_stack_merge_enabled {
+            tagger.service_id = src_ep.dual_stack_id;
+            tagger.service_id1 = dst_ep.dual_stack_id;
+        }
 
         // 双端统计量不要对Meter反向
         let key = StashKey::new(&tagger, src_ip, Some(dst_ip));
@@ -731,6 +861,15 @@ impl Stash {
     }
 
     fn flush_stats(&mut self) {
+        if let Some(alert) = self.alert.as_mut() {
+            alert.update_rules(&self.context.config.load().l7_alert_rules);
+            for doc in self.inner.values() {
+                if let Meter::App(meter) = &doc.meter {
+                    alert.add(&doc.tagger, meter);
+                }
+            }
+            alert.evaluate(self.start_time.as_secs() as u32);
+        }
         let mut entries = self
             .inner
             .drain()
@@ -763,7 +902,8 @@ struct Context {
     delay_seconds: u64,
     metric_type: MetricsType,
     config: CollectorAccess,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
+    exception_handler: ExceptionHandler,
 }
 
 pub struct Collector {
@@ -785,7 +925,8 @@ impl Collector {
         delay_seconds: u32,
         stats: &Arc<stats::Collector>,
         config: CollectorAccess,
-        ntp_diff: Arc<AtomicI64>,
+        time_source: TimeSource,
+        exception_handler: ExceptionHandler,
     ) -> Self {
         let delay_seconds = delay_seconds as u64;
         let name = match metric_type {
@@ -822,7 +963,8 @@ impl Collector {
                 delay_seconds,
                 metric_type,
                 config,
-                ntp_diff,
+                time_source,
+                exception_handler,
             },
         }
     }
@@ -850,11 +992,7 @@ impl Collector {
                     }
                     Err(Error::Timeout) => {
                         // qg会延时delay_seconds，这再多延时2秒刷新数据
-                        stash.collect(
-                            None,
-                            get_timestamp(stash.context.ntp_diff.load(Ordering::Relaxed)).as_secs()
-                                - 2,
-                        )
+                        stash.collect(None, stash.context.time_source.now().as_secs() - 2)
                     }
                     Err(Error::Terminated(..)) => break,
                 }
@@ -975,5 +1113,22 @@ mod tests {
         tagger.tag_value = 0x7fff;
         let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
         assert_eq!(map.insert(key), true);
+
+        tagger.code = StashKey::SINGLE_IP_PORT_CONN;
+        let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
+        assert_eq!(map.insert(key), true);
+        tagger.server_port ^= 0x1;
+        let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
+        assert_eq!(map.insert(key), true);
+
+        tagger.code = StashKey::SINGLE_IP_PORT_SNI;
+        tagger.server_name = "a.example.com".to_owned();
+        let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
+        assert_eq!(map.insert(key), true);
+        tagger.server_name = "b.example.com".to_owned();
+        let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
+        assert_eq!(map.insert(key), true);
+        let key = StashKey::new(&tagger, Ipv4Addr::UNSPECIFIED.into(), None);
+        assert_eq!(map.insert(key), false);
     }
 }
@@ -30,8 +30,12 @@ use rand::prelude::{Rng, SeedableRng, SmallRng};
 use super::consts::*;
 use super::round_to_minute;
 
-use crate::common::{enums::TapType, flow::CloseType, tagged_flow::TaggedFlow};
-use crate::config::handler::CollectorAccess;
+use crate::common::{
+    enums::TapType,
+    flow::{CloseType, Flow},
+    tagged_flow::TaggedFlow,
+};
+use crate::config::{handler::CollectorAccess, L4LogFilterConfig};
 use crate::sender::SendItem;
 use crate::utils::{
     queue::{DebugSender, Error, Receiver},
@@ -48,6 +52,7 @@ struct FlowAggrCounter {
     drop_before_window: AtomicU64,
     out: AtomicU64,
     drop_in_throttle: AtomicU64,
+    drop_by_filter: AtomicU64,
 }
 
 pub struct FlowAggrThread {
@@ -200,6 +205,10 @@ impl FlowAggr {
             f.flow.end_time =
                 round_to_minute(f.flow.flow_stat_time + Duration::from_secs(SECONDS_IN_MINUTE));
         }
+        if !l4_log_filter_matches(&self.config.load().l4_log_filter, &f.flow) {
+            self.counter.drop_by_filter.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
         self.counter.out.fetch_add(1, Ordering::Relaxed);
         if !self.output.send(f) {
             self.counter
@@ -278,6 +287,11 @@ impl RefCountable for FlowAggr {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.counter.drop_in_throttle.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "drop-by-filter",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.counter.drop_by_filter.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 
@@ -286,6 +300,36 @@ impl RefCountable for FlowAggr {
     // }
 }
 
+// 未开启时全部导出，开启后满足任一条件即导出
+fn l4_log_filter_matches(filter: &L4LogFilterConfig, flow: &Flow) -> bool {
+    if !filter.enabled {
+        return true;
+    }
+    if filter.close_types.contains(&(flow.close_type as u8)) {
+        return true;
+    }
+    if !filter.min_duration.is_zero() && flow.duration >= filter.min_duration {
+        return true;
+    }
+    let peers = &flow.flow_metrics_peers;
+    if filter.min_bytes > 0
+        && peers[0].total_byte_count + peers[1].total_byte_count >= filter.min_bytes
+    {
+        return true;
+    }
+    if filter.min_retrans_permille > 0 {
+        if let Some(stats) = flow.flow_perf_stats.as_ref() {
+            let retrans = stats.tcp.counts_peers[0].retrans_count as u64
+                + stats.tcp.counts_peers[1].retrans_count as u64;
+            let packets = peers[0].packet_count + peers[1].packet_count;
+            if packets > 0 && retrans * 1000 >= packets * filter.min_retrans_permille as u64 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
 struct ThrottlingQueue {
     config: CollectorAccess,
     throttle: u64,
@@ -377,3 +421,48 @@ impl ThrottlingQueue {
         self.throttle = new * Self::THROTTLE_BUCKET;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::flow::FlowPerfStats;
+
+    #[test]
+    fn l4_log_filter() {
+        let mut filter = L4LogFilterConfig::default();
+        let mut flow = Flow {
+            close_type: CloseType::TcpFin,
+            ..Default::default()
+        };
+        assert!(l4_log_filter_matches(&filter, &flow));
+
+        filter.enabled = true;
+        assert!(!l4_log_filter_matches(&filter, &flow));
+        flow.close_type = CloseType::TcpServerRst;
+        assert!(l4_log_filter_matches(&filter, &flow));
+
+        flow.close_type = CloseType::TcpFin;
+        filter.min_duration = Duration::from_secs(10);
+        flow.duration = Duration::from_secs(30);
+        assert!(l4_log_filter_matches(&filter, &flow));
+
+        flow.duration = Duration::from_secs(1);
+        filter.min_bytes = 1000;
+        flow.flow_metrics_peers[0].total_byte_count = 600;
+        flow.flow_metrics_peers[1].total_byte_count = 400;
+        assert!(l4_log_filter_matches(&filter, &flow));
+
+        flow.flow_metrics_peers[1].total_byte_count = 0;
+        filter.min_retrans_permille = 50;
+        flow.flow_metrics_peers[0].packet_count = 60;
+        flow.flow_metrics_peers[1].packet_count = 40;
+        let mut stats = FlowPerfStats::default();
+        stats.tcp.counts_peers[0].retrans_count = 4;
+        flow.flow_perf_stats = Some(stats.clone());
+        assert!(!l4_log_filter_matches(&filter, &flow));
+        stats.tcp.counts_peers[1].retrans_count = 1;
+        flow.flow_perf_stats = Some(stats);
+        assert!(l4_log_filter_matches(&filter, &flow));
+    }
+}
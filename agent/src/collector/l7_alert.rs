@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use ipnet::IpNet;
+use log::{info, warn};
+
+use crate::{
+    common::flow::L7Protocol,
+    config::{L7AlertMetric, L7AlertRule},
+    exception::ExceptionHandler,
+    metric::{
+        document::{Code, Direction, Tagger},
+        meter::AppMeter,
+    },
+    proto::trident::Exception,
+};
+
+struct CompiledRule {
+    server_net: Option<IpNet>,
+    server_port: u16,
+    l7_protocol: Option<L7Protocol>,
+}
+
+impl CompiledRule {
+    fn matches(&self, tagger: &Tagger) -> bool {
+        self.server_net
+            .as_ref()
+            .map_or(true, |net| net.contains(&tagger.ip))
+            && (self.server_port == 0 || self.server_port == tagger.server_port)
+            && self.l7_protocol.map_or(true, |p| p == tagger.l7_protocol)
+    }
+}
+
+// 在分钟collector输出前对服务端单端应用指标按规则累加，超过阈值时告警
+pub struct L7AlertEvaluator {
+    rules: Vec<L7AlertRule>,
+    compiled: Vec<CompiledRule>,
+    meters: Vec<AppMeter>,
+    firing: bool,
+    exception_handler: ExceptionHandler,
+}
+
+impl L7AlertEvaluator {
+    pub fn new(exception_handler: ExceptionHandler) -> Self {
+        Self {
+            rules: vec![],
+            compiled: vec![],
+            meters: vec![],
+            firing: false,
+            exception_handler,
+        }
+    }
+
+    pub fn update_rules(&mut self, rules: &[L7AlertRule]) {
+        if self.rules == rules {
+            return;
+        }
+        info!("l7 alert rules updated: {:?}", rules);
+        // 规则在加载配置时已校验
+        self.compiled = rules
+            .iter()
+            .map(|r| CompiledRule {
+                server_net: r.server_net().unwrap_or_default(),
+                server_port: r.server_port,
+                l7_protocol: r.l7_protocol().unwrap_or_default(),
+            })
+            .collect();
+        self.meters = vec![AppMeter::default(); rules.len()];
+        self.rules = rules.to_vec();
+    }
+
+    pub fn add(&mut self, tagger: &Tagger, meter: &AppMeter) {
+        // 每条流的应用指标在客户端和服务端各记录一次，只统计服务端单端数据
+        if tagger.code.contains(Code::IP_PATH) || !tagger.direction.is_server_to_client() {
+            return;
+        }
+        for (rule, m) in self.compiled.iter().zip(self.meters.iter_mut()) {
+            if rule.matches(tagger) {
+                m.sequential_merge(meter);
+            }
+        }
+    }
+
+    fn value(metric: L7AlertMetric, meter: &AppMeter) -> u64 {
+        match metric {
+            L7AlertMetric::Request => meter.traffic.request as u64,
+            L7AlertMetric::Response => meter.traffic.response as u64,
+            L7AlertMetric::ClientError => meter.anomaly.client_error as u64,
+            L7AlertMetric::ServerError => meter.anomaly.server_error as u64,
+            L7AlertMetric::Timeout => meter.anomaly.timeout as u64,
+            L7AlertMetric::RrtAvg if meter.latency.rrt_count > 0 => {
+                meter.latency.rrt_sum / meter.latency.rrt_count as u64
+            }
+            L7AlertMetric::RrtAvg => 0,
+            L7AlertMetric::RrtMax => meter.latency.rrt_max as u64,
+        }
+    }
+
+    // 每分钟调用一次，返回触发的规则数
+    pub fn evaluate(&mut self, timestamp: u32) -> usize {
+        let mut fired = 0;
+        for (rule, meter) in self.rules.iter().zip(self.meters.iter_mut()) {
+            let value = Self::value(rule.metric, meter);
+            if value > rule.threshold {
+                fired += 1;
+                warn!(
+                    "l7 alert {} triggered at {}: {:?} {} > {}",
+                    rule.name, timestamp, rule.metric, value, rule.threshold
+                );
+            }
+            *meter = AppMeter::default();
+        }
+        // 异常会被同步线程取走清除，告警期间每分钟都需要设置
+        if fired > 0 {
+            self.exception_handler.set(Exception::L7AlertTriggered);
+        } else if self.firing {
+            info!("l7 alerts recovered at {}", timestamp);
+            self.exception_handler.clear(Exception::L7AlertTriggered);
+        }
+        self.firing = fired > 0;
+        fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_error_alert() {
+        let handler = ExceptionHandler::default();
+        let mut evaluator = L7AlertEvaluator::new(handler.clone());
+        evaluator.update_rules(&[L7AlertRule {
+            name: "payment-5xx".into(),
+            server_ip: "10.1.0.0/16".into(),
+            server_port: 8080,
+            l7_protocol: "http1".into(),
+            metric: L7AlertMetric::ServerError,
+            threshold: 10,
+        }]);
+
+        let mut tagger = Tagger {
+            code: Code::IP | Code::SERVER_PORT | Code::DIRECTION | Code::L7_PROTOCOL,
+            ip: "10.1.2.3".parse().unwrap(),
+            server_port: 8080,
+            direction: Direction::ServerToClient,
+            l7_protocol: L7Protocol::Http1,
+            ..Default::default()
+        };
+        let mut meter = AppMeter::default();
+        meter.anomaly.server_error = 6;
+        evaluator.add(&tagger, &meter);
+        assert_eq!(evaluator.evaluate(60), 0);
+
+        evaluator.add(&tagger, &meter);
+        evaluator.add(&tagger, &meter);
+        // 客户端数据和其他服务不计入
+        tagger.direction = Direction::ClientToServer;
+        evaluator.add(&tagger, &meter);
+        tagger.direction = Direction::ServerToClient;
+        tagger.ip = "10.2.0.1".parse().unwrap();
+        evaluator.add(&tagger, &meter);
+        assert_eq!(evaluator.evaluate(120), 1);
+        assert_eq!(handler.take(), Exception::L7AlertTriggered as u64);
+
+        assert_eq!(evaluator.evaluate(180), 0);
+        assert_eq!(handler.take(), 0);
+    }
+}
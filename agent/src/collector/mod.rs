@@ -18,9 +18,12 @@ pub(crate) mod acc_flow;
 mod collector;
 mod consts;
 pub(crate) mod flow_aggr;
+mod l7_alert;
 pub(crate) mod quadruple_generator;
+mod security_event;
 
 pub use collector::Collector;
+pub use security_event::{SecurityEvent, SecurityEventDetector, SecurityEventType};
 
 use bitflags::bitflags;
 use std::time::Duration;
@@ -17,7 +17,7 @@
 use std::collections::{HashMap, VecDeque};
 use std::net::IpAddr;
 use std::sync::{
-    atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering},
     Arc, Weak,
 };
 use std::thread;
@@ -30,6 +30,7 @@ use log::{debug, info, warn};
 
 use super::acc_flow::{AccumulatedFlow, U16Set};
 use super::consts::*;
+use super::security_event::SecurityEventDetector;
 use super::MetricsType;
 
 use crate::common::{
@@ -40,9 +41,10 @@ use crate::common::{
 };
 use crate::config::handler::CollectorAccess;
 use crate::metric::meter::{
-    AppAnomaly, AppLatency, AppMeter, AppTraffic, FlowMeter, Latency, Performance, Traffic,
+    AppAnomaly, AppConnection, AppLatency, AppMeter, AppTraffic, FlowMeter, Latency, Performance,
+    Traffic,
 };
-use crate::rpc::get_timestamp;
+use crate::rpc::TimeSource;
 use crate::utils::{
     lru::Lru,
     possible_host::PossibleHost,
@@ -272,7 +274,7 @@ struct SubQuadGen {
     stashs: VecDeque<QuadrupleStash>, // flow_generator 不会有超过2分钟的延时
 
     connections: VecDeque<ConcurrentConnection>,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
     // TODO: 策略统计处理
     // traffic_setter: TrafficSetter,
 }
@@ -314,7 +316,7 @@ impl SubQuadGen {
             return false;
         }
 
-        let ts = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+        let ts = self.time_source.now();
         while time_in_second.as_secs() >= self.window_start.as_secs() + self.delay_seconds {
             let delay = ts.as_nanos() as i64 - self.window_start.as_nanos() as i64;
             self.counter
@@ -540,8 +542,9 @@ impl SubQuadGen {
 pub struct QuadrupleGeneratorThread {
     id: usize,
     input: Arc<Receiver<Box<TaggedFlow>>>,
-    second_output: DebugSender<Box<AccumulatedFlow>>,
-    minute_output: DebugSender<Box<AccumulatedFlow>>,
+    // 未生成对应粒度的指标时为None
+    second_output: Option<DebugSender<Box<AccumulatedFlow>>>,
+    minute_output: Option<DebugSender<Box<AccumulatedFlow>>>,
     flow_output: DebugSender<Arc<TaggedFlow>>,
     connection_lru_capacity: usize,
     metrics_type: MetricsType,
@@ -551,12 +554,14 @@ pub struct QuadrupleGeneratorThread {
     l7_metrics_enabled: Arc<AtomicBool>,
     vtap_flow_1s_enabled: Arc<AtomicBool>,
     collector_enabled: Arc<AtomicBool>,
+    sampling_ratio: Arc<AtomicU32>,
+    security_event: Arc<SecurityEventDetector>,
 
     thread_handle: Option<JoinHandle<()>>,
 
     running: Arc<AtomicBool>,
     config: CollectorAccess,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
 
     stats: Arc<Collector>,
 }
@@ -565,8 +570,8 @@ impl QuadrupleGeneratorThread {
     pub fn new(
         id: usize,
         input: Receiver<Box<TaggedFlow>>,
-        second_output: DebugSender<Box<AccumulatedFlow>>,
-        minute_output: DebugSender<Box<AccumulatedFlow>>,
+        second_output: Option<DebugSender<Box<AccumulatedFlow>>>,
+        minute_output: Option<DebugSender<Box<AccumulatedFlow>>>,
         flow_output: DebugSender<Arc<TaggedFlow>>,
         connection_lru_capacity: usize,
         metrics_type: MetricsType,
@@ -574,15 +579,17 @@ impl QuadrupleGeneratorThread {
         minute_delay_seconds: u64,
         possible_host_size: usize,
         config: CollectorAccess,
-        ntp_diff: Arc<AtomicI64>,
+        time_source: TimeSource,
         stats: Arc<Collector>,
+        security_event: Arc<SecurityEventDetector>,
     ) -> Self {
         let running = Arc::new(AtomicBool::new(false));
+        security_event.update_config(&config.load().security_event, config.load().vtap_id);
         Self {
             id,
             input: Arc::new(input),
-            second_output: second_output.clone(),
-            minute_output: minute_output.clone(),
+            second_output,
+            minute_output,
             flow_output: flow_output.clone(),
             connection_lru_capacity,
             metrics_type,
@@ -592,10 +599,12 @@ impl QuadrupleGeneratorThread {
             l7_metrics_enabled: Arc::new(AtomicBool::new(config.load().l7_metrics_enabled)),
             vtap_flow_1s_enabled: Arc::new(AtomicBool::new(config.load().vtap_flow_1s_enabled)),
             collector_enabled: Arc::new(AtomicBool::new(config.load().enabled)),
+            sampling_ratio: Arc::new(AtomicU32::new(config.load().sampling_ratio)),
+            security_event,
             thread_handle: None,
             running,
             config,
-            ntp_diff,
+            time_source,
             stats,
         }
     }
@@ -604,6 +613,7 @@ impl QuadrupleGeneratorThread {
         let l7_metrics_enabled = self.config.load().l7_metrics_enabled;
         let vtap_flow_1s_enabled = self.config.load().vtap_flow_1s_enabled;
         let collector_enabled = self.config.load().enabled;
+        let sampling_ratio = self.config.load().sampling_ratio;
         if self.l7_metrics_enabled.load(Ordering::Relaxed) != l7_metrics_enabled {
             info!(
                 "quadruple generator update l7_metrics_enabled to {}",
@@ -628,6 +638,16 @@ impl QuadrupleGeneratorThread {
             self.collector_enabled
                 .store(collector_enabled, Ordering::Relaxed);
         }
+        if self.sampling_ratio.load(Ordering::Relaxed) != sampling_ratio {
+            info!(
+                "quadruple generator update sampling_ratio to {}",
+                sampling_ratio
+            );
+            self.sampling_ratio.store(sampling_ratio, Ordering::Relaxed);
+        }
+        let config = self.config.load();
+        self.security_event
+            .update_config(&config.security_event, config.vtap_id);
     }
 
     pub fn start(&mut self) {
@@ -653,8 +673,10 @@ impl QuadrupleGeneratorThread {
             self.l7_metrics_enabled.clone(),
             self.vtap_flow_1s_enabled.clone(),
             self.collector_enabled.clone(),
+            self.sampling_ratio.clone(),
+            self.security_event.clone(),
             self.running.clone(),
-            self.ntp_diff.clone(),
+            self.time_source.clone(),
             self.stats.clone(),
         );
         self.thread_handle = Some(thread::spawn(move || quadruple_generator.handler_routine()));
@@ -691,9 +713,12 @@ pub struct QuadrupleGenerator {
     l7_metrics_enabled: Arc<AtomicBool>,
     vtap_flow_1s_enabled: Arc<AtomicBool>,
     collector_enabled: Arc<AtomicBool>,
+    // 镜像源的采样比例，大于1时流量统计按比例放大
+    sampling_ratio: Arc<AtomicU32>,
+    security_event: Arc<SecurityEventDetector>,
 
     running: Arc<AtomicBool>,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
 
     stats: Arc<Collector>,
 }
@@ -702,8 +727,8 @@ impl QuadrupleGenerator {
     pub fn new(
         id: usize,
         input: Arc<Receiver<Box<TaggedFlow>>>,
-        second_output: DebugSender<Box<AccumulatedFlow>>,
-        minute_output: DebugSender<Box<AccumulatedFlow>>,
+        second_output: Option<DebugSender<Box<AccumulatedFlow>>>,
+        minute_output: Option<DebugSender<Box<AccumulatedFlow>>>,
         flow_output: DebugSender<Arc<TaggedFlow>>,
         connection_lru_capacity: usize,
         metrics_type: MetricsType,
@@ -714,8 +739,10 @@ impl QuadrupleGenerator {
         l7_metrics_enabled: Arc<AtomicBool>,
         vtap_flow_1s_enabled: Arc<AtomicBool>,
         collector_enabled: Arc<AtomicBool>,
+        sampling_ratio: Arc<AtomicU32>,
+        security_event: Arc<SecurityEventDetector>,
         running: Arc<AtomicBool>,
-        ntp_diff: Arc<AtomicI64>,
+        time_source: TimeSource,
         stats: Arc<Collector>,
     ) -> Self {
         info!("new quadruple_generator id: {}, second_delay: {}, minute_delay: {}, l7_metrics_enabled: {}, vtap_flow_1s_enabled: {} collector_enabled: {}", id, second_delay_seconds, minute_delay_seconds, l7_metrics_enabled.load(Ordering::Relaxed), vtap_flow_1s_enabled.load(Ordering::Relaxed), collector_enabled.load(Ordering::Relaxed));
@@ -728,13 +755,13 @@ impl QuadrupleGenerator {
         let minute_slots = 2 as usize;
         let mut second_quad_gen = None;
         let mut minute_quad_gen = None;
-        let window_start = round_to_minute(get_timestamp(ntp_diff.load(Ordering::Relaxed)))
-            - Duration::from_secs(2 * SECONDS_IN_MINUTE);
+        let window_start =
+            round_to_minute(time_source.now()) - Duration::from_secs(2 * SECONDS_IN_MINUTE);
 
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(output) = second_output.filter(|_| metrics_type.contains(MetricsType::SECOND)) {
             second_quad_gen = Some(SubQuadGen {
                 id,
-                output: second_output,
+                output,
                 metrics_type: MetricsType::SECOND,
                 window_start,
                 slot_interval: 1,
@@ -743,7 +770,7 @@ impl QuadrupleGenerator {
                 stashs: VecDeque::with_capacity(second_slots),
                 connections: VecDeque::with_capacity(second_slots),
                 counter: Arc::new(QgCounter::default()),
-                ntp_diff: ntp_diff.clone(),
+                time_source: time_source.clone(),
                 // traffic_setter: traffic_setter,
             });
 
@@ -767,10 +794,10 @@ impl QuadrupleGenerator {
             );
         }
 
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(output) = minute_output.filter(|_| metrics_type.contains(MetricsType::MINUTE)) {
             minute_quad_gen = Some(SubQuadGen {
                 id,
-                output: minute_output,
+                output,
                 metrics_type: MetricsType::MINUTE,
                 window_start,
                 slot_interval: 60,
@@ -779,7 +806,7 @@ impl QuadrupleGenerator {
                 stashs: VecDeque::with_capacity(minute_slots),
                 connections: VecDeque::with_capacity(minute_slots),
                 counter: Arc::new(QgCounter::default()),
-                ntp_diff: ntp_diff.clone(),
+                time_source: time_source.clone(),
                 // traffic_setter: traffic_setter,
             });
 
@@ -819,8 +846,10 @@ impl QuadrupleGenerator {
             l7_metrics_enabled,
             vtap_flow_1s_enabled,
             collector_enabled,
+            sampling_ratio,
+            security_event,
             running,
-            ntp_diff,
+            time_source,
             stats,
         }
     }
@@ -869,8 +898,11 @@ impl QuadrupleGenerator {
         self.policy_ids[0].clear();
         self.policy_ids[1].clear();
 
-        let (flow_meter, app_meter) =
-            Self::generate_meter(&tagged_flow, self.l7_metrics_enabled.clone());
+        let (flow_meter, app_meter) = Self::generate_meter(
+            &tagged_flow,
+            self.l7_metrics_enabled.clone(),
+            self.sampling_ratio.load(Ordering::Relaxed),
+        );
 
         if second_inject {
             self.second_quad_gen.as_mut().unwrap().inject_flow(
@@ -905,6 +937,7 @@ impl QuadrupleGenerator {
     fn generate_meter(
         tagged_flow: &TaggedFlow,
         l7_metrics_enabled: Arc<AtomicBool>,
+        sampling_ratio: u32,
     ) -> (FlowMeter, AppMeter) {
         let (mut flow_meter, mut app_meter) = (FlowMeter::default(), AppMeter::default());
 
@@ -927,7 +960,9 @@ impl QuadrupleGenerator {
             l7_response: 0,
             syn: perf_stats.map(|s| s.tcp.syn_count).unwrap_or_default(),
             synack: perf_stats.map(|s| s.tcp.synack_count).unwrap_or_default(),
+            ..Default::default()
         };
+        flow_meter.traffic.scale(sampling_ratio);
         if tagged_flow.flow.flow_key.proto == IpProtocol::Tcp {
             match tagged_flow.flow.close_type {
                 CloseType::TcpServerRst => flow_meter.anomaly.server_rst_flow = 1,
@@ -1039,6 +1074,22 @@ impl QuadrupleGenerator {
             }
         }
 
+        // 流最后一个周期可能没有L7数据，连接统计不依赖stats.l7_protocol
+        let request_count = stats.l7.connection_request_count;
+        let first_request_delay = stats.l7.first_request_delay;
+        if request_count > 0 || first_request_delay > 0 {
+            let closed =
+                tagged_flow.flow.close_type != CloseType::ForcedReport && request_count > 0;
+            app_meter.connection = AppConnection {
+                closed: closed as u32,
+                closed_request: if closed { request_count } else { 0 },
+                single_request: (closed && request_count == 1) as u32,
+                first_request_delay_max: first_request_delay,
+                first_request_delay_sum: first_request_delay as u64,
+                first_request_delay_count: (first_request_delay > 0) as u32,
+            };
+        }
+
         (flow_meter, app_meter)
     }
 
@@ -1106,6 +1157,8 @@ impl QuadrupleGenerator {
             match self.input.recv(Some(Duration::from_secs(3))) {
                 Ok(tagged_flow) => {
                     let tagged_flow = Arc::new(*tagged_flow);
+                    self.security_event.add(&tagged_flow);
+                    self.security_event.flush(tagged_flow.flow.flow_stat_time);
                     if let Err(_) = self.output_flow.send(tagged_flow.clone()) {
                         debug!("qg push tagged flows to l4_flow queue failed maybe queue have terminated");
                     }
@@ -1114,7 +1167,9 @@ impl QuadrupleGenerator {
                     }
                 }
                 Err(Error::Timeout) => {
-                    self.handle(None, get_timestamp(self.ntp_diff.load(Ordering::Relaxed)));
+                    let now = self.time_source.now();
+                    self.security_event.flush(now);
+                    self.handle(None, now);
                 }
                 Err(Error::Terminated(_, _)) => {
                     if let Some(g) = self.second_quad_gen.as_mut() {
@@ -1136,6 +1191,7 @@ mod test {
 
     use super::*;
 
+    use crate::common::flow::{FlowPerfStats, L7PerfStats};
     use crate::debug::QueueDebugger;
     use crate::utils::queue;
 
@@ -1157,9 +1213,9 @@ mod test {
 
     #[test]
     fn second_inject_flow() {
-        let ntp_diff = Arc::new(AtomicI64::new(0));
-        let window_start = round_to_minute(get_timestamp(ntp_diff.load(Ordering::Relaxed)))
-            - Duration::from_secs(2 * SECONDS_IN_MINUTE);
+        let time_source = TimeSource::default();
+        let window_start =
+            round_to_minute(time_source.now()) - Duration::from_secs(2 * SECONDS_IN_MINUTE);
         let queue_debugger = QueueDebugger::new();
         let (s, r, _) = queue::bounded_with_debug(100, "", &queue_debugger);
         let slots = 30u64;
@@ -1174,7 +1230,7 @@ mod test {
             stashs: VecDeque::with_capacity(slots as usize),
             connections: VecDeque::with_capacity(slots as usize),
             counter: Arc::new(QgCounter::default()),
-            ntp_diff,
+            time_source,
         };
         for _ in 0..slots as usize {
             quad_gen.stashs.push_back(QuadrupleStash::new());
@@ -1232,4 +1288,43 @@ mod test {
             assert_eq!(ret.flow_meter.flow_load.load, 2);
         }
     }
+
+    #[test]
+    fn connection_meter() {
+        let l7_metrics_enabled = Arc::new(AtomicBool::new(true));
+        let mut tagged_flow = TaggedFlow::default();
+        tagged_flow.flow.flow_key.proto = IpProtocol::Tcp;
+        tagged_flow.flow.close_type = CloseType::ForcedReport;
+        tagged_flow.flow.flow_perf_stats = Some(FlowPerfStats {
+            l7_protocol: L7Protocol::Http1,
+            l7: L7PerfStats {
+                request_count: 1,
+                connection_request_count: 1,
+                first_request_delay: 1500,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let (_, app_meter) =
+            QuadrupleGenerator::generate_meter(&tagged_flow, l7_metrics_enabled.clone(), 1);
+        assert_eq!(app_meter.connection.closed, 0);
+        assert_eq!(app_meter.connection.first_request_delay_sum, 1500);
+        assert_eq!(app_meter.connection.first_request_delay_count, 1);
+
+        // 最后一个周期没有L7数据，按连接累计的请求数统计
+        tagged_flow.flow.close_type = CloseType::TcpFin;
+        tagged_flow.flow.flow_perf_stats = Some(FlowPerfStats {
+            l7: L7PerfStats {
+                connection_request_count: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let (_, app_meter) =
+            QuadrupleGenerator::generate_meter(&tagged_flow, l7_metrics_enabled, 1);
+        assert_eq!(app_meter.connection.closed, 1);
+        assert_eq!(app_meter.connection.closed_request, 5);
+        assert_eq!(app_meter.connection.single_request, 0);
+        assert_eq!(app_meter.connection.first_request_delay_count, 0);
+    }
 }
@@ -0,0 +1,468 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex,
+};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use prost::Message;
+
+use crate::common::{
+    enums::{IpProtocol, TcpFlags},
+    flow::CloseType,
+    tagged_flow::TaggedFlow,
+};
+use crate::config::SecurityEventConfig;
+use crate::proto::flow_log;
+use crate::sender::SendItem;
+use crate::utils::queue::DebugSender;
+
+// 单个源或目标记录的不同端口、主机数上限，超过后证据计数不再增加
+const MAX_DISTINCT: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityEventType {
+    PortScan,
+    HostScan,
+    SynFlood,
+}
+
+impl From<SecurityEventType> for flow_log::SecurityEventType {
+    fn from(t: SecurityEventType) -> Self {
+        match t {
+            SecurityEventType::PortScan => flow_log::SecurityEventType::PortScan,
+            SecurityEventType::HostScan => flow_log::SecurityEventType::HostScan,
+            SecurityEventType::SynFlood => flow_log::SecurityEventType::SynFlood,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecurityEvent {
+    pub event_type: SecurityEventType,
+    pub vtap_id: u16,
+    pub start_time: u32,
+    pub end_time: u32,
+    // 主机扫描的ip_dst及SYN Flood的ip_src为未指定地址
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub l3_epc_id_src: i32,
+    pub l3_epc_id_dst: i32,
+    pub port_dst: u16,
+
+    pub syn_only_flows: u32,
+    pub syn_packets: u32,
+    pub distinct_ports: u32,
+    pub distinct_hosts: u32,
+    pub distinct_sources: u32,
+}
+
+impl SecurityEvent {
+    pub fn encode(self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let pb_event: flow_log::SecurityEvent = self.into();
+        pb_event.encode(buf).map(|_| pb_event.encoded_len())
+    }
+}
+
+fn split_ip(ip: IpAddr) -> (u32, Vec<u8>) {
+    match ip {
+        IpAddr::V4(ip4) => (u32::from_be_bytes(ip4.octets()), vec![]),
+        IpAddr::V6(ip6) => (0, ip6.octets().to_vec()),
+    }
+}
+
+impl From<SecurityEvent> for flow_log::SecurityEvent {
+    fn from(e: SecurityEvent) -> Self {
+        let is_ipv6 = match e.event_type {
+            SecurityEventType::SynFlood => e.ip_dst.is_ipv6(),
+            _ => e.ip_src.is_ipv6(),
+        };
+        let (ip_src, ip6_src) = split_ip(e.ip_src);
+        let (ip_dst, ip6_dst) = split_ip(e.ip_dst);
+        flow_log::SecurityEvent {
+            event_type: flow_log::SecurityEventType::from(e.event_type) as i32,
+            vtap_id: e.vtap_id as u32,
+            start_time: e.start_time,
+            end_time: e.end_time,
+            is_ipv6: is_ipv6 as u32,
+            ip_src,
+            ip6_src,
+            ip_dst,
+            ip6_dst,
+            l3_epc_id_src: e.l3_epc_id_src,
+            l3_epc_id_dst: e.l3_epc_id_dst,
+            port_dst: e.port_dst as u32,
+            syn_only_flows: e.syn_only_flows,
+            syn_packets: e.syn_packets,
+            distinct_ports: e.distinct_ports,
+            distinct_hosts: e.distinct_hosts,
+            distinct_sources: e.distinct_sources,
+        }
+    }
+}
+
+fn insert_capped<T: Eq + std::hash::Hash>(set: &mut HashSet<T>, value: T) {
+    if set.len() < MAX_DISTINCT {
+        set.insert(value);
+    }
+}
+
+#[derive(Default)]
+struct Source {
+    epc: i32,
+    // 目的主机到扫描的端口
+    hosts: HashMap<IpAddr, (i32, HashSet<u16>)>,
+    // 目的端口到扫描的主机
+    ports: HashMap<u16, HashSet<IpAddr>>,
+    syn_only_flows: u32,
+    syn_packets: u32,
+}
+
+#[derive(Default)]
+struct Target {
+    epc: i32,
+    sources: HashSet<IpAddr>,
+    syn_only_flows: u32,
+    syn_packets: u32,
+}
+
+#[derive(Default)]
+struct Window {
+    config: SecurityEventConfig,
+    vtap_id: u16,
+    start: u64,
+    sources: HashMap<IpAddr, Source>,
+    targets: HashMap<(IpAddr, u16), Target>,
+    // 超出容量未统计的流数
+    overflow: u64,
+}
+
+impl Window {
+    fn add(&mut self, flow: &TaggedFlow, syn_packets: u32) {
+        let key = &flow.flow.flow_key;
+        let (src, dst) = (
+            &flow.flow.flow_metrics_peers[0],
+            &flow.flow.flow_metrics_peers[1],
+        );
+        let capacity = self.config.capacity;
+
+        if self.sources.len() < capacity || self.sources.contains_key(&key.ip_src) {
+            let s = self.sources.entry(key.ip_src).or_insert_with(|| Source {
+                epc: src.l3_epc_id,
+                ..Default::default()
+            });
+            Self::add_source(s, key.ip_dst, dst.l3_epc_id, key.port_dst, syn_packets);
+        } else {
+            self.overflow += 1;
+        }
+
+        let target_key = (key.ip_dst, key.port_dst);
+        if self.targets.len() >= capacity && !self.targets.contains_key(&target_key) {
+            self.overflow += 1;
+            return;
+        }
+        let target = self.targets.entry(target_key).or_insert_with(|| Target {
+            epc: dst.l3_epc_id,
+            ..Default::default()
+        });
+        insert_capped(&mut target.sources, key.ip_src);
+        target.syn_only_flows += 1;
+        target.syn_packets += syn_packets;
+    }
+
+    fn add_source(s: &mut Source, ip_dst: IpAddr, epc_dst: i32, port_dst: u16, syn_packets: u32) {
+        s.syn_only_flows += 1;
+        s.syn_packets += syn_packets;
+        if s.hosts.len() < MAX_DISTINCT || s.hosts.contains_key(&ip_dst) {
+            let (_, ports) = s
+                .hosts
+                .entry(ip_dst)
+                .or_insert_with(|| (epc_dst, HashSet::new()));
+            insert_capped(ports, port_dst);
+        }
+        if s.ports.len() < MAX_DISTINCT || s.ports.contains_key(&port_dst) {
+            insert_capped(s.ports.entry(port_dst).or_default(), ip_dst);
+        }
+    }
+
+    fn event(&self, event_type: SecurityEventType, end: u64) -> SecurityEvent {
+        SecurityEvent {
+            event_type,
+            vtap_id: self.vtap_id,
+            start_time: self.start as u32,
+            end_time: end as u32,
+            ip_src: Ipv4Addr::UNSPECIFIED.into(),
+            ip_dst: Ipv4Addr::UNSPECIFIED.into(),
+            l3_epc_id_src: 0,
+            l3_epc_id_dst: 0,
+            port_dst: 0,
+            syn_only_flows: 0,
+            syn_packets: 0,
+            distinct_ports: 0,
+            distinct_hosts: 0,
+            distinct_sources: 0,
+        }
+    }
+
+    fn evaluate(&self, end: u64) -> Vec<SecurityEvent> {
+        let config = &self.config;
+        let mut events = vec![];
+        for (ip_src, s) in self.sources.iter() {
+            if config.port_scan_threshold > 0 {
+                for (ip_dst, (epc_dst, ports)) in s.hosts.iter() {
+                    if ports.len() < config.port_scan_threshold as usize {
+                        continue;
+                    }
+                    events.push(SecurityEvent {
+                        ip_src: *ip_src,
+                        ip_dst: *ip_dst,
+                        l3_epc_id_src: s.epc,
+                        l3_epc_id_dst: *epc_dst,
+                        syn_only_flows: s.syn_only_flows,
+                        syn_packets: s.syn_packets,
+                        distinct_ports: ports.len() as u32,
+                        distinct_hosts: s.hosts.len() as u32,
+                        ..self.event(SecurityEventType::PortScan, end)
+                    });
+                }
+            }
+            if config.host_scan_threshold > 0 {
+                for (port_dst, hosts) in s.ports.iter() {
+                    if hosts.len() < config.host_scan_threshold as usize {
+                        continue;
+                    }
+                    events.push(SecurityEvent {
+                        ip_src: *ip_src,
+                        l3_epc_id_src: s.epc,
+                        port_dst: *port_dst,
+                        syn_only_flows: s.syn_only_flows,
+                        syn_packets: s.syn_packets,
+                        distinct_ports: s.ports.len() as u32,
+                        distinct_hosts: hosts.len() as u32,
+                        ..self.event(SecurityEventType::HostScan, end)
+                    });
+                }
+            }
+        }
+        if config.syn_flood_threshold > 0 {
+            for ((ip_dst, port_dst), t) in self.targets.iter() {
+                if t.syn_only_flows < config.syn_flood_threshold {
+                    continue;
+                }
+                events.push(SecurityEvent {
+                    ip_dst: *ip_dst,
+                    l3_epc_id_dst: t.epc,
+                    port_dst: *port_dst,
+                    syn_only_flows: t.syn_only_flows,
+                    syn_packets: t.syn_packets,
+                    distinct_sources: t.sources.len() as u32,
+                    ..self.event(SecurityEventType::SynFlood, end)
+                });
+            }
+        }
+        events
+    }
+
+    fn clear(&mut self, start: u64) {
+        self.start = start;
+        self.sources.clear();
+        self.targets.clear();
+        self.overflow = 0;
+    }
+}
+
+// 所有QuadrupleGenerator共享，同一扫描源的流可能分布在不同的dispatcher
+pub struct SecurityEventDetector {
+    enabled: AtomicBool,
+    // 当前窗口的结束时间，用于避免每条流都加锁，为0时窗口未开始
+    window_end: AtomicU64,
+    window: Mutex<Window>,
+    output: DebugSender<SendItem>,
+}
+
+impl SecurityEventDetector {
+    pub fn new(output: DebugSender<SendItem>) -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            window_end: AtomicU64::new(0),
+            window: Mutex::new(Window::default()),
+            output,
+        }
+    }
+
+    pub fn update_config(&self, config: &SecurityEventConfig, vtap_id: u16) {
+        let mut window = self.window.lock().unwrap();
+        if window.config == *config && window.vtap_id == vtap_id {
+            return;
+        }
+        info!("security event config updated: {:?}", config);
+        window.config = config.clone();
+        window.vtap_id = vtap_id;
+        window.clear(0);
+        self.window_end.store(0, Ordering::Relaxed);
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+    }
+
+    // 客户端只发送了SYN，未完成握手即结束的流
+    fn is_syn_only(flow: &TaggedFlow) -> bool {
+        let flow = &flow.flow;
+        let flags = flow.flow_metrics_peers[0].tcp_flags;
+        flow.flow_key.proto == IpProtocol::Tcp
+            && flow.close_type != CloseType::ForcedReport
+            && flags.contains(TcpFlags::SYN)
+            && !flags.contains(TcpFlags::ACK)
+    }
+
+    pub fn add(&self, flow: &TaggedFlow) {
+        if !self.enabled.load(Ordering::Relaxed) || !Self::is_syn_only(flow) {
+            return;
+        }
+        let syn_packets = flow
+            .flow
+            .flow_perf_stats
+            .as_ref()
+            .map(|s| s.tcp.syn_count)
+            .unwrap_or(1);
+        let mut window = self.window.lock().unwrap();
+        if window.start == 0 {
+            window.start = flow.flow.flow_stat_time.as_secs();
+            self.window_end.store(
+                window.start + window.config.window.as_secs(),
+                Ordering::Relaxed,
+            );
+        }
+        window.add(flow, syn_packets);
+    }
+
+    // 窗口结束时输出事件，返回输出的事件数
+    pub fn flush(&self, now: Duration) -> usize {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let now = now.as_secs();
+        let window_end = self.window_end.load(Ordering::Relaxed);
+        if window_end == 0 || now < window_end {
+            return 0;
+        }
+        let mut window = self.window.lock().unwrap();
+        if window.start == 0 || now < window.start + window.config.window.as_secs() {
+            return 0;
+        }
+        if window.overflow > 0 {
+            warn!(
+                "security event detector exceeded capacity {}, {} flows ignored",
+                window.config.capacity, window.overflow
+            );
+        }
+        let events = window.evaluate(now);
+        window.clear(now);
+        self.window_end
+            .store(now + window.config.window.as_secs(), Ordering::Relaxed);
+        drop(window);
+
+        let count = events.len();
+        for event in events {
+            debug!("security event: {:?}", event);
+            if self
+                .output
+                .send(SendItem::SecurityEvent(Box::new(event)))
+                .is_err()
+            {
+                debug!("security event queue terminated");
+                break;
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::debug::QueueDebugger;
+    use crate::utils::queue::bounded_with_debug;
+
+    fn flow(src: &str, dst: &str, port: u16, flags: TcpFlags) -> TaggedFlow {
+        let mut flow = TaggedFlow::default();
+        flow.flow.flow_key.proto = IpProtocol::Tcp;
+        flow.flow.flow_key.ip_src = src.parse().unwrap();
+        flow.flow.flow_key.ip_dst = dst.parse().unwrap();
+        flow.flow.flow_key.port_dst = port;
+        flow.flow.close_type = CloseType::ClientSynRepeat;
+        flow.flow.flow_stat_time = Duration::from_secs(100);
+        flow.flow.flow_metrics_peers[0].tcp_flags = flags;
+        flow
+    }
+
+    #[test]
+    fn scan_and_flood() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(64, "", &queue_debugger);
+        let detector = SecurityEventDetector::new(s);
+        detector.update_config(
+            &SecurityEventConfig {
+                enabled: true,
+                port_scan_threshold: 10,
+                host_scan_threshold: 10,
+                syn_flood_threshold: 20,
+                ..Default::default()
+            },
+            1,
+        );
+
+        for port in 0..10 {
+            detector.add(&flow("10.0.0.1", "10.0.1.1", 1000 + port, TcpFlags::SYN));
+        }
+        for host in 0..10 {
+            let dst = format!("10.0.2.{}", host);
+            detector.add(&flow("10.0.0.2", &dst, 22, TcpFlags::SYN));
+        }
+        for src in 0..20 {
+            let src = format!("10.0.3.{}", src);
+            detector.add(&flow(&src, "10.0.4.1", 80, TcpFlags::SYN));
+        }
+        // 完成握手的流不计入
+        for port in 0..20 {
+            detector.add(&flow("10.0.0.3", "10.0.1.1", port, TcpFlags::SYN_ACK));
+        }
+
+        assert_eq!(detector.flush(Duration::from_secs(120)), 0);
+        assert_eq!(detector.flush(Duration::from_secs(160)), 3);
+
+        let mut events = vec![];
+        while let Ok(SendItem::SecurityEvent(e)) = r.recv(Some(Duration::from_millis(10))) {
+            events.push(*e);
+        }
+        events.sort_by_key(|e| e.event_type as u8);
+        assert_eq!(events[0].event_type, SecurityEventType::PortScan);
+        assert_eq!(events[0].ip_src, "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(events[0].distinct_ports, 10);
+        assert_eq!(events[1].event_type, SecurityEventType::HostScan);
+        assert_eq!(events[1].port_dst, 22);
+        assert_eq!(events[1].distinct_hosts, 10);
+        assert_eq!(events[2].event_type, SecurityEventType::SynFlood);
+        assert_eq!(events[2].syn_only_flows, 20);
+        assert_eq!(events[2].distinct_sources, 20);
+        assert_eq!(events[2].start_time, 100);
+        assert_eq!(events[2].end_time, 160);
+    }
+}
@@ -0,0 +1,293 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use arc_swap::ArcSwap;
+use ipnet::IpNet;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serializer};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub type BusinessLabels = BTreeMap<String, String>;
+
+pub fn serialize_labels<S>(
+    labels: &Option<Arc<BusinessLabels>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match labels {
+        Some(l) => serializer.collect_map(l.iter()),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn to_pb_labels(labels: Option<Arc<BusinessLabels>>) -> HashMap<String, String> {
+    match labels {
+        Some(l) => l.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        None => HashMap::new(),
+    }
+}
+
+#[derive(Deserialize)]
+struct LabelEntry {
+    target: String,
+    #[serde(default)]
+    labels: BusinessLabels,
+}
+
+#[derive(Default)]
+pub struct LabelTable {
+    endpoints: HashMap<(IpAddr, u16), Arc<BusinessLabels>>,
+    // 按前缀长度降序排列，查找时第一个命中即为最长前缀
+    cidrs: Vec<(IpNet, Arc<BusinessLabels>)>,
+}
+
+impl LabelTable {
+    pub fn lookup(&self, ip: IpAddr, port: u16) -> Option<Arc<BusinessLabels>> {
+        if let Some(labels) = self.endpoints.get(&(ip, port)) {
+            return Some(labels.clone());
+        }
+        self.cidrs
+            .iter()
+            .find(|(net, _)| net.contains(&ip))
+            .map(|(_, labels)| labels.clone())
+    }
+
+    fn len(&self) -> usize {
+        self.endpoints.len() + self.cidrs.len()
+    }
+
+    fn insert(&mut self, target: &str, labels: BusinessLabels) -> Result<(), String> {
+        if labels.is_empty() {
+            return Err(format!("no labels for {}", target));
+        }
+        let labels = Arc::new(labels);
+        let target = target.trim();
+        if target.contains('/') {
+            let net = target
+                .parse::<IpNet>()
+                .map_err(|e| format!("invalid cidr {}: {}", target, e))?;
+            self.cidrs.push((net.trunc(), labels));
+        } else if let Ok(addr) = target.parse::<SocketAddr>() {
+            self.endpoints.insert((addr.ip(), addr.port()), labels);
+        } else {
+            let ip = target
+                .parse::<IpAddr>()
+                .map_err(|e| format!("invalid target {}: {}", target, e))?;
+            self.cidrs.push((IpNet::from(ip), labels));
+        }
+        Ok(())
+    }
+
+    fn sort(&mut self) {
+        self.cidrs.sort_by_key(|(net, _)| Reverse(net.prefix_len()));
+    }
+
+    // 每行格式为: 10.0.0.0/8,key=value,key2=value2，#开头为注释
+    pub fn from_csv(content: &str) -> Result<Self, String> {
+        let mut table = Self::default();
+        for (n, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut columns = line.split(',');
+            let target = columns.next().unwrap_or_default();
+            let mut labels = BusinessLabels::new();
+            for column in columns {
+                match column.split_once('=') {
+                    Some((k, v)) if !k.trim().is_empty() => {
+                        labels.insert(k.trim().to_string(), v.trim().to_string());
+                    }
+                    _ => return Err(format!("line {}: invalid label {}", n + 1, column)),
+                }
+            }
+            table
+                .insert(target, labels)
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        table.sort();
+        Ok(table)
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self, String> {
+        let entries: Vec<LabelEntry> = serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+        let mut table = Self::default();
+        for entry in entries {
+            table.insert(&entry.target, entry.labels)?;
+        }
+        table.sort();
+        Ok(table)
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Self::from_csv(&content),
+        }
+    }
+}
+
+// 定期检查标签文件的修改时间，变化后重新加载，加载失败时保留旧的标签
+pub struct BusinessLabeler {
+    path: PathBuf,
+    table: Arc<ArcSwap<LabelTable>>,
+    running: Arc<Mutex<bool>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    timer: Arc<Condvar>,
+}
+
+impl BusinessLabeler {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            table: Arc::new(ArcSwap::from_pointee(LabelTable::default())),
+            running: Arc::new(Mutex::new(false)),
+            thread: Mutex::new(None),
+            timer: Arc::new(Condvar::new()),
+        }
+    }
+
+    pub fn lookup(&self, ip: IpAddr, port: u16) -> Option<Arc<BusinessLabels>> {
+        self.table.load().lookup(ip, port)
+    }
+
+    fn refresh(path: &Path, table: &ArcSwap<LabelTable>, last_modified: &mut Option<SystemTime>) {
+        let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(m) => m,
+            Err(e) => {
+                debug!("stat business label file {} failed: {}", path.display(), e);
+                return;
+            }
+        };
+        if *last_modified == Some(modified) {
+            return;
+        }
+        *last_modified = Some(modified);
+        match LabelTable::load(path) {
+            Ok(t) => {
+                info!(
+                    "load {} business label entries from {}",
+                    t.len(),
+                    path.display()
+                );
+                table.store(Arc::new(t));
+            }
+            Err(e) => warn!("load business label file {} failed: {}", path.display(), e),
+        }
+    }
+
+    pub fn start(&self) {
+        let mut running_lock = self.running.lock().unwrap();
+        if *running_lock {
+            return;
+        }
+        *running_lock = true;
+        drop(running_lock);
+
+        let path = self.path.clone();
+        let table = self.table.clone();
+        let running = self.running.clone();
+        let timer = self.timer.clone();
+
+        *self.thread.lock().unwrap() = Some(thread::spawn(move || {
+            let mut last_modified = None;
+            loop {
+                Self::refresh(&path, &table, &mut last_modified);
+
+                let guard = running.lock().unwrap();
+                if !*guard {
+                    break;
+                }
+                let (guard, _) = timer.wait_timeout(guard, REFRESH_INTERVAL).unwrap();
+                if !*guard {
+                    break;
+                }
+            }
+        }));
+        info!("business labeler started");
+    }
+
+    pub fn stop(&self) {
+        let mut running_lock = self.running.lock().unwrap();
+        if !*running_lock {
+            return;
+        }
+        *running_lock = false;
+        drop(running_lock);
+        self.timer.notify_one();
+
+        if let Some(handle) = self.thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+        info!("business labeler stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_lookup() {
+        let csv = "# comment\n\
+                   10.0.0.0/8,domain=payment,owner=team-a\n\
+                   10.1.0.0/16,domain=order\n\
+                   10.1.1.1:8080,domain=gateway\n";
+        let table = LabelTable::from_csv(csv).unwrap();
+        assert_eq!(table.len(), 3);
+        let lookup = |ip: &str, port| {
+            table
+                .lookup(ip.parse().unwrap(), port)
+                .map(|l| l.get("domain").cloned().unwrap())
+        };
+        assert_eq!(lookup("10.1.1.1", 8080).as_deref(), Some("gateway"));
+        assert_eq!(lookup("10.1.1.1", 80).as_deref(), Some("order"));
+        assert_eq!(lookup("10.2.0.1", 80).as_deref(), Some("payment"));
+        assert_eq!(lookup("192.168.0.1", 80), None);
+
+        let yaml = "- target: 2001:db8::/32\n  labels: {domain: v6}\n\
+                    - target: 192.168.0.1\n  labels: {domain: host}\n";
+        let table = LabelTable::from_yaml(yaml).unwrap();
+        assert_eq!(
+            table
+                .lookup("2001:db8::1".parse().unwrap(), 0)
+                .unwrap()
+                .get("domain")
+                .unwrap(),
+            "v6"
+        );
+        assert!(table.lookup("192.168.0.1".parse().unwrap(), 1).is_some());
+        assert!(table.lookup("192.168.0.2".parse().unwrap(), 1).is_none());
+
+        assert!(LabelTable::from_csv("10.0.0.0/33,a=b").is_err());
+        assert!(LabelTable::from_csv("10.0.0.1,ab").is_err());
+    }
+}
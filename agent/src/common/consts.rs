@@ -186,6 +186,8 @@ pub const TCP_OPT_FLAG_SACK: u8 = 0x38; // 0011 1000, 同时也表示SACK的字
 
 pub const TCP_OPT_WIN_SCALE_LEN: usize = 3;
 pub const TCP_OPT_MSS_LEN: usize = 4;
+// 握手指纹中记录的TCP选项个数
+pub const TCP_OPT_LAYOUT_MAX: usize = 8;
 
 pub const VLAN_ID_MASK: u16 = 0xfff;
 
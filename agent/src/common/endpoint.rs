@@ -43,6 +43,7 @@ pub struct EndpointInfo {
     pub is_vip: bool,
     pub is_local_mac: bool, // 对应平台数据中的IsLocal字段
     pub is_local_ip: bool,  // 对应平台数据中的IsLocal字段
+    pub dual_stack_id: u32, // 双栈接口的接口ID，IPv4和IPv6地址共用，非双栈接口为0
 }
 
 impl EndpointInfo {
@@ -78,6 +79,7 @@ impl EndpointInfo {
         self.l3_epc_id = data.epc_id;
         self.is_device = true;
         self.is_local_ip = data.is_local;
+        self.dual_stack_id = if data.is_dual_stack() { data.id } else { 0 };
     }
 }
 
@@ -94,6 +96,7 @@ impl Default for EndpointInfo {
             is_vip: false,
             is_local_mac: false,
             is_local_ip: false,
+            dual_stack_id: 0,
         }
     }
 }
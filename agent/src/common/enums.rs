@@ -76,7 +76,17 @@ impl PartialEq<EthernetType> for u16 {
 // IPProtocol is an enumeration of IP protocol values, and acts as a decoder
 // for any type it supports.
 #[derive(
-    Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, TryFromPrimitive, IntoPrimitive,
+    Serialize,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Clone,
+    Copy,
+    Hash,
+    TryFromPrimitive,
+    IntoPrimitive,
 )]
 #[repr(u8)]
 pub enum IpProtocol {
@@ -179,7 +189,7 @@ impl PartialEq<LinkType> for u8 {
     }
 }
 
-#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Ord)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Ord, Hash)]
 #[repr(u16)]
 pub enum TapType {
     Any,
@@ -375,6 +385,67 @@ impl TcpFlags {
     }
 }
 
+bitflags! {
+    // IPv6扩展头链中的异常，可能是攻击者用于绕过安全设备的构造
+    #[derive(Default)]
+    pub struct Ipv6Anomaly: u8 {
+        const FRAGMENT_WITH_ROUTING = 0b000001;
+        // Hop-by-Hop或Destination选项过长，或扩展头数量过多
+        const OVERSIZED_OPTIONS = 0b000010;
+        const ESP_AFTER_FRAGMENT = 0b000100;
+        // Hop-by-Hop不是第一个扩展头
+        const MISPLACED_HOP_BY_HOP = 0b001000;
+        // Jumbo Payload选项与payload长度或分片头冲突，或长度不超过65535
+        const INVALID_JUMBOGRAM = 0b010000;
+    }
+}
+
+impl fmt::Display for Ipv6Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bit_strs = vec![];
+        if self.contains(Self::FRAGMENT_WITH_ROUTING) {
+            bit_strs.push("FRAGMENT_WITH_ROUTING");
+        }
+        if self.contains(Self::OVERSIZED_OPTIONS) {
+            bit_strs.push("OVERSIZED_OPTIONS");
+        }
+        if self.contains(Self::ESP_AFTER_FRAGMENT) {
+            bit_strs.push("ESP_AFTER_FRAGMENT");
+        }
+        if self.contains(Self::MISPLACED_HOP_BY_HOP) {
+            bit_strs.push("MISPLACED_HOP_BY_HOP");
+        }
+        if self.contains(Self::INVALID_JUMBOGRAM) {
+            bit_strs.push("INVALID_JUMBOGRAM");
+        }
+        write!(f, "{}", bit_strs.join("|"))
+    }
+}
+
+bitflags! {
+    // TCP流中疑似路径MTU问题的迹象，常见于VPN或overlay网络
+    #[derive(Default)]
+    pub struct MtuAnomaly: u8 {
+        // SYN或SYN/ACK中的MSS过小
+        const LOW_MSS = 0b01;
+        // 重传集中在同一个较大的payload长度，疑似PMTU黑洞
+        const RETRANS_CLUSTER = 0b10;
+    }
+}
+
+impl fmt::Display for MtuAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bit_strs = vec![];
+        if self.contains(Self::LOW_MSS) {
+            bit_strs.push("LOW_MSS");
+        }
+        if self.contains(Self::RETRANS_CLUSTER) {
+            bit_strs.push("RETRANS_CLUSTER");
+        }
+        write!(f, "{}", bit_strs.join("|"))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PacketDirection {
@@ -19,6 +19,7 @@ use std::{
     mem::swap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     process,
+    sync::Arc,
     time::Duration,
 };
 
@@ -26,9 +27,10 @@ use log::{error, warn};
 use serde::Serialize;
 
 use super::{
+    business_label::{to_pb_labels, BusinessLabels},
     decapsulate::TunnelType,
-    enums::{EthernetType, IpProtocol, TapType, TcpFlags},
-    tap_port::TapPort,
+    enums::{EthernetType, IpProtocol, Ipv6Anomaly, MtuAnomaly, TapType, TcpFlags},
+    tap_port::{CapturePoint, TapPort},
 };
 
 use crate::proto::flow_log;
@@ -102,6 +104,8 @@ pub struct FlowKey {
     pub port_src: u16,
     pub port_dst: u16,
     pub proto: IpProtocol,
+    // 只用于区分数据来源，流聚合仍使用tap_port
+    pub capture_point: CapturePoint,
 }
 
 fn append_key(dst: &mut String, key: &str) {
@@ -169,6 +173,7 @@ impl FlowKey {
         append_key_value(dst, "port_src", &self.port_src.to_string());
         append_key_value(dst, "port_dst", &self.port_dst.to_string());
         append_key_string(dst, "protocol", &format!("{:?}", self.proto));
+        append_key_string(dst, "capture_point", &self.capture_point.to_string());
     }
 }
 
@@ -185,6 +190,7 @@ impl Default for FlowKey {
             port_src: 0,
             port_dst: 0,
             proto: IpProtocol::default(),
+            capture_point: CapturePoint::default(),
         }
     }
 }
@@ -232,6 +238,7 @@ impl From<FlowKey> for flow_log::FlowKey {
             port_src: f.port_src as u32,
             port_dst: f.port_dst as u32,
             proto: f.proto as u32,
+            capture_point: Some(f.capture_point.or_from(f.tap_port).into()),
         }
     }
 }
@@ -354,12 +361,30 @@ impl From<TunnelField> for flow_log::TunnelField {
 pub struct TcpPerfCountsPeer {
     pub retrans_count: u32,
     pub zero_win_count: u32,
+    pub zero_win_duration: u32, // us, 统计周期内零窗口的累计时长
+    // 按win_scale换算后的通告窗口，均值为win_sum / win_count
+    pub win_min: u32,
+    pub win_sum: u64,
+    pub win_count: u32,
+    // 重复ACK突发、乱序及虚假重传，比重传数更能反映有损路径
+    pub dup_ack_burst_count: u32,
+    pub ooo_count: u32,
+    pub spurious_retrans_count: u32,
 }
 
 impl TcpPerfCountsPeer {
     pub fn sequential_merge(&mut self, other: &TcpPerfCountsPeer) {
         self.retrans_count += other.retrans_count;
         self.zero_win_count += other.zero_win_count;
+        self.zero_win_duration += other.zero_win_duration;
+        if other.win_count > 0 && (self.win_count == 0 || self.win_min > other.win_min) {
+            self.win_min = other.win_min;
+        }
+        self.win_sum += other.win_sum;
+        self.win_count += other.win_count;
+        self.dup_ack_burst_count += other.dup_ack_burst_count;
+        self.ooo_count += other.ooo_count;
+        self.spurious_retrans_count += other.spurious_retrans_count;
     }
 }
 
@@ -368,6 +393,13 @@ impl From<TcpPerfCountsPeer> for flow_log::TcpPerfCountsPeer {
         flow_log::TcpPerfCountsPeer {
             retrans_count: p.retrans_count,
             zero_win_count: p.zero_win_count,
+            zero_win_duration: p.zero_win_duration,
+            win_min: p.win_min,
+            win_sum: p.win_sum,
+            win_count: p.win_count,
+            dup_ack_burst_count: p.dup_ack_burst_count,
+            ooo_count: p.ooo_count,
+            spurious_retrans_count: p.spurious_retrans_count,
         }
     }
 }
@@ -459,6 +491,52 @@ impl TcpPerfStats {
             "zero_win_rx",
             &self.counts_peers[1].zero_win_count.to_string(),
         );
+        append_key_value(
+            dst,
+            "zero_win_duration_tx",
+            &self.counts_peers[0].zero_win_duration.to_string(),
+        );
+        append_key_value(
+            dst,
+            "zero_win_duration_rx",
+            &self.counts_peers[1].zero_win_duration.to_string(),
+        );
+        append_key_value(dst, "win_min_tx", &self.counts_peers[0].win_min.to_string());
+        append_key_value(dst, "win_min_rx", &self.counts_peers[1].win_min.to_string());
+        append_key_value(dst, "win_sum_tx", &self.counts_peers[0].win_sum.to_string());
+        append_key_value(dst, "win_sum_rx", &self.counts_peers[1].win_sum.to_string());
+        append_key_value(
+            dst,
+            "win_count_tx",
+            &self.counts_peers[0].win_count.to_string(),
+        );
+        append_key_value(
+            dst,
+            "win_count_rx",
+            &self.counts_peers[1].win_count.to_string(),
+        );
+        append_key_value(
+            dst,
+            "dup_ack_burst_tx",
+            &self.counts_peers[0].dup_ack_burst_count.to_string(),
+        );
+        append_key_value(
+            dst,
+            "dup_ack_burst_rx",
+            &self.counts_peers[1].dup_ack_burst_count.to_string(),
+        );
+        append_key_value(dst, "ooo_tx", &self.counts_peers[0].ooo_count.to_string());
+        append_key_value(dst, "ooo_rx", &self.counts_peers[1].ooo_count.to_string());
+        append_key_value(
+            dst,
+            "spurious_retrans_tx",
+            &self.counts_peers[0].spurious_retrans_count.to_string(),
+        );
+        append_key_value(
+            dst,
+            "spurious_retrans_rx",
+            &self.counts_peers[1].spurious_retrans_count.to_string(),
+        );
     }
 
     pub fn sequential_merge(&mut self, other: &TcpPerfStats) {
@@ -542,12 +620,16 @@ pub struct FlowPerfStats {
     pub l7: L7PerfStats,
     pub l4_protocol: L4Protocol,
     pub l7_protocol: L7Protocol,
+    pub udp: UdpPerfStats,
 }
 
 impl FlowPerfStats {
     pub fn to_kv_string(&self, dst: &mut String) {
         self.tcp.to_kv_string(dst);
         self.l7.to_kv_string(dst);
+        if self.l4_protocol == L4Protocol::Udp {
+            self.udp.to_kv_string(dst);
+        }
         append_key_string(dst, "l4_protocol", &format!("{:?}", self.l4_protocol));
         append_key_string(dst, "l7_protocol", &format!("{:?}", self.l7_protocol));
     }
@@ -564,6 +646,7 @@ impl FlowPerfStats {
         }
         self.tcp.sequential_merge(&other.tcp);
         self.l7.sequential_merge(&other.l7);
+        self.udp.sequential_merge(&other.udp);
     }
 
     pub fn reverse(&mut self) {
@@ -588,6 +671,64 @@ impl From<FlowPerfStats> for flow_log::FlowPerfStats {
             l7: Some(p.l7.into()),
             l4_protocol: p.l4_protocol as u32,
             l7_protocol: p.l7_protocol as u32,
+            udp: Some(p.udp.into()),
+        }
+    }
+}
+
+// UDP请求响应协议的统计，请求和响应由UdpPerf按端口识别的协议匹配
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct UdpPerfStats {
+    pub request_count: u32,
+    pub response_count: u32,
+    pub unanswered_count: u32, // 超时未响应的请求数
+    pub rrt_count: u32,
+    pub rrt_sum: u64, // us
+    pub rrt_max: u32, // us
+    // 相邻两次RRT之差的绝对值(us)
+    pub jitter_count: u32,
+    pub jitter_sum: u64,
+    pub jitter_max: u32,
+}
+
+impl UdpPerfStats {
+    pub fn to_kv_string(&self, dst: &mut String) {
+        append_key_value(dst, "udp_request", &self.request_count.to_string());
+        append_key_value(dst, "udp_response", &self.response_count.to_string());
+        append_key_value(dst, "udp_unanswered", &self.unanswered_count.to_string());
+        append_key_value(dst, "udp_rrt_count", &self.rrt_count.to_string());
+        append_key_value(dst, "udp_rrt_sum", &self.rrt_sum.to_string());
+        append_key_value(dst, "udp_rrt_max", &self.rrt_max.to_string());
+        append_key_value(dst, "udp_jitter_count", &self.jitter_count.to_string());
+        append_key_value(dst, "udp_jitter_sum", &self.jitter_sum.to_string());
+        append_key_value(dst, "udp_jitter_max", &self.jitter_max.to_string());
+    }
+
+    pub fn sequential_merge(&mut self, other: &UdpPerfStats) {
+        self.request_count += other.request_count;
+        self.response_count += other.response_count;
+        self.unanswered_count += other.unanswered_count;
+        self.rrt_count += other.rrt_count;
+        self.rrt_sum += other.rrt_sum;
+        self.rrt_max = self.rrt_max.max(other.rrt_max);
+        self.jitter_count += other.jitter_count;
+        self.jitter_sum += other.jitter_sum;
+        self.jitter_max = self.jitter_max.max(other.jitter_max);
+    }
+}
+
+impl From<UdpPerfStats> for flow_log::UdpPerfStats {
+    fn from(p: UdpPerfStats) -> Self {
+        flow_log::UdpPerfStats {
+            request_count: p.request_count,
+            response_count: p.response_count,
+            unanswered_count: p.unanswered_count,
+            rrt_count: p.rrt_count,
+            rrt_sum: p.rrt_sum,
+            rrt_max: p.rrt_max,
+            jitter_count: p.jitter_count,
+            jitter_sum: p.jitter_sum,
+            jitter_max: p.jitter_max,
         }
     }
 }
@@ -596,12 +737,19 @@ impl From<FlowPerfStats> for flow_log::FlowPerfStats {
 pub struct L7PerfStats {
     pub request_count: u32,
     pub response_count: u32,
-    pub err_client_count: u32, // client端原因导致的响应异常数量
-    pub err_server_count: u32, // server端原因导致的响应异常数量
-    pub err_timeout: u32,      // request请求timeout数量
-    pub rrt_count: u32,        // u32可记录40000M时延, 一条流在一分钟内的请求数远无法达到此数值
-    pub rrt_sum: u64,          // us RRT(Request Response Time)
-    pub rrt_max: u32,          // us agent保证在3600s以内
+    pub err_client_count: u32,  // client端原因导致的响应异常数量
+    pub err_server_count: u32,  // server端原因导致的响应异常数量
+    pub err_timeout: u32,       // request请求timeout数量
+    pub rrt_count: u32,         // u32可记录40000M时延, 一条流在一分钟内的请求数远无法达到此数值
+    pub rrt_sum: u64,           // us RRT(Request Response Time)
+    pub rrt_max: u32,           // us agent保证在3600s以内
+    pub rrt_outlier_count: u32, // 超过配置上限被截断的RRT数量
+    pub redirect_count: u32,    // 重定向响应数量，如Redis集群的MOVED/ASK
+    // 仅HTTP，连接建立以来的请求总数，及客户端SYN到首个请求的时延(us)，时延只上报一次
+    pub connection_request_count: u32,
+    pub first_request_delay: u32,
+    // 按API细分的统计，目前仅Kafka
+    pub api_stats: Vec<L7ApiPerfStats>,
 }
 
 impl L7PerfStats {
@@ -614,6 +762,42 @@ impl L7PerfStats {
         append_key_value(dst, "rrt_count", &self.rrt_count.to_string());
         append_key_value(dst, "rrt_sum", &self.rrt_sum.to_string());
         append_key_value(dst, "rrt_max", &self.rrt_max.to_string());
+        append_key_value(dst, "rrt_outlier", &self.rrt_outlier_count.to_string());
+        append_key_value(dst, "l7_redirect", &self.redirect_count.to_string());
+        if self.connection_request_count > 0 || self.first_request_delay > 0 {
+            append_key_value(
+                dst,
+                "connection_request",
+                &self.connection_request_count.to_string(),
+            );
+            append_key_value(
+                dst,
+                "first_request_delay",
+                &self.first_request_delay.to_string(),
+            );
+        }
+        for s in self.api_stats.iter() {
+            append_key_value(
+                dst,
+                &format!("l7_api_{}_request", s.api),
+                &s.request_count.to_string(),
+            );
+            append_key_value(
+                dst,
+                &format!("l7_api_{}_rrt_count", s.api),
+                &s.rrt_count.to_string(),
+            );
+            append_key_value(
+                dst,
+                &format!("l7_api_{}_rrt_sum", s.api),
+                &s.rrt_sum.to_string(),
+            );
+            append_key_value(
+                dst,
+                &format!("l7_api_{}_rrt_max", s.api),
+                &s.rrt_max.to_string(),
+            );
+        }
     }
 
     pub fn sequential_merge(&mut self, other: &L7PerfStats) {
@@ -627,6 +811,21 @@ impl L7PerfStats {
         if self.rrt_max < other.rrt_max {
             self.rrt_max = other.rrt_max
         }
+        self.rrt_outlier_count += other.rrt_outlier_count;
+        self.redirect_count += other.redirect_count;
+        // 累计值，取较新的
+        if other.connection_request_count > self.connection_request_count {
+            self.connection_request_count = other.connection_request_count;
+        }
+        if other.first_request_delay > 0 {
+            self.first_request_delay = other.first_request_delay;
+        }
+        for s in other.api_stats.iter() {
+            match self.api_stats.iter_mut().find(|x| x.api == s.api) {
+                Some(x) => x.sequential_merge(s),
+                None => self.api_stats.push(s.clone()),
+            }
+        }
     }
 }
 
@@ -641,6 +840,47 @@ impl From<L7PerfStats> for flow_log::L7PerfStats {
             rrt_count: p.rrt_count,
             rrt_sum: p.rrt_sum,
             rrt_max: p.rrt_max,
+            rrt_outlier_count: p.rrt_outlier_count,
+            redirect_count: p.redirect_count,
+            connection_request_count: p.connection_request_count,
+            first_request_delay: p.first_request_delay,
+            api_stats: p.api_stats.into_iter().map(|s| s.into()).collect(),
+        }
+    }
+}
+
+// 按API(如Kafka的api_key)细分的请求数和时延
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct L7ApiPerfStats {
+    pub api: u16,
+    pub request_count: u32,
+    pub response_count: u32,
+    pub rrt_count: u32,
+    pub rrt_sum: u64, // us
+    pub rrt_max: u32, // us
+}
+
+impl L7ApiPerfStats {
+    pub fn sequential_merge(&mut self, other: &L7ApiPerfStats) {
+        self.request_count += other.request_count;
+        self.response_count += other.response_count;
+        self.rrt_count += other.rrt_count;
+        self.rrt_sum += other.rrt_sum;
+        if self.rrt_max < other.rrt_max {
+            self.rrt_max = other.rrt_max
+        }
+    }
+}
+
+impl From<L7ApiPerfStats> for flow_log::L7ApiPerfStats {
+    fn from(p: L7ApiPerfStats) -> Self {
+        flow_log::L7ApiPerfStats {
+            api: p.api as u32,
+            request_count: p.request_count,
+            response_count: p.response_count,
+            rrt_count: p.rrt_count,
+            rrt_sum: p.rrt_sum,
+            rrt_max: p.rrt_max,
         }
     }
 }
@@ -681,6 +921,15 @@ const L7_PROTOCOL_REDIS: u8 = 80;
 const L7_PROTOCOL_KAFKA: u8 = 100;
 const L7_PROTOCOL_MQTT: u8 = 101;
 const L7_PROTOCOL_DNS: u8 = 120;
+const L7_PROTOCOL_RADIUS: u8 = 121;
+// 协议位图为u128，工业协议暂用小于128的值
+const L7_PROTOCOL_MODBUS: u8 = 122;
+const L7_PROTOCOL_S7: u8 = 123;
+const L7_PROTOCOL_SNMP: u8 = 124;
+const L7_PROTOCOL_TLS: u8 = 125;
+const L7_PROTOCOL_NTP: u8 = 126;
+const L7_PROTOCOL_TDS: u8 = 61;
+const L7_PROTOCOL_CLICKHOUSE: u8 = 62;
 const L7_PROTOCOL_MAX: u8 = 255;
 
 #[derive(Serialize, Debug, Clone, Copy, PartialEq, Hash, Eq)]
@@ -698,6 +947,14 @@ pub enum L7Protocol {
     Kafka = L7_PROTOCOL_KAFKA,
     Mqtt = L7_PROTOCOL_MQTT,
     Dns = L7_PROTOCOL_DNS,
+    Radius = L7_PROTOCOL_RADIUS,
+    Modbus = L7_PROTOCOL_MODBUS,
+    S7 = L7_PROTOCOL_S7,
+    Snmp = L7_PROTOCOL_SNMP,
+    Tds = L7_PROTOCOL_TDS,
+    Clickhouse = L7_PROTOCOL_CLICKHOUSE,
+    Tls = L7_PROTOCOL_TLS,
+    Ntp = L7_PROTOCOL_NTP,
     Max = L7_PROTOCOL_MAX,
 }
 
@@ -721,6 +978,14 @@ impl From<u8> for L7Protocol {
             L7_PROTOCOL_KAFKA => L7Protocol::Kafka,
             L7_PROTOCOL_MQTT => L7Protocol::Mqtt,
             L7_PROTOCOL_DNS => L7Protocol::Dns,
+            L7_PROTOCOL_RADIUS => L7Protocol::Radius,
+            L7_PROTOCOL_MODBUS => L7Protocol::Modbus,
+            L7_PROTOCOL_S7 => L7Protocol::S7,
+            L7_PROTOCOL_SNMP => L7Protocol::Snmp,
+            L7_PROTOCOL_TDS => L7Protocol::Tds,
+            L7_PROTOCOL_CLICKHOUSE => L7Protocol::Clickhouse,
+            L7_PROTOCOL_TLS => L7Protocol::Tls,
+            L7_PROTOCOL_NTP => L7Protocol::Ntp,
             _ => L7Protocol::Unknown,
         }
     }
@@ -740,6 +1005,14 @@ impl From<L7Protocol> for u8 {
             L7Protocol::Kafka => L7_PROTOCOL_KAFKA,
             L7Protocol::Mqtt => L7_PROTOCOL_MQTT,
             L7Protocol::Dns => L7_PROTOCOL_DNS,
+            L7Protocol::Radius => L7_PROTOCOL_RADIUS,
+            L7Protocol::Modbus => L7_PROTOCOL_MODBUS,
+            L7Protocol::S7 => L7_PROTOCOL_S7,
+            L7Protocol::Snmp => L7_PROTOCOL_SNMP,
+            L7Protocol::Tds => L7_PROTOCOL_TDS,
+            L7Protocol::Clickhouse => L7_PROTOCOL_CLICKHOUSE,
+            L7Protocol::Tls => L7_PROTOCOL_TLS,
+            L7Protocol::Ntp => L7_PROTOCOL_NTP,
             _ => L7_PROTOCOL_UNKNOWN,
         }
     }
@@ -768,6 +1041,8 @@ pub struct FlowMetricsPeer {
     pub is_vip: bool,           // 从grpc cidr中获取
     pub is_local_mac: bool,     // 同EndpointInfo中的IsLocalMac, 流日志中不需要存储
     pub is_local_ip: bool,      // 同EndpointInfo中的IsLocalIp, 流日志中不需要存储
+    pub dual_stack_id: u32,     // 同EndpointInfo中的DualStackId, 流日志中不需要存储
+    pub tcp_fingerprint: u32,   // 本端SYN或SYN-ACK的握手指纹，0表示未见到握手包
 }
 
 impl Default for FlowMetricsPeer {
@@ -793,6 +1068,8 @@ impl Default for FlowMetricsPeer {
             is_vip: false,
             is_local_mac: false,
             is_local_ip: false,
+            dual_stack_id: 0,
+            tcp_fingerprint: 0,
         }
     }
 }
@@ -828,6 +1105,12 @@ impl FlowMetricsPeer {
         append_keys_bool(dst, "l2_end", subfix[1], self.is_l2_end);
         append_keys_bool(dst, "l3_end", subfix[1], self.is_l3_end);
         append_key_string(dst, "tcp_flags", &self.tcp_flags.to_string());
+        append_keys_value(
+            dst,
+            "tcp_fingerprint",
+            subfix[1],
+            &self.tcp_fingerprint.to_string(),
+        );
     }
 
     pub fn sequential_merge(&mut self, other: &FlowMetricsPeer) {
@@ -850,6 +1133,10 @@ impl FlowMetricsPeer {
         self.is_vip = other.is_vip;
         self.is_local_mac = other.is_local_mac;
         self.is_local_ip = other.is_local_ip;
+        self.dual_stack_id = other.dual_stack_id;
+        if other.tcp_fingerprint != 0 {
+            self.tcp_fingerprint = other.tcp_fingerprint;
+        }
     }
 }
 
@@ -872,6 +1159,7 @@ impl From<FlowMetricsPeer> for flow_log::FlowMetricsPeer {
             is_device: m.is_device as u32,
             tcp_flags: m.tcp_flags.bits() as u32,
             is_vip_interface: m.is_vip_interface as u32,
+            tcp_fingerprint: m.tcp_fingerprint,
             is_vip: m.is_vip as u32,
         }
     }
@@ -885,6 +1173,8 @@ pub struct Flow {
     pub tunnel: TunnelField,
 
     pub flow_id: u64,
+    // 流创建时生成，ForcedReport的各分段保持不变，flow_id重用时用于拼接完整的流
+    pub flow_uuid: u128,
 
     /* TCP Seq */
     pub syn_seq: u32,
@@ -911,6 +1201,18 @@ pub struct Flow {
     pub is_new_flow: bool,
     pub reversed: bool,
     pub tap_side: TapSide,
+    // 流中所有IPv6报文扩展头异常的并集
+    pub ipv6_anomaly: Ipv6Anomaly,
+    // 由TCP性能统计检测，流结束前不清除
+    pub mtu_anomaly: MtuAnomaly,
+
+    /* 发送前根据本地业务标签文件填充 */
+    pub business_labels_src: Option<Arc<BusinessLabels>>,
+    pub business_labels_dst: Option<Arc<BusinessLabels>>,
+    // 发送前根据DNS应答填充
+    pub server_domain: String,
+    // 从TLS ClientHello中获取，流结束前不清除
+    pub tls_sni: String,
 }
 
 impl Flow {
@@ -924,6 +1226,7 @@ impl Flow {
         }
 
         append_key_value(dst, "flow_id", &self.flow_id.to_string());
+        append_key_string(dst, "flow_uuid", &format_flow_uuid(self.flow_uuid));
         append_key_value(dst, "syn_seq", &self.syn_seq.to_string());
         append_key_value(dst, "syn_ack_seq", &self.synack_seq.to_string());
         append_key_value(
@@ -948,6 +1251,32 @@ impl Flow {
         append_key_string(dst, "flow_source", &format!("{:?}", self.flow_source));
         append_key_bool(dst, "is_new_flow", self.is_new_flow);
         append_key_string(dst, "tap_side", &format!("{:?}", self.tap_side));
+        if !self.ipv6_anomaly.is_empty() {
+            append_key_string(dst, "ipv6_anomaly", &self.ipv6_anomaly.to_string());
+        }
+        if !self.mtu_anomaly.is_empty() {
+            append_key_string(dst, "mtu_anomaly", &self.mtu_anomaly.to_string());
+        }
+        if !self.server_domain.is_empty() {
+            append_key_string(dst, "server_domain", &self.server_domain);
+        }
+        if !self.tls_sni.is_empty() {
+            append_key_string(dst, "tls_sni", &self.tls_sni);
+        }
+        if let Some(labels) = &self.business_labels_src {
+            append_key_value(
+                dst,
+                "business_labels_src",
+                &serde_json::to_string(labels.as_ref()).unwrap(),
+            );
+        }
+        if let Some(labels) = &self.business_labels_dst {
+            append_key_value(
+                dst,
+                "business_labels_dst",
+                &serde_json::to_string(labels.as_ref()).unwrap(),
+            );
+        }
     }
 
     pub fn sequential_merge(&mut self, other: &Flow) {
@@ -972,6 +1301,8 @@ impl Flow {
         if other.vlan > 0 {
             self.vlan = other.vlan
         }
+        self.ipv6_anomaly |= other.ipv6_anomaly;
+        self.mtu_anomaly |= other.mtu_anomaly;
 
         if other.last_keepalive_seq != 0 {
             self.last_keepalive_seq = other.last_keepalive_seq;
@@ -1100,6 +1431,7 @@ impl From<Flow> for flow_log::Flow {
                 }
             },
             flow_id: f.flow_id,
+            flow_uuid: f.flow_uuid.to_be_bytes().to_vec(),
             start_time: f.start_time.as_nanos() as u64,
             end_time: f.end_time.as_nanos() as u64,
             duration: f.duration.as_nanos() as u64,
@@ -1119,10 +1451,16 @@ impl From<Flow> for flow_log::Flow {
             queue_hash: f.queue_hash as u32,
             is_new_flow: f.is_new_flow as u32,
             tap_side: f.tap_side as u32,
+            ipv6_anomaly: f.ipv6_anomaly.bits() as u32,
+            mtu_anomaly: f.mtu_anomaly.bits() as u32,
+            server_domain: f.server_domain,
+            tls_sni: f.tls_sni,
             syn_seq: f.syn_seq,
             synack_seq: f.synack_seq,
             last_keepalive_seq: f.last_keepalive_seq,
             last_keepalive_ack: f.last_keepalive_ack,
+            business_labels_src: to_pb_labels(f.business_labels_src),
+            business_labels_dst: to_pb_labels(f.business_labels_dst),
         }
     }
 }
@@ -1524,6 +1862,25 @@ pub fn get_direction(
     )
 }
 
+// 随机生成的UUIDv4
+pub fn new_flow_uuid() -> u128 {
+    let uuid = rand::random::<u128>();
+    (uuid & !((0xf << 76) | (0x3 << 62))) | (0x4 << 76) | (0x2 << 62)
+}
+
+// 8-4-4-4-12格式
+pub fn format_flow_uuid(uuid: u128) -> String {
+    let s = format!("{:032x}", uuid);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &s[..8],
+        &s[8..12],
+        &s[12..16],
+        &s[16..20],
+        &s[20..]
+    )
+}
+
 // 生成32位flowID,确保在1分钟内1个thread的flowID不重复
 pub fn get_uniq_flow_id_in_one_minute(flow_id: u64) -> u64 {
     // flowID中时间低8位可保证1分钟内时间的唯一，counter可保证一秒内流的唯一性（假设fps < 2^24）
@@ -34,17 +34,42 @@ use super::{
     consts::*,
     decapsulate::TunnelInfo,
     endpoint::EndpointData,
-    enums::{EthernetType, HeaderType, IpProtocol, PacketDirection, TcpFlags},
+    enums::{EthernetType, HeaderType, IpProtocol, Ipv6Anomaly, PacketDirection, TcpFlags},
     flow::L7Protocol,
     lookup_key::LookupKey,
     policy::PolicyData,
-    tap_port::TapPort,
+    tap_port::{CapturePoint, TapPort},
 };
 
 #[cfg(target_os = "linux")]
 use crate::ebpf::{SK_BPF_DATA, SOCK_DIR_RCV, SOCK_DIR_SND};
 use crate::error;
-use crate::utils::net::{is_unicast_link_local, MacAddr};
+use crate::utils::{
+    hasher::jenkins64,
+    intern::intern,
+    net::{is_unicast_link_local, MacAddr},
+    packet_pool::PacketBuffer,
+};
+
+// RFC 8504 5.3: 超过这些限制的扩展头通常不是正常业务产生的
+const IPV6_MAX_EXTENSION_HEADERS: usize = 8;
+const IPV6_MAX_OPTIONS_SIZE: usize = 64;
+const IPV6_OPTION_PAD1: u8 = 0;
+const IPV6_OPTION_JUMBO_PAYLOAD: u8 = 0xC2;
+
+// 包时间戳的来源
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimestampSource {
+    Software,
+    // 网卡硬件时间戳，只从TAI换算为UTC，不经时差校正
+    Hardware,
+}
+
+impl Default for TimestampSource {
+    fn default() -> Self {
+        Self::Software
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct MetaPacket<'a> {
@@ -67,6 +92,9 @@ pub struct MetaPacket<'a> {
     offset_port_1: usize,
     offset_ipv6_last_option: usize,
     offset_ipv6_fragment_option: usize,
+    // Hop-by-Hop中Jumbo Payload选项的长度，没有该选项时为0
+    ipv6_jumbo_payload: u32,
+    pub ipv6_anomaly: Ipv6Anomaly,
 
     pub header_type: HeaderType,
     // 读取时不要直接用这个字段，用MetaPacket.GetPktSize()
@@ -90,11 +118,15 @@ pub struct MetaPacket<'a> {
 
     pub tcp_data: MetaPacketTcpHeader,
     pub tap_port: TapPort, // packet与xflow复用
+    pub capture_point: CapturePoint,
     pub payload_len: u16,
     pub vlan: u16,
+    // 两层VLAN时的外层VLAN，vlan为内层
+    pub outer_vlan: u16,
     pub direction: PacketDirection,
     pub is_active_service: bool,
     pub queue_hash: u8,
+    pub timestamp_source: TimestampSource,
 
     // for xflow
     pub packet_count: u64,
@@ -104,7 +136,7 @@ pub struct MetaPacket<'a> {
     pub source_ip: u32,
 
     // for ebpf
-    pub raw_from_ebpf: Vec<u8>,
+    pub raw_from_ebpf: PacketBuffer,
 
     pub socket_id: u64,
     pub cap_seq: u64,
@@ -113,7 +145,14 @@ pub struct MetaPacket<'a> {
     pub process_id: u32,
     pub thread_id: u32,
     pub syscall_trace_id: u64,
-    pub process_name: String,
+    pub process_name: Arc<str>,
+    // unix socket数据的对端进程，地址和端口由eBPF合成
+    pub peer_process_id: u32,
+    pub unix_socket: bool,
+    // 进程所在的cgroup v2 ID，及由此关联的容器和POD，无法关联时为空
+    pub cgroup_id: u64,
+    pub container_id: Arc<str>,
+    pub pod_id: u32,
 }
 
 impl<'a> MetaPacket<'a> {
@@ -137,6 +176,13 @@ impl<'a> MetaPacket<'a> {
         }
     }
 
+    // 从以太网帧构造MetaPacket，两端均视为终端
+    pub fn from_raw(raw: &'a [u8], timestamp: Duration) -> error::Result<Self> {
+        let mut packet = Self::empty();
+        packet.update(raw, true, true, timestamp, raw.len())?;
+        Ok(packet)
+    }
+
     pub fn reset(&mut self) {
         *self = Self::empty();
     }
@@ -189,8 +235,14 @@ impl<'a> MetaPacket<'a> {
         let packet = self.raw.as_ref().unwrap();
         let mut offset = self.header_type.min_packet_size() + self.l2_l3_opt_size;
         let payload_offset = offset + self.l4_opt_size;
+        let mut option_count = 0;
 
         while offset + 1 < payload_offset {
+            if option_count < TCP_OPT_LAYOUT_MAX {
+                self.tcp_data.option_layout =
+                    self.tcp_data.option_layout << 8 | packet[offset] as u64;
+                option_count += 1;
+            }
             // 如果不足2B，EOL和NOP都可以忽略
             let assume_length = packet[offset + 1].max(2) as usize;
             match TcpOptionNumber::new(packet[offset]) {
@@ -246,6 +298,33 @@ impl<'a> MetaPacket<'a> {
         }
     }
 
+    // p0f风格的TCP握手指纹，由IP版本、初始TTL、MSS、窗口大小、窗口扩大因子及选项顺序计算，
+    // 仅对SYN和SYN-ACK有意义，非TCP握手包返回0
+    pub fn tcp_fingerprint(&self) -> u32 {
+        if self.header_type != HeaderType::Ipv4Tcp && self.header_type != HeaderType::Ipv6Tcp
+            || !self.tcp_data.flags.contains(TcpFlags::SYN)
+        {
+            return 0;
+        }
+        // 初始TTL取不小于当前TTL的常见值
+        let initial_ttl: u64 = match self.ttl {
+            0..=32 => 32,
+            33..=64 => 64,
+            65..=128 => 128,
+            _ => 255,
+        };
+        let fields = ((self.header_type == HeaderType::Ipv6Tcp) as u64) << 48
+            | initial_ttl << 40
+            | (self.tcp_data.mss as u64) << 24
+            | (self.tcp_data.win_size as u64) << 8
+            | self.tcp_data.win_scale as u64;
+        let hash = jenkins64(jenkins64(fields) ^ self.tcp_data.option_layout);
+        match (hash ^ hash >> 32) as u32 {
+            0 => 1,
+            h => h,
+        }
+    }
+
     fn update_ip6_opt(&mut self, l2_opt_size: usize) -> (u8, usize) {
         let packet = self.raw.as_ref().unwrap();
         let mut next_header = packet[IPV6_PROTO_OFFSET + l2_opt_size];
@@ -253,7 +332,13 @@ impl<'a> MetaPacket<'a> {
         let mut option_offset = original_offset;
         self.next_header = next_header;
         let mut size_checker = packet.len() as isize - option_offset as isize;
+        let mut header_count = 0;
+        let mut has_routing = false;
         loop {
+            if header_count > IPV6_MAX_EXTENSION_HEADERS {
+                self.ipv6_anomaly |= Ipv6Anomaly::OVERSIZED_OPTIONS;
+            }
+            header_count += 1;
             if let Ok(header) = IpProtocol::try_from(next_header) {
                 match header {
                     IpProtocol::Ah => {
@@ -279,9 +364,31 @@ impl<'a> MetaPacket<'a> {
                         }
                         self.offset_ipv6_last_option = option_offset;
                         next_header = packet[option_offset];
-                        let length = packet[option_offset + 1] as usize;
-                        option_offset += length * 8 + 8;
-                        size_checker -= length as isize * 8;
+                        let length = packet[option_offset + 1] as usize * 8 + 8;
+                        size_checker -= length as isize - 8;
+                        match header {
+                            IpProtocol::Ipv6Routing => {
+                                has_routing = true;
+                                if self.offset_ipv6_fragment_option > 0 {
+                                    self.ipv6_anomaly |= Ipv6Anomaly::FRAGMENT_WITH_ROUTING;
+                                }
+                            }
+                            _ if length > IPV6_MAX_OPTIONS_SIZE => {
+                                self.ipv6_anomaly |= Ipv6Anomaly::OVERSIZED_OPTIONS;
+                            }
+                            _ => (),
+                        }
+                        if header == IpProtocol::Ipv6HopByHop {
+                            // RFC 8200: Hop-by-Hop只能紧跟在IPv6头部之后
+                            if option_offset != original_offset {
+                                self.ipv6_anomaly |= Ipv6Anomaly::MISPLACED_HOP_BY_HOP;
+                            } else if size_checker >= 0 {
+                                self.ipv6_jumbo_payload = ipv6_jumbo_payload(
+                                    &packet[option_offset + 2..option_offset + length],
+                                );
+                            }
+                        }
+                        option_offset += length;
                         if size_checker < 0 {
                             break;
                         }
@@ -294,6 +401,9 @@ impl<'a> MetaPacket<'a> {
                         }
                         self.offset_ipv6_last_option = option_offset;
                         self.offset_ipv6_fragment_option = option_offset;
+                        if has_routing {
+                            self.ipv6_anomaly |= Ipv6Anomaly::FRAGMENT_WITH_ROUTING;
+                        }
                         next_header = packet[option_offset];
                         option_offset += 8;
                         continue;
@@ -303,6 +413,9 @@ impl<'a> MetaPacket<'a> {
                         return (next_header, option_offset - original_offset);
                     }
                     IpProtocol::Esp => {
+                        if self.offset_ipv6_fragment_option > 0 {
+                            self.ipv6_anomaly |= Ipv6Anomaly::ESP_AFTER_FRAGMENT;
+                        }
                         self.offset_ipv6_last_option = option_offset;
                         option_offset += size_checker as usize;
                         return (next_header, option_offset - original_offset);
@@ -315,6 +428,7 @@ impl<'a> MetaPacket<'a> {
         }
         self.offset_ipv6_last_option = 0;
         self.offset_ipv6_fragment_option = 0;
+        self.ipv6_jumbo_payload = 0;
         (packet[IPV6_PROTO_OFFSET + l2_opt_size], 0)
     }
 
@@ -394,38 +508,26 @@ impl<'a> MetaPacket<'a> {
             .map_err(|e| {
                 error::Error::ParsePacketFailed(format!("parse eth_type failed: {}", e))
             })?;
-        if eth_type == EthernetType::Dot1Q {
-            vlan_tag_size = VLAN_HEADER_SIZE;
+        // 最多解析两层VLAN，外层可以是802.1ad(QinQ)或802.1Q
+        while (eth_type == EthernetType::Dot1Q
+            || (eth_type == EthernetType::QinQ && vlan_tag_size == 0))
+            && vlan_tag_size < 2 * VLAN_HEADER_SIZE
+        {
             size_checker -= VLAN_HEADER_SIZE as isize;
             if size_checker < 0 {
                 return Err(error::Error::ParsePacketFailed("packet truncated".into()));
             }
-            let vlan_tag = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE + ETH_TYPE_LEN..]);
+            let vlan_tag =
+                read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE + vlan_tag_size + ETH_TYPE_LEN..]);
+            self.outer_vlan = self.vlan;
             self.vlan = vlan_tag & VLAN_ID_MASK;
+            vlan_tag_size += VLAN_HEADER_SIZE;
             eth_type = EthernetType::try_from(read_u16_be(
                 &packet[FIELD_OFFSET_ETH_TYPE + vlan_tag_size..],
             ))
             .map_err(|e| {
                 error::Error::ParsePacketFailed(format!("parse eth_type failed: {}", e))
             })?;
-            if eth_type == EthernetType::Dot1Q {
-                vlan_tag_size = VLAN_HEADER_SIZE;
-                size_checker -= VLAN_HEADER_SIZE as isize;
-                if size_checker < 0 {
-                    return Err(error::Error::ParsePacketFailed("packet truncated".into()));
-                }
-                let vlan_tag = read_u16_be(
-                    &packet
-                        [FIELD_OFFSET_ETH_TYPE + ETH_TYPE_LEN + ETH_TYPE_LEN + VLAN_HEADER_SIZE..],
-                );
-                self.vlan = vlan_tag & VLAN_ID_MASK;
-                eth_type = EthernetType::try_from(read_u16_be(
-                    &packet[FIELD_OFFSET_ETH_TYPE + vlan_tag_size..],
-                ))
-                .map_err(|e| {
-                    error::Error::ParsePacketFailed(format!("parse eth_type failed: {}", e))
-                })?;
-            }
         }
         self.lookup_key.eth_type = eth_type;
         self.lookup_key.src_mac =
@@ -492,11 +594,13 @@ impl<'a> MetaPacket<'a> {
                 }
                 // 为了不影响L4层的字段偏移，ipv6比ipv4多的20个字节放入m.l2l3OptSize
                 self.l2_l3_opt_size = IPV6_HEADER_ADJUST + vlan_tag_size;
-                let mut payload = read_u16_be(&packet[FIELD_OFFSET_PAYLOAD_LEN + vlan_tag_size..]);
+                let payload_length =
+                    read_u16_be(&packet[FIELD_OFFSET_PAYLOAD_LEN + vlan_tag_size..]) as usize;
+                let mut payload = payload_length;
                 // e1000网卡驱动，在开启TSO功能时，IPv6的payload可能为0
                 // e1000网卡驱动：https://elixir.bootlin.com/linux/v3.0/source/drivers/net/e1000e/netdev.c#L4423
                 if payload == 0 {
-                    payload = size_checker as u16;
+                    payload = size_checker as usize;
                 }
                 let label = read_u32_be(&packet[FIELD_OFFSET_PAYLOAD_LEN + vlan_tag_size..]);
                 self.data_offset_ihl_or_fl4b |= ((label >> 16) & 0xf) as u8;
@@ -506,7 +610,18 @@ impl<'a> MetaPacket<'a> {
                 })?;
                 let options_length = r.1;
                 self.l2_l3_opt_size += options_length;
-                self.packet_len = payload as usize
+                // RFC 2675: jumbogram的payload长度必须为0，不能分片，且长度超过65535
+                if self.ipv6_jumbo_payload > 0 {
+                    if payload_length == 0
+                        && self.offset_ipv6_fragment_option == 0
+                        && self.ipv6_jumbo_payload > u16::MAX as u32
+                    {
+                        payload = self.ipv6_jumbo_payload as usize;
+                    } else {
+                        self.ipv6_anomaly |= Ipv6Anomaly::INVALID_JUMBOGRAM;
+                    }
+                }
+                self.packet_len = payload
                     + HeaderType::Ipv6.min_packet_size()
                     + vlan_tag_size
                     + IPV6_HEADER_ADJUST;
@@ -747,6 +862,78 @@ impl<'a> MetaPacket<'a> {
         Ok(())
     }
 
+    // 校验IPv4头部及TCP/UDP的校验和，分片、截断或TSO合并的包无法校验，视为正确
+    pub fn verify_checksum(&self) -> Result<(), ChecksumError> {
+        fn read_u16_be(bs: &[u8]) -> u16 {
+            u16::from_be_bytes([bs[0], bs[1]])
+        }
+        let packet = match self.raw {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if self.header_type < HeaderType::Ipv4 {
+            return Ok(());
+        }
+        let ip_offset = ETH_HEADER_SIZE + self.vlan_tag_size;
+        let (l4_len, addrs) = match self.lookup_key.eth_type {
+            EthernetType::Ipv4 => {
+                let ihl = (self.data_offset_ihl_or_fl4b & 0xF) as usize * 4;
+                if ihl < IPV4_HEADER_SIZE {
+                    return Err(ChecksumError::L3);
+                }
+                if packet.len() < ip_offset + ihl {
+                    return Ok(());
+                }
+                if fold_checksum(checksum_sum(&packet[ip_offset..ip_offset + ihl], 0)) != 0xFFFF {
+                    return Err(ChecksumError::L3);
+                }
+                let total_length = read_u16_be(&packet[ip_offset + 2..]) as usize;
+                (
+                    total_length.saturating_sub(ihl),
+                    &packet[ip_offset + 12..ip_offset + 20],
+                )
+            }
+            EthernetType::Ipv6 => {
+                let payload = read_u16_be(&packet[ip_offset + 4..]) as usize;
+                // 扩展头不全时l2_l3_opt_size未设置，header_type为Ipv6，后面不会校验L4
+                let options_length = self
+                    .l2_l3_opt_size
+                    .saturating_sub(IPV6_HEADER_ADJUST + self.vlan_tag_size);
+                (
+                    payload.saturating_sub(options_length),
+                    &packet[ip_offset + 8..ip_offset + 40],
+                )
+            }
+            _ => return Ok(()),
+        };
+
+        let checksum_offset = match self.header_type {
+            HeaderType::Ipv4Tcp | HeaderType::Ipv6Tcp => 16,
+            HeaderType::Ipv4Udp | HeaderType::Ipv6Udp => 6,
+            _ => return Ok(()),
+        };
+        let l4_offset = HeaderType::Ipv4.min_packet_size() + self.l2_l3_opt_size;
+        // total_length或payload为0时(TSO)l4_len也为0
+        if self.npb_ignore_l4 || l4_len <= checksum_offset + 1 || packet.len() < l4_offset + l4_len
+        {
+            return Ok(());
+        }
+        let segment = &packet[l4_offset..l4_offset + l4_len];
+        // IPv4的UDP校验和可以为0，表示未计算
+        if self.header_type == HeaderType::Ipv4Udp && read_u16_be(&segment[checksum_offset..]) == 0
+        {
+            return Ok(());
+        }
+        let pseudo_header = checksum_sum(
+            addrs,
+            u8::from(self.lookup_key.proto) as u32 + l4_len as u32,
+        );
+        if fold_checksum(checksum_sum(segment, pseudo_header)) != 0xFFFF {
+            return Err(ChecksumError::L4);
+        }
+        Ok(())
+    }
+
     /// Get the meta packet's l3 payload len.
     pub fn l3_payload_len(&self) -> usize {
         self.l3_payload_len
@@ -809,20 +996,24 @@ impl<'a> MetaPacket<'a> {
 
         let cap_len = capture_size.min(data.cap_len as usize);
 
-        packet.raw_from_ebpf = vec![0u8; cap_len as usize];
-        data.cap_data
-            .copy_to_nonoverlapping(packet.raw_from_ebpf.as_mut_ptr() as *mut i8, cap_len);
+        packet.raw_from_ebpf = PacketBuffer::from_slice(std::slice::from_raw_parts(
+            data.cap_data as *const u8,
+            cap_len,
+        ));
         packet.packet_len = data.syscall_len as usize + 54; // 目前仅支持TCP
         packet.payload_len = data.cap_len as u16;
         packet.l4_payload_len = data.cap_len as usize;
         packet.tap_port = TapPort::from_ebpf(data.process_id);
+        packet.capture_point = CapturePoint::from_ebpf(data.process_id);
         packet.cap_seq = data.cap_seq;
         packet.process_id = data.process_id;
         packet.thread_id = data.thread_id;
+        packet.peer_process_id = data.peer_process_id;
+        packet.unix_socket = data.unix_socket;
+        packet.cgroup_id = data.cgroup_id;
         packet.syscall_trace_id = data.syscall_trace_id_call;
-        packet.process_name = CStr::from_ptr(data.process_name.as_ptr() as *const i8)
-            .to_str()?
-            .to_string();
+        packet.process_name =
+            intern(CStr::from_ptr(data.process_name.as_ptr() as *const i8).to_str()?);
         packet.socket_id = data.socket_id;
         packet.tcp_data.seq = data.tcp_seq as u32;
         packet.l7_protocol_from_ebpf = L7Protocol::from(data.l7_protocal_hint as u8);
@@ -830,6 +1021,7 @@ impl<'a> MetaPacket<'a> {
         return Ok(packet);
     }
 
+    // 按socket_id而不是五元组区分，unix socket合成的端口只有16位，可能冲突
     pub fn ebpf_flow_id(&self) -> u128 {
         let protocol = u8::from(self.l7_protocol_from_ebpf) as u128;
 
@@ -846,6 +1038,51 @@ impl<'a> MetaPacket<'a> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumError {
+    L3,
+    L4,
+}
+
+// 遍历Hop-by-Hop的选项，返回Jumbo Payload选项中的长度
+fn ipv6_jumbo_payload(options: &[u8]) -> u32 {
+    let mut offset = 0;
+    while offset + 1 < options.len() {
+        if options[offset] == IPV6_OPTION_PAD1 {
+            offset += 1;
+            continue;
+        }
+        let length = options[offset + 1] as usize;
+        if options[offset] == IPV6_OPTION_JUMBO_PAYLOAD
+            && length == 4
+            && offset + 6 <= options.len()
+        {
+            return u32::from_be_bytes(options[offset + 2..offset + 6].try_into().unwrap());
+        }
+        offset += length + 2;
+    }
+    0
+}
+
+// 按16位大端累加，奇数长度时最后一个字节补0
+fn checksum_sum(data: &[u8], mut sum: u32) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for c in chunks.by_ref() {
+        sum += u16::from_be_bytes([c[0], c[1]]) as u32;
+    }
+    if let [b] = chunks.remainder() {
+        sum += (*b as u32) << 8;
+    }
+    sum
+}
+
+pub(crate) fn fold_checksum(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
 impl<'a> fmt::Display for MetaPacket<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "\t\t{}\n", self.lookup_key)?;
@@ -883,12 +1120,16 @@ pub struct MetaPacketTcpHeader {
     pub win_scale: u8,
     pub sack_permitted: bool,
     pub sack: Option<Vec<u8>>, // sack value
+    // 前8个TCP选项的类型，按出现顺序从高位到低位排列
+    pub option_layout: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::utils::test::Capture;
+
     #[test]
     fn get_pkt_size() {
         let pkt = MetaPacket {
@@ -912,4 +1153,188 @@ mod tests {
             pkt
         );
     }
+
+    #[test]
+    fn tcp_fingerprint() {
+        let capture = Capture::load_pcap("resources/test/flow_generator/http.pcap", None);
+        let packets = capture.as_meta_packets();
+        // Linux的SYN：mss,sok,ts,nop,ws
+        assert_eq!(packets[0].tcp_data.option_layout, 0x0204080103);
+        let syn = packets[0].tcp_fingerprint();
+        assert_ne!(syn, 0);
+        // SYN-ACK的选项顺序相同，窗口大小不同
+        assert_eq!(packets[1].tcp_data.option_layout, 0x0204080103);
+        assert_ne!(packets[1].tcp_fingerprint(), 0);
+        assert_ne!(packets[1].tcp_fingerprint(), syn);
+        assert_eq!(packets[2].tcp_fingerprint(), 0);
+    }
+
+    #[test]
+    fn verify_checksum() {
+        let capture =
+            Capture::load_pcap("resources/test/flow_generator/rtt-rtt-2.pcap", Some(1500));
+        let packets: Vec<Vec<u8>> = capture.into();
+        for raw in packets.iter() {
+            let packet = MetaPacket::from_raw(raw, Duration::ZERO).unwrap();
+            assert_eq!(packet.verify_checksum(), Ok(()));
+        }
+
+        let mut raw = packets[3].clone();
+        raw[IPV4_TTL_OFFSET] ^= 0xFF;
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.verify_checksum(), Err(ChecksumError::L3));
+
+        let mut raw = packets[3].clone();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xFF;
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.verify_checksum(), Err(ChecksumError::L4));
+
+        // 截断的包无法校验L4
+        let packet = MetaPacket::from_raw(&raw[..raw.len() - 2], Duration::ZERO).unwrap();
+        assert_eq!(packet.verify_checksum(), Ok(()));
+    }
+
+    #[test]
+    fn qinq() {
+        let capture =
+            Capture::load_pcap("resources/test/flow_generator/rtt-rtt-2.pcap", Some(1500));
+        let packets: Vec<Vec<u8>> = capture.into();
+        let original = MetaPacket::from_raw(&packets[3], Duration::ZERO).unwrap();
+
+        // 外层802.1ad VLAN 100，内层802.1Q VLAN 200
+        let mut raw = packets[3][..FIELD_OFFSET_ETH_TYPE].to_vec();
+        raw.extend_from_slice(&[0x88, 0xa8, 0x00, 100, 0x81, 0x00, 0x00, 200]);
+        raw.extend_from_slice(&packets[3][FIELD_OFFSET_ETH_TYPE..]);
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.vlan, 200);
+        assert_eq!(packet.outer_vlan, 100);
+        assert_eq!(packet.lookup_key.eth_type, EthernetType::Ipv4);
+        assert_eq!(packet.lookup_key.proto, original.lookup_key.proto);
+        assert_eq!(packet.lookup_key.src_port, original.lookup_key.src_port);
+        assert_eq!(packet.lookup_key.dst_port, original.lookup_key.dst_port);
+        assert_eq!(packet.payload_len, original.payload_len);
+    }
+
+    // 以太网头 + IPv6头 + 扩展头 + UDP头，扩展头的第一个字节由此处填充
+    fn ipv6_packet(extensions: &[(IpProtocol, Vec<u8>)], payload_length: u16) -> Vec<u8> {
+        let mut packet = vec![0; ETH_HEADER_SIZE];
+        packet[12..14].copy_from_slice(&u16::from(EthernetType::Ipv6).to_be_bytes());
+        packet.extend_from_slice(&[0x60, 0, 0, 0]);
+        packet.extend_from_slice(&payload_length.to_be_bytes());
+        let first = extensions.first().map(|e| e.0).unwrap_or(IpProtocol::Udp);
+        packet.extend_from_slice(&[u8::from(first), 64]);
+        packet.extend_from_slice(&"fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(&"fd00::2".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        for (i, (_, header)) in extensions.iter().enumerate() {
+            let next = extensions
+                .get(i + 1)
+                .map(|e| e.0)
+                .unwrap_or(IpProtocol::Udp);
+            packet.push(u8::from(next));
+            packet.extend_from_slice(&header[1..]);
+        }
+        packet.extend_from_slice(&[0x30, 0x39, 0, 53, 0, 8, 0, 0]);
+        packet
+    }
+
+    fn options(size: usize) -> Vec<u8> {
+        let mut header = vec![0; size];
+        header[1] = (size / 8 - 1) as u8;
+        header
+    }
+
+    #[test]
+    fn ipv6_anomaly() {
+        let anomaly = |extensions: &[(IpProtocol, Vec<u8>)]| {
+            let raw = ipv6_packet(extensions, 0);
+            MetaPacket::from_raw(&raw, Duration::ZERO)
+                .unwrap()
+                .ipv6_anomaly
+        };
+        let fragment = (IpProtocol::Ipv6Fragment, vec![0; 8]);
+        let routing = (IpProtocol::Ipv6Routing, options(8));
+
+        let raw = ipv6_packet(&[(IpProtocol::Ipv6HopByHop, options(8))], 16);
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.lookup_key.proto, IpProtocol::Udp);
+        assert_eq!(packet.ipv6_anomaly, Ipv6Anomaly::empty());
+
+        assert_eq!(
+            anomaly(&[
+                (IpProtocol::Ipv6Destination, options(8)),
+                (IpProtocol::Ipv6HopByHop, options(8)),
+            ]),
+            Ipv6Anomaly::MISPLACED_HOP_BY_HOP
+        );
+        assert_eq!(
+            anomaly(&[fragment.clone(), routing.clone()]),
+            Ipv6Anomaly::FRAGMENT_WITH_ROUTING
+        );
+        assert_eq!(
+            anomaly(&[routing, fragment.clone()]),
+            Ipv6Anomaly::FRAGMENT_WITH_ROUTING
+        );
+        assert_eq!(
+            anomaly(&[fragment, (IpProtocol::Esp, vec![0; 8])]),
+            Ipv6Anomaly::ESP_AFTER_FRAGMENT
+        );
+        assert_eq!(
+            anomaly(&[(IpProtocol::Ipv6HopByHop, options(72))]),
+            Ipv6Anomaly::OVERSIZED_OPTIONS
+        );
+        let destinations = vec![(IpProtocol::Ipv6Destination, options(8)); 8];
+        assert_eq!(anomaly(&destinations), Ipv6Anomaly::empty());
+        let destinations = vec![(IpProtocol::Ipv6Destination, options(8)); 9];
+        assert_eq!(anomaly(&destinations), Ipv6Anomaly::OVERSIZED_OPTIONS);
+    }
+
+    #[test]
+    fn ipv6_jumbogram() {
+        let mut hop_by_hop = options(8);
+        hop_by_hop[2..4].copy_from_slice(&[IPV6_OPTION_JUMBO_PAYLOAD, 4]);
+        hop_by_hop[4..8].copy_from_slice(&70000u32.to_be_bytes());
+
+        let raw = ipv6_packet(&[(IpProtocol::Ipv6HopByHop, hop_by_hop.clone())], 0);
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.ipv6_anomaly, Ipv6Anomaly::empty());
+        assert_eq!(
+            packet.packet_len,
+            70000 + ETH_HEADER_SIZE + IPV6_HEADER_SIZE
+        );
+
+        // payload长度不为0时jumbogram无效，按payload长度计算
+        let raw = ipv6_packet(&[(IpProtocol::Ipv6HopByHop, hop_by_hop.clone())], 16);
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.ipv6_anomaly, Ipv6Anomaly::INVALID_JUMBOGRAM);
+        assert_eq!(packet.packet_len, 16 + ETH_HEADER_SIZE + IPV6_HEADER_SIZE);
+
+        hop_by_hop[4..8].copy_from_slice(&1000u32.to_be_bytes());
+        let raw = ipv6_packet(&[(IpProtocol::Ipv6HopByHop, hop_by_hop)], 0);
+        let packet = MetaPacket::from_raw(&raw, Duration::ZERO).unwrap();
+        assert_eq!(packet.ipv6_anomaly, Ipv6Anomaly::INVALID_JUMBOGRAM);
+    }
+
+    #[test]
+    fn unix_socket_port_collision() {
+        let unix_packet = |socket_id| {
+            let mut packet = MetaPacket {
+                socket_id,
+                unix_socket: true,
+                l7_protocol_from_ebpf: L7Protocol::Http1,
+                ..Default::default()
+            };
+            packet.lookup_key.src_ip = Ipv4Addr::LOCALHOST.into();
+            packet.lookup_key.dst_ip = Ipv4Addr::LOCALHOST.into();
+            packet.lookup_key.src_port = 1234;
+            packet.lookup_key.dst_port = 5678;
+            packet
+        };
+        // 不同连接合成的地址和端口相同时仍是不同的流
+        let a = unix_packet(1);
+        let b = unix_packet(2);
+        assert_eq!(a.lookup_key.src_port, b.lookup_key.src_port);
+        assert_eq!(a.lookup_key.dst_port, b.lookup_key.dst_port);
+        assert_ne!(a.ebpf_flow_id(), b.ebpf_flow_id());
+    }
 }
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+pub mod business_label;
 mod consts;
 pub mod decapsulate;
 pub mod endpoint;
@@ -32,10 +33,10 @@ pub mod tap_port;
 pub mod tap_types;
 
 pub use consts::*;
-pub use meta_packet::MetaPacket;
+pub use meta_packet::{MetaPacket, TimestampSource};
 pub use platform_data::PlatformData;
 pub use tagged_flow::TaggedFlow;
-pub use tap_port::TapPort;
+pub use tap_port::{CapturePoint, CaptureType, TapPort};
 pub use tap_types::TapTyper;
 
 use std::{
@@ -63,6 +63,9 @@ pub struct PlatformData {
     pub region_id: u32,
     pub pod_cluster_id: u32,
     pub pod_node_id: u32,
+    pub pod_id: u32,
+    // POD内的容器ID，eBPF数据按进程cgroup得到容器ID后据此关联POD
+    pub container_ids: Vec<String>,
     pub if_type: IfType,
     pub device_type: u8,
     pub is_vip_interface: bool,
@@ -87,6 +90,8 @@ impl Default for PlatformData {
             region_id: 0,
             pod_cluster_id: 0,
             pod_node_id: 0,
+            pod_id: 0,
+            container_ids: Vec::new(),
             if_type: IfType::LAN,
             device_type: 0,
             is_vip_interface: false,
@@ -97,6 +102,14 @@ impl Default for PlatformData {
     }
 }
 
+impl PlatformData {
+    // 同一接口同时拥有IPv4和IPv6地址，认为是双栈接口
+    pub fn is_dual_stack(&self) -> bool {
+        self.ips.iter().any(|ip| ip.raw_ip.is_ipv4())
+            && self.ips.iter().any(|ip| ip.raw_ip.is_ipv6())
+    }
+}
+
 impl TryFrom<&trident::Interface> for PlatformData {
     type Error = Error;
 
@@ -143,6 +156,8 @@ impl TryFrom<&trident::Interface> for PlatformData {
             region_id: p.region_id(),
             pod_cluster_id: p.pod_cluster_id(),
             pod_node_id: p.pod_node_id(),
+            pod_id: p.pod_id(),
+            container_ids: p.container_ids.clone(),
             if_type: IfType::try_from(p.if_type() as u8).map_err(|e| {
                 Error::ParsePlatformData(format!(
                     "parse trident::Interface to platform data if_type failed: {}",
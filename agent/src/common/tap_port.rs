@@ -20,13 +20,14 @@ use std::net::Ipv4Addr;
 use serde::Serialize;
 
 use super::decapsulate::TunnelType;
+use crate::proto::flow_log;
 
 // 64     60         40         32                                    0
 // +------+----------+----------+-------------------------------------+
 // | from | RESERVED | TUN_TYPE |              ip/id/mac              |
 // +------+----------+----------+-------------------------------------+
 // 注意ip/id/mac不能超过32bit，否则数据存储、四元组聚合都会有歧义
-#[derive(Serialize, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Serialize, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct TapPort(pub u64);
 
 impl TapPort {
@@ -159,3 +160,140 @@ impl fmt::Debug for TapPort {
         write!(f, "{}", self)
     }
 }
+
+// 采集点类型，TapPort中的from无法区分本地网卡与镜像流量，这里显式标明数据来源
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[repr(u8)]
+pub enum CaptureType {
+    Unknown = 0,
+    LocalNic = 1,
+    Mirror = 2,
+    Analyzer = 3,
+    Ebpf = 4,
+    NetFlow = 5,
+    SFlow = 6,
+    Otel = 7,
+}
+
+impl Default for CaptureType {
+    fn default() -> Self {
+        CaptureType::Unknown
+    }
+}
+
+impl From<TapPort> for CaptureType {
+    // 未设置采集点时根据TapPort推断，本地网卡和镜像无法区分
+    fn from(t: TapPort) -> Self {
+        match (t.0 >> TapPort::FROM_OFFSET) as u8 {
+            TapPort::FROM_NETFLOW => CaptureType::NetFlow,
+            TapPort::FROM_SFLOW => CaptureType::SFlow,
+            TapPort::FROM_EBPF => CaptureType::Ebpf,
+            TapPort::FROM_OTEL => CaptureType::Otel,
+            _ => CaptureType::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for CaptureType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CaptureType::Unknown => "Unknown",
+            CaptureType::LocalNic => "LocalNIC",
+            CaptureType::Mirror => "Mirror",
+            CaptureType::Analyzer => "Analyzer",
+            CaptureType::Ebpf => "eBPF",
+            CaptureType::NetFlow => "NetFlow",
+            CaptureType::SFlow => "sFlow",
+            CaptureType::Otel => "OTel",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// 具名采集点，与TapPort一同上报，不参与流的聚合
+#[derive(Serialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct CapturePoint {
+    pub capture_type: CaptureType,
+    // 本地网卡、镜像和分析器模式下为收包网卡的if_index
+    pub if_index: u32,
+    // eBPF为进程ID，NetFlow/sFlow为exporter的ID
+    pub source_id: u32,
+}
+
+impl CapturePoint {
+    pub fn new(capture_type: CaptureType, if_index: u32, source_id: u32) -> Self {
+        Self {
+            capture_type,
+            if_index,
+            source_id,
+        }
+    }
+
+    pub fn from_ebpf(process_id: u32) -> Self {
+        Self::new(CaptureType::Ebpf, 0, process_id)
+    }
+
+    // 兼容未设置采集点的旧数据
+    pub fn or_from(self, tap_port: TapPort) -> Self {
+        if self.capture_type != CaptureType::Unknown {
+            return self;
+        }
+        let capture_type = CaptureType::from(tap_port);
+        match capture_type {
+            CaptureType::Unknown => self,
+            _ => Self::new(capture_type, 0, tap_port.0 as u32),
+        }
+    }
+}
+
+impl fmt::Display for CapturePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.capture_type {
+            CaptureType::LocalNic | CaptureType::Mirror | CaptureType::Analyzer => {
+                write!(f, "{}@{}", self.capture_type, self.if_index)
+            }
+            _ => write!(f, "{}@{}", self.capture_type, self.source_id),
+        }
+    }
+}
+
+impl From<CapturePoint> for flow_log::CapturePoint {
+    fn from(c: CapturePoint) -> Self {
+        flow_log::CapturePoint {
+            capture_type: c.capture_type as u32,
+            if_index: c.if_index,
+            source_id: c.source_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_point() {
+        let local = CapturePoint::new(CaptureType::LocalNic, 3, 0);
+        assert_eq!(local.to_string(), "LocalNIC@3");
+        assert_eq!(local.or_from(TapPort::from_ebpf(100)), local);
+
+        let ebpf = CapturePoint::default().or_from(TapPort::from_ebpf(100));
+        assert_eq!(ebpf, CapturePoint::from_ebpf(100));
+        assert_eq!(ebpf.to_string(), "eBPF@100");
+
+        // 本地网卡的TapPort无法推断采集点
+        let tap_port = TapPort::from_local_mac(TunnelType::None, 0x12345678);
+        assert_eq!(
+            CapturePoint::default().or_from(tap_port).capture_type,
+            CaptureType::Unknown
+        );
+        assert_eq!(
+            flow_log::CapturePoint::from(ebpf),
+            flow_log::CapturePoint {
+                capture_type: 4,
+                if_index: 0,
+                source_id: 100,
+            }
+        );
+    }
+}
@@ -14,13 +14,16 @@
  * limitations under the License.
  */
 
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs;
 use std::io;
-use std::net::{IpAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 
+use ipnet::IpNet;
 use log::{error, info, warn};
 use md5::{Digest, Md5};
 use serde::Deserialize;
@@ -28,9 +31,10 @@ use thiserror::Error;
 use tokio::runtime::Runtime;
 
 use crate::common::decapsulate::TunnelType;
+use crate::common::flow::L7Protocol;
 use crate::common::{
     enums::TapType, DEFAULT_LOG_FILE, L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
-    L7_PROTOCOL_INFERENCE_TTL,
+    L7_PROTOCOL_INFERENCE_TTL, VLAN_ID_MASK,
 };
 use crate::proto::{
     common,
@@ -38,6 +42,7 @@ use crate::proto::{
 };
 use crate::rpc::Session;
 
+const RUNTIME_CONFIG_CACHE_FILE: &str = "runtime-config.pb";
 const K8S_CA_CRT_PATH: &str = "/run/secrets/kubernetes.io/serviceaccount/ca.crt";
 const MINUTE: Duration = Duration::from_secs(60);
 
@@ -64,9 +69,23 @@ pub struct Config {
     pub kubernetes_cluster_id: String,
     pub vtap_group_id_request: String,
     pub controller_domain_name: Vec<String>,
+    pub standalone: StandaloneConfig,
+    pub hardening: HardeningConfig,
+    // 升级时保留采集socket并原地exec新版本，减少升级期间的丢包
+    pub hot_upgrade: bool,
+    // 缓存的运行配置不超过该时长时，启动后不等待首次同步直接使用，为0时不使用
+    #[serde(with = "humantime_serde")]
+    pub runtime_config_cache_max_age: Duration,
+    // 接受控制器下发的白名单诊断命令
+    pub remote_exec_enabled: bool,
 }
 
 impl Config {
+    // 最近一次从控制器收到的运行配置缓存在日志目录下，供dump-config读取
+    pub fn runtime_config_cache(&self) -> PathBuf {
+        Path::new(&self.log_file).with_file_name(RUNTIME_CONFIG_CACHE_FILE)
+    }
+
     pub fn load_from_file<T: AsRef<Path>>(path: T) -> Result<Self, ConfigError> {
         let contents =
             fs::read_to_string(path).map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
@@ -82,6 +101,11 @@ impl Config {
             let mut cfg: Self = serde_yaml::from_str(contents)
                 .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
 
+            // 独立运行时不会连接控制器，仅用于确定本机IP
+            if cfg.standalone.enabled && cfg.controller_ips.is_empty() {
+                cfg.controller_ips.push(Ipv4Addr::LOCALHOST.to_string());
+            }
+
             for i in 0..cfg.controller_ips.len() {
                 if cfg.controller_ips[i].parse::<IpAddr>().is_err() {
                     let ip = resolve_domain(&cfg.controller_ips[i]);
@@ -189,6 +213,104 @@ impl Default for Config {
             kubernetes_cluster_id: "".into(),
             vtap_group_id_request: "".into(),
             controller_domain_name: vec![],
+            standalone: Default::default(),
+            hardening: Default::default(),
+            hot_upgrade: false,
+            runtime_config_cache_max_age: Duration::from_secs(24 * 60 * 60),
+            remote_exec_enabled: false,
+        }
+    }
+}
+
+// 不依赖控制器独立运行，运行配置取自本地配置文件
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct StandaloneConfig {
+    pub enabled: bool,
+    pub vtap_id: u16,
+    // 为空时使用默认值
+    pub tap_interface_regex: String,
+    pub l4_log_tap_types: Vec<u32>,
+    pub l7_log_store_tap_types: Vec<u32>,
+    // 为空时数据以json格式写入日志目录，否则发送到该地址，格式为ip:port
+    pub data_socket: String,
+}
+
+impl Default for StandaloneConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            vtap_id: 0,
+            tap_interface_regex: "".into(),
+            l4_log_tap_types: vec![u16::from(TapType::Any) as u32],
+            l7_log_store_tap_types: vec![u16::from(TapType::Any) as u32],
+            data_socket: "".into(),
+        }
+    }
+}
+
+impl StandaloneConfig {
+    // 本地配置文件同时作为控制器下发的static_config使用
+    pub fn runtime_config<P: AsRef<Path>>(&self, path: P) -> Result<RuntimeConfig, io::Error> {
+        self.trident_config(path)?.try_into()
+    }
+
+    pub fn trident_config<P: AsRef<Path>>(&self, path: P) -> Result<trident::Config, io::Error> {
+        let local_config = fs::read_to_string(path)?;
+        let mut conf = trident::Config {
+            vtap_id: Some(self.vtap_id as u32),
+            collector_enabled: Some(true),
+            l4_log_tap_types: self.l4_log_tap_types.clone(),
+            l7_log_store_tap_types: self.l7_log_store_tap_types.clone(),
+            local_config: Some(local_config),
+            ..Default::default()
+        };
+        if !self.tap_interface_regex.is_empty() {
+            conf.tap_interface_regex = Some(self.tap_interface_regex.clone());
+        }
+        if self.data_socket.is_empty() {
+            conf.collector_socket_type = Some(trident::SocketType::File as i32);
+        } else {
+            let addr = self.data_socket.parse::<SocketAddr>().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid data-socket {}: {}", self.data_socket, e),
+                )
+            })?;
+            conf.collector_socket_type = Some(trident::SocketType::Tcp as i32);
+            conf.analyzer_ip = Some(addr.ip().to_string());
+            conf.analyzer_port = Some(addr.port() as u32);
+        }
+        Ok(conf)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeccompMode {
+    Disabled,
+    // 不在白名单中的系统调用仅记录到内核审计日志
+    Log,
+    // 不在白名单中的系统调用返回EPERM
+    Enforce,
+}
+
+// 采集socket和eBPF创建后丢弃不需要的capability并限制系统调用，降低解析模块被利用时的影响
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HardeningConfig {
+    pub enabled: bool,
+    // 在采集socket和eBPF创建完成后生效，之后仅保留重建采集socket及进入容器网络命名空间所需的capability
+    pub keep_capabilities: Vec<String>,
+    pub seccomp: SeccompMode,
+}
+
+impl Default for HardeningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keep_capabilities: vec!["NET_ADMIN".into(), "NET_RAW".into(), "SYS_ADMIN".into()],
+            seccomp: SeccompMode::Disabled,
         }
     }
 }
@@ -203,8 +325,15 @@ pub struct YamlConfig {
     pub af_packet_blocks_enabled: bool,
     #[serde(alias = "afpacket-blocks")]
     pub af_packet_blocks: usize,
+    #[serde(alias = "afpacket-fanout")]
+    pub af_packet_fanout: AfPacketFanoutConfig,
+    // 使用网卡硬件时间戳，网卡或驱动不支持时回退为软件时间戳
+    #[serde(alias = "afpacket-hardware-timestamp")]
+    pub af_packet_hardware_timestamp: bool,
     pub enable_debug_stats: bool,
     pub analyzer_dedup_disabled: bool,
+    // 镜像源(如交换机)按1/N采样时配置为N，analyzer模式下流量统计按该比例放大
+    pub analyzer_sampling_ratio: u32,
     pub default_tap_type: u32,
     pub debug_listen_port: u16,
     pub enable_qos_bypass: bool,
@@ -214,9 +343,28 @@ pub struct YamlConfig {
     #[serde(with = "TapModeDef")]
     pub tap_mode: trident::TapMode,
     pub mirror_traffic_pcp: u16,
+    pub analyzer_tap_types: Vec<AnalyzerTapType>,
     pub vtap_group_id_request: String,
     pub pcap: PcapConfig,
     pub flow: FlowGeneratorConfig,
+    pub l7_snaplen: L7SnaplenConfig,
+    pub l7_parse_error_pcap: L7ParseErrorPcapConfig,
+    pub l7_parse_budget: L7ParseBudgetConfig,
+    pub l7_protocol_detection: L7ProtocolDetectionConfig,
+    pub udp_perf: UdpPerfConfig,
+    pub l7_alert_rules: Vec<L7AlertRule>,
+    pub l4_log_filter: L4LogFilterConfig,
+    pub security_event: SecurityEventConfig,
+    pub cpu_affinity: CpuAffinityConfig,
+    pub checksum_validation: ChecksumValidationConfig,
+    pub vlan_mode: VlanMode,
+    pub server_connection_metrics_enabled: bool,
+    pub tls_sni_metrics_enabled: bool,
+    pub pipeline_mode: PipelineMode,
+    pub time_source: TimeSourceConfig,
+    pub ip_anonymization: IpAnonymizationConfig,
+    pub profiler: ProfilerConfig,
+    pub l7_rrt_upper_bounds: Vec<L7RrtUpperBound>,
     pub flow_queue_size: usize,
     pub quadruple_queue_size: usize,
     pub analyzer_queue_size: usize,
@@ -230,6 +378,8 @@ pub struct YamlConfig {
     pub collector_sender_queue_count: usize,
     pub flow_sender_queue_size: usize,
     pub flow_sender_queue_count: usize,
+    // 按队列名配置发送队列的批量读取
+    pub queue_batching: HashMap<String, QueueBatchingConfig>,
     #[serde(with = "humantime_serde")]
     pub second_flow_extra_delay: Duration,
     #[serde(with = "humantime_serde")]
@@ -242,10 +392,35 @@ pub struct YamlConfig {
     pub grpc_buffer_size: usize,
     #[serde(with = "humantime_serde")]
     pub l7_log_session_aggr_timeout: Duration,
+    #[serde(with = "humantime_serde")]
+    pub l7_log_idle_connection_interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub l7_log_http_stream_interval: Duration,
+    pub l7_log_user_name_masked: bool,
+    pub l7_log_http_decompress: bool,
+    pub l7_log_sql_comment_trace_key: String,
+    pub l7_log_http_scrub: HttpScrubConfig,
+    pub l7_log_http_captured_headers: Vec<String>,
+    pub l7_log_http_custom_methods: Vec<String>,
+    pub l7_log_dns: DnsLogConfig,
+    pub l7_log_redis: RedisLogConfig,
+    pub l7_log_elasticsearch: ElasticsearchLogConfig,
+    pub l7_log_http_retry: HttpRetryConfig,
+    pub l7_log_truncate: L7LogTruncateConfig,
+    pub l7_log_session_policies: Vec<L7SessionPolicy>,
+    pub l7_log_sampling: L7LogSamplingConfig,
     pub tap_mac_script: String,
     pub cloud_gateway_traffic: bool,
+    pub dual_stack_merge_enabled: bool,
     pub ebpf_log_file: String,
+    pub ebpf_unix_socket_enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub ebpf_probe_stall_timeout: Duration,
     pub kubernetes_namespace: String,
+    pub capture_namespaces: Vec<String>,
+    pub windows_loopback_capture_enabled: bool,
+    pub business_label_file: String,
+    pub server_domain: ServerDomainConfig,
     pub external_metrics_sender_queue_size: usize,
     pub l7_protocol_inference_max_fail_count: usize,
     pub l7_protocol_inference_ttl: usize,
@@ -284,6 +459,170 @@ impl YamlConfig {
         {
             c.flow.flush_interval = Duration::from_secs(1);
         }
+        if c.time_source.ptp_sync_interval < Duration::from_millis(100)
+            || c.time_source.ptp_sync_interval > Duration::from_secs(60)
+        {
+            c.time_source.ptp_sync_interval = Duration::from_secs(1);
+        }
+        for size in c.l7_snaplen.tap_types.values_mut() {
+            *size = (*size).min(u16::MAX as u32);
+        }
+        if c.l7_snaplen.adaptive_packet_size > u16::MAX as u32 {
+            c.l7_snaplen.adaptive_packet_size = u16::MAX as u32;
+        }
+        if c.l7_snaplen.adaptive_packet_count == 0 {
+            c.l7_snaplen.adaptive_packet_count = 4;
+        }
+        if c.l7_parse_error_pcap.fail_count == 0 {
+            c.l7_parse_error_pcap.fail_count = 3;
+        }
+        if c.l7_parse_error_pcap.ring_size == 0 || c.l7_parse_error_pcap.ring_size > 128 {
+            c.l7_parse_error_pcap.ring_size = 16;
+        }
+        if c.l7_parse_budget.trip_count == 0 {
+            c.l7_parse_budget.trip_count = 3;
+        }
+        if c.security_event.window < Duration::from_secs(1) {
+            c.security_event.window = Duration::from_secs(60);
+        }
+        if c.security_event.capacity == 0 {
+            c.security_event.capacity = SecurityEventConfig::default().capacity;
+        }
+        c.l7_protocol_detection.order.retain(|p| {
+            if let Err(e) = parse_l7_protocol(p) {
+                warn!("ignore invalid l7-protocol-detection order: {}", e);
+                return false;
+            }
+            true
+        });
+        if c.l7_protocol_detection.min_payload_len == 0 {
+            c.l7_protocol_detection.min_payload_len = 1;
+        }
+        c.l7_protocol_detection
+            .protocol_min_payload_lens
+            .retain(|l| {
+                if let Err(e) = l.l7_protocol() {
+                    warn!(
+                        "ignore invalid l7-protocol-detection min payload len: {}",
+                        e
+                    );
+                    return false;
+                }
+                true
+            });
+        if c.udp_perf.request_timeout.is_zero() {
+            c.udp_perf.request_timeout = Duration::from_secs(3);
+        }
+        c.l7_alert_rules.retain(|r| {
+            if let Err(e) = r.server_net().and(r.l7_protocol()) {
+                warn!("ignore invalid l7 alert rule {}: {}", r.name, e);
+                return false;
+            }
+            true
+        });
+        for param in c.l7_log_http_scrub.query_params.iter_mut() {
+            param.make_ascii_lowercase();
+        }
+        for header in c.l7_log_http_scrub.headers.iter_mut() {
+            header.make_ascii_lowercase();
+        }
+        for header in c.l7_log_http_captured_headers.iter_mut() {
+            header.make_ascii_lowercase();
+        }
+        // Method区分大小写，按配置原样匹配
+        c.l7_log_http_custom_methods.retain(|m| {
+            if m.is_empty()
+                || !m
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            {
+                warn!("ignore invalid l7-log-http-custom-methods {}", m);
+                return false;
+            }
+            true
+        });
+        if c.l7_log_dns.max_answers_length < DNS_MIN_ANSWERS_LENGTH {
+            c.l7_log_dns.max_answers_length = DNS_MIN_ANSWERS_LENGTH;
+        }
+        if c.l7_log_redis.max_key_patterns == 0 {
+            c.l7_log_redis.max_key_patterns = RedisLogConfig::default().max_key_patterns;
+        }
+        if c.server_domain.capacity == 0 {
+            c.server_domain.capacity = ServerDomainConfig::default().capacity;
+        }
+        c.ip_anonymization.cidrs.retain(|cidr| {
+            if let Err(e) = cidr.parse::<IpNet>() {
+                warn!("ignore invalid ip-anonymization cidr {}: {}", cidr, e);
+                return false;
+            }
+            true
+        });
+        if c.ip_anonymization.mode == IpAnonymizationMode::PrefixPreserving
+            && c.ip_anonymization.key.is_empty()
+        {
+            warn!("ip-anonymization key is empty, fallback to truncate mode");
+            c.ip_anonymization.mode = IpAnonymizationMode::Truncate;
+        }
+        if c.profiler.enabled {
+            if c.profiler.token.is_empty() {
+                warn!("profiler token is empty, profiler disabled");
+                c.profiler.enabled = false;
+            } else if let Err(e) = c.profiler.listen_address.parse::<SocketAddr>() {
+                warn!(
+                    "invalid profiler listen-address {}: {}, profiler disabled",
+                    c.profiler.listen_address, e
+                );
+                c.profiler.enabled = false;
+            }
+            if c.profiler.max_seconds == 0 {
+                c.profiler.max_seconds = 1;
+            }
+        }
+        c.l7_rrt_upper_bounds.retain(|b| {
+            if b.upper_bound.is_zero() {
+                warn!("ignore zero l7 rrt upper bound of {}", b.protocol);
+                return false;
+            }
+            if let Err(e) = b.l7_protocol() {
+                warn!("ignore invalid l7 rrt upper bound: {}", e);
+                return false;
+            }
+            true
+        });
+        c.l7_log_session_policies.retain(|p| {
+            if let Err(e) = p.l7_protocol() {
+                warn!("ignore invalid l7 log session policy: {}", e);
+                return false;
+            }
+            true
+        });
+        if c.l7_log_sampling.rate > L7_LOG_SAMPLING_RATE_MAX {
+            warn!(
+                "l7-log-sampling rate {} greater than {}, set to {}",
+                c.l7_log_sampling.rate, L7_LOG_SAMPLING_RATE_MAX, L7_LOG_SAMPLING_RATE_MAX
+            );
+            c.l7_log_sampling.rate = L7_LOG_SAMPLING_RATE_MAX;
+        }
+        c.analyzer_tap_types.retain_mut(|t| {
+            if t.interface.is_empty() || t.tap_type >= 256 {
+                warn!("ignore invalid analyzer tap type {:?}", t);
+                return false;
+            }
+            t.vlan_tap_types.retain(|v| {
+                if v.vlan > VLAN_ID_MASK || v.tap_type == 0 || v.tap_type >= 256 {
+                    warn!("ignore invalid vlan tap type {:?} of {}", v, t.interface);
+                    return false;
+                }
+                true
+            });
+            true
+        });
+        if c.af_packet_fanout.socket_count == 0 {
+            c.af_packet_fanout.socket_count = 1;
+        }
+        if !(50..=100).contains(&c.af_packet_fanout.imbalance_threshold) {
+            c.af_packet_fanout.imbalance_threshold = 80;
+        }
         if c.flow_queue_size < 1 << 16 {
             c.flow_queue_size = 1 << 16;
         }
@@ -307,6 +646,11 @@ impl YamlConfig {
                 1 << 16
             }
         }
+        c.queue_batching.retain(|_, b| b.batch_size > 0);
+        for batching in c.queue_batching.values_mut() {
+            batching.batch_size = batching.batch_size.min(QUEUE_BATCH_SIZE_MAX);
+            batching.linger = batching.linger.min(QUEUE_BATCH_LINGER_MAX);
+        }
         if c.packet_delay < Duration::from_secs(1) || c.packet_delay > Duration::from_secs(10) {
             c.packet_delay = Duration::from_secs(1);
         }
@@ -318,6 +662,12 @@ impl YamlConfig {
         if c.l7_log_session_aggr_timeout.as_secs() < 10 {
             c.l7_log_session_aggr_timeout = Duration::from_secs(10);
         }
+        // 流式响应的持续记录随流统计定时输出，间隔不小于1s
+        if !c.l7_log_http_stream_interval.is_zero()
+            && c.l7_log_http_stream_interval < Duration::from_secs(1)
+        {
+            c.l7_log_http_stream_interval = Duration::from_secs(1);
+        }
 
         if c.external_metrics_sender_queue_size == 0 {
             c.external_metrics_sender_queue_size = 1 << 12;
@@ -331,11 +681,22 @@ impl YamlConfig {
             c.l7_protocol_inference_ttl = L7_PROTOCOL_INFERENCE_TTL;
         }
 
+        // 0表示关闭停滞检测
+        if !c.ebpf_probe_stall_timeout.is_zero()
+            && c.ebpf_probe_stall_timeout < Duration::from_secs(10)
+        {
+            c.ebpf_probe_stall_timeout = Duration::from_secs(10);
+        }
+
         // Enterprise Edition Feature: packet-sequence
         if c.packet_sequence_block_size <= 0 || c.packet_sequence_block_size >= 1024 {
             c.packet_sequence_block_size = 64;
         }
 
+        if c.analyzer_sampling_ratio == 0 {
+            c.analyzer_sampling_ratio = 1;
+        }
+
         // Enterprise Edition Feature: packet-sequence
         if c.packet_sequence_queue_size == 0 {
             if c.tap_mode == trident::TapMode::Analyzer {
@@ -368,8 +729,11 @@ impl Default for YamlConfig {
             profiler: false,
             af_packet_blocks_enabled: false,
             af_packet_blocks: 0,
+            af_packet_fanout: Default::default(),
+            af_packet_hardware_timestamp: false,
             enable_debug_stats: false,
             analyzer_dedup_disabled: false,
+            analyzer_sampling_ratio: 1,
             default_tap_type: 3,
             debug_listen_port: 0,
             enable_qos_bypass: false,
@@ -378,9 +742,28 @@ impl Default for YamlConfig {
             src_interfaces: vec![],
             tap_mode: trident::TapMode::Local,
             mirror_traffic_pcp: 0,
+            analyzer_tap_types: vec![],
             vtap_group_id_request: "".into(),
             pcap: Default::default(),
             flow: Default::default(),
+            l7_snaplen: Default::default(),
+            l7_parse_error_pcap: Default::default(),
+            l7_parse_budget: Default::default(),
+            l7_protocol_detection: Default::default(),
+            udp_perf: Default::default(),
+            l7_alert_rules: vec![],
+            l4_log_filter: Default::default(),
+            security_event: Default::default(),
+            cpu_affinity: Default::default(),
+            checksum_validation: Default::default(),
+            vlan_mode: VlanMode::Inner,
+            server_connection_metrics_enabled: false,
+            tls_sni_metrics_enabled: false,
+            pipeline_mode: PipelineMode::Full,
+            time_source: Default::default(),
+            ip_anonymization: Default::default(),
+            profiler: Default::default(),
+            l7_rrt_upper_bounds: vec![],
             flow_queue_size: 65536,
             quadruple_queue_size: 262144,
             analyzer_queue_size: 131072,
@@ -395,6 +778,7 @@ impl Default for YamlConfig {
             // default size changes according to tap_mode
             flow_sender_queue_size: 0,
             flow_sender_queue_count: 1,
+            queue_batching: HashMap::new(),
             second_flow_extra_delay: Duration::from_secs(0),
             packet_delay: Duration::from_secs(1),
             triple: Default::default(),
@@ -404,10 +788,32 @@ impl Default for YamlConfig {
             ingress_flavour: IngressFlavour::Kubernetes,
             grpc_buffer_size: 5,
             l7_log_session_aggr_timeout: Duration::from_secs(120),
+            l7_log_idle_connection_interval: Duration::ZERO,
+            l7_log_http_stream_interval: Duration::from_secs(60),
+            l7_log_user_name_masked: false,
+            l7_log_http_decompress: false,
+            l7_log_sql_comment_trace_key: "".into(),
+            l7_log_http_scrub: Default::default(),
+            l7_log_http_captured_headers: vec![],
+            l7_log_http_custom_methods: vec![],
+            l7_log_dns: Default::default(),
+            l7_log_redis: Default::default(),
+            l7_log_elasticsearch: Default::default(),
+            l7_log_http_retry: Default::default(),
+            l7_log_truncate: Default::default(),
+            l7_log_session_policies: vec![],
+            l7_log_sampling: Default::default(),
             tap_mac_script: "".into(),
             cloud_gateway_traffic: false,
+            dual_stack_merge_enabled: false,
             ebpf_log_file: "".into(),
+            ebpf_unix_socket_enabled: false,
+            ebpf_probe_stall_timeout: Duration::from_secs(60),
             kubernetes_namespace: "".into(),
+            capture_namespaces: vec![],
+            windows_loopback_capture_enabled: false,
+            business_label_file: "".into(),
+            server_domain: Default::default(),
             external_metrics_sender_queue_size: 0,
             l7_protocol_inference_max_fail_count: L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
             l7_protocol_inference_ttl: L7_PROTOCOL_INFERENCE_TTL,
@@ -497,6 +903,801 @@ impl Default for PcapConfig {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7SnaplenConfig {
+    // key为tap type，覆盖控制器下发的l7-log-packet-size
+    pub tap_types: HashMap<u16, u32>,
+    // 开启后，流的前adaptive-packet-count个应用层包按adaptive-packet-size截取，
+    // 之后恢复为tap type对应的长度，用于采集完整的HTTP/Kafka头部
+    pub adaptive_enabled: bool,
+    // 与L7Protocol的名字比较，忽略大小写
+    pub adaptive_protocols: Vec<String>,
+    pub adaptive_packet_size: u32,
+    pub adaptive_packet_count: u32,
+}
+
+impl Default for L7SnaplenConfig {
+    fn default() -> Self {
+        L7SnaplenConfig {
+            tap_types: HashMap::new(),
+            adaptive_enabled: false,
+            adaptive_protocols: vec!["Http1".into(), "Http2".into(), "Kafka".into()],
+            adaptive_packet_size: 4096,
+            adaptive_packet_count: 4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7ParseErrorPcapConfig {
+    pub enabled: bool,
+    // 流的应用解析连续失败达到该次数时，导出缓存的包
+    pub fail_count: u32,
+    // 每条流缓存的最近包数
+    pub ring_size: usize,
+    pub max_files_per_hour: u32,
+    pub file_directory: PathBuf,
+}
+
+impl Default for L7ParseErrorPcapConfig {
+    fn default() -> Self {
+        L7ParseErrorPcapConfig {
+            enabled: false,
+            fail_count: 3,
+            ring_size: 16,
+            max_files_per_hour: 10,
+            file_directory: "/var/lib/pcap/l7-parse-error".into(),
+        }
+    }
+}
+
+const QUEUE_BATCH_SIZE_MAX: usize = 1 << 16;
+const QUEUE_BATCH_LINGER_MAX: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct QueueBatchingConfig {
+    // 读到batch-size条数据或等待linger后处理一批，为0时不启用
+    pub batch_size: usize,
+    #[serde(with = "humantime_serde")]
+    pub linger: Duration,
+}
+
+impl Default for QueueBatchingConfig {
+    fn default() -> Self {
+        QueueBatchingConfig {
+            batch_size: 0,
+            linger: Duration::from_millis(10),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7ParseBudgetConfig {
+    // 每个协议每隔多少次解析采样一次耗时，为0时不采样
+    pub sample_interval: u32,
+    // 单包解析耗时上限，为0时不熔断
+    #[serde(with = "humantime_serde")]
+    pub packet_budget: Duration,
+    // 流的应用解析连续超时达到该次数时熔断
+    pub trip_count: u32,
+    // 熔断持续时间，期间流只统计字节数和L4性能
+    #[serde(with = "humantime_serde")]
+    pub cooldown: Duration,
+}
+
+impl Default for L7ParseBudgetConfig {
+    fn default() -> Self {
+        L7ParseBudgetConfig {
+            sample_interval: 64,
+            packet_budget: Duration::from_millis(1),
+            trip_count: 3,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7ProtocolDetectionConfig {
+    // 优先识别的协议，如Http1、Mysql，忽略大小写，未配置的协议按默认顺序排在后面
+    pub order: Vec<String>,
+    // 每条流最多尝试识别的包数，为0时不限制
+    pub max_attempts: u32,
+    // 按载荷开头的特征跳过不可能匹配的协议
+    pub content_hint: bool,
+    // 载荷小于该长度的包不参与识别，如TCP keepalive、TLS告警，不计入max-attempts
+    pub min_payload_len: usize,
+    // 单个协议的最小载荷长度，小于该长度时跳过该协议
+    pub protocol_min_payload_lens: Vec<L7MinPayloadLen>,
+}
+
+impl Default for L7ProtocolDetectionConfig {
+    fn default() -> Self {
+        Self {
+            order: vec![],
+            max_attempts: 5,
+            content_hint: true,
+            min_payload_len: 2,
+            protocol_min_payload_lens: vec![],
+        }
+    }
+}
+
+impl L7ProtocolDetectionConfig {
+    pub fn l7_protocols(&self) -> Vec<L7Protocol> {
+        self.order
+            .iter()
+            .filter_map(|p| parse_l7_protocol(p).ok())
+            .collect()
+    }
+
+    pub fn protocol_min_payload_lens(&self) -> Vec<(L7Protocol, usize)> {
+        self.protocol_min_payload_lens
+            .iter()
+            .filter_map(|l| l.l7_protocol().ok().map(|p| (p, l.min_len)))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7MinPayloadLen {
+    // 如Http1、Mysql，忽略大小写
+    pub protocol: String,
+    pub min_len: usize,
+}
+
+impl L7MinPayloadLen {
+    pub fn l7_protocol(&self) -> Result<L7Protocol, String> {
+        parse_l7_protocol(&self.protocol)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct UdpPerfConfig {
+    // 按服务端端口识别UDP请求响应协议，为空时不匹配该协议
+    pub dns_ports: Vec<u16>,
+    pub ntp_ports: Vec<u16>,
+    // QUIC仅匹配握手阶段的Initial包
+    pub quic_ports: Vec<u16>,
+    // 其它一问一答的协议，按请求的先后顺序匹配响应
+    pub generic_ports: Vec<u16>,
+    // 超过该时间未收到响应的请求计为丢失
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+}
+
+impl Default for UdpPerfConfig {
+    fn default() -> Self {
+        UdpPerfConfig {
+            dns_ports: vec![53],
+            ntp_ports: vec![123],
+            quic_ports: vec![443],
+            generic_ports: vec![],
+            request_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum L7AlertMetric {
+    Request,
+    Response,
+    ClientError,
+    ServerError,
+    Timeout,
+    // 单位为us
+    RrtAvg,
+    RrtMax,
+}
+
+// 采集器本地按分钟评估的应用指标告警规则，指标超过阈值时告警
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7AlertRule {
+    pub name: String,
+    // IP或CIDR，为空时匹配所有服务
+    pub server_ip: String,
+    // 为0时匹配所有端口
+    pub server_port: u16,
+    // 如Http1、Mysql，为空时匹配所有协议
+    pub l7_protocol: String,
+    pub metric: L7AlertMetric,
+    pub threshold: u64,
+}
+
+impl Default for L7AlertRule {
+    fn default() -> Self {
+        Self {
+            name: "".into(),
+            server_ip: "".into(),
+            server_port: 0,
+            l7_protocol: "".into(),
+            metric: L7AlertMetric::ServerError,
+            threshold: 100,
+        }
+    }
+}
+
+impl L7AlertRule {
+    pub fn server_net(&self) -> Result<Option<IpNet>, String> {
+        if self.server_ip.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(net) = self.server_ip.parse::<IpNet>() {
+            return Ok(Some(net.trunc()));
+        }
+        self.server_ip
+            .parse::<IpAddr>()
+            .map(|ip| Some(IpNet::from(ip)))
+            .map_err(|e| format!("invalid server-ip {}: {}", self.server_ip, e))
+    }
+
+    pub fn l7_protocol(&self) -> Result<Option<L7Protocol>, String> {
+        if self.l7_protocol.is_empty() {
+            return Ok(None);
+        }
+        parse_l7_protocol(&self.l7_protocol).map(Some)
+    }
+}
+
+fn parse_l7_protocol(name: &str) -> Result<L7Protocol, String> {
+    (0..=u8::MAX)
+        .map(L7Protocol::from)
+        .find(|p| *p != L7Protocol::Unknown && format!("{:?}", p).eq_ignore_ascii_case(name))
+        .ok_or(format!("unknown l7-protocol {}", name))
+}
+
+// 开启后l4流日志仅导出满足任一条件的流，在限速之前执行，用于减少存储同时保留异常流
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L4LogFilterConfig {
+    pub enabled: bool,
+    // CloseType取值，默认为所有客户端及服务端异常结束类型
+    pub close_types: Vec<u8>,
+    // 以下条件为0时不生效
+    #[serde(with = "humantime_serde")]
+    pub min_duration: Duration,
+    // 双向的累计字节数
+    pub min_bytes: u64,
+    // 统计周期内重传包数占总包数的千分比
+    pub min_retrans_permille: u32,
+}
+
+impl Default for L4LogFilterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            close_types: vec![2, 3, 7, 8, 9, 10, 11, 13, 15, 17, 18, 19],
+            min_duration: Duration::ZERO,
+            min_bytes: 0,
+            min_retrans_permille: 0,
+        }
+    }
+}
+
+// 根据只有SYN的TCP流检测扫描及SYN Flood，阈值为0时不检测对应事件
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SecurityEventConfig {
+    pub enabled: bool,
+    // 检测窗口，每个窗口结束时输出事件
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    // 同一源对同一主机扫描的不同端口数
+    pub port_scan_threshold: u32,
+    // 同一源对同一端口扫描的不同主机数
+    pub host_scan_threshold: u32,
+    // 同一目标端口只有SYN的流数
+    pub syn_flood_threshold: u32,
+    // 每个窗口最多跟踪的源及目标数，超出后新出现的源和目标不再统计
+    pub capacity: usize,
+}
+
+impl Default for SecurityEventConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_secs(60),
+            port_scan_threshold: 100,
+            host_scan_threshold: 100,
+            syn_flood_threshold: 1000,
+            capacity: 10000,
+        }
+    }
+}
+
+// 多路服务器上将dispatcher线程绑定到指定CPU，避免跨NUMA节点访问内存，仅Linux生效
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CpuAffinityConfig {
+    // 按dispatcher序号依次绑定，为空时不绑定
+    pub dispatcher_cores: Vec<usize>,
+    // 优先从绑定CPU所在NUMA节点分配dispatcher的队列、FlowMap及抓包缓存
+    pub numa_local_memory: bool,
+}
+
+// 镜像过程中损坏的包会产生虚假的流，校验失败的包计数并按配置丢弃
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ChecksumValidationConfig {
+    pub enabled: bool,
+    pub drop_corrupted: bool,
+    // 开启发送校验和卸载时，本机发出的包的校验和由网卡填写，采集到的值无效
+    pub tx_checksum_offload: bool,
+}
+
+impl Default for ChecksumValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            drop_corrupted: false,
+            tx_checksum_offload: true,
+        }
+    }
+}
+
+// 报文带两层VLAN(QinQ)时流的VLAN取值，inner为内层，outer为外层，strip忽略VLAN
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum VlanMode {
+    Inner,
+    Outer,
+    Strip,
+}
+
+impl Default for VlanMode {
+    fn default() -> Self {
+        VlanMode::Inner
+    }
+}
+
+// 资源受限的边缘节点可只运行指标或日志的处理流程，关闭的流程不创建队列和线程
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PipelineMode {
+    Full,
+    // 不生成指标，L4流日志仍由quadruple generator输出
+    LogsOnly,
+    // 不解析和发送应用日志，包括eBPF采集
+    MetricsOnly,
+}
+
+impl Default for PipelineMode {
+    fn default() -> Self {
+        PipelineMode::Full
+    }
+}
+
+impl PipelineMode {
+    pub fn metrics_enabled(&self) -> bool {
+        *self != PipelineMode::LogsOnly
+    }
+
+    pub fn l7_log_enabled(&self) -> bool {
+        *self != PipelineMode::MetricsOnly
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeSourceMode {
+    // 系统时钟，开启NTP时按控制器校正
+    System,
+    // 按PTP同步的网卡硬件时钟(PHC)校正系统时钟，精度为ns，不再使用NTP
+    Ptp,
+}
+
+impl Default for TimeSourceMode {
+    fn default() -> Self {
+        TimeSourceMode::System
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TimeSourceConfig {
+    pub mode: TimeSourceMode,
+    pub ptp_device: String,
+    // ptp4l将PHC同步为TAI时间，换算UTC需减去该偏移
+    #[serde(with = "humantime_serde")]
+    pub ptp_utc_offset: Duration,
+    #[serde(with = "humantime_serde")]
+    pub ptp_sync_interval: Duration,
+}
+
+impl Default for TimeSourceConfig {
+    fn default() -> Self {
+        Self {
+            mode: TimeSourceMode::System,
+            ptp_device: "/dev/ptp0".to_string(),
+            ptp_utc_offset: Duration::from_secs(37),
+            ptp_sync_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+// 时钟跳变或响应错配会产生极端的RRT，超过上限的RRT按上限统计并在日志中标记
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7RrtUpperBound {
+    // 如Http1、Mysql，忽略大小写
+    pub protocol: String,
+    #[serde(with = "humantime_serde")]
+    pub upper_bound: Duration,
+}
+
+impl Default for L7RrtUpperBound {
+    fn default() -> Self {
+        Self {
+            protocol: "".into(),
+            upper_bound: Duration::ZERO,
+        }
+    }
+}
+
+impl L7RrtUpperBound {
+    pub fn l7_protocol(&self) -> Result<L7Protocol, String> {
+        parse_l7_protocol(&self.protocol)
+    }
+}
+
+// analyzer模式下多个网卡镜像的链路语义不同，按采集网卡指定tap_type
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AnalyzerTapType {
+    pub interface: String,
+    // 0表示使用default-tap-type
+    pub tap_type: u32,
+    pub vlan_tap_types: Vec<VlanTapType>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct VlanTapType {
+    pub vlan: u16,
+    pub tap_type: u32,
+}
+
+// HTTP日志中敏感的查询参数和头部在解析时替换为mask
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HttpScrubConfig {
+    pub query_params: Vec<String>,
+    pub headers: Vec<String>,
+    pub mask: String,
+}
+
+impl Default for HttpScrubConfig {
+    fn default() -> Self {
+        Self {
+            query_params: [
+                "access_token",
+                "token",
+                "api_key",
+                "apikey",
+                "password",
+                "passwd",
+                "secret",
+                "client_secret",
+                "signature",
+                "sessionid",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            headers: [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "set-cookie",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            mask: "***".into(),
+        }
+    }
+}
+
+const DNS_MIN_ANSWERS_LENGTH: usize = 64;
+
+// DNS响应中各段的解析开关，Additional段包含SRV目标及NS的glue记录
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DnsLogConfig {
+    pub authority_section: bool,
+    pub additional_section: bool,
+    // answers字段的最大长度，超出时截断
+    pub max_answers_length: usize,
+    // 按(客户端EPC, 解析服务器IP)聚合为分钟粒度的指标，metrics-only时不再发送DNS日志
+    pub metrics_enabled: bool,
+    pub metrics_only: bool,
+    // 将punycode(xn--)标签解码为Unicode
+    pub idn_decode: bool,
+    // 转为小写并去除末尾的'.'
+    pub name_normalization: bool,
+}
+
+impl Default for DnsLogConfig {
+    fn default() -> Self {
+        Self {
+            authority_section: true,
+            additional_section: false,
+            max_answers_length: 1024,
+            metrics_enabled: false,
+            metrics_only: false,
+            idn_decode: false,
+            name_normalization: false,
+        }
+    }
+}
+
+// 按(客户端EPC, 服务端, key模式)聚合为分钟粒度的请求数及时延，metrics-only时不再发送Redis日志
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RedisLogConfig {
+    pub metrics_enabled: bool,
+    pub metrics_only: bool,
+    // 每分钟的key模式数上限，超出的计入空模式
+    pub max_key_patterns: usize,
+}
+
+impl Default for RedisLogConfig {
+    fn default() -> Self {
+        Self {
+            metrics_enabled: false,
+            metrics_only: false,
+            max_key_patterns: 1024,
+        }
+    }
+}
+
+// 目的端口为以下端口的HTTP日志按Elasticsearch REST API分类，ports为空时不处理
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ElasticsearchLogConfig {
+    pub ports: Vec<u16>,
+    // 响应时延不小于该值的查询标记为慢查询
+    #[serde(with = "humantime_serde")]
+    pub slow_search_threshold: Duration,
+}
+
+impl Default for ElasticsearchLogConfig {
+    fn default() -> Self {
+        Self {
+            ports: vec![9200],
+            slow_search_threshold: Duration::from_secs(1),
+        }
+    }
+}
+
+// 同一客户端在失败（5xx、408、429或超时）后window内重发的相同HTTP请求标记为重试
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HttpRetryConfig {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+}
+
+impl Default for HttpRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_secs(1),
+        }
+    }
+}
+
+// 应用日志中较长字段的截断长度，单位为字节，按UTF-8字符边界截断，为0时不截断
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7LogTruncateConfig {
+    // HTTP请求的path
+    pub http_url: usize,
+    // MySQL、ClickHouse、SQL Server的语句及错误信息
+    pub sql: usize,
+    // Redis的命令及回复
+    pub redis: usize,
+}
+
+impl Default for L7LogTruncateConfig {
+    fn default() -> Self {
+        Self {
+            http_url: 2048,
+            sql: 4096,
+            redis: 1024,
+        }
+    }
+}
+
+// 会话聚合阶段对日志的取舍
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SessionPolicy {
+    // 请求和响应合并为会话
+    Full,
+    // 只发送请求，不缓存等待响应
+    RequestOnly,
+    // 只发送响应
+    ResponseOnly,
+    // 正常合并，只发送异常及未收到响应的日志
+    ErrorOnly,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy::Full
+    }
+}
+
+pub const L7_LOG_SAMPLING_RATE_MAX: u32 = 1000;
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum L7LogSamplingMode {
+    Disabled,
+    // 逐条按比例随机采样
+    HeadBased,
+    // 异常及未收到响应的日志全部保留，其余按比例采样
+    ErrorBiased,
+    // 按trace id的哈希采样，采样率相同的agent对同一trace的取舍一致，没有trace id的日志随机采样
+    TraceIdConsistent,
+}
+
+// 会话聚合后、限速前对L7日志采样
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7LogSamplingConfig {
+    pub mode: L7LogSamplingMode,
+    // 保留比例，单位为千分之一
+    pub rate: u32,
+}
+
+impl Default for L7LogSamplingConfig {
+    fn default() -> Self {
+        Self {
+            mode: L7LogSamplingMode::Disabled,
+            rate: L7_LOG_SAMPLING_RATE_MAX,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct L7SessionPolicy {
+    // 如Http1、Mysql，忽略大小写
+    pub protocol: String,
+    pub policy: SessionPolicy,
+}
+
+impl L7SessionPolicy {
+    pub fn l7_protocol(&self) -> Result<L7Protocol, String> {
+        parse_l7_protocol(&self.protocol)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpAnonymizationMode {
+    Disabled,
+    // IPv4保留/24前缀，IPv6保留/48前缀
+    Truncate,
+    // Crypto-PAn前缀保持映射，相同前缀的IP映射后仍具有相同前缀
+    PrefixPreserving,
+}
+
+// 发送流日志前对匹配cidrs的IP做匿名化
+#[derive(Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct IpAnonymizationConfig {
+    pub mode: IpAnonymizationMode,
+    pub cidrs: Vec<String>,
+    // prefix-preserving模式的密钥，经SHA256得到Crypto-PAn密钥，相同密钥映射结果相同
+    pub key: String,
+}
+
+// 配置变更时会打印，不输出密钥
+impl fmt::Debug for IpAnonymizationConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpAnonymizationConfig")
+            .field("mode", &self.mode)
+            .field("cidrs", &self.cidrs)
+            .field("key", &if self.key.is_empty() { "" } else { "******" })
+            .finish()
+    }
+}
+
+impl Default for IpAnonymizationConfig {
+    fn default() -> Self {
+        Self {
+            mode: IpAnonymizationMode::Disabled,
+            cidrs: vec![],
+            key: "".into(),
+        }
+    }
+}
+
+// 根据采集到的DNS应答为流日志的服务端IP补充域名
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ServerDomainConfig {
+    pub enabled: bool,
+    // 缓存的IP数，超出时淘汰最久未使用的
+    pub capacity: usize,
+    // 应答中的记录超过该时间后不再使用
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+}
+
+impl Default for ServerDomainConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 65536,
+            ttl: Duration::from_secs(600),
+        }
+    }
+}
+
+// 按需采集CPU profile和/proc内存统计的调试HTTP接口，请求需携带Bearer token
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ProfilerConfig {
+    pub enabled: bool,
+    pub listen_address: String,
+    pub token: String,
+    // 单次CPU profile的最长秒数
+    pub max_seconds: u32,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_address: "127.0.0.1:30037".into(),
+            token: "".into(),
+            max_seconds: 300,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum AfPacketFanoutMode {
+    Disabled,
+    Hash,
+    Cpu,
+    Qm,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AfPacketFanoutConfig {
+    // 开启后创建socket-count个dispatcher，其af_packet socket加入同一个fanout组
+    pub mode: AfPacketFanoutMode,
+    pub socket_count: usize,
+    // 单个socket收包占比超过该百分比时上报异常
+    pub imbalance_threshold: u8,
+}
+
+impl Default for AfPacketFanoutConfig {
+    fn default() -> Self {
+        AfPacketFanoutConfig {
+            mode: AfPacketFanoutMode::Disabled,
+            socket_count: 1,
+            imbalance_threshold: 80,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct FlowGeneratorConfig {
@@ -590,6 +1791,67 @@ pub enum IngressFlavour {
     Openshift,
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct L7ObfuscationRule {
+    pub protocol: String,
+    pub field: String,
+    pub pattern: String,
+}
+
+// 服务端下发的应用协议解析配置，version为0表示未下发
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct L7ParserConfig {
+    pub version: u64,
+    pub http_header_whitelist: Vec<String>,
+    pub obfuscation_rules: Vec<L7ObfuscationRule>,
+    pub port_maps: BTreeMap<String, Vec<u16>>,
+}
+
+impl L7ParserConfig {
+    pub fn new(version: u64, conf: trident::L7ParserConfig) -> Self {
+        let obfuscation_rules = conf
+            .obfuscation_rules
+            .into_iter()
+            .filter_map(|rule| {
+                let rule = L7ObfuscationRule {
+                    protocol: rule.protocol().to_lowercase(),
+                    field: rule.field().to_owned(),
+                    pattern: rule.pattern().to_owned(),
+                };
+                if let Err(e) = regex::Regex::new(&rule.pattern) {
+                    warn!("invalid l7 obfuscation rule {:?}: {}", rule, e);
+                    return None;
+                }
+                Some(rule)
+            })
+            .collect();
+        let mut port_maps: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+        for map in conf.port_maps {
+            let ports = port_maps.entry(map.protocol().to_lowercase()).or_default();
+            for port in map.ports {
+                if port == 0 || port > u16::MAX as u32 {
+                    warn!("invalid port {} in l7 port map of {}", port, map.protocol());
+                    continue;
+                }
+                ports.push(port as u16);
+            }
+            ports.sort_unstable();
+            ports.dedup();
+        }
+        Self {
+            version,
+            http_header_whitelist: conf
+                .http_header_whitelist
+                .into_iter()
+                .map(|h| h.trim().to_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            obfuscation_rules,
+            port_maps,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RuntimeConfig {
     pub enabled: bool,
@@ -657,6 +1919,8 @@ pub struct RuntimeConfig {
     pub log_file_size: u32,
     pub external_agent_http_proxy_enabled: bool,
     pub external_agent_http_proxy_port: u16,
+    // 不在trident::Config中，由同步模块按版本单独更新
+    pub l7_parser_config: L7ParserConfig,
     // TODO: expand and remove
     pub yaml_config: YamlConfig,
 }
@@ -881,7 +2145,11 @@ impl TryFrom<trident::Config> for RuntimeConfig {
             log_file_size: conf.log_file_size(),
             external_agent_http_proxy_enabled: conf.external_agent_http_proxy_enabled(),
             external_agent_http_proxy_port: conf.external_agent_http_proxy_port() as u16,
-            yaml_config: YamlConfig::load(conf.local_config())?,
+            l7_parser_config: L7ParserConfig::default(),
+            yaml_config: YamlConfig::load(
+                super::layers::yaml_content(&conf)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?,
+            )?,
         };
         rc.validate()
             .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
@@ -915,4 +2183,148 @@ mod tests {
         assert_eq!(c.controller_ips.len(), 1);
         assert_eq!(&c.controller_ips[0], "127.0.0.1");
     }
+
+    #[test]
+    fn load_standalone() {
+        let contents = "standalone:\n  enabled: true\n  data-socket: 10.1.1.1:30033\nl7-log-sql-comment-trace-key: trace_id\n";
+        let c = Config::load(contents).unwrap();
+        assert!(c.standalone.enabled);
+        assert_eq!(c.controller_ips, vec!["127.0.0.1".to_string()]);
+
+        let path = std::env::temp_dir().join(format!("standalone_{}.yaml", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let rc = c.standalone.runtime_config(&path).unwrap();
+        assert!(rc.collector_enabled);
+        assert!(rc.l4_log_store_tap_types[u16::from(TapType::Any) as usize]);
+        assert_eq!(rc.collector_socket_type, trident::SocketType::Tcp);
+        assert_eq!(rc.analyzer_ip, "10.1.1.1");
+        assert_eq!(rc.analyzer_port, 30033);
+        assert_eq!(rc.yaml_config.l7_log_sql_comment_trace_key, "trace_id");
+
+        let mut standalone = c.standalone.clone();
+        standalone.data_socket = "".into();
+        let rc = standalone.runtime_config(&path).unwrap();
+        assert_eq!(rc.collector_socket_type, trident::SocketType::File);
+        standalone.data_socket = "10.1.1.1".into();
+        assert!(standalone.runtime_config(&path).is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn load_l7_snaplen() {
+        let c = YamlConfig::load(
+            "l7-snaplen:\n  tap-types:\n    3: 100000\n    9: 2048\n  adaptive-enabled: true\n  adaptive-packet-count: 0\n",
+        )
+        .expect("failed loading yaml config");
+        assert_eq!(c.l7_snaplen.tap_types.get(&3), Some(&65535));
+        assert_eq!(c.l7_snaplen.tap_types.get(&9), Some(&2048));
+        assert!(c.l7_snaplen.adaptive_enabled);
+        assert_eq!(c.l7_snaplen.adaptive_packet_count, 4);
+        assert_eq!(c.l7_snaplen.adaptive_packet_size, 4096);
+    }
+
+    #[test]
+    fn load_l7_rrt_upper_bounds() {
+        let c = YamlConfig::load(
+            "l7-rrt-upper-bounds:\n  - protocol: http1\n    upper-bound: 60s\n  - protocol: foo\n    upper-bound: 1s\n  - protocol: Mysql\n",
+        )
+        .expect("failed loading yaml config");
+        assert_eq!(c.l7_rrt_upper_bounds.len(), 1);
+        assert_eq!(
+            c.l7_rrt_upper_bounds[0].l7_protocol(),
+            Ok(L7Protocol::Http1)
+        );
+        assert_eq!(
+            c.l7_rrt_upper_bounds[0].upper_bound,
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn load_l7_log_session_policies() {
+        let c = YamlConfig::load(
+            "l7-log-session-policies:\n  - protocol: dns\n    policy: request-only\n  - protocol: foo\n    policy: error-only\n  - protocol: Http1\n    policy: error-only\n",
+        )
+        .expect("failed loading yaml config");
+        assert_eq!(c.l7_log_session_policies.len(), 2);
+        assert_eq!(
+            c.l7_log_session_policies[0].l7_protocol(),
+            Ok(L7Protocol::Dns)
+        );
+        assert_eq!(
+            c.l7_log_session_policies[0].policy,
+            SessionPolicy::RequestOnly
+        );
+        assert_eq!(
+            c.l7_log_session_policies[1].policy,
+            SessionPolicy::ErrorOnly
+        );
+    }
+
+    #[test]
+    fn load_analyzer_tap_types() {
+        let c = YamlConfig::load(
+            "analyzer-tap-types:\n  - interface: eth1\n    tap-type: 5\n    vlan-tap-types:\n      - vlan: 100\n        tap-type: 6\n      - vlan: 5000\n        tap-type: 7\n  - tap-type: 8\n",
+        )
+        .expect("failed loading yaml config");
+        assert_eq!(
+            c.analyzer_tap_types,
+            vec![AnalyzerTapType {
+                interface: "eth1".into(),
+                tap_type: 5,
+                vlan_tap_types: vec![VlanTapType {
+                    vlan: 100,
+                    tap_type: 6
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn load_af_packet_fanout() {
+        let c = YamlConfig::load(
+            "afpacket-fanout:\n  mode: qm\n  socket-count: 0\n  imbalance-threshold: 10\n",
+        )
+        .expect("failed loading yaml config");
+        assert_eq!(c.af_packet_fanout.mode, AfPacketFanoutMode::Qm);
+        assert_eq!(c.af_packet_fanout.socket_count, 1);
+        assert_eq!(c.af_packet_fanout.imbalance_threshold, 80);
+    }
+
+    #[test]
+    fn convert_l7_parser_config() {
+        let c = L7ParserConfig::new(
+            3,
+            trident::L7ParserConfig {
+                http_header_whitelist: vec![" X-Tenant ".into(), "".into()],
+                obfuscation_rules: vec![
+                    trident::L7ObfuscationRule {
+                        protocol: Some("MySQL".into()),
+                        field: Some("request_resource".into()),
+                        pattern: Some("password=\\S+".into()),
+                    },
+                    trident::L7ObfuscationRule {
+                        protocol: Some("http".into()),
+                        field: Some("request_resource".into()),
+                        pattern: Some("(".into()),
+                    },
+                ],
+                port_maps: vec![
+                    trident::L7PortMap {
+                        protocol: Some("HTTP".into()),
+                        ports: vec![8080, 80, 8080, 0, 70000],
+                    },
+                    trident::L7PortMap {
+                        protocol: Some("http".into()),
+                        ports: vec![81],
+                    },
+                ],
+            },
+        );
+        assert_eq!(c.version, 3);
+        assert_eq!(c.http_header_whitelist, vec!["x-tenant".to_string()]);
+        assert_eq!(c.obfuscation_rules.len(), 1);
+        assert_eq!(c.obfuscation_rules[0].protocol, "mysql");
+        assert_eq!(c.port_maps.get("http"), Some(&vec![80, 81, 8080]));
+    }
 }
@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use prost::Message;
+use serde_yaml::{Mapping, Value};
+
+use super::config::{Config, ConfigError, RuntimeConfig};
+use super::layers::{merge_layers, parse_yaml};
+use crate::proto::trident;
+
+const REDACTED: &str = "******";
+// 键名包含这些字段的配置值在输出时隐藏
+const SECRET_KEYS: [&str; 5] = ["token", "password", "passwd", "secret", "credential"];
+// 键名过于通用无法按子串匹配的密钥，按父键.键名精确匹配
+const SECRET_PATHS: [&str; 1] = ["ip-anonymization.key"];
+
+fn redact(value: &mut Value) {
+    redact_under(value, "")
+}
+
+fn redact_under(value: &mut Value, parent: &str) {
+    match value {
+        Value::Mapping(m) => {
+            for (k, v) in m.iter_mut() {
+                let (secret, key) = match k.as_str() {
+                    Some(k) => {
+                        let k = k.to_lowercase();
+                        let path = format!("{}.{}", parent, k);
+                        (
+                            SECRET_KEYS.iter().any(|s| k.contains(s))
+                                || SECRET_PATHS.contains(&path.as_str()),
+                            k,
+                        )
+                    }
+                    None => (false, String::new()),
+                };
+                match v {
+                    Value::String(s) if secret && !s.is_empty() => *s = REDACTED.into(),
+                    v => redact_under(v, &key),
+                }
+            }
+        }
+        Value::Sequence(s) => s.iter_mut().for_each(|v| redact_under(v, parent)),
+        _ => (),
+    }
+}
+
+// 控制器下发的配置分为两部分：trident::Config中的字段及local_config中的yaml配置，
+// 下发分层配置时yaml配置为合并结果，并输出各配置项的来源
+fn runtime_sections(conf: trident::Config) -> Result<Mapping, ConfigError> {
+    let mut sections = Mapping::new();
+    if conf.config_layers.is_empty() {
+        sections.insert("yaml-config".into(), parse_yaml(conf.local_config())?);
+    } else {
+        let layered = merge_layers(&conf.config_layers)?;
+        sections.insert("yaml-config".into(), layered.merged);
+        sections.insert(
+            "yaml-config-layers".into(),
+            Value::Sequence(layered.layers.into_iter().map(Value::from).collect()),
+        );
+        sections.insert(
+            "yaml-config-provenance".into(),
+            Value::Mapping(
+                layered
+                    .provenance
+                    .into_iter()
+                    .map(|(k, v)| (Value::from(k), Value::from(v)))
+                    .collect(),
+            ),
+        );
+    }
+    let mut runtime = serde_yaml::to_value(&trident::Config {
+        local_config: None,
+        config_layers: vec![],
+        ..conf
+    })
+    .map_err(|e| ConfigError::RuntimeConfigInvalid(e.to_string()))?;
+    if let Value::Mapping(m) = &mut runtime {
+        m.remove(&Value::from("local-config"));
+        m.remove(&Value::from("config-layers"));
+    }
+    sections.insert("runtime-config".into(), runtime);
+    Ok(sections)
+}
+
+fn effective_config(config_path: &Path) -> Result<Value, ConfigError> {
+    let contents = fs::read_to_string(config_path)
+        .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))?;
+    let config = Config::load(&contents)?;
+
+    let mut effective = Mapping::new();
+    effective.insert("static-config".into(), parse_yaml(&contents)?);
+
+    let (source, runtime_config) = if config.standalone.enabled {
+        (
+            config_path.to_path_buf(),
+            config
+                .standalone
+                .trident_config(config_path)
+                .map_err(|e| ConfigError::RuntimeConfigInvalid(e.to_string())),
+        )
+    } else {
+        let cache = config.runtime_config_cache();
+        let runtime_config = fs::read(&cache)
+            .map_err(|e| ConfigError::RuntimeConfigInvalid(format!("{}: {}", cache.display(), e)))
+            .and_then(|b| {
+                trident::RuntimeConfigCache::decode(b.as_slice())
+                    .map_err(|e| ConfigError::RuntimeConfigInvalid(e.to_string()))
+            })
+            .map(|c| {
+                effective.insert("runtime-config-timestamp".into(), c.timestamp().into());
+                c.config.unwrap_or_default()
+            });
+        (cache, runtime_config)
+    };
+    effective.insert(
+        "runtime-config-source".into(),
+        source.display().to_string().into(),
+    );
+    match runtime_config {
+        Ok(conf) => {
+            // 校验失败时agent不会应用该配置，仍然输出以便排查
+            if let Err(e) = RuntimeConfig::try_from(conf.clone()) {
+                effective.insert("runtime-config-error".into(), e.to_string().into());
+            }
+            effective.extend(runtime_sections(conf)?);
+        }
+        Err(e) => {
+            effective.insert("runtime-config-error".into(), e.to_string().into());
+        }
+    }
+
+    let mut effective = Value::Mapping(effective);
+    redact(&mut effective);
+    Ok(effective)
+}
+
+// 输出本地配置及最近一次从控制器收到的运行配置，format为yaml或json
+pub fn dump_config<P: AsRef<Path>>(config_path: P, format: &str) -> Result<String, ConfigError> {
+    let effective = effective_config(config_path.as_ref())?;
+    match format {
+        "yaml" => serde_yaml::to_string(&effective)
+            .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string())),
+        "json" => serde_json::to_string_pretty(&effective)
+            .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string())),
+        _ => Err(ConfigError::YamlConfigInvalid(format!(
+            "unknown dump format {}, should be yaml or json",
+            format
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_and_sections() {
+        let mut value = parse_yaml(
+            "profiler:\n  enabled: true\n  token: abc\nhttp-scrub:\n  query-params: [token]\nl7-log-sql-comment-trace-key: trace_id\n",
+        )
+        .unwrap();
+        redact(&mut value);
+        assert_eq!(value["profiler"]["token"], Value::from(REDACTED));
+        assert_eq!(value["profiler"]["enabled"], Value::from(true));
+        assert_eq!(value["http-scrub"]["query-params"][0], Value::from("token"));
+        assert_eq!(
+            value["l7-log-sql-comment-trace-key"],
+            Value::from("trace_id")
+        );
+
+        let mut value = parse_yaml(
+            "ip-anonymization:\n  mode: prefix-preserving\n  key: my-secret-key\nkey: not-secret\n",
+        )
+        .unwrap();
+        redact(&mut value);
+        assert_eq!(value["ip-anonymization"]["key"], Value::from(REDACTED));
+        assert_eq!(
+            value["ip-anonymization"]["mode"],
+            Value::from("prefix-preserving")
+        );
+        assert_eq!(value["key"], Value::from("not-secret"));
+
+        let conf = trident::Config {
+            vtap_id: Some(3),
+            local_config: Some("log-level: debug\n".into()),
+            ..Default::default()
+        };
+        let sections = Value::Mapping(runtime_sections(conf).unwrap());
+        assert_eq!(sections["runtime-config"]["vtap-id"], Value::from(3));
+        assert!(sections["runtime-config"].get("local-config").is_none());
+        assert_eq!(sections["yaml-config"]["log-level"], Value::from("debug"));
+        assert!(sections.get("yaml-config-provenance").is_none());
+
+        let conf = trident::Config {
+            local_config: Some("log-level: debug\n".into()),
+            config_layers: vec![trident::ConfigLayer {
+                r#type: Some(trident::ConfigLayerType::Group as i32),
+                name: Some("g-1".into()),
+                content: Some("log-level: warn\n".into()),
+            }],
+            ..Default::default()
+        };
+        let sections = Value::Mapping(runtime_sections(conf).unwrap());
+        assert!(sections["runtime-config"].get("config-layers").is_none());
+        assert_eq!(sections["yaml-config"]["log-level"], Value::from("warn"));
+        assert_eq!(
+            sections["yaml-config-provenance"]["log-level"],
+            Value::from("group:g-1")
+        );
+    }
+}
@@ -15,6 +15,7 @@
  */
 
 use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::fmt;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
@@ -34,17 +35,31 @@ use log::{info, warn, Level};
 
 use super::config::PortConfig;
 use super::{
-    config::{Config, PcapConfig, YamlConfig},
-    ConfigError, IngressFlavour, KubernetesPollerType, RuntimeConfig,
+    config::{
+        Config, DnsLogConfig, ElasticsearchLogConfig, HttpRetryConfig, HttpScrubConfig,
+        IpAnonymizationConfig, L4LogFilterConfig, L7AlertRule, L7LogSamplingConfig,
+        L7LogTruncateConfig, L7ParseBudgetConfig, L7ParseErrorPcapConfig,
+        L7ProtocolDetectionConfig, PcapConfig, RedisLogConfig, SecurityEventConfig, UdpPerfConfig,
+        YamlConfig,
+    },
+    ConfigError, IngressFlavour, KubernetesPollerType, L7ParserConfig, RuntimeConfig,
+    SessionPolicy,
 };
 
 #[cfg(target_os = "windows")]
-use crate::utils::net::links_by_name_regex;
+use crate::utils::net::{add_loopback_link, links_by_name_regex};
+#[cfg(target_os = "linux")]
+use crate::{
+    common::DEFAULT_CPU_CFS_PERIOD_US,
+    dispatcher::recv_engine::af_packet::OptTpacketVersion,
+    ebpf::CAP_LEN_MAX,
+    utils::{cgroups::Cgroups, environment::is_tt_pod, environment::is_tt_workload},
+};
 use crate::{
-    common::decapsulate::TunnelTypeBitmap,
+    common::{decapsulate::TunnelTypeBitmap, enums::TapType, flow::L7Protocol},
     dispatcher::recv_engine,
     exception::ExceptionHandler,
-    flow_generator::{FlowTimeout, TcpTimeout},
+    flow_generator::{set_custom_methods, FlowTimeout, L7ProtocolDetector, TcpTimeout},
     proto::trident::{self, CaptureSocketType},
     proto::{
         common::TridentType,
@@ -57,13 +72,6 @@ use crate::{
         net::{get_ctrl_ip_and_mac, MacAddr},
     },
 };
-#[cfg(target_os = "linux")]
-use crate::{
-    common::{enums::TapType, DEFAULT_CPU_CFS_PERIOD_US},
-    dispatcher::recv_engine::af_packet::OptTpacketVersion,
-    ebpf::CAP_LEN_MAX,
-    utils::{cgroups::Cgroups, environment::is_tt_pod, environment::is_tt_workload},
-};
 
 const MB: u64 = 1048576;
 const MINUTE: Duration = Duration::from_secs(60);
@@ -113,10 +121,18 @@ pub struct CollectorConfig {
     pub vtap_flow_1s_enabled: bool,
     pub l4_log_collect_nps_threshold: u64,
     pub l4_log_store_tap_types: [bool; 256],
+    pub l4_log_filter: L4LogFilterConfig,
+    pub security_event: SecurityEventConfig,
     pub l7_metrics_enabled: bool,
     pub trident_type: TridentType,
     pub vtap_id: u16,
     pub cloud_gateway_traffic: bool,
+    pub dual_stack_merge_enabled: bool,
+    pub server_connection_metrics_enabled: bool,
+    pub tls_sni_metrics_enabled: bool,
+    pub l7_alert_rules: Vec<L7AlertRule>,
+    // 仅analyzer模式下生效，其他模式为1
+    pub sampling_ratio: u32,
 }
 
 impl fmt::Debug for CollectorConfig {
@@ -142,10 +158,20 @@ impl fmt::Debug for CollectorConfig {
                 "l4_log_collect_nps_threshold",
                 &self.l4_log_collect_nps_threshold,
             )
+            .field("l4_log_filter", &self.l4_log_filter)
+            .field("security_event", &self.security_event)
             .field("l7_metrics_enabled", &self.l7_metrics_enabled)
             .field("trident_type", &self.trident_type)
             .field("vtap_id", &self.vtap_id)
             .field("cloud_gateway_traffic", &self.cloud_gateway_traffic)
+            .field("dual_stack_merge_enabled", &self.dual_stack_merge_enabled)
+            .field(
+                "server_connection_metrics_enabled",
+                &self.server_connection_metrics_enabled,
+            )
+            .field("tls_sni_metrics_enabled", &self.tls_sni_metrics_enabled)
+            .field("l7_alert_rules", &self.l7_alert_rules)
+            .field("sampling_ratio", &self.sampling_ratio)
             .finish()
     }
 }
@@ -176,6 +202,7 @@ pub struct SenderConfig {
     pub server_tx_bandwidth_threshold: u64,
     pub bandwidth_probe_interval: Duration,
     pub enabled: bool,
+    pub ip_anonymization: IpAnonymizationConfig,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -226,6 +253,9 @@ pub struct DispatcherConfig {
     pub region_id: u32,
     pub pod_cluster_id: u32,
     pub enabled: bool,
+    pub capture_namespaces: Vec<String>,
+    #[cfg(target_os = "windows")]
+    pub loopback_capture_enabled: bool,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -255,18 +285,59 @@ pub struct FlowConfig {
     pub app_proto_log_enabled: bool,
     pub l4_performance_enabled: bool,
     pub l7_log_packet_size: u32,
+    // 按tap type索引，未单独配置的tap type取l7_log_packet_size
+    pub l7_log_packet_sizes: [u32; 256],
+    // 按L7Protocol索引，为true的协议在流的前几个包中扩展采集长度
+    pub l7_snaplen_adaptive_protocols: [bool; 256],
+    pub l7_snaplen_adaptive_packet_size: u32,
+    pub l7_snaplen_adaptive_packet_count: u32,
 
     pub l7_protocol_inference_max_fail_count: usize,
     pub l7_protocol_inference_ttl: usize,
 
+    pub l7_parse_error_pcap: L7ParseErrorPcapConfig,
+    pub l7_parse_budget: L7ParseBudgetConfig,
+    pub l7_protocol_detector: L7ProtocolDetector,
+    pub udp_perf: UdpPerfConfig,
+    // 按L7Protocol索引，为0时不限制
+    pub l7_rrt_upper_bounds: [Duration; 256],
+    // 为0时不输出HTTP/2空闲连接日志
+    pub l7_log_idle_connection_interval: Duration,
+    // 为0时不输出HTTP/1流式响应的持续记录
+    pub l7_log_http_stream_interval: Duration,
+
     // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_flag: u8,
     pub packet_sequence_block_size: usize,
 }
 
+impl FlowConfig {
+    pub fn l7_log_packet_size(&self, tap_type: TapType) -> u32 {
+        let tap_type = u16::from(tap_type) as usize;
+        if tap_type < self.l7_log_packet_sizes.len() {
+            self.l7_log_packet_sizes[tap_type]
+        } else {
+            self.l7_log_packet_size
+        }
+    }
+}
+
+impl From<&L7ProtocolDetectionConfig> for L7ProtocolDetector {
+    fn from(detection: &L7ProtocolDetectionConfig) -> Self {
+        L7ProtocolDetector::new(
+            &detection.l7_protocols(),
+            detection.content_hint,
+            detection.max_attempts,
+            detection.min_payload_len,
+            &detection.protocol_min_payload_lens(),
+        )
+    }
+}
+
 impl From<&RuntimeConfig> for FlowConfig {
     fn from(conf: &RuntimeConfig) -> Self {
         let flow_config = &conf.yaml_config.flow;
+        let snaplen_config = &conf.yaml_config.l7_snaplen;
         FlowConfig {
             vtap_id: conf.vtap_id as u16,
             trident_type: conf.trident_type,
@@ -286,10 +357,54 @@ impl From<&RuntimeConfig> for FlowConfig {
             app_proto_log_enabled: conf.app_proto_log_enabled,
             l4_performance_enabled: conf.l4_performance_enabled,
             l7_log_packet_size: conf.l7_log_packet_size,
+            l7_log_packet_sizes: {
+                let mut sizes = [conf.l7_log_packet_size; 256];
+                for (&tap_type, &size) in snaplen_config.tap_types.iter() {
+                    if (tap_type as usize) < sizes.len() {
+                        sizes[tap_type as usize] = size;
+                    }
+                }
+                sizes
+            },
+            l7_snaplen_adaptive_protocols: {
+                let mut protocols = [false; 256];
+                if snaplen_config.adaptive_enabled {
+                    for i in 0..L7Protocol::Max as u8 {
+                        let protocol = L7Protocol::from(i);
+                        if protocol == L7Protocol::Unknown {
+                            continue;
+                        }
+                        let name = format!("{:?}", protocol);
+                        protocols[i as usize] = snaplen_config
+                            .adaptive_protocols
+                            .iter()
+                            .any(|p| p.eq_ignore_ascii_case(&name));
+                    }
+                }
+                protocols
+            },
+            l7_snaplen_adaptive_packet_size: snaplen_config.adaptive_packet_size,
+            l7_snaplen_adaptive_packet_count: snaplen_config.adaptive_packet_count,
             l7_protocol_inference_max_fail_count: conf
                 .yaml_config
                 .l7_protocol_inference_max_fail_count,
             l7_protocol_inference_ttl: conf.yaml_config.l7_protocol_inference_ttl,
+            l7_parse_error_pcap: conf.yaml_config.l7_parse_error_pcap.clone(),
+            l7_parse_budget: conf.yaml_config.l7_parse_budget.clone(),
+            l7_protocol_detector: (&conf.yaml_config.l7_protocol_detection).into(),
+            udp_perf: conf.yaml_config.udp_perf.clone(),
+            l7_rrt_upper_bounds: {
+                let mut bounds = [Duration::ZERO; 256];
+                // 协议名在加载配置时已校验
+                for b in conf.yaml_config.l7_rrt_upper_bounds.iter() {
+                    if let Ok(protocol) = b.l7_protocol() {
+                        bounds[protocol as usize] = b.upper_bound;
+                    }
+                }
+                bounds
+            },
+            l7_log_idle_connection_interval: conf.yaml_config.l7_log_idle_connection_interval,
+            l7_log_http_stream_interval: conf.yaml_config.l7_log_http_stream_interval,
             packet_sequence_flag: conf.yaml_config.packet_sequence_flag, // Enterprise Edition Feature: packet-sequence
             packet_sequence_block_size: conf.yaml_config.packet_sequence_block_size, // Enterprise Edition Feature: packet-sequence
         }
@@ -320,11 +435,60 @@ impl fmt::Debug for FlowConfig {
             .field("app_proto_log_enabled", &self.app_proto_log_enabled)
             .field("l4_performance_enabled", &self.l4_performance_enabled)
             .field("l7_log_packet_size", &self.l7_log_packet_size)
+            .field(
+                "l7_log_packet_sizes",
+                &self
+                    .l7_log_packet_sizes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, s)| *s != self.l7_log_packet_size)
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "l7_snaplen_adaptive_protocols",
+                &self
+                    .l7_snaplen_adaptive_protocols
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, b)| *b)
+                    .map(|(i, _)| L7Protocol::from(i as u8))
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "l7_snaplen_adaptive_packet_size",
+                &self.l7_snaplen_adaptive_packet_size,
+            )
+            .field(
+                "l7_snaplen_adaptive_packet_count",
+                &self.l7_snaplen_adaptive_packet_count,
+            )
             .field(
                 "l7_protocol_inference_max_fail_count",
                 &self.l7_protocol_inference_max_fail_count,
             )
             .field("l7_protocol_inference_ttl", &self.l7_protocol_inference_ttl)
+            .field("l7_parse_error_pcap", &self.l7_parse_error_pcap)
+            .field("l7_parse_budget", &self.l7_parse_budget)
+            .field("l7_protocol_detector", &self.l7_protocol_detector)
+            .field("udp_perf", &self.udp_perf)
+            .field(
+                "l7_rrt_upper_bounds",
+                &self
+                    .l7_rrt_upper_bounds
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, b)| !b.is_zero())
+                    .map(|(i, b)| (L7Protocol::from(i as u8), b))
+                    .collect::<Vec<_>>(),
+            )
+            .field(
+                "l7_log_idle_connection_interval",
+                &self.l7_log_idle_connection_interval,
+            )
+            .field(
+                "l7_log_http_stream_interval",
+                &self.l7_log_http_stream_interval,
+            )
             .finish()
     }
 }
@@ -333,7 +497,24 @@ impl fmt::Debug for FlowConfig {
 pub struct LogParserConfig {
     pub l7_log_collect_nps_threshold: u64,
     pub l7_log_session_aggr_timeout: Duration,
+    pub l7_log_user_name_masked: bool,
+    pub l7_log_http_decompress: bool,
+    pub l7_log_sql_comment_trace_key: String,
+    pub l7_log_http_scrub: HttpScrubConfig,
+    pub l7_log_http_captured_headers: Vec<String>,
+    pub l7_log_http_custom_methods: Vec<String>,
+    pub l7_log_dns: DnsLogConfig,
+    pub l7_log_redis: RedisLogConfig,
+    pub l7_log_elasticsearch: ElasticsearchLogConfig,
+    pub l7_log_http_retry: HttpRetryConfig,
+    pub l7_log_truncate: L7LogTruncateConfig,
+    // 响应首字节时延超过该值的HTTP会话标记为流式响应，为0时不标记
+    pub l7_log_http_stream_interval: Duration,
+    // 未配置的协议为SessionPolicy::Full
+    pub l7_log_session_policies: HashMap<L7Protocol, SessionPolicy>,
+    pub l7_log_sampling: L7LogSamplingConfig,
     pub l7_log_dynamic: L7LogDynamicConfig,
+    pub l7_parser: L7ParserConfig,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -380,6 +561,10 @@ pub struct EbpfConfig {
     pub log_path: String,
     pub l7_log_tap_types: [bool; 256],
     pub ctrl_mac: MacAddr,
+    pub capture_namespaces: Vec<String>,
+    pub unix_socket_enabled: bool,
+    pub probe_stall_timeout: Duration,
+    pub l7_protocol_detector: L7ProtocolDetector,
 }
 
 #[cfg(target_os = "linux")]
@@ -408,6 +593,10 @@ impl fmt::Debug for EbpfConfig {
                     .collect::<Vec<_>>(),
             )
             .field("ctrl_mac", &self.ctrl_mac)
+            .field("capture_namespaces", &self.capture_namespaces)
+            .field("unix_socket_enabled", &self.unix_socket_enabled)
+            .field("probe_stall_timeout", &self.probe_stall_timeout)
+            .field("l7_protocol_detector", &self.l7_protocol_detector)
             .finish()
     }
 }
@@ -657,6 +846,9 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 region_id: conf.region_id,
                 pod_cluster_id: conf.pod_cluster_id,
                 enabled: conf.enabled,
+                capture_namespaces: conf.yaml_config.capture_namespaces.clone(),
+                #[cfg(target_os = "windows")]
+                loopback_capture_enabled: conf.yaml_config.windows_loopback_capture_enabled,
             },
             sender: SenderConfig {
                 mtu: conf.mtu,
@@ -678,6 +870,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                     .unwrap()
                     .to_string(),
                 enabled: conf.collector_enabled,
+                ip_anonymization: conf.yaml_config.ip_anonymization.clone(),
             },
             collector: CollectorConfig {
                 enabled: conf.collector_enabled,
@@ -689,7 +882,20 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 trident_type: conf.trident_type,
                 vtap_id: conf.vtap_id as u16,
                 l4_log_store_tap_types: conf.l4_log_store_tap_types,
+                l4_log_filter: conf.yaml_config.l4_log_filter.clone(),
+                security_event: conf.yaml_config.security_event.clone(),
                 cloud_gateway_traffic: conf.yaml_config.cloud_gateway_traffic,
+                dual_stack_merge_enabled: conf.yaml_config.dual_stack_merge_enabled,
+                server_connection_metrics_enabled: conf
+                    .yaml_config
+                    .server_connection_metrics_enabled,
+                tls_sni_metrics_enabled: conf.yaml_config.tls_sni_metrics_enabled,
+                l7_alert_rules: conf.yaml_config.l7_alert_rules.clone(),
+                sampling_ratio: if conf.yaml_config.tap_mode == TapMode::Analyzer {
+                    conf.yaml_config.analyzer_sampling_ratio
+                } else {
+                    1
+                },
             },
             handler: HandlerConfig {
                 compressor_socket_type: conf.compressor_socket_type,
@@ -719,6 +925,26 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
             log_parser: LogParserConfig {
                 l7_log_collect_nps_threshold: conf.l7_log_collect_nps_threshold,
                 l7_log_session_aggr_timeout: conf.yaml_config.l7_log_session_aggr_timeout,
+                l7_log_user_name_masked: conf.yaml_config.l7_log_user_name_masked,
+                l7_log_http_decompress: conf.yaml_config.l7_log_http_decompress,
+                l7_log_sql_comment_trace_key: conf.yaml_config.l7_log_sql_comment_trace_key.clone(),
+                l7_log_http_scrub: conf.yaml_config.l7_log_http_scrub.clone(),
+                l7_log_http_captured_headers: conf.yaml_config.l7_log_http_captured_headers.clone(),
+                l7_log_http_custom_methods: conf.yaml_config.l7_log_http_custom_methods.clone(),
+                l7_log_dns: conf.yaml_config.l7_log_dns.clone(),
+                l7_log_redis: conf.yaml_config.l7_log_redis.clone(),
+                l7_log_elasticsearch: conf.yaml_config.l7_log_elasticsearch.clone(),
+                l7_log_http_retry: conf.yaml_config.l7_log_http_retry.clone(),
+                l7_log_truncate: conf.yaml_config.l7_log_truncate.clone(),
+                l7_log_http_stream_interval: conf.yaml_config.l7_log_http_stream_interval,
+                // 协议名在加载配置时已校验
+                l7_log_session_policies: conf
+                    .yaml_config
+                    .l7_log_session_policies
+                    .iter()
+                    .filter_map(|p| p.l7_protocol().ok().map(|l| (l, p.policy)))
+                    .collect(),
+                l7_log_sampling: conf.yaml_config.l7_log_sampling.clone(),
                 l7_log_dynamic: L7LogDynamicConfig {
                     proxy_client_origin: conf.http_log_proxy_client.to_string(),
                     proxy_client_lower: conf.http_log_proxy_client.to_string().to_lowercase(),
@@ -739,6 +965,7 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         .map(|item| TraceType::from(item))
                         .collect(),
                 },
+                l7_parser: conf.l7_parser_config.clone(),
             },
             debug: DebugConfig {
                 vtap_id: conf.vtap_id as u16,
@@ -776,6 +1003,10 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 } else {
                     MacAddr::ZERO
                 },
+                capture_namespaces: conf.yaml_config.capture_namespaces.clone(),
+                unix_socket_enabled: conf.yaml_config.ebpf_unix_socket_enabled,
+                probe_stall_timeout: conf.yaml_config.ebpf_probe_stall_timeout,
+                l7_protocol_detector: (&conf.yaml_config.l7_protocol_detection).into(),
             },
             metric_server: MetricServerConfig {
                 enabled: conf.external_agent_http_proxy_enabled,
@@ -813,6 +1044,7 @@ impl ConfigHandler {
         let candidate_config =
             ModuleConfig::try_from((config.clone(), RuntimeConfig::default())).unwrap();
         let current_config = Arc::new(ArcSwap::from_pointee(candidate_config.clone()));
+        set_custom_methods(&candidate_config.log_parser.l7_log_http_custom_methods);
 
         Self {
             static_config: config,
@@ -943,6 +1175,14 @@ impl ConfigHandler {
             warn!("{}", e);
         }
 
+        // 队列和线程在启动时按pipeline_mode创建，运行中不重建
+        if yaml_config.pipeline_mode != new_config.yaml_config.pipeline_mode {
+            warn!(
+                "pipeline_mode change from {:?} to {:?} is ignored, restart deepflow-agent to take effect",
+                yaml_config.pipeline_mode, new_config.yaml_config.pipeline_mode
+            );
+        }
+
         if candidate_config.dispatcher != new_config.dispatcher {
             if candidate_config.dispatcher.if_mac_source != new_config.dispatcher.if_mac_source {
                 if yaml_config.tap_mode != TapMode::Local {
@@ -955,11 +1195,13 @@ impl ConfigHandler {
 
             #[cfg(target_os = "windows")]
             if yaml_config.tap_mode == TapMode::Local
-                && candidate_config.dispatcher.tap_interface_regex
+                && (candidate_config.dispatcher.tap_interface_regex
                     != new_config.dispatcher.tap_interface_regex
+                    || candidate_config.dispatcher.loopback_capture_enabled
+                        != new_config.dispatcher.loopback_capture_enabled)
             {
                 fn switch_recv_engine(handler: &ConfigHandler, comp: &mut Components) {
-                    let pcap_interfaces = match links_by_name_regex(
+                    let mut pcap_interfaces = match links_by_name_regex(
                         &handler.candidate_config.dispatcher.tap_interface_regex,
                     ) {
                         Err(e) => {
@@ -968,6 +1210,9 @@ impl ConfigHandler {
                         }
                         Ok(links) => links,
                     };
+                    if handler.candidate_config.dispatcher.loopback_capture_enabled {
+                        add_loopback_link(&mut pcap_interfaces);
+                    }
                     for dispatcher in comp.dispatchers.iter() {
                         dispatcher.switch_recv_engine(pcap_interfaces.clone());
                     }
@@ -1403,6 +1648,17 @@ impl ConfigHandler {
                     restart_dispatcher = true;
                 }
             }
+            if candidate_config.sender.ip_anonymization != new_config.sender.ip_anonymization {
+                fn ip_anonymization_callback(_: &ConfigHandler, components: &mut Components) {
+                    components
+                        .l4_flow_uniform_sender
+                        .ip_anonymization_config_updated();
+                    if let Some(sender) = components.l7_flow_uniform_sender.as_ref() {
+                        sender.ip_anonymization_config_updated();
+                    }
+                }
+                callbacks.push(ip_anonymization_callback);
+            }
             info!(
                 "sender config change from {:#?} to {:#?}",
                 candidate_config.sender, new_config.sender
@@ -1429,12 +1685,19 @@ impl ConfigHandler {
                 candidate_config.log_parser, new_config.log_parser
             );
 
-            if candidate_config.log_parser.l7_log_dynamic != new_config.log_parser.l7_log_dynamic {
+            if candidate_config.log_parser.l7_log_dynamic != new_config.log_parser.l7_log_dynamic
+                || candidate_config.log_parser.l7_parser != new_config.log_parser.l7_parser
+            {
                 info!(
                     "l7 log dynamic config change from {:#?} to {:#?}",
                     candidate_config.log_parser.l7_log_dynamic,
                     new_config.log_parser.l7_log_dynamic
                 );
+                info!(
+                    "l7 parser config version change from {} to {}",
+                    candidate_config.log_parser.l7_parser.version,
+                    new_config.log_parser.l7_parser.version
+                );
                 fn l7_log_dynamic_callback(_: &ConfigHandler, components: &mut Components) {
                     for log_parser in components.log_parsers.iter().as_ref() {
                         log_parser.l7_log_dynamic_config_updated();
@@ -1469,6 +1732,8 @@ impl ConfigHandler {
                 }
                 callbacks.push(l7_log_collect_nps_threshold_callback);
             }
+            // 协议识别在多个线程中进行，直接更新全局的自定义Method
+            set_custom_methods(&new_config.log_parser.l7_log_http_custom_methods);
 
             candidate_config.log_parser = new_config.log_parser;
         }
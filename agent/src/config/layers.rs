@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde_yaml::{Mapping, Value};
+
+use super::config::ConfigError;
+use crate::proto::trident::{self, ConfigLayerType};
+
+pub fn parse_yaml(contents: &str) -> Result<Value, ConfigError> {
+    if contents.trim().is_empty() {
+        return Ok(Value::Mapping(Mapping::new()));
+    }
+    serde_yaml::from_str(contents).map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))
+}
+
+pub struct LayeredConfig {
+    // 按合并顺序排列的配置层名称
+    pub layers: Vec<String>,
+    pub merged: Value,
+    // 配置项路径(以.分隔)到设置该项的配置层名称
+    pub provenance: BTreeMap<String, String>,
+}
+
+fn layer_name(layer: &trident::ConfigLayer) -> String {
+    let kind = match layer.r#type() {
+        ConfigLayerType::Base => "base",
+        ConfigLayerType::Group => "group",
+        ConfigLayerType::Host => "host",
+    };
+    if layer.name().is_empty() {
+        kind.to_owned()
+    } else {
+        format!("{}:{}", kind, layer.name())
+    }
+}
+
+fn key_string(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        k => format!("{:?}", k),
+    }
+}
+
+// 覆盖或删除整个子树时，清除其中低层配置的来源
+fn clear_provenance(provenance: &mut BTreeMap<String, String>, path: &str) {
+    let prefix = format!("{}.", path);
+    provenance.retain(|p, _| p != path && !p.starts_with(&prefix));
+}
+
+fn record_provenance(
+    value: &Value,
+    path: String,
+    layer: &str,
+    provenance: &mut BTreeMap<String, String>,
+) {
+    match value {
+        Value::Mapping(m) if !m.is_empty() => {
+            for (k, v) in m.iter() {
+                record_provenance(v, format!("{}.{}", path, key_string(k)), layer, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path, layer.to_owned());
+        }
+    }
+}
+
+// 字典逐项递归合并，其他类型(包括列表)整体覆盖，值为null时删除低层的配置以恢复默认值
+fn merge_mapping(
+    dst: &mut Mapping,
+    src: Mapping,
+    prefix: &str,
+    layer: &str,
+    provenance: &mut BTreeMap<String, String>,
+) {
+    for (k, v) in src {
+        let path = if prefix.is_empty() {
+            key_string(&k)
+        } else {
+            format!("{}.{}", prefix, key_string(&k))
+        };
+        let v = match (dst.get_mut(&k), v) {
+            (Some(Value::Mapping(d)), Value::Mapping(s)) => {
+                merge_mapping(d, s, &path, layer, provenance);
+                continue;
+            }
+            (_, v) => v,
+        };
+        clear_provenance(provenance, &path);
+        if v.is_null() {
+            dst.remove(&k);
+        } else {
+            record_provenance(&v, path, layer, provenance);
+            dst.insert(k, v);
+        }
+    }
+}
+
+pub fn merge_layers(layers: &[trident::ConfigLayer]) -> Result<LayeredConfig, ConfigError> {
+    let mut sorted = layers.iter().collect::<Vec<_>>();
+    // 稳定排序，同类型保持下发顺序
+    sorted.sort_by_key(|l| l.r#type());
+
+    let mut config = LayeredConfig {
+        layers: vec![],
+        merged: Value::Mapping(Mapping::new()),
+        provenance: BTreeMap::new(),
+    };
+    for layer in sorted {
+        let name = layer_name(layer);
+        let src = match parse_yaml(layer.content()) {
+            Ok(Value::Mapping(m)) => m,
+            Ok(_) => {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "config layer {} is not a mapping",
+                    name
+                )))
+            }
+            Err(e) => {
+                return Err(ConfigError::YamlConfigInvalid(format!(
+                    "config layer {}: {}",
+                    name, e
+                )))
+            }
+        };
+        if let Value::Mapping(dst) = &mut config.merged {
+            merge_mapping(dst, src, "", &name, &mut config.provenance);
+        }
+        config.layers.push(name);
+    }
+    Ok(config)
+}
+
+// 运行配置使用的yaml配置，未下发分层配置时为local_config
+pub fn yaml_content(conf: &trident::Config) -> Result<Cow<str>, ConfigError> {
+    if conf.config_layers.is_empty() {
+        return Ok(Cow::Borrowed(conf.local_config()));
+    }
+    let layered = merge_layers(&conf.config_layers)?;
+    serde_yaml::to_string(&layered.merged)
+        .map(Cow::Owned)
+        .map_err(|e| ConfigError::YamlConfigInvalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(t: ConfigLayerType, name: &str, content: &str) -> trident::ConfigLayer {
+        trident::ConfigLayer {
+            r#type: Some(t as i32),
+            name: Some(name.into()),
+            content: Some(content.into()),
+        }
+    }
+
+    #[test]
+    fn merge_with_provenance() {
+        let layers = vec![
+            layer(
+                ConfigLayerType::Host,
+                "node-1",
+                "flow:\n  flush-interval: 3s\nl7-protocol-ports: ~\n",
+            ),
+            layer(
+                ConfigLayerType::Base,
+                "default",
+                "log-level: info\nflow:\n  flush-interval: 1s\n  capacity: 1024\nl7-protocol-ports:\n  HTTP: 80\n",
+            ),
+            layer(
+                ConfigLayerType::Group,
+                "g-1",
+                "log-level: debug\nsrc-interfaces: [eth0]\n",
+            ),
+            layer(ConfigLayerType::Group, "g-2", "src-interfaces: [eth1]\n"),
+        ];
+        let config = merge_layers(&layers).unwrap();
+        assert_eq!(
+            config.layers,
+            vec!["base:default", "group:g-1", "group:g-2", "host:node-1"]
+        );
+        assert_eq!(config.merged["log-level"], Value::from("debug"));
+        assert_eq!(config.merged["flow"]["flush-interval"], Value::from("3s"));
+        assert_eq!(config.merged["flow"]["capacity"], Value::from(1024));
+        assert_eq!(config.merged["src-interfaces"][0], Value::from("eth1"));
+        assert!(config.merged.get("l7-protocol-ports").is_none());
+
+        let provenance = config
+            .provenance
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            provenance,
+            vec![
+                ("flow.capacity", "base:default"),
+                ("flow.flush-interval", "host:node-1"),
+                ("log-level", "group:g-1"),
+                ("src-interfaces", "group:g-2"),
+            ]
+        );
+
+        let invalid = vec![layer(ConfigLayerType::Host, "node-1", "- a\n")];
+        assert!(merge_layers(&invalid).is_err());
+    }
+}
@@ -15,10 +15,21 @@
  */
 
 mod config;
+mod dump;
 pub mod handler;
+mod layers;
 
 pub use config::{
-    Config, ConfigError, FlowGeneratorConfig, IngressFlavour, KubernetesPollerType, PcapConfig,
-    RuntimeConfig, TripleMapConfig, XflowGeneratorConfig, YamlConfig,
+    AfPacketFanoutMode, ChecksumValidationConfig, Config, ConfigError, CpuAffinityConfig,
+    DnsLogConfig, ElasticsearchLogConfig, FlowGeneratorConfig, HardeningConfig, HttpRetryConfig,
+    HttpScrubConfig, IngressFlavour, IpAnonymizationConfig, IpAnonymizationMode,
+    KubernetesPollerType, L4LogFilterConfig, L7AlertMetric, L7AlertRule, L7LogSamplingConfig,
+    L7LogSamplingMode, L7LogTruncateConfig, L7MinPayloadLen, L7ObfuscationRule,
+    L7ParseBudgetConfig, L7ParseErrorPcapConfig, L7ParserConfig, L7ProtocolDetectionConfig,
+    L7RrtUpperBound, L7SessionPolicy, PcapConfig, PipelineMode, ProfilerConfig, RedisLogConfig,
+    RuntimeConfig, SeccompMode, SecurityEventConfig, ServerDomainConfig, SessionPolicy,
+    TimeSourceConfig, TimeSourceMode, TripleMapConfig, UdpPerfConfig, VlanMode,
+    XflowGeneratorConfig, YamlConfig, L7_LOG_SAMPLING_RATE_MAX,
 };
+pub use dump::dump_config;
 pub use handler::{DispatcherConfig, FlowAccess, FlowConfig, ModuleConfig};
@@ -37,6 +37,8 @@ use super::platform::{PlatformDebugger, PlatformMessage};
 
 use super::{
     error::{Error, Result},
+    flow::{FlowDebugger, FlowMessage},
+    l7_log::{L7LogDebugger, L7LogMessage, L7_LOG_RING_CAPACITY},
     queue::{QueueDebugger, QueueMessage},
     rpc::{RpcDebugger, RpcMessage},
     Beacon, Message, Module, BEACON_INTERVAL, BEACON_PORT, DEEPFLOW_AGENT_BEACON, MAX_BUF_SIZE,
@@ -47,6 +49,7 @@ use crate::platform::{ApiWatcher, GenericPoller};
 
 use crate::{
     config::handler::DebugAccess,
+    flow_generator::FlowSnapshot,
     rpc::{RunningConfig, Session, StaticConfig, Status},
 };
 
@@ -55,6 +58,8 @@ struct ModuleDebuggers {
     pub platform: PlatformDebugger,
     pub rpc: RpcDebugger,
     pub queue: Arc<QueueDebugger>,
+    pub l7_log: Arc<L7LogDebugger>,
+    pub flow: FlowDebugger,
 }
 
 pub struct Debugger {
@@ -74,6 +79,7 @@ pub struct ConstructDebugCtx {
     pub static_config: Arc<StaticConfig>,
     pub running_config: Arc<RwLock<RunningConfig>>,
     pub status: Arc<RwLock<Status>>,
+    pub flow_snapshot: Arc<FlowSnapshot>,
 }
 
 impl Debugger {
@@ -244,6 +250,24 @@ impl Debugger {
                     _ => unreachable!(),
                 }
             }
+            Module::L7Log => {
+                let req: Message<L7LogMessage> =
+                    decode_from_std_read(&mut payload, serialize_conf)?;
+                let debugger = &debuggers.l7_log;
+                let resp = match req.into_inner() {
+                    L7LogMessage::Query(filter) => debugger.query(filter),
+                    _ => unreachable!(),
+                };
+                iter_send_to(conn.0, conn.1, resp.iter(), serialize_conf)?;
+            }
+            Module::Flow => {
+                let req: Message<FlowMessage> = decode_from_std_read(&mut payload, serialize_conf)?;
+                let resp = match req.into_inner() {
+                    FlowMessage::Query(query) => debuggers.flow.query(query),
+                    _ => unreachable!(),
+                };
+                iter_send_to(conn.0, conn.1, resp.iter(), serialize_conf)?;
+            }
             _ => warn!("invalid module or invalid request, skip it"),
         }
 
@@ -264,6 +288,8 @@ impl Debugger {
                 context.status,
             ),
             queue: Arc::new(QueueDebugger::new()),
+            l7_log: Arc::new(L7LogDebugger::new(L7_LOG_RING_CAPACITY)),
+            flow: FlowDebugger::new(context.flow_snapshot),
         };
 
         Self {
@@ -278,6 +304,10 @@ impl Debugger {
         self.debuggers.queue.clone()
     }
 
+    pub fn clone_l7_log(&self) -> Arc<L7LogDebugger> {
+        self.debuggers.l7_log.clone()
+    }
+
     pub fn stop(&self) {
         if !self.running.swap(false, Ordering::Relaxed) {
             return;
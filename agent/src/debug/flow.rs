@@ -0,0 +1,87 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use bincode::{Decode, Encode};
+
+use super::MAX_BUF_SIZE;
+
+use crate::flow_generator::{export_flow_snapshot, FlowSnapshot, SnapshotFormat};
+
+// 调试端口只监听本地，默认的大小上限较小，避免长时间占用dispatcher
+pub const FLOW_QUERY_DEFAULT_MAX_BYTES: u32 = 1 << 20;
+const FLOW_QUERY_MAX_BYTES: u32 = 64 << 20;
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(3);
+// 预留编码头部的空间
+const MAX_CHUNK_LEN: usize = MAX_BUF_SIZE - 64;
+
+#[derive(PartialEq, Debug, Default, Clone, Encode, Decode)]
+pub struct FlowQuery {
+    // text, json 或 csv
+    pub format: Option<String>,
+    pub max_bytes: Option<u32>,
+}
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum FlowMessage {
+    // 请求
+    Query(FlowQuery),
+    // 响应，导出内容按UDP报文大小分段
+    Content(String),
+    Fin,
+    Err(String),
+}
+
+pub struct FlowDebugger {
+    snapshot: Arc<FlowSnapshot>,
+}
+
+impl FlowDebugger {
+    pub(super) fn new(snapshot: Arc<FlowSnapshot>) -> Self {
+        Self { snapshot }
+    }
+
+    pub(super) fn query(&self, query: FlowQuery) -> Vec<FlowMessage> {
+        let format = match query.format.as_deref().map(SnapshotFormat::try_from) {
+            Some(Ok(f)) => f,
+            Some(Err(e)) => return vec![FlowMessage::Err(e)],
+            None => SnapshotFormat::Text,
+        };
+        let max_bytes = query
+            .max_bytes
+            .unwrap_or(FLOW_QUERY_DEFAULT_MAX_BYTES)
+            .min(FLOW_QUERY_MAX_BYTES) as usize;
+        let results = match self.snapshot.take(SNAPSHOT_TIMEOUT) {
+            Ok(r) => r,
+            Err(e) => return vec![FlowMessage::Err(e)],
+        };
+        let output = export_flow_snapshot(&results, format, max_bytes);
+
+        let mut res = vec![];
+        let mut rest = output.as_str();
+        while !rest.is_empty() {
+            let mut end = rest.len().min(MAX_CHUNK_LEN);
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            res.push(FlowMessage::Content(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+        res.push(FlowMessage::Fin);
+        res
+    }
+}
@@ -0,0 +1,377 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use bincode::{Decode, Encode};
+use parking_lot::Mutex;
+
+use super::MAX_BUF_SIZE;
+
+use crate::flow_generator::AppProtoLogsData;
+
+// 最多缓存最近的1万条应用日志
+pub const L7_LOG_RING_CAPACITY: usize = 10000;
+pub const L7_LOG_QUERY_DEFAULT_LIMIT: u32 = 100;
+// 查询后开启记录的时长，期间无查询则停止记录
+pub const L7_LOG_SESSION_DURATION: Duration = Duration::from_secs(300);
+// 开启会话的查询等待一段时间后返回首批日志，不必再查询一次
+const L7_LOG_FIRST_BATCH_WAIT: Duration = Duration::from_secs(2);
+// 预留编码头部的空间，避免单条日志超过UDP报文上限
+const MAX_LOG_LEN: usize = MAX_BUF_SIZE - 64;
+// 每个线程固定写一个分片，发送线程之间不竞争同一把锁
+const RING_SHARDS: usize = 8;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: Cell<usize> = Cell::new(NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % RING_SHARDS);
+}
+
+#[derive(PartialEq, Debug, Default, Clone, Encode, Decode)]
+pub struct L7LogFilter {
+    // ip 和 port 同时匹配源端或目的端
+    pub ip: Option<String>,
+    pub port: Option<u16>,
+    // 与L7Protocol/L7ResponseStatus的名字比较，忽略大小写
+    pub protocol: Option<String>,
+    pub status: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(PartialEq, Debug, Encode, Decode)]
+pub enum L7LogMessage {
+    // 请求
+    Query(L7LogFilter),
+    // 响应
+    Log(String),
+    Fin,
+    Err(String),
+    // 调试会话未开启，本次查询开启记录，之后返回等待期间记录的日志
+    Started(u32),
+}
+
+struct CompiledFilter {
+    ip: Option<IpAddr>,
+    port: Option<u16>,
+    protocol: Option<String>,
+    status: Option<String>,
+}
+
+impl CompiledFilter {
+    fn matches(&self, log: &AppProtoLogsData) -> bool {
+        let base = &log.base_info;
+        if let Some(ip) = self.ip {
+            if base.ip_src != ip && base.ip_dst != ip {
+                return false;
+            }
+        }
+        if let Some(port) = self.port {
+            if base.port_src != port && base.port_dst != port {
+                return false;
+            }
+        }
+        if let Some(p) = self.protocol.as_ref() {
+            if !format!("{:?}", base.head.proto).eq_ignore_ascii_case(p) {
+                return false;
+            }
+        }
+        if let Some(s) = self.status.as_ref() {
+            if !format!("{:?}", base.head.status).eq_ignore_ascii_case(s) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// 只在调试会话期间记录，会话由查询开启，无人查询时发送路径上只有一次原子读
+pub struct L7LogDebugger {
+    // 每个分片的容量
+    shard_capacity: usize,
+    rings: Vec<Mutex<VecDeque<AppProtoLogsData>>>,
+    // 会话结束时间，单位秒，为0时未开启
+    session_end: AtomicU64,
+    first_batch_wait: Duration,
+}
+
+impl L7LogDebugger {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let shard_capacity = (capacity + RING_SHARDS - 1) / RING_SHARDS;
+        Self {
+            shard_capacity,
+            rings: (0..RING_SHARDS)
+                .map(|_| Mutex::new(VecDeque::new()))
+                .collect(),
+            session_end: AtomicU64::new(0),
+            first_batch_wait: L7_LOG_FIRST_BATCH_WAIT,
+        }
+    }
+
+    fn is_recording(&self) -> bool {
+        let end = self.session_end.load(Ordering::Relaxed);
+        end > 0 && now_secs() < end
+    }
+
+    // 由应用日志发送方调用，超过容量时丢弃最旧的日志
+    pub fn record(&self, log: &AppProtoLogsData) {
+        if self.shard_capacity == 0 || !self.is_recording() {
+            return;
+        }
+        let shard = SHARD.with(|s| s.get());
+        let mut ring = self.rings[shard].lock();
+        if ring.len() >= self.shard_capacity {
+            ring.pop_front();
+        }
+        ring.push_back(log.clone());
+    }
+
+    // 开启或延长调试会话，返回开启前是否已在记录
+    fn renew_session(&self) -> bool {
+        let recording = self.is_recording();
+        if !recording {
+            // 丢弃上一次会话遗留的日志
+            for ring in self.rings.iter() {
+                ring.lock().clear();
+            }
+        }
+        self.session_end.store(
+            now_secs() + L7_LOG_SESSION_DURATION.as_secs(),
+            Ordering::Relaxed,
+        );
+        recording
+    }
+
+    pub(super) fn query(&self, filter: L7LogFilter) -> Vec<L7LogMessage> {
+        let ip = match filter.ip.as_ref().map(|s| s.parse::<IpAddr>()) {
+            Some(Ok(ip)) => Some(ip),
+            Some(Err(e)) => return vec![L7LogMessage::Err(format!("invalid ip: {}", e))],
+            None => None,
+        };
+        let compiled = CompiledFilter {
+            ip,
+            port: filter.port,
+            protocol: filter.protocol,
+            status: filter.status,
+        };
+        let limit = filter.limit.unwrap_or(L7_LOG_QUERY_DEFAULT_LIMIT) as usize;
+        if self.shard_capacity == 0 {
+            return vec![L7LogMessage::Fin];
+        }
+        let mut res = vec![];
+        if !self.renew_session() {
+            res.push(L7LogMessage::Started(
+                L7_LOG_SESSION_DURATION.as_secs() as u32
+            ));
+            thread::sleep(self.first_batch_wait);
+        }
+
+        // 每个分片取最新的limit条，合并后按时间从新到旧输出
+        let mut matched = vec![];
+        for ring in self.rings.iter() {
+            matched.extend(
+                ring.lock()
+                    .iter()
+                    .rev()
+                    .filter(|l| compiled.matches(l))
+                    .take(limit)
+                    .cloned(),
+            );
+        }
+        matched.sort_by(|a, b| b.base_info.start_time.cmp(&a.base_info.start_time));
+        res.extend(matched.iter().take(limit).map(|l| {
+            let mut s = l.to_string();
+            if s.len() > MAX_LOG_LEN {
+                let mut end = MAX_LOG_LEN;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                s.truncate(end);
+            }
+            L7LogMessage::Log(s)
+        }));
+        res.push(L7LogMessage::Fin);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    use crate::common::{enums::IpProtocol, flow::L7Protocol, meta_packet::MetaPacket};
+    use crate::flow_generator::{
+        AppProtoHead, AppProtoLogsBaseInfo, AppProtoLogsInfo, DnsInfo, L7ResponseStatus,
+        LogMessageType,
+    };
+
+    fn log(
+        timestamp: u64,
+        dst: &str,
+        port: u16,
+        proto: L7Protocol,
+        status: L7ResponseStatus,
+    ) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = dst.parse().unwrap();
+        packet.lookup_key.dst_port = port;
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        let head = AppProtoHead {
+            proto,
+            msg_type: LogMessageType::Session,
+            status,
+            ..Default::default()
+        };
+        let base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        AppProtoLogsData::new(base_info, AppProtoLogsInfo::Dns(DnsInfo::default()))
+    }
+
+    #[test]
+    fn filter_matches() {
+        let l = log(1, "10.0.0.2", 53, L7Protocol::Dns, L7ResponseStatus::Ok);
+        let filter =
+            |ip: Option<&str>, port, protocol: Option<&str>, status: Option<&str>| CompiledFilter {
+                ip: ip.map(|s| s.parse().unwrap()),
+                port,
+                protocol: protocol.map(str::to_owned),
+                status: status.map(str::to_owned),
+            };
+        assert!(filter(None, None, None, None).matches(&l));
+        assert!(filter(Some("10.0.0.1"), Some(53), Some("dns"), Some("OK")).matches(&l));
+        assert!(filter(Some("10.0.0.2"), None, None, None).matches(&l));
+        assert!(!filter(Some("10.0.0.3"), None, None, None).matches(&l));
+        assert!(!filter(None, Some(80), None, None).matches(&l));
+        assert!(!filter(None, None, Some("http1"), None).matches(&l));
+        assert!(!filter(None, None, None, Some("servererror")).matches(&l));
+    }
+
+    #[test]
+    fn query_session() {
+        let mut debugger = L7LogDebugger::new(64);
+        debugger.first_batch_wait = Duration::ZERO;
+        // 未开启会话时不记录
+        debugger.record(&log(
+            1,
+            "10.0.0.2",
+            53,
+            L7Protocol::Dns,
+            L7ResponseStatus::Ok,
+        ));
+        assert_eq!(
+            debugger.query(L7LogFilter::default()),
+            vec![
+                L7LogMessage::Started(L7_LOG_SESSION_DURATION.as_secs() as u32),
+                L7LogMessage::Fin
+            ]
+        );
+
+        for t in 1..=3 {
+            debugger.record(&log(
+                t,
+                "10.0.0.2",
+                53,
+                L7Protocol::Dns,
+                L7ResponseStatus::Ok,
+            ));
+        }
+        debugger.record(&log(
+            4,
+            "10.0.0.3",
+            53,
+            L7Protocol::Dns,
+            L7ResponseStatus::ServerError,
+        ));
+        let res = debugger.query(L7LogFilter {
+            ip: Some("10.0.0.2".into()),
+            limit: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[2], L7LogMessage::Fin);
+        // 从新到旧
+        match (&res[0], &res[1]) {
+            (L7LogMessage::Log(a), L7LogMessage::Log(b)) => {
+                assert!(a.contains("Timestamp: 3s "), "{}", a);
+                assert!(b.contains("Timestamp: 2s "), "{}", b);
+            }
+            _ => panic!("unexpected {:?}", res),
+        }
+
+        let res = debugger.query(L7LogFilter {
+            status: Some("servererror".into()),
+            ..Default::default()
+        });
+        assert_eq!(res.len(), 2);
+
+        assert!(matches!(
+            &debugger.query(L7LogFilter {
+                ip: Some("bad".into()),
+                ..Default::default()
+            })[0],
+            L7LogMessage::Err(_)
+        ));
+    }
+
+    #[test]
+    fn first_batch() {
+        let mut debugger = L7LogDebugger::new(64);
+        debugger.first_batch_wait = Duration::from_millis(500);
+        let debugger = Arc::new(debugger);
+
+        let recorder = debugger.clone();
+        let handle = thread::spawn(move || {
+            // 等待查询开启会话
+            while !recorder.is_recording() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            recorder.record(&log(
+                1,
+                "10.0.0.2",
+                53,
+                L7Protocol::Dns,
+                L7ResponseStatus::Ok,
+            ));
+        });
+        let res = debugger.query(L7LogFilter::default());
+        handle.join().unwrap();
+
+        assert_eq!(res.len(), 3);
+        assert_eq!(
+            res[0],
+            L7LogMessage::Started(L7_LOG_SESSION_DURATION.as_secs() as u32)
+        );
+        assert!(matches!(&res[1], L7LogMessage::Log(l) if l.contains("Timestamp: 1s ")));
+        assert_eq!(res[2], L7LogMessage::Fin);
+    }
+}
@@ -16,6 +16,8 @@
 
 mod debugger;
 mod error;
+mod flow;
+mod l7_log;
 #[cfg(target_os = "linux")]
 mod platform;
 mod queue;
@@ -23,6 +25,8 @@ mod rpc;
 
 use bincode::{Decode, Encode};
 pub use debugger::{Client, ConstructDebugCtx, Debugger};
+pub use flow::{FlowMessage, FlowQuery, FLOW_QUERY_DEFAULT_MAX_BYTES};
+pub use l7_log::{L7LogDebugger, L7LogFilter, L7LogMessage, L7_LOG_RING_CAPACITY};
 #[cfg(target_os = "linux")]
 pub use platform::PlatformMessage;
 pub use queue::{QueueDebugger, QueueMessage};
@@ -49,6 +53,8 @@ pub enum Module {
     Platform,
     List,
     Queue,
+    L7Log,
+    Flow,
 }
 
 impl Default for Module {
@@ -26,6 +26,7 @@ use crate::config::RuntimeConfig;
 use crate::exception::ExceptionHandler;
 use crate::proto::trident::{self, SyncResponse};
 use crate::rpc::{RunningConfig, Session, StaticConfig, Status, Synchronizer};
+use crate::utils::drop_stats::DropStats;
 
 pub struct RpcDebugger {
     session: Arc<Session>,
@@ -84,6 +85,7 @@ impl RpcDebugger {
             &self.status,
             0,
             &exception_handler,
+            &DropStats::default(),
         );
         self.session.update_current_server().await;
 
@@ -21,7 +21,7 @@ use std::mem;
 use std::net::{IpAddr, Ipv4Addr};
 use std::process;
 use std::sync::{
-    atomic::{AtomicBool, AtomicI64, Ordering},
+    atomic::{AtomicBool, Ordering},
     Arc, Mutex,
 };
 use std::thread;
@@ -37,11 +37,17 @@ use super::{
     BpfOptions, Options, PacketCounter, Pipeline,
 };
 
+#[cfg(target_os = "windows")]
+use super::hairpin_dedup::HairpinDedup;
 #[cfg(target_os = "windows")]
 use windows_recv_engine::WinPacket;
 
 #[cfg(target_os = "linux")]
 use crate::platform::GenericPoller;
+#[cfg(target_os = "windows")]
+use crate::utils::bytes::read_u32_le;
+#[cfg(target_os = "linux")]
+use crate::{common::TimestampSource, utils::handoff};
 use crate::{
     common::{
         decapsulate::{TunnelInfo, TunnelType, TunnelTypeBitmap},
@@ -51,10 +57,10 @@ use crate::{
     },
     config::{handler::FlowAccess, DispatcherConfig},
     exception::ExceptionHandler,
-    flow_generator::MetaAppProto,
+    flow_generator::{FlowMap, FlowSnapshot, MetaAppProto},
     policy::PolicyGetter,
     proto::trident::{Exception, IfMacSource, TapMode},
-    rpc::get_timestamp,
+    rpc::TimeSource,
     utils::{
         bytes::read_u16_be,
         net::{self, get_route_src_ip, Link, MacAddr},
@@ -93,9 +99,12 @@ pub(super) struct BaseDispatcher {
     pub(super) tap_interface_whitelist: TapInterfaceWhitelist,
 
     pub(super) analyzer_dedup_disabled: bool,
+    // 采集Npcap回环设备时，去除同时出现在网卡和回环设备上的包
+    #[cfg(target_os = "windows")]
+    pub(super) hairpin_dedup: Option<HairpinDedup>,
 
     pub(super) flow_output_queue: DebugSender<Box<TaggedFlow>>,
-    pub(super) log_output_queue: DebugSender<Box<MetaAppProto>>,
+    pub(super) log_output_queue: Option<DebugSender<Box<MetaAppProto>>>,
 
     pub(super) counter: Arc<PacketCounter>,
     pub(super) terminated: Arc<AtomicBool>,
@@ -105,7 +114,8 @@ pub(super) struct BaseDispatcher {
 
     pub(super) policy_getter: PolicyGetter,
     pub(super) exception_handler: ExceptionHandler,
-    pub(super) ntp_diff: Arc<AtomicI64>,
+    pub(super) time_source: TimeSource,
+    pub(super) flow_snapshot: Arc<FlowSnapshot>,
 
     // Enterprise Edition Feature: packet-sequence
     pub(super) packet_sequence_output_queue:
@@ -132,6 +142,7 @@ impl BaseDispatcher {
             )
             .map_err(|e| Error::WinPcap(e.to_string()))?;
             info!("WinPacket init");
+            self.hairpin_dedup = Self::new_hairpin_dedup(&pcap_interfaces);
             self.need_update_bpf.store(true, Ordering::Relaxed);
             RecvEngine::WinPcap(Some(win_packet))
         } else {
@@ -142,7 +153,55 @@ impl BaseDispatcher {
     }
 }
 
+// 远程诊断时每个dispatcher最多输出的流数量
+const FLOW_SNAPSHOT_MAX: usize = 10000;
+
 impl BaseDispatcher {
+    // 各模式的dispatcher在收包循环开始前登记，退出后注销，
+    // 诊断命令只等待已登记的dispatcher提交流表快照
+    pub(super) fn register_flow_snapshot(&self) {
+        self.flow_snapshot.register(self.id as u32);
+    }
+
+    pub(super) fn unregister_flow_snapshot(&self) {
+        self.flow_snapshot.unregister(self.id as u32);
+    }
+
+    // 各模式的dispatcher在每次收包前调用，有新请求时提交流表快照
+    pub(super) fn submit_flow_snapshot(&self, generation: &mut u64, flow_map: &FlowMap) {
+        if let Some(g) = self.flow_snapshot.requested(generation) {
+            self.flow_snapshot
+                .submit(g, flow_map.snapshot(FLOW_SNAPSHOT_MAX));
+        }
+    }
+
+    // 各模式的dispatcher在收包循环开始前调用，恢复热升级前旧进程交接的流，
+    // 并登记为会响应热升级请求，未登记的dispatcher在exec时不等待也不交接socket
+    #[cfg(target_os = "linux")]
+    pub(super) fn resume_handoff_flows(&self, flow_map: &mut FlowMap) {
+        handoff::parkable(self.id);
+        flow_map.resume_handoff_flows(handoff::take_inherited_flows(self.id as u32));
+    }
+
+    // 各模式的dispatcher在每次收包前调用，返回true时交出流表并退出收包循环，
+    // 此后ring的读取位置不再推进
+    #[cfg(target_os = "linux")]
+    pub(super) fn park_for_handoff(&self, flow_map: &FlowMap) -> bool {
+        if !handoff::requested() {
+            return false;
+        }
+        handoff::park(self.id, flow_map.handoff_flows());
+        true
+    }
+
+    #[cfg(target_os = "windows")]
+    pub(super) fn new_hairpin_dedup(pcap_interfaces: &[Link]) -> Option<HairpinDedup> {
+        pcap_interfaces
+            .iter()
+            .any(|l| l.if_index == net::LOOPBACK_IF_INDEX)
+            .then(|| HairpinDedup::new(net::LOOPBACK_IF_INDEX as isize))
+    }
+
     #[cfg(all(target_os = "linux", not(target_arch = "s390x")))]
     fn is_engine_dpdk(&self) -> bool {
         match &self.engine {
@@ -163,8 +222,8 @@ impl BaseDispatcher {
         exception_handler: &ExceptionHandler,
         prev_timestamp: &mut Duration,
         counter: &PacketCounter,
-        ntp_diff: &AtomicI64,
-    ) -> Option<(Packet<'a>, Duration)> {
+        time_source: &TimeSource,
+    ) -> Option<(Packet<'a>, Duration, TimestampSource)> {
         let packet = engine.recv();
         if packet.is_err() {
             if let recv_engine::Error::Timeout = packet.unwrap_err() {
@@ -181,19 +240,13 @@ impl BaseDispatcher {
             counter.invalid_packets.fetch_add(1, Ordering::Relaxed);
             return None;
         }
-        let mut timestamp = packet.timestamp;
-        let time_diff = ntp_diff.load(Ordering::Relaxed);
-        if time_diff >= 0 {
-            timestamp += Duration::from_nanos(time_diff as u64);
-        } else {
-            timestamp -= Duration::from_nanos(-time_diff as u64);
-        }
+        let (mut timestamp, timestamp_source) = Self::correct_timestamp(&packet, time_source);
         if timestamp > *prev_timestamp {
             if timestamp - *prev_timestamp > Duration::from_secs(60) {
                 // Correct invalid timestamp under some environments. Root cause unclear.
                 // A large timestamp will lead to discarding of following packets, correct
                 // this by setting it to present time
-                let now = get_timestamp(time_diff);
+                let now = time_source.now();
                 if timestamp > now && timestamp - now > Duration::from_secs(60) {
                     timestamp = now;
                 }
@@ -211,7 +264,23 @@ impl BaseDispatcher {
             .rx_all_bytes
             .fetch_add(packet.data.len() as u64, Ordering::Relaxed);
 
-        Some((packet, timestamp))
+        Some((packet, timestamp, timestamp_source))
+    }
+
+    // 硬件时间戳来自网卡PTP时钟，只需从TAI换算为UTC，不按系统时钟的时差校正
+    #[cfg(target_os = "linux")]
+    fn correct_timestamp(packet: &Packet, time_source: &TimeSource) -> (Duration, TimestampSource) {
+        if packet.hw_timestamp {
+            (
+                time_source.correct_hw(packet.timestamp),
+                TimestampSource::Hardware,
+            )
+        } else {
+            (
+                time_source.correct(packet.timestamp),
+                TimestampSource::Software,
+            )
+        }
     }
 
     #[cfg(target_os = "windows")]
@@ -221,7 +290,7 @@ impl BaseDispatcher {
         exception_handler: &ExceptionHandler,
         prev_timestamp: &mut Duration,
         counter: &PacketCounter,
-        ntp_diff: &AtomicI64,
+        time_source: &TimeSource,
     ) -> Option<(Packet, Duration)> {
         let packet = engine.recv();
         if packet.is_err() {
@@ -233,25 +302,25 @@ impl BaseDispatcher {
             thread::sleep(Duration::from_millis(1));
             return None;
         }
-        let packet = packet.unwrap();
+        let mut packet = packet.unwrap();
+        if packet.if_index == net::LOOPBACK_IF_INDEX as isize
+            && !Self::loopback_to_ethernet(&mut packet)
+        {
+            counter.invalid_packets.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
         // Receiving incomplete eth header under some environments, unlikely to happen
         if packet.data.len() < ETH_HEADER_SIZE + VLAN_HEADER_SIZE {
             counter.invalid_packets.fetch_add(1, Ordering::Relaxed);
             return None;
         }
-        let mut timestamp = packet.timestamp;
-        let time_diff = ntp_diff.load(Ordering::Relaxed);
-        if time_diff >= 0 {
-            timestamp += Duration::from_nanos(time_diff as u64);
-        } else {
-            timestamp -= Duration::from_nanos(-time_diff as u64);
-        }
+        let mut timestamp = time_source.correct(packet.timestamp);
         if timestamp > *prev_timestamp {
             if timestamp - *prev_timestamp > Duration::from_secs(60) {
                 // Correct invalid timestamp under some environments. Root cause unclear.
                 // A large timestamp will lead to discarding of following packets, correct
                 // this by setting it to present time
-                let now = get_timestamp(time_diff);
+                let now = time_source.now();
                 if timestamp > now && timestamp - now > Duration::from_secs(60) {
                     timestamp = now;
                 }
@@ -272,6 +341,28 @@ impl BaseDispatcher {
         Some((packet, timestamp))
     }
 
+    // Npcap回环设备的包为DLT_NULL格式，以4字节地址族开头，替换为MAC全0的以太网头
+    #[cfg(target_os = "windows")]
+    fn loopback_to_ethernet(packet: &mut Packet) -> bool {
+        const NULL_HEADER_SIZE: usize = 4;
+        const AF_INET: u32 = 2;
+        const AF_INET6: u32 = 23;
+
+        if packet.data.len() < NULL_HEADER_SIZE {
+            return false;
+        }
+        let eth_type = match read_u32_le(&packet.data) {
+            AF_INET => EthernetType::Ipv4,
+            AF_INET6 => EthernetType::Ipv6,
+            _ => return false,
+        };
+        let mut header = [0u8; ETH_HEADER_SIZE];
+        header[FIELD_OFFSET_ETH_TYPE..].copy_from_slice(&u16::from(eth_type).to_be_bytes());
+        packet.data.splice(..NULL_HEADER_SIZE, header);
+        packet.capture_length += (ETH_HEADER_SIZE - NULL_HEADER_SIZE) as isize;
+        true
+    }
+
     #[cfg(target_os = "linux")]
     pub(super) fn decapsulate(
         packet: &mut [u8],
@@ -516,6 +607,8 @@ pub(super) struct TapTypeHandler {
     pub(super) default_tap_type: TapType,
     pub(super) mirror_traffic_pcp: u16,
     pub(super) tap_mode: TapMode,
+    // analyzer模式下按采集网卡配置的vlan到tap_type映射，优先于控制器下发的映射
+    pub(super) vlan_tap_types: HashMap<u16, TapType>,
 }
 
 impl TapTypeHandler {
@@ -529,10 +622,14 @@ impl TapTypeHandler {
             eth_type = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE + VLAN_HEADER_SIZE..]);
             // tap_type从qinq外层的vlan获取
             let pcp = (vlan_tag >> 13) & 0x7;
-            if pcp == self.mirror_traffic_pcp && self.tap_mode == TapMode::Analyzer {
+            if self.tap_mode == TapMode::Analyzer {
                 let vid = vlan_tag & VLAN_ID_MASK;
-                if let Some(t) = self.tap_typer.get_tap_type_by_vlan(vid) {
-                    tap_type = t;
+                if let Some(t) = self.vlan_tap_types.get(&vid) {
+                    tap_type = *t;
+                } else if pcp == self.mirror_traffic_pcp {
+                    if let Some(t) = self.tap_typer.get_tap_type_by_vlan(vid) {
+                        tap_type = t;
+                    }
                 }
             }
             l2_len += VLAN_HEADER_SIZE;
@@ -556,7 +653,7 @@ pub(super) struct TapInterfaceWhitelist {
     whitelist: HashSet<usize>,
     updated: bool,
     last_sync: Duration,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
 }
 
 impl TapInterfaceWhitelist {
@@ -582,7 +679,7 @@ impl TapInterfaceWhitelist {
             return false;
         }
         if now.is_zero() {
-            now = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+            now = self.time_source.now();
         }
         if now > self.last_sync && now - self.last_sync > Self::SYNC_INTERVAL {
             self.updated = false;
@@ -684,8 +781,14 @@ impl BaseDispatcherListener {
         self.on_bpf_change(config);
     }
 
-    pub(super) fn on_vm_change(&self, keys: &[u32], vm_macs: &[MacAddr]) {
+    pub(super) fn on_vm_change(
+        &self,
+        keys: &[u32],
+        vm_macs: &[MacAddr],
+        member_macs: &[Vec<MacAddr>],
+    ) {
         assert_eq!(keys.len(), vm_macs.len());
+        assert_eq!(keys.len(), member_macs.len());
         // assert keys in assending order for bsearch
         assert!(keys.windows(2).all(|w| w[0] <= w[1]));
         let mut pipelines = self.pipelines.lock().unwrap();
@@ -694,7 +797,14 @@ impl BaseDispatcherListener {
         pipelines.retain(|k, v| {
             match keys.binary_search(k) {
                 // 在POD和修改ifMacSource场景下，网卡对应的mac地址也会改变，这里需要比较mac
-                Ok(id) if vm_macs[id] == v.lock().unwrap().vm_mac => true,
+                Ok(id) => {
+                    let v = v.lock().unwrap();
+                    if vm_macs[id] == v.vm_mac && member_macs[id] == v.member_macs {
+                        return true;
+                    }
+                    deleted.push(v.vm_mac);
+                    false
+                }
                 _ => {
                     deleted.push(v.lock().unwrap().vm_mac);
                     false
@@ -728,6 +838,7 @@ impl BaseDispatcherListener {
                 *key,
                 Arc::new(Mutex::new(Pipeline {
                     vm_mac,
+                    member_macs: member_macs[i].clone(),
                     handlers,
                     timestamp: Duration::ZERO,
                 })),
@@ -756,3 +867,64 @@ impl BaseDispatcherListener {
         self.need_update_bpf.store(true, Ordering::Release);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyzer_vlan_tap_types() {
+        let handler = TapTypeHandler {
+            default_tap_type: TapType::Isp(2),
+            tap_mode: TapMode::Analyzer,
+            vlan_tap_types: [(100, TapType::Isp(5))].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut packet = [0u8; 64];
+        packet[FIELD_OFFSET_ETH_TYPE..FIELD_OFFSET_ETH_TYPE + 2].copy_from_slice(&[0x81, 0x00]);
+        packet[ETH_HEADER_SIZE..ETH_HEADER_SIZE + 2].copy_from_slice(&100u16.to_be_bytes());
+        packet[FIELD_OFFSET_ETH_TYPE + VLAN_HEADER_SIZE
+            ..FIELD_OFFSET_ETH_TYPE + VLAN_HEADER_SIZE + 2]
+            .copy_from_slice(&[0x08, 0x00]);
+        let (tap_type, eth_type, l2_len) = handler.get_l2_info(&packet).unwrap();
+        assert_eq!(tap_type, TapType::Isp(5));
+        assert_eq!(eth_type, EthernetType::Ipv4);
+        assert_eq!(l2_len, ETH_HEADER_SIZE + VLAN_HEADER_SIZE);
+
+        // 未配置的vlan使用网卡的默认tap_type
+        packet[ETH_HEADER_SIZE..ETH_HEADER_SIZE + 2].copy_from_slice(&200u16.to_be_bytes());
+        assert_eq!(handler.get_l2_info(&packet).unwrap().0, TapType::Isp(2));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn timestamp_source() {
+        use std::sync::atomic::{AtomicI64, AtomicU64};
+
+        let time_source = TimeSource::new(
+            Arc::new(AtomicI64::new(Duration::from_secs(1).as_nanos() as i64)),
+            Arc::new(AtomicU64::new(Duration::from_secs(37).as_nanos() as u64)),
+        );
+        let mut data = [0u8; 64];
+        let mut packet = Packet {
+            timestamp: Duration::from_secs(100),
+            if_index: 1,
+            capture_length: 64,
+            data: &mut data,
+            hw_timestamp: false,
+        };
+        assert_eq!(
+            BaseDispatcher::correct_timestamp(&packet, &time_source),
+            (Duration::from_secs(101), TimestampSource::Software)
+        );
+        packet.hw_timestamp = true;
+        assert_eq!(
+            BaseDispatcher::correct_timestamp(&packet, &time_source),
+            (Duration::from_secs(63), TimestampSource::Hardware)
+        );
+        assert_eq!(
+            MetaPacket::empty().timestamp_source,
+            TimestampSource::Software
+        );
+    }
+}
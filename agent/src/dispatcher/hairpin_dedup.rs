@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{hash_map::DefaultHasher, HashMap, VecDeque};
+use std::hash::Hasher;
+use std::time::Duration;
+
+use crate::{
+    common::{
+        enums::{EthernetType, IpProtocol},
+        ETH_HEADER_SIZE, FIELD_OFFSET_ETH_TYPE, IPV4_HEADER_SIZE, IPV6_HEADER_SIZE,
+        VLAN_HEADER_SIZE,
+    },
+    utils::bytes::read_u16_be,
+};
+
+// TCP取seq、ack、头部长度、标志位和窗口
+const TCP_FINGERPRINT_START: usize = 4;
+const TCP_FINGERPRINT_END: usize = 16;
+// 其他协议跳过UDP/ICMP头部，取负载的前若干字节
+const L4_HEADER_SKIP: usize = 8;
+const PAYLOAD_FINGERPRINT_SIZE: usize = 16;
+
+// 发往本机其他地址或经WinNAT/Hyper-V虚拟交换机回环的流量，会同时出现在物理或虚拟网卡
+// 和Npcap回环设备上，同一个包会被采集两次
+// WinNAT会改写两份拷贝之间的地址和端口(以及随之变化的校验和、ICMP标识符)，因此指纹只取
+// 转发和NAT都不改变的字段，较短时间内在不同接口上重复出现的包只保留第一次
+pub struct HairpinDedup {
+    loopback_if_index: isize,
+    window: Duration,
+    capacity: usize,
+    seen: HashMap<u64, (isize, Duration)>,
+    expiry: VecDeque<(Duration, u64)>,
+}
+
+impl HairpinDedup {
+    pub const WINDOW: Duration = Duration::from_millis(10);
+    const CAPACITY: usize = 65536;
+
+    pub fn new(loopback_if_index: isize) -> Self {
+        Self {
+            loopback_if_index,
+            window: Self::WINDOW,
+            capacity: Self::CAPACITY,
+            seen: HashMap::new(),
+            expiry: VecDeque::new(),
+        }
+    }
+
+    // 只处理回环设备与其他接口之间的重复，同一接口上的重传不算重复
+    pub fn is_duplicate(&mut self, packet: &[u8], if_index: isize, timestamp: Duration) -> bool {
+        let fingerprint = match Self::fingerprint(packet) {
+            Some(f) => f,
+            None => return false,
+        };
+        while let Some((t, f)) = self.expiry.front() {
+            if *t + self.window >= timestamp && self.expiry.len() < self.capacity {
+                break;
+            }
+            if let Some((_, last)) = self.seen.get(f) {
+                if last == t {
+                    self.seen.remove(f);
+                }
+            }
+            self.expiry.pop_front();
+        }
+        if let Some((prev_if_index, _)) = self.seen.get(&fingerprint) {
+            if *prev_if_index != if_index
+                && (*prev_if_index == self.loopback_if_index || if_index == self.loopback_if_index)
+            {
+                return true;
+            }
+        }
+        self.seen.insert(fingerprint, (if_index, timestamp));
+        self.expiry.push_back((timestamp, fingerprint));
+        false
+    }
+
+    // 不包括TTL/Hop Limit和校验和，回环设备上的包可能未填充校验和
+    fn fingerprint(packet: &[u8]) -> Option<u64> {
+        if packet.len() < ETH_HEADER_SIZE {
+            return None;
+        }
+        let mut offset = ETH_HEADER_SIZE;
+        let mut eth_type = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE..]);
+        if eth_type == EthernetType::Dot1Q {
+            if packet.len() < ETH_HEADER_SIZE + VLAN_HEADER_SIZE {
+                return None;
+            }
+            eth_type = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE + VLAN_HEADER_SIZE..]);
+            offset += VLAN_HEADER_SIZE;
+        }
+        let ip = &packet[offset..];
+        let mut hasher = DefaultHasher::new();
+        let (protocol, l4) = if eth_type == EthernetType::Ipv4 {
+            let header_len = ((*ip.first()? & 0xf) as usize) << 2;
+            if header_len < IPV4_HEADER_SIZE || ip.len() < header_len {
+                return None;
+            }
+            // 总长度、ID和分片偏移，协议
+            hasher.write(&ip[2..8]);
+            hasher.write_u8(ip[9]);
+            (ip[9], &ip[header_len..])
+        } else if eth_type == EthernetType::Ipv6 {
+            if ip.len() < IPV6_HEADER_SIZE {
+                return None;
+            }
+            // 流标签、负载长度、下一个头部
+            hasher.write(&ip[..7]);
+            (ip[6], &ip[IPV6_HEADER_SIZE..])
+        } else {
+            return None;
+        };
+        let (start, end) = if protocol == u8::from(IpProtocol::Tcp) {
+            (TCP_FINGERPRINT_START, TCP_FINGERPRINT_END)
+        } else {
+            (L4_HEADER_SKIP, L4_HEADER_SKIP + PAYLOAD_FINGERPRINT_SIZE)
+        };
+        if let Some(l4) = l4.get(start.min(l4.len())..end.min(l4.len())) {
+            hasher.write(l4);
+        }
+        Some(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOOPBACK: isize = 1;
+    const NIC: isize = 12;
+
+    fn tcp_packet(ttl: u8, seq: u32) -> Vec<u8> {
+        let mut packet = vec![0u8; ETH_HEADER_SIZE + IPV4_HEADER_SIZE + 20];
+        packet[FIELD_OFFSET_ETH_TYPE..ETH_HEADER_SIZE]
+            .copy_from_slice(&u16::from(EthernetType::Ipv4).to_be_bytes());
+        let ip = &mut packet[ETH_HEADER_SIZE..];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&40u16.to_be_bytes());
+        ip[4..6].copy_from_slice(&1234u16.to_be_bytes());
+        ip[8] = ttl;
+        ip[9] = u8::from(IpProtocol::Tcp);
+        ip[12..16].copy_from_slice(&[172, 20, 0, 2]);
+        ip[16..20].copy_from_slice(&[172, 20, 0, 1]);
+        let tcp = &mut ip[IPV4_HEADER_SIZE..];
+        tcp[..2].copy_from_slice(&50000u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&80u16.to_be_bytes());
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn hairpin() {
+        let mut dedup = HairpinDedup::new(LOOPBACK);
+        let t = Duration::from_secs(1);
+        // 经过一跳后TTL不同，仍判断为同一个包
+        assert!(!dedup.is_duplicate(&tcp_packet(128, 1), NIC, t));
+        assert!(dedup.is_duplicate(&tcp_packet(127, 1), LOOPBACK, t + Duration::from_micros(50)));
+        // 同一接口上的重传不去重
+        assert!(!dedup.is_duplicate(&tcp_packet(128, 1), NIC, t + Duration::from_millis(1)));
+        // 超过时间窗口不去重
+        assert!(!dedup.is_duplicate(&tcp_packet(128, 1), LOOPBACK, t + HairpinDedup::WINDOW * 3));
+        assert!(!dedup.is_duplicate(&tcp_packet(128, 2), LOOPBACK, t + HairpinDedup::WINDOW * 3));
+    }
+
+    // 模拟WinNAT改写源地址和源端口
+    fn nat(mut packet: Vec<u8>) -> Vec<u8> {
+        let ip = &mut packet[ETH_HEADER_SIZE..];
+        ip[12..16].copy_from_slice(&[192, 168, 1, 10]);
+        ip[IPV4_HEADER_SIZE..IPV4_HEADER_SIZE + 2].copy_from_slice(&61000u16.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn hairpin_nat() {
+        let mut dedup = HairpinDedup::new(LOOPBACK);
+        let t = Duration::from_secs(1);
+        assert!(!dedup.is_duplicate(&tcp_packet(128, 1), NIC, t));
+        assert!(dedup.is_duplicate(
+            &nat(tcp_packet(127, 1)),
+            LOOPBACK,
+            t + Duration::from_micros(50)
+        ));
+        // 同一连接的不同包不去重
+        assert!(!dedup.is_duplicate(
+            &nat(tcp_packet(127, 2)),
+            LOOPBACK,
+            t + Duration::from_micros(60)
+        ));
+    }
+
+    #[test]
+    fn capacity() {
+        let mut dedup = HairpinDedup::new(LOOPBACK);
+        dedup.capacity = 4;
+        let t = Duration::from_secs(1);
+        for seq in 0..10 {
+            dedup.is_duplicate(&tcp_packet(128, seq), NIC, t);
+        }
+        assert!(dedup.expiry.len() <= 4);
+        assert!(dedup.seen.len() <= 4);
+        assert!(dedup.is_duplicate(&tcp_packet(128, 9), LOOPBACK, t));
+    }
+}
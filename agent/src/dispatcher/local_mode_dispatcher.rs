@@ -29,17 +29,19 @@ use super::error::Result;
 
 #[cfg(target_os = "linux")]
 use crate::platform::{GenericPoller, Poller};
+#[cfg(target_os = "linux")]
+use crate::utils::net::bond_members;
 use crate::{
     common::{
         decapsulate::TunnelType,
         enums::{EthernetType, TapType},
-        MetaPacket, TapPort, FIELD_OFFSET_ETH_TYPE, MAC_ADDR_LEN, VLAN_HEADER_SIZE,
+        CapturePoint, CaptureType, MetaPacket, TapPort, FIELD_OFFSET_ETH_TYPE, MAC_ADDR_LEN,
+        VLAN_HEADER_SIZE,
     },
-    config::DispatcherConfig,
+    config::{DispatcherConfig, VlanMode},
     flow_generator::FlowMap,
     platform::LibvirtXmlExtractor,
     proto::{common::TridentType, trident::IfMacSource},
-    rpc::get_timestamp,
     utils::stats::{Countable, RefCountable, StatsOption},
     utils::{
         bytes::read_u16_be,
@@ -56,15 +58,14 @@ impl LocalModeDispatcher {
     pub(super) fn run(&mut self) {
         let base = &mut self.base;
         info!("Start dispatcher {}", base.id);
-        let time_diff = base.ntp_diff.load(Ordering::Relaxed);
-        let mut prev_timestamp = get_timestamp(time_diff);
+        let mut prev_timestamp = base.time_source.now();
 
         let (mut flow_map, flow_counter) = FlowMap::new(
             base.id as u32,
             base.flow_output_queue.clone(),
             base.policy_getter,
             base.log_output_queue.clone(),
-            base.ntp_diff.clone(),
+            base.time_source.clone(),
             base.flow_map_config.clone(),
             base.packet_sequence_output_queue.clone(), // Enterprise Edition Feature: packet-sequence
         );
@@ -74,8 +75,39 @@ impl LocalModeDispatcher {
             Countable::Ref(Arc::downgrade(&flow_counter) as Weak<dyn RefCountable>),
             vec![StatsOption::Tag("id", format!("{}", base.id))],
         );
+        for (protocol, counter) in flow_counter.l7_parse_time.iter() {
+            base.stats.register_countable(
+                "flow-perf-l7-parse",
+                Countable::Ref(Arc::downgrade(counter) as Weak<dyn RefCountable>),
+                vec![
+                    StatsOption::Tag("id", format!("{}", base.id)),
+                    StatsOption::Tag("protocol", format!("{:?}", protocol)),
+                ],
+            );
+        }
+        for (error, counter) in flow_counter.l7_parse_error.0.iter() {
+            base.stats.register_countable(
+                "flow-perf-l7-parse-error",
+                Countable::Ref(Arc::downgrade(counter) as Weak<dyn RefCountable>),
+                vec![
+                    StatsOption::Tag("id", format!("{}", base.id)),
+                    StatsOption::Tag("error", error.to_string()),
+                ],
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        base.resume_handoff_flows(&mut flow_map);
 
+        base.register_flow_snapshot();
+        let mut snapshot_generation = 0;
         while !base.terminated.load(Ordering::Relaxed) {
+            // 热升级时停止收包并交出流表，之后不再推进ring的读取位置
+            #[cfg(target_os = "linux")]
+            if base.park_for_handoff(&flow_map) {
+                break;
+            }
+            base.submit_flow_snapshot(&mut snapshot_generation, &flow_map);
             if base.reset_whitelist.swap(false, Ordering::Relaxed) {
                 base.tap_interface_whitelist.reset();
             }
@@ -85,7 +117,7 @@ impl LocalModeDispatcher {
                 &base.exception_handler,
                 &mut prev_timestamp,
                 &base.counter,
-                &base.ntp_diff,
+                &base.time_source,
             );
             if recved.is_none() {
                 flow_map.inject_flush_ticker(Duration::ZERO);
@@ -97,8 +129,17 @@ impl LocalModeDispatcher {
             }
             #[cfg(target_os = "windows")]
             let (mut packet, mut timestamp) = recved.unwrap();
+            #[cfg(target_os = "windows")]
+            if let Some(dedup) = base.hairpin_dedup.as_mut() {
+                if dedup.is_duplicate(&packet.data, packet.if_index, timestamp) {
+                    base.counter
+                        .hairpin_duplicates
+                        .fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            }
             #[cfg(target_os = "linux")]
-            let (packet, mut timestamp) = recved.unwrap();
+            let (packet, mut timestamp, timestamp_source) = recved.unwrap();
 
             let pipeline = {
                 let pipelines = base.pipelines.lock().unwrap();
@@ -133,34 +174,32 @@ impl LocalModeDispatcher {
 
             // compare 4 low bytes
             #[cfg(target_os = "linux")]
-            let (src_local, dst_local) = if pipeline.vm_mac.octets()[2..]
-                == packet.data[MAC_ADDR_LEN + 2..MAC_ADDR_LEN + MAC_ADDR_LEN]
-            {
-                // src mac
-                (true, false)
-            } else if pipeline.vm_mac.octets()[2..] == packet.data[2..MAC_ADDR_LEN]
-                || MacAddr::is_multicast(packet.data)
-            {
-                // dst mac
-                (false, true)
-            } else {
-                (false, false)
-            };
+            let (src_local, dst_local) =
+                if pipeline.is_local_mac(&packet.data[MAC_ADDR_LEN..MAC_ADDR_LEN + MAC_ADDR_LEN]) {
+                    // src mac
+                    (true, false)
+                } else if pipeline.is_local_mac(&packet.data[..MAC_ADDR_LEN])
+                    || MacAddr::is_multicast(packet.data)
+                {
+                    // dst mac
+                    (false, true)
+                } else {
+                    (false, false)
+                };
 
             #[cfg(target_os = "windows")]
-            let (src_local, dst_local) = if pipeline.vm_mac.octets()[2..]
-                == packet.data[MAC_ADDR_LEN + 2..MAC_ADDR_LEN + MAC_ADDR_LEN]
-            {
-                // src mac
-                (true, false)
-            } else if pipeline.vm_mac.octets()[2..] == packet.data[2..MAC_ADDR_LEN]
-                || MacAddr::is_multicast(&packet.data)
-            {
-                // dst mac
-                (false, true)
-            } else {
-                (false, false)
-            };
+            let (src_local, dst_local) =
+                if pipeline.is_local_mac(&packet.data[MAC_ADDR_LEN..MAC_ADDR_LEN + MAC_ADDR_LEN]) {
+                    // src mac
+                    (true, false)
+                } else if pipeline.is_local_mac(&packet.data[..MAC_ADDR_LEN])
+                    || MacAddr::is_multicast(&packet.data)
+                {
+                    // dst mac
+                    (false, true)
+                } else {
+                    (false, false)
+                };
 
             // LOCAL模式L2END使用underlay网络的MAC地址，实际流量解析使用overlay
 
@@ -209,6 +248,32 @@ impl LocalModeDispatcher {
                 warn!("meta_packet update failed: {:?}", e);
                 continue;
             }
+            #[cfg(target_os = "linux")]
+            {
+                meta_packet.timestamp_source = timestamp_source;
+            }
+            match base.options.vlan_mode {
+                VlanMode::Inner => (),
+                VlanMode::Outer if meta_packet.outer_vlan > 0 => {
+                    meta_packet.vlan = meta_packet.outer_vlan
+                }
+                VlanMode::Outer => (),
+                VlanMode::Strip => meta_packet.vlan = 0,
+            }
+            if !meta_packet.ipv6_anomaly.is_empty() {
+                base.counter.count_ipv6_anomaly(meta_packet.ipv6_anomaly);
+                debug!("ipv6 anomaly {}: {}", meta_packet.ipv6_anomaly, meta_packet);
+            }
+            let checksum = &base.options.checksum_validation;
+            if checksum.enabled && !(checksum.tx_checksum_offload && src_local) {
+                if let Err(e) = meta_packet.verify_checksum() {
+                    base.counter.checksum_errors.fetch_add(1, Ordering::Relaxed);
+                    debug!("{:?} checksum error: {}", e, meta_packet);
+                    if checksum.drop_corrupted {
+                        continue;
+                    }
+                }
+            }
 
             base.counter.rx.fetch_add(1, Ordering::Relaxed);
             base.counter
@@ -241,6 +306,8 @@ impl LocalModeDispatcher {
                 base.tunnel_info.tunnel_type,
                 u64::from(pipeline.vm_mac) as u32,
             );
+            meta_packet.capture_point =
+                CapturePoint::new(CaptureType::LocalNic, packet.if_index as u32, 0);
             BaseDispatcher::prepare_flow(&mut meta_packet, TapType::Tor, false, base.id as u8);
             for h in pipeline.handlers.iter_mut() {
                 h.handle(overlay_packet, &meta_packet);
@@ -262,6 +329,7 @@ impl LocalModeDispatcher {
             base.check_and_update_bpf();
         }
 
+        base.unregister_flow_snapshot();
         base.terminate_queue();
         info!("Stopped dispatcher {}", base.id);
     }
@@ -338,7 +406,12 @@ impl LocalModeDispatcherListener {
             trident_type,
             &self.base.options.tap_mac_script,
         );
-        self.base.on_vm_change(&keys, &macs);
+        #[cfg(target_os = "linux")]
+        let (macs, member_macs) =
+            self.map_bond_members(&interfaces, macs, if_mac_source, trident_type);
+        #[cfg(target_os = "windows")]
+        let member_macs = vec![vec![]; interfaces.len()];
+        self.base.on_vm_change(&keys, &macs, &member_macs);
         self.base.on_tap_interface_change(interfaces, if_mac_source);
     }
 
@@ -393,6 +466,56 @@ impl LocalModeDispatcherListener {
         macs
     }
 
+    // bond成员接口使用bond的MAC，使各成员上采集的流量归属同一个TapPort，
+    // 同时记录各成员的永久MAC，用于判断报文方向
+    #[cfg(target_os = "linux")]
+    fn map_bond_members(
+        &self,
+        interfaces: &Vec<Link>,
+        mut macs: Vec<MacAddr>,
+        if_mac_source: IfMacSource,
+        trident_type: TridentType,
+    ) -> (Vec<MacAddr>, Vec<Vec<MacAddr>>) {
+        let mut member_macs = vec![vec![]; interfaces.len()];
+        let bonds = match link_list() {
+            Ok(links) => links
+                .into_iter()
+                .filter(|link| link.if_type.as_deref() == Some("bond"))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                warn!("failed getting link list: {:?}", e);
+                return (macs, member_macs);
+            }
+        };
+        for bond in bonds {
+            let members = bond_members(&bond.name);
+            if members.is_empty() {
+                continue;
+            }
+            let bond_mac = self.get_mapped_macs(
+                &vec![bond.clone()],
+                if_mac_source,
+                trident_type,
+                &self.base.options.tap_mac_script,
+            )[0];
+            let perm_macs = members
+                .iter()
+                .filter_map(|m| m.perm_mac)
+                .collect::<Vec<_>>();
+            for (i, iface) in interfaces.iter().enumerate() {
+                if members.iter().any(|m| m.name == iface.name) {
+                    debug!(
+                        "interface {} is a member of bond {}, use mac {}",
+                        iface.name, bond.name, bond_mac
+                    );
+                    macs[i] = bond_mac;
+                    member_macs[i] = perm_macs.clone();
+                }
+            }
+        }
+        (macs, member_macs)
+    }
+
     #[cfg(target_os = "windows")]
     fn get_if_index_to_inner_mac_map() -> HashMap<u32, MacAddr> {
         let mut result = HashMap::new();
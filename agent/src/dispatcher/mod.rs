@@ -18,15 +18,17 @@ mod error;
 pub(crate) mod recv_engine;
 
 mod base_dispatcher;
+#[cfg(any(target_os = "windows", test))]
+mod hairpin_dedup;
 
 mod analyzer_mode_dispatcher;
 mod local_mode_dispatcher;
 mod mirror_mode_dispatcher;
 
-#[cfg(target_os = "windows")]
+use std::collections::HashMap;
 use std::process;
 use std::sync::{
-    atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Mutex, Weak,
 };
 use std::thread::{self, JoinHandle};
@@ -46,15 +48,21 @@ use mirror_mode_dispatcher::MirrorModeDispatcher;
 
 #[cfg(target_os = "linux")]
 use crate::platform::GenericPoller;
+#[cfg(target_os = "linux")]
+use crate::utils::{drop_stats::DropStats, numa};
 use crate::{
-    common::{enums::TapType, TaggedFlow, TapTyper},
-    config::{handler::FlowAccess, DispatcherConfig},
+    common::{
+        enums::{Ipv6Anomaly, TapType},
+        TaggedFlow, TapTyper, MAC_ADDR_LEN,
+    },
+    config::{handler::FlowAccess, ChecksumValidationConfig, DispatcherConfig, VlanMode},
     exception::ExceptionHandler,
-    flow_generator::MetaAppProto,
+    flow_generator::{FlowSnapshot, MetaAppProto},
     handler::{PacketHandler, PacketHandlerBuilder},
     platform::LibvirtXmlExtractor,
     policy::PolicyGetter,
     proto::{common::TridentType, trident::IfMacSource, trident::TapMode},
+    rpc::TimeSource,
     utils::{
         net::{Link, MacAddr},
         queue::DebugSender,
@@ -65,7 +73,7 @@ use crate::{
 use recv_engine::RecvEngine;
 #[cfg(target_os = "linux")]
 use recv_engine::{
-    af_packet::{self, bpf::*, BpfSyntax, OptTpacketVersion, Tpacket},
+    af_packet::{self, bpf::*, BpfSyntax, FanoutLoad, OptFanoutMode, OptTpacketVersion, Tpacket},
     DEFAULT_BLOCK_SIZE, FRAME_SIZE_MAX, FRAME_SIZE_MIN, POLL_TIMEOUT,
 };
 #[cfg(target_os = "windows")]
@@ -119,6 +127,9 @@ pub struct Dispatcher {
     terminated: Arc<AtomicBool>,
     running: AtomicBool,
     handle: Mutex<Option<JoinHandle<DispatcherFlavor>>>,
+    // 绑定的CPU及是否优先使用本地NUMA节点内存
+    #[cfg(target_os = "linux")]
+    cpu_affinity: Option<(usize, bool)>,
 }
 
 impl Dispatcher {
@@ -137,7 +148,16 @@ impl Dispatcher {
         }
         self.terminated.store(false, Ordering::Relaxed);
         let mut flavor = self.flavor.lock().unwrap().take().unwrap();
+        #[cfg(target_os = "linux")]
+        let cpu_affinity = self.cpu_affinity;
         self.handle.lock().unwrap().replace(thread::spawn(move || {
+            // FlowMap等在dispatcher线程内分配，绑定后随线程使用本地内存
+            #[cfg(target_os = "linux")]
+            if let Some((cpu, local_memory)) = cpu_affinity {
+                if let Err(e) = numa::pin_current_thread(cpu, local_memory) {
+                    warn!("pin dispatcher thread to cpu {} failed: {}", cpu, e);
+                }
+            }
             flavor.run();
             flavor
         }));
@@ -316,6 +336,10 @@ pub struct Options {
     pub af_packet_blocks: usize,
     #[cfg(target_os = "linux")]
     pub af_packet_version: OptTpacketVersion,
+    #[cfg(target_os = "linux")]
+    pub af_packet_fanout_mode: OptFanoutMode,
+    #[cfg(target_os = "linux")]
+    pub af_packet_hardware_timestamp: bool,
     pub snap_len: usize,
     pub tap_mode: TapMode,
     pub dpdk_conf: DpdkRingPortConf,
@@ -324,14 +348,29 @@ pub struct Options {
     pub vxlan_port: u16,
     pub controller_port: u16,
     pub controller_tls_port: u16,
+    pub checksum_validation: ChecksumValidationConfig,
+    pub vlan_mode: VlanMode,
 }
 
 struct Pipeline {
     vm_mac: MacAddr,
+    // 接口为bond成员时，bond所有成员的永久MAC
+    member_macs: Vec<MacAddr>,
     handlers: Vec<PacketHandler>,
     timestamp: Duration,
 }
 
+impl Pipeline {
+    // 只比较MAC的低4字节
+    fn is_local_mac(&self, mac: &[u8]) -> bool {
+        self.vm_mac.octets()[2..] == mac[2..MAC_ADDR_LEN]
+            || self
+                .member_macs
+                .iter()
+                .any(|m| m.octets()[2..] == mac[2..MAC_ADDR_LEN])
+    }
+}
+
 struct PacketCounter {
     terminated: Arc<AtomicBool>,
 
@@ -342,9 +381,18 @@ struct PacketCounter {
     err: AtomicU64,
 
     invalid_packets: AtomicU64,
+    checksum_errors: AtomicU64,
     get_token_failed: AtomicU64,
 
+    ipv6_fragment_with_routing: AtomicU64,
+    ipv6_oversized_options: AtomicU64,
+    ipv6_esp_after_fragment: AtomicU64,
+    ipv6_misplaced_hop_by_hop: AtomicU64,
+    ipv6_invalid_jumbogram: AtomicU64,
+
     retired: AtomicU64,
+    #[cfg(target_os = "windows")]
+    hairpin_duplicates: AtomicU64,
     kernel_counter: Arc<dyn stats::RefCountable>,
 }
 
@@ -360,12 +408,44 @@ impl PacketCounter {
             err: AtomicU64::new(0),
 
             invalid_packets: AtomicU64::new(0),
+            checksum_errors: AtomicU64::new(0),
             get_token_failed: AtomicU64::new(0),
 
+            ipv6_fragment_with_routing: AtomicU64::new(0),
+            ipv6_oversized_options: AtomicU64::new(0),
+            ipv6_esp_after_fragment: AtomicU64::new(0),
+            ipv6_misplaced_hop_by_hop: AtomicU64::new(0),
+            ipv6_invalid_jumbogram: AtomicU64::new(0),
+
             retired: AtomicU64::new(0),
+            #[cfg(target_os = "windows")]
+            hairpin_duplicates: AtomicU64::new(0),
             kernel_counter,
         }
     }
+
+    fn count_ipv6_anomaly(&self, anomaly: Ipv6Anomaly) {
+        for (flag, counter) in [
+            (
+                Ipv6Anomaly::FRAGMENT_WITH_ROUTING,
+                &self.ipv6_fragment_with_routing,
+            ),
+            (Ipv6Anomaly::OVERSIZED_OPTIONS, &self.ipv6_oversized_options),
+            (
+                Ipv6Anomaly::ESP_AFTER_FRAGMENT,
+                &self.ipv6_esp_after_fragment,
+            ),
+            (
+                Ipv6Anomaly::MISPLACED_HOP_BY_HOP,
+                &self.ipv6_misplaced_hop_by_hop,
+            ),
+            (Ipv6Anomaly::INVALID_JUMBOGRAM, &self.ipv6_invalid_jumbogram),
+        ] {
+            if anomaly.contains(flag) {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
 }
 
 impl stats::RefCountable for PacketCounter {
@@ -406,17 +486,63 @@ impl stats::RefCountable for PacketCounter {
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(self.invalid_packets.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "checksum_errors",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.checksum_errors.swap(0, Ordering::Relaxed)),
+            ),
             (
                 "get_token_failed",
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(get_token_failed),
             ),
+            (
+                "ipv6_fragment_with_routing",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(
+                    self.ipv6_fragment_with_routing.swap(0, Ordering::Relaxed),
+                ),
+            ),
+            (
+                "ipv6_oversized_options",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(
+                    self.ipv6_oversized_options.swap(0, Ordering::Relaxed),
+                ),
+            ),
+            (
+                "ipv6_esp_after_fragment",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(
+                    self.ipv6_esp_after_fragment.swap(0, Ordering::Relaxed),
+                ),
+            ),
+            (
+                "ipv6_misplaced_hop_by_hop",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(
+                    self.ipv6_misplaced_hop_by_hop.swap(0, Ordering::Relaxed),
+                ),
+            ),
+            (
+                "ipv6_invalid_jumbogram",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(
+                    self.ipv6_invalid_jumbogram.swap(0, Ordering::Relaxed),
+                ),
+            ),
             (
                 "retired",
                 stats::CounterType::Counted,
                 stats::CounterValue::Unsigned(self.retired.swap(0, Ordering::Relaxed)),
             ),
         ]);
+        #[cfg(target_os = "windows")]
+        counters.push((
+            "hairpin_duplicates",
+            stats::CounterType::Counted,
+            stats::CounterValue::Unsigned(self.hairpin_duplicates.swap(0, Ordering::Relaxed)),
+        ));
         counters
     }
 }
@@ -432,6 +558,7 @@ pub struct DispatcherBuilder {
     default_tap_type: Option<TapType>,
     mirror_traffic_pcp: Option<u16>,
     tap_typer: Option<Arc<TapTyper>>,
+    vlan_tap_types: Option<HashMap<u16, TapType>>,
     analyzer_dedup_disabled: Option<bool>,
     libvirt_xml_extractor: Option<Arc<LibvirtXmlExtractor>>,
     flow_output_queue: Option<DebugSender<Box<TaggedFlow>>>,
@@ -443,8 +570,15 @@ pub struct DispatcherBuilder {
     policy_getter: Option<PolicyGetter>,
     #[cfg(target_os = "linux")]
     platform_poller: Option<Arc<GenericPoller>>,
+    #[cfg(target_os = "linux")]
+    fanout_load: Option<Arc<FanoutLoad>>,
+    #[cfg(target_os = "linux")]
+    drop_stats: Option<DropStats>,
+    #[cfg(target_os = "linux")]
+    cpu_affinity: Option<(usize, bool)>,
     exception_handler: Option<ExceptionHandler>,
-    ntp_diff: Option<Arc<AtomicI64>>,
+    time_source: Option<TimeSource>,
+    flow_snapshot: Option<Arc<FlowSnapshot>>,
     #[cfg(target_os = "windows")]
     pcap_interfaces: Option<Vec<Link>>,
 }
@@ -499,6 +633,11 @@ impl DispatcherBuilder {
         self
     }
 
+    pub fn vlan_tap_types(mut self, v: HashMap<u16, TapType>) -> Self {
+        self.vlan_tap_types = Some(v);
+        self
+    }
+
     pub fn analyzer_dedup_disabled(mut self, v: bool) -> Self {
         self.analyzer_dedup_disabled = Some(v);
         self
@@ -549,6 +688,24 @@ impl DispatcherBuilder {
         self
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn fanout_load(mut self, v: Arc<FanoutLoad>) -> Self {
+        self.fanout_load = Some(v);
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn drop_stats(mut self, v: DropStats) -> Self {
+        self.drop_stats = Some(v);
+        self
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn cpu_affinity(mut self, cpu: usize, local_memory: bool) -> Self {
+        self.cpu_affinity = Some((cpu, local_memory));
+        self
+    }
+
     #[cfg(target_os = "windows")]
     pub fn pcap_interfaces(mut self, v: Vec<Link>) -> Self {
         self.pcap_interfaces = Some(v);
@@ -560,8 +717,13 @@ impl DispatcherBuilder {
         self
     }
 
-    pub fn ntp_diff(mut self, v: Arc<AtomicI64>) -> Self {
-        self.ntp_diff = Some(v);
+    pub fn time_source(mut self, v: TimeSource) -> Self {
+        self.time_source = Some(v);
+        self
+    }
+
+    pub fn flow_snapshot(mut self, v: Arc<FlowSnapshot>) -> Self {
+        self.flow_snapshot = Some(v);
         self
     }
 
@@ -621,10 +783,22 @@ impl DispatcherBuilder {
                     poll_timeout: POLL_TIMEOUT.as_nanos() as isize,
                     version: options.af_packet_version,
                     iface: self.src_interface.take().unwrap_or("".to_string()),
+                    fanout_mode: options.af_packet_fanout_mode,
+                    // 同一进程的dispatcher加入同一个fanout组
+                    fanout_group_id: process::id() as u16,
+                    hardware_timestamp: options.af_packet_hardware_timestamp,
                     ..Default::default()
                 };
                 info!("Afpacket init with {:?}", afp);
-                RecvEngine::AfPacket(Tpacket::new(afp).unwrap())
+                let mut tpacket =
+                    Tpacket::inherit_or_new(afp, self.id.unwrap_or_default()).unwrap();
+                if let Some(load) = self.fanout_load.take() {
+                    tpacket.set_fanout_load(load, self.id.unwrap_or_default());
+                }
+                if let Some(drop_stats) = self.drop_stats.take() {
+                    tpacket.set_drop_stats(drop_stats);
+                }
+                RecvEngine::AfPacket(tpacket)
             };
             engine
         };
@@ -672,6 +846,7 @@ impl DispatcherBuilder {
                     .mirror_traffic_pcp
                     .ok_or(Error::ConfigIncomplete("no mirror_traffic_pcp".into()))?,
                 tap_mode,
+                vlan_tap_types: self.vlan_tap_types.unwrap_or_default(),
             },
 
             need_update_bpf: Arc::new(AtomicBool::new(true)),
@@ -681,15 +856,16 @@ impl DispatcherBuilder {
             analyzer_dedup_disabled: self
                 .analyzer_dedup_disabled
                 .ok_or(Error::ConfigIncomplete("no analyzer_dedup_disabled".into()))?,
+            #[cfg(target_os = "windows")]
+            hairpin_dedup: BaseDispatcher::new_hairpin_dedup(
+                self.pcap_interfaces.as_deref().unwrap_or_default(),
+            ),
 
             flow_output_queue: self
                 .flow_output_queue
                 .take()
                 .ok_or(Error::ConfigIncomplete("no flow_output_queue".into()))?,
-            log_output_queue: self
-                .log_output_queue
-                .take()
-                .ok_or(Error::ConfigIncomplete("no log_output_queue".into()))?,
+            log_output_queue: self.log_output_queue.take(),
 
             counter: stat_counter.clone(),
             terminated: terminated.clone(),
@@ -710,10 +886,11 @@ impl DispatcherBuilder {
                 .exception_handler
                 .take()
                 .ok_or(Error::ConfigIncomplete("no exception handler".into()))?,
-            ntp_diff: self
-                .ntp_diff
+            time_source: self
+                .time_source
                 .take()
-                .ok_or(Error::ConfigIncomplete("no ntp_diff".into()))?,
+                .ok_or(Error::ConfigIncomplete("no time_source".into()))?,
+            flow_snapshot: self.flow_snapshot.take().unwrap_or_default(),
             // Enterprise Edition Feature: packet-sequence
             packet_sequence_output_queue: self
                 .packet_sequence_output_queue
@@ -747,6 +924,8 @@ impl DispatcherBuilder {
             terminated,
             running: AtomicBool::new(false),
             handle: Mutex::new(None),
+            #[cfg(target_os = "linux")]
+            cpu_affinity: self.cpu_affinity,
         })
     }
 }
@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use log::{info, warn};
+
+use crate::exception::ExceptionHandler;
+use crate::proto::trident::Exception;
+use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
+
+// 收包过少时占比没有意义，不做判断
+const MIN_PACKETS: u64 = 1000;
+
+// 统计同一个fanout组内各socket的收包量，判断负载是否均衡
+// 每个socket的kernel_packets/kernel_drops由TpacketCounter上报，并在读取时记录到这里
+pub struct FanoutLoad {
+    packets: Vec<AtomicU64>,
+    drops: Vec<AtomicU64>,
+    // 单个socket收包占比的上限，百分比
+    threshold: u8,
+    imbalanced: AtomicBool,
+    exception_handler: ExceptionHandler,
+}
+
+impl FanoutLoad {
+    pub fn new(sockets: usize, threshold: u8, exception_handler: ExceptionHandler) -> Self {
+        Self {
+            packets: (0..sockets).map(|_| AtomicU64::new(0)).collect(),
+            drops: (0..sockets).map(|_| AtomicU64::new(0)).collect(),
+            threshold,
+            imbalanced: AtomicBool::new(false),
+            exception_handler,
+        }
+    }
+
+    pub(super) fn record(&self, index: usize, packets: u64, drops: u64) {
+        if let (Some(p), Some(d)) = (self.packets.get(index), self.drops.get(index)) {
+            p.fetch_add(packets, Ordering::Relaxed);
+            d.fetch_add(drops, Ordering::Relaxed);
+        }
+    }
+
+    // 返回(收包最多的socket, 其占比百分比)
+    fn max_load(packets: &[u64]) -> Option<(usize, u64)> {
+        let total: u64 = packets.iter().sum();
+        if total < MIN_PACKETS {
+            return None;
+        }
+        packets
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, p)| **p)
+            .map(|(i, p)| (i, p * 100 / total))
+    }
+
+    fn check(&self, packets: &[u64]) -> Option<(usize, u64)> {
+        let max_load = Self::max_load(packets);
+        let imbalanced = packets.len() > 1
+            && matches!(max_load, Some((_, ratio)) if ratio > self.threshold as u64);
+        let last_imbalanced = self.imbalanced.swap(imbalanced, Ordering::Relaxed);
+        // 异常会被同步线程取走清除，不均衡期间每个周期都需要设置
+        if imbalanced {
+            if !last_imbalanced {
+                let (index, ratio) = max_load.unwrap();
+                warn!(
+                    "af_packet fanout imbalanced: socket {} received {}% of packets",
+                    index, ratio
+                );
+            }
+            self.exception_handler
+                .set(Exception::AfPacketFanoutImbalanced);
+        } else if last_imbalanced {
+            info!("af_packet fanout balanced");
+            self.exception_handler
+                .clear(Exception::AfPacketFanoutImbalanced);
+        }
+        max_load
+    }
+}
+
+impl RefCountable for FanoutLoad {
+    fn get_counters(&self) -> Vec<Counter> {
+        let packets: Vec<u64> = self
+            .packets
+            .iter()
+            .map(|p| p.swap(0, Ordering::Relaxed))
+            .collect();
+        let drops: u64 = self
+            .drops
+            .iter()
+            .map(|d| d.swap(0, Ordering::Relaxed))
+            .sum();
+        let max_load = self.check(&packets);
+        vec![
+            (
+                "kernel_packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(packets.iter().sum()),
+            ),
+            (
+                "kernel_drops",
+                CounterType::Counted,
+                CounterValue::Unsigned(drops),
+            ),
+            (
+                "max_load_socket",
+                CounterType::Gauged,
+                CounterValue::Unsigned(max_load.map(|(i, _)| i as u64).unwrap_or_default()),
+            ),
+            (
+                "max_load_ratio",
+                CounterType::Gauged,
+                CounterValue::Unsigned(max_load.map(|(_, r)| r).unwrap_or_default()),
+            ),
+            (
+                "imbalanced",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.imbalanced.load(Ordering::Relaxed) as u64),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imbalance() {
+        let handler = ExceptionHandler::default();
+        let load = FanoutLoad::new(2, 80, handler.clone());
+
+        load.record(0, 500, 0);
+        load.record(1, 400, 0);
+        let _ = load.get_counters();
+        assert!(!load.imbalanced.load(Ordering::Relaxed));
+
+        load.record(0, 9000, 10);
+        load.record(1, 1000, 0);
+        let _ = load.get_counters();
+        assert!(load.imbalanced.load(Ordering::Relaxed));
+        assert_eq!(handler.take(), Exception::AfPacketFanoutImbalanced as u64);
+
+        load.record(0, 5000, 0);
+        load.record(1, 5000, 0);
+        let _ = load.get_counters();
+        assert!(!load.imbalanced.load(Ordering::Relaxed));
+    }
+}
@@ -20,6 +20,8 @@ use std::time::Duration;
 use libc::{c_uint, sockaddr_ll};
 
 const TP_STATUS_KERNEL: u32 = 0;
+// 内核使用了网卡的原始硬件时间戳
+const TP_STATUS_TS_RAW_HARDWARE: u32 = 1 << 31;
 const TPACKET_ALIGNMENT: usize = 0x10;
 pub const TP_STATUS_USER: isize = 1;
 
@@ -34,6 +36,7 @@ pub trait Header {
     fn get_data(&self) -> &mut [u8];
     fn get_length(&self) -> isize;
     fn get_iface_index(&self) -> isize;
+    fn is_hw_timestamp(&self) -> bool;
     fn next(&mut self) -> bool;
 }
 
@@ -208,6 +211,10 @@ impl Header for *mut Tpacket2Hdr {
         }
     }
 
+    fn is_hw_timestamp(&self) -> bool {
+        unsafe { (*(*self)).tp_status & TP_STATUS_TS_RAW_HARDWARE != 0 }
+    }
+
     fn next(&mut self) -> bool {
         return false;
     }
@@ -304,6 +311,10 @@ impl Header for V3Wrapper {
         }
     }
 
+    fn is_hw_timestamp(&self) -> bool {
+        unsafe { (*self.v3_header).tp_status & TP_STATUS_TS_RAW_HARDWARE != 0 }
+    }
+
     fn next(&mut self) -> bool {
         unsafe {
             self.used += 1;
@@ -362,4 +373,22 @@ mod tests {
 
         assert_ne!(raw, [10; 1000])
     }
+
+    #[test]
+    fn test_af_packet_header_hw_timestamp() {
+        let mut raw: [u8; 1000] = [0; 1000];
+        let v2 = Tpacket2Hdr::from((&mut raw) as *mut u8);
+        unsafe {
+            (*v2).tp_status = TP_STATUS_USER as u32;
+            assert!(!v2.is_hw_timestamp());
+            (*v2).tp_status |= TP_STATUS_TS_RAW_HARDWARE;
+            assert!(v2.is_hw_timestamp());
+        }
+
+        let v3 = V3Wrapper::from(&mut raw as *mut u8);
+        unsafe {
+            (*v3.v3_header).tp_status = TP_STATUS_TS_RAW_HARDWARE;
+        }
+        assert!(v3.is_hw_timestamp());
+    }
 }
@@ -15,13 +15,17 @@
  */
 pub mod bpf;
 #[cfg(target_os = "linux")]
+mod fanout;
+#[cfg(target_os = "linux")]
 mod header;
 pub mod options;
 #[cfg(target_os = "linux")]
 pub mod tpacket;
 
 pub use bpf::*;
-pub use options::{OptSocketType, OptTpacketVersion, Options};
+#[cfg(target_os = "linux")]
+pub use fanout::FanoutLoad;
+pub use options::{OptFanoutMode, OptSocketType, OptTpacketVersion, Options};
 #[cfg(target_os = "linux")]
 pub use tpacket::Tpacket;
 
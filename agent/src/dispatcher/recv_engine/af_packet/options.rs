@@ -18,6 +18,7 @@ use page_size;
 
 pub use public::error::af_packet::{Error, Result};
 
+use crate::config::AfPacketFanoutMode;
 use crate::proto::trident::CaptureSocketType;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd)]
@@ -57,6 +58,49 @@ impl OptTpacketVersion {
     }
 }
 
+// linux/if_packet.h
+const PACKET_FANOUT_HASH: u16 = 0;
+const PACKET_FANOUT_CPU: u16 = 2;
+const PACKET_FANOUT_QM: u16 = 5;
+const PACKET_FANOUT_FLAG_DEFRAG: u16 = 0x8000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptFanoutMode {
+    Disabled,
+    Hash,
+    Cpu,
+    Qm,
+}
+
+impl Default for OptFanoutMode {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+impl From<AfPacketFanoutMode> for OptFanoutMode {
+    fn from(m: AfPacketFanoutMode) -> Self {
+        match m {
+            AfPacketFanoutMode::Disabled => Self::Disabled,
+            AfPacketFanoutMode::Hash => Self::Hash,
+            AfPacketFanoutMode::Cpu => Self::Cpu,
+            AfPacketFanoutMode::Qm => Self::Qm,
+        }
+    }
+}
+
+impl OptFanoutMode {
+    // PACKET_FANOUT选项的高16位，hash模式需先重组分片，保证同一个流的分片落到同一个socket
+    pub fn type_flags(&self) -> Option<u16> {
+        match self {
+            Self::Disabled => None,
+            Self::Hash => Some(PACKET_FANOUT_HASH | PACKET_FANOUT_FLAG_DEFRAG),
+            Self::Cpu => Some(PACKET_FANOUT_CPU),
+            Self::Qm => Some(PACKET_FANOUT_QM),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum OptSocketType {
     SocketTypeDgram = 2,
@@ -80,6 +124,10 @@ pub struct Options {
     pub version: OptTpacketVersion,
     pub socket_type: OptSocketType,
     pub iface: String,
+    pub fanout_mode: OptFanoutMode,
+    pub fanout_group_id: u16,
+    // 使用网卡硬件时间戳，网卡或驱动不支持时回退为软件时间戳
+    pub hardware_timestamp: bool,
 }
 
 impl Default for Options {
@@ -94,6 +142,9 @@ impl Default for Options {
             version: OptTpacketVersion::TpacketVersionHighestavailablet,
             socket_type: OptSocketType::SocketTypeRaw,
             iface: "".to_string(),
+            fanout_mode: OptFanoutMode::Disabled,
+            fanout_group_id: 0,
+            hardware_timestamp: false,
         }
     }
 }
@@ -15,20 +15,27 @@
  */
 
 use std::io;
+use std::os::unix::io::FromRawFd;
+use std::sync::{
+    atomic::{AtomicU32, AtomicU64, Ordering},
+    Arc, Weak,
+};
 
 use libc::{
     c_int, c_uint, c_void, getsockopt, mmap, munmap, off_t, poll, pollfd, size_t, sockaddr,
     sockaddr_ll, socklen_t, AF_PACKET, ETH_P_ALL, MAP_LOCKED, MAP_NORESERVE, MAP_SHARED, POLLERR,
     POLLIN, PROT_READ, PROT_WRITE, SOL_PACKET, SOL_SOCKET, SO_ATTACH_FILTER,
 };
-use log::warn;
+use log::{info, warn};
 use public::error::*;
 use public::packet::Packet;
 use socket::{self, Socket};
 
-use super::{bpf, header, options};
+use super::{bpf, fanout::FanoutLoad, header, options};
 
 use crate::utils::{
+    drop_stats::{DropSource, DropStats},
+    handoff::{self, CaptureSocket},
     net::{self, link_by_name},
     stats,
 };
@@ -36,6 +43,10 @@ use crate::utils::{
 const PACKET_VERSION: c_int = 10;
 const PACKET_RX_RING: c_int = 5;
 const PACKET_STATISTICS: c_int = 6;
+const PACKET_TIMESTAMP: c_int = 17;
+const PACKET_FANOUT: c_int = 18;
+// linux/net_tstamp.h
+const SOF_TIMESTAMPING_RAW_HARDWARE: c_int = 1 << 6;
 const MILLI_SECONDS: u32 = 1000000;
 
 // https://www.ietf.org/archive/id/draft-gharris-opsawg-pcap-01.html
@@ -79,6 +90,12 @@ pub struct Tpacket {
     tp_version: options::OptTpacketVersion,
 
     v3: Option<*mut header::V3Wrapper>,
+
+    // fanout组的负载统计及本socket在组内的序号
+    fanout_load: Option<(Arc<FanoutLoad>, usize)>,
+    drop_stats: Option<DropStats>,
+    // 热升级时交接给新进程的socket参数
+    handoff: Arc<CaptureSocket>,
 }
 
 // it's safe because ring points to mmap'ed buffer
@@ -183,6 +200,47 @@ impl Tpacket {
         }
     }
 
+    // 组内所有socket的fanout类型需一致，高16位为类型及标志，低16位为组id
+    fn fanout_arg(opts: &options::Options) -> Option<c_int> {
+        opts.fanout_mode
+            .type_flags()
+            .map(|type_flags| (type_flags as c_int) << 16 | opts.fanout_group_id as c_int)
+    }
+
+    fn set_fanout(&self) -> af_packet::Result<()> {
+        if let Some(arg) = Self::fanout_arg(&self.opts) {
+            self.raw_socket.setsockopt(SOL_PACKET, PACKET_FANOUT, arg)?;
+        }
+        Ok(())
+    }
+
+    // 需先开启网卡的硬件时间戳，未指定网卡时只设置socket选项，由各网卡自身的配置决定
+    fn set_hw_timestamp(&self) -> Result<()> {
+        if self.opts.iface != "" {
+            net::enable_rx_hw_timestamp(&self.opts.iface)
+                .map_err(|e| af_packet::Error::LinkError(e.to_string()))?;
+        }
+        self.raw_socket
+            .setsockopt(SOL_PACKET, PACKET_TIMESTAMP, SOF_TIMESTAMPING_RAW_HARDWARE)?;
+        Ok(())
+    }
+
+    fn try_set_hw_timestamp(&self) {
+        if !self.opts.hardware_timestamp {
+            return;
+        }
+        match self.set_hw_timestamp() {
+            Ok(_) => info!(
+                "interface {:?} hardware timestamp enabled",
+                self.opts.iface
+            ),
+            Err(e) => warn!(
+                "interface {:?} enable hardware timestamp failed, fallback to software timestamp: {}",
+                self.opts.iface, e
+            ),
+        }
+    }
+
     fn mmap_ring(&mut self) -> af_packet::Result<()> {
         // 接收队列
         unsafe {
@@ -265,6 +323,7 @@ impl Tpacket {
                     x.clear_status();
                 }
                 self.offset += 1;
+                self.handoff.offset.store(self.offset, Ordering::Relaxed);
                 self.should_release_packet = false;
             }
             self.current = Some(self.get_packet_header());
@@ -286,6 +345,7 @@ impl Tpacket {
                 if_index: x.get_iface_index(),
                 data: x.get_data(),
                 capture_length: x.get_length(),
+                hw_timestamp: x.is_hw_timestamp(),
             };
             self.header_next_needed = true;
             return Some(packet);
@@ -300,22 +360,32 @@ impl Tpacket {
         Ok(())
     }
 
-    pub fn get_counter_handle(&self) -> TpacketCounter {
-        TpacketCounter {
+    pub fn get_counter_handle(&self) -> Arc<TpacketCounter> {
+        let counter = Arc::new(TpacketCounter {
             tp_version: self.tp_version,
             fd: self.raw_socket.fileno(),
+            fanout_load: self.fanout_load.clone(),
+            drop_stats: self.drop_stats.clone(),
+            packets: AtomicU64::new(0),
+            drops: AtomicU64::new(0),
+            freezes: AtomicU64::new(0),
+        });
+        if let Some(drop_stats) = self.drop_stats.as_ref() {
+            drop_stats.register(Arc::downgrade(&counter) as Weak<dyn DropSource>);
         }
+        counter
     }
 
-    pub fn new(opts: options::Options) -> Result<Self> {
-        opts.check()?;
-        // 创建原始socket
-        let raw_socket = Socket::new(
-            AF_PACKET,
-            opts.socket_type.to_i32(),
-            (ETH_P_ALL as u16).to_be() as i32,
-        )?;
-        let mut tpacket = Tpacket {
+    pub fn set_fanout_load(&mut self, load: Arc<FanoutLoad>, index: usize) {
+        self.fanout_load = Some((load, index));
+    }
+
+    pub fn set_drop_stats(&mut self, drop_stats: DropStats) {
+        self.drop_stats = Some(drop_stats);
+    }
+
+    fn from_socket(opts: options::Options, raw_socket: Socket) -> Self {
+        Tpacket {
             _stats: Stats {
                 packets: 0,
                 polls: 0,
@@ -329,11 +399,106 @@ impl Tpacket {
             header_next_needed: false,
             tp_version: opts.version,
             v3: Option::None,
-        };
+            fanout_load: None,
+            drop_stats: None,
+            handoff: Default::default(),
+        }
+    }
+
+    fn handoff_socket(&self, id: usize) -> CaptureSocket {
+        CaptureSocket {
+            id,
+            iface: self.opts.iface.clone(),
+            fd: self.raw_socket.fileno(),
+            version: self.tp_version as c_int,
+            frame_size: self.opts.frame_size,
+            block_size: self.opts.block_size,
+            num_blocks: self.opts.num_blocks,
+            fanout: Self::fanout_arg(&self.opts).unwrap_or_default(),
+            offset: AtomicU32::new(self.offset),
+        }
+    }
+
+    pub fn new(opts: options::Options) -> Result<Self> {
+        opts.check()?;
+        // 创建原始socket
+        let raw_socket = Socket::new(
+            AF_PACKET,
+            opts.socket_type.to_i32(),
+            (ETH_P_ALL as u16).to_be() as i32,
+        )?;
+        let mut tpacket = Self::from_socket(opts, raw_socket);
         tpacket.bind()?;
         tpacket.set_version()?;
         tpacket.set_ring()?;
         tpacket.mmap_ring()?;
+        tpacket.set_fanout()?;
+        tpacket.try_set_hw_timestamp();
+        Ok(tpacket)
+    }
+
+    // 复用热升级前进程交接的socket，ring和fanout参数与配置不一致时重新创建
+    fn inherit(opts: options::Options, socket: CaptureSocket) -> Option<Self> {
+        let version = match socket.version {
+            v if v == options::OptTpacketVersion::TpacketVersion2 as c_int => {
+                options::OptTpacketVersion::TpacketVersion2
+            }
+            v if v == options::OptTpacketVersion::TpacketVersion3 as c_int => {
+                options::OptTpacketVersion::TpacketVersion3
+            }
+            _ => options::OptTpacketVersion::TpacketVersion1,
+        };
+        let raw_socket = unsafe { Socket::from_raw_fd(socket.fd) };
+        let matched = (opts.version == version
+            || opts.version == options::OptTpacketVersion::TpacketVersionHighestavailablet)
+            && version != options::OptTpacketVersion::TpacketVersion1
+            && opts.frame_size == socket.frame_size
+            && opts.block_size == socket.block_size
+            && opts.num_blocks == socket.num_blocks
+            && Self::fanout_arg(&opts).unwrap_or_default() == socket.fanout;
+        if !matched {
+            info!(
+                "handoff socket {} of interface {:?} does not match current options, recreate it",
+                socket.fd, socket.iface
+            );
+            let _ = raw_socket.close();
+            return None;
+        }
+
+        let mut tpacket = Self::from_socket(opts, raw_socket);
+        tpacket.tp_version = version;
+        tpacket.offset = socket.offset.load(Ordering::Relaxed);
+        if let Err(e) = tpacket.mmap_ring() {
+            warn!("mmap handoff socket {} failed: {}", socket.fd, e);
+            let _ = tpacket.raw_socket.close();
+            return None;
+        }
+        // 交接的socket保留原有的时间戳选项，按当前配置重新设置
+        if tpacket.opts.hardware_timestamp {
+            tpacket.try_set_hw_timestamp();
+        } else {
+            let _ = tpacket
+                .raw_socket
+                .setsockopt(SOL_PACKET, PACKET_TIMESTAMP, 0 as c_int);
+        }
+        info!(
+            "reuse handoff socket {} of interface {:?} at offset {}",
+            socket.fd, socket.iface, tpacket.offset
+        );
+        Some(tpacket)
+    }
+
+    // id为dispatcher序号，同一网卡可能有多个fanout socket
+    pub fn inherit_or_new(opts: options::Options, id: usize) -> Result<Self> {
+        opts.check()?;
+        let inherited = handoff::take_inherited(id, &opts.iface)
+            .and_then(|socket| Self::inherit(opts.clone(), socket));
+        let mut tpacket = match inherited {
+            Some(t) => t,
+            None => Self::new(opts)?,
+        };
+        tpacket.handoff = Arc::new(tpacket.handoff_socket(id));
+        handoff::register(Arc::downgrade(&tpacket.handoff));
         Ok(tpacket)
     }
 }
@@ -356,10 +521,17 @@ impl Drop for Tpacket {
 pub struct TpacketCounter {
     tp_version: options::OptTpacketVersion,
     fd: i32,
+    fanout_load: Option<(Arc<FanoutLoad>, usize)>,
+    drop_stats: Option<DropStats>,
+    // PACKET_STATISTICS读取后内核计数清零，秒级轮询和stats都会读取，累加到这里由stats取走
+    packets: AtomicU64,
+    drops: AtomicU64,
+    freezes: AtomicU64,
 }
 
-impl stats::RefCountable for TpacketCounter {
-    fn get_counters(&self) -> Vec<stats::Counter> {
+impl TpacketCounter {
+    // 返回(packets, drops, freezes)，其中packets包含drops
+    fn read_statistics(&self) -> Option<(u64, u64, u64)> {
         if self.tp_version == options::OptTpacketVersion::TpacketVersion3 {
             let mut stats_v3 = TpacketStatsV3 {
                 tp_packets: 0,
@@ -377,26 +549,14 @@ impl stats::RefCountable for TpacketCounter {
                 );
                 if ret != 0 {
                     warn!("{:?}", io::Error::last_os_error());
-                    return vec![];
+                    return None;
                 }
             }
-            vec![
-                (
-                    "kernel_packets",
-                    stats::CounterType::Counted,
-                    stats::CounterValue::Unsigned(stats_v3.tp_packets as u64),
-                ),
-                (
-                    "kernel_drops",
-                    stats::CounterType::Counted,
-                    stats::CounterValue::Unsigned(stats_v3.tp_drops as u64),
-                ),
-                (
-                    "kernel_freezes",
-                    stats::CounterType::Counted,
-                    stats::CounterValue::Unsigned(stats_v3.tp_freeze_q_cnt as u64),
-                ),
-            ]
+            Some((
+                stats_v3.tp_packets as u64,
+                stats_v3.tp_drops as u64,
+                stats_v3.tp_freeze_q_cnt as u64,
+            ))
         } else if self.tp_version == options::OptTpacketVersion::TpacketVersion2 {
             let mut stats = TpacketStats {
                 tp_packets: 0,
@@ -413,24 +573,66 @@ impl stats::RefCountable for TpacketCounter {
                 );
                 if ret != 0 {
                     warn!("{:?}", io::Error::last_os_error());
-                    return vec![];
+                    return None;
                 }
             }
-            vec![
-                (
-                    "kernel_packets",
-                    stats::CounterType::Counted,
-                    stats::CounterValue::Unsigned(stats.tp_packets as u64),
-                ),
-                (
-                    "kernel_drops",
-                    stats::CounterType::Counted,
-                    stats::CounterValue::Unsigned(stats.tp_drops as u64),
-                ),
-            ]
+            Some((stats.tp_packets as u64, stats.tp_drops as u64, 0))
         } else {
             warn!("invalid tp version");
-            vec![]
+            None
+        }
+    }
+}
+
+impl DropSource for TpacketCounter {
+    fn poll(&self) {
+        let (packets, drops, freezes) = match self.read_statistics() {
+            Some(s) => s,
+            None => return,
+        };
+        self.packets.fetch_add(packets, Ordering::Relaxed);
+        self.drops.fetch_add(drops, Ordering::Relaxed);
+        self.freezes.fetch_add(freezes, Ordering::Relaxed);
+        if let Some(drop_stats) = self.drop_stats.as_ref() {
+            drop_stats.record_kernel(packets, drops);
+        }
+    }
+}
+
+impl stats::RefCountable for TpacketCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        if self.tp_version != options::OptTpacketVersion::TpacketVersion3
+            && self.tp_version != options::OptTpacketVersion::TpacketVersion2
+        {
+            warn!("invalid tp version");
+            return vec![];
+        }
+        self.poll();
+        let packets = self.packets.swap(0, Ordering::Relaxed);
+        let drops = self.drops.swap(0, Ordering::Relaxed);
+        let freezes = self.freezes.swap(0, Ordering::Relaxed);
+        if let Some((load, index)) = self.fanout_load.as_ref() {
+            load.record(*index, packets, drops);
+        }
+        let mut counters = vec![
+            (
+                "kernel_packets",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(packets),
+            ),
+            (
+                "kernel_drops",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(drops),
+            ),
+        ];
+        if self.tp_version == options::OptTpacketVersion::TpacketVersion3 {
+            counters.push((
+                "kernel_freezes",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(freezes),
+            ));
         }
+        counters
     }
 }
@@ -107,7 +107,7 @@ impl RecvEngine {
     pub fn get_counter_handle(&self) -> Arc<dyn stats::RefCountable> {
         match self {
             #[cfg(target_os = "linux")]
-            Self::AfPacket(e) => Arc::new(e.get_counter_handle()),
+            Self::AfPacket(e) => e.get_counter_handle(),
             Self::Dpdk() => todo!(),
             #[cfg(target_os = "windows")]
             Self::WinPcap(w) => match w {
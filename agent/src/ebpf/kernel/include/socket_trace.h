@@ -71,6 +71,8 @@ struct mmsghdr {
 #define NULL ((void*)0)
 #endif
 
+#define AF_UNIX         1	/* Unix domain sockets          */
+#define PF_UNIX         AF_UNIX
 #define AF_INET         2	/* Internet IP Protocol         */
 #define PF_INET         AF_INET
 #define AF_INET6        10	/* IP version 6                 */
@@ -37,7 +37,9 @@ struct __socket_data {
 	/* 进程/线程信息 */
 	__u32 pid;  // 表示线程号 如果'pid == tgid'表示一个进程, 否则是线程
 	__u32 tgid; // 进程号
+	__u32 peer_tgid; // unix socket对端进程号，其他类型socket为0
 	__u64 coroutine_id; // CoroutineID, i.e., golang goroutine id
+	__u64 cgroup_id; // 进程所在cgroup v2的ID，即cgroup目录的inode号
 	__u8  comm[16]; // 进程或线程名
 
 	/* 连接（socket）信息 */
@@ -59,6 +61,7 @@ struct __socket_data {
 	__u64 timestamp;     // 数据捕获时间戳
 	__u8  direction: 1;  // bits[0]: 方向，值为T_EGRESS(0), T_INGRESS(1)
 	__u8  msg_type:  7;  // bits[1-7]: 信息类型，值为MSG_UNKNOWN(0), MSG_REQUEST(1), MSG_RESPONSE(2)
+	__u8  unix_socket;   // 为1表示unix socket数据，tuple中为合成的127.0.0.1地址
 
 	__u64 syscall_len;   // 本次系统调用读、写数据的总长度
 	__u64 data_seq;      // cap_data在Socket中的相对顺序号
@@ -88,6 +91,11 @@ struct trace_stats {
 	__u64 trace_map_count;     // 对同一进程/线程的多次转发表进行统计
 };
 
+// 用户态下发的追踪配置
+struct trace_conf_t {
+	__u32 unix_socket_enabled; // 是否追踪unix socket，默认不追踪
+};
+
 struct socket_info_t {
 	__u64 l7_proto: 8;
 	__u64 seq: 56; // socket 读写数据的序列号，用于排序
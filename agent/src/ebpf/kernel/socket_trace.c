@@ -63,6 +63,11 @@ MAP_PERARRAY(trace_uid_map, __u32, struct trace_uid_t, 1)
  */
 MAP_PERARRAY(trace_stats_map, __u32, struct trace_stats, 1)
 
+/*
+ * 用户态下发的追踪配置
+ */
+MAP_ARRAY(trace_conf_map, __u32, struct trace_conf_t, 1)
+
 // write() syscall's input argument.
 // Key is {tgid, pid}.
 BPF_HASH(active_write_args_map, __u64, struct data_args_t)
@@ -167,6 +172,42 @@ static __inline void get_sock_flags(void *sk,
 }
 #endif
 
+#ifdef BPF_USE_CORE
+/*
+ * unix socket没有IP地址和端口，使用本端和对端sock地址生成端口，
+ * 保证同一连接两端看到的端口互为镜像，从而能聚合为一条流。
+ * 端口只有16位，不同连接可能生成相同的端口对，agent按socket_id而不是
+ * 五元组聚合eBPF数据，冲突只影响日志中展示的合成地址和端口。
+ */
+static __inline __u16 unix_sock_port(void *sk)
+{
+	__u64 v = (__u64)sk;
+	__u16 port = (__u16)((v >> 4) ^ (v >> 20) ^ (v >> 36));
+	return port == 0 ? 1 : port;
+}
+
+static __inline void *get_unix_peer(void *sk)
+{
+	struct unix_sock *usk = sk;
+	void *peer = NULL;
+	bpf_core_read(&peer, sizeof(peer), &usk->peer);
+	return peer;
+}
+
+// sk_peer_pid在connect()/socketpair()时设置为对端进程的tgid
+static __inline __u32 get_unix_peer_tgid(void *sk)
+{
+	struct sock *__sk = sk;
+	struct pid *peer_pid = NULL;
+	__u32 tgid = 0;
+	bpf_core_read(&peer_pid, sizeof(peer_pid), &__sk->sk_peer_pid);
+	if (peer_pid == NULL)
+		return 0;
+	bpf_core_read(&tgid, sizeof(tgid), &peer_pid->numbers[0].nr);
+	return tgid;
+}
+#endif
+
 static __inline int is_tcp_udp_data(void *sk,
 				    struct member_fields_offset *offset,
 				    struct conn_info_t *conn_info)
@@ -191,9 +232,6 @@ struct skc_flags_t {
 	bpf_probe_read(&conn_info->skc_family, sizeof(conn_info->skc_family),
 		       sk + STRUCT_SOCK_FAMILY_OFFSET);
 #endif
-	/*
-	 * Without thinking about PF_UNIX.
-	 */
 	switch (conn_info->skc_family) {
 	case PF_INET:
 		break;
@@ -201,6 +239,20 @@ struct skc_flags_t {
 		if (conn_info->skc_ipv6only == 0)
 			conn_info->skc_family = PF_INET;
 		break;
+#ifdef BPF_USE_CORE
+	/*
+	 * 非CO-RE方式无法获取unix_sock成员的偏移，仅CO-RE方式支持PF_UNIX。
+	 * 已连接的unix stream socket的skc_state同样为TCP_ESTABLISHED。
+	 */
+	case PF_UNIX: {
+		// 未开启unix socket追踪时不处理，避免无用的数据上送
+		__u32 k0 = 0;
+		struct trace_conf_t *trace_conf = trace_conf_map__lookup(&k0);
+		if (trace_conf == NULL || !trace_conf->unix_socket_enabled)
+			return SOCK_CHECK_TYPE_ERROR;
+		break;
+	}
+#endif
 	default:
 		return SOCK_CHECK_TYPE_ERROR;
 	}
@@ -260,6 +312,13 @@ static __inline void init_conn_info(__u32 tgid, __u32 fd,
 #endif
 	conn_info->tuple.dport = __bpf_ntohs(inet_dport);
 	conn_info->tuple.num = inet_sport;
+#ifdef BPF_USE_CORE
+	if (conn_info->skc_family == PF_UNIX) {
+		void *peer = get_unix_peer(sk);
+		conn_info->tuple.num = unix_sock_port(sk);
+		conn_info->tuple.dport = peer ? unix_sock_port(peer) : 0;
+	}
+#endif
 	conn_info->prev_count = 0;
 	conn_info->direction = 0;
 	*((__u32 *) conn_info->prev_buf) = 0;
@@ -292,9 +351,8 @@ static __inline bool get_socket_info(struct __socket_data *v,
 #ifdef BPF_USE_CORE
 	struct sock *__sk = sk;
 #endif
-	/*
-	 * Without thinking about PF_UNIX.
-	 */
+	v->peer_tgid = 0;
+	v->unix_socket = 0;
 	switch (conn_info->skc_family) {
 	case PF_INET:
 #ifdef BPF_USE_CORE
@@ -320,6 +378,16 @@ static __inline bool get_socket_info(struct __socket_data *v,
 #endif
 		v->tuple.addr_len = 16;
 		break;
+#ifdef BPF_USE_CORE
+	case PF_UNIX:
+		// 以127.0.0.1作为两端地址，端口见unix_sock_port()
+		*(__u32 *)v->tuple.rcv_saddr = __bpf_htonl(0x7f000001);
+		*(__u32 *)v->tuple.daddr = __bpf_htonl(0x7f000001);
+		v->tuple.addr_len = 4;
+		v->peer_tgid = get_unix_peer_tgid(sk);
+		v->unix_socket = 1;
+		break;
+#endif
 	default:
 		return false;
 	}
@@ -758,9 +826,12 @@ data_submit(struct pt_regs *ctx, struct conn_info_t *conn_info,
 	__u32 tcp_seq = 0;
 	__u64 thread_trace_id = 0;
 
-	if (conn_info->direction == T_INGRESS && conn_info->tuple.l4_protocol == IPPROTO_TCP) {
+	// unix socket没有TCP SEQ
+	bool has_tcp_seq = conn_info->tuple.l4_protocol == IPPROTO_TCP &&
+			   conn_info->skc_family != PF_UNIX;
+	if (conn_info->direction == T_INGRESS && has_tcp_seq) {
 		tcp_seq = get_tcp_read_seq_from_fd(conn_info->fd);
-	} else if (conn_info->direction == T_EGRESS && conn_info->tuple.l4_protocol == IPPROTO_TCP) {
+	} else if (conn_info->direction == T_EGRESS && has_tcp_seq) {
 		tcp_seq = get_tcp_write_seq_from_fd(conn_info->fd);
 	}
 
@@ -892,7 +963,7 @@ data_submit(struct pt_regs *ctx, struct conn_info_t *conn_info,
 	v->syscall_len = syscall_len;
 	v->msg_type = conn_info->message_type;
 	v->tcp_seq = 0;
-	if (conn_info->tuple.l4_protocol == IPPROTO_TCP)
+	if (has_tcp_seq)
 		v->tcp_seq = tcp_seq - syscall_len;
 
 	v->thread_trace_id = thread_trace_id;
@@ -910,6 +981,7 @@ data_submit(struct pt_regs *ctx, struct conn_info_t *conn_info,
 		v->tcp_seq = extra->tcp_seq;
 
 	v->coroutine_id = extra->coroutine_id;
+	v->cgroup_id = bpf_get_current_cgroup_id();
 	/*
 	 * the bitwise AND operation will set the range of possible values for
 	 * the UNKNOWN_VALUE register to [0, BUFSIZE)
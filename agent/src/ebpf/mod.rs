@@ -80,6 +80,15 @@ pub const EVENT_TYPE_PROC_EXEC: u32 = 1 << 5;
 #[allow(dead_code)]
 pub const EVENT_TYPE_PROC_EXIT: u32 = 1 << 6;
 
+pub const PROBE_NAME_SZ: usize = 128;
+pub const PROBE_ATTACH_FAILURES_MAX: usize = 64;
+pub const ATTACHED_PROBES_MAX: usize = 4096;
+
+//probe类型
+#[allow(dead_code)]
+pub const KPROBE: i32 = 0;
+pub const UPROBE: i32 = 1;
+
 //Process exec/exit events
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -104,9 +113,11 @@ pub struct tuple_t {
 #[derive(Debug, Copy, Clone)]
 pub struct SK_BPF_DATA {
     /* session info */
-    pub process_id: u32,   // tgid in kernel struct task_struct
-    pub thread_id: u32,    // pid in kernel struct task_struct, main thread iff pid==tgid
-    pub coroutine_id: u64, // CoroutineID, i.e., golang goroutine id
+    pub process_id: u32,      // tgid in kernel struct task_struct
+    pub thread_id: u32,       // pid in kernel struct task_struct, main thread iff pid==tgid
+    pub peer_process_id: u32, // unix socket对端进程的tgid，其他类型socket为0
+    pub coroutine_id: u64,    // CoroutineID, i.e., golang goroutine id
+    pub cgroup_id: u64,       // 进程所在cgroup v2的ID，即cgroup目录的inode号
 
     pub process_name: [u8; 16usize], //进程或线程名字，占用16bytes
 
@@ -129,6 +140,7 @@ pub struct SK_BPF_DATA {
     pub msg_type: u8, // 信息类型，值为MSG_REQUEST(1), MSG_RESPONSE(2), 需要应用层分析进一步确认。
     pub need_reconfirm: bool, // true: 表示eBPF程序对L7协议类型的判断并不确定需要上层重新核实。
     // false: 表示eBPF程序对L7协议类型的判断是有把握的不需要上层重新核实。
+    pub unix_socket: bool, // unix socket数据，tuple中为合成的127.0.0.1地址和端口
 
     /* trace info */
     pub tcp_seq: u64, // 收发cap_data数据时TCP协议栈将会用到的TCP SEQ，可用于关联eBPF DATA与网络中的TCP Packet
@@ -279,6 +291,77 @@ pub struct SK_TRACE_STATS {
     pub probes_count: u32,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PROBE_ATTACH_FAILURE {
+    pub name: [u8; PROBE_NAME_SZ],
+    pub binary_path: [u8; 256], // 仅uprobe有效
+    pub pid: i32,               // 仅uprobe有效
+    pub error: i32,
+}
+
+impl Default for PROBE_ATTACH_FAILURE {
+    fn default() -> Self {
+        Self {
+            name: [0; PROBE_NAME_SZ],
+            binary_path: [0; 256],
+            pid: 0,
+            error: 0,
+        }
+    }
+}
+
+impl PROBE_ATTACH_FAILURE {
+    pub fn name(&self) -> String {
+        c_str(&self.name)
+    }
+
+    pub fn binary_path(&self) -> String {
+        c_str(&self.binary_path)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ATTACHED_PROBE {
+    pub name: [u8; PROBE_NAME_SZ],
+    pub binary_path: [u8; 256], // 仅uprobe有效
+    pub offset: u64,            // 仅uprobe有效，符号在文件中的偏移
+    pub run_cnt: u64,           // eBPF程序运行次数，需开启kernel.bpf_stats_enabled，否则为0
+    pub pid: i32,               // 仅uprobe有效
+    pub probe_type: i32,        // KPROBE或UPROBE
+    pub isret: bool,
+}
+
+impl Default for ATTACHED_PROBE {
+    fn default() -> Self {
+        Self {
+            name: [0; PROBE_NAME_SZ],
+            binary_path: [0; 256],
+            offset: 0,
+            run_cnt: 0,
+            pid: 0,
+            probe_type: KPROBE,
+            isret: false,
+        }
+    }
+}
+
+impl ATTACHED_PROBE {
+    pub fn name(&self) -> String {
+        c_str(&self.name)
+    }
+
+    pub fn binary_path(&self) -> String {
+        c_str(&self.binary_path)
+    }
+}
+
+fn c_str(s: &[u8]) -> String {
+    let end = s.iter().position(|c| *c == 0).unwrap_or(s.len());
+    String::from_utf8_lossy(&s[..end]).into_owned()
+}
+
 extern "C" {
     // 初始化tracer用于设置eBPF环境初始化。
     // 参数：
@@ -330,6 +413,22 @@ extern "C" {
     // 返回值：成功返回0，否则返回非0
     pub fn tracer_start() -> c_int;
 
+    // 取走probe attach失败记录
+    // 返回值：取走的记录数量
+    pub fn probe_attach_failures(failures: *mut PROBE_ATTACH_FAILURE, max: c_int) -> c_int;
+
+    // 重新扫描procfs并为新进程及重启的进程attach golang uprobe
+    // 返回值：成功返回0，否则返回非0
+    pub fn go_probes_resync() -> c_int;
+
+    // 开启或关闭内核中unix socket数据的追踪，tracer运行后调用
+    // 返回值：成功返回0，否则返回非0
+    pub fn set_unix_socket_trace(enabled: bool) -> c_int;
+
+    // 获取socket tracer当前已attach的probe
+    // 返回值：获取的probe数量
+    pub fn socket_tracer_probes(probes: *mut ATTACHED_PROBE, max: c_int) -> c_int;
+
     // 注意：eBPF tracer初始化加载运行后进行内核适配，
     // 适配完成后马上进入stop状态，需调用tracer_start()才开始工作。
 }
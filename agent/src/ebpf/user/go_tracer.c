@@ -48,8 +48,6 @@
 #include "socket.h"
 
 #define MAP_GO_OFFSETS_MAP_NAME	"uprobe_offsets_map"
-#define PROCFS_CHECK_PERIOD  60	// 60 seconds
-static uint64_t procfs_check_count;
 
 static char build_info_magic[] = "\xff Go buildinf:";
 
@@ -638,22 +636,11 @@ static void clear_probes_by_pid(struct bpf_tracer *tracer, int pid,
 }
 
 /**
- * period_update_procfs - Managing all golang processes
+ * update_procfs - Managing all golang processes
  * @tracer: struct bpf_tracer
  */
-static int __attribute__ ((unused)) period_update_procfs(void)
+static int update_procfs(struct bpf_tracer *tracer)
 {
-	procfs_check_count++;
-	if (procfs_check_count % PROCFS_CHECK_PERIOD != 0)
-		return ETR_INVAL;
-
-	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
-	if (tracer == NULL)
-		return ETR_INVAL;
-
-	if (tracer->state != TRACER_RUNNING)
-		return ETR_INVAL;
-
 	struct tracer_probes_conf *conf = tracer->tps;
 	struct probe *probe;
 	struct list_head *p, *n;
@@ -757,6 +744,28 @@ void update_go_offsets_to_map(struct bpf_tracer *tracer)
 	}
 }
 
+/**
+ * go_probes_resync - 重新扫描procfs，清理已退出进程的uprobe并为新进程及
+ * 重启的进程重新attach，由上层在检测到uprobe事件停止时调用
+ *
+ * @return 0 if ok, not 0 on error
+ */
+int go_probes_resync(void)
+{
+	struct bpf_tracer *tracer = find_bpf_tracer(SK_TRACER_NAME);
+	if (tracer == NULL)
+		return ETR_INVAL;
+
+	if (tracer->state != TRACER_RUNNING)
+		return ETR_INVAL;
+
+	// Protect the probes operation in multiple threads, similar to process_execute_handle()
+	pthread_mutex_lock(&tracer->mutex_probes_lock);
+	int ret = update_procfs(tracer);
+	pthread_mutex_unlock(&tracer->mutex_probes_lock);
+	return ret;
+}
+
 static void process_execute_handle(int pid, struct bpf_tracer *tracer)
 {
 	/*
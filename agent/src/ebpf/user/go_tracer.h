@@ -55,4 +55,5 @@ void update_go_offsets_to_map(struct bpf_tracer *tracer);
 void go_process_exec(int pid);
 void go_process_exit(int pid);
 void go_process_events_handle(void);
+int go_probes_resync(void);
 #endif
@@ -39,6 +39,7 @@
 #define MAP_PERF_SOCKET_DATA_NAME	"__socket_data"
 #define MAP_TRACE_UID_NAME		"__trace_uid_map"
 #define MAP_TRACE_STATS_NAME		"__trace_stats_map"
+#define MAP_TRACE_CONF_NAME		"__trace_conf_map"
 
 // 在socket map回收时，对每条socket信息超过10秒没有收发动作就回收掉
 #define SOCKET_RECLAIM_TIMEOUT_DEF  10
@@ -522,7 +523,10 @@ static void reader_raw_cb(void *t, void *raw, int raw_size)
 		    need_proto_reconfirm(sd->data_type);
 		submit_data->process_id = sd->tgid;
 		submit_data->thread_id = sd->pid;
+		submit_data->peer_process_id = sd->peer_tgid;
+		submit_data->unix_socket = sd->unix_socket;
 		submit_data->coroutine_id = sd->coroutine_id;
+		submit_data->cgroup_id = sd->cgroup_id;
 		submit_data->cap_data =
 		    (char *)((void **)&submit_data->cap_data + 1);
 		submit_data->syscall_len = sd->syscall_len;
@@ -1092,6 +1096,65 @@ struct socket_trace_stats socket_tracer_stats(void)
 	return stats;
 }
 
+int set_unix_socket_trace(bool enabled)
+{
+	struct bpf_tracer *t = find_bpf_tracer(SK_TRACER_NAME);
+	if (t == NULL)
+		return -1;
+
+	struct trace_conf_t conf;
+	memset(&conf, 0, sizeof(conf));
+	conf.unix_socket_enabled = enabled;
+	if (!bpf_table_set_value(t, MAP_TRACE_CONF_NAME, 0, (void *)&conf))
+		return -1;
+
+	ebpf_info("unix socket trace %s\n", enabled ? "enabled" : "disabled");
+	return 0;
+}
+
+int socket_tracer_probes(struct attached_probe *probes, int max)
+{
+	struct bpf_tracer *t = find_bpf_tracer(SK_TRACER_NAME);
+	if (t == NULL)
+		return 0;
+
+	struct probe *p;
+	struct bpf_prog_info info;
+	uint32_t info_len;
+	int count = 0;
+
+	// 与uprobe的attach/detach互斥
+	pthread_mutex_lock(&t->mutex_probes_lock);
+	list_for_each_entry(p, &t->probes_head, list) {
+		if (count >= max)
+			break;
+		if (!p->installed)
+			continue;
+
+		struct attached_probe *a = &probes[count++];
+		memset(a, 0, sizeof(*a));
+		snprintf(a->name, sizeof(a->name), "%s", p->name);
+		a->type = p->type;
+		a->isret = p->isret;
+		if (p->type == UPROBE && p->private_data != NULL) {
+			struct symbol_uprobe *sym = p->private_data;
+			if (sym->binary_path)
+				snprintf(a->binary_path, sizeof(a->binary_path),
+					 "%s", sym->binary_path);
+			a->offset = sym->entry;
+			a->pid = sym->pid;
+		}
+
+		memset(&info, 0, sizeof(info));
+		info_len = sizeof(info);
+		if (bpf_obj_get_info_by_fd(p->prog_fd, &info, &info_len) == 0)
+			a->run_cnt = info.run_cnt;
+	}
+	pthread_mutex_unlock(&t->mutex_probes_lock);
+
+	return count;
+}
+
 /**
  * Register extra event handle.
  *
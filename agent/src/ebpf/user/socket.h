@@ -33,7 +33,9 @@ struct socket_bpf_data {
 	/* session info */
 	uint32_t process_id;	   // tgid in kernel struct task_struct
 	uint32_t thread_id;	   // pid in kernel struct task_struct, main thread iff pid==tgid
+	uint32_t peer_process_id;  // unix socket对端进程的tgid，其他类型socket为0
 	uint64_t coroutine_id;	   // CoroutineID, i.e., golang goroutine id
+	uint64_t cgroup_id;	   // 进程所在cgroup v2的ID，即cgroup目录的inode号
 	uint8_t  process_name[16]; // 进程名字
 
 	struct __tuple_t tuple;	   // Socket五元组信息
@@ -42,6 +44,7 @@ struct socket_bpf_data {
 				   // 存在一定误判性（例如标识为A协议但实际上是未知协议，或标识为多种协议），上层应用应继续深入判断
 	uint8_t msg_type;	   // 信息类型，值为MSG_UNKNOWN(0), MSG_REQUEST(1), MSG_RESPONSE(2)
 	bool need_reconfirm; 	   // 是否需要上层再确认 
+	bool unix_socket;	   // unix socket数据，tuple中为合成的127.0.0.1地址和端口
 
 	/* trace info */
 	uint64_t tcp_seq;		   // 收发cap_data数据时TCP协议栈将会用到的TCP SEQ，可用于关联eBPF DATA与网络中的TCP Packet
@@ -158,6 +161,21 @@ struct extra_event {
 };
 
 struct socket_trace_stats socket_tracer_stats(void);
+/**
+ * set_unix_socket_trace - 开启或关闭内核中unix socket数据的追踪
+ * @enabled 是否开启
+ *
+ * @return 0 if ok, not 0 on error
+ */
+int set_unix_socket_trace(bool enabled);
+/**
+ * socket_tracer_probes - 获取socket tracer当前已attach的probe
+ * @probes 输出数组
+ * @max 数组长度
+ *
+ * @return 获取的probe数量
+ */
+int socket_tracer_probes(struct attached_probe *probes, int max);
 int running_socket_tracer(l7_handle_fn handle,
 			  int thread_nr,
 			  uint32_t perf_pages_cnt,
@@ -58,6 +58,13 @@ static volatile int ready_flag_cpus[MAX_CPU_NR];
 
 static struct list_head extra_waiting_head;	// 额外事务处理的注册
 
+/*
+ * probe attach失败记录, 失败的probe会被释放，这里保留信息供上层上报
+ */
+static struct probe_attach_failure attach_failures[PROBE_ATTACH_FAILURES_MAX];
+static int attach_failures_count;
+static pthread_mutex_t attach_failures_lock = PTHREAD_MUTEX_INITIALIZER;
+
 #define EVENT_PERIOD_TIME	1	// 事件处理的周期时间，单位：秒
 static struct list_head period_events_head;	// 周期性事件处理的注册
 
@@ -528,6 +535,40 @@ static int tracepoint_detach(struct tracepoint *tp)
 	return ETR_OK;
 }
 
+static void record_attach_failure(struct probe *p, int error)
+{
+	pthread_mutex_lock(&attach_failures_lock);
+	if (attach_failures_count < PROBE_ATTACH_FAILURES_MAX) {
+		struct probe_attach_failure *f =
+		    &attach_failures[attach_failures_count++];
+		memset(f, 0, sizeof(*f));
+		snprintf(f->name, sizeof(f->name), "%s", p->name);
+		f->error = error;
+		if (p->type == UPROBE && p->private_data != NULL) {
+			struct symbol_uprobe *sym = p->private_data;
+			if (sym->binary_path)
+				snprintf(f->binary_path, sizeof(f->binary_path),
+					 "%s", sym->binary_path);
+			f->pid = sym->pid;
+		}
+	}
+	pthread_mutex_unlock(&attach_failures_lock);
+}
+
+int probe_attach_failures(struct probe_attach_failure *failures, int max)
+{
+	int count;
+	pthread_mutex_lock(&attach_failures_lock);
+	count = attach_failures_count < max ? attach_failures_count : max;
+	memcpy(failures, attach_failures, count * sizeof(*failures));
+	if (count < attach_failures_count)
+		memmove(attach_failures, attach_failures + count,
+			(attach_failures_count - count) * sizeof(*failures));
+	attach_failures_count -= count;
+	pthread_mutex_unlock(&attach_failures_lock);
+	return count;
+}
+
 int tracer_hooks_process(struct bpf_tracer *tracer, enum tracer_hook_type type,
 			 int *probes_count)
 {
@@ -579,6 +620,8 @@ int tracer_hooks_process(struct bpf_tracer *tracer, enum tracer_hook_type type,
 		}
 
 		if (error) {
+			if (type == HOOK_ATTACH)
+				record_attach_failure(p, error);
 			free_probe_from_tracer(p);
 			continue;
 		}
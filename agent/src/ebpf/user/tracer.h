@@ -461,4 +461,36 @@ void free_probe_from_tracer(struct probe *pb);
 int tracer_hooks_process(struct bpf_tracer *tracer,
 			 enum tracer_hook_type type, int *probes_count);
 int tracer_uprobes_update(struct bpf_tracer *tracer);
+
+#define PROBE_ATTACH_FAILURES_MAX 64
+
+// probe attach失败记录，由上层周期性取走并上报
+struct probe_attach_failure {
+	char name[PROBE_NAME_SZ];
+	char binary_path[256];	// 仅uprobe有效
+	int pid;			// 仅uprobe有效
+	int error;
+};
+
+/**
+ * probe_attach_failures - 取走attach失败记录
+ * @failures 输出数组
+ * @max 数组长度
+ *
+ * @return 取走的记录数量，缓存满后新的失败记录会被丢弃
+ */
+int probe_attach_failures(struct probe_attach_failure *failures, int max);
+
+#define ATTACHED_PROBES_MAX 4096
+
+// 已attach的probe，由上层按probe跟踪数据到达情况
+struct attached_probe {
+	char name[PROBE_NAME_SZ];
+	char binary_path[256];	// 仅uprobe有效
+	uint64_t offset;	// 仅uprobe有效，符号在文件中的偏移
+	uint64_t run_cnt;	// eBPF程序运行次数，需开启kernel.bpf_stats_enabled，否则为0
+	int pid;		// 仅uprobe有效
+	int type;		// KPROBE或UPROBE
+	bool isret;
+};
 #endif
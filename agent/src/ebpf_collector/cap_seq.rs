@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+
+const WINDOW_SIZE: u64 = u128::BITS as u64;
+// 跨度超过该值认为socket的cap_seq重新开始，不计入丢失
+const MAX_GAP: u64 = 1 << 16;
+
+// 记录最近WINDOW_SIZE个cap_seq是否收到，早于窗口的事件按重复丢弃
+struct CapSeqWindow {
+    base: u64,
+    // 第i位表示base + i已收到
+    seen: u128,
+}
+
+impl CapSeqWindow {
+    fn new(cap_seq: u64) -> Self {
+        Self {
+            base: cap_seq,
+            seen: 1,
+        }
+    }
+
+    // 重复时返回None，否则返回滑出窗口时发现的丢失数量
+    fn insert(&mut self, cap_seq: u64) -> Option<u64> {
+        if cap_seq < self.base {
+            if self.base - cap_seq >= MAX_GAP {
+                *self = Self::new(cap_seq);
+                return Some(0);
+            }
+            return None;
+        }
+        let offset = cap_seq - self.base;
+        if offset < WINDOW_SIZE {
+            let bit = 1u128 << offset;
+            if self.seen & bit != 0 {
+                return None;
+            }
+            self.seen |= bit;
+            return Some(0);
+        }
+        if offset - WINDOW_SIZE >= MAX_GAP {
+            *self = Self::new(cap_seq);
+            return Some(0);
+        }
+
+        let shift = offset - WINDOW_SIZE + 1;
+        let lost = if shift < WINDOW_SIZE {
+            let shifted_out = self.seen & ((1u128 << shift) - 1);
+            self.seen >>= shift;
+            shift - shifted_out.count_ones() as u64
+        } else {
+            let lost = WINDOW_SIZE - self.seen.count_ones() as u64 + shift - WINDOW_SIZE;
+            self.seen = 0;
+            lost
+        };
+        self.base += shift;
+        self.seen |= 1u128 << (cap_seq - self.base);
+        Some(lost)
+    }
+}
+
+// eBPF在重新attach或perf buffer丢失恢复后可能重复上送事件，按socket记录cap_seq窗口去重
+pub struct CapSeqFilter {
+    windows: LruCache<u64, CapSeqWindow>,
+}
+
+impl CapSeqFilter {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            windows: LruCache::new(capacity),
+        }
+    }
+
+    // 返回None表示重复事件需要丢弃，否则返回新发现的丢失事件数
+    pub fn check(&mut self, socket_id: u64, cap_seq: u64) -> Option<u64> {
+        match self.windows.get_mut(&socket_id) {
+            Some(window) => window.insert(cap_seq),
+            None => {
+                self.windows.put(socket_id, CapSeqWindow::new(cap_seq));
+                Some(0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_and_gap() {
+        let mut filter = CapSeqFilter::new(16);
+        assert_eq!(filter.check(1, 100), Some(0));
+        assert_eq!(filter.check(1, 102), Some(0));
+        // 乱序到达
+        assert_eq!(filter.check(1, 101), Some(0));
+        assert_eq!(filter.check(1, 101), None);
+        assert_eq!(filter.check(1, 100), None);
+        // 其他socket互不影响
+        assert_eq!(filter.check(2, 101), Some(0));
+
+        // 滑动到[103, 231)，移出的100~102均已收到
+        assert_eq!(filter.check(1, 230), Some(0));
+        // 滑动到[200, 328)，103~199均未收到
+        assert_eq!(filter.check(1, 327), Some(97));
+        assert_eq!(filter.check(1, 150), None);
+        // 跳到[400, 528)，200~399中只收到了230和327
+        assert_eq!(filter.check(1, 527), Some(198));
+
+        // 序号重新开始
+        assert_eq!(filter.check(1, 527 + MAX_GAP + WINDOW_SIZE), Some(0));
+        assert_eq!(filter.check(1, 3), Some(0));
+        assert_eq!(filter.check(1, 3), None);
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::utils::intern::intern;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// cgroup v1与v2混合挂载时v2位于unified目录下
+const CGROUP_UNIFIED_ROOT: &str = "/sys/fs/cgroup/unified";
+const CONTAINER_ID_LEN: usize = 64;
+// 未知cgroup id触发重新扫描的最小间隔
+const SCAN_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_SCAN_DEPTH: usize = 16;
+
+// 从cgroup路径中提取容器ID，支持以下形式：
+//   /kubepods/burstable/pod<uid>/<id>
+//   /kubepods.slice/.../docker-<id>.scope、cri-containerd-<id>.scope、crio-<id>.scope
+pub fn container_id_from_path(path: &str) -> Option<&str> {
+    path.rsplit('/').find_map(|name| {
+        let name = name.strip_suffix(".scope").unwrap_or(name);
+        let id = name.rsplit(|c: char| c == '-' || c == ':').next()?;
+        if id.len() == CONTAINER_ID_LEN && id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            Some(id)
+        } else {
+            None
+        }
+    })
+}
+
+// eBPF上送的cgroup id为cgroup v2目录的inode号，遍历cgroup目录建立inode到容器ID的映射
+// 仅在出现未知cgroup id时按间隔重新扫描，扫描结果整体替换，已删除的cgroup随之清除
+pub struct CgroupResolver {
+    root: PathBuf,
+    containers: HashMap<u64, Arc<str>>,
+    last_scan: Option<Instant>,
+}
+
+impl CgroupResolver {
+    pub fn new() -> Self {
+        let root = if Path::new(CGROUP_ROOT).join("cgroup.controllers").exists() {
+            CGROUP_ROOT
+        } else {
+            CGROUP_UNIFIED_ROOT
+        };
+        Self::with_root(root)
+    }
+
+    fn with_root<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            containers: HashMap::new(),
+            last_scan: None,
+        }
+    }
+
+    // 非容器进程返回None
+    pub fn resolve(&mut self, cgroup_id: u64) -> Option<Arc<str>> {
+        if cgroup_id == 0 {
+            return None;
+        }
+        if let Some(id) = self.containers.get(&cgroup_id) {
+            return Some(id.clone());
+        }
+        if self
+            .last_scan
+            .map(|t| t.elapsed() < SCAN_INTERVAL)
+            .unwrap_or(false)
+        {
+            return None;
+        }
+        self.scan();
+        self.containers.get(&cgroup_id).cloned()
+    }
+
+    fn scan(&mut self) {
+        self.last_scan = Some(Instant::now());
+        let mut containers = HashMap::new();
+        Self::walk(&self.root, "", 0, &mut containers);
+        debug!(
+            "scan cgroup {} found {} container cgroups",
+            self.root.display(),
+            containers.len()
+        );
+        self.containers = containers;
+    }
+
+    fn walk(dir: &Path, rel_path: &str, depth: usize, containers: &mut HashMap<u64, Arc<str>>) {
+        if depth > MAX_SCAN_DEPTH {
+            return;
+        }
+        let entries = match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let metadata = match entry.metadata() {
+                Ok(m) if m.is_dir() => m,
+                _ => continue,
+            };
+            let name = entry.file_name();
+            let rel_path = format!("{}/{}", rel_path, name.to_string_lossy());
+            // 容器的子cgroup同样归属于该容器
+            if let Some(id) = container_id_from_path(&rel_path) {
+                containers.insert(metadata.ino(), intern(id));
+            }
+            Self::walk(&entry.path(), &rel_path, depth + 1, containers);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn container_id() {
+        for path in [
+            format!("/kubepods/burstable/pod1234-5678/{}", ID),
+            format!(
+                "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod12_34.slice/cri-containerd-{}.scope",
+                ID
+            ),
+            format!("/system.slice/docker-{}.scope", ID),
+            format!("/kubepods.slice/crio-{}.scope/container", ID),
+            format!("/system.slice/containerd.service/kubepods-pod12.slice:cri-containerd:{}", ID),
+        ] {
+            assert_eq!(container_id_from_path(&path), Some(ID), "{}", path);
+        }
+        assert_eq!(container_id_from_path("/user.slice/user-1000.slice"), None);
+        assert_eq!(container_id_from_path("/"), None);
+    }
+
+    #[test]
+    fn resolve() {
+        let root = std::env::temp_dir().join(format!("cgroup-resolver-{}", std::process::id()));
+        let container = root.join("kubepods").join("besteffort").join(ID);
+        fs::create_dir_all(&container).unwrap();
+        let cgroup_id = fs::metadata(&container).unwrap().ino();
+        let host_id = fs::metadata(root.join("kubepods")).unwrap().ino();
+
+        let mut resolver = CgroupResolver::with_root(&root);
+        assert_eq!(resolver.resolve(cgroup_id).as_deref(), Some(ID));
+        assert_eq!(resolver.resolve(host_id), None);
+        assert_eq!(resolver.resolve(0), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
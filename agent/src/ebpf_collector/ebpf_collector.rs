@@ -16,31 +16,41 @@
 
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::fs;
+use std::os::raw::c_int;
 use std::sync::atomic::{AtomicI64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
+use arc_swap::access::Access;
 use log::{debug, error, info, warn};
 use lru::LruCache;
 
-use super::{Error, Result};
+use super::{
+    cap_seq::CapSeqFilter, cgroup::CgroupResolver, probe_health::ProbeHealth,
+    process_metrics::ProcessL7Metrics, Error, Result,
+};
 use crate::common::enums::{IpProtocol, PacketDirection};
 use crate::common::flow::L7Protocol;
 use crate::common::meta_packet::MetaPacket;
 use crate::config::handler::{EbpfConfig, LogParserAccess};
-use crate::debug::QueueDebugger;
+use crate::debug::{L7LogDebugger, QueueDebugger};
 use crate::ebpf;
+use crate::exception::ExceptionHandler;
 use crate::flow_generator::{
-    dns_check_protocol, dubbo_check_protocol, http1_check_protocol, http2_check_protocol,
-    kafka_check_protocol, mqtt_check_protocol, mysql_check_protocol, redis_check_protocol,
-    AppProtoHeadEnum, AppProtoLogsBaseInfo, AppProtoLogsData, AppProtoLogsInfoEnum, AppTable,
-    DnsLog, DubboLog, Error as LogError, HttpLog, KafkaLog, L7LogParse, LogMessageType, MqttLog,
-    MysqlLog, RedisLog, Result as LogResult,
+    l4_protocol_bitmap, AppProtoHeadEnum, AppProtoLogsBaseInfo, AppProtoLogsData,
+    AppProtoLogsInfoEnum, AppTable, ClickhouseLog, DetectCounter, DnsLog, DubboLog,
+    Error as LogError, HttpLog, KafkaLog, L7LogParse, L7ProtocolDetector, LogMessageType,
+    ModbusLog, MqttLog, MysqlLog, NtpLog, RadiusLog, RedisLog, Result as LogResult, S7Log, SnmpLog,
+    TdsLog, TlsLog,
 };
+use crate::platform::{get_pod_namespace, get_pod_uid};
 use crate::policy::PolicyGetter;
+use crate::rpc::get_timestamp;
 use crate::sender::SendItem;
 use crate::utils::{
+    drop_stats::{DropSource, DropStats},
     queue::{bounded_with_debug, DebugSender, Receiver},
     LeakyBucket,
 };
@@ -59,6 +69,11 @@ struct SessionAggr {
 
     log_rate: Arc<LeakyBucket>,
     output: DebugSender<SendItem>,
+    l7_log_debugger: Arc<L7LogDebugger>,
+    log_parser_config: LogParserAccess,
+
+    process_metrics: ProcessL7Metrics,
+    process_metrics_enabled: bool,
 }
 
 impl SessionAggr {
@@ -71,6 +86,9 @@ impl SessionAggr {
         counter: SyncEbpfCounter,
         log_rate: Arc<LeakyBucket>,
         output: DebugSender<SendItem>,
+        l7_log_debugger: Arc<L7LogDebugger>,
+        log_parser_config: LogParserAccess,
+        process_metrics: ProcessL7Metrics,
     ) -> Self {
         let slot_count = l7_log_session_timeout.as_secs() / Self::SLOT_WIDTH;
         let slot_count = slot_count.min(16).max(1) as usize;
@@ -82,6 +100,10 @@ impl SessionAggr {
             last_flush_time: 0,
             counter,
             log_rate,
+            l7_log_debugger,
+            log_parser_config,
+            process_metrics,
+            process_metrics_enabled: false,
             maps: [
                 Some(HashMap::new()),
                 Some(HashMap::new()),
@@ -103,12 +125,20 @@ impl SessionAggr {
         }
     }
 
-    fn send(&self, log: AppProtoLogsData) {
+    fn send(&mut self, mut log: AppProtoLogsData) {
         debug!("ebpf_collector out: {}", log);
+        // 指标统计不受日志限速影响
+        if self.process_metrics_enabled {
+            self.process_metrics.add(&log);
+        }
         if !self.log_rate.acquire(1) {
             self.counter.counter().throttle_drop += 1;
             return;
         }
+        // 与流量路径一致，发送前截断较长的字段
+        log.special_info
+            .truncate(&self.log_parser_config.load().l7_log_truncate);
+        self.l7_log_debugger.record(&log);
         let _ = self.output.send(SendItem::L7FlowLog(Box::new(log)));
         self.counter.counter().tx += 1;
     }
@@ -222,7 +252,7 @@ impl SessionAggr {
         self.maps[slot_index as usize].replace(map);
     }
 
-    fn handle(&mut self, log: AppProtoLogsData) {
+    fn handle(&mut self, log: AppProtoLogsData, key: u64) {
         let solt_time = log.base_info.start_time.as_secs();
         if solt_time < self.start_time {
             self.send(log);
@@ -237,7 +267,6 @@ impl SessionAggr {
             slot_index = self.flush(slot_index - self.slot_count + 1);
         }
 
-        let key = log.ebpf_flow_session_id();
         self.slot_handle(log, slot_index as usize, key, 1);
     }
 }
@@ -260,24 +289,8 @@ struct FlowItem {
     is_skip: bool,
 
     parser: Option<Box<dyn L7LogParse>>,
-}
-
-impl From<IpProtocol> for u128 {
-    fn from(protocol: IpProtocol) -> Self {
-        let bitmap = if protocol == IpProtocol::Tcp {
-            1 << u8::from(L7Protocol::Http1)
-                | 1 << u8::from(L7Protocol::Http2)
-                | 1 << u8::from(L7Protocol::Dns)
-                | 1 << u8::from(L7Protocol::Mysql)
-                | 1 << u8::from(L7Protocol::Redis)
-                | 1 << u8::from(L7Protocol::Dubbo)
-                | 1 << u8::from(L7Protocol::Kafka)
-                | 1 << u8::from(L7Protocol::Mqtt)
-        } else {
-            1 << u8::from(L7Protocol::Dns)
-        };
-        return bitmap;
-    }
+    // HTTP1最近一个未匹配响应的请求的cap_seq
+    last_request_cap_seq: Option<u64>,
 }
 
 impl FlowItem {
@@ -290,15 +303,29 @@ impl FlowItem {
         log_parser_config: &LogParserAccess,
     ) -> Option<Box<dyn L7LogParse>> {
         match protocol {
-            L7Protocol::Dns => Some(Box::from(DnsLog::default())),
+            L7Protocol::Dns => Some(Box::from(DnsLog::new(log_parser_config))),
             L7Protocol::Http1 => Some(Box::from(HttpLog::new(log_parser_config, false))),
-            L7Protocol::Http2 => Some(Box::from(HttpLog::new(log_parser_config, false))),
+            L7Protocol::Http2 => {
+                let mut http = HttpLog::new(log_parser_config, false);
+                // eBPF可能从长连接的中途开始采集，需要重新同步帧边界
+                http.set_frame_resync(true);
+                Some(Box::from(http))
+            }
             L7Protocol::Http1TLS => Some(Box::from(HttpLog::new(log_parser_config, true))),
-            L7Protocol::Mysql => Some(Box::from(MysqlLog::default())),
+            L7Protocol::Mysql => Some(Box::from(MysqlLog::new(log_parser_config))),
             L7Protocol::Redis => Some(Box::from(RedisLog::default())),
             L7Protocol::Kafka => Some(Box::from(KafkaLog::default())),
             L7Protocol::Dubbo => Some(Box::from(DubboLog::new(log_parser_config))),
             L7Protocol::Mqtt => Some(Box::from(MqttLog::default())),
+            L7Protocol::Radius => Some(Box::from(RadiusLog::new(log_parser_config))),
+            L7Protocol::Modbus => Some(Box::from(ModbusLog::default())),
+            L7Protocol::S7 => Some(Box::from(S7Log::default())),
+            L7Protocol::Snmp => Some(Box::from(SnmpLog::default())),
+            L7Protocol::Tds => Some(Box::from(TdsLog::default())),
+            L7Protocol::Clickhouse => Some(Box::from(ClickhouseLog::default())),
+            L7Protocol::Tls => Some(Box::from(TlsLog::default())),
+            // eBPF数据没有响应的采集时间，不计算时钟偏差
+            L7Protocol::Ntp => Some(Box::from(NtpLog::default())),
             _ => None,
         }
     }
@@ -315,7 +342,7 @@ impl FlowItem {
         let l7_protocol = app_table.get_protocol_from_ebpf(packet, local_epc, remote_epc);
         let is_from_app = l7_protocol.is_some();
         let (l7_protocol, server_port) = l7_protocol.unwrap_or((L7Protocol::Unknown, 0));
-        let mut protocol_bitmap = u128::from(l4_protocol);
+        let mut protocol_bitmap = l4_protocol_bitmap(l4_protocol);
         if packet.l7_protocol_from_ebpf == L7Protocol::Http1TLS {
             protocol_bitmap |= 1 << u8::from(L7Protocol::Http1TLS);
             protocol_bitmap &= !(1 << u8::from(L7Protocol::Http1));
@@ -334,21 +361,7 @@ impl FlowItem {
             protocol_bitmap,
             protocol_bitmap_image: protocol_bitmap,
             parser: Self::get_parser(l7_protocol, log_parser_config),
-        }
-    }
-
-    fn _check(&mut self, protocol: L7Protocol, packet: &MetaPacket) -> bool {
-        match protocol {
-            L7Protocol::Dns => dns_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Dubbo => dubbo_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Kafka => kafka_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Mqtt => mqtt_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Mysql => mysql_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Redis => redis_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Http1 => http1_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Http2 => http2_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Http1TLS => http1_check_protocol(&mut self.protocol_bitmap, packet),
-            _ => false,
+            last_request_cap_seq: None,
         }
     }
 
@@ -358,33 +371,21 @@ impl FlowItem {
         local_epc: i32,
         app_table: &mut AppTable,
         log_parser_config: &LogParserAccess,
+        detector: &L7ProtocolDetector,
     ) -> LogResult<AppProtoHeadEnum> {
         if self.is_skip {
             return Err(LogError::L7ProtocolCheckLimit);
         }
 
-        let protocols = [
-            L7Protocol::Http1TLS,
-            L7Protocol::Http1,
-            L7Protocol::Http2,
-            L7Protocol::Dubbo,
-            L7Protocol::Mysql,
-            L7Protocol::Redis,
-            L7Protocol::Kafka,
-            L7Protocol::Mqtt,
-            L7Protocol::Dns,
-        ];
-
-        for i in protocols {
-            if self.protocol_bitmap & 1 << u8::from(i) == 0 {
-                continue;
-            }
-            if self._check(i, packet) {
-                self.l7_protocol = i;
-                self.server_port = packet.lookup_key.dst_port;
-                self.parser = Self::get_parser(i, log_parser_config);
-                return self._parse(packet, local_epc, app_table);
-            }
+        let mut counter = DetectCounter::default();
+        if let Some(protocol) = detector.detect(&mut self.protocol_bitmap, packet, &mut counter) {
+            self.l7_protocol = protocol;
+            self.server_port = packet.lookup_key.dst_port;
+            self.parser = Self::get_parser(protocol, log_parser_config);
+            return self._parse(packet, local_epc, app_table);
+        }
+        if counter.checked == 0 {
+            return Err(LogError::L7ProtocolUnknown);
         }
         self.is_skip = app_table.set_protocol_from_ebpf(
             packet,
@@ -449,10 +450,11 @@ impl FlowItem {
         self.protocol_bitmap = if self.l4_protocol == l4_protocol {
             self.protocol_bitmap_image
         } else {
-            u128::from(l4_protocol)
+            l4_protocol_bitmap(l4_protocol)
         };
         self.l4_protocol = l4_protocol;
         self.parser = None;
+        self.last_request_cap_seq = None;
     }
 
     fn parse(
@@ -461,6 +463,7 @@ impl FlowItem {
         local_epc: i32,
         app_table: &mut AppTable,
         log_parser_config: &LogParserAccess,
+        detector: &L7ProtocolDetector,
     ) -> LogResult<AppProtoHeadEnum> {
         let time_in_sec = packet.lookup_key.timestamp.as_secs();
         if self.last_packet + Self::FLOW_ITEM_TIMEOUT < time_in_sec {
@@ -476,17 +479,41 @@ impl FlowItem {
             return Err(LogError::L7ProtocolUnknown);
         }
 
-        if packet.l4_payload_len() <= 1 {
+        // 与流量路径一致，过短的载荷不参与识别
+        if packet.l4_payload_len() < detector.min_payload_len() {
             return Err(LogError::L7ProtocolUnknown);
         }
 
-        return self.check(packet, local_epc, app_table, log_parser_config);
+        return self.check(packet, local_epc, app_table, log_parser_config, detector);
     }
 
     fn get_info(&mut self) -> AppProtoLogsInfoEnum {
         self.parser.as_ref().unwrap().info()
     }
 
+    // HTTP1没有请求ID，默认依赖请求和响应的cap_seq相邻来聚合
+    // 请求被拆分为多次系统调用（如TLS的多次SSL_write）时cap_seq不再相邻，响应使用该socket上最近一个请求的cap_seq聚合
+    fn session_key(&mut self, log: &AppProtoLogsData, cap_seq: u64) -> u64 {
+        if !matches!(
+            log.base_info.head.proto,
+            L7Protocol::Http1 | L7Protocol::Http1TLS
+        ) {
+            return log.ebpf_flow_session_id();
+        }
+        match log.base_info.head.msg_type {
+            LogMessageType::Request => self.last_request_cap_seq = Some(cap_seq),
+            LogMessageType::Response => {
+                if let Some(request_cap_seq) =
+                    self.last_request_cap_seq.take().filter(|s| *s < cap_seq)
+                {
+                    return log.ebpf_flow_session_id_by_request(request_cap_seq);
+                }
+            }
+            _ => (),
+        }
+        log.ebpf_flow_session_id()
+    }
+
     fn lookup_epc(&mut self, packet: &MetaPacket, mut policy_getter: PolicyGetter, local_epc: i32) {
         let key = &packet.lookup_key;
         if key.timestamp.as_secs() > self.last_policy + Self::POLICY_INTERVAL {
@@ -505,14 +532,15 @@ impl FlowItem {
         policy_getter: PolicyGetter,
         app_table: &mut AppTable,
         log_parser_config: &LogParserAccess,
+        detector: &L7ProtocolDetector,
         local_epc: i32,
         vtap_id: u16,
-    ) -> Option<Vec<AppProtoLogsData>> {
+    ) -> Option<Vec<(AppProtoLogsData, u64)>> {
         // 策略EPC
         self.lookup_epc(packet, policy_getter, local_epc);
         // 应用解析, 获取日志信息
-        let result = self
-            .parse(packet, local_epc, app_table, log_parser_config)
+        let logs: Vec<AppProtoLogsData> = self
+            .parse(packet, local_epc, app_table, log_parser_config, detector)
             .ok()?
             .into_iter()
             .zip(self.get_info().into_iter())
@@ -530,15 +558,28 @@ impl FlowItem {
                 }
             })
             .collect();
+        let result = logs
+            .into_iter()
+            .map(|log| {
+                let key = self.session_key(&log, packet.cap_seq);
+                (log, key)
+            })
+            .collect();
         Some(result)
     }
 }
 
+#[derive(Default)]
 pub struct EbpfCounter {
     rx: u64,
     tx: u64,
     unknown_protocol: u64,
     throttle_drop: u64,
+    cap_seq_duplicate: u64,
+    cap_seq_gap: u64,
+    probe_attach_failed: u64,
+    probe_stalled: u64,
+    probe_reattach: u64,
 }
 
 impl EbpfCounter {
@@ -547,6 +588,11 @@ impl EbpfCounter {
         self.tx = 0;
         self.unknown_protocol = 0;
         self.throttle_drop = 0;
+        self.cap_seq_duplicate = 0;
+        self.cap_seq_gap = 0;
+        self.probe_attach_failed = 0;
+        self.probe_stalled = 0;
+        self.probe_reattach = 0;
     }
 }
 
@@ -566,11 +612,18 @@ unsafe impl Sync for SyncEbpfCounter {}
 
 impl OwnedCountable for SyncEbpfCounter {
     fn get_counters(&self) -> Vec<Counter> {
-        let (rx, tx, unknow, drop) = (
+        let (rx, tx, unknow, drop, duplicate, gap) = (
             self.counter().rx,
             self.counter().tx,
             self.counter().unknown_protocol,
             self.counter().throttle_drop,
+            self.counter().cap_seq_duplicate,
+            self.counter().cap_seq_gap,
+        );
+        let (attach_failed, stalled, reattach) = (
+            self.counter().probe_attach_failed,
+            self.counter().probe_stalled,
+            self.counter().probe_reattach,
         );
         self.counter().reset();
 
@@ -597,6 +650,31 @@ impl OwnedCountable for SyncEbpfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(drop),
             ),
+            (
+                "cap_seq_duplicate",
+                CounterType::Counted,
+                CounterValue::Unsigned(duplicate),
+            ),
+            (
+                "cap_seq_gap",
+                CounterType::Counted,
+                CounterValue::Unsigned(gap),
+            ),
+            (
+                "probe_attach_failed",
+                CounterType::Counted,
+                CounterValue::Unsigned(attach_failed),
+            ),
+            (
+                "probe_stalled",
+                CounterType::Counted,
+                CounterValue::Unsigned(stalled),
+            ),
+            (
+                "probe_reattach",
+                CounterType::Counted,
+                CounterValue::Unsigned(reattach),
+            ),
             (
                 "perf_pages_count",
                 CounterType::Counted,
@@ -706,9 +784,29 @@ struct EbpfRunner {
     l7_log_dynamic_is_updated: bool,
 
     config: EbpfConfig,
+    // 进程是否属于capture-namespaces限定的命名空间，以(pid, 进程启动时间)为key避免PID复用后沿用旧结果
+    pid_allowed: LruCache<(u32, u64), bool>,
+    // 进程启动时间及读取时的包时间戳，避免每个包都读取/proc
+    pid_start_time: LruCache<u32, (u64, Duration)>,
+    cap_seq_filter: CapSeqFilter,
+    cgroup_resolver: CgroupResolver,
+    probe_health: ProbeHealth,
 
     log_rate: Arc<LeakyBucket>,
     output: DebugSender<SendItem>,
+    l7_log_debugger: Arc<L7LogDebugger>,
+    metrics_output: DebugSender<SendItem>,
+}
+
+// /proc/<pid>/stat第22个字段为进程启动时间(clock ticks)，进程名可能包含空格和括号，从最后一个')'之后解析
+fn parse_start_time(stat: &str) -> Option<u64> {
+    let fields = &stat[stat.rfind(')')? + 1..];
+    // ')'之后从第3个字段state开始
+    fields.split_whitespace().nth(22 - 3)?.parse().ok()
+}
+
+fn process_start_time(pid: u32) -> Option<u64> {
+    parse_start_time(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
 }
 
 fn lookup_epc(packet: &MetaPacket, mut policy_getter: PolicyGetter, local_epc: i32) -> i32 {
@@ -722,16 +820,82 @@ fn lookup_epc(packet: &MetaPacket, mut policy_getter: PolicyGetter, local_epc: i
 
 impl EbpfRunner {
     const FLOW_MAP_SIZE: usize = 1 << 14;
+    const PID_CACHE_SIZE: usize = 1 << 12;
+    // PID复用后最多在该时间内沿用旧进程的结果
+    const PID_START_TIME_TTL: Duration = Duration::from_secs(1);
 
     fn on_config_change(&mut self, config: &EbpfConfig) {
         info!(
             "ebpf collector config change from {:#?} to {:#?}.",
             self.config, config
         );
+        if self.config.unix_socket_enabled != config.unix_socket_enabled {
+            EbpfCollector::set_unix_socket_trace(config.unix_socket_enabled);
+        }
         self.config = config.clone();
+        self.pid_allowed.clear();
+        self.probe_health
+            .set_stall_timeout(config.probe_stall_timeout);
         unsafe { CAPTURE_SIZE = config.l7_log_packet_size }
     }
 
+    fn is_pid_allowed(&mut self, pid: u32, now: Duration) -> bool {
+        let start_time = match self.pid_start_time.get(&pid) {
+            Some((t, checked)) if now < *checked + Self::PID_START_TIME_TTL => *t,
+            _ => match process_start_time(pid) {
+                Some(t) => {
+                    self.pid_start_time.put(pid, (t, now));
+                    t
+                }
+                // 进程已退出时无法判断所属命名空间
+                None => return false,
+            },
+        };
+        if let Some(allowed) = self.pid_allowed.get(&(pid, start_time)) {
+            return *allowed;
+        }
+        let allowed = match get_pod_namespace(pid) {
+            Some(ns) => self.config.capture_namespaces.contains(&ns),
+            // 属于POD但命名空间暂未解析到时不缓存，待poller刷新后重新判断
+            None if get_pod_uid(pid).is_some() => return false,
+            None => false,
+        };
+        self.pid_allowed.put((pid, start_time), allowed);
+        allowed
+    }
+
+    fn check_probe_health(&mut self, counter: &mut EbpfCounter, now: Duration) {
+        for pid in EXEC_PIDS.lock().unwrap().drain(..) {
+            self.probe_health.on_exec(pid, now);
+        }
+        if now < self.probe_health.next_check() {
+            return;
+        }
+        let mut attached = vec![ebpf::ATTACHED_PROBE::default(); ebpf::ATTACHED_PROBES_MAX];
+        let n =
+            unsafe { ebpf::socket_tracer_probes(attached.as_mut_ptr(), attached.len() as c_int) };
+        let result = match self.probe_health.check(now, &attached[..n.max(0) as usize]) {
+            Some(r) => r,
+            None => return,
+        };
+        let mut failures =
+            vec![ebpf::PROBE_ATTACH_FAILURE::default(); ebpf::PROBE_ATTACH_FAILURES_MAX];
+        let n =
+            unsafe { ebpf::probe_attach_failures(failures.as_mut_ptr(), failures.len() as c_int) };
+        counter.probe_attach_failed += self
+            .probe_health
+            .report_attach_failures(&failures[..n.max(0) as usize]);
+        counter.probe_stalled += result.stalled;
+        if result.resync {
+            info!("ebpf uprobes stalled, resync golang processes");
+            if unsafe { ebpf::go_probes_resync() } == 0 {
+                counter.probe_reattach += 1;
+            } else {
+                warn!("ebpf go_probes_resync() failed");
+            }
+        }
+    }
+
     fn l7_log_dynamic_config_updated(&mut self) {
         debug!("ebpf l7 log config updated.");
         self.l7_log_dynamic_is_updated = true;
@@ -743,11 +907,21 @@ impl EbpfRunner {
             sync_counter,
             self.log_rate.clone(),
             self.output.clone(),
+            self.l7_log_debugger.clone(),
+            self.log_parser_config.clone(),
+            ProcessL7Metrics::new(
+                self.config.l7_log_session_timeout,
+                self.metrics_output.clone(),
+            ),
         );
         let mut flow_map: LruCache<u128, FlowItem> = LruCache::new(Self::FLOW_MAP_SIZE);
 
         while unsafe { SWITCH } {
             let mut packet = self.receiver.recv(Some(Duration::from_millis(1)));
+            let now = get_timestamp(self.time_diff.load(Ordering::Relaxed));
+            aggr.process_metrics_enabled = self.config.l7_metrics_enabled;
+            aggr.process_metrics.flush(now, self.config.vtap_id);
+            self.check_probe_health(sync_counter.counter(), now);
             if packet.is_err() {
                 continue;
             }
@@ -761,6 +935,33 @@ impl EbpfRunner {
             sync_counter.counter().rx += 1;
 
             let packet = packet.as_mut().unwrap();
+            // golang uprobe的数据由内核标记为HTTP1 TLS
+            self.probe_health.on_event(
+                packet.process_id,
+                packet.l7_protocol_from_ebpf == L7Protocol::Http1TLS,
+                now,
+            );
+            // 内核按配置过滤unix socket，关闭配置前已上送的数据在此丢弃
+            if packet.unix_socket && !self.config.unix_socket_enabled {
+                continue;
+            }
+            if !self.config.capture_namespaces.is_empty()
+                && !self.is_pid_allowed(packet.process_id, packet.lookup_key.timestamp)
+            {
+                continue;
+            }
+            match self.cap_seq_filter.check(packet.socket_id, packet.cap_seq) {
+                Some(lost) => sync_counter.counter().cap_seq_gap += lost,
+                None => {
+                    sync_counter.counter().cap_seq_duplicate += 1;
+                    continue;
+                }
+            }
+            // 按进程的cgroup关联容器，平台数据中没有该容器时pod_id为0
+            if let Some(container_id) = self.cgroup_resolver.resolve(packet.cgroup_id) {
+                packet.pod_id = self.policy_getter.lookup_pod_by_container(&container_id);
+                packet.container_id = container_id;
+            }
             packet.timestamp_adjust(self.time_diff.load(Ordering::Relaxed));
             packet.set_loopback_mac(self.config.ctrl_mac);
 
@@ -794,12 +995,13 @@ impl EbpfRunner {
                     self.policy_getter,
                     &mut self.app_table,
                     &self.log_parser_config,
+                    &self.config.l7_protocol_detector,
                     self.config.epc_id as i32,
                     self.config.vtap_id,
                 ) {
-                    for d in data {
+                    for (d, key) in data {
                         // 应用日志聚合
-                        aggr.handle(d);
+                        aggr.handle(d, key);
                     }
                 }
                 Some(())
@@ -821,16 +1023,40 @@ impl SyncEbpfRunner {
     }
 }
 
+// eBPF统计为tracer启动以来的累计值，记录上次读取的值计算增量
+struct EbpfDropSource {
+    drop_stats: DropStats,
+    // (events, lost)
+    last: Mutex<(u64, u64)>,
+}
+
+impl DropSource for EbpfDropSource {
+    fn poll(&self) {
+        let stats = unsafe { ebpf::socket_tracer_stats() };
+        // perf buffer丢失的数据用户态未收到，也计入总数
+        let events = stats.user_enqueue_count + stats.kern_lost;
+        let lost = stats.kern_lost + stats.user_enqueue_lost;
+        let mut last = self.last.lock().unwrap();
+        if events >= last.0 && lost >= last.1 {
+            self.drop_stats.record_ebpf(events - last.0, lost - last.1);
+        }
+        *last = (events, lost);
+    }
+}
+
 pub struct EbpfCollector {
     thread_runner: EbpfRunner,
     thread_handle: Option<JoinHandle<()>>,
 
     counter: EbpfCounter,
+    drop_source: Option<Arc<EbpfDropSource>>,
 }
 
 static mut SWITCH: bool = false;
 static mut SENDER: Option<DebugSender<Box<MetaPacket>>> = None;
 static mut CAPTURE_SIZE: usize = ebpf::CAP_LEN_MAX as usize;
+// C库回调线程中收到的exec事件，由EbpfRunner取走用于检查重启的进程是否重新attach
+static EXEC_PIDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
 
 impl EbpfCollector {
     extern "C" fn ebpf_callback(sd: *mut ebpf::SK_BPF_DATA) {
@@ -850,6 +1076,15 @@ impl EbpfCollector {
         }
     }
 
+    extern "C" fn process_event_callback(pe: *mut ebpf::PROCESS_EVENT) {
+        let pid = unsafe { (*pe).pid };
+        let mut pids = EXEC_PIDS.lock().unwrap();
+        // 没有消费者时避免无限增长
+        if pids.len() < EbpfRunner::PID_CACHE_SIZE {
+            pids.push(pid);
+        }
+    }
+
     fn ebpf_init(config: &EbpfConfig, sender: DebugSender<Box<MetaPacket<'static>>>) -> Result<()> {
         // ebpf内核模块初始化
         unsafe {
@@ -868,6 +1103,12 @@ impl EbpfCollector {
                 return Err(Error::EbpfInitError);
             }
 
+            if ebpf::register_event_handle(ebpf::EVENT_TYPE_PROC_EXEC, Self::process_event_callback)
+                != 0
+            {
+                warn!("ebpf register_event_handle() failed, restarted processes are not tracked");
+            }
+
             if ebpf::running_socket_tracer(
                 Self::ebpf_callback, /* 回调接口 rust -> C */
                 1,                   /* 工作线程数，是指用户态有多少线程参与数据处理 */
@@ -881,6 +1122,7 @@ impl EbpfCollector {
                 return Err(Error::EbpfRunningError);
             }
             ebpf::bpf_tracer_finish();
+            Self::set_unix_socket_trace(config.unix_socket_enabled);
         }
         // ebpf和ebpf collector通信配置初始化
         unsafe {
@@ -892,6 +1134,12 @@ impl EbpfCollector {
         Ok(())
     }
 
+    fn set_unix_socket_trace(enabled: bool) {
+        if unsafe { ebpf::set_unix_socket_trace(enabled) } != 0 {
+            warn!("ebpf set_unix_socket_trace({}) failed", enabled);
+        }
+    }
+
     fn ebpf_start() {
         debug!("ebpf collector starting ebpf-kernel.");
         unsafe {
@@ -927,7 +1175,10 @@ impl EbpfCollector {
         policy_getter: PolicyGetter,
         l7_log_rate: Arc<LeakyBucket>,
         output: DebugSender<SendItem>,
+        metrics_output: DebugSender<SendItem>,
         queue_debugger: &QueueDebugger,
+        l7_log_debugger: Arc<L7LogDebugger>,
+        exception_handler: ExceptionHandler,
     ) -> Result<Box<Self>> {
         info!("ebpf collector init...");
         let (sender, receiver, _) =
@@ -945,9 +1196,16 @@ impl EbpfCollector {
                 ),
                 policy_getter,
                 config: config.clone(),
+                pid_allowed: LruCache::new(EbpfRunner::PID_CACHE_SIZE),
+                pid_start_time: LruCache::new(EbpfRunner::PID_CACHE_SIZE),
+                cap_seq_filter: CapSeqFilter::new(EbpfRunner::FLOW_MAP_SIZE),
+                cgroup_resolver: CgroupResolver::new(),
+                probe_health: ProbeHealth::new(config.probe_stall_timeout, exception_handler),
                 log_parser_config,
                 output,
                 log_rate: l7_log_rate,
+                l7_log_debugger,
+                metrics_output,
                 l7_log_dynamic_is_updated: false,
             },
             thread_handle: None,
@@ -956,10 +1214,25 @@ impl EbpfCollector {
                 tx: 0,
                 unknown_protocol: 0,
                 throttle_drop: 0,
+                cap_seq_duplicate: 0,
+                cap_seq_gap: 0,
+                probe_attach_failed: 0,
+                probe_stalled: 0,
+                probe_reattach: 0,
             },
+            drop_source: None,
         }));
     }
 
+    pub fn set_drop_stats(&mut self, drop_stats: DropStats) {
+        let source = Arc::new(EbpfDropSource {
+            drop_stats: drop_stats.clone(),
+            last: Mutex::new((0, 0)),
+        });
+        drop_stats.register(Arc::downgrade(&source) as Weak<dyn DropSource>);
+        self.drop_source = Some(source);
+    }
+
     pub fn l7_log_dynamic_config_updated(&mut self) {
         self.thread_runner.l7_log_dynamic_config_updated();
     }
@@ -1032,6 +1305,72 @@ impl Drop for EbpfCollector {
 
 #[cfg(test)]
 mod tests {
+    use arc_swap::{access::Map, ArcSwap};
+
+    use super::*;
+
+    use crate::config::handler::{LogParserConfig, ModuleConfig};
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsInfo, HttpInfo};
+    use crate::utils::queue::bounded_with_debug;
+
     #[test]
     fn test_ebpf_collector() {}
+
+    #[test]
+    fn start_time() {
+        let stat = "1234 (my (proc) x) S 1 1234 1234 0 -1 4194560 1000 0 0 0 10 5 0 0 20 0 4 0 987654 123456789 2000 18446744073709551615";
+        assert_eq!(parse_start_time(stat), Some(987654));
+        assert_eq!(parse_start_time("1234 (proc) S 1 2"), None);
+        assert_eq!(parse_start_time(""), None);
+    }
+
+    #[test]
+    fn truncate_before_send() {
+        let mut module_config = ModuleConfig::default();
+        module_config.log_parser.l7_log_truncate.http_url = 8;
+        let config: LogParserAccess = Map::new(
+            Arc::new(ArcSwap::from_pointee(module_config)),
+            |config| -> &LogParserConfig { &config.log_parser },
+        );
+        let queue_debugger = QueueDebugger::new();
+        let (output, receiver, _) = bounded_with_debug(16, "", &queue_debugger);
+        let (metrics_output, _, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut counter = EbpfCounter::default();
+        let mut aggr = SessionAggr::new(
+            Duration::from_secs(120),
+            SyncEbpfCounter {
+                counter: &mut counter,
+            },
+            Arc::new(LeakyBucket::new(None)),
+            output,
+            Arc::new(L7LogDebugger::new(0)),
+            config,
+            ProcessL7Metrics::new(Duration::from_secs(120), metrics_output),
+        );
+
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        let head = AppProtoHead {
+            proto: L7Protocol::Http1,
+            msg_type: LogMessageType::Request,
+            ..Default::default()
+        };
+        let info = HttpInfo {
+            path: "/api/v1/users/1024".to_string(),
+            ..Default::default()
+        };
+        aggr.send(AppProtoLogsData::new(
+            AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0),
+            AppProtoLogsInfo::HttpV1(info),
+        ));
+
+        match receiver.recv(Some(Duration::from_millis(10))) {
+            Ok(SendItem::L7FlowLog(log)) => match log.special_info.into_inner() {
+                AppProtoLogsInfo::HttpV1(info) => assert_eq!(info.path, "/api/v1/"),
+                _ => panic!("unexpected info"),
+            },
+            _ => panic!("no log sent"),
+        }
+        assert_eq!(counter.tx, 1);
+    }
 }
@@ -14,7 +14,11 @@
  * limitations under the License.
  */
 
+mod cap_seq;
+mod cgroup;
 pub mod ebpf_collector;
+mod probe_health;
+mod process_metrics;
 
 use thiserror::Error;
 
@@ -0,0 +1,552 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::ebpf::{ATTACHED_PROBE, PROBE_ATTACH_FAILURE, UPROBE};
+use crate::exception::ExceptionHandler;
+use crate::proto::trident::Exception;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// C库在进程exec后延迟120秒才attach uprobe，超过该时间仍未attach认为需要重新attach
+const EXEC_ATTACH_DELAY: Duration = Duration::from_secs(150);
+
+// uprobe按所在文件和偏移区分，同一文件的多个进程共用，进程重启后仍是同一个probe
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ProbeKey {
+    Kprobe(String),
+    Uprobe { binary_path: String, offset: u64 },
+}
+
+struct ProbeState {
+    name: String,
+    pids: HashSet<u32>,
+    run_cnt: u64,
+    // 最近一次确认probe触发的时间
+    last_hit: Duration,
+    // uprobe所在进程最近一次有系统调用数据的时间
+    last_syscall: Duration,
+    // 最近一次仍处于attach状态的时间
+    last_attached: Duration,
+    stalled: bool,
+}
+
+impl ProbeState {
+    fn new(name: String, now: Duration) -> Self {
+        Self {
+            name,
+            pids: HashSet::new(),
+            run_cnt: 0,
+            last_hit: now,
+            last_syscall: Duration::ZERO,
+            last_attached: now,
+            stalled: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CheckResult {
+    // 本次新发现停滞的probe数
+    pub stalled: u64,
+    // 是否需要重新attach uprobe
+    pub resync: bool,
+}
+
+// 按已attach的probe跟踪数据到达情况:
+// 1. uprobe按文件和偏移跟踪，golang uprobe的数据按进程归属到该进程上的uprobe，
+//    进程仍有系统调用数据但uprobe数据消失时认为停滞，触发重新扫描进程并attach
+// 2. kprobe按eBPF程序的运行次数跟踪，需开启kernel.bpf_stats_enabled，
+//    其他probe仍有数据而该kprobe的运行次数不再增长时告警
+// 3. 进程exec后，若文件上曾attach过uprobe而该进程一直没有attach，说明重启的进程
+//    未重新attach，触发重新扫描
+// 4. 所有数据都停止时只打印告警，空闲的主机同样没有数据，不做处理
+// 5. probe attach失败的记录由C库缓存，这里取走后逐条告警并上报异常
+pub struct ProbeHealth {
+    stall_timeout: Duration,
+    syscall_events: u64,
+    last_event: Duration,
+    idle_warned: bool,
+    probes: HashMap<ProbeKey, ProbeState>,
+    // 进程上attach的uprobe
+    pid_probes: HashMap<u32, Vec<ProbeKey>>,
+    // 等待C库attach的新进程
+    exec_pids: Vec<(u32, Duration)>,
+    proc_root: PathBuf,
+    last_check: Duration,
+    last_resync: Duration,
+    exception_handler: ExceptionHandler,
+}
+
+impl ProbeHealth {
+    pub fn new(stall_timeout: Duration, exception_handler: ExceptionHandler) -> Self {
+        Self {
+            stall_timeout,
+            syscall_events: 0,
+            last_event: Duration::ZERO,
+            idle_warned: false,
+            probes: HashMap::new(),
+            pid_probes: HashMap::new(),
+            exec_pids: vec![],
+            proc_root: PathBuf::from("/proc"),
+            last_check: Duration::ZERO,
+            last_resync: Duration::ZERO,
+            exception_handler,
+        }
+    }
+
+    pub fn set_stall_timeout(&mut self, stall_timeout: Duration) {
+        if self.stall_timeout != stall_timeout {
+            info!(
+                "ebpf probe stall timeout changed from {:?} to {:?}",
+                self.stall_timeout, stall_timeout
+            );
+            self.stall_timeout = stall_timeout;
+        }
+    }
+
+    pub fn on_event(&mut self, pid: u32, from_uprobe: bool, now: Duration) {
+        self.last_event = now;
+        if !from_uprobe {
+            self.syscall_events += 1;
+        }
+        let keys = match self.pid_probes.get(&pid) {
+            Some(keys) => keys,
+            None => return,
+        };
+        for key in keys {
+            let state = match self.probes.get_mut(key) {
+                Some(s) => s,
+                None => continue,
+            };
+            if !from_uprobe {
+                state.last_syscall = now;
+                continue;
+            }
+            state.last_hit = now;
+            if state.stalled {
+                info!(
+                    "ebpf uprobe {} recovered, process {}",
+                    Self::describe(key, state),
+                    pid
+                );
+                state.stalled = false;
+            }
+        }
+    }
+
+    pub fn on_exec(&mut self, pid: u32, now: Duration) {
+        self.exec_pids.push((pid, now));
+    }
+
+    fn describe(key: &ProbeKey, state: &ProbeState) -> String {
+        match key {
+            ProbeKey::Kprobe(name) => name.clone(),
+            ProbeKey::Uprobe {
+                binary_path,
+                offset,
+            } => format!("{} at {}+{:#x}", state.name, binary_path, offset),
+        }
+    }
+
+    // 用C库当前attach的probe刷新状态，detach超过2倍stall_timeout的probe被清理
+    fn refresh(&mut self, attached: &[ATTACHED_PROBE], now: Duration) {
+        let mut run_cnts: HashMap<ProbeKey, u64> = HashMap::new();
+        for state in self.probes.values_mut() {
+            state.pids.clear();
+        }
+        self.pid_probes.clear();
+        for a in attached {
+            let key = if a.probe_type == UPROBE {
+                ProbeKey::Uprobe {
+                    binary_path: a.binary_path(),
+                    offset: a.offset,
+                }
+            } else {
+                ProbeKey::Kprobe(a.name())
+            };
+            let state = self
+                .probes
+                .entry(key.clone())
+                .or_insert_with(|| ProbeState::new(a.name(), now));
+            state.last_attached = now;
+            *run_cnts.entry(key.clone()).or_default() += a.run_cnt;
+            if a.probe_type == UPROBE && a.pid > 0 && state.pids.insert(a.pid as u32) {
+                self.pid_probes.entry(a.pid as u32).or_default().push(key);
+            }
+        }
+        for (key, run_cnt) in run_cnts {
+            let state = self.probes.get_mut(&key).unwrap();
+            // 进程重启后运行次数重新计算，变化即认为有触发
+            if run_cnt != state.run_cnt {
+                if state.run_cnt > 0 {
+                    state.last_hit = now;
+                }
+                state.run_cnt = run_cnt;
+            }
+        }
+        let stall_timeout = self.stall_timeout;
+        self.probes.retain(|key, state| {
+            if now > state.last_attached + stall_timeout * 2 {
+                debug!("ebpf probe {} detached", Self::describe(key, state));
+                return false;
+            }
+            true
+        });
+    }
+
+    // 新进程的可执行文件上曾attach过uprobe，但过了C库的处理时间仍未attach
+    fn check_exec(&mut self, now: Duration) -> u64 {
+        let mut missing = 0;
+        let mut pending = vec![];
+        for (pid, exec_time) in self.exec_pids.drain(..) {
+            if now < exec_time + EXEC_ATTACH_DELAY {
+                pending.push((pid, exec_time));
+                continue;
+            }
+            if self.pid_probes.contains_key(&pid) {
+                continue;
+            }
+            let exe = match fs::read_link(self.proc_root.join(pid.to_string()).join("exe")) {
+                Ok(exe) => exe,
+                // 进程已退出
+                Err(_) => continue,
+            };
+            let exe = exe.to_string_lossy();
+            let probe = self.probes.iter().find(|(k, _)| {
+                matches!(k, ProbeKey::Uprobe { binary_path, .. } if binary_path.as_str() == exe)
+            });
+            if let Some((key, state)) = probe {
+                warn!(
+                    "ebpf uprobe {} not attached to process {} {:?} after exec",
+                    Self::describe(key, state),
+                    pid,
+                    now - exec_time
+                );
+                missing += 1;
+            }
+        }
+        self.exec_pids = pending;
+        missing
+    }
+
+    // 获取attach的probe列表需要遍历C库的probe，只在检查前获取
+    pub fn next_check(&self) -> Duration {
+        self.last_check + CHECK_INTERVAL
+    }
+
+    // 每CHECK_INTERVAL返回一次结果，其余时间返回None
+    pub fn check(&mut self, now: Duration, attached: &[ATTACHED_PROBE]) -> Option<CheckResult> {
+        if now < self.last_check + CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+        let mut result = CheckResult::default();
+        if self.stall_timeout.is_zero() {
+            self.exec_pids.clear();
+            return Some(result);
+        }
+        self.refresh(attached, now);
+
+        if self.last_event > Duration::ZERO && now > self.last_event + self.stall_timeout {
+            if !self.idle_warned {
+                warn!(
+                    "no ebpf events received in {:?}, {} syscall events before, {} probes attached",
+                    now - self.last_event,
+                    self.syscall_events,
+                    self.probes.len()
+                );
+                self.idle_warned = true;
+            }
+        } else {
+            self.idle_warned = false;
+        }
+
+        let stall_timeout = self.stall_timeout;
+        let last_event = self.last_event;
+        let mut uprobe_stalled = false;
+        for (key, state) in self.probes.iter_mut() {
+            if state.stalled || state.last_attached != now {
+                continue;
+            }
+            let stalled = match key {
+                ProbeKey::Uprobe { .. } => state.last_syscall > state.last_hit + stall_timeout,
+                // 未开启bpf_stats时运行次数为0，无法判断
+                ProbeKey::Kprobe(_) => {
+                    state.run_cnt > 0 && last_event > state.last_hit + stall_timeout
+                }
+            };
+            if !stalled {
+                continue;
+            }
+            match key {
+                ProbeKey::Uprobe { .. } => {
+                    let mut pids = state.pids.iter().collect::<Vec<_>>();
+                    pids.sort();
+                    warn!(
+                        "ebpf uprobe {} stalled for {:?} while syscall events of processes {:?} continue",
+                        Self::describe(key, state),
+                        state.last_syscall - state.last_hit,
+                        pids
+                    );
+                    uprobe_stalled = true;
+                }
+                ProbeKey::Kprobe(_) => warn!(
+                    "ebpf kprobe {} not triggered for {:?} while other probes continue",
+                    Self::describe(key, state),
+                    last_event - state.last_hit
+                ),
+            }
+            state.stalled = true;
+            result.stalled += 1;
+        }
+        let missing = self.check_exec(now);
+        result.stalled += missing;
+
+        // 重新attach需要扫描procfs，限制频率
+        if (uprobe_stalled || missing > 0)
+            && (self.last_resync.is_zero() || now >= self.last_resync + stall_timeout)
+        {
+            self.last_resync = now;
+            result.resync = true;
+        }
+        Some(result)
+    }
+    // 返回失败的probe数
+    pub fn report_attach_failures(&self, failures: &[PROBE_ATTACH_FAILURE]) -> u64 {
+        for f in failures {
+            if f.pid > 0 {
+                warn!(
+                    "ebpf probe {} attach failed with error {}, process {} binary {}",
+                    f.name(),
+                    f.error,
+                    f.pid,
+                    f.binary_path()
+                );
+            } else {
+                warn!(
+                    "ebpf probe {} attach failed with error {}",
+                    f.name(),
+                    f.error
+                );
+            }
+        }
+        // 异常在同步时被清除，只在有新的失败时设置
+        if !failures.is_empty() {
+            self.exception_handler.set(Exception::EbpfProbeAttachFailed);
+        }
+        failures.len() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    use crate::ebpf::KPROBE;
+
+    const BINARY: &str = "/usr/local/bin/server";
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    fn probe(probe_type: i32, name: &str, pid: i32, run_cnt: u64) -> ATTACHED_PROBE {
+        let mut p = ATTACHED_PROBE::default();
+        p.name[..name.len()].copy_from_slice(name.as_bytes());
+        p.probe_type = probe_type;
+        p.run_cnt = run_cnt;
+        if probe_type == UPROBE {
+            p.binary_path[..BINARY.len()].copy_from_slice(BINARY.as_bytes());
+            p.offset = 0x1000;
+            p.pid = pid;
+        }
+        p
+    }
+
+    #[test]
+    fn uprobe_stall() {
+        let mut health = ProbeHealth::new(secs(60), ExceptionHandler::default());
+        let mut attached = vec![probe(UPROBE, "uprobe/runtime.casgstatus", 100, 0)];
+        assert_eq!(
+            health.check(secs(10), &attached),
+            Some(CheckResult::default())
+        );
+        health.on_event(100, true, secs(11));
+        // 没有attach uprobe的进程不影响
+        health.on_event(200, false, secs(11));
+        for t in (20..=70).step_by(10) {
+            health.on_event(100, false, secs(t));
+            health.on_event(200, false, secs(t));
+            assert_eq!(
+                health.check(secs(t), &attached),
+                Some(CheckResult::default())
+            );
+        }
+        assert_eq!(health.check(secs(75), &attached), None);
+
+        health.on_event(100, false, secs(80));
+        assert_eq!(
+            health.check(secs(80), &attached),
+            Some(CheckResult {
+                stalled: 1,
+                resync: true
+            })
+        );
+        // 已告警的probe不重复计数
+        health.on_event(100, false, secs(90));
+        assert_eq!(
+            health.check(secs(90), &attached),
+            Some(CheckResult::default())
+        );
+
+        // 进程重启后新进程attach到同一个probe，数据恢复
+        attached = vec![probe(UPROBE, "uprobe/runtime.casgstatus", 101, 0)];
+        assert_eq!(
+            health.check(secs(100), &attached),
+            Some(CheckResult::default())
+        );
+        health.on_event(101, true, secs(101));
+        assert!(!health.probes.values().next().unwrap().stalled);
+        assert_eq!(health.pid_probes.keys().collect::<Vec<_>>(), vec![&101]);
+
+        // 再次停滞
+        health.on_event(101, false, secs(170));
+        assert_eq!(
+            health.check(secs(170), &attached),
+            Some(CheckResult {
+                stalled: 1,
+                resync: true
+            })
+        );
+
+        // detach后被清理
+        assert_eq!(health.check(secs(200), &[]), Some(CheckResult::default()));
+        assert_eq!(health.probes.len(), 1);
+        assert_eq!(health.check(secs(400), &[]), Some(CheckResult::default()));
+        assert!(health.probes.is_empty());
+    }
+
+    #[test]
+    fn kprobe_stall() {
+        let mut health = ProbeHealth::new(secs(60), ExceptionHandler::default());
+        let attached = |read, write| {
+            vec![
+                probe(KPROBE, "kprobe/__sys_sendmsg", 0, write),
+                probe(KPROBE, "tracepoint/syscalls/sys_enter_read", 0, read),
+            ]
+        };
+        assert_eq!(
+            health.check(secs(10), &attached(5, 5)),
+            Some(CheckResult::default())
+        );
+        for t in (20..=70).step_by(10) {
+            health.on_event(100, false, secs(t));
+            assert_eq!(
+                health.check(secs(t), &attached(5 + t, 5)),
+                Some(CheckResult::default())
+            );
+        }
+        health.on_event(100, false, secs(80));
+        assert_eq!(
+            health.check(secs(80), &attached(100, 5)),
+            Some(CheckResult {
+                stalled: 1,
+                resync: false
+            })
+        );
+
+        // 未开启bpf_stats时不判断
+        let mut health = ProbeHealth::new(secs(60), ExceptionHandler::default());
+        for t in (10..=200).step_by(10) {
+            health.on_event(100, false, secs(t));
+            assert_eq!(
+                health.check(secs(t), &attached(0, 0)),
+                Some(CheckResult::default())
+            );
+        }
+    }
+
+    #[test]
+    fn exec_without_uprobe() {
+        let dir = tempfile::tempdir().unwrap();
+        for pid in ["101", "102"] {
+            fs::create_dir(dir.path().join(pid)).unwrap();
+        }
+        symlink(BINARY, dir.path().join("101/exe")).unwrap();
+        symlink("/usr/bin/bash", dir.path().join("102/exe")).unwrap();
+
+        let mut health = ProbeHealth::new(secs(60), ExceptionHandler::default());
+        health.proc_root = dir.path().to_path_buf();
+        let attached = vec![probe(UPROBE, "uprobe/runtime.casgstatus", 100, 0)];
+        assert_eq!(
+            health.check(secs(10), &attached),
+            Some(CheckResult::default())
+        );
+        // 101重启后未attach，102不是golang进程，103已退出
+        health.on_exec(101, secs(15));
+        health.on_exec(102, secs(15));
+        health.on_exec(103, secs(15));
+        assert_eq!(
+            health.check(secs(100), &attached),
+            Some(CheckResult::default())
+        );
+        assert_eq!(
+            health.check(secs(170), &attached),
+            Some(CheckResult {
+                stalled: 1,
+                resync: true
+            })
+        );
+        assert!(health.exec_pids.is_empty());
+    }
+
+    #[test]
+    fn disabled() {
+        let mut health = ProbeHealth::new(Duration::ZERO, ExceptionHandler::default());
+        let attached = vec![probe(UPROBE, "uprobe/runtime.casgstatus", 100, 0)];
+        health.on_exec(100, secs(1));
+        health.on_event(100, false, secs(100));
+        assert_eq!(
+            health.check(secs(100), &attached),
+            Some(CheckResult::default())
+        );
+        assert!(health.exec_pids.is_empty());
+    }
+
+    #[test]
+    fn attach_failures() {
+        let handler = ExceptionHandler::default();
+        let health = ProbeHealth::new(secs(60), handler.clone());
+        assert_eq!(health.report_attach_failures(&[]), 0);
+        assert_eq!(handler.take(), 0);
+
+        let mut failure = PROBE_ATTACH_FAILURE::default();
+        failure.name[..11].copy_from_slice(b"uprobe/test");
+        failure.pid = 100;
+        failure.error = -1;
+        assert_eq!(failure.name(), "uprobe/test");
+        assert_eq!(health.report_attach_failures(&[failure]), 1);
+        assert_eq!(handler.take(), Exception::EbpfProbeAttachFailed as u64);
+    }
+}
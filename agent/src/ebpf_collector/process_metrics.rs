@@ -0,0 +1,267 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::common::{
+    enums::{IpProtocol, TapType},
+    flow::L7Protocol,
+};
+use crate::flow_generator::{AppProtoLogsData, L7ResponseStatus, LogMessageType};
+use crate::metric::{
+    document::{Code, Direction, Document, Tagger, TapSide},
+    meter::{AppMeter, Meter},
+};
+use crate::sender::SendItem;
+use crate::utils::queue::DebugSender;
+
+const MINUTE: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ProcessL7Key {
+    process_kname: Arc<str>,
+    // 数据采集自服务端进程时为true
+    server_side: bool,
+    l7_protocol: L7Protocol,
+    protocol: u8,
+    server_ip: IpAddr,
+    server_port: u16,
+    server_epc: i32,
+    tap_type: u16,
+}
+
+// 按(进程名, 应用协议, 服务端)聚合eBPF应用日志，生成分钟粒度的应用指标，
+// 在没有POD信息时也可以构建进程级别的服务拓扑
+pub struct ProcessL7Metrics {
+    // 会话聚合会延迟发送日志，一个分钟在超过该延迟后才输出
+    delay: Duration,
+    last_check: u64,
+    stash: BTreeMap<u64, HashMap<ProcessL7Key, AppMeter>>,
+    output: DebugSender<SendItem>,
+}
+
+impl ProcessL7Metrics {
+    pub fn new(delay: Duration, output: DebugSender<SendItem>) -> Self {
+        Self {
+            delay,
+            last_check: 0,
+            stash: BTreeMap::new(),
+            output,
+        }
+    }
+
+    pub fn add(&mut self, log: &AppProtoLogsData) {
+        let base = &log.base_info;
+        let (process_kname, server_side) = match base.tap_side {
+            TapSide::ClientProcess => (&base.process_kname_0, false),
+            TapSide::ServerProcess => (&base.process_kname_1, true),
+            _ => return,
+        };
+        if process_kname.is_empty() {
+            return;
+        }
+        let key = ProcessL7Key {
+            process_kname: process_kname.clone(),
+            server_side,
+            l7_protocol: base.head.proto,
+            protocol: u8::from(base.protocol),
+            server_ip: base.ip_dst,
+            server_port: base.port_dst,
+            server_epc: base.l3_epc_id_dst,
+            tap_type: u16::from(base.tap_type),
+        };
+        let minute = base.start_time.as_secs() / MINUTE * MINUTE;
+        let meter = self
+            .stash
+            .entry(minute)
+            .or_default()
+            .entry(key)
+            .or_default();
+
+        match base.head.msg_type {
+            LogMessageType::Request => meter.traffic.request += 1,
+            LogMessageType::Response => meter.traffic.response += 1,
+            LogMessageType::Session => {
+                meter.traffic.request += 1;
+                meter.traffic.response += 1;
+            }
+            _ => (),
+        }
+        if base.head.msg_type != LogMessageType::Request {
+            match base.head.status {
+                L7ResponseStatus::ClientError => meter.anomaly.client_error += 1,
+                L7ResponseStatus::ServerError => meter.anomaly.server_error += 1,
+                _ => (),
+            }
+        }
+        if base.head.rrt > 0 {
+            let rrt = base.head.rrt.min(u32::MAX as u64) as u32;
+            meter.latency.rrt_max = meter.latency.rrt_max.max(rrt);
+            meter.latency.rrt_sum += base.head.rrt;
+            meter.latency.rrt_count += 1;
+        }
+    }
+
+    // 输出所有结束时间早于now - delay的分钟
+    pub fn flush(&mut self, now: Duration, vtap_id: u16) {
+        if now.as_secs() == self.last_check {
+            return;
+        }
+        self.last_check = now.as_secs();
+        let deadline = now.saturating_sub(self.delay).as_secs();
+        while let Some((&minute, _)) = self.stash.iter().next() {
+            if minute + MINUTE > deadline {
+                break;
+            }
+            let meters = self.stash.remove(&minute).unwrap();
+            debug!(
+                "flush {} process l7 metrics of minute {}",
+                meters.len(),
+                minute
+            );
+            for (key, meter) in meters {
+                let _ = self
+                    .output
+                    .send(SendItem::Metrics(Box::new(Self::to_document(
+                        minute, key, meter, vtap_id,
+                    ))));
+            }
+        }
+    }
+
+    fn to_document(minute: u64, key: ProcessL7Key, meter: AppMeter, vtap_id: u16) -> Document {
+        let mut doc = Document::new(Meter::App(meter));
+        doc.timestamp = minute as u32;
+        doc.tagger = Tagger {
+            code: Code::IP
+                | Code::L3_EPC_ID
+                | Code::VTAP_ID
+                | Code::PROTOCOL
+                | Code::SERVER_PORT
+                | Code::DIRECTION
+                | Code::TAP_TYPE
+                | Code::L7_PROTOCOL
+                | Code::PROCESS_KNAME,
+            ip: key.server_ip,
+            is_ipv6: key.server_ip.is_ipv6(),
+            l3_epc_id: key.server_epc as i16,
+            vtap_id,
+            protocol: IpProtocol::try_from(key.protocol).unwrap_or_default(),
+            server_port: key.server_port,
+            direction: if key.server_side {
+                Direction::ServerProcessToClient
+            } else {
+                Direction::ClientProcessToServer
+            },
+            tap_side: if key.server_side {
+                TapSide::ServerProcess
+            } else {
+                TapSide::ClientProcess
+            },
+            tap_type: TapType::try_from(key.tap_type).unwrap_or_default(),
+            l7_protocol: key.l7_protocol,
+            process_kname: key.process_kname.to_string(),
+            ..Default::default()
+        };
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{enums::PacketDirection, meta_packet::MetaPacket};
+    use crate::debug::QueueDebugger;
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, AppProtoLogsInfo, DnsInfo};
+    use crate::utils::queue::bounded_with_debug;
+
+    fn log(timestamp: u64, msg_type: LogMessageType, status: L7ResponseStatus) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = "10.0.0.2".parse().unwrap();
+        packet.lookup_key.src_port = 40000;
+        packet.lookup_key.dst_port = 53;
+        packet.lookup_key.proto = IpProtocol::Udp;
+        packet.lookup_key.l2_end_0 = true;
+        packet.direction = PacketDirection::ClientToServer;
+        packet.process_name = "nginx".into();
+        let head = AppProtoHead {
+            proto: L7Protocol::Dns,
+            msg_type,
+            status,
+            code: 0,
+            rrt: if msg_type == LogMessageType::Session {
+                100
+            } else {
+                0
+            },
+            version: 0,
+            ..Default::default()
+        };
+        AppProtoLogsData::new(
+            AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0),
+            AppProtoLogsInfo::Dns(DnsInfo::default()),
+        )
+    }
+
+    #[test]
+    fn aggregate_by_process() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut metrics = ProcessL7Metrics::new(Duration::from_secs(60), s);
+
+        metrics.add(&log(
+            61,
+            LogMessageType::Session,
+            L7ResponseStatus::ServerError,
+        ));
+        metrics.add(&log(62, LogMessageType::Request, L7ResponseStatus::Ok));
+        metrics.add(&log(121, LogMessageType::Session, L7ResponseStatus::Ok));
+
+        metrics.flush(Duration::from_secs(150), 1);
+        assert!(r.recv(Some(Duration::from_millis(10))).is_err());
+
+        metrics.flush(Duration::from_secs(181), 1);
+        let doc = match r.recv(Some(Duration::from_millis(10))) {
+            Ok(SendItem::Metrics(doc)) => doc,
+            _ => panic!("no process metrics"),
+        };
+        assert!(r.recv(Some(Duration::from_millis(10))).is_err());
+        assert_eq!(doc.timestamp, 60);
+        assert_eq!(doc.tagger.process_kname, "nginx");
+        assert_eq!(doc.tagger.direction, Direction::ClientProcessToServer);
+        assert_eq!(doc.tagger.server_port, 53);
+        assert_eq!(doc.tagger.ip, "10.0.0.2".parse::<IpAddr>().unwrap());
+        match doc.meter {
+            Meter::App(m) => {
+                assert_eq!(m.traffic.request, 2);
+                assert_eq!(m.traffic.response, 1);
+                assert_eq!(m.anomaly.server_error, 1);
+                assert_eq!(m.latency.rrt_count, 1);
+                assert_eq!(m.latency.rrt_sum, 100);
+            }
+            _ => panic!("unexpected meter"),
+        }
+    }
+}
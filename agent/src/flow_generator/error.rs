@@ -14,10 +14,126 @@
  * limitations under the License.
  */
 
+use std::fmt;
 use std::str::Utf8Error;
 
 use thiserror::Error;
 
+// 解析失败所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseStage {
+    // 协议头及分帧
+    Header,
+    Request,
+    Response,
+    // 消息体中的字段
+    Payload,
+    // 连接上的协议状态不满足，如未识别协议时收到响应
+    Session,
+}
+
+impl ParseStage {
+    pub const ALL: [ParseStage; 5] = [
+        ParseStage::Header,
+        ParseStage::Request,
+        ParseStage::Response,
+        ParseStage::Payload,
+        ParseStage::Session,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseStage::Header => "header",
+            ParseStage::Request => "request",
+            ParseStage::Response => "response",
+            ParseStage::Payload => "payload",
+            ParseStage::Session => "session",
+        }
+    }
+}
+
+impl fmt::Display for ParseStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// 解析失败的上下文，用于定位失败的阶段和位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseContext {
+    pub stage: ParseStage,
+    // 失败位置在payload中的偏移
+    pub offset: usize,
+    // 期望值和实际值，多为长度
+    pub mismatch: Option<(usize, usize)>,
+}
+
+impl ParseContext {
+    pub fn new(stage: ParseStage) -> Self {
+        Self {
+            stage,
+            offset: 0,
+            mismatch: None,
+        }
+    }
+
+    pub fn at(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn mismatch(mut self, expected: usize, found: usize) -> Self {
+        self.mismatch = Some((expected, found));
+        self
+    }
+}
+
+impl From<ParseStage> for ParseContext {
+    fn from(stage: ParseStage) -> Self {
+        Self::new(stage)
+    }
+}
+
+impl fmt::Display for ParseContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stage={} offset={}", self.stage, self.offset)?;
+        if let Some((expected, found)) = self.mismatch {
+            write!(f, " expected={} found={}", expected, found)?;
+        }
+        Ok(())
+    }
+}
+
+// 所有解析失败类型，与Error::parse_failure返回的名称对应，用于按类型注册统计
+pub const L7_PARSE_FAILURES: [&str; 26] = [
+    "dubbo_header",
+    "http_header",
+    "kafka_log",
+    "kafka_perf",
+    "mqtt_log",
+    "mqtt_perf",
+    "redis_log",
+    "redis_perf",
+    "mysql_log",
+    "mysql_perf",
+    "dns_log",
+    "dns_perf",
+    "radius_log",
+    "radius_perf",
+    "modbus_log",
+    "modbus_perf",
+    "s7_log",
+    "s7_perf",
+    "snmp_header",
+    "tds_log",
+    "tds_perf",
+    "clickhouse_log",
+    "clickhouse_perf",
+    "tls_log",
+    "tls_perf",
+    "ntp_header",
+];
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("invalid packet timestamp")]
@@ -31,42 +147,137 @@ pub enum Error {
     ZeroPayloadLen,
     #[error("invalid ip protocol")]
     InvalidIpProtocol,
-    #[error("dubbo header parse failed")]
-    DubboHeaderParseFailed,
-    #[error("http header parse failed")]
-    HttpHeaderParseFailed,
-    #[error("kafka log parse failed")]
-    KafkaLogParseFailed,
-    #[error("kafka perf parse failed")]
-    KafkaPerfParseFailed,
-    #[error("mqtt log parse failed")]
-    MqttLogParseFailed,
-    #[error("mqtt perf parse failed")]
-    MqttPerfParseFailed,
-    #[error("redis log parse failed")]
-    RedisLogParseFailed,
-    #[error("redis perf parse failed")]
-    RedisPerfParseFailed,
-    #[error("mysql log parse failed")]
-    MysqlLogParseFailed,
-    #[error("mysql perf parse failed")]
-    MysqlPerfParseFailed,
-    #[error("{0}")]
-    DNSLogParseFailed(String),
-    #[error("{0}")]
-    DNSPerfParseFailed(&'static str),
+    #[error("dubbo header parse failed: {0}")]
+    DubboHeaderParseFailed(ParseContext),
+    #[error("http header parse failed: {0}")]
+    HttpHeaderParseFailed(ParseContext),
+    #[error("kafka log parse failed: {0}")]
+    KafkaLogParseFailed(ParseContext),
+    #[error("kafka perf parse failed: {0}")]
+    KafkaPerfParseFailed(ParseContext),
+    #[error("mqtt log parse failed: {0}")]
+    MqttLogParseFailed(ParseContext),
+    #[error("mqtt perf parse failed: {0}")]
+    MqttPerfParseFailed(ParseContext),
+    #[error("redis log parse failed: {0}")]
+    RedisLogParseFailed(ParseContext),
+    #[error("redis perf parse failed: {0}")]
+    RedisPerfParseFailed(ParseContext),
+    #[error("mysql log parse failed: {0}")]
+    MysqlLogParseFailed(ParseContext),
+    #[error("mysql perf parse failed: {0}")]
+    MysqlPerfParseFailed(ParseContext),
+    // LOAD DATA LOCAL的文件内容及多语句查询的后续响应，不是解析失败
+    #[error("mysql payload skipped")]
+    MysqlPayloadSkipped,
+    #[error("{1}: {0}")]
+    DNSLogParseFailed(ParseContext, String),
+    #[error("{1}: {0}")]
+    DNSPerfParseFailed(ParseContext, &'static str),
+    #[error("{1}: {0}")]
+    RadiusLogParseFailed(ParseContext, String),
+    #[error("radius perf parse failed: {0}")]
+    RadiusPerfParseFailed(ParseContext),
+    #[error("modbus log parse failed: {0}")]
+    ModbusLogParseFailed(ParseContext),
+    #[error("modbus perf parse failed: {0}")]
+    ModbusPerfParseFailed(ParseContext),
+    #[error("s7 log parse failed: {0}")]
+    S7LogParseFailed(ParseContext),
+    #[error("s7 perf parse failed: {0}")]
+    S7PerfParseFailed(ParseContext),
+    #[error("snmp header parse failed: {0}")]
+    SnmpHeaderParseFailed(ParseContext),
+    #[error("tds log parse failed: {0}")]
+    TdsLogParseFailed(ParseContext),
+    #[error("tds perf parse failed: {0}")]
+    TdsPerfParseFailed(ParseContext),
+    #[error("clickhouse log parse failed: {0}")]
+    ClickhouseLogParseFailed(ParseContext),
+    #[error("clickhouse perf parse failed: {0}")]
+    ClickhousePerfParseFailed(ParseContext),
+    #[error("tls log parse failed: {0}")]
+    TlsLogParseFailed(ParseContext),
+    #[error("tls perf parse failed: {0}")]
+    TlsPerfParseFailed(ParseContext),
+    #[error("ntp header parse failed: {0}")]
+    NtpHeaderParseFailed(ParseContext),
     #[error("l7 protocol unknown")]
     L7ProtocolUnknown,
     #[error("l7 protocol check limit")]
     L7ProtocolCheckLimit,
     #[error("l7 protocol parse limit")]
     L7ProtocolParseLimit,
+    #[error("l7 protocol parse circuit break")]
+    L7ProtocolParseBreak,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    // 应用协议已识别但解析失败，不含协议识别和请求匹配等错误
+    pub fn is_l7_parse_failure(&self) -> bool {
+        self.parse_failure().is_some()
+    }
+
+    // 返回解析失败的类型名及上下文
+    pub fn parse_failure(&self) -> Option<(&'static str, &ParseContext)> {
+        let failure = match self {
+            Error::DubboHeaderParseFailed(c) => ("dubbo_header", c),
+            Error::HttpHeaderParseFailed(c) => ("http_header", c),
+            Error::KafkaLogParseFailed(c) => ("kafka_log", c),
+            Error::KafkaPerfParseFailed(c) => ("kafka_perf", c),
+            Error::MqttLogParseFailed(c) => ("mqtt_log", c),
+            Error::MqttPerfParseFailed(c) => ("mqtt_perf", c),
+            Error::RedisLogParseFailed(c) => ("redis_log", c),
+            Error::RedisPerfParseFailed(c) => ("redis_perf", c),
+            Error::MysqlLogParseFailed(c) => ("mysql_log", c),
+            Error::MysqlPerfParseFailed(c) => ("mysql_perf", c),
+            Error::DNSLogParseFailed(c, _) => ("dns_log", c),
+            Error::DNSPerfParseFailed(c, _) => ("dns_perf", c),
+            Error::RadiusLogParseFailed(c, _) => ("radius_log", c),
+            Error::RadiusPerfParseFailed(c) => ("radius_perf", c),
+            Error::ModbusLogParseFailed(c) => ("modbus_log", c),
+            Error::ModbusPerfParseFailed(c) => ("modbus_perf", c),
+            Error::S7LogParseFailed(c) => ("s7_log", c),
+            Error::S7PerfParseFailed(c) => ("s7_perf", c),
+            Error::SnmpHeaderParseFailed(c) => ("snmp_header", c),
+            Error::TdsLogParseFailed(c) => ("tds_log", c),
+            Error::TdsPerfParseFailed(c) => ("tds_perf", c),
+            Error::ClickhouseLogParseFailed(c) => ("clickhouse_log", c),
+            Error::ClickhousePerfParseFailed(c) => ("clickhouse_perf", c),
+            Error::TlsLogParseFailed(c) => ("tls_log", c),
+            Error::TlsPerfParseFailed(c) => ("tls_perf", c),
+            Error::NtpHeaderParseFailed(c) => ("ntp_header", c),
+            _ => return None,
+        };
+        Some(failure)
+    }
+}
+
 impl From<Utf8Error> for Error {
-    fn from(_: Utf8Error) -> Self {
-        Self::HttpHeaderParseFailed
+    fn from(e: Utf8Error) -> Self {
+        Self::HttpHeaderParseFailed(ParseContext::new(ParseStage::Payload).at(e.valid_up_to()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_failure() {
+        let e = Error::MysqlLogParseFailed(ParseContext::new(ParseStage::Header).mismatch(5, 3));
+        assert_eq!(
+            e.to_string(),
+            "mysql log parse failed: stage=header offset=0 expected=5 found=3"
+        );
+        let (name, context) = e.parse_failure().unwrap();
+        assert!(L7_PARSE_FAILURES.contains(&name));
+        assert_eq!(context.stage, ParseStage::Header);
+
+        let e = Error::DNSLogParseFailed(ParseStage::Payload.into(), "payload too short".into());
+        assert_eq!(e.to_string(), "payload too short: stage=payload offset=0");
+        assert!(!Error::L7ProtocolUnknown.is_l7_parse_failure());
     }
 }
@@ -22,10 +22,7 @@ use std::{
     net::Ipv4Addr,
     rc::Rc,
     str::FromStr,
-    sync::{
-        atomic::{AtomicI64, Ordering},
-        Arc,
-    },
+    sync::{atomic::Ordering, Arc},
     time::{Duration, SystemTime},
 };
 
@@ -38,20 +35,29 @@ use log::{debug, warn};
 use super::{
     app_table::AppTable,
     error::Error,
+    flow_snapshot::{DispatcherFlows, FlowEntry},
     flow_state::{StateMachine, StateValue},
-    perf::{FlowPerf, FlowPerfCounter, L7RrtCache},
-    protocol_logs::MetaAppProto,
+    parse_error_pcap::{PacketRing, ParseErrorPcap},
+    perf::{FlowPerf, FlowPerfCounter, L7RrtCache, UdpRequestMatcher},
+    protocol_logs::{
+        client_hello_server_name, IdleConnection, LogMessageType, MetaAppProto, StreamContinuation,
+    },
     service_table::{ServiceKey, ServiceTable},
     FlowMapKey, FlowNode, FlowState, FlowTimeKey, COUNTER_FLOW_ID_MASK, FLOW_METRICS_PEER_DST,
     FLOW_METRICS_PEER_SRC, L7_PROTOCOL_UNKNOWN_LIMIT, L7_RRT_CACHE_CAPACITY, QUEUE_BATCH_SIZE,
     SERVICE_TABLE_IPV4_CAPACITY, SERVICE_TABLE_IPV6_CAPACITY, STATISTICAL_INTERVAL,
     THREAD_FLOW_ID_MASK, TIMER_FLOW_ID_MASK, TIME_MAX_INTERVAL, TIME_UNIT,
 };
+#[cfg(target_os = "linux")]
+use crate::utils::handoff::FlowHandoff;
 use crate::{
     common::{
         endpoint::{EndpointData, EndpointInfo, EPC_FROM_DEEPFLOW, EPC_FROM_INTERNET},
         enums::{EthernetType, HeaderType, IpProtocol, PacketDirection, TapType, TcpFlags},
-        flow::{CloseType, Flow, FlowKey, FlowMetricsPeer, L4Protocol, L7Protocol, TunnelField},
+        flow::{
+            new_flow_uuid, CloseType, Flow, FlowKey, FlowMetricsPeer, L4Protocol, L7Protocol,
+            TunnelField,
+        },
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader},
         policy::PolicyData,
@@ -62,7 +68,7 @@ use crate::{
     debug::QueueDebugger,
     policy::{Policy, PolicyGetter},
     proto::common::TridentType,
-    rpc::get_timestamp,
+    rpc::TimeSource,
     utils::net::MacAddr,
     utils::queue::{self, DebugSender, Receiver},
 };
@@ -82,27 +88,41 @@ pub struct FlowMap {
     total_flow: usize,
 
     output_queue: DebugSender<Box<TaggedFlow>>,
-    out_log_queue: DebugSender<Box<MetaAppProto>>,
+    // 仅输出指标时为None
+    out_log_queue: Option<DebugSender<Box<MetaAppProto>>>,
     output_buffer: Vec<TaggedFlow>,
     last_queue_flush: Duration,
     config: FlowAccess,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
     counter: Arc<FlowPerfCounter>,
-    ntp_diff: Arc<AtomicI64>,
+    time_source: TimeSource,
+    parse_error_pcap: ParseErrorPcap,
     packet_sequence_queue: DebugSender<Box<packet_sequence_block::PacketSequenceBlock>>, // Enterprise Edition Feature: packet-sequence
+
+    // 热升级时旧进程交接的流，超过handoff_deadline仍未出现的流不再恢复
+    #[cfg(target_os = "linux")]
+    handoff_flows: HashMap<HandoffKey, FlowHandoff>,
+    #[cfg(target_os = "linux")]
+    handoff_deadline: Duration,
 }
 
+// (tap_port, ip_src, ip_dst, port_src, port_dst, proto)
+#[cfg(target_os = "linux")]
+type HandoffKey = (TapPort, std::net::IpAddr, std::net::IpAddr, u16, u16, u8);
+
 impl FlowMap {
     pub fn new(
         id: u32,
         output_queue: DebugSender<Box<TaggedFlow>>,
         policy_getter: PolicyGetter,
-        app_proto_log_queue: DebugSender<Box<MetaAppProto>>,
-        ntp_diff: Arc<AtomicI64>,
+        app_proto_log_queue: Option<DebugSender<Box<MetaAppProto>>>,
+        time_source: TimeSource,
         config: FlowAccess,
         packet_sequence_queue: DebugSender<Box<packet_sequence_block::PacketSequenceBlock>>, // Enterprise Edition Feature: packet-sequence
     ) -> (Self, Arc<FlowPerfCounter>) {
         let counter = Arc::new(FlowPerfCounter::default());
+        let mut rrt_cache = L7RrtCache::new(L7_RRT_CACHE_CAPACITY);
+        rrt_cache.set_rrt_upper_bounds(&config.load().l7_rrt_upper_bounds);
 
         (
             Self {
@@ -128,18 +148,180 @@ impl FlowMap {
                 output_buffer: vec![],
                 last_queue_flush: Duration::ZERO,
                 config,
-                rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(L7_RRT_CACHE_CAPACITY))),
+                rrt_cache: Rc::new(RefCell::new(rrt_cache)),
                 counter: counter.clone(),
-                ntp_diff,
+                time_source,
+                parse_error_pcap: ParseErrorPcap::new(),
                 packet_sequence_queue, // Enterprise Edition Feature: packet-sequence
+                #[cfg(target_os = "linux")]
+                handoff_flows: HashMap::new(),
+                #[cfg(target_os = "linux")]
+                handoff_deadline: Duration::ZERO,
             },
             counter,
         )
     }
 
+    // 热升级前导出流表，dispatcher停止收包后调用
+    #[cfg(target_os = "linux")]
+    pub fn handoff_flows(&self) -> Vec<FlowHandoff> {
+        self.node_map
+            .iter()
+            .flat_map(|m| m.values())
+            .flatten()
+            .map(|node| {
+                let flow = &node.tagged_flow.flow;
+                let key = &flow.flow_key;
+                let peers = &flow.flow_metrics_peers;
+                FlowHandoff {
+                    dispatcher_id: self.id,
+                    tap_port: key.tap_port.0,
+                    ip_src: key.ip_src,
+                    ip_dst: key.ip_dst,
+                    port_src: key.port_src,
+                    port_dst: key.port_dst,
+                    proto: u8::from(key.proto),
+                    flow_id: flow.flow_id,
+                    flow_uuid: flow.flow_uuid,
+                    start_time: flow.start_time,
+                    flow_state: node.flow_state,
+                    total_packet_count: [peers[0].total_packet_count, peers[1].total_packet_count],
+                    total_byte_count: [peers[0].total_byte_count, peers[1].total_byte_count],
+                }
+            })
+            .collect()
+    }
+
+    // 热升级后载入旧进程交接的流，流的首包到达时恢复其状态
+    #[cfg(target_os = "linux")]
+    pub fn resume_handoff_flows(&mut self, flows: Vec<FlowHandoff>) {
+        self.handoff_deadline = self.time_source.now() + self.config.load().flow_timeout.max;
+        self.handoff_flows = flows
+            .into_iter()
+            .map(|f| {
+                (
+                    (
+                        TapPort(f.tap_port),
+                        f.ip_src,
+                        f.ip_dst,
+                        f.port_src,
+                        f.port_dst,
+                        f.proto,
+                    ),
+                    f,
+                )
+            })
+            .collect();
+    }
+
+    // 新流若为交接的流则沿用flow_id、开始时间、累计统计量及TCP状态，
+    // 首包方向可能与交接时相反，此时统计量按反向累加
+    #[cfg(target_os = "linux")]
+    fn resume_handoff_flow(&mut self, node: &mut FlowNode) {
+        let flow = &mut node.tagged_flow.flow;
+        if flow.start_time > self.handoff_deadline {
+            self.handoff_flows = HashMap::new();
+            return;
+        }
+        let key = &flow.flow_key;
+        let proto = u8::from(key.proto);
+        let forward = (
+            key.tap_port,
+            key.ip_src,
+            key.ip_dst,
+            key.port_src,
+            key.port_dst,
+            proto,
+        );
+        let backward = (
+            key.tap_port,
+            key.ip_dst,
+            key.ip_src,
+            key.port_dst,
+            key.port_src,
+            proto,
+        );
+        let (state, reversed) = match self.handoff_flows.remove(&forward) {
+            Some(state) => (state, false),
+            None => match self.handoff_flows.remove(&backward) {
+                Some(state) => (state, true),
+                None => return,
+            },
+        };
+        flow.flow_id = state.flow_id;
+        flow.flow_uuid = state.flow_uuid;
+        flow.start_time = state.start_time;
+        flow.is_new_flow = false;
+        for i in 0..2 {
+            let peer = &mut flow.flow_metrics_peers[if reversed { 1 - i } else { i }];
+            peer.total_packet_count += state.total_packet_count[i];
+            peer.total_byte_count += state.total_byte_count[i];
+        }
+        // 交接前的状态以旧流的方向记录，需要转换到当前流的方向
+        node.flow_state = if reversed {
+            state.flow_state.reversed()
+        } else {
+            state.flow_state
+        };
+    }
+
+    // 流表快照，最多包含max条流，total为流的总数
+    pub fn snapshot(&self, max: usize) -> DispatcherFlows {
+        let node_map = match self.node_map.as_ref() {
+            Some(m) => m,
+            None => {
+                return DispatcherFlows {
+                    id: self.id,
+                    responded: true,
+                    ..Default::default()
+                }
+            }
+        };
+        let flows = node_map
+            .values()
+            .flatten()
+            .take(max)
+            .map(|node| {
+                let flow = &node.tagged_flow.flow;
+                let key = &flow.flow_key;
+                let peers = &flow.flow_metrics_peers;
+                FlowEntry {
+                    dispatcher_id: self.id,
+                    flow_id: flow.flow_id,
+                    tap_port: key.tap_port.to_string(),
+                    ip_src: key.ip_src,
+                    port_src: key.port_src,
+                    ip_dst: key.ip_dst,
+                    port_dst: key.port_dst,
+                    proto: format!("{:?}", key.proto),
+                    state: format!("{:?}", node.flow_state),
+                    l7_protocol: format!(
+                        "{:?}",
+                        node.meta_flow_perf
+                            .as_ref()
+                            .map(|p| p.l7_protocol())
+                            .unwrap_or_default()
+                    ),
+                    packets_tx: peers[0].total_packet_count,
+                    packets_rx: peers[1].total_packet_count,
+                    bytes_tx: peers[0].total_byte_count,
+                    bytes_rx: peers[1].total_byte_count,
+                    start_time: flow.start_time.as_secs(),
+                    recent_time: node.recent_time.as_secs(),
+                }
+            })
+            .collect();
+        DispatcherFlows {
+            id: self.id,
+            responded: true,
+            total: node_map.values().map(|nodes| nodes.len()).sum(),
+            flows,
+        }
+    }
+
     pub fn inject_flush_ticker(&mut self, mut timestamp: Duration) -> bool {
         if timestamp.is_zero() {
-            timestamp = get_timestamp(self.ntp_diff.load(Ordering::Relaxed));
+            timestamp = self.time_source.now();
         } else if timestamp < self.start_time {
             return false;
         }
@@ -149,6 +331,10 @@ impl FlowMap {
             return true;
         }
 
+        self.rrt_cache
+            .borrow_mut()
+            .set_rrt_upper_bounds(&self.config.load().l7_rrt_upper_bounds);
+
         // 根据包到达时间的容差调整
         let next_start_time_in_unit = ((timestamp - self.config.load().packet_delay).as_nanos()
             / TIME_UNIT.as_nanos()) as u64;
@@ -194,6 +380,8 @@ impl FlowMap {
             }
             // 未超时Flow的统计信息发送到队列下游
             self.node_updated_aftercare(&mut node, timeout, None);
+            self.report_idle_connection(&mut node, timestamp);
+            self.report_http_stream(&mut node, timestamp);
             // Enterprise Edition Feature: packet-sequence
             if self.config.load().packet_sequence_flag > 0 && node.packet_sequence_block.is_some() {
                 // flush the packet_sequence_block at the regular time
@@ -484,6 +672,13 @@ impl FlowMap {
     fn update_syn_or_syn_ack_seq(&mut self, node: &mut FlowNode, meta_packet: &mut MetaPacket) {
         let tcp_flag = meta_packet.tcp_data.flags;
         let flow = &mut node.tagged_flow.flow;
+        // 记录握手包的指纹，用于被动识别操作系统，重传的SYN不覆盖
+        if tcp_flag == TcpFlags::SYN || tcp_flag == TcpFlags::SYN_ACK {
+            let peer = &mut flow.flow_metrics_peers[meta_packet.direction as usize];
+            if peer.tcp_fingerprint == 0 {
+                peer.tcp_fingerprint = meta_packet.tcp_fingerprint();
+            }
+        }
         if tcp_flag == TcpFlags::SYN {
             flow.syn_seq = meta_packet.tcp_data.seq;
         } else if tcp_flag == TcpFlags::SYN_ACK && meta_packet.payload_len == 0 {
@@ -560,6 +755,7 @@ impl FlowMap {
                 proto: lookup_key.proto,
                 tap_type: lookup_key.tap_type,
                 tap_port: meta_packet.tap_port,
+                capture_point: meta_packet.capture_point,
             },
             tunnel: if let Some(tunnel) = meta_packet.tunnel {
                 TunnelField {
@@ -577,6 +773,7 @@ impl FlowMap {
                 TunnelField::default()
             },
             flow_id: Self::generate_flow_id(lookup_key.timestamp, self.id, total_flow),
+            flow_uuid: new_flow_uuid(),
             start_time: lookup_key.timestamp,
             flow_stat_time: Duration::from_nanos(
                 (lookup_key.timestamp.as_nanos() / TIME_UNIT.as_nanos() * TIME_UNIT.as_nanos())
@@ -586,6 +783,7 @@ impl FlowMap {
             eth_type: lookup_key.eth_type,
             queue_hash: meta_packet.queue_hash,
             is_new_flow: true,
+            ipv6_anomaly: meta_packet.ipv6_anomaly,
             // 统计量
             flow_metrics_peers: [
                 FlowMetricsPeer {
@@ -623,6 +821,9 @@ impl FlowMap {
             meta_flow_perf: None,
             next_tcp_seq0: 0,
             next_tcp_seq1: 0,
+            l7_snaplen_extended_count: 0,
+            l7_parse_error_ring: None,
+            l7_activity: Default::default(),
             policy_data_cache: Default::default(),
             endpoint_data_cache: EndpointData {
                 src_info: EndpointInfo {
@@ -636,6 +837,7 @@ impl FlowMap {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
 
                 dst_info: EndpointInfo {
@@ -649,20 +851,38 @@ impl FlowMap {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
             },
             packet_sequence_block: None, // Enterprise Edition Feature: packet-sequence
         };
+        #[cfg(target_os = "linux")]
+        if !self.handoff_flows.is_empty() {
+            self.resume_handoff_flow(&mut node);
+        }
         // 标签
         (self.policy_getter).lookup(meta_packet, self.id as usize);
         self.update_endpoint_and_policy_data(&mut node, meta_packet);
 
-        if self.config.load().collector_enabled {
+        let config = self.config.load();
+        if config.collector_enabled {
+            let l4_protocol = L4Protocol::from(meta_packet.lookup_key.proto);
+            let udp_request = if l4_protocol == L4Protocol::Udp {
+                UdpRequestMatcher::new(
+                    &config.udp_perf,
+                    meta_packet.lookup_key.src_port,
+                    meta_packet.lookup_key.dst_port,
+                )
+                .map(|m| (m, config.udp_perf.request_timeout))
+            } else {
+                None
+            };
             node.meta_flow_perf = FlowPerf::new(
                 self.rrt_cache.clone(),
-                L4Protocol::from(meta_packet.lookup_key.proto),
+                l4_protocol,
                 self.app_table.get_protocol(meta_packet),
                 self.counter.clone(),
+                udp_request,
             )
         }
         node
@@ -729,6 +949,7 @@ impl FlowMap {
         if meta_packet.vlan > 0 {
             flow.vlan = meta_packet.vlan;
         }
+        flow.ipv6_anomaly |= meta_packet.ipv6_anomaly;
         if let Some(tunnel) = meta_packet.tunnel {
             match meta_packet.direction {
                 PacketDirection::ClientToServer => {
@@ -807,6 +1028,11 @@ impl FlowMap {
     ) {
         if let Some(perf) = node.meta_flow_perf.as_mut() {
             let flow_id = node.tagged_flow.flow.flow_id;
+            let config = self.config.load();
+            let error_pcap = &config.l7_parse_error_pcap;
+            if let Some(ring) = node.l7_parse_error_ring.as_mut() {
+                ring.push(meta_packet, error_pcap.ring_size);
+            }
             match perf.parse(
                 &meta_packet,
                 is_first_packet_direction,
@@ -814,22 +1040,49 @@ impl FlowMap {
                 self.l4_metrics_enabled(),
                 self.l7_metrics_enabled(),
                 &mut self.app_table,
+                &config.l7_parse_budget,
+                &config.l7_protocol_detector,
             ) {
                 Err(Error::L7ReqNotFound(c)) => {
                     self.counter
                         .mismatched_response
                         .fetch_add(c, Ordering::Relaxed);
                 }
-                Err(e) => debug!("{}", e),
+                Err(e) => {
+                    debug!("{}", e);
+                    self.counter.l7_parse_error.record(&e);
+                    if error_pcap.enabled && e.is_l7_parse_failure() {
+                        let ring = node.l7_parse_error_ring.get_or_insert_with(|| {
+                            let mut ring = Box::new(PacketRing::default());
+                            ring.push(meta_packet, error_pcap.ring_size);
+                            ring
+                        });
+                        self.parse_error_pcap.on_parse_error(
+                            error_pcap,
+                            ring,
+                            flow_id,
+                            meta_packet.lookup_key.timestamp,
+                        );
+                    }
+                }
                 _ => (),
             }
+            // 只在识别为TLS且尚未获取到SNI时解析ClientHello
+            let flow = &mut node.tagged_flow.flow;
+            if flow.tls_sni.is_empty() && perf.l7_protocol() == L7Protocol::Tls {
+                if let Some(sni) = meta_packet
+                    .get_l4_payload()
+                    .and_then(client_hello_server_name)
+                {
+                    flow.tls_sni = sni;
+                }
+            }
         }
-        if self.config.load().app_proto_log_enabled && meta_packet.packet_len > 0 {
-            self.write_to_app_proto_log(
-                node,
-                &meta_packet,
-                self.config.load().l7_log_packet_size as u16,
-            );
+        if self.config.load().app_proto_log_enabled
+            && self.out_log_queue.is_some()
+            && meta_packet.packet_len > 0
+        {
+            self.write_to_app_proto_log(node, &meta_packet);
         }
     }
 
@@ -949,10 +1202,15 @@ impl FlowMap {
                 .rrt_cache
                 .borrow_mut()
                 .get_and_remove_l7_req_timeout(flow.flow_id);
+            if let Some(perf) = node.meta_flow_perf.as_mut() {
+                flow.mtu_anomaly = perf.mtu_anomaly();
+                perf.flow_closed();
+            }
             // 如果返回None，就清空掉flow_perf_stats
             flow.flow_perf_stats = node.meta_flow_perf.as_mut().and_then(|perf| {
                 perf.copy_and_reset_perf_data(
                     flow.reversed,
+                    flow.end_time,
                     l7_timeout_count as u32,
                     self.l4_metrics_enabled(),
                     self.l7_metrics_enabled(),
@@ -995,9 +1253,13 @@ impl FlowMap {
                 return;
             }
             if flow.flow_key.proto == IpProtocol::Tcp || flow.flow_key.proto == IpProtocol::Udp {
+                if let Some(perf) = node.meta_flow_perf.as_ref() {
+                    flow.mtu_anomaly = perf.mtu_anomaly();
+                }
                 flow.flow_perf_stats = node.meta_flow_perf.as_mut().and_then(|perf| {
                     perf.copy_and_reset_perf_data(
                         flow.reversed,
+                        flow.flow_stat_time + STATISTICAL_INTERVAL,
                         0,
                         self.l4_metrics_enabled(),
                         self.l7_metrics_enabled(),
@@ -1009,17 +1271,106 @@ impl FlowMap {
         }
     }
 
-    fn write_to_app_proto_log(
-        &mut self,
-        node: &mut FlowNode,
-        meta_packet: &MetaPacket,
-        pkt_size: u16,
-    ) {
-        let lookup_key = &meta_packet.lookup_key; //  trisolaris接口定义: 0(TAP_ANY)表示所有都需要
-        if !self.config.load().l7_log_tap_types[u16::from(TapType::Any) as usize]
-            && (lookup_key.tap_type > TapType::Max
-                || !self.config.load().l7_log_tap_types[u16::from(lookup_key.tap_type) as usize])
+    fn l7_log_tap_type_enabled(&self, tap_type: TapType) -> bool {
+        let config = self.config.load();
+        //  trisolaris接口定义: 0(TAP_ANY)表示所有都需要
+        config.l7_log_tap_types[u16::from(TapType::Any) as usize]
+            || (tap_type <= TapType::Max && config.l7_log_tap_types[u16::from(tap_type) as usize])
+    }
+
+    // HTTP/2连接持续无请求时定期输出一条空闲连接日志
+    fn report_idle_connection(&mut self, node: &mut FlowNode, timestamp: Duration) {
+        let interval = self.config.load().l7_log_idle_connection_interval;
+        if interval.is_zero()
+            || !self.config.load().app_proto_log_enabled
+            || self.out_log_queue.is_none()
+        {
+            return;
+        }
+        match node.meta_flow_perf.as_ref() {
+            Some(perf) if perf.l7_protocol() == L7Protocol::Http2 => (),
+            _ => return,
+        }
+        if !self.l7_log_tap_type_enabled(node.tagged_flow.flow.flow_key.tap_type) {
+            return;
+        }
+
+        let activity = &node.l7_activity;
+        let last_activity = activity.last_activity.max(node.tagged_flow.flow.start_time);
+        if timestamp < last_activity + interval || timestamp < activity.last_reported + interval {
+            return;
+        }
+        let idle = IdleConnection {
+            connection_age: (timestamp - node.tagged_flow.flow.start_time).as_micros() as u64,
+            streams_opened: activity.streams_opened,
+            last_activity: last_activity.as_micros() as u64,
+        };
+        node.l7_activity.last_reported = timestamp;
+
+        node.tagged_flow.flow.set_tap_side(
+            self.config.load().trident_type,
+            self.config.load().cloud_gateway_traffic,
+        );
+        let app_proto = MetaAppProto::idle_connection(&node.tagged_flow, timestamp, idle);
+        if let Some(Err(_)) = self
+            .out_log_queue
+            .as_ref()
+            .map(|q| q.send(Box::new(app_proto)))
+        {
+            warn!("flow-map push MetaAppProto to queue failed because queue have terminated");
+        }
+    }
+
+    // HTTP/1 SSE或持续时间超过间隔的响应，流式传输期间定期输出一条持续记录
+    fn report_http_stream(&mut self, node: &mut FlowNode, timestamp: Duration) {
+        let interval = self.config.load().l7_log_http_stream_interval;
+        if interval.is_zero()
+            || !self.config.load().app_proto_log_enabled
+            || self.out_log_queue.is_none()
+        {
+            return;
+        }
+        let stream = match node.meta_flow_perf.as_ref().and_then(|p| p.http_stream()) {
+            Some(s) if s.event_stream || s.last_time >= s.start_time + interval => *s,
+            _ => return,
+        };
+        if !self.l7_log_tap_type_enabled(node.tagged_flow.flow.flow_key.tap_type) {
+            return;
+        }
+
+        let last_reported = node.l7_activity.stream_last_reported.max(stream.start_time);
+        if timestamp < last_reported + interval {
+            return;
+        }
+        let continuation = StreamContinuation {
+            stream_age: (timestamp - stream.start_time).as_micros() as u64,
+            bytes_streamed: stream.bytes,
+            last_activity: stream.last_time.as_micros() as u64,
+        };
+        node.l7_activity.stream_last_reported = timestamp;
+
+        node.tagged_flow.flow.set_tap_side(
+            self.config.load().trident_type,
+            self.config.load().cloud_gateway_traffic,
+        );
+        let app_proto = MetaAppProto::stream_continuation(
+            &node.tagged_flow,
+            timestamp,
+            stream.status_code,
+            continuation,
+        );
+        if let Some(Err(_)) = self
+            .out_log_queue
+            .as_ref()
+            .map(|q| q.send(Box::new(app_proto)))
         {
+            warn!("flow-map push MetaAppProto to queue failed because queue have terminated");
+        }
+    }
+
+    fn write_to_app_proto_log(&mut self, node: &mut FlowNode, meta_packet: &MetaPacket) {
+        let lookup_key = &meta_packet.lookup_key;
+        if !self.l7_log_tap_type_enabled(lookup_key.tap_type) {
             return;
         }
 
@@ -1027,7 +1378,7 @@ impl FlowMap {
             return;
         }
         // 考虑性能，最好是l7 perf解析后，满足需要的包生成log
-        let (head, offset) = match node
+        let (mut head, offset) = match node
             .meta_flow_perf
             .as_mut()
             .and_then(|perf| perf.app_proto_head(self.l7_metrics_enabled()))
@@ -1036,15 +1387,58 @@ impl FlowMap {
             None => return,
         };
 
+        if head.proto == L7Protocol::Http2 {
+            let activity = &mut node.l7_activity;
+            activity.last_activity = lookup_key.timestamp;
+            if head.msg_type == LogMessageType::Request {
+                activity.streams_opened += 1;
+            }
+        }
+
         node.tagged_flow.flow.set_tap_side(
             self.config.load().trident_type,
             self.config.load().cloud_gateway_traffic,
         );
 
-        if let Some(app_proto) =
-            MetaAppProto::new(&node.tagged_flow, meta_packet, head, offset, pkt_size)
-        {
-            if let Err(_) = self.out_log_queue.send(Box::new(app_proto)) {
+        let pkt_size = {
+            let config = self.config.load();
+            let mut pkt_size = config.l7_log_packet_size(lookup_key.tap_type);
+            // HTTP/Kafka等协议的头部可能超过截取长度，流的前几个包按扩展长度采集，之后恢复
+            if config.l7_snaplen_adaptive_protocols[head.proto as usize]
+                && node.l7_snaplen_extended_count < config.l7_snaplen_adaptive_packet_count
+            {
+                node.l7_snaplen_extended_count += 1;
+                pkt_size = pkt_size.max(config.l7_snaplen_adaptive_packet_size);
+            }
+            pkt_size.min(u16::MAX as u32) as u16
+        };
+
+        // 超过上限的RRT按上限上报并标记
+        let rrt_upper_bound =
+            self.config.load().l7_rrt_upper_bounds[head.proto as usize].as_micros() as u64;
+        let rrt_outlier = rrt_upper_bound > 0 && head.rrt > rrt_upper_bound;
+        if rrt_outlier {
+            head.rrt = rrt_upper_bound;
+        }
+        if head.msg_type == LogMessageType::Response && lookup_key.proto == IpProtocol::Tcp {
+            if let Some(perf) = node.meta_flow_perf.as_ref() {
+                head.split_rrt(perf.server_rtt().as_micros() as u64);
+            }
+        }
+
+        if let Some(app_proto) = MetaAppProto::new(
+            &node.tagged_flow,
+            meta_packet,
+            head,
+            offset,
+            pkt_size,
+            rrt_outlier,
+        ) {
+            if let Some(Err(_)) = self
+                .out_log_queue
+                .as_ref()
+                .map(|q| q.send(Box::new(app_proto)))
+            {
                 warn!("flow-map push MetaAppProto to queue failed because queue have terminated");
             }
         }
@@ -1178,6 +1572,7 @@ impl FlowMap {
             }
             peer_src.is_local_mac = src_info.is_local_mac;
             peer_src.is_local_ip = src_info.is_local_ip;
+            peer_src.dual_stack_id = src_info.dual_stack_id;
         }
         {
             let dst_info = node.endpoint_data_cache.dst_info;
@@ -1193,6 +1588,7 @@ impl FlowMap {
             }
             peer_dst.is_local_mac = dst_info.is_local_mac;
             peer_dst.is_local_ip = dst_info.is_local_ip;
+            peer_dst.dual_stack_id = dst_info.dual_stack_id;
         }
 
         // update policy data
@@ -1256,8 +1652,8 @@ pub fn _new_flow_map_and_receiver(
         0,
         output_queue_sender,
         policy_getter,
-        app_proto_log_queue,
-        Arc::new(AtomicI64::new(0)),
+        Some(app_proto_log_queue),
+        TimeSource::default(),
         Map::new(current_config.clone(), |config| -> &FlowConfig {
             &config.flow
         }),
@@ -1304,6 +1700,7 @@ pub fn _new_meta_packet<'a>() -> MetaPacket<'a> {
             is_vip: false,
             is_local_mac: false,
             is_local_ip: false,
+            dual_stack_id: 0,
 
             l2_epc_id: EPC_FROM_DEEPFLOW,
             l3_epc_id: 1,
@@ -1317,6 +1714,7 @@ pub fn _new_meta_packet<'a>() -> MetaPacket<'a> {
             is_vip: false,
             is_local_mac: false,
             is_local_ip: false,
+            dual_stack_id: 0,
 
             l2_epc_id: EPC_FROM_DEEPFLOW,
             l3_epc_id: EPC_FROM_INTERNET,
@@ -1333,7 +1731,7 @@ mod tests {
     use crate::{
         common::{
             enums::EthernetType,
-            flow::CloseType,
+            flow::{format_flow_uuid, CloseType},
             policy::{NpbAction, NpbTunnelType, PolicyData, TapSide},
             tap_port::TapPort,
         },
@@ -1373,6 +1771,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn snapshot() {
+        let (mut flow_map, _) = _new_flow_map_and_receiver(TridentType::TtProcess);
+        let snapshot = flow_map.snapshot(10);
+        assert_eq!(snapshot.total, 0);
+        assert!(snapshot.flows.is_empty());
+        flow_map.inject_meta_packet(_new_meta_packet());
+        let snapshot = flow_map.snapshot(10);
+        assert_eq!(snapshot.total, 1);
+        assert_eq!(snapshot.flows.len(), 1);
+        assert_eq!(snapshot.flows[0].packets_tx, 1);
+        assert_eq!(snapshot.flows[0].packets_rx, 0);
+        let snapshot = flow_map.snapshot(0);
+        assert_eq!(snapshot.total, 1);
+        assert!(snapshot.flows.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn resume_handoff_flows() {
+        let (mut old_map, _) = _new_flow_map_and_receiver(TridentType::TtProcess);
+        old_map.inject_meta_packet(_new_meta_packet());
+        let flows = old_map.handoff_flows();
+        assert_eq!(flows.len(), 1);
+        let old = old_map.snapshot(1).flows.remove(0);
+
+        // 交接后首包为反方向
+        let (mut new_map, _) = _new_flow_map_and_receiver(TridentType::TtProcess);
+        new_map.resume_handoff_flows(flows);
+        let mut packet = _new_meta_packet();
+        _reverse_meta_packet(&mut packet);
+        new_map.inject_meta_packet(packet);
+        assert!(new_map.handoff_flows.is_empty());
+        let new = new_map.snapshot(1).flows.remove(0);
+        assert_eq!(new.flow_id, old.flow_id);
+        assert_eq!(new.start_time, old.start_time);
+        assert_eq!(new.packets_tx + new.packets_rx, 2);
+        assert_eq!(
+            new.bytes_tx + new.bytes_rx,
+            2 * (old.bytes_tx + old.bytes_rx)
+        );
+    }
+
     #[test]
     fn syn_fin() {
         let (mut flow_map, output_queue_receiver) =
@@ -1529,6 +1970,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn flow_uuid_across_forced_report() {
+        let (mut flow_map, output_queue_receiver) =
+            _new_flow_map_and_receiver(TridentType::TtProcess);
+        let packet0 = _new_meta_packet();
+        let timestamp = packet0.lookup_key.timestamp;
+        flow_map.inject_meta_packet(packet0);
+
+        let mut uuids = vec![];
+        for i in 1..=2 {
+            let mut packet = _new_meta_packet();
+            packet.tcp_data.flags = TcpFlags::ACK;
+            packet.lookup_key.timestamp = timestamp + Duration::from_secs(10 * i);
+            let flush_timestamp = packet.lookup_key.timestamp;
+            flow_map.inject_meta_packet(packet);
+            flow_map.inject_flush_ticker(flush_timestamp + Duration::from_secs(10));
+
+            if let Ok(tagged_flow) = output_queue_receiver.recv(Some(TIME_UNIT)) {
+                assert_eq!(tagged_flow.flow.close_type, CloseType::ForcedReport);
+                uuids.push(tagged_flow.flow.flow_uuid);
+            }
+        }
+        assert!(uuids.iter().all(|u| *u == uuids[0]));
+
+        let uuid = new_flow_uuid();
+        assert_ne!(uuid, new_flow_uuid());
+        let s = format_flow_uuid(uuid);
+        assert_eq!(s.len(), 36);
+        assert_eq!(&s[14..15], "4");
+    }
+
     #[test]
     fn udp_arp_short_flow() {
         let (mut flow_map, output_queue_receiver) =
@@ -16,7 +16,10 @@
 
 use std::{net::IpAddr, time::Duration};
 
-use super::{perf::FlowPerf, FlowState, FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC};
+use super::{
+    parse_error_pcap::PacketRing, perf::FlowPerf, FlowState, FLOW_METRICS_PEER_DST,
+    FLOW_METRICS_PEER_SRC,
+};
 use crate::{
     common::{
         decapsulate::TunnelType,
@@ -137,6 +140,18 @@ impl FlowMapKey {
     }
 }
 
+// HTTP/2连接上的请求活动，用于发现只有keepalive的空闲连接
+#[derive(Debug, Default)]
+pub struct L7ConnectionActivity {
+    pub streams_opened: u32,
+    // 最近一次请求或响应的时间
+    pub last_activity: Duration,
+    // 最近一次输出空闲连接日志的时间
+    pub last_reported: Duration,
+    // 最近一次输出HTTP/1流式响应持续记录的时间
+    pub stream_last_reported: Duration,
+}
+
 pub struct FlowNode {
     // 用作time_set比对的标识，等于FlowTimeKey的timestamp_key, 只有创建FlowNode和刷新更新流节点的超时才会更新
     pub timestamp_key: u64,
@@ -158,6 +173,11 @@ pub struct FlowNode {
     pub policy_in_tick: [bool; 2],
     // 当前统计周期（目前是自然秒）是否更新策略
     pub packet_in_tick: bool, // 当前统计周期（目前是自然秒）是否有包
+    // 已按扩展长度采集的应用层包数，见l7_snaplen_adaptive_packet_count
+    pub l7_snaplen_extended_count: u32,
+    // 应用解析失败后缓存的最近包，见l7-parse-error-pcap
+    pub l7_parse_error_ring: Option<Box<PacketRing>>,
+    pub l7_activity: L7ConnectionActivity,
 
     // Enterprise Edition Feature: packet-sequence
     pub packet_sequence_block: Option<packet_sequence_block::PacketSequenceBlock>,
@@ -0,0 +1,477 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::net::IpAddr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::proto::trident::TapMode;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const CSV_HEADER: &str = "dispatcher_id,flow_id,tap_port,ip_src,port_src,ip_dst,port_dst,proto,state,l7_protocol,packets_tx,packets_rx,bytes_tx,bytes_rx,start_time,recent_time";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl Default for SnapshotFormat {
+    fn default() -> Self {
+        SnapshotFormat::Text
+    }
+}
+
+impl TryFrom<&str> for SnapshotFormat {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(SnapshotFormat::Text),
+            "json" => Ok(SnapshotFormat::Json),
+            "csv" => Ok(SnapshotFormat::Csv),
+            _ => Err(format!("unsupported format {}", s)),
+        }
+    }
+}
+
+// 流表中一条流的概要，时间单位为秒
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlowEntry {
+    pub dispatcher_id: u32,
+    pub flow_id: u64,
+    pub tap_port: String,
+    pub ip_src: IpAddr,
+    pub port_src: u16,
+    pub ip_dst: IpAddr,
+    pub port_dst: u16,
+    pub proto: String,
+    pub state: String,
+    pub l7_protocol: String,
+    pub packets_tx: u64,
+    pub packets_rx: u64,
+    pub bytes_tx: u64,
+    pub bytes_rx: u64,
+    pub start_time: u64,
+    pub recent_time: u64,
+}
+
+impl FlowEntry {
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.dispatcher_id,
+            self.flow_id,
+            csv_field(&self.tap_port),
+            self.ip_src,
+            self.port_src,
+            self.ip_dst,
+            self.port_dst,
+            csv_field(&self.proto),
+            csv_field(&self.state),
+            csv_field(&self.l7_protocol),
+            self.packets_tx,
+            self.packets_rx,
+            self.bytes_tx,
+            self.bytes_rx,
+            self.start_time,
+            self.recent_time,
+        )
+    }
+}
+
+impl fmt::Display for FlowEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flow_id={} tap_port={} {}:{} > {}:{} proto={} state={} l7_protocol={} packets={}/{} bytes={}/{} start={} recent={}",
+            self.flow_id,
+            self.tap_port,
+            self.ip_src,
+            self.port_src,
+            self.ip_dst,
+            self.port_dst,
+            self.proto,
+            self.state,
+            self.l7_protocol,
+            self.packets_tx,
+            self.packets_rx,
+            self.bytes_tx,
+            self.bytes_rx,
+            self.start_time,
+            self.recent_time,
+        )
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DispatcherFlows {
+    pub id: u32,
+    // 超时未提交快照时为false
+    pub responded: bool,
+    // 流表中流的总数，flows最多只包含部分
+    pub total: usize,
+    #[serde(skip)]
+    pub flows: Vec<FlowEntry>,
+}
+
+#[derive(Serialize)]
+struct JsonSummary<'a> {
+    dispatchers: &'a [DispatcherFlows],
+    truncated: bool,
+}
+
+fn truncated_marker(omitted: usize) -> String {
+    format!("truncated, {} flows omitted\n", omitted)
+}
+
+// 按格式导出快照，输出不超过max_bytes，超出部分的流被丢弃，
+// text和csv在末尾输出截断标记和丢弃的流数量，json标记truncated
+pub fn export(results: &[DispatcherFlows], format: SnapshotFormat, max_bytes: usize) -> String {
+    let mut output = String::new();
+    let mut truncated = false;
+    let flows = results.iter().flat_map(|r| r.flows.iter());
+    let flow_count = results.iter().map(|r| r.flows.len()).sum::<usize>();
+    // 为截断标记预留空间
+    let marker_len = truncated_marker(flow_count).len();
+    let mut written = 0;
+    match format {
+        SnapshotFormat::Text => {
+            for r in results {
+                if r.responded {
+                    let _ = writeln!(output, "dispatcher {} flows {}", r.id, r.total);
+                } else {
+                    let _ = writeln!(output, "dispatcher {} not responding", r.id);
+                }
+            }
+            let max_bytes = max_bytes.saturating_sub(marker_len);
+            for flow in flows {
+                let line = flow.to_string();
+                if output.len() + line.len() + 1 > max_bytes {
+                    truncated = true;
+                    break;
+                }
+                let _ = writeln!(output, "{}", line);
+                written += 1;
+            }
+        }
+        SnapshotFormat::Csv => {
+            let max_bytes = max_bytes.saturating_sub(marker_len);
+            let _ = writeln!(output, "{}", CSV_HEADER);
+            for flow in flows {
+                let line = flow.to_csv();
+                if output.len() + line.len() + 1 > max_bytes {
+                    truncated = true;
+                    break;
+                }
+                let _ = writeln!(output, "{}", line);
+                written += 1;
+            }
+        }
+        SnapshotFormat::Json => {
+            // 为摘要预留空间，false比true长一个字节
+            let summary_len = serde_json::to_string(&JsonSummary {
+                dispatchers: results,
+                truncated: false,
+            })
+            .map(|s| s.len())
+            .unwrap_or_default();
+            let mut entries = vec![];
+            let mut len = summary_len + "\"flows\":[],".len();
+            for flow in flows {
+                let entry = match serde_json::to_string(flow) {
+                    Ok(e) => e,
+                    Err(_) => continue,
+                };
+                if len + entry.len() + 1 > max_bytes {
+                    truncated = true;
+                    break;
+                }
+                len += entry.len() + 1;
+                entries.push(entry);
+            }
+            let summary = serde_json::to_string(&JsonSummary {
+                dispatchers: results,
+                truncated,
+            })
+            .unwrap_or_else(|_| "{}".to_owned());
+            // 在摘要对象末尾插入flows数组
+            let _ = write!(
+                output,
+                "{},\"flows\":[{}]}}",
+                &summary[..summary.len() - 1],
+                entries.join(",")
+            );
+            return output;
+        }
+    }
+    if truncated {
+        output.push_str(&truncated_marker(flow_count - written));
+    }
+    output
+}
+
+// 流表只能在dispatcher线程内访问，诊断命令发起请求后由各dispatcher在处理循环中
+// 检查并提交自己流表的快照
+#[derive(Default)]
+pub struct FlowSnapshot {
+    // 镜像和分析模式的dispatcher没有收包循环，不响应快照请求，直接拒绝
+    unsupported_tap_mode: Mutex<Option<TapMode>>,
+    generation: AtomicU64,
+    // dispatcher id -> (已提交的generation, 快照)
+    results: Mutex<HashMap<u32, (u64, DispatcherFlows)>>,
+}
+
+impl FlowSnapshot {
+    pub fn set_tap_mode(&self, tap_mode: TapMode) {
+        *self.unsupported_tap_mode.lock().unwrap() = match tap_mode {
+            TapMode::Local => None,
+            _ => Some(tap_mode),
+        };
+    }
+
+    pub fn register(&self, id: u32) {
+        self.results.lock().unwrap().insert(
+            id,
+            (
+                0,
+                DispatcherFlows {
+                    id,
+                    ..Default::default()
+                },
+            ),
+        );
+    }
+
+    pub fn unregister(&self, id: u32) {
+        self.results.lock().unwrap().remove(&id);
+    }
+
+    // 有新请求时返回其generation
+    pub fn requested(&self, last: &mut u64) -> Option<u64> {
+        let generation = self.generation.load(Ordering::Relaxed);
+        if generation == *last {
+            return None;
+        }
+        *last = generation;
+        Some(generation)
+    }
+
+    pub fn submit(&self, generation: u64, flows: DispatcherFlows) {
+        if let Some(r) = self.results.lock().unwrap().get_mut(&flows.id) {
+            *r = (generation, flows);
+        }
+    }
+
+    // 发起请求并等待所有dispatcher提交，超时未提交的dispatcher标记为未响应
+    pub fn take(&self, timeout: Duration) -> Result<Vec<DispatcherFlows>, String> {
+        if let Some(tap_mode) = *self.unsupported_tap_mode.lock().unwrap() {
+            return Err(format!(
+                "flow table snapshot is unsupported in {:?} tap mode",
+                tap_mode
+            ));
+        }
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let start = Instant::now();
+        loop {
+            let done = self
+                .results
+                .lock()
+                .unwrap()
+                .values()
+                .all(|(g, _)| *g >= generation);
+            if done || start.elapsed() >= timeout {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        let mut results = self
+            .results
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .map(|(id, (g, flows))| {
+                if *g >= generation {
+                    DispatcherFlows {
+                        responded: true,
+                        ..std::mem::take(flows)
+                    }
+                } else {
+                    DispatcherFlows {
+                        id: *id,
+                        ..Default::default()
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        results.sort_by_key(|r| r.id);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    fn entry(flow_id: u64) -> FlowEntry {
+        FlowEntry {
+            dispatcher_id: 1,
+            flow_id,
+            tap_port: "LMAC@1@00:00:00:01".to_owned(),
+            ip_src: "10.0.0.1".parse().unwrap(),
+            port_src: 40000,
+            ip_dst: "10.0.0.2".parse().unwrap(),
+            port_dst: 80,
+            proto: "Tcp".to_owned(),
+            state: "Established".to_owned(),
+            l7_protocol: "Http1".to_owned(),
+            packets_tx: 3,
+            packets_rx: 2,
+            bytes_tx: 300,
+            bytes_rx: 200,
+            start_time: 100,
+            recent_time: 105,
+        }
+    }
+
+    #[test]
+    fn take_snapshot() {
+        let snapshot = Arc::new(FlowSnapshot::default());
+        snapshot.register(1);
+        snapshot.register(0);
+        let mut last = 0;
+        assert_eq!(snapshot.requested(&mut last), None);
+
+        let s = snapshot.clone();
+        let dispatcher = thread::spawn(move || {
+            let mut last = 0;
+            loop {
+                if let Some(g) = s.requested(&mut last) {
+                    s.submit(
+                        g,
+                        DispatcherFlows {
+                            id: 1,
+                            total: 1,
+                            flows: vec![entry(1)],
+                            ..Default::default()
+                        },
+                    );
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        let results = snapshot.take(Duration::from_millis(200)).unwrap();
+        dispatcher.join().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0],
+            DispatcherFlows {
+                id: 0,
+                ..Default::default()
+            }
+        );
+        assert!(results[1].responded);
+        assert_eq!(results[1].flows, vec![entry(1)]);
+
+        snapshot.set_tap_mode(TapMode::Mirror);
+        assert_eq!(
+            snapshot.take(Duration::from_millis(200)).unwrap_err(),
+            "flow table snapshot is unsupported in Mirror tap mode"
+        );
+        assert_eq!(snapshot.requested(&mut last), None);
+    }
+
+    #[test]
+    fn export_formats() {
+        let results = vec![DispatcherFlows {
+            id: 1,
+            responded: true,
+            total: 3,
+            flows: vec![entry(1), entry(2), entry(3)],
+        }];
+
+        let text = export(&results, SnapshotFormat::Text, usize::MAX);
+        assert_eq!(text.lines().count(), 4);
+        assert!(!text.contains("truncated"));
+        assert!(text.starts_with("dispatcher 1 flows 3\n"));
+
+        let csv = export(&results, SnapshotFormat::Csv, usize::MAX);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+        assert!(!csv.contains("truncated"));
+
+        let json = export(&results, SnapshotFormat::Json, usize::MAX);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["truncated"], false);
+        assert_eq!(value["dispatchers"][0]["total"], 3);
+        assert_eq!(value["flows"].as_array().unwrap().len(), 3);
+        assert_eq!(value["flows"][2]["flow_id"], 3);
+    }
+
+    #[test]
+    fn export_size_cap() {
+        let results = vec![DispatcherFlows {
+            id: 1,
+            responded: true,
+            total: 100,
+            flows: (0..100).map(entry).collect(),
+        }];
+        for format in [
+            SnapshotFormat::Text,
+            SnapshotFormat::Csv,
+            SnapshotFormat::Json,
+        ] {
+            let output = export(&results, format, 2048);
+            assert!(output.len() <= 2048, "{:?} {}", format, output.len());
+        }
+        for format in [SnapshotFormat::Text, SnapshotFormat::Csv] {
+            let output = export(&results, format, 2048);
+            let written = output.lines().filter(|l| l.contains("10.0.0.1")).count();
+            assert!(written > 0);
+            assert_eq!(
+                output.lines().last().unwrap(),
+                format!("truncated, {} flows omitted", 100 - written),
+                "{:?}",
+                format
+            );
+        }
+        let json = export(&results, SnapshotFormat::Json, 2048);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["truncated"], true);
+        assert!(!value["flows"].as_array().unwrap().is_empty());
+    }
+}
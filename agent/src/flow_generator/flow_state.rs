@@ -18,11 +18,13 @@ use std::mem::{self, MaybeUninit};
 use std::rc::Rc;
 use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
+
 use super::FlowTimeout;
 
 use crate::common::enums::TcpFlags;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FlowState {
     Raw,
     Opening1,
@@ -46,6 +48,23 @@ pub enum FlowState {
     Max,
 }
 
+impl FlowState {
+    // 流的方向反转后对应的状态，Tx/Rx表示由源端/目的端发起关闭
+    // 握手中的状态由SYN、SYN/ACK的先后区分，反转后没有对应状态，从Raw重新开始
+    pub fn reversed(self) -> Self {
+        match self {
+            FlowState::ClosingTx1 => FlowState::ClosingRx1,
+            FlowState::ClosingTx2 => FlowState::ClosingRx2,
+            FlowState::ClosingRx1 => FlowState::ClosingTx1,
+            FlowState::ClosingRx2 => FlowState::ClosingTx2,
+            FlowState::Opening1 | FlowState::Opening2 | FlowState::Syn1 | FlowState::SynAck1 => {
+                FlowState::Raw
+            }
+            s => s,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StateValue {
     pub timeout: Duration,
@@ -719,6 +738,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reversed_state() {
+        for (state, reversed) in [
+            (FlowState::ClosingTx1, FlowState::ClosingRx1),
+            (FlowState::ClosingRx2, FlowState::ClosingTx2),
+            (FlowState::Opening1, FlowState::Raw),
+            (FlowState::Established, FlowState::Established),
+            (FlowState::Reset, FlowState::Reset),
+        ] {
+            assert_eq!(state.reversed(), reversed);
+        }
+        assert_eq!(
+            FlowState::ClosingTx2.reversed().reversed(),
+            FlowState::ClosingTx2
+        );
+    }
+
     #[test]
     fn simple_get_state() {
         let flow_timeout: FlowTimeout = TcpTimeout::default().into();
@@ -755,6 +791,7 @@ mod tests {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
                 dst_info: EndpointInfo {
                     real_ip: Ipv4Addr::UNSPECIFIED.into(),
@@ -767,10 +804,14 @@ mod tests {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
             },
             next_tcp_seq0: 0,
             next_tcp_seq1: 0,
+            l7_snaplen_extended_count: 0,
+            l7_parse_error_ring: None,
+            l7_activity: Default::default(),
             packet_in_tick: false,
             policy_in_tick: [false; 2],
             packet_sequence_block: Some(packet_sequence_block::PacketSequenceBlock::default()), // Enterprise Edition Feature: packet-sequence
@@ -818,6 +859,7 @@ mod tests {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
                 dst_info: EndpointInfo {
                     real_ip: Ipv4Addr::UNSPECIFIED.into(),
@@ -830,6 +872,7 @@ mod tests {
                     is_vip: false,
                     is_local_mac: false,
                     is_local_ip: false,
+                    dual_stack_id: 0,
                 },
             }));
 
@@ -0,0 +1,56 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use super::protocol_logs::{protocol_checks, AppLogs};
+use crate::common::{
+    enums::{IpProtocol, PacketDirection},
+    flow::L7Protocol,
+    meta_packet::MetaPacket,
+};
+
+// 供fuzz使用，非法输入只应返回错误而不能panic
+
+// 按协议解析任意payload，两个方向各解析一次以覆盖请求和响应
+pub fn _l7_log_parse(protocol: L7Protocol, payload: &[u8]) {
+    let protos: &[IpProtocol] = match protocol {
+        L7Protocol::Dns => &[IpProtocol::Tcp, IpProtocol::Udp],
+        L7Protocol::Radius | L7Protocol::Snmp | L7Protocol::Ntp => &[IpProtocol::Udp],
+        _ => &[IpProtocol::Tcp],
+    };
+    let mut logs = AppLogs::default();
+    for &proto in protos {
+        for direction in [
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        ] {
+            let _ = logs.parse(protocol, payload, proto, direction);
+        }
+    }
+}
+
+// 从原始帧构造MetaPacket并执行所有协议识别，返回l4 payload
+pub fn _meta_packet_update(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut packet = MetaPacket::empty();
+    packet.update(frame, true, true, Duration::ZERO, 0).ok()?;
+
+    let mut bitmap = u128::MAX;
+    for (_, check) in protocol_checks() {
+        check(&mut bitmap, &packet);
+    }
+    packet.get_l4_payload().map(|p| p.to_vec())
+}
@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 协议解析的golden文件测试：resources/test/flow_generator/<协议>/xxx.pcap逐包解析的输出
+// 与同目录的xxx.result比较，由src/bin/deepflow-agent-golden.rs重新生成result
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, ErrorKind, Result},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::protocol_logs::{DnsLog, DubboLog, HttpLog, KafkaLog, MqttLog, MysqlLog, RedisLog};
+use crate::common::{
+    enums::PacketDirection,
+    meta_packet::{fold_checksum, MetaPacket},
+    ETH_HEADER_SIZE, VLAN_HEADER_SIZE,
+};
+
+pub const GOLDEN_DIR: &str = "resources/test/flow_generator";
+pub const PCAP_EXTENSION: &str = "pcap";
+pub const RESULT_EXTENSION: &str = "result";
+
+// 与utils::test::Capture::load_pcap的默认值相同
+const DEFAULT_PARSE_LEN: usize = 128;
+
+pub struct GoldenParser {
+    // 与GOLDEN_DIR下的目录同名
+    pub name: &'static str,
+    // 超过的部分截断
+    parse_len: usize,
+    output: fn(&mut [MetaPacket]) -> String,
+}
+
+pub const GOLDEN_PARSERS: [GoldenParser; 7] = [
+    GoldenParser {
+        name: "dns",
+        parse_len: DEFAULT_PARSE_LEN,
+        output: DnsLog::golden_output,
+    },
+    GoldenParser {
+        name: "dubbo",
+        parse_len: DEFAULT_PARSE_LEN,
+        output: DubboLog::golden_output,
+    },
+    GoldenParser {
+        name: "http",
+        parse_len: 1500,
+        output: HttpLog::golden_output,
+    },
+    GoldenParser {
+        name: "kafka",
+        parse_len: DEFAULT_PARSE_LEN,
+        output: KafkaLog::golden_output,
+    },
+    GoldenParser {
+        name: "mqtt",
+        parse_len: 1024,
+        output: MqttLog::golden_output,
+    },
+    GoldenParser {
+        name: "mysql",
+        parse_len: 1400,
+        output: MysqlLog::golden_output,
+    },
+    GoldenParser {
+        name: "redis",
+        parse_len: DEFAULT_PARSE_LEN,
+        output: RedisLog::golden_output,
+    },
+];
+
+impl GoldenParser {
+    pub fn find(name: &str) -> Option<&'static GoldenParser> {
+        GOLDEN_PARSERS.iter().find(|p| p.name == name)
+    }
+
+    pub fn run(&self, pcap: &Path) -> Result<String> {
+        let data = fs::read(pcap)?;
+        let records = pcap_records(&data)?;
+        let mut packets = Vec::with_capacity(records.len());
+        for (timestamp, range) in records {
+            let frame = &data[range.start..range.end.min(range.start + self.parse_len)];
+            let mut packet = MetaPacket::empty();
+            packet
+                .update(frame, true, true, timestamp, 0)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            packets.push(packet);
+        }
+        Ok((self.output)(&mut packets))
+    }
+
+    // 协议目录下的pcap及同名的result文件，按文件名排序
+    pub fn cases(&self, dir: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut cases = vec![];
+        for entry in fs::read_dir(dir.join(self.name))? {
+            let pcap = entry?.path();
+            if pcap.extension().map(|e| e == PCAP_EXTENSION) != Some(true) {
+                continue;
+            }
+            let result = pcap.with_extension(RESULT_EXTENSION);
+            cases.push((pcap, result));
+        }
+        cases.sort();
+        Ok(cases)
+    }
+}
+
+// 按首包的目的端口区分方向，对有l4 payload的包调用f生成输出
+pub(crate) fn parse_payloads<F>(packets: &mut [MetaPacket], mut f: F) -> String
+where
+    F: FnMut(&[u8], &MetaPacket, &mut String),
+{
+    let mut output = String::new();
+    let first_dst_port = match packets.first() {
+        Some(p) => p.lookup_key.dst_port,
+        None => return output,
+    };
+    for packet in packets.iter_mut() {
+        packet.direction = if packet.lookup_key.dst_port == first_dst_port {
+            PacketDirection::ClientToServer
+        } else {
+            PacketDirection::ServerToClient
+        };
+        let packet = &*packet;
+        if let Some(payload) = packet.get_l4_payload() {
+            f(payload, packet, &mut output);
+        }
+    }
+    output
+}
+
+const PCAP_GLOBAL_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+const LINK_TYPE_ETHERNET: u32 = 1;
+
+// 只支持以太网链路类型的pcap，不支持pcapng，返回每个包的时间戳及在文件中的位置
+fn pcap_records(data: &[u8]) -> Result<Vec<(Duration, Range<usize>)>> {
+    if data.len() < PCAP_GLOBAL_HEADER_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, "pcap too short"));
+    }
+    let magic = u32::from_le_bytes(data[..4].try_into().unwrap());
+    let (big_endian, nanos) = match magic {
+        PCAP_MAGIC => (false, false),
+        PCAP_MAGIC_NS => (false, true),
+        _ if magic.swap_bytes() == PCAP_MAGIC => (true, false),
+        _ if magic.swap_bytes() == PCAP_MAGIC_NS => (true, true),
+        _ => return Err(Error::new(ErrorKind::InvalidData, "not a pcap file")),
+    };
+    let read_u32 = |bs: &[u8]| {
+        let bs = bs[..4].try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(bs)
+        } else {
+            u32::from_le_bytes(bs)
+        }
+    };
+    if read_u32(&data[20..]) != LINK_TYPE_ETHERNET {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "only ethernet link type is supported",
+        ));
+    }
+
+    let mut records = vec![];
+    let mut offset = PCAP_GLOBAL_HEADER_LEN;
+    while offset + PCAP_RECORD_HEADER_LEN <= data.len() {
+        let seconds = read_u32(&data[offset..]) as u64;
+        let fraction = read_u32(&data[offset + 4..]);
+        let incl_len = read_u32(&data[offset + 8..]) as usize;
+        offset += PCAP_RECORD_HEADER_LEN;
+        if offset + incl_len > data.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "pcap truncated"));
+        }
+        let timestamp = if nanos {
+            Duration::new(seconds, fraction)
+        } else {
+            Duration::new(seconds, fraction.saturating_mul(1000))
+        };
+        records.push((timestamp, offset..offset + incl_len));
+        offset += incl_len;
+    }
+    Ok(records)
+}
+
+// 用户提供的pcap加入测试前替换MAC和IP地址，并增量更新IPv4头部及TCP/UDP校验和，
+// 同一个Anonymizer内相同的地址映射为相同的值，payload不做修改，需要人工检查
+#[derive(Default)]
+pub struct Anonymizer {
+    ips: HashMap<IpAddr, IpAddr>,
+    macs: HashMap<[u8; 6], [u8; 6]>,
+}
+
+impl Anonymizer {
+    pub fn anonymize_pcap(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = data.to_vec();
+        for (_, range) in pcap_records(&data)? {
+            self.anonymize_frame(&mut data[range]);
+        }
+        Ok(data)
+    }
+
+    fn anonymize_frame(&mut self, frame: &mut [u8]) {
+        if frame.len() < ETH_HEADER_SIZE {
+            return;
+        }
+        self.anonymize_mac(&mut frame[..6]);
+        self.anonymize_mac(&mut frame[6..12]);
+        let mut ip_offset = ETH_HEADER_SIZE;
+        let mut eth_type = u16::from_be_bytes([frame[12], frame[13]]);
+        if eth_type == 0x8100 {
+            if frame.len() < ETH_HEADER_SIZE + VLAN_HEADER_SIZE {
+                return;
+            }
+            ip_offset += VLAN_HEADER_SIZE;
+            eth_type = u16::from_be_bytes([frame[16], frame[17]]);
+        }
+        let frame = &mut frame[ip_offset..];
+        match eth_type {
+            0x0800 if frame.len() >= 20 => {
+                let old = frame[12..20].to_vec();
+                for offset in [12, 16] {
+                    let ip = <[u8; 4]>::try_from(&frame[offset..offset + 4]).unwrap();
+                    if let IpAddr::V4(ip) = self.anonymize_ip(Ipv4Addr::from(ip).into()) {
+                        frame[offset..offset + 4].copy_from_slice(&ip.octets());
+                    }
+                }
+                let new = frame[12..20].to_vec();
+                update_checksum(frame, 10, &old, &new);
+                // 非首个分片不含L4头部
+                if u16::from_be_bytes([frame[6], frame[7]]) & 0x1FFF != 0 {
+                    return;
+                }
+                let ihl = (frame[0] & 0xF) as usize * 4;
+                let protocol = frame[9];
+                Self::update_l4_checksum(frame, protocol, ihl, &old, &new, true);
+            }
+            0x86dd if frame.len() >= 40 => {
+                let old = frame[8..40].to_vec();
+                for offset in [8, 24] {
+                    let ip = <[u8; 16]>::try_from(&frame[offset..offset + 16]).unwrap();
+                    if let IpAddr::V6(ip) = self.anonymize_ip(Ipv6Addr::from(ip).into()) {
+                        frame[offset..offset + 16].copy_from_slice(&ip.octets());
+                    }
+                }
+                let new = frame[8..40].to_vec();
+                // 有扩展头时不更新
+                let next_header = frame[6];
+                Self::update_l4_checksum(frame, next_header, 40, &old, &new, false);
+            }
+            _ => (),
+        }
+    }
+
+    fn update_l4_checksum(
+        packet: &mut [u8],
+        protocol: u8,
+        l4_offset: usize,
+        old: &[u8],
+        new: &[u8],
+        is_ipv4: bool,
+    ) {
+        let offset = match protocol {
+            6 => l4_offset + 16,
+            17 => l4_offset + 6,
+            _ => return,
+        };
+        if packet.len() < offset + 2 {
+            return;
+        }
+        // IPv4的UDP校验和为0表示未计算
+        if protocol == 17 && is_ipv4 && packet[offset..offset + 2] == [0, 0] {
+            return;
+        }
+        update_checksum(packet, offset, old, new);
+    }
+
+    fn anonymize_mac(&mut self, mac: &mut [u8]) {
+        let old = <[u8; 6]>::try_from(&*mac).unwrap();
+        // 保留广播、组播及全0地址
+        if old[0] & 1 != 0 || old == [0; 6] {
+            return;
+        }
+        let n = self.macs.len() as u32 + 1;
+        let new = self.macs.entry(old).or_insert_with(|| {
+            let n = n.to_be_bytes();
+            [0x02, 0, n[0], n[1], n[2], n[3]]
+        });
+        mac.copy_from_slice(new);
+    }
+
+    fn anonymize_ip(&mut self, ip: IpAddr) -> IpAddr {
+        if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+            return ip;
+        }
+        if let IpAddr::V4(v4) = ip {
+            if v4.is_broadcast() {
+                return ip;
+            }
+        }
+        let n = self.ips.len() as u32 + 1;
+        *self.ips.entry(ip).or_insert_with(|| match ip {
+            IpAddr::V4(_) => Ipv4Addr::from(0x0a00_0000 + n).into(),
+            IpAddr::V6(_) => {
+                Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, (n >> 16) as u16, n as u16).into()
+            }
+        })
+    }
+}
+
+// RFC 1624：字段由old改为new后增量更新位于offset的校验和
+fn update_checksum(packet: &mut [u8], offset: usize, old: &[u8], new: &[u8]) {
+    let mut sum = !u16::from_be_bytes([packet[offset], packet[offset + 1]]) as u32;
+    for (o, n) in old.chunks_exact(2).zip(new.chunks_exact(2)) {
+        sum += !u16::from_be_bytes([o[0], o[1]]) as u32;
+        sum += u16::from_be_bytes([n[0], n[1]]) as u32;
+    }
+    let checksum = !fold_checksum(sum);
+    packet[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::meta_packet::ChecksumError;
+
+    #[test]
+    fn golden_files() {
+        for parser in GOLDEN_PARSERS.iter() {
+            for (pcap, result) in parser.cases(Path::new(GOLDEN_DIR)).unwrap() {
+                let expected = match fs::read_to_string(&result) {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let output = parser.run(&pcap).unwrap();
+                assert!(
+                    output == expected,
+                    "output of {} different from {}, run `cargo run --bin deepflow-agent-golden -- update --diff` to show the difference",
+                    pcap.display(),
+                    result.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn anonymize() {
+        let data = fs::read("resources/test/flow_generator/rtt-rtt-2.pcap").unwrap();
+        let mut anonymizer = Anonymizer::default();
+        let anonymized = anonymizer.anonymize_pcap(&data).unwrap();
+        assert_eq!(anonymized.len(), data.len());
+        assert_eq!(anonymizer.ips.len(), 2);
+        assert_eq!(anonymizer.macs.len(), 2);
+
+        let mapped: Vec<IpAddr> = vec![
+            Ipv4Addr::new(10, 0, 0, 1).into(),
+            Ipv4Addr::new(10, 0, 0, 2).into(),
+        ];
+        for (_, range) in pcap_records(&anonymized).unwrap() {
+            let packet = MetaPacket::from_raw(&anonymized[range], Duration::ZERO).unwrap();
+            assert!(mapped.contains(&packet.lookup_key.src_ip));
+            assert!(mapped.contains(&packet.lookup_key.dst_ip));
+            assert_eq!(packet.verify_checksum(), Ok::<(), ChecksumError>(()));
+        }
+    }
+}
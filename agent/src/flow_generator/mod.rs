@@ -19,8 +19,14 @@ mod error;
 mod flow_config;
 pub mod flow_map;
 mod flow_node;
+mod flow_snapshot;
 mod flow_state;
+#[doc(hidden)]
+pub mod fuzz;
+#[doc(hidden)]
+pub mod golden;
 mod packet_sequence; // Enterprise Edition Feature: packet-sequence
+mod parse_error_pcap;
 pub mod perf;
 mod protocol_logs;
 mod service_table;
@@ -30,17 +36,25 @@ pub use error::{Error, Result};
 pub use flow_config::{FlowMapConfig, FlowMapRuntimeConfig, FlowTimeout, TcpTimeout};
 pub use flow_map::FlowMap;
 use flow_node::{FlowMapKey, FlowNode, FlowTimeKey};
+pub use flow_snapshot::{export as export_flow_snapshot, FlowSnapshot, SnapshotFormat};
 pub use flow_state::FlowState;
 pub use packet_sequence::PacketSequenceParser; // Enterprise Edition Feature: packet-sequence
 pub use perf::L7RrtCache;
 pub use protocol_logs::{
-    dns_check_protocol, dubbo_check_protocol, http1_check_protocol, http2_check_protocol,
-    kafka_check_protocol, mqtt_check_protocol, mysql_check_protocol, redis_check_protocol,
+    check_protocol, l4_protocol_bitmap, AppLogs, AppProtoHead, AppProtoHeadEnum,
+    AppProtoLogsBaseInfo, AppProtoLogsData, AppProtoLogsInfo, AppProtoLogsInfoEnum,
+    AppProtoLogsParser, ClickhouseInfo, ClickhouseLog, DetectCounter, DnsInfo, DnsLog, DubboInfo,
+    DubboLog, HttpInfo, HttpLog, KafkaInfo, KafkaLog, L7LogParse, L7LogSampler, L7ProtocolDetector,
+    L7ResponseStatus, L7SessionId, LogMessageType, MetaAppProto, ModbusInfo, ModbusLog, MqttInfo,
+    MqttLog, MysqlInfo, MysqlLog, NtpInfo, NtpLog, RadiusInfo, RadiusLog, RedisInfo, RedisLog,
+    S7Info, S7Log, SnmpInfo, SnmpLog, TdsInfo, TdsLog, TlsInfo, TlsLog,
 };
 pub use protocol_logs::{
-    AppProtoHead, AppProtoHeadEnum, AppProtoLogsBaseInfo, AppProtoLogsData, AppProtoLogsInfo,
-    AppProtoLogsInfoEnum, AppProtoLogsParser, DnsLog, DubboLog, HttpLog, KafkaLog, L7LogParse,
-    LogMessageType, MetaAppProto, MqttLog, MysqlLog, RedisLog,
+    clickhouse_check_protocol, dns_check_protocol, dubbo_check_protocol, http1_check_protocol,
+    http2_check_protocol, kafka_check_protocol, modbus_check_protocol, mqtt_check_protocol,
+    mysql_check_protocol, ntp_check_protocol, radius_check_protocol, redis_check_protocol,
+    s7_check_protocol, set_custom_methods, snmp_check_protocol, tds_check_protocol,
+    tls_check_protocol,
 };
 
 use std::time::Duration;
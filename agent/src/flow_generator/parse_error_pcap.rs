@@ -0,0 +1,216 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Result},
+    path::Path,
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::common::{enums::LinkType, meta_packet::MetaPacket};
+use crate::config::L7ParseErrorPcapConfig;
+use crate::utils::{packet_pool::PacketBuffer, pcapng::PcapngWriter};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+struct RingPacket {
+    timestamp: Duration,
+    packet_len: u32,
+    raw: PacketBuffer,
+}
+
+// 流的应用解析失败后开始缓存最近的包，失败次数达到阈值时导出
+#[derive(Default)]
+pub struct PacketRing {
+    packets: VecDeque<RingPacket>,
+    fail_count: u32,
+    dumped: bool,
+}
+
+impl PacketRing {
+    pub fn push(&mut self, packet: &MetaPacket, ring_size: usize) {
+        if self.dumped || ring_size == 0 {
+            return;
+        }
+        let raw = match packet.raw {
+            Some(raw) if !raw.is_empty() => raw,
+            _ => return,
+        };
+        if self.packets.len() >= ring_size {
+            self.packets.pop_front();
+        }
+        self.packets.push_back(RingPacket {
+            timestamp: packet.lookup_key.timestamp,
+            packet_len: packet.packet_len as u32,
+            raw: PacketBuffer::from_slice(raw),
+        });
+    }
+}
+
+// 每小时导出的文件数有上限，避免解析异常时写满磁盘
+pub struct ParseErrorPcap {
+    hour_start: Duration,
+    dumped_in_hour: u32,
+}
+
+impl ParseErrorPcap {
+    pub fn new() -> Self {
+        Self {
+            hour_start: Duration::ZERO,
+            dumped_in_hour: 0,
+        }
+    }
+
+    // 返回true表示本次失败触发了导出
+    pub fn on_parse_error(
+        &mut self,
+        config: &L7ParseErrorPcapConfig,
+        ring: &mut PacketRing,
+        flow_id: u64,
+        timestamp: Duration,
+    ) -> bool {
+        if ring.dumped {
+            return false;
+        }
+        ring.fail_count += 1;
+        if ring.fail_count < config.fail_count || ring.packets.is_empty() {
+            return false;
+        }
+
+        if timestamp >= self.hour_start + HOUR || timestamp < self.hour_start {
+            self.hour_start = timestamp;
+            self.dumped_in_hour = 0;
+        }
+        // 当前小时配额用尽时不导出，也不再缓存该流的包
+        ring.dumped = true;
+        let packets = std::mem::take(&mut ring.packets);
+        if self.dumped_in_hour >= config.max_files_per_hour {
+            return false;
+        }
+        self.dumped_in_hour += 1;
+
+        let mut filename = config.file_directory.clone();
+        filename.push(format!("{}.pcapng", flow_id));
+        let comment = format!("flow_id={} l7_parse_errors={}", flow_id, ring.fail_count);
+        match Self::write(&filename, &packets, &comment) {
+            Ok(_) => {
+                info!(
+                    "dump {} packets of flow {} with {} l7 parse errors to {}",
+                    packets.len(),
+                    flow_id,
+                    ring.fail_count,
+                    filename.display()
+                );
+                true
+            }
+            Err(e) => {
+                warn!("failed to write {}: {}", filename.display(), e);
+                false
+            }
+        }
+    }
+
+    fn write(filename: &Path, packets: &VecDeque<RingPacket>, comment: &str) -> Result<()> {
+        if let Some(dir) = filename.parent() {
+            if !dir.exists() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let mut writer = PcapngWriter::new(BufWriter::new(File::create(filename)?), Some(comment))?;
+        let interface = writer.add_interface(LinkType::Ethernet, None)?;
+        for packet in packets {
+            writer.write_packet(
+                interface,
+                packet.timestamp,
+                &packet.raw,
+                packet.packet_len,
+                None,
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{path::PathBuf, process};
+
+    use crate::utils::pcapng::{read_blocks, BLOCK_ENHANCED_PACKET};
+
+    fn packet(raw: &[u8], timestamp: Duration) -> MetaPacket {
+        let mut packet = MetaPacket::default();
+        packet.raw = Some(raw);
+        packet.packet_len = raw.len();
+        packet.lookup_key.timestamp = timestamp;
+        packet
+    }
+
+    #[test]
+    fn dump_after_repeated_errors() {
+        let dir: PathBuf = std::env::temp_dir().join(format!("parse_error_pcap_{}", process::id()));
+        let config = L7ParseErrorPcapConfig {
+            enabled: true,
+            fail_count: 2,
+            ring_size: 2,
+            max_files_per_hour: 1,
+            file_directory: dir.clone(),
+        };
+        let mut pcap = ParseErrorPcap::new();
+
+        let mut ring = PacketRing::default();
+        for i in 0..3u8 {
+            ring.push(&packet(&[i; 20], Duration::from_secs(1)), config.ring_size);
+        }
+        assert_eq!(ring.packets.len(), 2);
+        assert!(!pcap.on_parse_error(&config, &mut ring, 1, Duration::from_secs(1)));
+        assert!(pcap.on_parse_error(&config, &mut ring, 1, Duration::from_secs(1)));
+        let data = fs::read(dir.join("1.pcapng")).unwrap();
+        let packets = read_blocks(&data)
+            .into_iter()
+            .filter(|(t, _)| *t == BLOCK_ENHANCED_PACKET)
+            .map(|(_, b)| b)
+            .collect::<Vec<_>>();
+        assert_eq!(packets.len(), 2);
+        // 20字节的包数据在20字节的包头之后
+        assert_eq!(&packets[0][20..40], &[1; 20]);
+        // 同一条流只导出一次
+        ring.push(&packet(&[0; 20], Duration::from_secs(2)), config.ring_size);
+        assert!(!pcap.on_parse_error(&config, &mut ring, 1, Duration::from_secs(2)));
+
+        // 超出每小时配额
+        let mut ring = PacketRing::default();
+        ring.push(&packet(&[0; 20], Duration::from_secs(3)), config.ring_size);
+        assert!(!pcap.on_parse_error(&config, &mut ring, 2, Duration::from_secs(3)));
+        assert!(!pcap.on_parse_error(&config, &mut ring, 2, Duration::from_secs(3)));
+        assert!(!dir.join("2.pcapng").exists());
+
+        let mut ring = PacketRing::default();
+        ring.push(
+            &packet(&[0; 20], Duration::from_secs(3700)),
+            config.ring_size,
+        );
+        assert!(!pcap.on_parse_error(&config, &mut ring, 3, Duration::from_secs(3700)));
+        assert!(pcap.on_parse_error(&config, &mut ring, 3, Duration::from_secs(3700)));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
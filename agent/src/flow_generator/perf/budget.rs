@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use super::stats::L7ParseTimeCounter;
+use crate::config::L7ParseBudgetConfig;
+
+// 流级别的解析熔断器，异常载荷连续导致解析超时时暂停该流的应用解析
+#[derive(Default)]
+pub struct L7ParseBreaker {
+    over_budget_count: u32,
+    break_until: Duration,
+}
+
+impl L7ParseBreaker {
+    pub fn is_broken(&self, timestamp: Duration) -> bool {
+        timestamp < self.break_until
+    }
+
+    // 返回true表示本次解析触发了熔断
+    pub fn on_parsed(
+        &mut self,
+        config: &L7ParseBudgetConfig,
+        elapsed: Duration,
+        timestamp: Duration,
+        counter: Option<&L7ParseTimeCounter>,
+    ) -> bool {
+        if config.packet_budget.is_zero() || elapsed <= config.packet_budget {
+            self.over_budget_count = 0;
+            return false;
+        }
+        if let Some(c) = counter {
+            c.over_budget_count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.over_budget_count += 1;
+        if self.over_budget_count < config.trip_count {
+            return false;
+        }
+        self.over_budget_count = 0;
+        self.break_until = timestamp + config.cooldown;
+        if let Some(c) = counter {
+            c.circuit_break_count.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn break_after_repeated_overrun() {
+        let config = L7ParseBudgetConfig {
+            sample_interval: 1,
+            packet_budget: Duration::from_millis(1),
+            trip_count: 2,
+            cooldown: Duration::from_secs(10),
+        };
+        let counter = L7ParseTimeCounter::default();
+        let mut breaker = L7ParseBreaker::default();
+        let slow = Duration::from_millis(2);
+        let fast = Duration::from_micros(10);
+
+        // 超时不连续时不熔断
+        assert!(!breaker.on_parsed(&config, slow, Duration::from_secs(1), Some(&counter)));
+        assert!(!breaker.on_parsed(&config, fast, Duration::from_secs(1), Some(&counter)));
+        assert!(!breaker.on_parsed(&config, slow, Duration::from_secs(2), Some(&counter)));
+        assert!(breaker.on_parsed(&config, slow, Duration::from_secs(3), Some(&counter)));
+        assert!(breaker.is_broken(Duration::from_secs(12)));
+        assert!(!breaker.is_broken(Duration::from_secs(13)));
+        assert_eq!(counter.over_budget_count.load(Ordering::Relaxed), 3);
+        assert_eq!(counter.circuit_break_count.load(Ordering::Relaxed), 1);
+
+        let config = L7ParseBudgetConfig {
+            packet_budget: Duration::ZERO,
+            ..config
+        };
+        for _ in 0..3 {
+            assert!(!breaker.on_parsed(&config, slow, Duration::from_secs(20), None));
+        }
+    }
+}
@@ -28,7 +28,7 @@ use crate::{
         flow::{FlowPerfStats, L7PerfStats, L7Protocol},
         meta_packet::MetaPacket,
     },
-    flow_generator::error::{Error, Result},
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
     utils::bytes::{read_u16_be, read_u16_le},
 };
 
@@ -87,12 +87,20 @@ impl L7FlowPerf for DnsPerfData {
             }
             IpProtocol::Tcp => {
                 if payload.len() <= DNS_TCP_PAYLOAD_OFFSET {
-                    return Err(Error::DNSPerfParseFailed("dns payload length error"));
+                    return Err(Error::DNSPerfParseFailed(
+                        ParseContext::new(ParseStage::Header)
+                            .mismatch(DNS_TCP_PAYLOAD_OFFSET + 1, payload.len()),
+                        "dns payload length error",
+                    ));
                 }
 
                 let size = read_u16_be(payload) as usize;
                 if size < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    return Err(Error::DNSPerfParseFailed("dns payload length error"));
+                    return Err(Error::DNSPerfParseFailed(
+                        ParseContext::new(ParseStage::Header)
+                            .mismatch(payload.len() - DNS_TCP_PAYLOAD_OFFSET, size),
+                        "dns payload length error",
+                    ));
                 }
                 self.decode_payload(
                     &payload[DNS_TCP_PAYLOAD_OFFSET..],
@@ -100,7 +108,12 @@ impl L7FlowPerf for DnsPerfData {
                     flow_id,
                 )?;
             }
-            _ => return Err(Error::DNSPerfParseFailed("dns translation type error")),
+            _ => {
+                return Err(Error::DNSPerfParseFailed(
+                    ParseStage::Header.into(),
+                    "dns translation type error",
+                ))
+            }
         }
 
         self.session_data.l7_proto = L7Protocol::Dns;
@@ -123,9 +136,11 @@ impl L7FlowPerf for DnsPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -160,6 +175,7 @@ impl L7FlowPerf for DnsPerfData {
                 code: self.session_data.status_code as u16,
                 rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -193,7 +209,10 @@ impl DnsPerfData {
 
     fn decode_payload(&mut self, payload: &[u8], timestamp: Duration, flow_id: u64) -> Result<()> {
         if payload.len() < DNS_HEADER_SIZE {
-            return Err(Error::DNSPerfParseFailed("protocol mismatch"));
+            return Err(Error::DNSPerfParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(DNS_HEADER_SIZE, payload.len()),
+                "protocol mismatch",
+            ));
         }
         self.session_data.id = read_u16_le(&payload[..DNS_HEADER_FLAGS_OFFSET]);
 
@@ -247,16 +266,20 @@ impl DnsPerfData {
             }
 
             let rrt = timestamp - req_timestamp;
-            if rrt > perf_stats.rrt_max {
-                perf_stats.rrt_max = rrt;
-            }
-            perf_stats.rrt_last = rrt;
-            perf_stats.rrt_sum += rrt;
-            perf_stats.rrt_count += 1;
+            perf_stats.update_rrt(
+                rrt,
+                self.session_data
+                    .rrt_cache
+                    .borrow()
+                    .rrt_upper_bound(L7Protocol::Dns),
+            );
             return Ok(());
         }
 
-        return Err(Error::DNSPerfParseFailed("decode dns payload failed"));
+        return Err(Error::DNSPerfParseFailed(
+            ParseContext::new(ParseStage::Header).at(DNS_HEADER_FLAGS_OFFSET),
+            "decode dns payload failed",
+        ));
     }
 }
 
@@ -306,6 +329,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(176754000),
                     rrt_last: Duration::from_nanos(4804000),
                     rrt_sum: Duration::from_nanos(181558000),
+                    rrt_outlier_count: 0,
                 }),
                 session_data: DnsSessionData {
                     id: 0,
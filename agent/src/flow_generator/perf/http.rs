@@ -28,20 +28,36 @@ use crate::{
         meta_packet::MetaPacket,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
         protocol_logs::{
             check_http_method, consts::*, get_http_request_version, get_http_resp_info,
-            is_http_v1_payload, AppProtoHead, Httpv2Headers, L7ResponseStatus, LogMessageType,
+            is_event_stream, is_http_v1_payload, AppProtoHead, Httpv2Headers, L7ResponseStatus,
+            LogMessageType,
         },
     },
 };
 
+// HTTP/1响应头之后持续发送的Body，如SSE或长时间未结束的响应，时间为包的时间戳
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HttpStreamState {
+    // Content-Type为text/event-stream
+    pub event_stream: bool,
+    pub status_code: u16,
+    // 响应首字节的时间
+    pub start_time: Duration,
+    pub last_time: Duration,
+    // 含响应头的累计字节数
+    pub bytes: u64,
+}
+
 struct HttpSessionData {
     // HTTPv2 Header
     httpv2_headers: Httpv2Headers,
+    // 最近一个HTTP/1响应，收到新请求时清除
+    stream: Option<HttpStreamState>,
 
     pub status: L7ResponseStatus,
     pub status_code: u16,
@@ -98,6 +114,16 @@ impl L7FlowPerf for HttpPerfData {
             self.session_data.l7_proto = L7Protocol::Http1;
             return Ok(());
         }
+        // 响应Body的后续包不是HTTP头，按流式响应计数，不视为解析失败
+        if self.session_data.l7_proto == L7Protocol::Http1
+            && meta.direction == PacketDirection::ServerToClient
+        {
+            if let Some(stream) = self.session_data.stream.as_mut() {
+                stream.last_time = meta.lookup_key.timestamp;
+                stream.bytes += payload.len() as u64;
+                return Ok(());
+            }
+        }
         if self
             .parse_http_v2(payload, meta.lookup_key.timestamp, meta.direction, flow_id)
             .is_ok()
@@ -107,7 +133,7 @@ impl L7FlowPerf for HttpPerfData {
             return Ok(());
         }
 
-        Err(Error::HttpHeaderParseFailed)
+        Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()))
     }
 
     fn data_updated(&self) -> bool {
@@ -124,9 +150,11 @@ impl L7FlowPerf for HttpPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -165,6 +193,7 @@ impl L7FlowPerf for HttpPerfData {
                 code: self.session_data.status_code,
                 rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -175,6 +204,7 @@ impl HttpPerfData {
     pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
         let session_data = HttpSessionData {
             httpv2_headers: Httpv2Headers::default(),
+            stream: None,
             status_code: 0,
             status: L7ResponseStatus::default(),
             has_log_data: false,
@@ -189,6 +219,19 @@ impl HttpPerfData {
         }
     }
 
+    pub fn stream(&self) -> Option<&HttpStreamState> {
+        self.session_data.stream.as_ref()
+    }
+
+    fn has_event_stream_header(lines: &[String]) -> bool {
+        lines.iter().skip(1).any(|line| match line.split_once(':') {
+            Some((key, value)) => {
+                key.trim().eq_ignore_ascii_case("content-type") && is_event_stream(value)
+            }
+            None => false,
+        })
+    }
+
     fn parse_lines(payload: &[u8]) -> Vec<String> {
         let mut lines = Vec::new();
         let mut line = String::new();
@@ -212,12 +255,12 @@ impl HttpPerfData {
         flow_id: u64,
     ) -> Result<()> {
         if !is_http_v1_payload(payload) {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
         }
 
         let lines = Self::parse_lines(payload);
         if lines.len() == 0 {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
         }
 
         let line_info = lines[0].as_str();
@@ -245,6 +288,13 @@ impl HttpPerfData {
 
             perf_stats.resp_count += 1;
             perf_stats.rrt_last = Duration::ZERO;
+            self.session_data.stream = Some(HttpStreamState {
+                event_stream: Self::has_event_stream_header(&lines),
+                status_code: status_code as u16,
+                start_time: timestamp,
+                last_time: timestamp,
+                bytes: payload.len() as u64,
+            });
 
             let req_timestamp = match self
                 .session_data
@@ -261,22 +311,26 @@ impl HttpPerfData {
             }
 
             let rrt = timestamp - req_timestamp;
-            if rrt > perf_stats.rrt_max {
-                perf_stats.rrt_max = rrt;
-            }
-            perf_stats.rrt_last = rrt;
-            perf_stats.rrt_sum += rrt;
-            perf_stats.rrt_count += 1;
+            perf_stats.update_rrt(
+                rrt,
+                self.session_data
+                    .rrt_cache
+                    .borrow()
+                    .rrt_upper_bound(self.session_data.l7_proto),
+            );
         } else {
             // HTTP请求行：GET /background.png HTTP/1.0
             let context: Vec<&str> = line_info.split(" ").collect();
             if context.len() != 3 {
-                return Err(Error::HttpHeaderParseFailed);
+                return Err(Error::HttpHeaderParseFailed(
+                    ParseContext::new(ParseStage::Request).mismatch(3, context.len()),
+                ));
             }
             check_http_method(context[0])?;
             get_http_request_version(context[2])?;
 
             self.session_data.msg_type = LogMessageType::Request;
+            self.session_data.stream = None;
 
             let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
             perf_stats.req_count += 1;
@@ -307,7 +361,7 @@ impl HttpPerfData {
 
         if self.session_data.httpv2_headers.flags & FLAG_HEADERS_PADDED != 0 {
             if u32::from(payload[0]) > self.session_data.httpv2_headers.frame_length {
-                return Err(Error::HttpHeaderParseFailed);
+                return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
             }
             l_offset += 1;
             end_index = payload[0] as usize;
@@ -318,13 +372,19 @@ impl HttpPerfData {
         }
 
         if payload.len() <= l_offset {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(
+                ParseContext::new(ParseStage::Payload).mismatch(l_offset + 1, payload.len()),
+            ));
         }
 
         end_index = self.session_data.httpv2_headers.frame_length as usize - end_index;
 
         if end_index > payload.len() || end_index < l_offset {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(
+                ParseContext::new(ParseStage::Payload)
+                    .at(l_offset)
+                    .mismatch(end_index, payload.len()),
+            ));
         }
 
         let frame_payload = &payload[l_offset..end_index];
@@ -334,7 +394,9 @@ impl HttpPerfData {
         let parse_rst = parser.parse(frame_payload);
 
         if let Err(_) = parse_rst {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(
+                ParseContext::new(ParseStage::Payload).at(l_offset),
+            ));
         }
 
         let header_list = parse_rst.unwrap();
@@ -353,7 +415,7 @@ impl HttpPerfData {
                 _ => {}
             }
         }
-        Err(Error::HttpHeaderParseFailed)
+        Err(Error::HttpHeaderParseFailed(ParseStage::Payload.into()))
     }
 
     fn has_magic(payload: &[u8]) -> bool {
@@ -383,7 +445,7 @@ impl HttpPerfData {
             // 参考：https://tools.ietf.org/html/rfc7540#section-6.5
             if self.session_data.httpv2_headers.frame_type == FRAME_HEADERS {
                 if self.session_data.httpv2_headers.stream_id == 0 {
-                    return Err(Error::HttpHeaderParseFailed);
+                    return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
                 }
 
                 // TODO 调用第三库解析有时会导致panic, 先默认返回成功
@@ -394,11 +456,13 @@ impl HttpPerfData {
             let offset = self.session_data.httpv2_headers.frame_length as usize + H2C_HEADER_SIZE;
 
             if frame_payload.len() <= offset {
-                return Err(Error::HttpHeaderParseFailed);
+                return Err(Error::HttpHeaderParseFailed(
+                    ParseContext::new(ParseStage::Header).mismatch(offset + 1, frame_payload.len()),
+                ));
             }
             frame_payload = &frame_payload[offset..];
         }
-        Err(Error::HttpHeaderParseFailed)
+        Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()))
     }
 
     // HTTPv2协议参考:https://tools.ietf.org/html/rfc7540
@@ -447,12 +511,13 @@ impl HttpPerfData {
             }
 
             let rrt = timestamp - req_timestamp;
-            if rrt > perf_stats.rrt_max {
-                perf_stats.rrt_max = rrt;
-            }
-            perf_stats.rrt_last = rrt;
-            perf_stats.rrt_sum += rrt;
-            perf_stats.rrt_count += 1;
+            perf_stats.update_rrt(
+                rrt,
+                self.session_data
+                    .rrt_cache
+                    .borrow()
+                    .rrt_upper_bound(self.session_data.l7_proto),
+            );
             perf_stats.resp_count += 1;
         } else {
             self.session_data.msg_type = LogMessageType::Request;
@@ -516,6 +581,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(84051000),
                         rrt_last: Duration::from_nanos(84051000),
                         rrt_sum: Duration::from_nanos(84051000),
+                        rrt_outlier_count: 0,
                     }),
                     session_data: HttpSessionData {
                         l7_proto: L7Protocol::Http1,
@@ -525,6 +591,7 @@ mod tests {
                         msg_type: LogMessageType::Response,
                         rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                         httpv2_headers: Httpv2Headers::default(),
+                        stream: None,
                     },
                 },
             ),
@@ -540,6 +607,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(2023000),
                         rrt_last: Duration::from_nanos(2023000),
                         rrt_sum: Duration::from_nanos(2023000),
+                        rrt_outlier_count: 0,
                     }),
                     session_data: HttpSessionData {
                         l7_proto: L7Protocol::Http2,
@@ -549,6 +617,7 @@ mod tests {
                         msg_type: LogMessageType::Response,
                         rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                         httpv2_headers: Httpv2Headers::default(),
+                        stream: None,
                     },
                 },
             ),
@@ -0,0 +1,256 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol},
+        meta_packet::MetaPacket,
+    },
+    flow_generator::{
+        error::{Error, ParseStage, Result},
+        perf::l7_rrt::L7RrtCache,
+        perf::stats::PerfStats,
+        perf::L7FlowPerf,
+        protocol_logs::{consts::*, AppProtoHead, L7ResponseStatus, LogMessageType},
+    },
+    utils::bytes::read_u16_be,
+};
+
+pub struct ModbusPerfData {
+    stats: Option<PerfStats>,
+
+    transaction_id: u16,
+    status_code: u16,
+    status: L7ResponseStatus,
+
+    has_log_data: bool,
+
+    l7_proto: L7Protocol,
+    msg_type: LogMessageType,
+
+    rrt_cache: Rc<RefCell<L7RrtCache>>,
+}
+
+impl PartialEq for ModbusPerfData {
+    fn eq(&self, other: &ModbusPerfData) -> bool {
+        self.stats == other.stats
+            && self.l7_proto == other.l7_proto
+            && self.msg_type == other.msg_type
+            && self.transaction_id == other.transaction_id
+            && self.status_code == other.status_code
+            && self.status == other.status
+            && self.has_log_data == other.has_log_data
+    }
+}
+
+impl Eq for ModbusPerfData {}
+
+impl fmt::Debug for ModbusPerfData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(stats) = self.stats.as_ref() {
+            write!(f, "status: {:?}", stats)?;
+        } else {
+            write!(f, "status: None")?;
+        };
+        write!(f, "l7_proto: {:?}", self.l7_proto)?;
+        write!(f, "msg_type: {:?}", self.msg_type)?;
+
+        write!(f, "transaction_id: {:?}", self.transaction_id)?;
+        write!(f, "status_code: {:?}", self.status_code)?;
+        write!(f, "status {:?}", self.status)?;
+        write!(f, "has_log_data: {:?}", self.has_log_data)
+    }
+}
+
+impl L7FlowPerf for ModbusPerfData {
+    fn parse(&mut self, packet: &MetaPacket, flow_id: u64) -> Result<()> {
+        if packet.lookup_key.proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+
+        let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+        if payload.len() < MODBUS_MBAP_HEADER_LEN + 1
+            || read_u16_be(&payload[2..]) != MODBUS_PROTOCOL_ID
+        {
+            return Err(Error::ModbusPerfParseFailed(ParseStage::Header.into()));
+        }
+        self.transaction_id = read_u16_be(payload);
+        let function_code = payload[MODBUS_MBAP_HEADER_LEN];
+
+        match packet.direction {
+            PacketDirection::ClientToServer => {
+                self.calc_request(packet.lookup_key.timestamp, flow_id);
+            }
+            PacketDirection::ServerToClient => {
+                let exception_code = if function_code & MODBUS_EXCEPTION_MASK != 0 {
+                    payload.get(MODBUS_MBAP_HEADER_LEN + 1).copied()
+                } else {
+                    None
+                };
+                self.calc_response(exception_code, packet.lookup_key.timestamp, flow_id)?;
+            }
+        }
+        self.l7_proto = L7Protocol::Modbus;
+        self.has_log_data = true;
+        Ok(())
+    }
+
+    fn data_updated(&self) -> bool {
+        self.stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        if let Some(stats) = self.stats.take() {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::Modbus,
+                l7: L7PerfStats {
+                    request_count: stats.req_count,
+                    response_count: stats.resp_count,
+                    rrt_count: stats.rrt_count,
+                    rrt_sum: stats.rrt_sum.as_micros() as u64,
+                    rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
+                    err_client_count: stats.req_err_count,
+                    err_server_count: stats.resp_err_count,
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        } else {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::Modbus,
+                l7: L7PerfStats {
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if self.l7_proto != L7Protocol::Modbus || !self.has_log_data {
+            return None;
+        }
+        self.has_log_data = false;
+
+        let rrt = self
+            .stats
+            .as_ref()
+            .map(|s| s.rrt_last.as_micros() as u64)
+            .unwrap_or_default();
+
+        Some((
+            AppProtoHead {
+                proto: self.l7_proto,
+                msg_type: self.msg_type,
+                status: self.status,
+                code: self.status_code,
+                rrt,
+                version: 0,
+                ..Default::default()
+            },
+            0,
+        ))
+    }
+}
+
+impl ModbusPerfData {
+    pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
+        Self {
+            stats: None,
+            transaction_id: 0,
+            status_code: 0,
+            status: L7ResponseStatus::default(),
+            has_log_data: false,
+            l7_proto: L7Protocol::default(),
+            msg_type: LogMessageType::default(),
+            rrt_cache,
+        }
+    }
+
+    fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
+        self.msg_type = LogMessageType::Request;
+        self.status = L7ResponseStatus::default();
+        self.status_code = 0;
+
+        let stats = self.stats.get_or_insert(PerfStats::default());
+        stats.req_count += 1;
+        stats.rrt_last = Duration::ZERO;
+        self.rrt_cache.borrow_mut().add_req_time(
+            flow_id,
+            Some(self.transaction_id as u32),
+            timestamp,
+        );
+    }
+
+    fn calc_response(
+        &mut self,
+        exception_code: Option<u8>,
+        timestamp: Duration,
+        flow_id: u64,
+    ) -> Result<()> {
+        self.msg_type = LogMessageType::Response;
+
+        let stats = self.stats.get_or_insert(PerfStats::default());
+        stats.resp_count += 1;
+        stats.rrt_last = Duration::ZERO;
+        match exception_code {
+            None => {
+                self.status = L7ResponseStatus::Ok;
+                self.status_code = 0;
+            }
+            Some(code) => {
+                self.status_code = code as u16;
+                match code {
+                    MODBUS_EXCEPTION_ILLEGAL_FUNCTION
+                    | MODBUS_EXCEPTION_ILLEGAL_DATA_ADDRESS
+                    | MODBUS_EXCEPTION_ILLEGAL_DATA_VALUE => {
+                        stats.req_err_count += 1;
+                        self.status = L7ResponseStatus::ClientError;
+                    }
+                    _ => {
+                        stats.resp_err_count += 1;
+                        self.status = L7ResponseStatus::ServerError;
+                    }
+                }
+            }
+        }
+
+        let req_timestamp = self
+            .rrt_cache
+            .borrow_mut()
+            .get_and_remove_l7_req_time(flow_id, Some(self.transaction_id as u32))
+            .ok_or(Error::L7ReqNotFound(1))?;
+        if timestamp < req_timestamp {
+            return Ok(());
+        }
+
+        let rrt = timestamp - req_timestamp;
+        stats.update_rrt(
+            rrt,
+            self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::Modbus),
+        );
+        Ok(())
+    }
+}
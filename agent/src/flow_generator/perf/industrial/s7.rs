@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol},
+        meta_packet::MetaPacket,
+    },
+    flow_generator::{
+        error::{Error, ParseContext, ParseStage, Result},
+        perf::l7_rrt::L7RrtCache,
+        perf::stats::PerfStats,
+        perf::L7FlowPerf,
+        protocol_logs::{consts::*, AppProtoHead, L7ResponseStatus, LogMessageType},
+    },
+    utils::bytes::read_u16_be,
+};
+
+pub struct S7PerfData {
+    stats: Option<PerfStats>,
+
+    pdu_ref: u16,
+    status_code: u16,
+    status: L7ResponseStatus,
+
+    has_log_data: bool,
+
+    l7_proto: L7Protocol,
+    msg_type: LogMessageType,
+
+    rrt_cache: Rc<RefCell<L7RrtCache>>,
+}
+
+impl PartialEq for S7PerfData {
+    fn eq(&self, other: &S7PerfData) -> bool {
+        self.stats == other.stats
+            && self.l7_proto == other.l7_proto
+            && self.msg_type == other.msg_type
+            && self.pdu_ref == other.pdu_ref
+            && self.status_code == other.status_code
+            && self.status == other.status
+            && self.has_log_data == other.has_log_data
+    }
+}
+
+impl Eq for S7PerfData {}
+
+impl fmt::Debug for S7PerfData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(stats) = self.stats.as_ref() {
+            write!(f, "status: {:?}", stats)?;
+        } else {
+            write!(f, "status: None")?;
+        };
+        write!(f, "l7_proto: {:?}", self.l7_proto)?;
+        write!(f, "msg_type: {:?}", self.msg_type)?;
+
+        write!(f, "pdu_ref: {:?}", self.pdu_ref)?;
+        write!(f, "status_code: {:?}", self.status_code)?;
+        write!(f, "status {:?}", self.status)?;
+        write!(f, "has_log_data: {:?}", self.has_log_data)
+    }
+}
+
+impl L7FlowPerf for S7PerfData {
+    fn parse(&mut self, packet: &MetaPacket, flow_id: u64) -> Result<()> {
+        if packet.lookup_key.proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+
+        let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+        // TPKT + COTP DT，连接建立阶段的报文不承载S7
+        if payload.len() < S7_TPKT_HEADER_LEN + 2
+            || payload[0] != S7_TPKT_VERSION
+            || payload[S7_TPKT_HEADER_LEN + 1] != S7_COTP_PDU_TYPE_DT
+        {
+            return Err(Error::S7PerfParseFailed(ParseStage::Header.into()));
+        }
+        let offset = S7_TPKT_HEADER_LEN + 1 + payload[S7_TPKT_HEADER_LEN] as usize;
+        if payload.len() < offset + S7_HEADER_LEN || payload[offset] != S7_PROTOCOL_ID {
+            return Err(Error::S7PerfParseFailed(
+                ParseContext::new(ParseStage::Header).at(offset),
+            ));
+        }
+        let header = &payload[offset..];
+        self.pdu_ref = read_u16_be(&header[4..]);
+
+        match (header[1], packet.direction) {
+            (S7_ROSCTR_JOB, PacketDirection::ClientToServer)
+            | (S7_ROSCTR_USERDATA, PacketDirection::ClientToServer) => {
+                self.calc_request(packet.lookup_key.timestamp, flow_id);
+            }
+            (S7_ROSCTR_USERDATA, PacketDirection::ServerToClient) => {
+                self.calc_response(None, packet.lookup_key.timestamp, flow_id)?;
+            }
+            (S7_ROSCTR_ACK, PacketDirection::ServerToClient)
+            | (S7_ROSCTR_ACK_DATA, PacketDirection::ServerToClient) => {
+                if header.len() < S7_ACK_HEADER_LEN {
+                    return Err(Error::S7PerfParseFailed(
+                        ParseContext::new(ParseStage::Response)
+                            .mismatch(S7_ACK_HEADER_LEN, header.len()),
+                    ));
+                }
+                let error = match (header[10], header[11]) {
+                    (0, 0) => None,
+                    (class, code) => Some((class, code)),
+                };
+                self.calc_response(error, packet.lookup_key.timestamp, flow_id)?;
+            }
+            _ => return Err(Error::S7PerfParseFailed(ParseStage::Session.into())),
+        }
+        self.l7_proto = L7Protocol::S7;
+        self.has_log_data = true;
+        Ok(())
+    }
+
+    fn data_updated(&self) -> bool {
+        self.stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        if let Some(stats) = self.stats.take() {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::S7,
+                l7: L7PerfStats {
+                    request_count: stats.req_count,
+                    response_count: stats.resp_count,
+                    rrt_count: stats.rrt_count,
+                    rrt_sum: stats.rrt_sum.as_micros() as u64,
+                    rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
+                    err_client_count: stats.req_err_count,
+                    err_server_count: stats.resp_err_count,
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        } else {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::S7,
+                l7: L7PerfStats {
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if self.l7_proto != L7Protocol::S7 || !self.has_log_data {
+            return None;
+        }
+        self.has_log_data = false;
+
+        let rrt = self
+            .stats
+            .as_ref()
+            .map(|s| s.rrt_last.as_micros() as u64)
+            .unwrap_or_default();
+
+        Some((
+            AppProtoHead {
+                proto: self.l7_proto,
+                msg_type: self.msg_type,
+                status: self.status,
+                code: self.status_code,
+                rrt,
+                version: 0,
+                ..Default::default()
+            },
+            0,
+        ))
+    }
+}
+
+impl S7PerfData {
+    pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
+        Self {
+            stats: None,
+            pdu_ref: 0,
+            status_code: 0,
+            status: L7ResponseStatus::default(),
+            has_log_data: false,
+            l7_proto: L7Protocol::default(),
+            msg_type: LogMessageType::default(),
+            rrt_cache,
+        }
+    }
+
+    fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
+        self.msg_type = LogMessageType::Request;
+        self.status = L7ResponseStatus::default();
+        self.status_code = 0;
+
+        let stats = self.stats.get_or_insert(PerfStats::default());
+        stats.req_count += 1;
+        stats.rrt_last = Duration::ZERO;
+        self.rrt_cache
+            .borrow_mut()
+            .add_req_time(flow_id, Some(self.pdu_ref as u32), timestamp);
+    }
+
+    fn calc_response(
+        &mut self,
+        error: Option<(u8, u8)>,
+        timestamp: Duration,
+        flow_id: u64,
+    ) -> Result<()> {
+        self.msg_type = LogMessageType::Response;
+
+        let stats = self.stats.get_or_insert(PerfStats::default());
+        stats.resp_count += 1;
+        stats.rrt_last = Duration::ZERO;
+        match error {
+            None => {
+                self.status = L7ResponseStatus::Ok;
+                self.status_code = 0;
+            }
+            Some((class, code)) => {
+                self.status_code = (class as u16) << 8 | code as u16;
+                match class {
+                    S7_ERROR_CLASS_NO_RESOURCES | S7_ERROR_CLASS_SERVICE_PROCESSING => {
+                        stats.resp_err_count += 1;
+                        self.status = L7ResponseStatus::ServerError;
+                    }
+                    _ => {
+                        stats.req_err_count += 1;
+                        self.status = L7ResponseStatus::ClientError;
+                    }
+                }
+            }
+        }
+
+        let req_timestamp = self
+            .rrt_cache
+            .borrow_mut()
+            .get_and_remove_l7_req_time(flow_id, Some(self.pdu_ref as u32))
+            .ok_or(Error::L7ReqNotFound(1))?;
+        if timestamp < req_timestamp {
+            return Ok(());
+        }
+
+        let rrt = timestamp - req_timestamp;
+        stats.update_rrt(rrt, self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::S7));
+        Ok(())
+    }
+}
@@ -19,11 +19,15 @@ use std::time::Duration;
 
 use lru::LruCache;
 
+use crate::common::flow::L7Protocol;
+
 const SUB_QUEUE_SIZE: usize = 1024;
 
 pub struct L7RrtCache {
     double_key_cache: LruCache<u64, VecDeque<(u32, Duration)>>,
     single_key_cache: LruCache<u64, Duration>,
+    // 按L7Protocol索引，为0时不限制
+    rrt_upper_bounds: [Duration; 256],
 }
 
 impl L7RrtCache {
@@ -31,9 +35,18 @@ impl L7RrtCache {
         L7RrtCache {
             double_key_cache: LruCache::new(cap),
             single_key_cache: LruCache::new(cap),
+            rrt_upper_bounds: [Duration::ZERO; 256],
         }
     }
 
+    pub fn set_rrt_upper_bounds(&mut self, bounds: &[Duration; 256]) {
+        self.rrt_upper_bounds = *bounds;
+    }
+
+    pub fn rrt_upper_bound(&self, protocol: L7Protocol) -> Duration {
+        self.rrt_upper_bounds[protocol as usize]
+    }
+
     fn double_key_cache_add_req_time(&mut self, key0: u64, key1: u32, timestamp: Duration) {
         if let Some(vec) = self.double_key_cache.get_mut(&key0) {
             match vec.binary_search_by_key(&key1, |&(a, _)| a) {
@@ -14,51 +14,65 @@
  * limitations under the License.
  */
 
+mod budget;
 mod dns;
 mod http;
+mod industrial;
 pub mod l7_rrt;
 mod mq;
+mod ntp;
+mod radius;
 mod rpc;
+mod snmp;
 mod sql;
 mod stats;
 pub mod tcp;
+mod tls;
 mod udp;
 
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::{Duration, Instant};
 
 use enum_dispatch::enum_dispatch;
+use log::debug;
 
 use super::app_table::AppTable;
 use super::error::{Error, Result};
 use super::protocol_logs::AppProtoHead;
 
 use crate::common::{
-    enums::IpProtocol,
+    enums::{IpProtocol, MtuAnomaly},
     flow::{FlowPerfStats, L4Protocol, L7Protocol},
     meta_packet::MetaPacket,
 };
+use crate::config::L7ParseBudgetConfig;
 
-use super::protocol_logs::{
-    dns_check_protocol, dubbo_check_protocol, http1_check_protocol, http2_check_protocol,
-    kafka_check_protocol, mqtt_check_protocol, mysql_check_protocol, redis_check_protocol,
-};
+use super::protocol_logs::{l4_protocol_bitmap, DetectCounter, L7ProtocolDetector};
 use {
     self::http::HttpPerfData,
+    budget::L7ParseBreaker,
     dns::DnsPerfData,
+    industrial::{ModbusPerfData, S7PerfData},
     mq::{KafkaPerfData, MqttPerfData},
+    ntp::NtpPerfData,
+    radius::RadiusPerfData,
     rpc::DubboPerfData,
-    sql::{MysqlPerfData, RedisPerfData},
+    snmp::SnmpPerfData,
+    sql::{ClickhousePerfData, MysqlPerfData, RedisPerfData, TdsPerfData},
+    stats::L7ParseTimeCounter,
     tcp::TcpPerf,
+    tls::TlsPerfData,
     udp::UdpPerf,
 };
 
+pub use self::http::HttpStreamState;
 pub use l7_rrt::L7RrtCache;
-pub use stats::FlowPerfCounter;
+pub use stats::{FlowPerfCounter, L7ParseErrorCounters};
 
 pub use dns::DNS_PORT;
+pub use udp::UdpRequestMatcher;
 
 const ART_MAX: Duration = Duration::from_secs(30);
 
@@ -67,6 +81,12 @@ pub trait L4FlowPerf {
     fn parse(&mut self, packet: &MetaPacket, direction: bool) -> Result<()>;
     fn data_updated(&self) -> bool;
     fn copy_and_reset_data(&mut self, flow_reversed: bool) -> FlowPerfStats;
+    // 统计周期结束，在copy_and_reset_data之前调用，用于拆分跨周期的统计
+    fn end_period(&mut self, _period_end: Duration) {}
+    // 采集点到服务端的往返时延，不随统计周期重置
+    fn server_rtt(&self) -> Duration;
+    // 流中检测到的MSS/MTU异常，不随统计周期重置
+    fn mtu_anomaly(&self) -> MtuAnomaly;
 }
 
 #[enum_dispatch(L7FlowPerfTable)]
@@ -92,6 +112,14 @@ pub enum L7FlowPerfTable {
     DubboPerfData,
     MysqlPerfData,
     HttpPerfData,
+    RadiusPerfData,
+    ModbusPerfData,
+    S7PerfData,
+    SnmpPerfData,
+    TdsPerfData,
+    ClickhousePerfData,
+    TlsPerfData,
+    NtpPerfData,
 }
 
 pub struct FlowPerf {
@@ -99,6 +127,9 @@ pub struct FlowPerf {
     l7: Option<L7FlowPerfTable>,
 
     rrt_cache: Rc<RefCell<L7RrtCache>>,
+    counter: Arc<FlowPerfCounter>,
+    l7_parse_counter: Option<Arc<L7ParseTimeCounter>>,
+    breaker: L7ParseBreaker,
 
     protocol_bitmap: u128,
     l7_protocol: L7Protocol,
@@ -106,11 +137,18 @@ pub struct FlowPerf {
     is_from_app: bool,
     is_success: bool,
     is_skip: bool,
+    // 未识别出协议的包数，超过L7ProtocolDetector.max_attempts后不再识别
+    check_attempts: u32,
+
+    // 统计HTTP连接复用：客户端SYN的seq和时间，SYN到首个请求的时延，连接上的累计请求数
+    syn_seq: u32,
+    syn_timestamp: Duration,
+    first_request_delay: Option<Duration>,
+    first_request_reported: bool,
+    connection_request_count: u32,
 }
 
 impl FlowPerf {
-    const PROTOCOL_CHECK_LIMIT: usize = 5;
-
     fn l7_new(protocol: L7Protocol, rrt_cache: Rc<RefCell<L7RrtCache>>) -> Option<L7FlowPerfTable> {
         match protocol {
             L7Protocol::Dns => Some(L7FlowPerfTable::from(DnsPerfData::new(rrt_cache.clone()))),
@@ -122,22 +160,56 @@ impl FlowPerf {
             L7Protocol::Http1 | L7Protocol::Http2 => {
                 Some(L7FlowPerfTable::from(HttpPerfData::new(rrt_cache.clone())))
             }
+            L7Protocol::Radius => Some(L7FlowPerfTable::from(RadiusPerfData::new(
+                rrt_cache.clone(),
+            ))),
+            L7Protocol::Modbus => Some(L7FlowPerfTable::from(ModbusPerfData::new(
+                rrt_cache.clone(),
+            ))),
+            L7Protocol::S7 => Some(L7FlowPerfTable::from(S7PerfData::new(rrt_cache.clone()))),
+            L7Protocol::Snmp => Some(L7FlowPerfTable::from(SnmpPerfData::new(rrt_cache.clone()))),
+            L7Protocol::Tds => Some(L7FlowPerfTable::from(TdsPerfData::new(rrt_cache.clone()))),
+            L7Protocol::Clickhouse => Some(L7FlowPerfTable::from(ClickhousePerfData::new(
+                rrt_cache.clone(),
+            ))),
+            L7Protocol::Tls => Some(L7FlowPerfTable::from(TlsPerfData::new(rrt_cache.clone()))),
+            L7Protocol::Ntp => Some(L7FlowPerfTable::from(NtpPerfData::new(rrt_cache.clone()))),
             _ => None,
         }
     }
 
-    fn _l7_check(&mut self, protocol: L7Protocol, packet: &MetaPacket) -> bool {
-        match protocol {
-            L7Protocol::Dns => dns_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Dubbo => dubbo_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Kafka => kafka_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Mqtt => mqtt_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Mysql => mysql_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Redis => redis_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Http1 => http1_check_protocol(&mut self.protocol_bitmap, packet),
-            L7Protocol::Http2 => http2_check_protocol(&mut self.protocol_bitmap, packet),
-            _ => false,
+    // 按采样间隔统计解析耗时，开启熔断时每次解析都需要计时
+    fn timed_l7_parse(
+        &mut self,
+        packet: &MetaPacket,
+        flow_id: u64,
+        budget: &L7ParseBudgetConfig,
+    ) -> Result<()> {
+        let sampled = self
+            .l7_parse_counter
+            .as_ref()
+            .map(|c| c.sample(budget.sample_interval))
+            .unwrap_or_default();
+        if !sampled && budget.packet_budget.is_zero() {
+            return self.l7.as_mut().unwrap().parse(packet, flow_id);
         }
+
+        let start = Instant::now();
+        let ret = self.l7.as_mut().unwrap().parse(packet, flow_id);
+        let elapsed = start.elapsed();
+
+        let counter = self.l7_parse_counter.as_deref();
+        if sampled {
+            counter.unwrap().record(elapsed);
+        }
+        let timestamp = packet.lookup_key.timestamp;
+        if self.breaker.on_parsed(budget, elapsed, timestamp, counter) {
+            debug!(
+                "flow {} {:?} parse exceeded budget {:?} for {} times, skip l7 parse for {:?}",
+                flow_id, self.l7_protocol, budget.packet_budget, budget.trip_count, budget.cooldown
+            );
+        }
+        ret
     }
 
     fn _l7_parse(
@@ -145,12 +217,16 @@ impl FlowPerf {
         packet: &MetaPacket,
         flow_id: u64,
         app_table: &mut AppTable,
+        budget: &L7ParseBudgetConfig,
     ) -> Result<()> {
         if self.is_skip {
             return Err(Error::L7ProtocolParseLimit);
         }
+        if self.breaker.is_broken(packet.lookup_key.timestamp) {
+            return Err(Error::L7ProtocolParseBreak);
+        }
 
-        let ret = self.l7.as_mut().unwrap().parse(packet, flow_id);
+        let ret = self.timed_l7_parse(packet, flow_id, budget);
         if !self.is_success {
             if ret.is_ok() {
                 app_table.set_protocol(packet, self.l7_protocol);
@@ -167,35 +243,40 @@ impl FlowPerf {
         packet: &MetaPacket,
         flow_id: u64,
         app_table: &mut AppTable,
+        budget: &L7ParseBudgetConfig,
+        detector: &L7ProtocolDetector,
     ) -> Result<()> {
         if self.is_skip {
             return Err(Error::L7ProtocolCheckLimit);
         }
 
-        let protocols = if packet.lookup_key.proto == IpProtocol::Tcp {
-            vec![
-                L7Protocol::Http1,
-                L7Protocol::Http2,
-                L7Protocol::Dubbo,
-                L7Protocol::Mysql,
-                L7Protocol::Redis,
-                L7Protocol::Kafka,
-                L7Protocol::Mqtt,
-                L7Protocol::Dns,
-            ]
-        } else {
-            vec![L7Protocol::Dns]
-        };
-
-        for i in protocols {
-            if self.protocol_bitmap & 1 << u8::from(i) == 0 {
-                continue;
-            }
-            if self._l7_check(i, packet) {
-                self.l7_protocol = i;
-                self.l7 = Self::l7_new(i, self.rrt_cache.clone());
-                return self._l7_parse(packet, flow_id, app_table);
-            }
+        let mut detect_counter = DetectCounter::default();
+        let detected = detector.detect(&mut self.protocol_bitmap, packet, &mut detect_counter);
+        if detect_counter.small_skipped > 0 {
+            self.counter
+                .l7_protocol_small_payload_skipped
+                .fetch_add(detect_counter.small_skipped as u64, Ordering::Relaxed);
+        }
+        if let Some(protocol) = detected {
+            self.l7_protocol = protocol;
+            self.l7 = Self::l7_new(protocol, self.rrt_cache.clone());
+            self.l7_parse_counter = self.counter.l7_parse_time(protocol);
+            return self._l7_parse(packet, flow_id, app_table, budget);
+        }
+        // 候选协议都因载荷过短跳过时没有实际识别，不计入识别次数
+        if detect_counter.checked == 0 {
+            return Err(Error::L7ProtocolUnknown);
+        }
+        self.check_attempts += 1;
+        let max_attempts = detector.max_attempts();
+        if max_attempts > 0 && self.check_attempts >= max_attempts {
+            debug!(
+                "flow {} l7 protocol unknown after {} attempts",
+                flow_id, self.check_attempts
+            );
+            app_table.set_protocol(packet, L7Protocol::Unknown);
+            self.is_skip = true;
+            return Err(Error::L7ProtocolUnknown);
         }
         self.is_skip = app_table.set_protocol(packet, L7Protocol::Unknown);
 
@@ -207,20 +288,29 @@ impl FlowPerf {
         packet: &MetaPacket,
         flow_id: u64,
         app_table: &mut AppTable,
+        budget: &L7ParseBudgetConfig,
+        detector: &L7ProtocolDetector,
     ) -> Result<()> {
         if self.l7.is_some() {
-            return self._l7_parse(packet, flow_id, app_table);
+            return self._l7_parse(packet, flow_id, app_table, budget);
         }
 
         if self.is_from_app {
             return Err(Error::L7ProtocolUnknown);
         }
 
-        if packet.l4_payload_len() < 2 {
+        // 过短的载荷(keepalive、TLS告警等)不参与识别，也不计入识别次数
+        let payload_len = packet.l4_payload_len();
+        if payload_len < detector.min_payload_len() {
+            if payload_len > 0 {
+                self.counter
+                    .l7_small_payload_skipped
+                    .fetch_add(1, Ordering::Relaxed);
+            }
             return Err(Error::L7ProtocolUnknown);
         }
 
-        return self.l7_check(packet, flow_id, app_table);
+        return self.l7_check(packet, flow_id, app_table, budget, detector);
     }
 
     pub fn new(
@@ -228,10 +318,14 @@ impl FlowPerf {
         l4_proto: L4Protocol,
         l7_proto: Option<L7Protocol>,
         counter: Arc<FlowPerfCounter>,
+        udp_request: Option<(UdpRequestMatcher, Duration)>,
     ) -> Option<Self> {
         let l4 = match l4_proto {
-            L4Protocol::Tcp => L4FlowPerfTable::from(TcpPerf::new(counter)),
-            L4Protocol::Udp => L4FlowPerfTable::from(UdpPerf::new()),
+            L4Protocol::Tcp => L4FlowPerfTable::from(TcpPerf::new(counter.clone())),
+            L4Protocol::Udp => L4FlowPerfTable::from(match udp_request {
+                Some((matcher, timeout)) => UdpPerf::with_request_matcher(matcher, timeout),
+                None => UdpPerf::new(),
+            }),
             _ => {
                 return None;
             }
@@ -242,23 +336,25 @@ impl FlowPerf {
         Some(Self {
             l4,
             l7: Self::l7_new(l7_protocol, rrt_cache.clone()),
-            protocol_bitmap: if l4_proto == L4Protocol::Tcp {
-                1 << u8::from(L7Protocol::Http1)
-                    | 1 << u8::from(L7Protocol::Http2)
-                    | 1 << u8::from(L7Protocol::Dns)
-                    | 1 << u8::from(L7Protocol::Mysql)
-                    | 1 << u8::from(L7Protocol::Redis)
-                    | 1 << u8::from(L7Protocol::Dubbo)
-                    | 1 << u8::from(L7Protocol::Kafka)
-                    | 1 << u8::from(L7Protocol::Mqtt)
+            protocol_bitmap: l4_protocol_bitmap(if l4_proto == L4Protocol::Tcp {
+                IpProtocol::Tcp
             } else {
-                1 << u8::from(L7Protocol::Dns)
-            },
+                IpProtocol::Udp
+            }),
             rrt_cache,
+            l7_parse_counter: counter.l7_parse_time(l7_protocol),
+            counter,
+            breaker: L7ParseBreaker::default(),
             l7_protocol,
             is_from_app: l7_proto.is_some(),
             is_success: false,
             is_skip: false,
+            check_attempts: 0,
+            syn_seq: 0,
+            syn_timestamp: Duration::ZERO,
+            first_request_delay: None,
+            first_request_reported: false,
+            connection_request_count: 0,
         })
     }
 
@@ -266,8 +362,11 @@ impl FlowPerf {
         let l7_protocol = l7_proto.unwrap_or(L7Protocol::Unknown);
         self.is_from_app = l7_proto.is_some();
         self.is_skip = false;
+        self.check_attempts = 0;
         self.is_success = false;
         self.l7 = Self::l7_new(l7_protocol, self.rrt_cache.clone());
+        self.l7_parse_counter = self.counter.l7_parse_time(l7_protocol);
+        self.breaker = L7ParseBreaker::default();
     }
 
     pub fn parse(
@@ -278,25 +377,51 @@ impl FlowPerf {
         l4_performance_enabled: bool,
         l7_performance_enabled: bool,
         app_table: &mut AppTable,
+        budget: &L7ParseBudgetConfig,
+        detector: &L7ProtocolDetector,
     ) -> Result<()> {
         if l4_performance_enabled {
             self.l4.parse(packet, is_first_packet_direction)?;
         }
         if l7_performance_enabled {
+            self.update_first_request(packet);
             // 抛出错误由flowMap.FlowPerfCounter处理
-            self.l7_parse(packet, flow_id, app_table)?;
+            self.l7_parse(packet, flow_id, app_table, budget, detector)?;
         }
         Ok(())
     }
 
+    // 客户端首个携带payload的包seq为SYN的seq+1，不依赖流的方向是否已校正
+    fn update_first_request(&mut self, packet: &MetaPacket) {
+        if self.first_request_delay.is_some() {
+            return;
+        }
+        let timestamp = packet.lookup_key.timestamp;
+        if packet.is_syn() {
+            if self.syn_timestamp.is_zero() {
+                self.syn_seq = packet.tcp_data.seq;
+                self.syn_timestamp = timestamp;
+            }
+        } else if !self.syn_timestamp.is_zero()
+            && packet.l4_payload_len() > 0
+            && packet.tcp_data.seq == self.syn_seq.wrapping_add(1)
+        {
+            self.first_request_delay = Some(timestamp.saturating_sub(self.syn_timestamp));
+        }
+    }
+
     pub fn copy_and_reset_perf_data(
         &mut self,
         flow_reversed: bool,
+        period_end: Duration,
         l7_timeout_count: u32,
         l4_performance_enabled: bool,
         l7_performance_enabled: bool,
     ) -> Option<FlowPerfStats> {
         let mut stats = None;
+        if l4_performance_enabled {
+            self.l4.end_period(period_end);
+        }
         if l4_performance_enabled && self.l4.data_updated() {
             stats.replace(self.l4.copy_and_reset_data(flow_reversed));
         }
@@ -316,9 +441,47 @@ impl FlowPerf {
             }
         }
 
+        if let (L7Protocol::Http1 | L7Protocol::Http2, Some(stats)) =
+            (self.l7_protocol, stats.as_mut())
+        {
+            self.connection_request_count += stats.l7.request_count;
+            stats.l7.connection_request_count = self.connection_request_count;
+            if !self.first_request_reported {
+                if let Some(delay) = self.first_request_delay {
+                    stats.l7.first_request_delay = delay.as_micros() as u32;
+                    self.first_request_reported = true;
+                }
+            }
+        }
+
         stats
     }
 
+    pub fn server_rtt(&self) -> Duration {
+        self.l4.server_rtt()
+    }
+
+    pub fn mtu_anomaly(&self) -> MtuAnomaly {
+        self.l4.mtu_anomaly()
+    }
+
+    pub fn flow_closed(&mut self) {
+        if let L4FlowPerfTable::UdpPerf(udp) = &mut self.l4 {
+            udp.flow_closed();
+        }
+    }
+
+    pub fn l7_protocol(&self) -> L7Protocol {
+        self.l7_protocol
+    }
+
+    pub fn http_stream(&self) -> Option<&HttpStreamState> {
+        match self.l7.as_ref() {
+            Some(L7FlowPerfTable::HttpPerfData(http)) => http.stream(),
+            _ => None,
+        }
+    }
+
     pub fn app_proto_head(&mut self, l7_performance_enabled: bool) -> Option<(AppProtoHead, u16)> {
         if !l7_performance_enabled {
             return None;
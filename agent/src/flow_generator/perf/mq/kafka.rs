@@ -16,17 +16,18 @@
 
 use std::cell::RefCell;
 use std::fmt;
+use std::mem;
 use std::rc::Rc;
 use std::time::Duration;
 
 use crate::{
     common::{
         enums::{IpProtocol, PacketDirection},
-        flow::{FlowPerfStats, L7PerfStats, L7Protocol},
+        flow::{FlowPerfStats, L7ApiPerfStats, L7PerfStats, L7Protocol},
         meta_packet::MetaPacket,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
@@ -60,6 +61,9 @@ pub struct KafkaPerfData {
     l7_proto: L7Protocol,
     msg_type: LogMessageType,
 
+    // 按api_key统计，便于区分Metadata、JoinGroup等请求的时延
+    api_stats: Vec<L7ApiPerfStats>,
+
     rrt_cache: Rc<RefCell<L7RrtCache>>,
 }
 
@@ -73,6 +77,7 @@ impl PartialEq for KafkaPerfData {
             && self.status_code == other.status_code
             && self.status == other.status
             && self.has_log_data == other.has_log_data
+            && self.api_stats == other.api_stats
     }
 }
 
@@ -135,9 +140,12 @@ impl L7FlowPerf for KafkaPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    api_stats: mem::take(&mut self.api_stats),
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -173,6 +181,7 @@ impl L7FlowPerf for KafkaPerfData {
                 code: self.status_code,
                 rrt: rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -194,6 +203,7 @@ impl KafkaPerfData {
             msg_type: LogMessageType::default(),
             status: L7ResponseStatus::default(),
             has_log_data: false,
+            api_stats: vec![],
             rrt_cache: rrt_cache,
         }
     }
@@ -211,18 +221,27 @@ impl KafkaPerfData {
     //  MetadataRequest | ProduceRequest | FetchRequest | ListOffsetRequest |......
     fn parse_request_header(&mut self, payload: &[u8], payload_len: u16) -> Result<()> {
         if payload.len() < KAFKA_REQ_HEADER_LEN {
-            return Err(Error::KafkaPerfParseFailed);
+            return Err(Error::KafkaPerfParseFailed(
+                ParseContext::new(ParseStage::Request)
+                    .mismatch(KAFKA_REQ_HEADER_LEN, payload.len()),
+            ));
         }
 
         let message_size = bytes::read_u32_be(payload);
 
         if message_size + KAFKA_REMAIN != payload_len as u32 {
-            return Err(Error::KafkaPerfParseFailed);
+            return Err(Error::KafkaPerfParseFailed(
+                ParseContext::new(ParseStage::Request)
+                    .mismatch(payload_len as usize, (message_size + KAFKA_REMAIN) as usize),
+            ));
         }
         let client_id_len = bytes::read_u16_be(&payload[12..]) as usize;
 
         if payload.len() < KAFKA_REQ_HEADER_LEN + client_id_len {
-            return Err(Error::KafkaPerfParseFailed);
+            return Err(Error::KafkaPerfParseFailed(
+                ParseContext::new(ParseStage::Request)
+                    .mismatch(KAFKA_REQ_HEADER_LEN + client_id_len, payload.len()),
+            ));
         }
 
         self.api_key = bytes::read_u16_be(&payload[4..]);
@@ -240,19 +259,40 @@ impl KafkaPerfData {
     //  MetadataResponse | ProduceResponse | FetchResponse | ListOffsetResponse |......
     fn parse_response_header(&mut self, payload: &[u8], payload_len: u16) -> Result<()> {
         if payload.len() < KAFKA_RESP_HEADER_LEN {
-            return Err(Error::KafkaPerfParseFailed);
+            return Err(Error::KafkaPerfParseFailed(
+                ParseContext::new(ParseStage::Response)
+                    .mismatch(KAFKA_RESP_HEADER_LEN, payload.len()),
+            ));
         }
 
         let message_size = bytes::read_u32_be(payload);
         if message_size + KAFKA_REMAIN != payload_len as u32 {
-            return Err(Error::KafkaPerfParseFailed);
+            return Err(Error::KafkaPerfParseFailed(
+                ParseContext::new(ParseStage::Response)
+                    .mismatch(payload_len as usize, (message_size + KAFKA_REMAIN) as usize),
+            ));
         }
 
         self.correlation_id = bytes::read_u32_be(&payload[4..]);
         Ok(())
     }
 
+    fn api_stats(&mut self, api_key: u16) -> &mut L7ApiPerfStats {
+        let index = match self.api_stats.iter().position(|s| s.api == api_key) {
+            Some(i) => i,
+            None => {
+                self.api_stats.push(L7ApiPerfStats {
+                    api: api_key,
+                    ..Default::default()
+                });
+                self.api_stats.len() - 1
+            }
+        };
+        &mut self.api_stats[index]
+    }
+
     fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
+        self.api_stats(self.api_key).request_count += 1;
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.rrt_last = Duration::ZERO;
         stats.req_count += 1;
@@ -290,6 +330,8 @@ impl KafkaPerfData {
         };
 
         let api_key = (req_timestmp_nanos & KAFKA_API_KEY_MASK_VALUE) >> KAFKA_API_KEY_OFFSET;
+        self.api_stats(api_key as u16).response_count += 1;
+        let stats = self.stats.get_or_insert(PerfStats::default());
         // 只支持对fetch命令解析返回码
         if api_key as i16 == KAFKA_FETCH && payload.len() > KAFKA_FETCH_STATUS_CODE_OFFSET {
             self.status_code = bytes::read_u16_be(&payload[12..]);
@@ -315,12 +357,16 @@ impl KafkaPerfData {
             - (req_timestmp_nanos & KAFKA_REQ_TIMESTAMP_MASK_VALUE);
 
         let rrt = Duration::from_nanos(rrt);
-        if rrt > stats.rrt_max {
-            stats.rrt_max = rrt;
-        }
-        stats.rrt_last = rrt;
-        stats.rrt_sum += rrt;
-        stats.rrt_count += 1;
+        let rrt = stats.update_rrt(
+            rrt,
+            self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::Kafka),
+        );
+
+        let rrt_us = rrt.as_micros() as u32;
+        let api_stats = self.api_stats(api_key as u16);
+        api_stats.rrt_count += 1;
+        api_stats.rrt_sum += rrt_us as u64;
+        api_stats.rrt_max = api_stats.rrt_max.max(rrt_us);
         return false;
     }
 
@@ -333,6 +379,7 @@ impl KafkaPerfData {
         self.msg_type = LogMessageType::default();
         self.status = L7ResponseStatus::default();
         self.has_log_data = false;
+        self.api_stats.clear();
     }
 }
 
@@ -382,6 +429,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(4941000),
                     rrt_last: Duration::from_nanos(4941000),
                     rrt_sum: Duration::from_nanos(4941000),
+                    rrt_outlier_count: 0,
                 },
             ),
             (
@@ -395,6 +443,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(504829000),
                     rrt_last: Duration::from_nanos(504829000),
                     rrt_sum: Duration::from_nanos(504829000),
+                    rrt_outlier_count: 0,
                 },
             ),
         ];
@@ -403,4 +452,34 @@ mod tests {
             assert_eq!(item.1, run(item.0), "parse pcap {} unexcepted", item.0);
         }
     }
+    #[test]
+    fn api_stats() {
+        let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
+        let mut kafka_perf_data = KafkaPerfData::new(rrt_cache);
+
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("kafka.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            if packet.lookup_key.dst_port == first_dst_port {
+                packet.direction = PacketDirection::ClientToServer;
+            } else {
+                packet.direction = PacketDirection::ServerToClient;
+            }
+            let _ = kafka_perf_data.parse(packet, 1608373855724393643);
+        }
+
+        let stats = kafka_perf_data.copy_and_reset_data(0);
+        assert_eq!(
+            stats.l7.api_stats,
+            vec![L7ApiPerfStats {
+                api: 18,
+                request_count: 1,
+                response_count: 1,
+                rrt_count: 1,
+                rrt_sum: 4941,
+                rrt_max: 4941,
+            }]
+        );
+    }
 }
@@ -29,14 +29,14 @@ use crate::{
         meta_packet::MetaPacket,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
         protocol_logs::{
             mqtt::{
                 mqtt_fixed_header, parse_connack_packet, parse_connect_packet, parse_status_code,
-                PacketKind,
+                PacketKind, MQTT_V5,
             },
             AppProtoHead, L7ResponseStatus, LogMessageType,
         },
@@ -110,9 +110,11 @@ impl L7FlowPerf for MqttPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -148,6 +150,7 @@ impl L7FlowPerf for MqttPerfData {
                 code: self.status_code as u16,
                 rrt: rrt,
                 version: self.proto_version,
+                ..Default::default()
             },
             0,
         ))
@@ -173,29 +176,39 @@ impl MqttPerfData {
     }
 
     fn parse_mqtt(&mut self, mut payload: &[u8], timestamp: Duration, flow_id: u64) -> Result<()> {
-        // 现在只支持 MQTT 3.1.1解析
-        if self.proto_version != 0 && self.proto_version != 4 {
-            warn!("cannot parse packet, perf parser only support to parse MQTT V3.1.1 packet");
-            return Err(Error::MqttPerfParseFailed);
+        // 现在只支持 MQTT 3.1.1及v5.0解析
+        if self.proto_version != 0 && self.proto_version != 4 && self.proto_version != MQTT_V5 {
+            warn!("cannot parse packet, perf parser only support to parse MQTT V3.1.1 and V5.0 packet");
+            return Err(Error::MqttPerfParseFailed(ParseStage::Session.into()));
         }
 
+        let total_len = payload.len();
         loop {
-            let (input, header) =
-                mqtt_fixed_header(payload).map_err(|_| Error::MqttPerfParseFailed)?;
+            let offset = total_len - payload.len();
+            let (input, header) = mqtt_fixed_header(payload).map_err(|_| {
+                Error::MqttPerfParseFailed(ParseContext::new(ParseStage::Header).at(offset))
+            })?;
             match header.kind {
                 PacketKind::Connect => {
                     let data = take(header.remaining_length as u32);
                     let (_, (version, _)) = data
                         .and_then(parse_connect_packet)
                         .parse(input)
-                        .map_err(|_| Error::MqttPerfParseFailed)?;
+                        .map_err(|_| {
+                            Error::MqttPerfParseFailed(
+                                ParseContext::new(ParseStage::Request).at(offset),
+                            )
+                        })?;
                     self.proto_version = version;
                     self.msg_type = LogMessageType::Request;
                     self.calc_request(timestamp, flow_id);
                 }
                 PacketKind::Connack => {
-                    let (_, return_code) =
-                        parse_connack_packet(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, return_code) = parse_connack_packet(input).map_err(|_| {
+                        Error::MqttPerfParseFailed(
+                            ParseContext::new(ParseStage::Response).at(offset),
+                        )
+                    })?;
                     self.status_code = return_code;
                     self.msg_type = LogMessageType::Response;
                     self.status = parse_status_code(return_code);
@@ -222,6 +235,7 @@ impl MqttPerfData {
                     self.msg_type = LogMessageType::Session;
                     self.calc_request(timestamp, flow_id);
                 }
+                PacketKind::TopicSummary => (),
             }
             if input.len() <= header.remaining_length as usize {
                 break;
@@ -260,10 +274,10 @@ impl MqttPerfData {
         };
 
         let rrt = timestamp - req_timestamp;
-        stats.rrt_max = stats.rrt_max.max(rrt);
-        stats.rrt_last = rrt;
-        stats.rrt_sum += rrt;
-        stats.rrt_count += 1;
+        stats.update_rrt(
+            rrt,
+            self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::Mqtt),
+        );
     }
 
     fn reset(&mut self) {
@@ -323,6 +337,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(256746000),
                     rrt_last: Duration::from_nanos(256746000),
                     rrt_sum: Duration::from_nanos(256746000),
+                    rrt_outlier_count: 0,
                 },
             ),
             (
@@ -336,6 +351,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(272795000),
                     rrt_last: Duration::from_nanos(272795000),
                     rrt_sum: Duration::from_nanos(272795000),
+                    rrt_outlier_count: 0,
                 },
             ),
         ];
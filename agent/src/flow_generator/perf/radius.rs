@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::super::protocol_logs::{consts::*, AppProtoHead, L7ResponseStatus, LogMessageType};
+use super::{stats::PerfStats, L7FlowPerf, L7RrtCache};
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol},
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+};
+
+#[derive(Clone)]
+struct RadiusSessionData {
+    pub identifier: u8,
+    pub status_code: u8,
+    pub status: L7ResponseStatus,
+    pub has_log_data: bool,
+
+    pub l7_proto: L7Protocol,
+    pub msg_type: LogMessageType,
+    rrt_cache: Rc<RefCell<L7RrtCache>>,
+}
+
+pub struct RadiusPerfData {
+    perf_stats: Option<PerfStats>,
+    session_data: RadiusSessionData,
+}
+
+impl Eq for RadiusPerfData {}
+
+impl PartialEq for RadiusPerfData {
+    fn eq(&self, other: &RadiusPerfData) -> bool {
+        self.perf_stats == other.perf_stats
+            && self.session_data.l7_proto == other.session_data.l7_proto
+            && self.session_data.msg_type == other.session_data.msg_type
+            && self.session_data.status == other.session_data.status
+            && self.session_data.has_log_data == other.session_data.has_log_data
+    }
+}
+
+impl fmt::Debug for RadiusPerfData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(perf_stats) = self.perf_stats.as_ref() {
+            write!(f, "perf_stats: {:?}", perf_stats)?;
+        } else {
+            write!(f, "perf_stats: None")?;
+        };
+        write!(f, "l7_proto: {:?}", self.session_data.l7_proto)?;
+        write!(f, "msg_type: {:?}", self.session_data.msg_type)?;
+        write!(f, "status {:?}", self.session_data.status)?;
+        write!(f, "has_log_data: {:?}", self.session_data.has_log_data)
+    }
+}
+
+impl L7FlowPerf for RadiusPerfData {
+    fn parse(&mut self, packet: &MetaPacket, flow_id: u64) -> Result<()> {
+        if packet.lookup_key.proto != IpProtocol::Udp {
+            return Err(Error::RadiusPerfParseFailed(ParseStage::Header.into()));
+        }
+        let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+        self.decode_payload(payload, packet.lookup_key.timestamp, flow_id)?;
+
+        self.session_data.l7_proto = L7Protocol::Radius;
+        self.session_data.has_log_data = true;
+
+        Ok(())
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        if let Some(stats) = self.perf_stats.take() {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::Radius,
+                l7: L7PerfStats {
+                    request_count: stats.req_count,
+                    response_count: stats.resp_count,
+                    rrt_count: stats.rrt_count,
+                    rrt_sum: stats.rrt_sum.as_micros() as u64,
+                    rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
+                    err_client_count: stats.req_err_count,
+                    err_server_count: stats.resp_err_count,
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        } else {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::Radius,
+                l7: L7PerfStats {
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if self.session_data.l7_proto != L7Protocol::Radius || !self.session_data.has_log_data {
+            return None;
+        }
+        self.session_data.has_log_data = false;
+
+        let rrt = self
+            .perf_stats
+            .as_ref()
+            .map(|s| s.rrt_last.as_micros() as u64)
+            .unwrap_or(0);
+        Some((
+            AppProtoHead {
+                proto: self.session_data.l7_proto,
+                msg_type: self.session_data.msg_type,
+                status: self.session_data.status,
+                code: self.session_data.status_code as u16,
+                rrt,
+                version: 0,
+                ..Default::default()
+            },
+            0,
+        ))
+    }
+}
+
+impl RadiusPerfData {
+    pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
+        let session_data = RadiusSessionData {
+            identifier: 0,
+            status_code: 0,
+            status: L7ResponseStatus::default(),
+            has_log_data: false,
+            l7_proto: L7Protocol::default(),
+            msg_type: LogMessageType::default(),
+            rrt_cache,
+        };
+        Self {
+            perf_stats: None,
+            session_data,
+        }
+    }
+
+    fn decode_payload(&mut self, payload: &[u8], timestamp: Duration, flow_id: u64) -> Result<()> {
+        if payload.len() < RADIUS_HEADER_LEN {
+            return Err(Error::RadiusPerfParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(RADIUS_HEADER_LEN, payload.len()),
+            ));
+        }
+        let code = payload[0];
+        self.session_data.identifier = payload[RADIUS_IDENTIFIER_OFFSET];
+
+        match code {
+            RADIUS_ACCESS_REQUEST | RADIUS_ACCOUNTING_REQUEST => {
+                self.session_data.msg_type = LogMessageType::Request;
+                self.session_data.status_code = 0;
+
+                let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+                perf_stats.req_count += 1;
+                perf_stats.rrt_last = Duration::ZERO;
+                self.session_data.rrt_cache.borrow_mut().add_req_time(
+                    flow_id,
+                    Some(self.session_data.identifier as u32),
+                    timestamp,
+                );
+                Ok(())
+            }
+            RADIUS_ACCESS_ACCEPT
+            | RADIUS_ACCESS_REJECT
+            | RADIUS_ACCOUNTING_RESPONSE
+            | RADIUS_ACCESS_CHALLENGE => {
+                self.session_data.msg_type = LogMessageType::Response;
+                self.session_data.status_code = code;
+
+                let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+                perf_stats.resp_count += 1;
+                // Access-Reject表示认证失败，按客户端错误统计
+                if code == RADIUS_ACCESS_REJECT {
+                    perf_stats.req_err_count += 1;
+                    self.session_data.status = L7ResponseStatus::ClientError;
+                } else {
+                    self.session_data.status = L7ResponseStatus::Ok;
+                }
+                perf_stats.rrt_last = Duration::ZERO;
+
+                let req_timestamp = self
+                    .session_data
+                    .rrt_cache
+                    .borrow_mut()
+                    .get_and_remove_l7_req_time(flow_id, Some(self.session_data.identifier as u32))
+                    .ok_or(Error::L7ReqNotFound(1))?;
+
+                if timestamp < req_timestamp {
+                    return Ok(());
+                }
+
+                let rrt = timestamp - req_timestamp;
+                perf_stats.update_rrt(
+                    rrt,
+                    self.session_data
+                        .rrt_cache
+                        .borrow()
+                        .rrt_upper_bound(L7Protocol::Radius),
+                );
+                Ok(())
+            }
+            _ => Err(Error::RadiusPerfParseFailed(ParseStage::Header.into())),
+        }
+    }
+}
@@ -113,9 +113,11 @@ impl L7FlowPerf for DubboPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -151,6 +153,7 @@ impl L7FlowPerf for DubboPerfData {
                 code: self.session_data.dubbo_header.status_code as u16,
                 rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -221,12 +224,13 @@ impl DubboPerfData {
         }
 
         let rrt = timestamp - req_timestamp;
-        if rrt > perf_stats.rrt_max {
-            perf_stats.rrt_max = rrt;
-        }
-        perf_stats.rrt_last = rrt;
-        perf_stats.rrt_sum += rrt;
-        perf_stats.rrt_count += 1;
+        perf_stats.update_rrt(
+            rrt,
+            self.session_data
+                .rrt_cache
+                .borrow()
+                .rrt_upper_bound(L7Protocol::Dubbo),
+        );
         false
     }
 
@@ -286,6 +290,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(4332000),
                     rrt_last: Duration::from_nanos(4332000),
                     rrt_sum: Duration::from_nanos(4332000),
+                    rrt_outlier_count: 0,
                 }),
                 session_data: DubboSessionData {
                     l7_proto: L7Protocol::Dubbo,
@@ -14,10 +14,14 @@
  * limitations under the License.
  */
 
+mod clickhouse;
 mod mysql;
 mod redis;
+mod tds;
 
+pub use clickhouse::ClickhousePerfData;
 pub use mysql::MysqlPerfData;
 pub use mysql::PORT as MYSQL_PORT;
 pub use redis::RedisPerfData;
 pub use redis::PORT as REDIS_PORT;
+pub use tds::TdsPerfData;
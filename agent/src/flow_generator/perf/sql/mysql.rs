@@ -21,12 +21,12 @@ use std::time::Duration;
 
 use crate::{
     common::{
-        enums::IpProtocol,
+        enums::{IpProtocol, PacketDirection},
         flow::{FlowPerfStats, L7PerfStats, L7Protocol},
         meta_packet::MetaPacket,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
@@ -48,6 +48,8 @@ pub struct MysqlPerfData {
     has_log_data: bool,
     decode_response: bool,
     has_response: bool,
+    // 服务端已请求LOAD DATA LOCAL的文件，等待文件发送结束后的响应
+    load_data: bool,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
 }
 
@@ -94,15 +96,35 @@ impl L7FlowPerf for MysqlPerfData {
 
         let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
 
+        // 客户端发送的文件内容不是MySQL请求，跳过以免误识别，
+        // 响应时延按查询请求到文件发送结束后的OK或ERR计算
+        if self.load_data {
+            if packet.direction == PacketDirection::ClientToServer {
+                return Ok(());
+            }
+            self.load_data = false;
+        } else if packet.direction == PacketDirection::ServerToClient
+            && self.decode_response
+            && !self.has_response
+            && MysqlHeader::local_infile_request(payload)
+        {
+            self.load_data = true;
+            return Ok(());
+        }
+
         let mut header = MysqlHeader::default();
         let offset = header.decode(payload);
         if offset < 0 {
-            return Err(Error::MysqlPerfParseFailed);
+            return Err(Error::MysqlPerfParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(HEADER_LEN + 1, payload.len()),
+            ));
         }
         let offset = offset as usize;
         let msg_type = header
             .check(packet.direction, offset, payload, self.l7_proto)
-            .ok_or(Error::MysqlPerfParseFailed)?;
+            .ok_or(Error::MysqlPerfParseFailed(
+                ParseContext::new(ParseStage::Header).at(offset),
+            ))?;
 
         match msg_type {
             LogMessageType::Request => {
@@ -158,9 +180,11 @@ impl L7FlowPerf for MysqlPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -196,6 +220,7 @@ impl L7FlowPerf for MysqlPerfData {
                 code: 0,
                 rrt: rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -213,6 +238,7 @@ impl MysqlPerfData {
             has_log_data: false,
             decode_response: false,
             has_response: false,
+            load_data: false,
             rrt_cache: rrt_cache,
         }
     }
@@ -264,12 +290,10 @@ impl MysqlPerfData {
         }
 
         let rrt = timestamp - req_timestamp;
-        if rrt > stats.rrt_max {
-            stats.rrt_max = rrt;
-        }
-        stats.rrt_last = rrt;
-        stats.rrt_sum += rrt;
-        stats.rrt_count += 1;
+        stats.update_rrt(
+            rrt,
+            self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::Mysql),
+        );
         false
     }
 
@@ -300,7 +324,7 @@ mod test {
 
     use super::*;
 
-    use crate::{common::enums::PacketDirection, utils::test::Capture};
+    use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/mysql";
 
@@ -338,6 +362,7 @@ mod test {
                         rrt_max: Duration::from_nanos(123000),
                         rrt_sum: Duration::from_nanos(373000),
                         rrt_last: Duration::ZERO,
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Mysql,
                     msg_type: LogMessageType::Request,
@@ -346,6 +371,7 @@ mod test {
                     has_log_data: true,
                     decode_response: true,
                     has_response: true,
+                    load_data: false,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
             ),
@@ -361,6 +387,7 @@ mod test {
                         rrt_max: Duration::from_nanos(146000),
                         rrt_sum: Duration::from_nanos(226000),
                         rrt_last: Duration::ZERO,
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Mysql,
                     msg_type: LogMessageType::Request,
@@ -369,6 +396,7 @@ mod test {
                     has_log_data: true,
                     decode_response: true,
                     has_response: true,
+                    load_data: false,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
             ),
@@ -384,6 +412,7 @@ mod test {
                         rrt_max: Duration::from_nanos(5355000),
                         rrt_sum: Duration::from_nanos(127090000),
                         rrt_last: Duration::from_nanos(692000),
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Mysql,
                     msg_type: LogMessageType::Response,
@@ -392,6 +421,7 @@ mod test {
                     has_log_data: true,
                     decode_response: true,
                     has_response: false,
+                    load_data: false,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
             ),
@@ -26,11 +26,11 @@ use crate::{
         meta_packet::MetaPacket,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
-        protocol_logs::{decode, AppProtoHead, L7ResponseStatus, LogMessageType},
+        protocol_logs::{decode, decode_redirect, AppProtoHead, L7ResponseStatus, LogMessageType},
     },
 };
 
@@ -43,6 +43,8 @@ pub struct RedisPerfData {
     active: u32,
     status: L7ResponseStatus,
     has_log_data: bool,
+    // 集群MOVED/ASK重定向数量，随stats一起清零
+    redirect_count: u32,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
 }
 
@@ -54,6 +56,7 @@ impl PartialEq for RedisPerfData {
             && self.active == other.active
             && self.status == other.status
             && self.has_log_data == other.has_log_data
+            && self.redirect_count == other.redirect_count
     }
 }
 
@@ -70,7 +73,8 @@ impl fmt::Debug for RedisPerfData {
         write!(f, "msg_type: {:?}", self.msg_type)?;
         write!(f, "active: {:?}", self.active)?;
         write!(f, "status {:?}", self.status)?;
-        write!(f, "has_log_data: {:?}", self.has_log_data)
+        write!(f, "has_log_data: {:?}", self.has_log_data)?;
+        write!(f, "redirect_count: {:?}", self.redirect_count)
     }
 }
 
@@ -85,12 +89,19 @@ impl L7FlowPerf for RedisPerfData {
         if self.l7_proto == L7Protocol::Unknown
             && packet.direction == PacketDirection::ServerToClient
         {
-            return Err(Error::RedisPerfParseFailed);
+            return Err(Error::RedisPerfParseFailed(ParseStage::Session.into()));
         }
         // Redis协议通过Redis请求来识别，对于请求报文格式严格检查，回应有分段的情况不会严格检查
         let (context, _, is_error_resp) =
-            decode(payload, packet.direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisPerfParseFailed)?;
+            decode(payload, packet.direction == PacketDirection::ClientToServer).ok_or(
+                Error::RedisPerfParseFailed(ParseContext::new(
+                    if packet.direction == PacketDirection::ClientToServer {
+                        ParseStage::Request
+                    } else {
+                        ParseStage::Response
+                    },
+                )),
+            )?;
         self.l7_proto = L7Protocol::Redis;
         self.has_log_data = true;
         if packet.direction == PacketDirection::ClientToServer {
@@ -111,6 +122,8 @@ impl L7FlowPerf for RedisPerfData {
     }
 
     fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        let redirect_count = self.redirect_count;
+        self.redirect_count = 0;
         if let Some(stats) = self.stats.take() {
             FlowPerfStats {
                 l7_protocol: L7Protocol::Redis,
@@ -120,9 +133,12 @@ impl L7FlowPerf for RedisPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_outlier_count: stats.rrt_outlier_count,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    redirect_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -157,6 +173,7 @@ impl L7FlowPerf for RedisPerfData {
                 code: 0,
                 rrt: rrt,
                 version: 0,
+                ..Default::default()
             },
             0,
         ))
@@ -172,6 +189,7 @@ impl RedisPerfData {
             active: 0,
             status: L7ResponseStatus::default(),
             has_log_data: false,
+            redirect_count: 0,
             rrt_cache: rrt_cache,
         }
     }
@@ -198,7 +216,10 @@ impl RedisPerfData {
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.resp_count += 1;
         self.msg_type = LogMessageType::Response;
-        if context.len() > 1 && context[0] == b'-' && is_error_resp {
+        if is_error_resp && decode_redirect(context).is_some() {
+            self.redirect_count += 1;
+            self.status = L7ResponseStatus::Redirect;
+        } else if context.len() > 1 && context[0] == b'-' && is_error_resp {
             stats.resp_err_count += 1;
             self.status = L7ResponseStatus::ServerError;
         } else {
@@ -223,12 +244,10 @@ impl RedisPerfData {
             return false;
         }
         let rrt = timestamp - req_timestamp;
-        if rrt > stats.rrt_max {
-            stats.rrt_max = rrt;
-        }
-        stats.rrt_last = rrt;
-        stats.rrt_sum += rrt;
-        stats.rrt_count += 1;
+        stats.update_rrt(
+            rrt,
+            self.rrt_cache.borrow().rrt_upper_bound(L7Protocol::Redis),
+        );
         false
     }
 
@@ -239,6 +258,7 @@ impl RedisPerfData {
         self.active = 0;
         self.status = L7ResponseStatus::default();
         self.has_log_data = false;
+        self.redirect_count = 0;
     }
 }
 
@@ -289,11 +309,13 @@ mod tests {
                         rrt_max: Duration::from_nanos(96000),
                         rrt_last: Duration::ZERO,
                         rrt_sum: Duration::from_nanos(592000),
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Redis,
                     status: L7ResponseStatus::ServerError,
                     active: 0,
                     has_log_data: true,
+                    redirect_count: 0,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
@@ -310,11 +332,13 @@ mod tests {
                         rrt_max: Duration::from_nanos(73000),
                         rrt_last: Duration::from_nanos(73000),
                         rrt_sum: Duration::from_nanos(73000),
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Redis,
                     active: 0,
                     status: L7ResponseStatus::ServerError,
                     has_log_data: true,
+                    redirect_count: 0,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
@@ -331,11 +355,13 @@ mod tests {
                         rrt_max: Duration::from_nanos(1209000),
                         rrt_last: Duration::from_nanos(1209000),
                         rrt_sum: Duration::from_nanos(1209000),
+                        rrt_outlier_count: 0,
                     }),
                     l7_proto: L7Protocol::Redis,
                     active: 0,
                     status: L7ResponseStatus::Ok,
                     has_log_data: true,
+                    redirect_count: 0,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                 },
@@ -14,11 +14,35 @@
  * limitations under the License.
  */
 
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
 use std::time::Duration;
 
+use crate::common::flow::L7Protocol;
+use crate::flow_generator::error::{Error, ParseStage, L7_PARSE_FAILURES};
 use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
 
+const L7_PERF_PROTOCOLS: [L7Protocol; 16] = [
+    L7Protocol::Http1,
+    L7Protocol::Http2,
+    L7Protocol::Dubbo,
+    L7Protocol::Mysql,
+    L7Protocol::Redis,
+    L7Protocol::Kafka,
+    L7Protocol::Mqtt,
+    L7Protocol::Dns,
+    L7Protocol::Radius,
+    L7Protocol::Modbus,
+    L7Protocol::S7,
+    L7Protocol::Snmp,
+    L7Protocol::Tds,
+    L7Protocol::Clickhouse,
+    L7Protocol::Tls,
+    L7Protocol::Ntp,
+];
+
 // 每次获取统计数据后此结构体都会被清零，不能在其中保存Flow级别的信息避免被清空
 #[derive(Debug, Default, PartialEq)]
 pub struct PerfStats {
@@ -30,18 +54,75 @@ pub struct PerfStats {
     pub rrt_max: Duration,
     pub rrt_last: Duration,
     pub rrt_sum: Duration,
+    pub rrt_outlier_count: u32,
+}
+
+impl PerfStats {
+    // 超过上限的RRT按上限计入sum和max并单独计数，rrt_last保留原值用于日志标记，返回计入统计的RRT
+    pub fn update_rrt(&mut self, rrt: Duration, upper_bound: Duration) -> Duration {
+        self.rrt_last = rrt;
+        let rrt = if !upper_bound.is_zero() && rrt > upper_bound {
+            self.rrt_outlier_count += 1;
+            upper_bound
+        } else {
+            rrt
+        };
+        if rrt > self.rrt_max {
+            self.rrt_max = rrt;
+        }
+        self.rrt_sum += rrt;
+        self.rrt_count += 1;
+        rrt
+    }
 }
 
-#[derive(Default)]
 pub struct FlowPerfCounter {
     closed: AtomicBool,
 
     // tcp stats
     pub ignored_packet_count: AtomicU64,
     pub invalid_packet_count: AtomicU64,
+    // 检测到MSS/MTU异常的流数
+    pub low_mss_count: AtomicU64,
+    pub retrans_cluster_count: AtomicU64,
 
     // L7 stats
     pub mismatched_response: AtomicU64,
+    // 载荷小于全局下限未参与识别的包数
+    pub l7_small_payload_skipped: AtomicU64,
+    // 载荷小于协议的最小长度而跳过的识别次数
+    pub l7_protocol_small_payload_skipped: AtomicU64,
+    pub l7_parse_time: Vec<(L7Protocol, Arc<L7ParseTimeCounter>)>,
+    pub l7_parse_error: L7ParseErrorCounters,
+}
+
+impl Default for FlowPerfCounter {
+    fn default() -> Self {
+        Self {
+            closed: AtomicBool::new(false),
+            ignored_packet_count: AtomicU64::new(0),
+            invalid_packet_count: AtomicU64::new(0),
+            low_mss_count: AtomicU64::new(0),
+            retrans_cluster_count: AtomicU64::new(0),
+            mismatched_response: AtomicU64::new(0),
+            l7_small_payload_skipped: AtomicU64::new(0),
+            l7_protocol_small_payload_skipped: AtomicU64::new(0),
+            l7_parse_time: L7_PERF_PROTOCOLS
+                .iter()
+                .map(|p| (*p, Arc::new(L7ParseTimeCounter::default())))
+                .collect(),
+            l7_parse_error: L7ParseErrorCounters::default(),
+        }
+    }
+}
+
+impl FlowPerfCounter {
+    pub fn l7_parse_time(&self, protocol: L7Protocol) -> Option<Arc<L7ParseTimeCounter>> {
+        self.l7_parse_time
+            .iter()
+            .find(|(p, _)| *p == protocol)
+            .map(|(_, c)| c.clone())
+    }
 }
 
 impl RefCountable for FlowPerfCounter {
@@ -49,6 +130,12 @@ impl RefCountable for FlowPerfCounter {
         let ignored = self.ignored_packet_count.swap(0, Ordering::Relaxed);
         let invalid = self.invalid_packet_count.swap(0, Ordering::Relaxed);
         let mismatched = self.mismatched_response.swap(0, Ordering::Relaxed);
+        let low_mss = self.low_mss_count.swap(0, Ordering::Relaxed);
+        let retrans_cluster = self.retrans_cluster_count.swap(0, Ordering::Relaxed);
+        let small_payload = self.l7_small_payload_skipped.swap(0, Ordering::Relaxed);
+        let protocol_small_payload = self
+            .l7_protocol_small_payload_skipped
+            .swap(0, Ordering::Relaxed);
 
         vec![
             (
@@ -66,6 +153,190 @@ impl RefCountable for FlowPerfCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(mismatched),
             ),
+            (
+                "low_mss_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(low_mss),
+            ),
+            (
+                "retrans_cluster_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(retrans_cluster),
+            ),
+            (
+                "l7_small_payload_skipped",
+                CounterType::Counted,
+                CounterValue::Unsigned(small_payload),
+            ),
+            (
+                "l7_protocol_small_payload_skipped",
+                CounterType::Counted,
+                CounterValue::Unsigned(protocol_small_payload),
+            ),
         ]
     }
 }
+
+// 单个应用协议的解析耗时，每sample-interval次解析采样一次，耗时单位为ns
+#[derive(Default)]
+pub struct L7ParseTimeCounter {
+    pub parse_count: AtomicU64,
+    pub sampled_count: AtomicU64,
+    pub sampled_time_sum: AtomicU64,
+    pub sampled_time_max: AtomicU64,
+    pub over_budget_count: AtomicU64,
+    pub circuit_break_count: AtomicU64,
+}
+
+impl L7ParseTimeCounter {
+    // 返回true表示本次解析需要采样
+    pub fn sample(&self, interval: u32) -> bool {
+        let count = self.parse_count.fetch_add(1, Ordering::Relaxed);
+        interval > 0 && count % interval as u64 == 0
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        let elapsed = elapsed.as_nanos() as u64;
+        self.sampled_count.fetch_add(1, Ordering::Relaxed);
+        self.sampled_time_sum.fetch_add(elapsed, Ordering::Relaxed);
+        self.sampled_time_max.fetch_max(elapsed, Ordering::Relaxed);
+    }
+}
+
+impl RefCountable for L7ParseTimeCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "parse_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.parse_count.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "sampled_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.sampled_count.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "sampled_time_sum",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.sampled_time_sum.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "sampled_time_max",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.sampled_time_max.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "over_budget_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.over_budget_count.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "circuit_break_count",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.circuit_break_count.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+// 单个解析失败类型在各阶段的失败次数
+#[derive(Default)]
+pub struct L7ParseErrorCounter {
+    stages: [AtomicU64; ParseStage::ALL.len()],
+}
+
+impl L7ParseErrorCounter {
+    pub fn record(&self, stage: ParseStage) {
+        self.stages[stage as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl RefCountable for L7ParseErrorCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        ParseStage::ALL
+            .iter()
+            .map(|s| {
+                (
+                    s.as_str(),
+                    CounterType::Counted,
+                    CounterValue::Unsigned(self.stages[*s as usize].swap(0, Ordering::Relaxed)),
+                )
+            })
+            .collect()
+    }
+}
+
+// 每个解析失败类型一个计数器，按类型分别注册到stats
+pub struct L7ParseErrorCounters(pub Vec<(&'static str, Arc<L7ParseErrorCounter>)>);
+
+impl Default for L7ParseErrorCounters {
+    fn default() -> Self {
+        Self(
+            L7_PARSE_FAILURES
+                .iter()
+                .map(|n| (*n, Arc::new(L7ParseErrorCounter::default())))
+                .collect(),
+        )
+    }
+}
+
+impl L7ParseErrorCounters {
+    // 非解析失败的错误不计数
+    pub fn record(&self, e: &Error) {
+        if let Some((name, context)) = e.parse_failure() {
+            if let Some((_, counter)) = self.0.iter().find(|(n, _)| *n == name) {
+                counter.record(context.stage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow_generator::error::ParseContext;
+
+    #[test]
+    fn parse_error_counters() {
+        let counters = L7ParseErrorCounters::default();
+        counters.record(&Error::MysqlLogParseFailed(ParseStage::Header.into()));
+        counters.record(&Error::MysqlLogParseFailed(
+            ParseContext::new(ParseStage::Response).at(3),
+        ));
+        counters.record(&Error::MysqlLogParseFailed(ParseStage::Header.into()));
+        counters.record(&Error::L7ProtocolUnknown);
+
+        let (_, mysql) = counters.0.iter().find(|(n, _)| *n == "mysql_log").unwrap();
+        let values = mysql
+            .get_counters()
+            .into_iter()
+            .map(|(n, _, v)| (n, v))
+            .collect::<Vec<_>>();
+        assert_eq!(values[0], ("header", CounterValue::Unsigned(2)));
+        assert_eq!(values[2], ("response", CounterValue::Unsigned(1)));
+        assert_eq!(values[4], ("session", CounterValue::Unsigned(0)));
+        assert_eq!(mysql.get_counters()[0].2, CounterValue::Unsigned(0));
+    }
+
+    #[test]
+    fn rrt_upper_bound() {
+        let mut stats = PerfStats::default();
+        let bound = Duration::from_secs(10);
+        assert_eq!(
+            stats.update_rrt(Duration::from_secs(2), bound),
+            Duration::from_secs(2)
+        );
+        assert_eq!(stats.update_rrt(Duration::from_secs(3600), bound), bound);
+        assert_eq!(stats.rrt_last, Duration::from_secs(3600));
+        assert_eq!(stats.rrt_max, bound);
+        assert_eq!(stats.rrt_sum, Duration::from_secs(12));
+        assert_eq!(stats.rrt_count, 2);
+        assert_eq!(stats.rrt_outlier_count, 1);
+
+        // 上限为0时不限制
+        stats.update_rrt(Duration::from_secs(3600), Duration::ZERO);
+        assert_eq!(stats.rrt_max, Duration::from_secs(3600));
+        assert_eq!(stats.rrt_outlier_count, 1);
+    }
+}
@@ -22,11 +22,12 @@ use bitflags::bitflags;
 use log::debug;
 
 use super::{FlowPerfCounter, L4FlowPerf, ART_MAX};
+use crate::flow_generator::STATISTICAL_INTERVAL;
 
 use crate::{
     common::{
-        enums::TcpFlags,
-        flow::{FlowPerfStats, L4Protocol},
+        enums::{MtuAnomaly, TcpFlags},
+        flow::{FlowPerfStats, L4Protocol, TcpPerfStats},
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader},
     },
@@ -36,6 +37,13 @@ use crate::{
 const SRT_MAX: Duration = Duration::from_secs(10);
 const RTT_FULL_MAX: Duration = Duration::from_secs(30);
 const RTT_MAX: Duration = Duration::from_secs(30);
+// 低于IPv4默认MSS时认为MSS过小
+const LOW_MSS_THRESHOLD: u16 = 536;
+// 同一方向连续重传相同长度的大包且期间没有新数据发出，疑似PMTU黑洞
+const RETRANS_CLUSTER_MIN_PAYLOAD: u16 = 1200;
+const RETRANS_CLUSTER_COUNT: u32 = 3;
+// 连续重复ACK达到快速重传的触发次数时计为一次突发
+const DUP_ACK_BURST_THRESHOLD: u32 = 3;
 
 fn adjust_rtt(d: Duration, max: Duration) -> Duration {
     if d > max {
@@ -92,10 +100,20 @@ struct SessionPeer {
     payload_len: u32,
     win_size: u16,
     win_scale: u8,
+    // 零窗口开始时间，窗口恢复后清零，跨统计周期保留
+    zero_win_timestamp: Duration,
 
     ack_received: bool, // ack_retrans check
     syn_received: bool,
 
+    // 最近连续重传的payload长度及次数
+    retrans_payload_len: u16,
+    retrans_payload_count: u32,
+
+    // 最近一个携带ACK标志的包的确认号，及其后连续重复ACK的次数
+    ack: u32,
+    dup_ack_count: u32,
+
     is_handshake_ack_packet: bool,
     srt_calculable: bool,
     rtt_calculable: bool,
@@ -335,6 +353,28 @@ impl SessionPeer {
         }
     }
 
+    // seq_list降序排列，第一个segment的结尾即已发送的最大seq
+    fn highest_seq(&self) -> Option<u32> {
+        if self.seq_list_len <= 0 {
+            return None;
+        }
+        Some(self.seq_list[0].seq + self.seq_list[0].len)
+    }
+
+    // 返回true表示本次重传使连续重传次数刚好达到阈值
+    fn check_retrans_cluster(&mut self, payload_len: u16) -> bool {
+        if payload_len < RETRANS_CLUSTER_MIN_PAYLOAD {
+            self.retrans_payload_count = 0;
+            return false;
+        }
+        if payload_len != self.retrans_payload_len {
+            self.retrans_payload_len = payload_len;
+            self.retrans_payload_count = 0;
+        }
+        self.retrans_payload_count += 1;
+        self.retrans_payload_count == RETRANS_CLUSTER_COUNT
+    }
+
     // 在TCP_STATE_ESTABLISHED阶段更新数据
     fn update_data(&mut self, p: &MetaPacket) {
         let header = &p.tcp_data;
@@ -344,6 +384,9 @@ impl SessionPeer {
             self.payload_len = 1;
         }
         self.seq = header.seq;
+        if header.flags.contains(TcpFlags::ACK) {
+            self.ack = header.ack;
+        }
         self.win_size = header.win_size;
         // winScale不能在这里更新p.winScale = tcpHeader.WinScale
     }
@@ -386,6 +429,36 @@ impl TimeStats {
     }
 }
 
+// 单方向的通告窗口统计，窗口大小已按win_scale换算
+#[derive(Default, Debug, PartialEq, Eq)]
+struct WinStats {
+    zero_win_duration: Duration,
+    min: u32,
+    sum: u64,
+    count: u32,
+}
+
+impl WinStats {
+    fn update(&mut self, win_size: u32) {
+        if self.count == 0 || self.min > win_size {
+            self.min = win_size;
+        }
+        self.sum += win_size as u64;
+        self.count += 1;
+    }
+}
+
+// 单方向的丢包及乱序信号，比重传数更能反映有损路径
+#[derive(Default, Debug, PartialEq, Eq)]
+struct LossStats {
+    // 该方向发出的重复ACK突发
+    dup_ack_burst: u32,
+    // 新数据的seq小于已发送的最大seq
+    out_of_order: u32,
+    // 重传的数据已被对端确认
+    spurious_retrans: u32,
+}
+
 // art---Application Response Time
 // 现有3个连续包PSH/ACK--ACK--PSH/ACK,其中第一个包是client端的请求包，
 // 后2个包是server端的应答包，art表示后2个包之间的时间间隔
@@ -596,8 +669,15 @@ impl PerfData {
 pub struct TcpPerf {
     ctrl_info: PerfControl,
     perf_data: PerfData,
+    win_stats: [WinStats; 2],
+    loss_stats: [LossStats; 2],
     counter: Arc<FlowPerfCounter>,
     handshaking: bool,
+    // SYN到SYN/ACK的时延，用于拆分应用时延
+    server_rtt: Duration,
+    mtu_anomaly: MtuAnomaly,
+    // 上一个统计周期的结束时间，零窗口时长只计入所在的统计周期
+    period_start: Duration,
 }
 
 impl TcpPerf {
@@ -605,8 +685,36 @@ impl TcpPerf {
         Self {
             ctrl_info: Default::default(),
             perf_data: Default::default(),
+            win_stats: Default::default(),
+            loss_stats: Default::default(),
             counter,
             handshaking: false,
+            server_rtt: Duration::ZERO,
+            mtu_anomaly: MtuAnomaly::empty(),
+            period_start: Duration::ZERO,
+        }
+    }
+
+    // 每种异常在流中只计数一次
+    fn set_mtu_anomaly(&mut self, anomaly: MtuAnomaly) {
+        if self.mtu_anomaly.contains(anomaly) {
+            return;
+        }
+        self.mtu_anomaly |= anomaly;
+        let counter = if anomaly == MtuAnomaly::LOW_MSS {
+            &self.counter.low_mss_count
+        } else {
+            &self.counter.retrans_cluster_count
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn check_mss(&mut self, p: &MetaPacket) {
+        // 未携带MSS选项时为0
+        let mss = p.tcp_data.mss;
+        if mss > 0 && mss < LOW_MSS_THRESHOLD {
+            debug!("low tcp mss {} at {}", mss, p.lookup_key);
+            self.set_mtu_anomaly(MtuAnomaly::LOW_MSS);
         }
     }
 
@@ -665,11 +773,28 @@ impl TcpPerf {
             return (false, false);
         }
 
+        let loss_stats = if fpd {
+            &mut self.loss_stats[0]
+        } else {
+            &mut self.loss_stats[1]
+        };
+        let highest_seq = same_dir.highest_seq();
         // 连接建立后，即ESTABLISHED阶段，用SeqArray判断包重传
         match same_dir.assert_seq_number(&p.tcp_data, p.payload_len) {
             PacketSeqType::Retrans => {
                 // established retrans
                 self.perf_data.calc_retrans(fpd);
+                let end = p.tcp_data.seq.wrapping_add(p.payload_len as u32);
+                if oppo_dir.ack != 0 && oppo_dir.ack.wrapping_sub(end) as i32 >= 0 {
+                    loss_stats.spurious_retrans += 1;
+                }
+                if same_dir.check_retrans_cluster(p.payload_len) {
+                    debug!(
+                        "tcp retrans cluster at payload len {} {}",
+                        p.payload_len, p.lookup_key
+                    );
+                    self.set_mtu_anomaly(MtuAnomaly::RETRANS_CLUSTER);
+                }
                 (false, true)
             }
             PacketSeqType::Error => {
@@ -678,7 +803,16 @@ impl TcpPerf {
                     .fetch_add(1, Ordering::Relaxed);
                 (true, false)
             }
-            _ => (false, false),
+            seq_type => {
+                // 有新数据发出说明路径可用
+                same_dir.retrans_payload_count = 0;
+                if !matches!(seq_type, PacketSeqType::NotCare)
+                    && highest_seq.map_or(false, |seq| p.tcp_data.seq < seq)
+                {
+                    loss_stats.out_of_order += 1;
+                }
+                (false, false)
+            }
         }
     }
 
@@ -733,6 +867,9 @@ impl TcpPerf {
                 let rtt = adjust_rtt(p.lookup_key.timestamp - oppo_dir.timestamp, RTT_MAX);
                 if !rtt.is_zero() {
                     self.perf_data.calc_rtt(rtt, fpd);
+                    if p.is_syn_ack() {
+                        self.server_rtt = rtt;
+                    }
                 }
                 is_opening = true;
             }
@@ -840,6 +977,28 @@ impl TcpPerf {
         if win_size == 0 {
             self.perf_data.calc_zero_win(fpd);
         }
+        // 零窗口持续时间按包累加，持续到同方向通告非零窗口为止，
+        // 只计入包所在统计周期的部分，之前的部分已在周期结束时计入或该周期无包未输出
+        let period_start = self.period_start.max(Duration::from_nanos(
+            (p.lookup_key.timestamp.as_nanos() / STATISTICAL_INTERVAL.as_nanos()
+                * STATISTICAL_INTERVAL.as_nanos()) as u64,
+        ));
+        let win_stats = if fpd {
+            &mut self.win_stats[0]
+        } else {
+            &mut self.win_stats[1]
+        };
+        if !same_dir.zero_win_timestamp.is_zero() {
+            win_stats.zero_win_duration += p
+                .lookup_key
+                .timestamp
+                .saturating_sub(same_dir.zero_win_timestamp.max(period_start));
+            same_dir.zero_win_timestamp = Duration::ZERO;
+        }
+        if win_size == 0 {
+            same_dir.zero_win_timestamp = p.lookup_key.timestamp;
+        }
+        win_stats.update(win_size);
 
         // PSH/URG
         if p.tcp_data.flags & TcpFlags::MASK == TcpFlags::PSH_ACK_URG {
@@ -888,6 +1047,11 @@ impl TcpPerf {
         // 计算ART
         if !self.handshaking {
             self.flow_established(p, fpd);
+            self.check_dup_ack(p, fpd);
+        }
+
+        if p.tcp_data.flags.contains(TcpFlags::SYN) {
+            self.check_mss(p);
         }
 
         // calculate syn/synack count
@@ -910,6 +1074,46 @@ impl TcpPerf {
         is_retrans
     }
 
+    // 纯ACK的确认号和窗口与同方向上一个包相同时为重复ACK，窗口更新不计入
+    fn check_dup_ack(&mut self, p: &MetaPacket, fpd: bool) {
+        let (same_dir, loss_stats) = if fpd {
+            (&mut self.ctrl_info.0, &mut self.loss_stats[0])
+        } else {
+            (&mut self.ctrl_info.1, &mut self.loss_stats[1])
+        };
+        if !p.is_ack() || p.tcp_data.ack != same_dir.ack || p.tcp_data.win_size != same_dir.win_size
+        {
+            same_dir.dup_ack_count = 0;
+            return;
+        }
+        same_dir.dup_ack_count += 1;
+        if same_dir.dup_ack_count == DUP_ACK_BURST_THRESHOLD {
+            loss_stats.dup_ack_burst += 1;
+        }
+    }
+
+    fn update_loss_stats(&mut self, stats: &mut TcpPerfStats, flow_reversed: bool) {
+        for (i, loss) in self.loss_stats.iter().enumerate() {
+            let peer = &mut stats.counts_peers[i ^ flow_reversed as usize];
+            peer.dup_ack_burst_count = loss.dup_ack_burst;
+            peer.ooo_count = loss.out_of_order;
+            peer.spurious_retrans_count = loss.spurious_retrans;
+        }
+        self.loss_stats = Default::default();
+    }
+
+    // 在update_perf_stats之后调用，此时counts_peers已按flow_reversed交换
+    fn update_win_stats(&mut self, stats: &mut TcpPerfStats, flow_reversed: bool) {
+        for (i, win) in self.win_stats.iter().enumerate() {
+            let peer = &mut stats.counts_peers[i ^ flow_reversed as usize];
+            peer.zero_win_duration = win.zero_win_duration.as_micros() as u32;
+            peer.win_min = win.min;
+            peer.win_sum = win.sum;
+            peer.win_count = win.count;
+        }
+        self.win_stats = Default::default();
+    }
+
     // 异常flag判断，方向识别，payload_len计算等
     // 去除功能不相关报文
     fn is_interested_packet(&self, p: &MetaPacket) -> bool {
@@ -977,15 +1181,39 @@ impl L4FlowPerf for TcpPerf {
     fn data_updated(&self) -> bool {
         let d = &self.perf_data;
         d.updated
+            || self.win_stats.iter().any(|w| w.count > 0)
+            || self.loss_stats.iter().any(|l| *l != LossStats::default())
+    }
+
+    // 统计周期结束时仍未恢复的零窗口，将本周期内的部分计入本周期
+    fn end_period(&mut self, period_end: Duration) {
+        let ctrl_info = [&self.ctrl_info.0, &self.ctrl_info.1];
+        for (win, ctrl) in self.win_stats.iter_mut().zip(ctrl_info) {
+            if !ctrl.zero_win_timestamp.is_zero() {
+                win.zero_win_duration +=
+                    period_end.saturating_sub(ctrl.zero_win_timestamp.max(self.period_start));
+            }
+        }
+        self.period_start = self.period_start.max(period_end);
     }
 
     fn copy_and_reset_data(&mut self, flow_reversed: bool) -> FlowPerfStats {
         let mut stats = FlowPerfStats::default();
         stats.l4_protocol = L4Protocol::Tcp;
         self.perf_data.update_perf_stats(&mut stats, flow_reversed);
+        self.update_win_stats(&mut stats.tcp, flow_reversed);
+        self.update_loss_stats(&mut stats.tcp, flow_reversed);
         self.perf_data = Default::default();
         stats
     }
+
+    fn server_rtt(&self) -> Duration {
+        self.server_rtt
+    }
+
+    fn mtu_anomaly(&self) -> MtuAnomaly {
+        self.mtu_anomaly
+    }
 }
 
 impl fmt::Debug for TcpPerf {
@@ -1744,6 +1972,155 @@ mod tests {
         perf.ctrl_info.0.update_data(&packet);
     }
 
+    #[test]
+    fn zero_win_duration() {
+        let mut perf = TcpPerf::new(Arc::new(FlowPerfCounter::default()));
+        let ack_packet = |win_size: u16, timestamp: u64| {
+            let mut packet = MetaPacket::empty();
+            packet.tcp_data = MetaPacketTcpHeader {
+                data_offset: 5,
+                flags: TcpFlags::ACK,
+                seq: 100,
+                ack: 200,
+                win_size,
+                ..Default::default()
+            };
+            packet.lookup_key = LookupKey {
+                timestamp: Duration::from_millis(timestamp),
+                ..Default::default()
+            };
+            packet
+        };
+
+        for (win_size, timestamp) in [(100, 1000), (0, 1200), (0, 1500), (50, 1700)] {
+            perf.parse(&ack_packet(win_size, timestamp), true).unwrap();
+        }
+        assert!(perf.data_updated());
+        perf.end_period(Duration::from_secs(2));
+        let report = perf.copy_and_reset_data(false);
+        let peer = &report.tcp.counts_peers[0];
+        assert_eq!(peer.zero_win_count, 2);
+        assert_eq!(peer.zero_win_duration, 500_000);
+        assert_eq!(peer.win_min, 0);
+        assert_eq!(peer.win_sum, 150);
+        assert_eq!(peer.win_count, 4);
+        assert_eq!(report.tcp.counts_peers[1].win_count, 0);
+
+        // 零窗口跨统计周期时按周期边界拆分，第5秒无包不输出统计，其间的时长不计入
+        let mut durations = vec![];
+        for (win_size, second) in [(0, 2), (0, 3), (0, 4), (0, 6), (20, 7)] {
+            perf.parse(&ack_packet(win_size, second * 1000 + 500), true)
+                .unwrap();
+            perf.end_period(Duration::from_secs(second + 1));
+            let report = perf.copy_and_reset_data(true);
+            durations.push(report.tcp.counts_peers[1].zero_win_duration);
+        }
+        assert_eq!(
+            durations,
+            [500_000, 1_000_000, 1_000_000, 1_000_000, 500_000]
+        );
+    }
+
+    #[test]
+    fn mtu_anomaly() {
+        let counter = Arc::new(FlowPerfCounter::default());
+        let mut perf = TcpPerf::new(counter.clone());
+        let packet = |flags: TcpFlags, seq: u32, payload_len: u16, mss: u16, timestamp: u64| {
+            let mut packet: MetaPacket = MiniMetaPacket {
+                data_offset: 5,
+                flags,
+                seq,
+                ack: if flags == TcpFlags::SYN { 0 } else { 1001 },
+                timestamp,
+                payload_len,
+                packet_len: payload_len as usize + 54,
+            }
+            .into();
+            packet.tcp_data.mss = mss;
+            packet
+        };
+
+        perf.parse(&packet(TcpFlags::SYN, 100, 0, 1460, 1), true)
+            .unwrap();
+        perf.parse(&packet(TcpFlags::SYN_ACK, 1000, 0, 500, 2), false)
+            .unwrap();
+        perf.parse(&packet(TcpFlags::ACK, 101, 0, 0, 3), true)
+            .unwrap();
+        assert_eq!(perf.mtu_anomaly(), MtuAnomaly::LOW_MSS);
+
+        perf.parse(&packet(TcpFlags::PSH_ACK, 101, 1400, 0, 4), true)
+            .unwrap();
+        perf.parse(&packet(TcpFlags::PSH_ACK, 1501, 1400, 0, 5), true)
+            .unwrap();
+        // 期间有新数据发出，重新计数
+        for (seq, timestamp) in [(101, 6), (101, 7), (2901, 8), (1501, 9), (1501, 10)] {
+            let _ = perf.parse(&packet(TcpFlags::PSH_ACK, seq, 1400, 0, timestamp), true);
+        }
+        assert_eq!(perf.mtu_anomaly(), MtuAnomaly::LOW_MSS);
+        // 小包重传不计数
+        let _ = perf.parse(&packet(TcpFlags::PSH_ACK, 101, 100, 0, 11), true);
+        for timestamp in 12..15 {
+            let _ = perf.parse(&packet(TcpFlags::PSH_ACK, 1501, 1400, 0, timestamp), true);
+        }
+        assert_eq!(
+            perf.mtu_anomaly(),
+            MtuAnomaly::LOW_MSS | MtuAnomaly::RETRANS_CLUSTER
+        );
+        // 每条流只计数一次
+        let _ = perf.parse(&packet(TcpFlags::PSH_ACK, 1501, 1400, 0, 15), true);
+        assert_eq!(counter.low_mss_count.load(Ordering::Relaxed), 1);
+        assert_eq!(counter.retrans_cluster_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn loss_signals() {
+        let mut perf = TcpPerf::new(Arc::new(FlowPerfCounter::default()));
+        let packet = |flags: TcpFlags, seq: u32, ack: u32, payload_len: u16, timestamp: u64| {
+            let packet: MetaPacket = MiniMetaPacket {
+                data_offset: 5,
+                flags,
+                seq,
+                ack,
+                timestamp,
+                payload_len,
+                packet_len: payload_len as usize + 54,
+            }
+            .into();
+            packet
+        };
+
+        perf.parse(&packet(TcpFlags::SYN, 100, 0, 0, 1), true)
+            .unwrap();
+        perf.parse(&packet(TcpFlags::SYN_ACK, 1000, 101, 0, 2), false)
+            .unwrap();
+        perf.parse(&packet(TcpFlags::ACK, 101, 1001, 0, 3), true)
+            .unwrap();
+        // 第二个包丢失，第三、四个包先到达，之后补发第二个包
+        for (seq, timestamp) in [(101, 4), (301, 5), (401, 6), (201, 7)] {
+            let _ = perf.parse(&packet(TcpFlags::PSH_ACK, seq, 1001, 100, timestamp), true);
+        }
+        // 服务端对缺失的数据连续发出重复ACK
+        for timestamp in 8..12 {
+            let _ = perf.parse(&packet(TcpFlags::ACK, 1001, 201, 0, timestamp), false);
+        }
+        perf.parse(&packet(TcpFlags::ACK, 1001, 501, 0, 12), false)
+            .unwrap();
+        // 已确认数据的重传
+        let _ = perf.parse(&packet(TcpFlags::PSH_ACK, 301, 1001, 100, 13), true);
+
+        assert!(perf.data_updated());
+        let report = perf.copy_and_reset_data(false);
+        let (client, server) = (&report.tcp.counts_peers[0], &report.tcp.counts_peers[1]);
+        assert_eq!(client.ooo_count, 1);
+        assert_eq!(client.spurious_retrans_count, 1);
+        assert_eq!(client.retrans_count, 1);
+        assert_eq!(server.dup_ack_burst_count, 1);
+        assert_eq!(client.dup_ack_burst_count + server.ooo_count, 0);
+
+        let report = perf.copy_and_reset_data(true);
+        assert_eq!(report.tcp.counts_peers[1].ooo_count, 0);
+    }
+
     #[test]
     fn preprocess() {
         let perf = TcpPerf::new(Arc::new(FlowPerfCounter::default()));
@@ -15,17 +15,188 @@
  */
 
 use std::cmp::max;
+use std::collections::VecDeque;
 use std::time::Duration;
 
 use crate::common::{
-    enums::PacketDirection,
-    flow::{FlowPerfStats, L4Protocol},
+    enums::{MtuAnomaly, PacketDirection},
+    flow::{FlowPerfStats, L4Protocol, UdpPerfStats},
     meta_packet::MetaPacket,
 };
+use crate::config::UdpPerfConfig;
 use crate::flow_generator::error::{Error, Result};
 
 use super::{L4FlowPerf, ART_MAX};
 
+// 每个流最多缓存的未响应请求数，超过时最早的请求计为未响应
+const PENDING_REQUEST_MAX: usize = 16;
+
+const DNS_HEADER_LEN: usize = 12;
+const NTP_PACKET_LEN: usize = 48;
+const NTP_MODE_CLIENT: u8 = 3;
+const NTP_MODE_SERVER: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UdpRequestProtocol {
+    // 按事务ID匹配
+    Dns,
+    // 响应的Origin Timestamp即请求的Transmit Timestamp
+    Ntp,
+    // 仅匹配握手阶段的Initial包
+    Quic,
+    // 按请求的先后顺序匹配
+    Generic,
+}
+
+// 按服务端端口识别的UDP请求响应协议，发往服务端端口的包为请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpRequestMatcher {
+    protocol: UdpRequestProtocol,
+    server_port: u16,
+}
+
+impl UdpRequestMatcher {
+    pub fn new(config: &UdpPerfConfig, src_port: u16, dst_port: u16) -> Option<Self> {
+        // 首包可能来自服务端，目的端口优先
+        for port in [dst_port, src_port] {
+            let protocol = if config.dns_ports.contains(&port) {
+                UdpRequestProtocol::Dns
+            } else if config.ntp_ports.contains(&port) {
+                UdpRequestProtocol::Ntp
+            } else if config.quic_ports.contains(&port) {
+                UdpRequestProtocol::Quic
+            } else if config.generic_ports.contains(&port) {
+                UdpRequestProtocol::Generic
+            } else {
+                continue;
+            };
+            return Some(Self {
+                protocol,
+                server_port: port,
+            });
+        }
+        None
+    }
+
+    // 返回请求或响应的匹配键，不是该协议的请求或响应时返回None
+    fn key(&self, payload: &[u8], is_request: bool) -> Option<u64> {
+        match self.protocol {
+            UdpRequestProtocol::Dns => {
+                if payload.len() < DNS_HEADER_LEN || (payload[2] & 0x80 != 0) == is_request {
+                    return None;
+                }
+                Some(u16::from_be_bytes([payload[0], payload[1]]) as u64)
+            }
+            UdpRequestProtocol::Ntp => {
+                if payload.len() < NTP_PACKET_LEN {
+                    return None;
+                }
+                let (mode, offset) = if is_request {
+                    (NTP_MODE_CLIENT, 40)
+                } else {
+                    (NTP_MODE_SERVER, 24)
+                };
+                if payload[0] & 0x7 != mode {
+                    return None;
+                }
+                Some(u64::from_be_bytes(
+                    payload[offset..offset + 8].try_into().unwrap(),
+                ))
+            }
+            UdpRequestProtocol::Quic => {
+                // 长包头、版本号非0(排除版本协商)，且包类型为Initial
+                if payload.len() < 5
+                    || payload[0] & 0xc0 != 0xc0
+                    || payload[1..5] == [0, 0, 0, 0]
+                    || payload[0] & 0x30 != 0
+                {
+                    return None;
+                }
+                Some(0)
+            }
+            UdpRequestProtocol::Generic => Some(0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UdpRequestState {
+    matcher: UdpRequestMatcher,
+    timeout: Duration,
+    // (匹配键, 请求时间)
+    pending: VecDeque<(u64, Duration)>,
+    last_rrt: Option<Duration>,
+    handshake_done: bool,
+    stats: UdpPerfStats,
+}
+
+impl UdpRequestState {
+    fn parse(&mut self, header: &MetaPacket) -> bool {
+        let timestamp = header.lookup_key.timestamp;
+        let mut updated = self.expire(timestamp.saturating_sub(self.timeout));
+
+        if self.handshake_done {
+            return updated;
+        }
+        let is_request = header.lookup_key.dst_port == self.matcher.server_port;
+        let key = match self
+            .matcher
+            .key(header.get_l4_payload().unwrap_or_default(), is_request)
+        {
+            Some(k) => k,
+            None => return updated,
+        };
+        if is_request {
+            // QUIC重传的Initial包不重复计数
+            if self.matcher.protocol == UdpRequestProtocol::Quic && !self.pending.is_empty() {
+                return updated;
+            }
+            if self.pending.len() >= PENDING_REQUEST_MAX {
+                self.pending.pop_front();
+                self.stats.unanswered_count += 1;
+            }
+            self.pending.push_back((key, timestamp));
+            self.stats.request_count += 1;
+            return true;
+        }
+
+        self.stats.response_count += 1;
+        if let Some(index) = self.pending.iter().position(|(k, _)| *k == key) {
+            let (_, request_time) = self.pending.remove(index).unwrap();
+            let rrt = timestamp.saturating_sub(request_time);
+            let rrt_us = rrt.as_micros() as u32;
+            self.stats.rrt_count += 1;
+            self.stats.rrt_sum += rrt_us as u64;
+            self.stats.rrt_max = self.stats.rrt_max.max(rrt_us);
+            if let Some(last) = self.last_rrt {
+                let jitter = if rrt > last { rrt - last } else { last - rrt }.as_micros() as u32;
+                self.stats.jitter_count += 1;
+                self.stats.jitter_sum += jitter as u64;
+                self.stats.jitter_max = self.stats.jitter_max.max(jitter);
+            }
+            self.last_rrt = Some(rrt);
+            if self.matcher.protocol == UdpRequestProtocol::Quic {
+                self.handshake_done = true;
+            }
+        }
+        true
+    }
+
+    // 早于deadline的请求计为未响应
+    fn expire(&mut self, deadline: Duration) -> bool {
+        let mut expired = false;
+        while let Some((_, t)) = self.pending.front() {
+            if *t >= deadline {
+                break;
+            }
+            self.pending.pop_front();
+            self.stats.unanswered_count += 1;
+            expired = true;
+        }
+        expired
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct UdpPerf {
     req_timestamp: Duration,
@@ -34,12 +205,36 @@ pub struct UdpPerf {
     art_count: u32,
     last_pkt_direction: PacketDirection,
     data_update_flag: bool,
+    request: Option<Box<UdpRequestState>>,
 }
 
 impl UdpPerf {
     pub fn new() -> Self {
         UdpPerf::default()
     }
+
+    pub fn with_request_matcher(matcher: UdpRequestMatcher, timeout: Duration) -> Self {
+        UdpPerf {
+            request: Some(Box::new(UdpRequestState {
+                matcher,
+                timeout,
+                pending: VecDeque::new(),
+                last_rrt: None,
+                handshake_done: false,
+                stats: UdpPerfStats::default(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    // 流结束时仍未响应的请求计为未响应
+    pub fn flow_closed(&mut self) {
+        if let Some(request) = self.request.as_mut() {
+            if request.expire(Duration::MAX) {
+                self.data_update_flag = true;
+            }
+        }
+    }
 }
 
 impl L4FlowPerf for UdpPerf {
@@ -48,6 +243,12 @@ impl L4FlowPerf for UdpPerf {
             return Err(Error::ZeroPayloadLen);
         }
 
+        if let Some(request) = self.request.as_mut() {
+            if request.parse(header) {
+                self.data_update_flag = true;
+            }
+        }
+
         let pkt_timestamp = header.lookup_key.timestamp;
         if header.direction == PacketDirection::ClientToServer {
             self.req_timestamp = pkt_timestamp;
@@ -78,9 +279,20 @@ impl L4FlowPerf for UdpPerf {
         stats.tcp.art_max = (self.art_max.as_nanos() / Duration::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_sum = (self.art_sum.as_nanos() / Duration::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_count = self.art_count;
+        if let Some(request) = self.request.as_mut() {
+            stats.udp = std::mem::take(&mut request.stats);
+        }
 
         stats
     }
+
+    fn server_rtt(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn mtu_anomaly(&self) -> MtuAnomaly {
+        MtuAnomaly::empty()
+    }
 }
 
 #[cfg(test)]
@@ -88,7 +300,8 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
-    use crate::utils::test::Capture;
+    use crate::common::{enums::IpProtocol, tap_port::TapPort};
+    use crate::utils::{packet_pool::PacketBuffer, test::Capture};
 
     use super::*;
 
@@ -160,6 +373,73 @@ mod tests {
         )
     }
 
+    fn packet(timestamp_ms: u64, is_request: bool, payload: &[u8]) -> MetaPacket<'static> {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = IpProtocol::Udp;
+        packet.lookup_key.timestamp = Duration::from_millis(timestamp_ms);
+        if is_request {
+            packet.lookup_key.src_port = 40000;
+            packet.lookup_key.dst_port = 53;
+            packet.direction = PacketDirection::ClientToServer;
+        } else {
+            packet.lookup_key.src_port = 53;
+            packet.lookup_key.dst_port = 40000;
+            packet.direction = PacketDirection::ServerToClient;
+        }
+        packet.tap_port = TapPort::from_ebpf(1);
+        packet.raw_from_ebpf = PacketBuffer::from_slice(payload);
+        packet.payload_len = payload.len() as u16;
+        packet
+    }
+
+    fn dns(id: u16, is_request: bool) -> Vec<u8> {
+        let mut payload = vec![0; DNS_HEADER_LEN];
+        payload[..2].copy_from_slice(&id.to_be_bytes());
+        if !is_request {
+            payload[2] = 0x80;
+        }
+        payload
+    }
+
+    #[test]
+    fn dns_request_response() {
+        let matcher = UdpRequestMatcher::new(&UdpPerfConfig::default(), 40000, 53).unwrap();
+        let mut perf = UdpPerf::with_request_matcher(matcher, Duration::from_secs(3));
+        // 响应乱序到达，按事务ID匹配
+        perf.parse(&packet(0, true, &dns(1, true)), false).unwrap();
+        perf.parse(&packet(1, true, &dns(2, true)), false).unwrap();
+        perf.parse(&packet(11, false, &dns(2, false)), false)
+            .unwrap();
+        perf.parse(&packet(20, false, &dns(1, false)), false)
+            .unwrap();
+        // 请求3超时未响应
+        perf.parse(&packet(100, true, &dns(3, true)), false)
+            .unwrap();
+        perf.parse(&packet(4000, true, &dns(4, true)), false)
+            .unwrap();
+        perf.parse(&packet(4030, false, &dns(4, false)), false)
+            .unwrap();
+
+        let stats = perf.copy_and_reset_data(false).udp;
+        assert_eq!(stats.request_count, 4);
+        assert_eq!(stats.response_count, 3);
+        assert_eq!(stats.unanswered_count, 1);
+        assert_eq!(stats.rrt_count, 3);
+        assert_eq!(stats.rrt_sum, 60000);
+        assert_eq!(stats.rrt_max, 30000);
+        // |20-10| + |30-20|
+        assert_eq!(stats.jitter_count, 2);
+        assert_eq!(stats.jitter_sum, 20000);
+        assert_eq!(stats.jitter_max, 10000);
+
+        perf.parse(&packet(5000, true, &dns(5, true)), false)
+            .unwrap();
+        perf.flow_closed();
+        let stats = perf.copy_and_reset_data(false).udp;
+        assert_eq!(stats.request_count, 1);
+        assert_eq!(stats.unanswered_count, 1);
+    }
+
     #[test]
     fn udp_report() {
         udp_perf_helper(
@@ -23,6 +23,7 @@ pub const FLAG_HEADERS_PADDED: u8 = 0x8;
 pub const FLAG_HEADERS_PRIORITY: u8 = 0x20;
 
 pub const HTTP_METHOD_AND_SPACE_MAX_OFFSET: usize = 9; // Method：OPTIONS
+pub const HTTP_METHOD_MAX_LEN: usize = 32; // 包括自定义Method
 pub const HTTP_V1_0_VERSION: &str = "HTTP/1.0";
 pub const HTTP_V1_1_VERSION: &str = "HTTP/1.1";
 pub const HTTP_V1_VERSION_LEN: usize = 8;
@@ -32,10 +33,39 @@ pub const HTTP_STATUS_CLIENT_ERROR_MIN: u16 = 400;
 pub const HTTP_STATUS_CLIENT_ERROR_MAX: u16 = 499;
 pub const HTTP_STATUS_SERVER_ERROR_MIN: u16 = 500;
 pub const HTTP_STATUS_SERVER_ERROR_MAX: u16 = 600;
-pub const HTTP_RESP_MIN_LEN: usize = 13; // 响应行："HTTP/1.1 200 "
+pub const HTTP_RESP_MIN_LEN: usize = 12; // 响应行："HTTP/1.1 200"
+pub const HTTP_STATUS_CODE_LEN: usize = 3;
 
 pub const HTTP_HOST_OFFSET: usize = 6;
 pub const HTTP_CONTENT_LENGTH_OFFSET: usize = 16;
+pub const HTTP_HEADER_END: &[u8] = b"\r\n\r\n";
+// 估算解码后Body长度时的最大解压输出，超过后按压缩比外推
+pub const HTTP_DECOMPRESS_MAX_OUTPUT: u64 = 256 * 1024;
+pub const HTTP_DECOMPRESS_BUFFER_SIZE: usize = 4096;
+pub const GZIP_HEADER_LEN: usize = 10;
+pub const GZIP_TRAILER_LEN: usize = 8;
+pub const GZIP_FLAG_HCRC: u8 = 0x02;
+pub const GZIP_FLAG_EXTRA: u8 = 0x04;
+pub const GZIP_FLAG_NAME: u8 = 0x08;
+pub const GZIP_FLAG_COMMENT: u8 = 0x10;
+// gRPC-Web帧：1字节flag + 4字节长度，flag最高位为1表示trailers帧
+pub const GRPC_WEB_FRAME_HEADER_LEN: usize = 5;
+pub const GRPC_WEB_FLAG_TRAILERS: u8 = 0x80;
+// Server-Sent Events响应的Content-Type
+pub const HTTP_CONTENT_TYPE_EVENT_STREAM: &str = "text/event-stream";
+// Elasticsearch REST请求的操作类型
+pub const ES_OP_SEARCH: &str = "search";
+pub const ES_OP_MSEARCH: &str = "msearch";
+pub const ES_OP_COUNT: &str = "count";
+pub const ES_OP_BULK: &str = "bulk";
+pub const ES_OP_DOC_GET: &str = "doc_get";
+pub const ES_OP_DOC_INDEX: &str = "doc_index";
+pub const ES_OP_DOC_CREATE: &str = "doc_create";
+pub const ES_OP_DOC_UPDATE: &str = "doc_update";
+pub const ES_OP_DOC_DELETE: &str = "doc_delete";
+pub const ES_OP_INDEX_CREATE: &str = "index_create";
+pub const ES_OP_INDEX_DELETE: &str = "index_delete";
+pub const ES_OP_OTHER: &str = "other";
 
 pub const HTTPV2_FRAME_HEADER_LENGTH: usize = 9;
 pub const HTTPV2_MAGIC_LENGTH: usize = 24;
@@ -43,6 +73,17 @@ pub const HTTPV2_MAGIC_PREFIX: &str = "PRI * HTTP";
 
 pub const HTTPV2_FRAME_DATA_TYPE: u8 = 0x00;
 pub const HTTPV2_FRAME_HEADERS_TYPE: u8 = 0x01;
+pub const HTTPV2_FRAME_PRIORITY_TYPE: u8 = 0x02;
+pub const HTTPV2_FRAME_RST_STREAM_TYPE: u8 = 0x03;
+pub const HTTPV2_FRAME_SETTINGS_TYPE: u8 = 0x04;
+pub const HTTPV2_FRAME_PUSH_PROMISE_TYPE: u8 = 0x05;
+pub const HTTPV2_FRAME_PING_TYPE: u8 = 0x06;
+pub const HTTPV2_FRAME_GOAWAY_TYPE: u8 = 0x07;
+pub const HTTPV2_FRAME_WINDOW_UPDATE_TYPE: u8 = 0x08;
+pub const HTTPV2_FRAME_CONTINUATION_TYPE: u8 = 0x09;
+// SETTINGS_MAX_FRAME_SIZE的初始值
+pub const HTTPV2_DEFAULT_MAX_FRAME_SIZE: u32 = 16384;
+pub const HTTPV2_FRAME_LENGTH_MAX: u32 = 0xffffff;
 
 pub const HTTPV2_FRAME_TYPE_MIN: u8 = 0x00;
 pub const HTTPV2_FRAME_TYPE_MAX: u8 = 0x09;
@@ -132,9 +173,17 @@ pub const INT_FLAGS_8: u8 = 0xfe;
 
 pub const INT_BASE_LEN: usize = 1;
 
+// OK packet
+pub const STATUS_FLAGS_LEN: usize = 2;
+pub const WARNINGS_LEN: usize = 2;
+pub const SERVER_SESSION_STATE_CHANGED: u16 = 0x4000;
+pub const SESSION_TRACK_SCHEMA: u8 = 1;
+
 pub const MYSQL_RESPONSE_CODE_OK: u8 = 0;
 pub const MYSQL_RESPONSE_CODE_ERR: u8 = 0xff;
 pub const MYSQL_RESPONSE_CODE_EOF: u8 = 0xfe;
+// LOAD DATA LOCAL INFILE时服务端请求客户端发送文件
+pub const MYSQL_RESPONSE_CODE_LOCAL_INFILE: u8 = 0xfb;
 
 pub const COM_QUIT: u8 = 1;
 pub const COM_INIT_DB: u8 = 2;
@@ -180,6 +229,7 @@ pub const DNS_NAME_MAX_SIZE: usize = 255;
 pub const DNS_HEADER_QDCOUNT_OFFSET: usize = 4;
 pub const DNS_HEADER_ANCOUNT_OFFSET: usize = 6;
 pub const DNS_HEADER_NSCOUNT_OFFSET: usize = 8;
+pub const DNS_HEADER_ARCOUNT_OFFSET: usize = 10;
 pub const QUESTION_CLASS_OFFSET: usize = 2;
 pub const QUESTION_CLASS_TYPE_SIZE: usize = 4;
 pub const RR_CLASS_OFFSET: usize = 2;
@@ -190,8 +240,225 @@ pub const DNS_TYPE_NS: u16 = 2;
 pub const DNS_TYPE_SOA: u16 = 6;
 pub const DNS_TYPE_WKS: u16 = 11;
 pub const DNS_TYPE_PTR: u16 = 12;
+pub const DNS_TYPE_MX: u16 = 15;
+pub const DNS_TYPE_TXT: u16 = 16;
 pub const DNS_TYPE_AAAA: u16 = 28;
+pub const DNS_TYPE_SRV: u16 = 33;
 pub const DNS_TYPE_DNAME: u16 = 39;
+pub const DNS_TYPE_OPT: u16 = 41;
 pub const DNS_TYPE_WKS_LENGTH: usize = 5;
 pub const DNS_TYPE_PTR_LENGTH: usize = 2;
+// preference(2B)
+pub const DNS_TYPE_MX_NAME_OFFSET: usize = 2;
+// priority(2B), weight(2B), port(2B)
+pub const DNS_TYPE_SRV_WEIGHT_OFFSET: usize = 2;
+pub const DNS_TYPE_SRV_PORT_OFFSET: usize = 4;
+pub const DNS_TYPE_SRV_NAME_OFFSET: usize = 6;
 pub const DOMAIN_NAME_SPLIT: char = ';';
+
+// radius constants
+pub const RADIUS_AUTH_PORT: u16 = 1812;
+pub const RADIUS_ACCT_PORT: u16 = 1813;
+
+pub const RADIUS_HEADER_LEN: usize = 20;
+pub const RADIUS_IDENTIFIER_OFFSET: usize = 1;
+pub const RADIUS_LENGTH_OFFSET: usize = 2;
+pub const RADIUS_MAX_LEN: usize = 4096;
+pub const RADIUS_ATTR_HEADER_LEN: usize = 2;
+
+pub const RADIUS_ACCESS_REQUEST: u8 = 1;
+pub const RADIUS_ACCESS_ACCEPT: u8 = 2;
+pub const RADIUS_ACCESS_REJECT: u8 = 3;
+pub const RADIUS_ACCOUNTING_REQUEST: u8 = 4;
+pub const RADIUS_ACCOUNTING_RESPONSE: u8 = 5;
+pub const RADIUS_ACCESS_CHALLENGE: u8 = 11;
+
+pub const RADIUS_ATTR_USER_NAME: u8 = 1;
+pub const RADIUS_ATTR_NAS_IP_ADDRESS: u8 = 4;
+pub const RADIUS_ATTR_NAS_IPV6_ADDRESS: u8 = 95;
+
+// modbus/tcp constants
+pub const MODBUS_PORT: u16 = 502;
+pub const MODBUS_MBAP_HEADER_LEN: usize = 7;
+pub const MODBUS_PROTOCOL_ID: u16 = 0;
+// MBAP长度字段包含unit id和PDU，PDU最大253字节
+pub const MODBUS_MAX_LENGTH: usize = 254;
+pub const MODBUS_EXCEPTION_MASK: u8 = 0x80;
+
+pub const MODBUS_EXCEPTION_ILLEGAL_FUNCTION: u8 = 1;
+pub const MODBUS_EXCEPTION_ILLEGAL_DATA_ADDRESS: u8 = 2;
+pub const MODBUS_EXCEPTION_ILLEGAL_DATA_VALUE: u8 = 3;
+
+// s7comm constants
+pub const S7_PORT: u16 = 102;
+pub const S7_TPKT_VERSION: u8 = 3;
+pub const S7_TPKT_HEADER_LEN: usize = 4;
+pub const S7_COTP_PDU_TYPE_DT: u8 = 0xf0;
+pub const S7_PROTOCOL_ID: u8 = 0x32;
+pub const S7_HEADER_LEN: usize = 10;
+pub const S7_ACK_HEADER_LEN: usize = 12;
+
+pub const S7_ROSCTR_JOB: u8 = 1;
+pub const S7_ROSCTR_ACK: u8 = 2;
+pub const S7_ROSCTR_ACK_DATA: u8 = 3;
+pub const S7_ROSCTR_USERDATA: u8 = 7;
+
+pub const S7_ERROR_CLASS_NO_RESOURCES: u8 = 0x83;
+pub const S7_ERROR_CLASS_SERVICE_PROCESSING: u8 = 0x84;
+
+// snmp constants
+pub const SNMP_PORT: u16 = 161;
+pub const SNMP_TRAP_PORT: u16 = 162;
+pub const SNMP_MAX_LEN: usize = 65507;
+
+pub const SNMP_VERSION_1: u8 = 0;
+pub const SNMP_VERSION_2C: u8 = 1;
+pub const SNMP_VERSION_3: u8 = 3;
+
+pub const BER_TAG_INTEGER: u8 = 0x02;
+pub const BER_TAG_OCTET_STRING: u8 = 0x04;
+pub const BER_TAG_SEQUENCE: u8 = 0x30;
+
+pub const SNMP_PDU_GET_REQUEST: u8 = 0xa0;
+pub const SNMP_PDU_GET_NEXT_REQUEST: u8 = 0xa1;
+pub const SNMP_PDU_RESPONSE: u8 = 0xa2;
+pub const SNMP_PDU_SET_REQUEST: u8 = 0xa3;
+pub const SNMP_PDU_TRAP: u8 = 0xa4;
+pub const SNMP_PDU_GET_BULK_REQUEST: u8 = 0xa5;
+pub const SNMP_PDU_INFORM_REQUEST: u8 = 0xa6;
+pub const SNMP_PDU_TRAP_V2: u8 = 0xa7;
+pub const SNMP_PDU_REPORT: u8 = 0xa8;
+
+pub const SNMP_V3_FLAG_PRIV: u8 = 0x02;
+pub const SNMP_V3_FLAG_REPORTABLE: u8 = 0x04;
+
+pub const SNMP_ERROR_TOO_BIG: u8 = 1;
+pub const SNMP_ERROR_GEN_ERR: u8 = 5;
+pub const SNMP_ERROR_RESOURCE_UNAVAILABLE: u8 = 13;
+pub const SNMP_ERROR_COMMIT_FAILED: u8 = 14;
+pub const SNMP_ERROR_UNDO_FAILED: u8 = 15;
+pub const SNMP_ERROR_MAX: i64 = 18;
+
+// tds(sql server) constants
+pub const TDS_HEADER_LEN: usize = 8;
+// EOM, IGNORE, RESETCONNECTION等状态位
+pub const TDS_STATUS_MASK: u8 = 0x1f;
+pub const TDS_STATUS_EOM: u8 = 0x01;
+
+pub const TDS_PACKET_SQL_BATCH: u8 = 0x01;
+pub const TDS_PACKET_RPC: u8 = 0x03;
+pub const TDS_PACKET_TABULAR_RESULT: u8 = 0x04;
+pub const TDS_PACKET_ATTENTION: u8 = 0x06;
+
+pub const TDS_TOKEN_RETURNSTATUS: u8 = 0x79;
+pub const TDS_TOKEN_ORDER: u8 = 0xa9;
+pub const TDS_TOKEN_ERROR: u8 = 0xaa;
+pub const TDS_TOKEN_INFO: u8 = 0xab;
+pub const TDS_TOKEN_LOGINACK: u8 = 0xad;
+pub const TDS_TOKEN_ENVCHANGE: u8 = 0xe3;
+pub const TDS_TOKEN_DONE: u8 = 0xfd;
+pub const TDS_TOKEN_DONEPROC: u8 = 0xfe;
+pub const TDS_TOKEN_DONEINPROC: u8 = 0xff;
+// token类型1字节，status 2字节，curcmd 2字节，rowcount 8字节(TDS 7.2及以上)
+pub const TDS_DONE_TOKEN_LEN: usize = 13;
+pub const TDS_DONE_ERROR: u16 = 0x0002;
+pub const TDS_DONE_COUNT: u16 = 0x0010;
+// 严重级别17及以上为服务端资源不足或软硬件错误
+pub const TDS_ERROR_CLASS_SERVER: u8 = 17;
+
+pub const TDS_RPC_PROC_ID_SWITCH: u16 = 0xffff;
+pub const TDS_RPC_SP_EXECUTESQL: u16 = 10;
+pub const TDS_RPC_SP_PREPARE: u16 = 11;
+pub const TDS_RPC_SP_PREPEXEC: u16 = 13;
+pub const TDS_TYPE_INTN: u8 = 0x26;
+pub const TDS_TYPE_NVARCHAR: u8 = 0xe7;
+// NVARCHAR(MAX)的最大长度为0xffff，值按PLP格式分块传输
+pub const TDS_NVARCHAR_MAX: u16 = 0xffff;
+pub const TDS_NULL_LEN: u16 = 0xffff;
+pub const TDS_PLP_NULL: u64 = u64::MAX;
+
+// clickhouse native protocol constants
+pub const CLICKHOUSE_CLIENT_HELLO: u8 = 0;
+pub const CLICKHOUSE_CLIENT_QUERY: u8 = 1;
+
+pub const CLICKHOUSE_SERVER_DATA: u8 = 1;
+pub const CLICKHOUSE_SERVER_EXCEPTION: u8 = 2;
+pub const CLICKHOUSE_SERVER_PROGRESS: u8 = 3;
+pub const CLICKHOUSE_SERVER_END_OF_STREAM: u8 = 5;
+pub const CLICKHOUSE_SERVER_PROFILE_INFO: u8 = 6;
+pub const CLICKHOUSE_SERVER_TOTALS: u8 = 7;
+pub const CLICKHOUSE_SERVER_EXTREMES: u8 = 8;
+pub const CLICKHOUSE_SERVER_LOG: u8 = 10;
+pub const CLICKHOUSE_SERVER_TABLE_COLUMNS: u8 = 11;
+pub const CLICKHOUSE_SERVER_PROFILE_EVENTS: u8 = 14;
+
+// Hello中的协议版本(revision)范围
+pub const CLICKHOUSE_MIN_REVISION: u64 = 54000;
+pub const CLICKHOUSE_MAX_REVISION: u64 = 60000;
+// Query中各字段出现的最低协议版本
+pub const CLICKHOUSE_REVISION_SETTINGS_AS_STRINGS: u32 = 54429;
+pub const CLICKHOUSE_REVISION_INTERSERVER_SECRET: u32 = 54441;
+pub const CLICKHOUSE_REVISION_OPENTELEMETRY: u32 = 54442;
+pub const CLICKHOUSE_REVISION_X_FORWARDED_FOR: u32 = 54443;
+pub const CLICKHOUSE_REVISION_REFERER: u32 = 54447;
+pub const CLICKHOUSE_REVISION_DISTRIBUTED_DEPTH: u32 = 54448;
+pub const CLICKHOUSE_REVISION_INITIAL_QUERY_START_TIME: u32 = 54449;
+pub const CLICKHOUSE_REVISION_PARALLEL_REPLICAS: u32 = 54453;
+
+pub const CLICKHOUSE_QUERY_KIND_NONE: u8 = 0;
+pub const CLICKHOUSE_QUERY_KIND_SECONDARY: u8 = 2;
+pub const CLICKHOUSE_INTERFACE_TCP: u8 = 1;
+pub const CLICKHOUSE_INTERFACE_HTTP: u8 = 2;
+pub const CLICKHOUSE_QUERY_STAGE_COMPLETE: u64 = 2;
+pub const CLICKHOUSE_MAX_QUERY_ID_LEN: usize = 128;
+
+// tls constants
+pub const TLS_RECORD_HEADER_LEN: usize = 5;
+pub const TLS_HANDSHAKE_HEADER_LEN: usize = 4;
+pub const TLS_MAJOR_VERSION: u8 = 3;
+// SSL 3.0至TLS 1.3的记录层次版本号
+pub const TLS_MAX_MINOR_VERSION: u8 = 4;
+// 加密后的记录长度上限为2^14 + 2048
+pub const TLS_MAX_RECORD_LEN: usize = 18432;
+
+pub const TLS_CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+pub const TLS_CONTENT_ALERT: u8 = 21;
+pub const TLS_CONTENT_HANDSHAKE: u8 = 22;
+pub const TLS_CONTENT_APPLICATION_DATA: u8 = 23;
+
+pub const TLS_HANDSHAKE_CLIENT_HELLO: u8 = 1;
+pub const TLS_HANDSHAKE_SERVER_HELLO: u8 = 2;
+pub const TLS_HANDSHAKE_CERTIFICATE: u8 = 11;
+
+pub const TLS_EXTENSION_SERVER_NAME: u16 = 0;
+pub const TLS_EXTENSION_SUPPORTED_GROUPS: u16 = 10;
+pub const TLS_EXTENSION_EC_POINT_FORMATS: u16 = 11;
+pub const TLS_EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+pub const TLS_SERVER_NAME_HOST: u8 = 0;
+
+pub const TLS_ALERT_LEVEL_FATAL: u8 = 2;
+// 明文告警只有level和description两个字节，握手完成后的加密告警更长
+pub const TLS_ALERT_LEN: u16 = 2;
+// 每个日志最多记录的证书数量
+pub const TLS_MAX_CERTIFICATES: usize = 10;
+
+// ntp constants
+pub const NTP_PORT: u16 = 123;
+// 不含扩展字段和MAC的包头长度
+pub const NTP_HEADER_LEN: usize = 48;
+pub const NTP_MIN_VERSION: u8 = 1;
+pub const NTP_MAX_VERSION: u8 = 4;
+
+pub const NTP_MODE_SYMMETRIC_ACTIVE: u8 = 1;
+pub const NTP_MODE_SYMMETRIC_PASSIVE: u8 = 2;
+pub const NTP_MODE_CLIENT: u8 = 3;
+pub const NTP_MODE_SERVER: u8 = 4;
+pub const NTP_MODE_BROADCAST: u8 = 5;
+
+// 时钟未同步
+pub const NTP_LEAP_ALARM: u8 = 3;
+// stratum为0的响应为Kiss-o'-Death，reference id为kiss code
+pub const NTP_STRATUM_KISS_OF_DEATH: u8 = 0;
+pub const NTP_STRATUM_PRIMARY: u8 = 1;
+// NTP时间戳从1900年开始计数
+pub const NTP_UNIX_EPOCH_OFFSET: u64 = 2208988800;
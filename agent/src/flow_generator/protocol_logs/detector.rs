@@ -0,0 +1,432 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{
+    clickhouse_check_protocol, dns_check_protocol, dubbo_check_protocol, http1_check_protocol,
+    http2_check_protocol, kafka_check_protocol, modbus_check_protocol, mqtt_check_protocol,
+    mysql_check_protocol, ntp_check_protocol, radius_check_protocol, redis_check_protocol,
+    s7_check_protocol, snmp_check_protocol, tds_check_protocol, tls_check_protocol,
+};
+use crate::common::{enums::IpProtocol, flow::L7Protocol, meta_packet::MetaPacket};
+
+pub type CheckFn = fn(&mut u128, &MetaPacket) -> bool;
+
+// 载荷开头的粗分类，同一个包的所有协议识别共用，跳过不可能匹配的协议
+const HINT_TEXT: u8 = 1;
+const HINT_BINARY: u8 = 1 << 1;
+const HINT_TLS: u8 = 1 << 2;
+const HINT_ANY: u8 = HINT_TEXT | HINT_BINARY | HINT_TLS;
+
+const TLS_CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const TLS_MAJOR_VERSION: u8 = 3;
+
+fn payload_hint(payload: &[u8]) -> u8 {
+    match payload {
+        [TLS_CONTENT_TYPE_HANDSHAKE, TLS_MAJOR_VERSION, ..] => HINT_TLS,
+        // 文本协议的请求以方法名或RESP类型符开头
+        [a, b, ..] if (0x20..0x7f).contains(a) && (0x20..0x7f).contains(b) => HINT_TEXT,
+        _ => HINT_BINARY,
+    }
+}
+
+struct Detector {
+    protocol: L7Protocol,
+    tcp: bool,
+    udp: bool,
+    // 可能匹配的载荷分类
+    hints: u8,
+    check: CheckFn,
+}
+
+const fn detector(
+    protocol: L7Protocol,
+    tcp: bool,
+    udp: bool,
+    hints: u8,
+    check: CheckFn,
+) -> Detector {
+    Detector {
+        protocol,
+        tcp,
+        udp,
+        hints,
+        check,
+    }
+}
+
+// 默认的识别优先级，Http1TLS只出现在eBPF的bitmap中
+const DETECTORS: [Detector; 17] = [
+    detector(
+        L7Protocol::Http1TLS,
+        true,
+        false,
+        HINT_TEXT,
+        http1_check_protocol,
+    ),
+    detector(
+        L7Protocol::Http1,
+        true,
+        false,
+        HINT_TEXT,
+        http1_check_protocol,
+    ),
+    detector(
+        L7Protocol::Http2,
+        true,
+        false,
+        HINT_ANY,
+        http2_check_protocol,
+    ),
+    detector(
+        L7Protocol::Dubbo,
+        true,
+        false,
+        HINT_ANY,
+        dubbo_check_protocol,
+    ),
+    detector(
+        L7Protocol::Mysql,
+        true,
+        false,
+        HINT_ANY,
+        mysql_check_protocol,
+    ),
+    detector(
+        L7Protocol::Redis,
+        true,
+        false,
+        HINT_TEXT,
+        redis_check_protocol,
+    ),
+    detector(
+        L7Protocol::Kafka,
+        true,
+        false,
+        HINT_ANY,
+        kafka_check_protocol,
+    ),
+    detector(L7Protocol::Mqtt, true, false, HINT_ANY, mqtt_check_protocol),
+    detector(L7Protocol::Dns, true, true, HINT_ANY, dns_check_protocol),
+    detector(
+        L7Protocol::Radius,
+        false,
+        true,
+        HINT_ANY,
+        radius_check_protocol,
+    ),
+    detector(
+        L7Protocol::Modbus,
+        true,
+        false,
+        HINT_ANY,
+        modbus_check_protocol,
+    ),
+    detector(L7Protocol::S7, true, false, HINT_ANY, s7_check_protocol),
+    detector(L7Protocol::Snmp, false, true, HINT_ANY, snmp_check_protocol),
+    detector(L7Protocol::Tds, true, false, HINT_ANY, tds_check_protocol),
+    detector(
+        L7Protocol::Clickhouse,
+        true,
+        false,
+        HINT_ANY,
+        clickhouse_check_protocol,
+    ),
+    detector(L7Protocol::Tls, true, false, HINT_TLS, tls_check_protocol),
+    detector(L7Protocol::Ntp, false, true, HINT_ANY, ntp_check_protocol),
+];
+
+impl Detector {
+    fn matches_l4(&self, proto: IpProtocol) -> bool {
+        match proto {
+            IpProtocol::Tcp => self.tcp,
+            IpProtocol::Udp => self.udp,
+            _ => false,
+        }
+    }
+}
+
+// 所有协议的识别函数，按默认优先级排列
+pub fn protocol_checks() -> impl Iterator<Item = (L7Protocol, CheckFn)> {
+    DETECTORS.iter().map(|d| (d.protocol, d.check))
+}
+
+// 流开始识别时的候选协议，不含Http1TLS
+pub fn l4_protocol_bitmap(proto: IpProtocol) -> u128 {
+    DETECTORS
+        .iter()
+        .filter(|d| d.protocol != L7Protocol::Http1TLS && d.matches_l4(proto))
+        .fold(0, |bitmap, d| bitmap | 1 << u8::from(d.protocol))
+}
+
+// 识别过程中实际执行和因载荷过短跳过的识别函数个数
+#[derive(Debug, Default)]
+pub struct DetectCounter {
+    pub checked: u32,
+    pub small_skipped: u32,
+}
+
+// 迭代项为识别器及其最小载荷长度
+fn detect_in<'a, I: Iterator<Item = (&'a Detector, usize)>>(
+    detectors: I,
+    content_hint: bool,
+    bitmap: &mut u128,
+    packet: &MetaPacket,
+    counter: &mut DetectCounter,
+) -> Option<L7Protocol> {
+    let hint = match packet.get_l4_payload() {
+        Some(payload) if content_hint => payload_hint(payload),
+        _ => HINT_ANY,
+    };
+    let payload_len = packet.l4_payload_len();
+    for (d, min_payload_len) in detectors {
+        if *bitmap & 1 << u8::from(d.protocol) == 0 || d.hints & hint == 0 {
+            continue;
+        }
+        // 不清除bitmap，后续较长的包仍可识别
+        if payload_len < min_payload_len {
+            counter.small_skipped += 1;
+            continue;
+        }
+        counter.checked += 1;
+        if (d.check)(bitmap, packet) {
+            return Some(d.protocol);
+        }
+    }
+    None
+}
+
+// 按配置排序的协议识别器，排序在配置更新时计算一次，不在每个包上重复
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct L7ProtocolDetector {
+    // DETECTORS的下标
+    tcp: Vec<u8>,
+    udp: Vec<u8>,
+    content_hint: bool,
+    // 每条流最多尝试识别的包数，为0时不限制
+    max_attempts: u32,
+    // 载荷小于该长度的包不参与识别
+    min_payload_len: usize,
+    // 按DETECTORS的下标，载荷小于该长度时跳过该协议
+    protocol_min_payload_lens: Vec<usize>,
+}
+
+impl Default for L7ProtocolDetector {
+    fn default() -> Self {
+        Self::new(&[], true, 0, DEFAULT_MIN_PAYLOAD_LEN, &[])
+    }
+}
+
+// 识别至少需要2字节载荷
+const DEFAULT_MIN_PAYLOAD_LEN: usize = 2;
+
+impl L7ProtocolDetector {
+    // order中的协议优先识别，其余协议按默认优先级
+    pub fn new(
+        order: &[L7Protocol],
+        content_hint: bool,
+        max_attempts: u32,
+        min_payload_len: usize,
+        protocol_min_payload_lens: &[(L7Protocol, usize)],
+    ) -> Self {
+        let mut indices = (0..DETECTORS.len() as u8).collect::<Vec<_>>();
+        // 稳定排序，未配置的协议保持默认顺序
+        indices.sort_by_key(|i| {
+            let protocol = DETECTORS[*i as usize].protocol;
+            order
+                .iter()
+                .position(|p| *p == protocol)
+                .unwrap_or(order.len())
+        });
+        let filter = |proto| {
+            indices
+                .iter()
+                .copied()
+                .filter(|i| DETECTORS[*i as usize].matches_l4(proto))
+                .collect()
+        };
+        Self {
+            tcp: filter(IpProtocol::Tcp),
+            udp: filter(IpProtocol::Udp),
+            content_hint,
+            max_attempts,
+            min_payload_len,
+            protocol_min_payload_lens: DETECTORS
+                .iter()
+                .map(|d| {
+                    protocol_min_payload_lens
+                        .iter()
+                        .rev()
+                        .find(|(p, _)| *p == d.protocol)
+                        .map(|(_, l)| *l)
+                        .unwrap_or_default()
+                })
+                .collect(),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn min_payload_len(&self) -> usize {
+        self.min_payload_len
+    }
+
+    pub fn detect(
+        &self,
+        bitmap: &mut u128,
+        packet: &MetaPacket,
+        counter: &mut DetectCounter,
+    ) -> Option<L7Protocol> {
+        let indices = match packet.lookup_key.proto {
+            IpProtocol::Tcp => &self.tcp,
+            IpProtocol::Udp => &self.udp,
+            _ => return None,
+        };
+        detect_in(
+            indices.iter().map(|i| {
+                let i = *i as usize;
+                (&DETECTORS[i], self.protocol_min_payload_lens[i])
+            }),
+            self.content_hint,
+            bitmap,
+            packet,
+            counter,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::tap_port::TapPort;
+    use crate::utils::packet_pool::PacketBuffer;
+
+    fn packet(proto: IpProtocol, payload: &[u8]) -> MetaPacket<'static> {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.proto = proto;
+        packet.tap_port = TapPort::from_ebpf(1);
+        packet.raw_from_ebpf = PacketBuffer::from_slice(payload);
+        packet.payload_len = payload.len() as u16;
+        packet
+    }
+
+    #[test]
+    fn order_and_hint() {
+        assert_eq!(payload_hint(b"GET / HTTP/1.1\r\n"), HINT_TEXT);
+        assert_eq!(payload_hint(&[0x16, 0x03, 0x01, 0, 5]), HINT_TLS);
+        assert_eq!(payload_hint(&[0xda, 0xbb]), HINT_BINARY);
+
+        let bitmap = l4_protocol_bitmap(IpProtocol::Udp);
+        assert_eq!(
+            bitmap,
+            1 << u8::from(L7Protocol::Dns)
+                | 1 << u8::from(L7Protocol::Radius)
+                | 1 << u8::from(L7Protocol::Snmp)
+                | 1 << u8::from(L7Protocol::Ntp)
+        );
+        assert_eq!(
+            l4_protocol_bitmap(IpProtocol::Tcp) & 1 << u8::from(L7Protocol::Http1TLS),
+            0
+        );
+
+        let detector = L7ProtocolDetector::new(
+            &[L7Protocol::Tls, L7Protocol::Mysql],
+            true,
+            5,
+            DEFAULT_MIN_PAYLOAD_LEN,
+            &[],
+        );
+        let order = detector
+            .tcp
+            .iter()
+            .map(|i| DETECTORS[*i as usize].protocol)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            &order[..4],
+            &[
+                L7Protocol::Tls,
+                L7Protocol::Mysql,
+                L7Protocol::Http1TLS,
+                L7Protocol::Http1
+            ]
+        );
+        assert_eq!(order.len(), 14);
+        assert!(!order.contains(&L7Protocol::Ntp));
+
+        let http = packet(IpProtocol::Tcp, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        let mut bitmap = l4_protocol_bitmap(IpProtocol::Tcp);
+        assert_eq!(
+            detector.detect(&mut bitmap, &http, &mut DetectCounter::default()),
+            Some(L7Protocol::Http1)
+        );
+        let mut bitmap = l4_protocol_bitmap(IpProtocol::Tcp);
+        assert_eq!(
+            L7ProtocolDetector::default().detect(&mut bitmap, &http, &mut DetectCounter::default()),
+            Some(L7Protocol::Http1)
+        );
+
+        // 二进制载荷跳过文本协议，不清除其bitmap
+        let binary = packet(IpProtocol::Tcp, &[0xff, 0xfe, 0xfd, 0xfc]);
+        let mut bitmap = l4_protocol_bitmap(IpProtocol::Tcp);
+        detector.detect(&mut bitmap, &binary, &mut DetectCounter::default());
+        assert_ne!(bitmap & 1 << u8::from(L7Protocol::Http1), 0);
+        assert_ne!(bitmap & 1 << u8::from(L7Protocol::Redis), 0);
+    }
+
+    #[test]
+    fn min_payload_len() {
+        let detector = L7ProtocolDetector::new(
+            &[],
+            true,
+            0,
+            DEFAULT_MIN_PAYLOAD_LEN,
+            &[(L7Protocol::Http1, 32), (L7Protocol::Redis, 8)],
+        );
+        assert_eq!(detector.min_payload_len(), DEFAULT_MIN_PAYLOAD_LEN);
+
+        // 只跳过HTTP/1，载荷不短于8字节的其它协议照常识别
+        let http = packet(IpProtocol::Tcp, b"GET / HTTP/1.1\r\n\r\n");
+        let mut bitmap = l4_protocol_bitmap(IpProtocol::Tcp);
+        let mut counter = DetectCounter::default();
+        assert_ne!(
+            detector.detect(&mut bitmap, &http, &mut counter),
+            Some(L7Protocol::Http1)
+        );
+        assert_eq!(counter.small_skipped, 1);
+        assert_ne!(bitmap & 1 << u8::from(L7Protocol::Http1), 0);
+
+        // 所有候选协议都因载荷过短跳过时没有执行识别
+        let mut only_http = 1 << u8::from(L7Protocol::Http1);
+        let mut counter = DetectCounter::default();
+        assert_eq!(detector.detect(&mut only_http, &http, &mut counter), None);
+        assert_eq!(counter.checked, 0);
+        assert_eq!(counter.small_skipped, 1);
+
+        let http = packet(
+            IpProtocol::Tcp,
+            b"GET /index.html HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        let mut counter = DetectCounter::default();
+        assert_eq!(
+            detector.detect(&mut bitmap, &http, &mut counter),
+            Some(L7Protocol::Http1)
+        );
+        assert_eq!(counter.small_skipped, 0);
+        assert!(counter.checked > 0);
+    }
+}
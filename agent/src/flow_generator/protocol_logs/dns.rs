@@ -13,13 +13,16 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::net::IpAddr;
+
 use serde::Serialize;
 
 use super::{
     consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
-    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, LogMessageType,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
 };
 
+use crate::config::{handler::LogParserAccess, DnsLogConfig};
 use crate::proto::flow_log;
 use crate::{
     common::{
@@ -29,12 +32,84 @@ use crate::{
         IPV4_ADDR_LEN, IPV6_ADDR_LEN,
     },
     flow_generator::{
-        error::{Error, Result},
+        error::{Error, ParseContext, ParseStage, Result},
+        golden,
         perf::DNS_PORT,
     },
     utils::{bytes::read_u16_be, net::parse_ip_slice},
 };
 
+const IDN_PREFIX: &str = "xn--";
+const PUNYCODE_BASE: u32 = 36;
+const PUNYCODE_TMIN: u32 = 1;
+const PUNYCODE_TMAX: u32 = 26;
+const PUNYCODE_SKEW: u32 = 38;
+const PUNYCODE_DAMP: u32 = 700;
+const PUNYCODE_INITIAL_BIAS: u32 = 72;
+const PUNYCODE_INITIAL_N: u32 = 0x80;
+
+fn punycode_adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time {
+        delta / PUNYCODE_DAMP
+    } else {
+        delta / 2
+    };
+    delta += delta / num_points;
+    let mut k = 0;
+    while delta > ((PUNYCODE_BASE - PUNYCODE_TMIN) * PUNYCODE_TMAX) / 2 {
+        delta /= PUNYCODE_BASE - PUNYCODE_TMIN;
+        k += PUNYCODE_BASE;
+    }
+    k + (PUNYCODE_BASE - PUNYCODE_TMIN + 1) * delta / (delta + PUNYCODE_SKEW)
+}
+
+// RFC 3492，输入为去掉xn--前缀的标签，非法时返回None
+fn punycode_decode(input: &str) -> Option<String> {
+    if !input.is_ascii() {
+        return None;
+    }
+    let (basic, extended) = match input.rfind('-') {
+        Some(i) => (&input[..i], &input[i + 1..]),
+        None => ("", input),
+    };
+    let mut output = basic.chars().collect::<Vec<_>>();
+    let (mut n, mut i, mut bias) = (PUNYCODE_INITIAL_N, 0u32, PUNYCODE_INITIAL_BIAS);
+    let mut digits = extended.bytes();
+    while digits.len() > 0 {
+        let old_i = i;
+        let mut w = 1u32;
+        let mut k = PUNYCODE_BASE;
+        loop {
+            let digit = match digits.next()? {
+                b @ b'a'..=b'z' => b - b'a',
+                b @ b'A'..=b'Z' => b - b'A',
+                b @ b'0'..=b'9' => b - b'0' + 26,
+                _ => return None,
+            } as u32;
+            i = i.checked_add(digit.checked_mul(w)?)?;
+            let t = if k <= bias {
+                PUNYCODE_TMIN
+            } else if k >= bias + PUNYCODE_TMAX {
+                PUNYCODE_TMAX
+            } else {
+                k - bias
+            };
+            if digit < t {
+                break;
+            }
+            w = w.checked_mul(PUNYCODE_BASE - t)?;
+            k += PUNYCODE_BASE;
+        }
+        let len = output.len() as u32 + 1;
+        bias = punycode_adapt(i - old_i, len, old_i == 0);
+        n = n.checked_add(i / len)?;
+        i %= len;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
 #[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
 pub struct DnsInfo {
     #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
@@ -50,13 +125,33 @@ pub struct DnsInfo {
     // A: ipv4/ipv6地址
     // NS: name server
     // SOA: primary name server
+    // MX: preference exchange
+    // SRV: priority weight port target
+    // TXT: 各字符串拼接后的文本
     #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
     pub answers: String,
+    // 开启IDN解码或名称规范化后，query_name被改写时保留的原始名称
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub raw_query_name: String,
+    // Answer段中的A/AAAA记录，用于为流日志的服务端IP补充域名
+    #[serde(skip)]
+    pub answer_ips: Vec<IpAddr>,
 }
 
 impl DnsInfo {
     pub fn merge(&mut self, other: Self) {
         self.answers = other.answers;
+        self.answer_ips = other.answer_ips;
+    }
+}
+
+impl L7SessionId for DnsInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.trans_id > 0 {
+            Some(self.trans_id as u32)
+        } else {
+            None
+        }
     }
 }
 
@@ -67,6 +162,7 @@ impl From<DnsInfo> for flow_log::DnsInfo {
             query_type: f.domain_type as u32,
             query_name: f.query_name,
             answers: f.answers,
+            raw_query_name: f.raw_query_name,
         }
     }
 }
@@ -74,18 +170,75 @@ impl From<DnsInfo> for flow_log::DnsInfo {
 #[derive(Clone, Debug, Default)]
 pub struct DnsLog {
     info: DnsInfo,
+    config: DnsLogConfig,
 
     msg_type: LogMessageType,
     status: L7ResponseStatus,
     status_code: u8,
+    // 正在解析Answer段
+    answer_section: bool,
 }
 
 impl DnsLog {
+    pub fn new(config: &LogParserAccess) -> Self {
+        Self {
+            config: config.load().l7_log_dns.clone(),
+            ..Default::default()
+        }
+    }
+
+    // 超过max_answers_length的部分截断
+    fn push_answer(&mut self, answer: &str) {
+        let answers = &mut self.info.answers;
+        let max_length = self.config.max_answers_length;
+        if answers.len() + answer.len() <= max_length {
+            answers.push_str(answer);
+            return;
+        }
+        let mut end = max_length.saturating_sub(answers.len());
+        while !answer.is_char_boundary(end) {
+            end -= 1;
+        }
+        answers.push_str(&answer[..end]);
+    }
+
     fn reset_logs(&mut self) {
         self.info.trans_id = 0;
         self.info.query_type = 0;
         self.info.query_name = String::new();
         self.info.answers = String::new();
+        self.info.raw_query_name = String::new();
+        self.info.answer_ips.clear();
+        self.answer_section = false;
+    }
+
+    // 按配置将punycode标签解码为Unicode，转为小写并去除末尾的'.'，解码失败的标签保持不变
+    fn normalize_name(&self, name: &str) -> String {
+        let config = &self.config;
+        let name = if config.name_normalization {
+            name.trim_end_matches('.')
+        } else {
+            name
+        };
+        let name = if config.idn_decode {
+            name.split('.')
+                .map(|label| match label.get(..IDN_PREFIX.len()) {
+                    Some(prefix) if prefix.eq_ignore_ascii_case(IDN_PREFIX) => {
+                        punycode_decode(&label[IDN_PREFIX.len()..])
+                            .unwrap_or_else(|| label.to_owned())
+                    }
+                    _ => label.to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(".")
+        } else {
+            name.to_owned()
+        };
+        if config.name_normalization {
+            name.to_lowercase()
+        } else {
+            name
+        }
     }
 
     fn decode_name(&self, payload: &[u8], g_offset: usize) -> Result<(String, usize)> {
@@ -95,7 +248,10 @@ impl DnsLog {
 
         if payload.len() <= l_offset {
             let err_msg = format!("payload too short: {}", payload.len());
-            return Err(Error::DNSLogParseFailed(err_msg));
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Payload).at(l_offset),
+                err_msg,
+            ));
         }
 
         if payload[index] == DNS_NAME_TAIL {
@@ -107,17 +263,26 @@ impl DnsLog {
             match name_type {
                 DNS_NAME_RESERVERD_40 | DNS_NAME_RESERVERD_80 => {
                     let err_msg = format!("dns name label type error: {}", payload[index]);
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(index),
+                        err_msg,
+                    ));
                 }
                 DNS_NAME_COMPRESS_POINTER => {
                     if index + 2 > payload.len() {
                         let err_msg = format!("dns name invalid index: {}", index);
-                        return Err(Error::DNSLogParseFailed(err_msg));
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(index),
+                            err_msg,
+                        ));
                     }
                     let index_ptr = read_u16_be(&payload[index..]) as usize & 0x3fff;
                     if index_ptr >= index {
                         let err_msg = format!("dns name compress pointer invalid: {}", index_ptr);
-                        return Err(Error::DNSLogParseFailed(err_msg));
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(index),
+                            err_msg,
+                        ));
                     }
                     index = index_ptr;
                 }
@@ -127,7 +292,10 @@ impl DnsLog {
                         || (size > g_offset && (size - g_offset) > DNS_NAME_MAX_SIZE)
                     {
                         let err_msg = format!("dns name invalid index: {}", size);
-                        return Err(Error::DNSLogParseFailed(err_msg));
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(index),
+                            err_msg,
+                        ));
                     }
 
                     if buffer.len() > 0 {
@@ -139,17 +307,26 @@ impl DnsLog {
                         }
                         Err(e) => {
                             let err_msg = format!("decode name error {}", e);
-                            return Err(Error::DNSLogParseFailed(err_msg));
+                            return Err(Error::DNSLogParseFailed(
+                                ParseContext::new(ParseStage::Payload).at(index + 1),
+                                err_msg,
+                            ));
                         }
                     }
                     if buffer.len() > DNS_NAME_MAX_SIZE {
                         let err_msg = format!("dns name invalid length:{}", buffer.len());
-                        return Err(Error::DNSLogParseFailed(err_msg));
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(index),
+                            err_msg,
+                        ));
                     }
                     index = size;
                     if index >= payload.len() {
                         let err_msg = format!("dns name invalid index: {}", index);
-                        return Err(Error::DNSLogParseFailed(err_msg));
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(index),
+                            err_msg,
+                        ));
                     }
 
                     if index > l_offset {
@@ -169,13 +346,26 @@ impl DnsLog {
         let qtype_size = payload[offset..].len();
         if qtype_size < QUESTION_CLASS_TYPE_SIZE {
             let err_msg = format!("question length error: {}", qtype_size);
-            return Err(Error::DNSLogParseFailed(err_msg));
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Payload)
+                    .at(offset)
+                    .mismatch(QUESTION_CLASS_TYPE_SIZE, qtype_size),
+                err_msg,
+            ));
         }
 
         if self.info.query_name.len() > 0 {
             self.info.query_name.push(DOMAIN_NAME_SPLIT);
         }
-        self.info.query_name.push_str(&name);
+        if self.config.idn_decode || self.config.name_normalization {
+            if self.info.raw_query_name.len() > 0 {
+                self.info.raw_query_name.push(DOMAIN_NAME_SPLIT);
+            }
+            self.info.raw_query_name.push_str(&name);
+            self.info.query_name.push_str(&self.normalize_name(&name));
+        } else {
+            self.info.query_name.push_str(&name);
+        }
         if self.info.query_type == DNS_REQUEST {
             self.info.domain_type = read_u16_be(&payload[offset..]);
             self.msg_type = LogMessageType::Request;
@@ -184,33 +374,60 @@ impl DnsLog {
         Ok(offset + QUESTION_CLASS_TYPE_SIZE)
     }
 
-    fn decode_resource_record(&mut self, payload: &[u8], g_offset: usize) -> Result<usize> {
+    // decode_data为false时只跳过该记录
+    fn decode_resource_record(
+        &mut self,
+        payload: &[u8],
+        g_offset: usize,
+        decode_data: bool,
+    ) -> Result<usize> {
         let (_, offset) = self.decode_name(payload, g_offset)?;
 
         if payload.len() <= offset {
             let err_msg = format!("payload length error: {}", payload.len());
-            return Err(Error::DNSLogParseFailed(err_msg));
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Payload).at(offset),
+                err_msg,
+            ));
         }
 
         let resource_len = payload[offset..].len();
         if resource_len < RR_RDATA_OFFSET {
             let err_msg = format!("resource record length error: {}", resource_len);
-            return Err(Error::DNSLogParseFailed(err_msg));
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Payload)
+                    .at(offset)
+                    .mismatch(RR_RDATA_OFFSET, resource_len),
+                err_msg,
+            ));
         }
 
-        self.info.domain_type = read_u16_be(&payload[offset..]);
+        let domain_type = read_u16_be(&payload[offset..]);
         let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
-        if data_length != 0 {
-            self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?;
+        if data_length > resource_len - RR_RDATA_OFFSET {
+            let err_msg = format!("resource record data length error: {}", data_length);
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Payload)
+                    .at(offset + RR_RDATA_OFFSET)
+                    .mismatch(data_length, resource_len - RR_RDATA_OFFSET),
+                err_msg,
+            ));
+        }
+        // EDNS的OPT伪记录不是应答
+        if decode_data && domain_type != DNS_TYPE_OPT {
+            self.info.domain_type = domain_type;
+            if data_length != 0 {
+                self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?;
+            }
         }
 
         Ok(offset + RR_RDATA_OFFSET + data_length)
     }
 
     fn decode_rdata(&mut self, payload: &[u8], g_offset: usize, data_length: usize) -> Result<()> {
-        let answer_name_len = self.info.answers.len();
-        if answer_name_len > 0
-            && self.info.answers[answer_name_len - 1..] != DOMAIN_NAME_SPLIT.to_string()
+        if self.info.answers.len() > 0
+            && !self.info.answers.ends_with(DOMAIN_NAME_SPLIT)
+            && self.info.answers.len() < self.config.max_answers_length
         {
             self.info.answers.push(DOMAIN_NAME_SPLIT);
         }
@@ -220,7 +437,10 @@ impl DnsLog {
                 IPV4_ADDR_LEN | IPV6_ADDR_LEN => {
                     if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length])
                     {
-                        self.info.answers.push_str(&ipaddr.to_string());
+                        self.push_answer(&ipaddr.to_string());
+                        if self.answer_section {
+                            self.info.answer_ips.push(ipaddr);
+                        }
                     }
                 }
                 _ => {
@@ -228,7 +448,10 @@ impl DnsLog {
                         "domain type {} data length {} invalid",
                         self.info.domain_type, data_length
                     );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
                 }
             },
             DNS_TYPE_NS | DNS_TYPE_DNAME | DNS_TYPE_SOA => {
@@ -237,11 +460,66 @@ impl DnsLog {
                         "domain type {} data length {} invalid",
                         self.info.domain_type, data_length
                     );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
                 }
 
                 let (name, _) = self.decode_name(payload, g_offset)?;
-                self.info.answers.push_str(&name);
+                self.push_answer(&name);
+            }
+            DNS_TYPE_MX => {
+                if data_length <= DNS_TYPE_MX_NAME_OFFSET {
+                    let err_msg = format!(
+                        "domain type {} data length {} invalid",
+                        self.info.domain_type, data_length
+                    );
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
+                }
+                let preference = read_u16_be(&payload[g_offset..]);
+                let (name, _) = self.decode_name(payload, g_offset + DNS_TYPE_MX_NAME_OFFSET)?;
+                self.push_answer(&format!("{} {}", preference, name));
+            }
+            DNS_TYPE_SRV => {
+                if data_length <= DNS_TYPE_SRV_NAME_OFFSET {
+                    let err_msg = format!(
+                        "domain type {} data length {} invalid",
+                        self.info.domain_type, data_length
+                    );
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
+                }
+                let priority = read_u16_be(&payload[g_offset..]);
+                let weight = read_u16_be(&payload[g_offset + DNS_TYPE_SRV_WEIGHT_OFFSET..]);
+                let port = read_u16_be(&payload[g_offset + DNS_TYPE_SRV_PORT_OFFSET..]);
+                let (target, _) = self.decode_name(payload, g_offset + DNS_TYPE_SRV_NAME_OFFSET)?;
+                self.push_answer(&format!("{} {} {} {}", priority, weight, port, target));
+            }
+            DNS_TYPE_TXT => {
+                // 一个或多个<length><string>
+                let mut data = &payload[g_offset..g_offset + data_length];
+                let mut text = String::new();
+                while let Some((&length, remain)) = data.split_first() {
+                    let length = length as usize;
+                    if length > remain.len() {
+                        let err_msg = format!("txt string length {} invalid", length);
+                        return Err(Error::DNSLogParseFailed(
+                            ParseContext::new(ParseStage::Payload)
+                                .at(g_offset + data_length - data.len())
+                                .mismatch(length, remain.len()),
+                            err_msg,
+                        ));
+                    }
+                    text.push_str(&String::from_utf8_lossy(&remain[..length]));
+                    data = &remain[length..];
+                }
+                self.push_answer(&text);
             }
             DNS_TYPE_WKS => {
                 if data_length < DNS_TYPE_WKS_LENGTH {
@@ -249,10 +527,13 @@ impl DnsLog {
                         "domain type {} data length {} invalid",
                         self.info.domain_type, data_length
                     );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
                 }
                 if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length]) {
-                    self.info.answers.push_str(&ipaddr.to_string());
+                    self.push_answer(&ipaddr.to_string());
                 }
             }
             DNS_TYPE_PTR => {
@@ -261,7 +542,10 @@ impl DnsLog {
                         "domain type {} data length {} invalid",
                         self.info.domain_type, data_length
                     );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(g_offset),
+                        err_msg,
+                    ));
                 }
             }
             _ => {
@@ -269,7 +553,10 @@ impl DnsLog {
                     "other domain type {} data length {} invalid",
                     self.info.domain_type, data_length
                 );
-                return Err(Error::DNSLogParseFailed(err_msg));
+                return Err(Error::DNSLogParseFailed(
+                    ParseContext::new(ParseStage::Payload).at(g_offset),
+                    err_msg,
+                ));
             }
         }
         Ok(())
@@ -288,7 +575,10 @@ impl DnsLog {
     fn decode_payload(&mut self, payload: &[u8]) -> Result<AppProtoHead> {
         if payload.len() <= DNS_HEADER_SIZE {
             let err_msg = format!("dns payload length too short:{}", payload.len());
-            return Err(Error::DNSLogParseFailed(err_msg));
+            return Err(Error::DNSLogParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(DNS_HEADER_SIZE + 1, payload.len()),
+                err_msg,
+            ));
         }
         self.info.trans_id = read_u16_be(&payload[..DNS_HEADER_FLAGS_OFFSET]);
         self.info.query_type = payload[DNS_HEADER_FLAGS_OFFSET] & 0x80;
@@ -297,22 +587,42 @@ impl DnsLog {
         let qd_count = read_u16_be(&payload[DNS_HEADER_QDCOUNT_OFFSET..]);
         let an_count = read_u16_be(&payload[DNS_HEADER_ANCOUNT_OFFSET..]);
         let ns_count = read_u16_be(&payload[DNS_HEADER_NSCOUNT_OFFSET..]);
+        let ar_count = read_u16_be(&payload[DNS_HEADER_ARCOUNT_OFFSET..]);
 
         let mut g_offset = DNS_HEADER_SIZE;
 
         for _i in 0..qd_count {
             g_offset = self.decode_question(payload, g_offset)?;
         }
+        // 未被改写时不重复保存
+        if self.info.raw_query_name == self.info.query_name {
+            self.info.raw_query_name = String::new();
+        }
 
         if self.info.query_type == DNS_RESPONSE {
             self.info.query_type = 1;
 
+            self.answer_section = true;
             for _i in 0..an_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
+                g_offset = self.decode_resource_record(payload, g_offset, true)?;
             }
+            self.answer_section = false;
 
             for _i in 0..ns_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
+                g_offset =
+                    self.decode_resource_record(payload, g_offset, self.config.authority_section)?;
+            }
+
+            if self.config.additional_section {
+                // domain_type保持为应答的类型；Additional段尽力解析，失败时保留已解析的部分
+                let domain_type = self.info.domain_type;
+                for _i in 0..ar_count {
+                    match self.decode_resource_record(payload, g_offset, true) {
+                        Ok(offset) => g_offset = offset,
+                        Err(_) => break,
+                    }
+                }
+                self.info.domain_type = domain_type;
             }
 
             self.msg_type = LogMessageType::Response;
@@ -324,6 +634,7 @@ impl DnsLog {
             code: self.status_code as u16,
             rrt: 0,
             version: 0,
+            ..Default::default()
         })
     }
 }
@@ -341,12 +652,20 @@ impl L7LogParse for DnsLog {
             IpProtocol::Tcp => {
                 if payload.len() <= DNS_TCP_PAYLOAD_OFFSET {
                     let err_msg = format!("dns payload length error:{}", payload.len());
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Header)
+                            .mismatch(DNS_TCP_PAYLOAD_OFFSET + 1, payload.len()),
+                        err_msg,
+                    ));
                 }
                 let size = read_u16_be(payload);
                 if (size as usize) < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
                     let err_msg = format!("dns payload length error:{}", size);
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(Error::DNSLogParseFailed(
+                        ParseContext::new(ParseStage::Header)
+                            .mismatch(payload.len() - DNS_TCP_PAYLOAD_OFFSET, size as usize),
+                        err_msg,
+                    ));
                 }
                 Ok(AppProtoHeadEnum::Single(
                     self.decode_payload(&payload[DNS_TCP_PAYLOAD_OFFSET..])?,
@@ -354,7 +673,10 @@ impl L7LogParse for DnsLog {
             }
             _ => {
                 let err_msg = format!("dns payload length error:{}", payload.len());
-                return Err(Error::DNSLogParseFailed(err_msg));
+                return Err(Error::DNSLogParseFailed(
+                    ParseContext::new(ParseStage::Header),
+                    err_msg,
+                ));
             }
         }
     }
@@ -388,6 +710,19 @@ pub fn dns_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     return ret.is_ok() && dns.msg_type == LogMessageType::Request;
 }
 
+impl DnsLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let mut dns = DnsLog::default();
+            let _ = dns.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_dns = dns_check_protocol(&mut bitmap, packet);
+            output.push_str(&format!("{:?} is_dns: {}\r\n", dns.info, is_dns));
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -402,30 +737,139 @@ mod tests {
     fn run(name: &str) -> String {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
+        DnsLog::golden_output(&mut packets)
+    }
 
-        let mut output = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
+    #[test]
+    fn truncated_rdata() {
+        // 1个A记录的应答，data length为4但只有2字节数据
+        let payload = [
+            0,
+            1,
+            0x81,
+            0x80,
+            0,
+            0,
+            0,
+            1,
+            0,
+            0,
+            0,
+            0,
+            DNS_NAME_TAIL,
+            0,
+            1,
+            0,
+            1,
+            0,
+            0,
+            0,
+            60,
+            0,
+            4,
+            10,
+            1,
+        ];
+        let mut dns = DnsLog::default();
+        assert!(dns
+            .parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .is_err());
+    }
 
-            let mut dns = DnsLog::default();
-            let _ = dns.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_dns = dns_check_protocol(&mut bitmap, packet);
-            output.push_str(&format!("{:?} is_dns: {}\r\n", dns.info, is_dns));
+    #[test]
+    fn srv_and_additional() {
+        let mut payload = vec![0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 2];
+        // 问题: _http._tcp.svc SRV
+        payload.extend_from_slice(b"\x05_http\x04_tcp\x03svc\x00");
+        payload.extend_from_slice(&[0, 33, 0, 1]);
+        // 应答: 0 5 8080 web.svc，svc为指向问题中标签的压缩指针
+        payload.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 60, 0, 12]);
+        payload.extend_from_slice(&[0, 0, 0, 5, 0x1f, 0x90, 3, b'w', b'e', b'b', 0xc0, 23]);
+        // Additional: web.svc A 10.0.0.1及EDNS的OPT记录
+        payload.extend_from_slice(&[0xc0, 50, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 1]);
+        payload.extend_from_slice(&[0, 0, 41, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut dns = DnsLog::default();
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(dns.info.query_name, "_http._tcp.svc");
+        assert_eq!(dns.info.answers, "0 5 8080 web.svc");
+
+        dns.config.additional_section = true;
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(dns.info.answers, "0 5 8080 web.svc;10.0.0.1");
+        assert_eq!(dns.info.domain_type, DNS_TYPE_SRV);
+
+        dns.config.max_answers_length = 10;
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(dns.info.answers, "0 5 8080 w");
+    }
+
+    #[test]
+    fn txt_and_mx() {
+        let mut dns = DnsLog::default();
+        let mut payload = vec![0, 1, 0x81, 0x80, 0, 0, 0, 2, 0, 0, 0, 0];
+        payload.extend_from_slice(&[DNS_NAME_TAIL, 0, 16, 0, 1, 0, 0, 0, 60, 0, 12]);
+        payload.extend_from_slice(b"\x05v=spf\x05 -all");
+        payload.extend_from_slice(&[DNS_NAME_TAIL, 0, 15, 0, 1, 0, 0, 0, 60, 0, 10]);
+        payload.extend_from_slice(b"\x00\x0a\x02mx\x03com\x00");
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(dns.info.answers, "v=spf -all;10 mx.com");
+
+        // TXT字符串长度超出rdata
+        payload[23] = 6;
+        assert!(dns
+            .parse(&payload, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .is_err());
+    }
+
+    #[test]
+    fn idn_and_normalization() {
+        for (label, decoded) in [
+            ("bcher-kva", Some("bücher")),
+            ("fiqs8s", Some("中国")),
+            ("e1afmkfd", Some("пример")),
+            ("80akhbyknj4f", Some("испытание")),
+            ("ab!", None),
+        ] {
+            assert_eq!(punycode_decode(label).as_deref(), decoded, "{}", label);
         }
-        output
+
+        let mut payload = vec![0, 1, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        payload.extend_from_slice(b"\x0cxn--E1AFMKFD\x10xn--80akhbyknj4f\x03WWW\x01.\x00");
+        payload.extend_from_slice(&[0, 1, 0, 1]);
+
+        let mut dns = DnsLog::default();
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(dns.info.query_name, "xn--E1AFMKFD.xn--80akhbyknj4f.WWW..");
+        assert_eq!(dns.info.raw_query_name, "");
+
+        dns.config.idn_decode = true;
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(dns.info.query_name, "пример.испытание.WWW..");
+        assert_eq!(
+            dns.info.raw_query_name,
+            "xn--E1AFMKFD.xn--80akhbyknj4f.WWW.."
+        );
+
+        dns.config.name_normalization = true;
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(dns.info.query_name, "пример.испытание.www");
+
+        // 未被改写的名称不保留原始名称
+        let mut payload = vec![0, 1, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        payload.extend_from_slice(b"\x03www\x07example\x03com\x00");
+        payload.extend_from_slice(&[0, 1, 0, 1]);
+        dns.parse(&payload, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(dns.info.query_name, "www.example.com");
+        assert_eq!(dns.info.raw_query_name, "");
     }
 
     #[test]
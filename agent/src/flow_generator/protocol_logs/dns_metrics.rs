@@ -0,0 +1,302 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use log::debug;
+
+use super::{AppProtoLogsData, L7ResponseStatus, LogMessageType};
+use crate::common::flow::L7Protocol;
+use crate::metric::{
+    document::{Code, Document, Tagger},
+    meter::{AppMeter, Meter},
+};
+use crate::sender::SendItem;
+use crate::utils::queue::DebugSender;
+
+const MINUTE: u64 = 60;
+const DNS_RCODE_SERVFAIL: u16 = 2;
+const DNS_RCODE_NXDOMAIN: u16 = 3;
+// 时延直方图，小于16us时逐一计数，之后每个2的幂区间等分为8个桶，误差不超过12.5%
+const LATENCY_BUCKETS: usize = 240;
+
+fn latency_bucket(rrt: u32) -> usize {
+    if rrt < 16 {
+        return rrt as usize;
+    }
+    let exp = 31 - rrt.leading_zeros();
+    ((exp - 2) * 8 + ((rrt >> (exp - 3)) & 7)) as usize
+}
+
+// 桶的下界
+fn bucket_latency(bucket: usize) -> u32 {
+    if bucket < 16 {
+        return bucket as u32;
+    }
+    let exp = bucket as u32 / 8 + 2;
+    (8 + bucket as u32 % 8) << (exp - 3)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DnsMetricsKey {
+    vtap_id: u16,
+    client_epc: i32,
+    resolver_ip: IpAddr,
+    resolver_epc: i32,
+}
+
+#[derive(Default)]
+struct DnsStats {
+    meter: AppMeter,
+    latency: Vec<u32>,
+}
+
+impl DnsStats {
+    fn rrt_p95(&self) -> u32 {
+        let count = self.latency.iter().map(|c| *c as u64).sum::<u64>();
+        let target = (count * 95 + 99) / 100;
+        let mut seen = 0;
+        for (bucket, c) in self.latency.iter().enumerate() {
+            seen += *c as u64;
+            if seen >= target && seen > 0 {
+                return bucket_latency(bucket);
+            }
+        }
+        0
+    }
+}
+
+// 按(客户端EPC, 解析服务器IP)聚合DNS日志，生成分钟粒度的应用指标，
+// 开启metrics-only时不再发送DNS日志，大幅减少日志量的同时保留解析服务器的健康状况
+pub struct DnsMetrics {
+    // 会话聚合会延迟发送日志，一个分钟在超过该延迟后才输出
+    delay: Duration,
+    last_check: u64,
+    stash: BTreeMap<u64, HashMap<DnsMetricsKey, DnsStats>>,
+    output: DebugSender<SendItem>,
+}
+
+impl DnsMetrics {
+    pub fn new(delay: Duration, output: DebugSender<SendItem>) -> Self {
+        Self {
+            delay,
+            last_check: 0,
+            stash: BTreeMap::new(),
+            output,
+        }
+    }
+
+    // 日志已转换为客户端到服务端的方向
+    pub fn add(&mut self, log: &AppProtoLogsData) {
+        let base = &log.base_info;
+        if base.head.proto != L7Protocol::Dns {
+            return;
+        }
+        let key = DnsMetricsKey {
+            vtap_id: base.vtap_id,
+            client_epc: base.l3_epc_id_src,
+            resolver_ip: base.ip_dst,
+            resolver_epc: base.l3_epc_id_dst,
+        };
+        let minute = base.start_time.as_secs() / MINUTE * MINUTE;
+        let stats = self
+            .stash
+            .entry(minute)
+            .or_default()
+            .entry(key)
+            .or_default();
+        let meter = &mut stats.meter;
+
+        match base.head.msg_type {
+            // 会话聚合结束时仍未匹配到响应的请求计为超时
+            LogMessageType::Request => {
+                meter.traffic.request += 1;
+                meter.anomaly.timeout += 1;
+                return;
+            }
+            LogMessageType::Response => meter.traffic.response += 1,
+            LogMessageType::Session => {
+                meter.traffic.request += 1;
+                meter.traffic.response += 1;
+            }
+            _ => return,
+        }
+        match base.head.status {
+            L7ResponseStatus::ClientError => meter.anomaly.client_error += 1,
+            L7ResponseStatus::ServerError => meter.anomaly.server_error += 1,
+            _ => (),
+        }
+        match base.head.code {
+            DNS_RCODE_NXDOMAIN => meter.dns.nxdomain += 1,
+            DNS_RCODE_SERVFAIL => meter.dns.servfail += 1,
+            _ => (),
+        }
+        if base.head.rrt > 0 {
+            let rrt = base.head.rrt.min(u32::MAX as u64) as u32;
+            meter.latency.rrt_max = meter.latency.rrt_max.max(rrt);
+            meter.latency.rrt_sum += base.head.rrt;
+            meter.latency.rrt_count += 1;
+            if stats.latency.is_empty() {
+                stats.latency = vec![0; LATENCY_BUCKETS];
+            }
+            stats.latency[latency_bucket(rrt)] += 1;
+        }
+    }
+
+    // 输出所有结束时间早于now - delay的分钟
+    pub fn flush(&mut self, now: Duration) {
+        if now.as_secs() == self.last_check {
+            return;
+        }
+        self.last_check = now.as_secs();
+        let deadline = now.saturating_sub(self.delay).as_secs();
+        while let Some((&minute, _)) = self.stash.iter().next() {
+            if minute + MINUTE > deadline {
+                break;
+            }
+            let stats = self.stash.remove(&minute).unwrap();
+            debug!("flush {} dns metrics of minute {}", stats.len(), minute);
+            for (key, stats) in stats {
+                let _ = self
+                    .output
+                    .send(SendItem::Metrics(Box::new(Self::to_document(
+                        minute, key, stats,
+                    ))));
+            }
+        }
+    }
+
+    // 客户端只区分EPC，IP为全0
+    fn to_document(minute: u64, key: DnsMetricsKey, stats: DnsStats) -> Document {
+        let mut meter = stats.meter;
+        meter.dns.rrt_p95 = stats.rrt_p95();
+        let mut doc = Document::new(Meter::App(meter));
+        doc.timestamp = minute as u32;
+        doc.tagger = Tagger {
+            code: Code::IP_PATH | Code::L3_EPC_PATH | Code::VTAP_ID | Code::L7_PROTOCOL,
+            ip: if key.resolver_ip.is_ipv6() {
+                Ipv6Addr::UNSPECIFIED.into()
+            } else {
+                Ipv4Addr::UNSPECIFIED.into()
+            },
+            ip1: key.resolver_ip,
+            is_ipv6: key.resolver_ip.is_ipv6(),
+            l3_epc_id: key.client_epc as i16,
+            l3_epc_id1: key.resolver_epc as i16,
+            vtap_id: key.vtap_id,
+            l7_protocol: L7Protocol::Dns,
+            ..Default::default()
+        };
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        meta_packet::MetaPacket,
+    };
+    use crate::debug::QueueDebugger;
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, AppProtoLogsInfo, DnsInfo};
+    use crate::utils::queue::bounded_with_debug;
+
+    fn log(timestamp: u64, msg_type: LogMessageType, code: u16, rrt: u64) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = "10.0.0.53".parse().unwrap();
+        packet.lookup_key.src_port = 40000;
+        packet.lookup_key.dst_port = 53;
+        packet.lookup_key.proto = IpProtocol::Udp;
+        packet.direction = PacketDirection::ClientToServer;
+        let head = AppProtoHead {
+            proto: L7Protocol::Dns,
+            msg_type,
+            status: match code {
+                0 => L7ResponseStatus::Ok,
+                DNS_RCODE_NXDOMAIN => L7ResponseStatus::ClientError,
+                _ => L7ResponseStatus::ServerError,
+            },
+            code,
+            rrt,
+            version: 0,
+            ..Default::default()
+        };
+        let mut base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        base_info.l3_epc_id_src = 3;
+        base_info.l3_epc_id_dst = -2;
+        AppProtoLogsData::new(base_info, AppProtoLogsInfo::Dns(DnsInfo::default()))
+    }
+
+    #[test]
+    fn latency_histogram() {
+        for rrt in [0, 15, 16, 17, 31, 32, 1000, 123456, u32::MAX] {
+            let bucket = latency_bucket(rrt);
+            assert!(bucket < LATENCY_BUCKETS);
+            let lower = bucket_latency(bucket);
+            assert!(lower <= rrt && rrt - lower <= rrt / 8, "{}", rrt);
+        }
+    }
+
+    #[test]
+    fn aggregate_by_resolver() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut metrics = DnsMetrics::new(Duration::from_secs(60), s);
+
+        for i in 0..18 {
+            metrics.add(&log(61, LogMessageType::Session, 0, 1000 + i));
+        }
+        metrics.add(&log(62, LogMessageType::Session, DNS_RCODE_NXDOMAIN, 5000));
+        metrics.add(&log(63, LogMessageType::Session, DNS_RCODE_SERVFAIL, 80000));
+        metrics.add(&log(64, LogMessageType::Request, 0, 0));
+        metrics.add(&log(121, LogMessageType::Session, 0, 1000));
+
+        metrics.flush(Duration::from_secs(150));
+        assert!(r.recv(Some(Duration::from_millis(10))).is_err());
+
+        metrics.flush(Duration::from_secs(181));
+        let doc = match r.recv(Some(Duration::from_millis(10))) {
+            Ok(SendItem::Metrics(doc)) => doc,
+            _ => panic!("no dns metrics"),
+        };
+        assert!(r.recv(Some(Duration::from_millis(10))).is_err());
+        assert_eq!(doc.timestamp, 60);
+        assert_eq!(doc.tagger.l3_epc_id, 3);
+        assert_eq!(doc.tagger.l3_epc_id1, -2);
+        assert_eq!(doc.tagger.ip1, "10.0.0.53".parse::<IpAddr>().unwrap());
+        assert_eq!(doc.tagger.ip, Ipv4Addr::UNSPECIFIED);
+        match doc.meter {
+            Meter::App(m) => {
+                assert_eq!(m.traffic.request, 21);
+                assert_eq!(m.traffic.response, 20);
+                assert_eq!(m.anomaly.timeout, 1);
+                assert_eq!(m.dns.nxdomain, 1);
+                assert_eq!(m.dns.servfail, 1);
+                assert_eq!(m.latency.rrt_count, 20);
+                assert_eq!(m.latency.rrt_max, 80000);
+                // 20个样本的p95为第19个，即5000us所在的桶
+                assert_eq!(m.dns.rrt_p95, bucket_latency(latency_bucket(5000)));
+            }
+            _ => panic!("unexpected meter"),
+        }
+    }
+}
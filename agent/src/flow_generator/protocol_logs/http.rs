@@ -15,24 +15,32 @@
  */
 
 use std::str;
+use std::sync::RwLock;
+use std::time::Duration;
 
 use arc_swap::access::Access;
-use log::info;
-use regex::Regex;
+use flate2::{Decompress, FlushDecompress, Status};
+use log::{info, warn};
 use serde::Serialize;
 
 use super::{
     consts::*, value_is_default, AppProtoHead, AppProtoLogsInfo, L7LogParse, L7ResponseStatus,
+    L7SessionId,
 };
 use super::{AppProtoHeadEnum, AppProtoLogsInfoEnum, LogMessageType};
 
 use crate::common::enums::{IpProtocol, PacketDirection};
 use crate::common::flow::L7Protocol;
 use crate::common::meta_packet::MetaPacket;
-use crate::config::handler::{L7LogDynamicConfig, LogParserAccess, TraceType};
-use crate::flow_generator::error::{Error, Result};
+use crate::common::tap_port::TapPort;
+use crate::config::{
+    handler::{L7LogDynamicConfig, LogParserAccess, TraceType},
+    HttpScrubConfig,
+};
+use crate::flow_generator::error::{Error, ParseContext, ParseStage, Result};
+use crate::flow_generator::golden;
 use crate::proto::flow_log;
-use crate::utils::bytes::read_u32_be;
+use crate::utils::bytes::{read_u16_le, read_u32_be, read_u32_le};
 use crate::utils::net::h2pack;
 
 #[derive(Serialize, Debug, Default, Clone)]
@@ -61,11 +69,105 @@ pub struct HttpInfo {
     pub req_content_length: Option<u64>,
     #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
     pub resp_content_length: Option<u64>,
+    // Content-Encoding，请求和响应都存在时取响应的
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub content_encoding: String,
+    // 解码后的Body长度，无Content-Encoding时与Content-Length一致
+    #[serde(
+        rename = "request_body_length",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub req_body_length: Option<u64>,
+    #[serde(
+        rename = "response_body_length",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resp_body_length: Option<u64>,
+    // gRPC-Web请求标记为grpc，grpc_status取自响应头或Body中的trailers帧
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub rpc_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_status: Option<u32>,
+    // 按l7-log-http-captured-headers配置采集的头部，key为小写
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub request_headers: Vec<(String, String)>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub response_headers: Vec<(String, String)>,
+    // 仅空闲连接记录携带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_connection: Option<IdleConnection>,
+    // 目的端口为Elasticsearch时根据URL补充的操作类型及索引名称
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub es_operation: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub es_index: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub es_slow_search: bool,
+    // 存在取值冲突的Content-Length，或同时携带Content-Length和Transfer-Encoding
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub smuggling_suspected: bool,
+    // 失败后在窗口内重发的相同请求标记为重试，与失败的请求使用相同的retry_group_id
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub retry: bool,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub retry_group_id: u64,
+    // 从连接中途开始解析，dynamic table未知导致部分头部缺失
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub hpack_incomplete: bool,
+    // SSE(text/event-stream)或保持时间超过l7-log-http-stream-interval的响应
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub streaming: bool,
+    // 仅流式响应的持续记录携带
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_continuation: Option<StreamContinuation>,
+}
+
+// HTTP/2长连接空闲时周期输出的连接信息，时间单位为微秒
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IdleConnection {
+    pub connection_age: u64,
+    pub streams_opened: u32,
+    // 最近一次请求或响应的时间戳，连接上没有请求时为连接建立的时间
+    pub last_activity: u64,
+}
+
+impl From<IdleConnection> for flow_log::IdleConnection {
+    fn from(c: IdleConnection) -> Self {
+        flow_log::IdleConnection {
+            connection_age: c.connection_age,
+            streams_opened: c.streams_opened,
+            last_activity: c.last_activity,
+        }
+    }
+}
+
+// HTTP/1流式响应持续期间周期输出的记录，时间单位为微秒
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamContinuation {
+    // 距响应首字节的时长
+    pub stream_age: u64,
+    // 含响应头的累计字节数
+    pub bytes_streamed: u64,
+    pub last_activity: u64,
+}
+
+impl From<StreamContinuation> for flow_log::StreamContinuation {
+    fn from(c: StreamContinuation) -> Self {
+        flow_log::StreamContinuation {
+            stream_age: c.stream_age,
+            bytes_streamed: c.bytes_streamed,
+            last_activity: c.last_activity,
+        }
+    }
 }
 
 impl HttpInfo {
     pub fn merge(&mut self, other: Self) {
         self.resp_content_length = other.resp_content_length;
+        self.resp_body_length = other.resp_body_length;
+        if !other.content_encoding.is_empty() {
+            self.content_encoding = other.content_encoding;
+        }
         if self.trace_id.is_empty() {
             self.trace_id = other.trace_id;
         }
@@ -75,6 +177,51 @@ impl HttpInfo {
         if self.x_request_id.is_empty() {
             self.x_request_id = other.x_request_id;
         }
+        if self.rpc_type.is_empty() {
+            self.rpc_type = other.rpc_type;
+        }
+        if other.grpc_status.is_some() {
+            self.grpc_status = other.grpc_status;
+        }
+        if self.request_headers.is_empty() {
+            self.request_headers = other.request_headers;
+        }
+        if !other.response_headers.is_empty() {
+            self.response_headers = other.response_headers;
+        }
+        self.smuggling_suspected |= other.smuggling_suspected;
+        self.hpack_incomplete |= other.hpack_incomplete;
+        self.streaming |= other.streaming;
+    }
+
+    // 响应保持时间超过阈值的请求（如长轮询）视为流式响应，rrt为首字节时延
+    pub fn amend_streaming(&mut self, rrt: Duration, threshold: Duration) {
+        if !threshold.is_zero() && rrt >= threshold {
+            self.streaming = true;
+        }
+    }
+
+    // 请求路径为空（如单独的响应）时不处理，rrt为0表示未与响应合并，不判断慢查询
+    pub fn amend_elasticsearch(&mut self, rrt: Duration, slow_search_threshold: Duration) {
+        if self.path.is_empty() {
+            return;
+        }
+        let (operation, index) = elasticsearch_operation(&self.method, &self.path);
+        self.es_slow_search = (operation == ES_OP_SEARCH || operation == ES_OP_MSEARCH)
+            && !rrt.is_zero()
+            && rrt >= slow_search_threshold;
+        self.es_operation = operation.to_owned();
+        self.es_index = index.to_owned();
+    }
+}
+
+impl L7SessionId for HttpInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.stream_id > 0 {
+            Some(self.stream_id)
+        } else {
+            None
+        }
     }
 }
 
@@ -98,6 +245,32 @@ impl From<HttpInfo> for flow_log::HttpInfo {
                 _ => -1,
             },
             x_request_id: f.x_request_id,
+            content_encoding: f.content_encoding,
+            req_body_length: match f.req_body_length {
+                Some(length) => length as i64,
+                _ => -1,
+            },
+            resp_body_length: match f.resp_body_length {
+                Some(length) => length as i64,
+                _ => -1,
+            },
+            rpc_type: f.rpc_type,
+            grpc_status: match f.grpc_status {
+                Some(status) => status as i32,
+                _ => -1,
+            },
+            request_headers: f.request_headers.into_iter().collect(),
+            response_headers: f.response_headers.into_iter().collect(),
+            idle_connection: f.idle_connection.map(|c| c.into()),
+            es_operation: f.es_operation,
+            es_index: f.es_index,
+            es_slow_search: f.es_slow_search,
+            smuggling_suspected: f.smuggling_suspected,
+            retry: f.retry,
+            retry_group_id: f.retry_group_id,
+            hpack_incomplete: f.hpack_incomplete,
+            streaming: f.streaming,
+            stream_continuation: f.stream_continuation.map(|c| c.into()),
         }
     }
 }
@@ -112,8 +285,14 @@ pub struct HttpLog {
     info: HttpInfo,
 
     is_https: bool,
+    decompress: bool,
 
     l7_log_dynamic_config: L7LogDynamicConfig,
+    scrub: HttpScrubConfig,
+    captured_headers: Vec<String>,
+
+    // eBPF数据可能从连接中途开始，缺少preface且不在帧边界上，需要重新同步
+    frame_resync: bool,
 }
 
 fn parse_lines(payload: &[u8], limit: usize) -> Vec<&[u8]> {
@@ -137,6 +316,331 @@ fn parse_lines(payload: &[u8], limit: usize) -> Vec<&[u8]> {
     return lines;
 }
 
+// 返回小写的头部名称及值，遇到空行（头部结束）时停止
+// 以空格或制表符开头的行为上一个头部的折叠续行(obs-fold)，以单个空格拼接
+fn parse_header_fields(lines: &[&[u8]]) -> Result<Vec<(String, String)>> {
+    let mut fields: Vec<(String, String)> = vec![];
+    let mut folding = false;
+    for line in lines {
+        match line.first() {
+            None | Some(b'\r') => break,
+            Some(b' ') | Some(b'\t') => {
+                if let (true, Some((_, value))) = (folding, fields.last_mut()) {
+                    let line = str::from_utf8(line)?.trim();
+                    if !line.is_empty() {
+                        value.push(' ');
+                        value.push_str(line);
+                    }
+                }
+                continue;
+            }
+            _ => (),
+        }
+        folding = false;
+        let col_index = match line.iter().position(|x| *x == b':') {
+            Some(i) if i + 1 < line.len() => i,
+            _ => continue,
+        };
+        let key = str::from_utf8(&line[..col_index])?.to_lowercase();
+        let value = str::from_utf8(&line[col_index + 1..])?.trim();
+        fields.push((key, value.to_owned()));
+        folding = true;
+    }
+    Ok(fields)
+}
+
+// 允许"5, 5"形式的重复值，取值不一致或非法时返回None
+fn parse_content_length(value: &str) -> Option<u64> {
+    let mut length = None;
+    for v in value.split(',') {
+        let v = v.trim().parse::<u64>().ok()?;
+        if length.is_some() && length != Some(v) {
+            return None;
+        }
+        length = Some(v);
+    }
+    length
+}
+
+// 解析chunked编码的Body，返回可见chunk的长度之和及是否已出现结束chunk
+fn parse_chunked_body<'a>(body: &'a [u8], chunks: &mut Vec<&'a [u8]>) -> (u64, bool) {
+    let mut length = 0;
+    let mut p = body;
+    loop {
+        let line_end = match p.windows(2).position(|w| w == b"\r\n") {
+            Some(i) => i,
+            None => return (length, false),
+        };
+        let size_line = match p[..line_end].iter().position(|c| *c == b';') {
+            Some(i) => &p[..i],
+            None => &p[..line_end],
+        };
+        let size = match str::from_utf8(size_line)
+            .ok()
+            .and_then(|s| u64::from_str_radix(s.trim(), 16).ok())
+        {
+            Some(s) => s,
+            None => return (length, false),
+        };
+        if size == 0 {
+            return (length, true);
+        }
+        length += size;
+        p = &p[line_end + 2..];
+        if size + 2 > p.len() as u64 {
+            chunks.push(&p[..(size as usize).min(p.len())]);
+            return (length, false);
+        }
+        chunks.push(&p[..size as usize]);
+        p = &p[size as usize + 2..];
+    }
+}
+
+fn gzip_header_length(body: &[u8]) -> Option<usize> {
+    if body.len() < GZIP_HEADER_LEN || body[0] != 0x1f || body[1] != 0x8b || body[2] != 8 {
+        return None;
+    }
+    let flags = body[3];
+    let mut offset = GZIP_HEADER_LEN;
+    if flags & GZIP_FLAG_EXTRA != 0 {
+        offset += 2 + read_u16_le(body.get(offset..offset + 2)?) as usize;
+    }
+    for flag in [GZIP_FLAG_NAME, GZIP_FLAG_COMMENT] {
+        if flags & flag != 0 {
+            offset += body.get(offset..)?.iter().position(|c| *c == 0)? + 1;
+        }
+    }
+    if flags & GZIP_FLAG_HCRC != 0 {
+        offset += 2;
+    }
+    if offset > body.len() {
+        return None;
+    }
+    Some(offset)
+}
+
+// 返回XML中第一个开始标签的名称（去掉命名空间前缀）及标签之后的内容
+fn xml_next_element(xml: &str) -> Option<(&str, &str)> {
+    let mut rest = xml;
+    loop {
+        rest = &rest[rest.find('<')? + 1..];
+        // 跳过结束标签、注释、声明和处理指令
+        if rest.starts_with(|c: char| c == '/' || c == '!' || c == '?') {
+            continue;
+        }
+        let end = rest.find(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')?;
+        let name = &rest[..end];
+        let local = name.rsplit(':').next().unwrap_or(name);
+        return Some((local, &rest[end..]));
+    }
+}
+
+// SOAP请求优先取SOAPAction（SOAP 1.2为Content-Type的action参数），否则取Body中的第一个元素
+// XML-RPC请求取methodName
+fn soap_operation(content_type: &str, soap_action: &str, body: &[u8]) -> Option<String> {
+    let media_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    if media_type != "text/xml"
+        && media_type != "application/xml"
+        && media_type != "application/soap+xml"
+    {
+        return None;
+    }
+    let action = if !soap_action.is_empty() {
+        soap_action
+    } else {
+        content_type
+            .split(';')
+            .find_map(|p| p.trim().strip_prefix("action="))
+            .unwrap_or_default()
+    };
+    // SOAPAction通常为URI，取最后一段作为操作名
+    let action = action
+        .trim_matches('"')
+        .rsplit(|c: char| c == '/' || c == '#')
+        .next();
+    if let Some(action) = action.filter(|a| !a.is_empty()) {
+        return Some(action.to_owned());
+    }
+
+    let body = String::from_utf8_lossy(body);
+    let (root, mut rest) = xml_next_element(&body)?;
+    match root {
+        "methodCall" => {
+            let start = rest.find("<methodName>")? + "<methodName>".len();
+            let end = rest[start..].find('<')?;
+            Some(rest[start..start + end].trim().to_owned()).filter(|m| !m.is_empty())
+        }
+        "Envelope" => loop {
+            let (name, next) = xml_next_element(rest)?;
+            if name == "Body" {
+                return xml_next_element(next).map(|(op, _)| op.to_owned());
+            }
+            rest = next;
+        },
+        _ => None,
+    }
+}
+
+// 估算gzip/deflate编码Body解码后的长度
+// Body完整的gzip直接读取尾部ISIZE，否则流式解压可见部分，按压缩比外推
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GrpcWebEncoding {
+    Binary,
+    // application/grpc-web-text，帧经过base64编码
+    Text,
+}
+
+fn grpc_web_encoding(content_type: &str) -> Option<GrpcWebEncoding> {
+    let media_type = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    if media_type.starts_with("application/grpc-web-text") {
+        Some(GrpcWebEncoding::Text)
+    } else if media_type.starts_with("application/grpc-web") {
+        Some(GrpcWebEncoding::Binary)
+    } else {
+        None
+    }
+}
+
+pub fn is_event_stream(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .map(|t| {
+            t.trim()
+                .eq_ignore_ascii_case(HTTP_CONTENT_TYPE_EVENT_STREAM)
+        })
+        .unwrap_or_default()
+}
+
+// grpc-web-text的Body可能由多段各自补齐的base64拼接而成，截断时只解码完整的4字节组
+fn decode_grpc_web_text(body: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    let mut rest = body;
+    while !rest.is_empty() {
+        let end = match rest.iter().position(|b| *b == b'=') {
+            Some(i) => i + rest[i..].iter().take_while(|b| **b == b'=').count(),
+            None => rest.len() - rest.len() % 4,
+        };
+        if end == 0 {
+            break;
+        }
+        match base64::decode(&rest[..end]) {
+            Ok(d) => decoded.extend_from_slice(&d),
+            Err(_) => break,
+        }
+        rest = &rest[end..];
+    }
+    decoded
+}
+
+// 跳过数据帧，从trailers帧中提取grpc-status
+fn grpc_web_status(mut frames: &[u8]) -> Option<u32> {
+    while frames.len() >= GRPC_WEB_FRAME_HEADER_LEN {
+        let flag = frames[0];
+        let length = read_u32_be(&frames[1..]) as usize;
+        let data = &frames[GRPC_WEB_FRAME_HEADER_LEN..];
+        if flag & GRPC_WEB_FLAG_TRAILERS != 0 {
+            let trailers = str::from_utf8(&data[..length.min(data.len())]).ok()?;
+            return trailers.split("\r\n").find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                if key.trim().eq_ignore_ascii_case("grpc-status") {
+                    value.trim().parse().ok()
+                } else {
+                    None
+                }
+            });
+        }
+        if length >= data.len() {
+            break;
+        }
+        frames = &data[length..];
+    }
+    None
+}
+
+// 按Elasticsearch REST API的URL分类，返回操作类型及索引名称
+// 索引为第一段不以_开头的路径，可能为逗号分隔的多个索引或通配符
+fn elasticsearch_operation<'a>(method: &str, path: &'a str) -> (&'static str, &'a str) {
+    let path = path
+        .split(|c: char| c == '?' || c == '#')
+        .next()
+        .unwrap_or_default();
+    let segments = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    let index = match segments.first() {
+        Some(s) if !s.starts_with('_') => *s,
+        _ => "",
+    };
+    let endpoint = segments.iter().find(|s| s.starts_with('_'));
+    let method = method.to_ascii_uppercase();
+    let operation = match endpoint.copied() {
+        Some("_search") | Some("_async_search") => ES_OP_SEARCH,
+        Some("_msearch") => ES_OP_MSEARCH,
+        Some("_count") => ES_OP_COUNT,
+        Some("_bulk") => ES_OP_BULK,
+        Some("_mget") => ES_OP_DOC_GET,
+        Some("_create") => ES_OP_DOC_CREATE,
+        Some("_update") => ES_OP_DOC_UPDATE,
+        Some("_doc") => match method.as_str() {
+            "GET" | "HEAD" => ES_OP_DOC_GET,
+            "DELETE" => ES_OP_DOC_DELETE,
+            _ => ES_OP_DOC_INDEX,
+        },
+        Some(_) => ES_OP_OTHER,
+        // 只有索引名称时为索引级别的操作
+        None if segments.len() == 1 && !index.is_empty() => match method.as_str() {
+            "PUT" => ES_OP_INDEX_CREATE,
+            "DELETE" => ES_OP_INDEX_DELETE,
+            _ => ES_OP_OTHER,
+        },
+        None => ES_OP_OTHER,
+    };
+    (operation, index)
+}
+
+fn estimate_decoded_length(encoding: &str, body: &[u8], wire_length: u64) -> Option<u64> {
+    let (data, zlib_header) = match encoding {
+        "gzip" | "x-gzip" => {
+            if body.len() as u64 == wire_length && body.len() >= GZIP_HEADER_LEN + GZIP_TRAILER_LEN
+            {
+                gzip_header_length(body)?;
+                return Some(read_u32_le(&body[body.len() - 4..]) as u64);
+            }
+            (&body[gzip_header_length(body)?..], false)
+        }
+        // RFC规定deflate为zlib格式，但也有实现直接发送raw deflate
+        "deflate" => (
+            body,
+            body.len() >= 2
+                && body[0] & 0x0f == 8
+                && (body[0] as u16 * 256 + body[1] as u16) % 31 == 0,
+        ),
+        _ => return None,
+    };
+
+    let mut decoder = Decompress::new(zlib_header);
+    let mut output = [0u8; HTTP_DECOMPRESS_BUFFER_SIZE];
+    while decoder.total_out() < HTTP_DECOMPRESS_MAX_OUTPUT {
+        let (consumed, decoded) = (decoder.total_in(), decoder.total_out());
+        match decoder.decompress(
+            &data[consumed as usize..],
+            &mut output,
+            FlushDecompress::None,
+        ) {
+            Ok(Status::StreamEnd) => return Some(decoder.total_out()),
+            Ok(_) if decoder.total_in() > consumed || decoder.total_out() > decoded => (),
+            _ => break,
+        }
+    }
+    let (consumed, decoded) = (decoder.total_in(), decoder.total_out());
+    if consumed == 0 || decoded == 0 {
+        return None;
+    }
+    Some(decoded.saturating_mul(wire_length) / consumed)
+}
+
 impl HttpLog {
     const TRACE_ID: u8 = 0;
     const SPAN_ID: u8 = 1;
@@ -144,11 +648,18 @@ impl HttpLog {
     pub fn new(config: &LogParserAccess, is_https: bool) -> Self {
         Self {
             l7_log_dynamic_config: config.load().l7_log_dynamic.clone(),
+            decompress: config.load().l7_log_http_decompress,
+            scrub: config.load().l7_log_http_scrub.clone(),
+            captured_headers: config.load().l7_log_http_captured_headers.clone(),
             is_https,
             ..Default::default()
         }
     }
 
+    pub fn set_frame_resync(&mut self, frame_resync: bool) {
+        self.frame_resync = frame_resync;
+    }
+
     fn get_l7_protocol(&self) -> L7Protocol {
         match self.proto {
             L7Protocol::Http1 => {
@@ -171,12 +682,78 @@ impl HttpLog {
 
     pub fn update_config(&mut self, config: &LogParserAccess) {
         self.l7_log_dynamic_config = config.load().l7_log_dynamic.clone();
+        self.decompress = config.load().l7_log_http_decompress;
+        self.scrub = config.load().l7_log_http_scrub.clone();
+        self.captured_headers = config.load().l7_log_http_captured_headers.clone();
         info!(
             "http log update l7 log dynamic config to {:#?}",
             self.l7_log_dynamic_config
         );
     }
 
+    // 将敏感查询参数的值替换为mask，其余部分保持不变
+    fn scrub_path(&self, path: &str) -> String {
+        let (resource, query) = match path.split_once('?') {
+            Some(p) if !self.scrub.query_params.is_empty() => p,
+            _ => return path.to_owned(),
+        };
+        let (query, fragment) = match query.split_once('#') {
+            Some((q, f)) => (q, Some(f)),
+            None => (query, None),
+        };
+        let mut scrubbed = String::with_capacity(path.len());
+        scrubbed.push_str(resource);
+        scrubbed.push('?');
+        for (i, param) in query.split('&').enumerate() {
+            if i > 0 {
+                scrubbed.push('&');
+            }
+            match param.split_once('=') {
+                Some((key, _))
+                    if self
+                        .scrub
+                        .query_params
+                        .iter()
+                        .any(|p| p.eq_ignore_ascii_case(key)) =>
+                {
+                    scrubbed.push_str(key);
+                    scrubbed.push('=');
+                    scrubbed.push_str(&self.scrub.mask);
+                }
+                _ => scrubbed.push_str(param),
+            }
+        }
+        if let Some(fragment) = fragment {
+            scrubbed.push('#');
+            scrubbed.push_str(fragment);
+        }
+        scrubbed
+    }
+
+    // key需为小写
+    fn scrub_header(&self, key: &str, value: &str) -> String {
+        if self.scrub.headers.iter().any(|h| h == key) {
+            self.scrub.mask.clone()
+        } else {
+            value.to_owned()
+        }
+    }
+
+    // key需为小写，采集的值同样经过脱敏
+    fn capture_header(&self, key: &str, value: &str, headers: &mut Vec<(String, String)>) {
+        if self.captured_headers.iter().any(|h| h == key) {
+            headers.push((key.to_owned(), self.scrub_header(key, value)));
+        }
+    }
+
+    fn set_captured_headers(&mut self, headers: Vec<(String, String)>, direction: PacketDirection) {
+        if direction == PacketDirection::ServerToClient {
+            self.info.response_headers = headers;
+        } else {
+            self.info.request_headers = headers;
+        }
+    }
+
     fn reset_logs(&mut self) {
         self.status_code = 0;
         self.info = HttpInfo::default();
@@ -198,13 +775,23 @@ impl HttpLog {
         }
     }
 
+    // 参考https://github.com/grpc/grpc/blob/master/doc/statuscodes.md，按错误原因区分客户端和服务端异常
+    fn set_grpc_status(&mut self, grpc_status: u32) {
+        self.info.grpc_status = Some(grpc_status);
+        self.status = match grpc_status {
+            0 => L7ResponseStatus::Ok,
+            1 | 3 | 5 | 6 | 7 | 9 | 11 | 16 => L7ResponseStatus::ClientError,
+            _ => L7ResponseStatus::ServerError,
+        };
+    }
+
     fn parse_http_v1(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
         if !is_http_v1_payload(payload) {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
         }
         let lines = parse_lines(payload, 20);
         if lines.len() == 0 {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()));
         }
 
         if direction == PacketDirection::ServerToClient {
@@ -221,69 +808,152 @@ impl HttpLog {
             // HTTP请求行：GET /background.png HTTP/1.0
             let contexts: Vec<&str> = str::from_utf8(lines[0])?.split(" ").collect();
             if contexts.len() != 3 {
-                return Err(Error::HttpHeaderParseFailed);
+                return Err(Error::HttpHeaderParseFailed(
+                    ParseContext::new(ParseStage::Request).mismatch(3, contexts.len()),
+                ));
             }
 
             self.info.method = contexts[0].to_string();
-            self.info.path = contexts[1].to_string();
+            self.info.path = self.scrub_path(contexts[1]);
             self.info.version = get_http_request_version(contexts[2])?.to_string();
 
             self.msg_type = LogMessageType::Request;
         }
 
+        let fields = parse_header_fields(&lines[1..])?;
         let mut content_length: Option<u64> = None;
-        for body_line in &lines[1..] {
-            let col_index = body_line.iter().position(|x| *x == b':');
-            if col_index.is_none() {
-                continue;
-            }
-            let col_index = col_index.unwrap();
-            if col_index + 1 >= body_line.len() {
-                continue;
-            }
-            let key = str::from_utf8(&body_line[..col_index])?.to_lowercase();
-            let value = str::from_utf8(&body_line[col_index + 1..])?.trim();
-            if &key == "content-length" {
-                content_length = Some(value.parse::<u64>().unwrap_or_default());
-            } else if self.l7_log_dynamic_config.is_trace_id(key.as_str()) {
-                if let Some(id) = Self::decode_id(value, key.as_str(), Self::TRACE_ID) {
+        let mut length_conflict = false;
+        let mut transfer_encoding = false;
+        let mut is_chunked = false;
+        let (mut content_type, mut soap_action) = ("", "");
+        let mut grpc_status = None;
+        let mut headers = vec![];
+        for (key, value) in fields.iter() {
+            let (key, value) = (key.as_str(), value.as_str());
+            self.capture_header(key, value, &mut headers);
+            if key == "content-length" {
+                match (parse_content_length(value), content_length) {
+                    (Some(length), None) if !length_conflict => content_length = Some(length),
+                    (Some(length), Some(last)) if length == last => (),
+                    _ => {
+                        content_length = None;
+                        length_conflict = true;
+                    }
+                }
+            } else if key == "content-encoding" {
+                self.info.content_encoding = value.to_lowercase();
+            } else if key == "transfer-encoding" {
+                transfer_encoding = true;
+                is_chunked = value.to_lowercase().contains("chunked");
+            } else if key == "content-type" {
+                content_type = value;
+                self.info.streaming |= is_event_stream(value);
+            } else if key == "soapaction" {
+                soap_action = value;
+            } else if key == "grpc-status" {
+                grpc_status = value.parse::<u32>().ok();
+            } else if self.l7_log_dynamic_config.is_trace_id(key) {
+                if let Some(id) = Self::decode_id(value, key, Self::TRACE_ID) {
                     self.info.trace_id = id;
                 }
                 // 存在配置相同字段的情况，如“sw8”
-                if self.l7_log_dynamic_config.is_span_id(key.as_str()) {
-                    if let Some(id) = Self::decode_id(value, key.as_str(), Self::SPAN_ID) {
+                if self.l7_log_dynamic_config.is_span_id(key) {
+                    if let Some(id) = Self::decode_id(value, key, Self::SPAN_ID) {
                         self.info.span_id = id;
                     }
                 }
-            } else if self.l7_log_dynamic_config.is_span_id(key.as_str()) {
-                if let Some(id) = Self::decode_id(value, key.as_str(), Self::SPAN_ID) {
+            } else if self.l7_log_dynamic_config.is_span_id(key) {
+                if let Some(id) = Self::decode_id(value, key, Self::SPAN_ID) {
                     self.info.span_id = id;
                 }
             } else if !self.l7_log_dynamic_config.x_request_id_origin.is_empty()
                 && key == self.l7_log_dynamic_config.x_request_id_lower
             {
-                self.info.x_request_id = value.to_owned();
+                self.info.x_request_id = self.scrub_header(key, value);
             } else if direction == PacketDirection::ClientToServer {
-                if &key == "host" {
+                if key == "host" {
                     self.info.host = value.to_owned();
                 } else if !self.l7_log_dynamic_config.proxy_client_origin.is_empty()
                     && key == self.l7_log_dynamic_config.proxy_client_lower
                 {
-                    self.info.client_ip = value.to_owned();
+                    self.info.client_ip = self.scrub_header(key, value);
                 }
             }
         }
+        self.set_captured_headers(headers, direction);
+        // 前后端对Body边界的判断可能不一致，标记会话但仍按Transfer-Encoding优先解析
+        self.info.smuggling_suspected =
+            length_conflict || (transfer_encoding && content_length.is_some());
+
+        let body = match payload
+            .windows(HTTP_HEADER_END.len())
+            .position(|w| w == HTTP_HEADER_END)
+        {
+            Some(i) => &payload[i + HTTP_HEADER_END.len()..],
+            None => &[],
+        };
+        // SOAP/XML-RPC以操作名作为request_resource
+        if direction == PacketDirection::ClientToServer {
+            if let Some(operation) = soap_operation(content_type, soap_action, body) {
+                self.info.path = operation;
+            }
+        }
+        let mut chunks = vec![];
+        let mut chunked_data = vec![];
+        let body = if is_chunked {
+            // chunked编码仅在包内出现结束chunk时才能确定Body长度
+            let (length, completed) = parse_chunked_body(body, &mut chunks);
+            if completed {
+                content_length = Some(length);
+            }
+            if self.decompress && !self.info.content_encoding.is_empty() {
+                chunked_data = chunks.concat();
+            }
+            &chunked_data[..]
+        } else {
+            &body[..body.len().min(content_length.unwrap_or_default() as usize)]
+        };
+        let body_length = self.decoded_body_length(content_length, body);
+
+        if let Some(encoding) = grpc_web_encoding(content_type) {
+            self.info.rpc_type = "grpc".to_owned();
+            // trailers-only响应的grpc-status在响应头中，否则在Body末尾的trailers帧中
+            if direction == PacketDirection::ServerToClient && grpc_status.is_none() {
+                let frames = if is_chunked {
+                    chunks.concat()
+                } else {
+                    body.to_vec()
+                };
+                grpc_status = match encoding {
+                    GrpcWebEncoding::Binary => grpc_web_status(&frames),
+                    GrpcWebEncoding::Text => grpc_web_status(&decode_grpc_web_text(&frames)),
+                };
+            }
+            if let Some(status) = grpc_status {
+                self.set_grpc_status(status);
+            }
+        }
 
         // 当解析完所有Header仍未找到Content-Length，则认为该字段值为0
         if direction == PacketDirection::ServerToClient {
             self.info.resp_content_length = content_length;
+            self.info.resp_body_length = body_length;
         } else {
             self.info.req_content_length = content_length;
+            self.info.req_body_length = body_length;
         }
         self.proto = L7Protocol::Http1;
         Ok(())
     }
 
+    fn decoded_body_length(&self, content_length: Option<u64>, body: &[u8]) -> Option<u64> {
+        match self.info.content_encoding.as_str() {
+            "" | "identity" => content_length,
+            encoding if self.decompress => estimate_decoded_length(encoding, body, content_length?),
+            _ => None,
+        }
+    }
+
     fn has_magic(payload: &[u8]) -> bool {
         if payload.len() < HTTPV2_MAGIC_LENGTH {
             return false;
@@ -295,12 +965,66 @@ impl HttpLog {
         }
     }
 
+    // 按帧类型校验长度和StreamId，参考https://tools.ietf.org/html/rfc7540#section-6
+    fn is_valid_frame_header(payload: &[u8], max_frame_size: u32) -> bool {
+        if payload.len() < HTTPV2_FRAME_HEADER_LENGTH {
+            return false;
+        }
+        let mut header = Httpv2Headers::default();
+        if header.parse_headers_frame(payload).is_err() || header.frame_length > max_frame_size {
+            return false;
+        }
+        let (length, stream_id) = (header.frame_length, header.stream_id);
+        match header.frame_type {
+            HTTPV2_FRAME_DATA_TYPE
+            | HTTPV2_FRAME_HEADERS_TYPE
+            | HTTPV2_FRAME_PUSH_PROMISE_TYPE
+            | HTTPV2_FRAME_CONTINUATION_TYPE => stream_id != 0,
+            HTTPV2_FRAME_PRIORITY_TYPE => stream_id != 0 && length == 5,
+            HTTPV2_FRAME_RST_STREAM_TYPE => stream_id != 0 && length == 4,
+            HTTPV2_FRAME_SETTINGS_TYPE => stream_id == 0 && length % 6 == 0,
+            HTTPV2_FRAME_PING_TYPE => stream_id == 0 && length == 8,
+            HTTPV2_FRAME_GOAWAY_TYPE => stream_id == 0 && length >= 8,
+            HTTPV2_FRAME_WINDOW_UPDATE_TYPE => length == 4,
+            _ => false,
+        }
+    }
+
+    // 帧头合法，且帧之后为数据结尾或另一个合法帧头，被截断的帧只接受Headers帧
+    fn is_frame_boundary(payload: &[u8], max_frame_size: u32) -> bool {
+        if !Self::is_valid_frame_header(payload, max_frame_size) {
+            return false;
+        }
+        let next = HTTPV2_FRAME_HEADER_LENGTH + (read_u32_be(payload) >> 8) as usize;
+        if next > payload.len() {
+            return payload[3] == HTTPV2_FRAME_HEADERS_TYPE;
+        }
+        payload.len() - next < HTTPV2_FRAME_HEADER_LENGTH
+            || Self::is_valid_frame_header(&payload[next..], max_frame_size)
+    }
+
+    // 查找时按默认的最大帧长度校验，减少误判
+    fn find_frame_boundary(payload: &[u8]) -> Option<usize> {
+        (1..payload.len().saturating_sub(HTTPV2_FRAME_HEADER_LENGTH))
+            .find(|&i| Self::is_frame_boundary(&payload[i..], HTTPV2_DEFAULT_MAX_FRAME_SIZE))
+    }
+
     fn parse_http_v2(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
         let mut content_length: Option<u64> = None;
         let mut header_frame_parsed = false;
         let mut is_httpv2 = false;
         let mut frame_payload = payload;
+        if self.frame_resync
+            && !Self::has_magic(payload)
+            && !Self::is_frame_boundary(payload, HTTPV2_FRAME_LENGTH_MAX)
+        {
+            if let Some(offset) = Self::find_frame_boundary(payload) {
+                frame_payload = &payload[offset..];
+            }
+        }
         let mut httpv2_header = Httpv2Headers::default();
+        let mut grpc_status = None;
+        let mut headers = vec![];
 
         while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
             if Self::has_magic(frame_payload) {
@@ -349,9 +1073,14 @@ impl HttpLog {
                 let parse_rst = parser.parse(header_frame_payload);
 
                 if let Err(_) = parse_rst {
-                    return Err(Error::HttpHeaderParseFailed);
+                    return Err(Error::HttpHeaderParseFailed(
+                        ParseContext::new(ParseStage::Payload).at(l_offset as usize),
+                    ));
                 }
                 let header_list = parse_rst.unwrap();
+                if parser.is_incomplete() {
+                    self.info.hpack_incomplete = true;
+                }
 
                 for header in header_list.iter() {
                     match header.0.as_slice() {
@@ -375,7 +1104,11 @@ impl HttpLog {
                         }
                         b":path" => {
                             self.info.path =
-                                String::from_utf8_lossy(header.1.as_slice()).into_owned()
+                                self.scrub_path(&String::from_utf8_lossy(header.1.as_slice()))
+                        }
+                        b"content-encoding" => {
+                            self.info.content_encoding =
+                                String::from_utf8_lossy(header.1.as_slice()).to_lowercase()
                         }
                         b"content-length" => {
                             content_length = Some(
@@ -385,6 +1118,18 @@ impl HttpLog {
                                     .unwrap_or_default(),
                             )
                         }
+                        b"content-type" => {
+                            let content_type = String::from_utf8_lossy(header.1.as_slice());
+                            if grpc_web_encoding(&content_type).is_some() {
+                                self.info.rpc_type = "grpc".to_owned();
+                            }
+                            self.info.streaming |= is_event_stream(&content_type);
+                        }
+                        b"grpc-status" => {
+                            grpc_status = str::from_utf8(header.1.as_slice())
+                                .ok()
+                                .and_then(|s| s.parse::<u32>().ok())
+                        }
                         _ => {}
                     }
 
@@ -394,6 +1139,11 @@ impl HttpLog {
 
                     let key = String::from_utf8_lossy(header.0.as_ref()).into_owned();
                     let key = key.as_str();
+                    self.capture_header(
+                        key,
+                        &String::from_utf8_lossy(header.1.as_ref()),
+                        &mut headers,
+                    );
 
                     if self.l7_log_dynamic_config.is_trace_id(key) {
                         if let Some(id) = Self::decode_id(
@@ -425,13 +1175,13 @@ impl HttpLog {
                         && header.0 == self.l7_log_dynamic_config.x_request_id_lower.as_bytes()
                     {
                         self.info.x_request_id =
-                            String::from_utf8_lossy(header.1.as_ref()).into_owned();
+                            self.scrub_header(key, &String::from_utf8_lossy(header.1.as_ref()));
                     } else if direction == PacketDirection::ClientToServer
                         && !self.l7_log_dynamic_config.proxy_client_origin.is_empty()
                         && header.0 == self.l7_log_dynamic_config.proxy_client_lower.as_bytes()
                     {
                         self.info.client_ip =
-                            String::from_utf8_lossy(header.1.as_ref()).into_owned();
+                            self.scrub_header(key, &String::from_utf8_lossy(header.1.as_ref()));
                     }
                 }
                 header_frame_parsed = true;
@@ -473,25 +1223,40 @@ impl HttpLog {
         }
 
         if is_httpv2 {
+            self.set_captured_headers(headers, direction);
+            // :method或:status引用了未知的dynamic table项时，仍输出缺少部分字段的日志
+            let partial = self.frame_resync && self.info.hpack_incomplete;
             if direction == PacketDirection::ClientToServer {
-                if check_http_method(&self.info.method).is_err() {
-                    return Err(Error::HttpHeaderParseFailed);
+                if partial && self.info.method.is_empty() {
+                    self.msg_type = LogMessageType::Request;
+                } else if check_http_method(&self.info.method).is_err() {
+                    return Err(Error::HttpHeaderParseFailed(ParseStage::Request.into()));
                 }
                 self.info.req_content_length = content_length;
+                // HTTP2的DATA帧不做解压，仅在未编码时记录Body长度
+                self.info.req_body_length = self.decoded_body_length(content_length, &[]);
             } else {
-                if self.status_code < HTTP_STATUS_CODE_MIN
+                if partial && self.status_code == 0 {
+                    self.msg_type = LogMessageType::Response;
+                    self.status = L7ResponseStatus::NotExist;
+                } else if self.status_code < HTTP_STATUS_CODE_MIN
                     || self.status_code > HTTP_STATUS_CODE_MAX
                 {
-                    return Err(Error::HttpHeaderParseFailed);
+                    return Err(Error::HttpHeaderParseFailed(ParseStage::Response.into()));
                 }
                 self.info.resp_content_length = content_length;
+                self.info.resp_body_length = self.decoded_body_length(content_length, &[]);
+                // HTTP2的DATA帧不做解析，仅处理trailers-only响应头中的grpc-status
+                if let Some(status) = grpc_status.filter(|_| !self.info.rpc_type.is_empty()) {
+                    self.set_grpc_status(status);
+                }
             }
             self.info.version = String::from("2");
             self.info.stream_id = httpv2_header.stream_id;
             self.proto = L7Protocol::Http2;
             return Ok(());
         }
-        Err(Error::HttpHeaderParseFailed)
+        Err(Error::HttpHeaderParseFailed(ParseStage::Header.into()))
     }
 
     // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS
@@ -598,6 +1363,7 @@ impl L7LogParse for HttpLog {
             code: self.status_code,
             rrt: 0,
             version: 0,
+            ..Default::default()
         }))
     }
 
@@ -634,12 +1400,16 @@ impl Httpv2Headers {
     pub fn parse_headers_frame(&mut self, payload: &[u8]) -> Result<()> {
         let frame_type = payload[3];
         if frame_type < HTTPV2_FRAME_TYPE_MIN || frame_type > HTTPV2_FRAME_TYPE_MAX {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(
+                ParseContext::new(ParseStage::Header).at(3),
+            ));
         }
 
         let stream_id = read_u32_be(&payload[5..]);
         if stream_id & 0x80000000 != 0 {
-            return Err(Error::HttpHeaderParseFailed);
+            return Err(Error::HttpHeaderParseFailed(
+                ParseContext::new(ParseStage::Header).at(5),
+            ));
         }
 
         self.frame_length = read_u32_be(&payload) >> 8;
@@ -651,11 +1421,62 @@ impl Httpv2Headers {
     }
 }
 
-const HTTP_METHODS: [&'static str; 9] = [
-    "OPTIONS", "GET", "HEAD", "POST", "PUT", "DELETE", "TRACE", "CONNECT", "PATCH",
+// 参考：https://www.w3.org/Protocols/rfc2616/rfc2616-sec9.html 及WebDAV(RFC 4918)
+const HTTP_METHODS: [&'static str; 17] = [
+    "OPTIONS",
+    "GET",
+    "HEAD",
+    "POST",
+    "PUT",
+    "DELETE",
+    "TRACE",
+    "CONNECT",
+    "PATCH",
+    "PROPFIND",
+    "PROPPATCH",
+    "MKCOL",
+    "COPY",
+    "MOVE",
+    "LOCK",
+    "UNLOCK",
+    "PURGE",
 ];
 const RESPONSE_PREFIX: &'static str = "HTTP/";
 
+// 由l7-log-http-custom-methods配置，协议识别函数没有配置参数，因此使用进程全局变量
+static CUSTOM_METHODS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+pub fn set_custom_methods(methods: &[String]) {
+    let methods = methods
+        .iter()
+        .filter(|m| {
+            if m.len() > HTTP_METHOD_MAX_LEN {
+                warn!(
+                    "ignore http custom method {} longer than {}",
+                    m, HTTP_METHOD_MAX_LEN
+                );
+                return false;
+            }
+            true
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut custom = CUSTOM_METHODS.write().unwrap();
+    if *custom != methods {
+        info!("http custom methods set to {:?}", methods);
+        *custom = methods;
+    }
+}
+
+fn is_http_method(method: &[u8]) -> bool {
+    HTTP_METHODS.iter().any(|m| m.as_bytes() == method)
+        || CUSTOM_METHODS
+            .read()
+            .unwrap()
+            .iter()
+            .any(|m| m.as_bytes() == method)
+}
+
 fn has_prefix(s: &[u8], prefix: &[u8]) -> bool {
     s.len() >= prefix.len() && &s[..prefix.len()] == prefix
 }
@@ -664,21 +1485,21 @@ pub fn is_http_v1_payload(buf: &[u8]) -> bool {
     if has_prefix(buf, RESPONSE_PREFIX.as_bytes()) {
         return true;
     }
-    for m in HTTP_METHODS {
-        if has_prefix(buf, m.as_bytes()) {
-            return true;
-        }
+    match buf
+        .iter()
+        .take(HTTP_METHOD_MAX_LEN + 1)
+        .position(|b| *b == b' ')
+    {
+        Some(i) => is_http_method(&buf[..i]),
+        None => false,
     }
-    false
 }
 
-// 参考：https://www.w3.org/Protocols/rfc2616/rfc2616-sec9.html
 pub fn check_http_method(method: &str) -> Result<()> {
-    match method {
-        "OPTIONS" | "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "TRACE" | "CONNECT" | "PATCH" => {
-            Ok(())
-        }
-        _ => Err(Error::HttpHeaderParseFailed),
+    if is_http_method(method.as_bytes()) {
+        Ok(())
+    } else {
+        Err(Error::HttpHeaderParseFailed(ParseStage::Request.into()))
     }
 }
 
@@ -686,7 +1507,10 @@ pub fn check_http_method(method: &str) -> Result<()> {
 pub fn get_http_method(line_info: &[u8]) -> Result<(String, usize)> {
     // 截取请求行第一个空格前，进行method匹配
     if line_info.len() < HTTP_METHOD_AND_SPACE_MAX_OFFSET {
-        return Err(Error::HttpHeaderParseFailed);
+        return Err(Error::HttpHeaderParseFailed(
+            ParseContext::new(ParseStage::Request)
+                .mismatch(HTTP_METHOD_AND_SPACE_MAX_OFFSET, line_info.len()),
+        ));
     }
     let line_str = str::from_utf8(line_info).unwrap_or_default();
     if let Some(space_index) = line_str.find(' ') {
@@ -694,7 +1518,7 @@ pub fn get_http_method(line_info: &[u8]) -> Result<(String, usize)> {
         check_http_method(method)?;
         return Ok((method.to_string(), space_index));
     }
-    Err(Error::HttpHeaderParseFailed)
+    Err(Error::HttpHeaderParseFailed(ParseStage::Request.into()))
 }
 
 pub fn get_http_request_version(version: &str) -> Result<&str> {
@@ -703,33 +1527,37 @@ pub fn get_http_request_version(version: &str) -> Result<&str> {
     match version {
         HTTP_V1_0_VERSION => return Ok("1.0"),
         HTTP_V1_1_VERSION => return Ok("1.1"),
-        _ => return Err(Error::HttpHeaderParseFailed),
+        _ => return Err(Error::HttpHeaderParseFailed(ParseStage::Request.into())),
     }
 }
 
 pub fn get_http_resp_info(line_info: &str) -> Result<(String, u16)> {
     if line_info.len() < HTTP_RESP_MIN_LEN {
-        return Err(Error::HttpHeaderParseFailed);
+        return Err(Error::HttpHeaderParseFailed(
+            ParseContext::new(ParseStage::Response).mismatch(HTTP_RESP_MIN_LEN, line_info.len()),
+        ));
     }
     // HTTP响应行：HTTP/1.1 404 Not Found.
-    let mut params = line_info.split(' ');
+    // 原因短语可以包含空格，也可能连同前面的空格一起省略，如“HTTP/1.1 200”
+    let mut params = line_info.splitn(3, ' ');
     // version解析
     let version = match params.next().unwrap_or_default() {
         HTTP_V1_0_VERSION => "1.0".to_string(),
         HTTP_V1_1_VERSION => "1.1".to_string(),
-        _ => return Err(Error::HttpHeaderParseFailed),
+        _ => return Err(Error::HttpHeaderParseFailed(ParseStage::Response.into())),
     };
 
-    // 响应码值校验
+    // 响应码值校验，必须为3位数字
     // 参考：https://baike.baidu.com/item/HTTP%E7%8A%B6%E6%80%81%E7%A0%81/5053660?fr=aladdin
-    let status_code = params
-        .next()
-        .unwrap_or_default()
-        .parse::<u16>()
-        .unwrap_or_default();
+    let status_code = params.next().unwrap_or_default();
+    if status_code.len() != HTTP_STATUS_CODE_LEN || !status_code.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(Error::HttpHeaderParseFailed(ParseStage::Response.into()));
+    }
+    let status_code = status_code.parse::<u16>().unwrap_or_default();
 
     if status_code < HTTP_STATUS_CODE_MIN || status_code > HTTP_STATUS_CODE_MAX {
-        return Err(Error::HttpHeaderParseFailed);
+        return Err(Error::HttpHeaderParseFailed(ParseStage::Response.into()));
     }
     Ok((version, status_code))
 }
@@ -753,9 +1581,17 @@ pub fn http1_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
         return false;
     }
 
-    let regex = Regex::new("^(GET|POST|HEAD|PUT|DELETE|CONNECT|TRACE|OPTIONS|LINK|UNLINK|COPY|MOVE|PATCH|WRAPPED|EXTENSION\\-METHOD).+HTTP/1.[01]$").unwrap();
-    let line = String::from_utf8_lossy(lines[0]).into_owned();
-    return regex.is_match(line.as_str());
+    // 请求行：Method SP Request-URI SP HTTP-Version
+    let line = match str::from_utf8(lines[0]) {
+        Ok(line) => line,
+        Err(_) => return false,
+    };
+    match (line.split_once(' '), line.rsplit_once(' ')) {
+        (Some((method, _)), Some((head, version))) if head.len() > method.len() => {
+            check_http_method(method).is_ok() && get_http_request_version(version).is_ok()
+        }
+        _ => false,
+    }
 }
 
 // 通过请求识别HTTPv2
@@ -771,43 +1607,19 @@ pub fn http2_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     }
     let payload = payload.unwrap();
     let mut http2 = HttpLog::default();
+    http2.frame_resync = packet.tap_port.is_from(TapPort::FROM_EBPF);
+    // 协议识别要求能解出请求方法，不接受不完整的头部
     return http2
         .parse_http_v2(payload, PacketDirection::ClientToServer)
-        .is_ok();
+        .is_ok()
+        && !http2.info.method.is_empty();
 }
 
-#[cfg(test)]
-mod tests {
-    use std::fs;
-    use std::path::Path;
-
-    use super::*;
-
-    use crate::{common::enums::PacketDirection, utils::test::Capture};
-
-    const FILE_DIR: &str = "resources/test/flow_generator/http";
-
-    fn run(name: &str) -> String {
-        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1500));
-        let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
-
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
+impl HttpLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
         let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-
+        golden::parse_payloads(packets, |payload, packet, output| {
             let mut http = HttpLog::default();
             http.l7_log_dynamic_config = L7LogDynamicConfig {
                 proxy_client_origin: "".to_string(),
@@ -824,8 +1636,28 @@ mod tests {
             is_http |= http2_check_protocol(&mut bitmap, packet);
 
             output.push_str(&format!("{:?} is_http: {}\r\n", http.info, is_http));
-        }
-        output
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    use crate::{common::enums::PacketDirection, utils::test::Capture};
+
+    const FILE_DIR: &str = "resources/test/flow_generator/http";
+
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1500));
+        let mut packets = capture.as_meta_packets();
+        HttpLog::golden_output(&mut packets)
     }
 
     #[test]
@@ -851,4 +1683,375 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn parse_encoded_body() {
+        let body = "{\"message\":\"Empty cart\",\"success\":false}".repeat(20);
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut payload = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            compressed.len()
+        )
+        .into_bytes();
+        payload.extend_from_slice(&compressed);
+
+        let mut http = HttpLog::default();
+        let _ = http.parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.info.content_encoding, "gzip");
+        assert_eq!(http.info.resp_content_length, Some(compressed.len() as u64));
+        assert_eq!(http.info.resp_body_length, None);
+
+        http.decompress = true;
+        let _ = http.parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.info.resp_body_length, Some(body.len() as u64));
+
+        // Body被截断时按压缩比估算
+        let _ = http.parse(
+            &payload[..payload.len() - 8],
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        );
+        assert!(http.info.resp_body_length.unwrap() > compressed.len() as u64);
+
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3;ext=1\r\nabc\r\n2\r\nde\r\n0\r\n\r\n";
+        let _ = http.parse(chunked, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.info.resp_content_length, Some(5));
+        assert_eq!(http.info.resp_body_length, Some(5));
+
+        // 未出现结束chunk时长度未知
+        let _ = http.parse(
+            &chunked[..chunked.len() - 5],
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(http.info.resp_content_length, None);
+    }
+
+    #[test]
+    fn scrub() {
+        let mut http = HttpLog::default();
+        http.l7_log_dynamic_config.x_request_id_origin = "Cookie".to_string();
+        http.l7_log_dynamic_config.x_request_id_lower = "cookie".to_string();
+
+        let payload = b"GET /api/login?user=alice&Token=abc123&page=2#top HTTP/1.1\r\nHost: example.com\r\nCookie: session=xyz\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.path, "/api/login?user=alice&Token=***&page=2#top");
+        assert_eq!(http.info.x_request_id, "***");
+
+        http.scrub.query_params.clear();
+        http.scrub.headers.clear();
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(
+            http.info.path,
+            "/api/login?user=alice&Token=abc123&page=2#top"
+        );
+        assert_eq!(http.info.x_request_id, "session=xyz");
+    }
+
+    #[test]
+    fn parse_soap() {
+        let mut http = HttpLog::default();
+        let payload = b"POST /ws/user HTTP/1.1\r\nContent-Type: text/xml; charset=utf-8\r\nSOAPAction: \"http://tempuri.org/GetUser\"\r\n\r\n<soap:Envelope/>";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.path, "GetUser");
+
+        let payload = b"POST /ws/user HTTP/1.1\r\nContent-Type: application/soap+xml\r\n\r\n<?xml version=\"1.0\"?><soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\"><soap:Header><!-- auth --></soap:Header><soap:Body><m:GetPrice xmlns:m=\"urn:shop\"><m:Item>Apple</m:Item></m:GetPrice></soap:Body></soap:Envelope>";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.path, "GetPrice");
+
+        let payload = b"POST /RPC2 HTTP/1.0\r\nContent-Type: text/xml\r\n\r\n<?xml version=\"1.0\"?>\n<methodCall>\n  <methodName>examples.getStateName</methodName>\n</methodCall>";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.path, "examples.getStateName");
+
+        // 非XML请求保留原始路径
+        let payload = b"POST /api/user HTTP/1.1\r\nContent-Type: application/json\r\nSOAPAction: GetUser\r\n\r\n{}";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.path, "/api/user");
+    }
+
+    #[test]
+    fn parse_grpc_web() {
+        let mut http = HttpLog::default();
+        let payload = b"POST /helloworld.Greeter/SayHello HTTP/1.1\r\nContent-Type: application/grpc-web+proto\r\nContent-Length: 12\r\n\r\n\x00\x00\x00\x00\x07\x0a\x05hello";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.rpc_type, "grpc");
+        assert_eq!(http.info.grpc_status, None);
+
+        // 数据帧之后是trailers帧
+        let trailers = b"grpc-status: 13\r\ngrpc-message: internal\r\n";
+        let mut body = vec![0, 0, 0, 0, 2, 0x0a, 0x00, GRPC_WEB_FLAG_TRAILERS];
+        body.extend_from_slice(&(trailers.len() as u32).to_be_bytes());
+        body.extend_from_slice(trailers);
+        let mut payload = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/grpc-web+proto\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        payload.extend_from_slice(&body);
+        let _ = http.parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.info.grpc_status, Some(13));
+        assert_eq!(http.status, L7ResponseStatus::ServerError);
+
+        // grpc-web-text的数据帧和trailers帧分别做base64编码
+        let text = format!(
+            "{}{}",
+            base64::encode(&body[..7]),
+            base64::encode(&body[7..])
+        );
+        let payload = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/grpc-web-text\r\nContent-Length: {}\r\n\r\n{}",
+            text.len(),
+            text
+        );
+        let _ = http.parse(
+            payload.as_bytes(),
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(http.info.grpc_status, Some(13));
+
+        // trailers-only响应
+        let payload = b"HTTP/1.1 200 OK\r\nContent-Type: application/grpc-web+proto\r\ngrpc-status: 5\r\nContent-Length: 0\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.info.grpc_status, Some(5));
+        assert_eq!(http.status, L7ResponseStatus::ClientError);
+    }
+
+    #[test]
+    fn status_line() {
+        assert_eq!(
+            get_http_resp_info("HTTP/1.1 404 Not Found").unwrap(),
+            ("1.1".to_string(), 404)
+        );
+        // 省略原因短语
+        assert_eq!(
+            get_http_resp_info("HTTP/1.0 200").unwrap(),
+            ("1.0".to_string(), 200)
+        );
+        assert!(get_http_resp_info("HTTP/1.1 20 OK").is_err());
+        assert!(get_http_resp_info("HTTP/1.1 2000 OK").is_err());
+        assert!(get_http_resp_info("HTTP/2.0 200 OK").is_err());
+    }
+
+    #[test]
+    fn custom_methods() {
+        assert!(is_http_v1_payload(b"PROPFIND /dav/ HTTP/1.1\r\n"));
+        assert!(!is_http_v1_payload(b"GETX / HTTP/1.1\r\n"));
+        assert!(!is_http_v1_payload(b"REPORT /calendar HTTP/1.1\r\n"));
+
+        // 其他测试不使用该Method，不需要恢复
+        set_custom_methods(&["REPORT".to_owned(), "X".repeat(HTTP_METHOD_MAX_LEN + 1)]);
+        assert!(is_http_v1_payload(b"REPORT /calendar HTTP/1.1\r\n"));
+        assert!(check_http_method("REPORT").is_ok());
+        assert!(check_http_method("report").is_err());
+        assert!(check_http_method(&"X".repeat(HTTP_METHOD_MAX_LEN + 1)).is_err());
+
+        let mut http = HttpLog::default();
+        let payload = b"REPORT /calendar HTTP/1.0\r\nHost: example.com\r\n\r\n";
+        assert!(http
+            .parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .is_ok());
+        assert_eq!(http.info.method, "REPORT");
+        assert_eq!(http.info.version, "1.0");
+    }
+
+    #[test]
+    fn streaming() {
+        assert!(is_event_stream("text/event-stream; charset=utf-8"));
+        assert!(!is_event_stream("text/html"));
+
+        let mut http = HttpLog::default();
+        let payload = b"HTTP/1.1 200 OK\r\nContent-Type: Text/Event-Stream\r\n\r\ndata: 1\n\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert!(http.info.streaming);
+
+        let payload = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert!(!http.info.streaming);
+
+        // 长轮询的响应首字节时延超过阈值
+        let mut info = HttpInfo::default();
+        info.amend_streaming(Duration::from_secs(30), Duration::ZERO);
+        assert!(!info.streaming);
+        info.amend_streaming(Duration::from_secs(30), Duration::from_secs(60));
+        assert!(!info.streaming);
+        info.amend_streaming(Duration::from_secs(90), Duration::from_secs(60));
+        assert!(info.streaming);
+    }
+
+    #[test]
+    fn obs_fold() {
+        let mut http = HttpLog::default();
+        http.captured_headers = vec!["x-note".into()];
+        let payload = b"GET / HTTP/1.1\r\nX-Note: first\r\n \t second\r\nHost: a.com\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(
+            http.info.request_headers,
+            vec![("x-note".to_string(), "first second".to_string())]
+        );
+        assert_eq!(http.info.host, "a.com");
+
+        // 折叠续行中的冒号不作为新的头部
+        let payload = b"GET / HTTP/1.1\r\nHost: a.com\r\n Content-Length: 10\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        assert_eq!(http.info.host, "a.com Content-Length: 10");
+        assert_eq!(http.info.req_content_length, None);
+    }
+
+    #[test]
+    fn smuggling() {
+        let cases: [(&[u8], Option<u64>, bool); 5] = [
+            (b"Content-Length: 5\r\n", Some(5), false),
+            (
+                b"Content-Length: 5, 5\r\nContent-Length: 5\r\n",
+                Some(5),
+                false,
+            ),
+            (b"Content-Length: 5\r\nContent-Length: 6\r\n", None, true),
+            (b"Content-Length: abc\r\nContent-Length: 5\r\n", None, true),
+            (
+                b"Content-Length: 100\r\nTransfer-Encoding: chunked\r\n",
+                Some(5),
+                true,
+            ),
+        ];
+        for (headers, length, suspected) in cases {
+            let mut payload = b"POST /upload HTTP/1.1\r\nHost: a.com\r\n".to_vec();
+            payload.extend_from_slice(headers);
+            payload.extend_from_slice(b"\r\n5\r\nhello\r\n0\r\n\r\n");
+            let mut http = HttpLog::default();
+            assert!(http
+                .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+                .is_ok());
+            assert_eq!(http.info.req_content_length, length);
+            assert_eq!(http.info.smuggling_suspected, suspected);
+        }
+    }
+
+    #[test]
+    fn captured_headers() {
+        let mut http = HttpLog::default();
+        http.captured_headers = vec!["user-agent".into(), "set-cookie".into(), "server".into()];
+        http.scrub.headers = vec!["set-cookie".into()];
+        http.scrub.mask = "***".into();
+
+        let payload =
+            b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nUser-Agent: curl/7.68.0\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ClientToServer);
+        let mut info = http.info.clone();
+        assert_eq!(
+            info.request_headers,
+            vec![("user-agent".to_string(), "curl/7.68.0".to_string())]
+        );
+
+        let payload = b"HTTP/1.1 200\r\nServer: nginx\r\nSet-Cookie: sid=1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let _ = http.parse(payload, IpProtocol::Tcp, PacketDirection::ServerToClient);
+        assert_eq!(http.status_code, 200);
+        assert_eq!(http.info.resp_content_length, Some(5));
+        info.merge(http.info.clone());
+        assert_eq!(
+            info.request_headers,
+            vec![("user-agent".to_string(), "curl/7.68.0".to_string())]
+        );
+        assert_eq!(
+            info.response_headers,
+            vec![
+                ("server".to_string(), "nginx".to_string()),
+                ("set-cookie".to_string(), "***".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn elasticsearch() {
+        let cases = [
+            (
+                "GET",
+                "/logs-2024.01/_search?q=error",
+                ES_OP_SEARCH,
+                "logs-2024.01",
+            ),
+            ("POST", "/_msearch", ES_OP_MSEARCH, ""),
+            ("POST", "/_bulk", ES_OP_BULK, ""),
+            ("POST", "/orders/_bulk?refresh=true", ES_OP_BULK, "orders"),
+            ("GET", "/orders/_doc/1", ES_OP_DOC_GET, "orders"),
+            ("PUT", "/orders/_doc/1", ES_OP_DOC_INDEX, "orders"),
+            ("DELETE", "/orders/_doc/1", ES_OP_DOC_DELETE, "orders"),
+            ("POST", "/orders/_update/1", ES_OP_DOC_UPDATE, "orders"),
+            ("PUT", "/orders/_create/1", ES_OP_DOC_CREATE, "orders"),
+            ("PUT", "/orders", ES_OP_INDEX_CREATE, "orders"),
+            ("DELETE", "/orders", ES_OP_INDEX_DELETE, "orders"),
+            ("GET", "/_cat/indices", ES_OP_OTHER, ""),
+        ];
+        for (method, path, operation, index) in cases {
+            assert_eq!(
+                elasticsearch_operation(method, path),
+                (operation, index),
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        let mut info = HttpInfo {
+            method: "POST".into(),
+            path: "/a,b/_search".into(),
+            ..Default::default()
+        };
+        info.amend_elasticsearch(Duration::ZERO, Duration::from_secs(1));
+        assert_eq!(info.es_operation, ES_OP_SEARCH);
+        assert_eq!(info.es_index, "a,b");
+        assert!(!info.es_slow_search);
+        info.amend_elasticsearch(Duration::from_millis(1500), Duration::from_secs(1));
+        assert!(info.es_slow_search);
+
+        // 非查询请求不标记慢查询
+        info.path = "/_bulk".into();
+        info.amend_elasticsearch(Duration::from_millis(1500), Duration::from_secs(1));
+        assert!(!info.es_slow_search);
+    }
+
+    #[test]
+    fn frame_resync() {
+        // 上一个DATA帧的尾部 + Headers帧(:method POST, :scheme http, 未知的dynamic index, :authority a) + DATA帧
+        let mut request = vec![0xff; 7];
+        request.extend_from_slice(&[0, 0, 6, 1, 4, 0, 0, 0, 3]);
+        request.extend_from_slice(&[0x83, 0x86, 0xbe, 0x41, 0x01, b'a']);
+        request.extend_from_slice(&[0, 0, 5, 0, 1, 0, 0, 0, 3]);
+        request.extend_from_slice(b"hello");
+
+        let mut http = HttpLog::default();
+        assert!(http
+            .parse(&request, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .is_err());
+
+        http.set_frame_resync(true);
+        assert!(http
+            .parse(&request, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .is_ok());
+        assert_eq!(http.info.stream_id, 3);
+        assert_eq!(http.info.method, "POST");
+        assert_eq!(http.info.host, "a");
+        assert!(http.info.path.is_empty());
+        assert_eq!(http.info.req_content_length, Some(5));
+        assert!(http.info.hpack_incomplete);
+
+        // :status引用了未知的dynamic index
+        let response = [0, 0, 1, 1, 4, 0, 0, 0, 3, 0xbf];
+        let mut http = HttpLog::default();
+        assert!(http
+            .parse(&response, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_err());
+
+        http.set_frame_resync(true);
+        assert!(http
+            .parse(&response, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_ok());
+        assert_eq!(http.msg_type, LogMessageType::Response);
+        assert_eq!(http.status, L7ResponseStatus::NotExist);
+        assert!(http.info.hpack_incomplete);
+    }
 }
@@ -0,0 +1,271 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use super::{AppProtoLogsData, AppProtoLogsInfo, HttpInfo, L7ResponseStatus, LogMessageType};
+
+// 客户端通常会对这两种4xx响应重试
+const HTTP_REQUEST_TIMEOUT: u16 = 408;
+const HTTP_TOO_MANY_REQUESTS: u16 = 429;
+
+fn http_info(log: &AppProtoLogsData) -> Option<&HttpInfo> {
+    match &log.special_info {
+        AppProtoLogsInfo::HttpV1(h)
+        | AppProtoLogsInfo::HttpV2(h)
+        | AppProtoLogsInfo::HttpV1TLS(h) => Some(h),
+        _ => None,
+    }
+}
+
+fn http_info_mut(log: &mut AppProtoLogsData) -> Option<&mut HttpInfo> {
+    match &mut log.special_info {
+        AppProtoLogsInfo::HttpV1(h)
+        | AppProtoLogsInfo::HttpV2(h)
+        | AppProtoLogsInfo::HttpV1TLS(h) => Some(h),
+        _ => None,
+    }
+}
+
+// 同一客户端(IP, EPC)的相同方法、域名及路径视为相同的请求
+fn retry_key(log: &AppProtoLogsData) -> Option<u64> {
+    let info = http_info(log)?;
+    if info.method.is_empty() || info.path.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    log.base_info.ip_src.hash(&mut hasher);
+    log.base_info.l3_epc_id_src.hash(&mut hasher);
+    info.method.hash(&mut hasher);
+    info.host.hash(&mut hasher);
+    info.path.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+fn is_failure(log: &AppProtoLogsData) -> bool {
+    let head = &log.base_info.head;
+    match head.status {
+        L7ResponseStatus::ServerError | L7ResponseStatus::Error => true,
+        L7ResponseStatus::ClientError => {
+            head.code == HTTP_REQUEST_TIMEOUT || head.code == HTTP_TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+// 客户端在失败后很快重发相同的请求，会放大请求数和错误率。
+// 识别窗口内的重试，标记retry并与失败的请求使用相同的retry_group_id，供下游去重：
+// 1. 失败的响应：会话发送时记录，窗口从响应时刻开始，同一连接上的重试也会识别
+// 2. 超时：新连接上出现相同的请求时上一个请求仍未收到响应，窗口从上一个请求开始
+// retry_group_id取自组内首个请求的会话聚合key，1分钟内唯一
+#[derive(Default)]
+pub struct HttpRetry {
+    // retry key -> (失败时刻, retry_group_id)
+    failures: HashMap<u64, (Duration, u64)>,
+    // retry key -> (请求时刻, flow_id, 会话聚合key)
+    pending: HashMap<u64, (Duration, u64, u64)>,
+    last_expire: u64,
+}
+
+impl HttpRetry {
+    fn expire(&mut self, now: Duration, window: Duration) {
+        if now.as_secs() == self.last_expire {
+            return;
+        }
+        self.last_expire = now.as_secs();
+        self.failures.retain(|_, (t, _)| *t + window >= now);
+        self.pending.retain(|_, (t, _, _)| *t + window >= now);
+    }
+
+    // 会话聚合前调用，仅处理请求，time_window中缓存了尚未收到响应的请求
+    pub fn on_request(
+        &mut self,
+        log: &mut AppProtoLogsData,
+        session_key: u64,
+        time_window: &mut [HashMap<u64, AppProtoLogsData>],
+        window: Duration,
+    ) {
+        if log.base_info.head.msg_type != LogMessageType::Request {
+            return;
+        }
+        let key = match retry_key(log) {
+            Some(k) => k,
+            None => return,
+        };
+        let start = log.base_info.start_time;
+        let flow_id = log.base_info.flow_id;
+        self.expire(start, window);
+
+        let mut group = None;
+        if let Some((failed_at, g)) = self.failures.get(&key) {
+            if start >= *failed_at && start - *failed_at <= window {
+                group = Some(*g);
+            }
+        }
+        if group.is_none() {
+            if let Some((t, pending_flow, pending_key)) = self.pending.get(&key) {
+                if *pending_flow != flow_id && start >= *t && start - *t <= window {
+                    group = Self::mark_pending(*pending_flow, *pending_key, time_window);
+                }
+            }
+        }
+        if let Some(g) = group {
+            if let Some(info) = http_info_mut(log) {
+                info.retry = true;
+                info.retry_group_id = g;
+            }
+        }
+        self.pending.insert(key, (start, flow_id, session_key));
+    }
+
+    // 上一个请求仍在缓存中且未合并响应时为超时，返回其retry_group_id
+    fn mark_pending(
+        flow_id: u64,
+        session_key: u64,
+        time_window: &mut [HashMap<u64, AppProtoLogsData>],
+    ) -> Option<u64> {
+        let pending = time_window
+            .iter_mut()
+            .find_map(|map| map.get_mut(&session_key))?;
+        if pending.base_info.flow_id != flow_id
+            || pending.base_info.head.msg_type != LogMessageType::Request
+        {
+            return None;
+        }
+        let info = http_info_mut(pending)?;
+        if info.retry_group_id == 0 {
+            info.retry_group_id = session_key;
+        }
+        Some(info.retry_group_id)
+    }
+
+    // 发送前调用，记录失败的会话
+    pub fn on_send(&mut self, log: &mut AppProtoLogsData, session_key: u64) {
+        if log.base_info.head.msg_type != LogMessageType::Session || !is_failure(log) {
+            return;
+        }
+        let key = match retry_key(log) {
+            Some(k) => k,
+            None => return,
+        };
+        let end_time = log.base_info.end_time;
+        if let Some(info) = http_info_mut(log) {
+            if info.retry_group_id == 0 {
+                info.retry_group_id = session_key;
+            }
+            self.failures.insert(key, (end_time, info.retry_group_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    };
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo};
+
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn request(flow_id: u64, millis: u64) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_millis(millis);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = "10.0.0.2".parse().unwrap();
+        packet.lookup_key.src_port = 40000;
+        packet.lookup_key.dst_port = 80;
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.direction = PacketDirection::ClientToServer;
+        let head = AppProtoHead {
+            proto: L7Protocol::Http1,
+            msg_type: LogMessageType::Request,
+            ..Default::default()
+        };
+        let mut base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        base_info.flow_id = flow_id;
+        AppProtoLogsData::new(
+            base_info,
+            AppProtoLogsInfo::HttpV1(HttpInfo {
+                method: "GET".to_owned(),
+                path: "/api".to_owned(),
+                host: "example.com".to_owned(),
+                ..Default::default()
+            }),
+        )
+    }
+
+    fn retry_of(log: &AppProtoLogsData) -> (bool, u64) {
+        let info = http_info(log).unwrap();
+        (info.retry, info.retry_group_id)
+    }
+
+    #[test]
+    fn retry_after_error() {
+        let mut retry = HttpRetry::default();
+        let mut time_window = vec![HashMap::new()];
+
+        let mut first = request(1, 1000);
+        retry.on_request(&mut first, 100, &mut time_window, WINDOW);
+        first.base_info.head.msg_type = LogMessageType::Session;
+        first.base_info.head.status = L7ResponseStatus::ServerError;
+        first.base_info.head.code = 503;
+        first.base_info.end_time = Duration::from_millis(1010);
+        retry.on_send(&mut first, 100);
+        assert_eq!(retry_of(&first), (false, 100));
+
+        let mut second = request(2, 1020);
+        retry.on_request(&mut second, 200, &mut time_window, WINDOW);
+        assert_eq!(retry_of(&second), (true, 100));
+
+        // 超出窗口
+        let mut third = request(3, 3000);
+        retry.on_request(&mut third, 300, &mut time_window, WINDOW);
+        assert_eq!(retry_of(&third), (false, 0));
+    }
+
+    #[test]
+    fn retry_after_timeout() {
+        let mut retry = HttpRetry::default();
+        let mut time_window = vec![HashMap::new(), HashMap::new()];
+
+        let mut first = request(1, 1000);
+        retry.on_request(&mut first, 100, &mut time_window, WINDOW);
+        time_window[0].insert(100, first);
+
+        let mut second = request(2, 1500);
+        retry.on_request(&mut second, 200, &mut time_window, WINDOW);
+        assert_eq!(retry_of(&second), (true, 100));
+        assert_eq!(retry_of(&time_window[0][&100]), (false, 100));
+
+        // 已收到响应的请求不在缓存中，不视为超时
+        let mut third = request(3, 1800);
+        retry.on_request(&mut third, 300, &mut time_window, WINDOW);
+        assert_eq!(retry_of(&third), (false, 0));
+        time_window[1].insert(300, third);
+
+        // 同一连接上的相同请求不视为重试
+        let mut fourth = request(3, 1900);
+        retry.on_request(&mut fourth, 301, &mut time_window, WINDOW);
+        assert_eq!(retry_of(&fourth), (false, 0));
+    }
+}
@@ -0,0 +1,274 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use serde::Serialize;
+
+use super::super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::bytes::read_u16_be,
+};
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ModbusInfo {
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub transaction_id: u16,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub unit_id: u8,
+    #[serde(rename = "request_type")]
+    pub function_code: u8,
+    #[serde(rename = "response_code", skip_serializing_if = "value_is_default")]
+    pub exception_code: u8,
+}
+
+impl ModbusInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.exception_code = other.exception_code;
+    }
+}
+
+impl L7SessionId for ModbusInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.transaction_id > 0 {
+            Some(self.transaction_id as u32)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<ModbusInfo> for flow_log::ModbusInfo {
+    fn from(f: ModbusInfo) -> Self {
+        flow_log::ModbusInfo {
+            transaction_id: f.transaction_id as u32,
+            unit_id: f.unit_id as u32,
+            function_code: f.function_code as u32,
+            exception_code: f.exception_code as u32,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ModbusLog {
+    info: ModbusInfo,
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u16,
+}
+
+impl ModbusLog {
+    fn reset_logs(&mut self) {
+        self.info = ModbusInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    // 协议识别时严格检查MBAP长度，日志解析时payload可能被截断不做检查
+    fn decode_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+        strict: bool,
+    ) -> Result<AppProtoHead> {
+        if payload.len() < MODBUS_MBAP_HEADER_LEN + 1 {
+            return Err(Error::ModbusLogParseFailed(
+                ParseContext::new(ParseStage::Header)
+                    .mismatch(MODBUS_MBAP_HEADER_LEN + 1, payload.len()),
+            ));
+        }
+        if read_u16_be(&payload[2..]) != MODBUS_PROTOCOL_ID {
+            return Err(Error::ModbusLogParseFailed(
+                ParseContext::new(ParseStage::Header).at(2),
+            ));
+        }
+        let length = read_u16_be(&payload[4..]) as usize;
+        if length < 2 || length > MODBUS_MAX_LENGTH {
+            return Err(Error::ModbusLogParseFailed(
+                ParseContext::new(ParseStage::Header).at(4),
+            ));
+        }
+        if strict && length + MODBUS_MBAP_HEADER_LEN - 1 != payload.len() {
+            return Err(Error::ModbusLogParseFailed(
+                ParseContext::new(ParseStage::Header)
+                    .mismatch(length + MODBUS_MBAP_HEADER_LEN - 1, payload.len()),
+            ));
+        }
+
+        self.info.transaction_id = read_u16_be(payload);
+        self.info.unit_id = payload[6];
+        let function_code = payload[MODBUS_MBAP_HEADER_LEN];
+        self.info.function_code = function_code & !MODBUS_EXCEPTION_MASK;
+
+        match direction {
+            PacketDirection::ClientToServer => {
+                if function_code & MODBUS_EXCEPTION_MASK != 0 {
+                    return Err(Error::ModbusLogParseFailed(
+                        ParseContext::new(ParseStage::Request).at(MODBUS_MBAP_HEADER_LEN),
+                    ));
+                }
+                self.msg_type = LogMessageType::Request;
+            }
+            PacketDirection::ServerToClient => {
+                self.msg_type = LogMessageType::Response;
+                // 异常响应：功能码最高位置1，后跟1字节异常码
+                if function_code & MODBUS_EXCEPTION_MASK != 0 {
+                    let exception_code = *payload.get(MODBUS_MBAP_HEADER_LEN + 1).ok_or(
+                        Error::ModbusLogParseFailed(
+                            ParseContext::new(ParseStage::Response).at(MODBUS_MBAP_HEADER_LEN + 1),
+                        ),
+                    )?;
+                    self.info.exception_code = exception_code;
+                    self.status_code = exception_code as u16;
+                    self.status = match exception_code {
+                        MODBUS_EXCEPTION_ILLEGAL_FUNCTION
+                        | MODBUS_EXCEPTION_ILLEGAL_DATA_ADDRESS
+                        | MODBUS_EXCEPTION_ILLEGAL_DATA_VALUE => L7ResponseStatus::ClientError,
+                        _ => L7ResponseStatus::ServerError,
+                    };
+                }
+            }
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Modbus,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for ModbusLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        let head = self.decode_payload(payload, direction, false)?;
+        Ok(AppProtoHeadEnum::Single(head))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Modbus(self.info.clone()))
+    }
+}
+
+// MBAP头部特征较弱，要求使用标准端口502
+pub fn modbus_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Tcp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Modbus));
+        return false;
+    }
+    if packet.lookup_key.dst_port != MODBUS_PORT {
+        if packet.lookup_key.src_port != MODBUS_PORT {
+            *bitmap &= !(1 << u8::from(L7Protocol::Modbus));
+        }
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    let mut modbus = ModbusLog::default();
+    modbus
+        .decode_payload(payload, PacketDirection::ClientToServer, true)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        // Read Holding Registers: tid=0x0102, unit=0x11, addr=0x006b, count=3
+        let request = [
+            0x01, 0x02, 0x00, 0x00, 0x00, 0x06, 0x11, 0x03, 0x00, 0x6b, 0x00, 0x03,
+        ];
+        let mut modbus = ModbusLog::default();
+        let head = modbus
+            .decode_payload(&request, PacketDirection::ClientToServer, true)
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(
+            modbus.info,
+            ModbusInfo {
+                transaction_id: 0x0102,
+                unit_id: 0x11,
+                function_code: 3,
+                exception_code: 0,
+            }
+        );
+        // 长度字段与payload不一致
+        assert!(modbus
+            .decode_payload(&request[..10], PacketDirection::ClientToServer, true)
+            .is_err());
+
+        let response = [
+            0x01, 0x02, 0x00, 0x00, 0x00, 0x09, 0x11, 0x03, 0x06, 0x02, 0x2b, 0x00, 0x00, 0x00,
+            0x64,
+        ];
+        let head = modbus
+            .parse(&response, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::Ok);
+        assert_eq!(modbus.info.exception_code, 0);
+
+        // Illegal Data Address
+        let exception = [0x01, 0x02, 0x00, 0x00, 0x00, 0x03, 0x11, 0x83, 0x02];
+        let head = modbus
+            .parse(&exception, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, 2);
+        assert_eq!(modbus.info.function_code, 3);
+
+        // Server Device Failure
+        let exception = [0x01, 0x02, 0x00, 0x00, 0x00, 0x03, 0x11, 0x83, 0x04];
+        let head = modbus
+            .parse(&exception, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::ServerError);
+    }
+}
@@ -0,0 +1,289 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use serde::Serialize;
+
+use super::super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::bytes::read_u16_be,
+};
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct S7Info {
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub pdu_ref: u16,
+    #[serde(rename = "request_type")]
+    pub rosctr: u8,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub function: u8,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub error_class: u8,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub error_code: u8,
+}
+
+impl S7Info {
+    pub fn merge(&mut self, other: Self) {
+        self.error_class = other.error_class;
+        self.error_code = other.error_code;
+    }
+}
+
+impl L7SessionId for S7Info {
+    fn session_id(&self) -> Option<u32> {
+        if self.pdu_ref > 0 {
+            Some(self.pdu_ref as u32)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<S7Info> for flow_log::S7Info {
+    fn from(f: S7Info) -> Self {
+        flow_log::S7Info {
+            pdu_ref: f.pdu_ref as u32,
+            rosctr: f.rosctr as u32,
+            function: f.function as u32,
+            error_class: f.error_class as u32,
+            error_code: f.error_code as u32,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct S7Log {
+    info: S7Info,
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u16,
+}
+
+impl S7Log {
+    fn reset_logs(&mut self) {
+        self.info = S7Info::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    // S7comm承载在TPKT(RFC1006)和COTP数据报文之上，返回S7头部偏移
+    fn s7_offset(payload: &[u8], strict: bool) -> Result<usize> {
+        if payload.len() < S7_TPKT_HEADER_LEN + 2 || payload[0] != S7_TPKT_VERSION {
+            return Err(Error::S7LogParseFailed(
+                ParseContext::new(ParseStage::Header)
+                    .mismatch(S7_TPKT_HEADER_LEN + 2, payload.len()),
+            ));
+        }
+        if strict && read_u16_be(&payload[2..]) as usize != payload.len() {
+            return Err(Error::S7LogParseFailed(
+                ParseContext::new(ParseStage::Header).at(2),
+            ));
+        }
+        // COTP长度字段不包含自身，连接建立等非DT报文不承载S7
+        if payload[S7_TPKT_HEADER_LEN + 1] != S7_COTP_PDU_TYPE_DT {
+            return Err(Error::S7LogParseFailed(
+                ParseContext::new(ParseStage::Header).at(S7_TPKT_HEADER_LEN + 1),
+            ));
+        }
+        let offset = S7_TPKT_HEADER_LEN + 1 + payload[S7_TPKT_HEADER_LEN] as usize;
+        if payload.len() < offset + S7_HEADER_LEN || payload[offset] != S7_PROTOCOL_ID {
+            return Err(Error::S7LogParseFailed(
+                ParseContext::new(ParseStage::Header).at(offset),
+            ));
+        }
+        Ok(offset)
+    }
+
+    fn decode_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+        strict: bool,
+    ) -> Result<AppProtoHead> {
+        let offset = Self::s7_offset(payload, strict)?;
+        let header = &payload[offset..];
+        self.info.rosctr = header[1];
+        self.info.pdu_ref = read_u16_be(&header[4..]);
+
+        let param_offset = match (self.info.rosctr, direction) {
+            (S7_ROSCTR_JOB, PacketDirection::ClientToServer)
+            | (S7_ROSCTR_USERDATA, PacketDirection::ClientToServer) => {
+                self.msg_type = LogMessageType::Request;
+                S7_HEADER_LEN
+            }
+            (S7_ROSCTR_USERDATA, PacketDirection::ServerToClient) => {
+                self.msg_type = LogMessageType::Response;
+                S7_HEADER_LEN
+            }
+            (S7_ROSCTR_ACK, PacketDirection::ServerToClient)
+            | (S7_ROSCTR_ACK_DATA, PacketDirection::ServerToClient) => {
+                self.msg_type = LogMessageType::Response;
+                if header.len() < S7_ACK_HEADER_LEN {
+                    return Err(Error::S7LogParseFailed(
+                        ParseContext::new(ParseStage::Response)
+                            .mismatch(S7_ACK_HEADER_LEN, header.len()),
+                    ));
+                }
+                self.info.error_class = header[10];
+                self.info.error_code = header[11];
+                self.status_code = (header[10] as u16) << 8 | header[11] as u16;
+                self.status = match header[10] {
+                    0 => L7ResponseStatus::Ok,
+                    S7_ERROR_CLASS_NO_RESOURCES | S7_ERROR_CLASS_SERVICE_PROCESSING => {
+                        L7ResponseStatus::ServerError
+                    }
+                    _ => L7ResponseStatus::ClientError,
+                };
+                S7_ACK_HEADER_LEN
+            }
+            _ => return Err(Error::S7LogParseFailed(ParseStage::Session.into())),
+        };
+        // Job和Ack_Data参数区首字节为功能码，如0x04读变量，0x05写变量
+        let param_len = read_u16_be(&header[6..]);
+        if param_len > 0 && self.info.rosctr != S7_ROSCTR_USERDATA {
+            if let Some(function) = header.get(param_offset) {
+                self.info.function = *function;
+            }
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::S7,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for S7Log {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        let head = self.decode_payload(payload, direction, false)?;
+        Ok(AppProtoHeadEnum::Single(head))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::S7(self.info.clone()))
+    }
+}
+
+// 连接建立阶段的COTP CR/CC报文不是S7，识别失败时不清除bitmap，等待后续数据报文
+pub fn s7_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Tcp {
+        *bitmap &= !(1 << u8::from(L7Protocol::S7));
+        return false;
+    }
+    if packet.lookup_key.dst_port != S7_PORT {
+        if packet.lookup_key.src_port != S7_PORT {
+            *bitmap &= !(1 << u8::from(L7Protocol::S7));
+        }
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    let mut s7 = S7Log::default();
+    s7.decode_payload(payload, PacketDirection::ClientToServer, true)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // TPKT + COTP DT + S7 Job Read Var
+    const READ_VAR_JOB: [u8; 31] = [
+        0x03, 0x00, 0x00, 0x1f, 0x02, 0xf0, 0x80, 0x32, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0e,
+        0x00, 0x00, 0x04, 0x01, 0x12, 0x0a, 0x10, 0x02, 0x00, 0x01, 0x00, 0x01, 0x84, 0x00, 0x00,
+        0x00,
+    ];
+
+    #[test]
+    fn parse() {
+        let mut s7 = S7Log::default();
+        let head = s7
+            .decode_payload(&READ_VAR_JOB, PacketDirection::ClientToServer, true)
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(
+            s7.info,
+            S7Info {
+                pdu_ref: 5,
+                rosctr: S7_ROSCTR_JOB,
+                function: 0x04,
+                ..Default::default()
+            }
+        );
+
+        // Ack_Data，错误类0x85 (access error)
+        let ack_data = [
+            0x03, 0x00, 0x00, 0x15, 0x02, 0xf0, 0x80, 0x32, 0x03, 0x00, 0x00, 0x00, 0x05, 0x00,
+            0x02, 0x00, 0x00, 0x85, 0x00, 0x04, 0x01,
+        ];
+        let head = s7
+            .parse(&ack_data, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, 0x8500);
+        assert_eq!(s7.info.function, 0x04);
+        assert_eq!(s7.info.pdu_ref, 5);
+    }
+
+    #[test]
+    fn not_s7() {
+        let mut s7 = S7Log::default();
+        // COTP Connection Request
+        let cotp_cr = [
+            0x03, 0x00, 0x00, 0x16, 0x11, 0xe0, 0x00, 0x00, 0x00, 0x01, 0x00, 0xc0, 0x01, 0x0a,
+            0xc1, 0x02, 0x01, 0x00, 0xc2, 0x02, 0x01, 0x02,
+        ];
+        assert!(s7
+            .decode_payload(&cotp_cr, PacketDirection::ClientToServer, true)
+            .is_err());
+        // TPKT长度与payload不一致
+        assert!(s7
+            .decode_payload(&READ_VAR_JOB[..20], PacketDirection::ClientToServer, true)
+            .is_err());
+    }
+}
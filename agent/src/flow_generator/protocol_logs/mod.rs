@@ -15,32 +15,56 @@
  */
 
 pub mod consts;
+mod detector;
 mod dns;
+mod dns_metrics;
 mod http;
+mod http_retry;
+mod industrial;
 mod mq;
+mod ntp;
+mod ntp_metrics;
 mod parser;
+mod radius;
+mod redis_metrics;
 mod rpc;
+mod sampling;
+mod snmp;
 mod sql;
+mod tls;
 
 pub use self::http::{
     check_http_method, get_http_request_version, get_http_resp_info, http1_check_protocol,
-    http2_check_protocol, is_http_v1_payload, HttpInfo, HttpLog, Httpv2Headers,
+    http2_check_protocol, is_event_stream, is_http_v1_payload, set_custom_methods, HttpInfo,
+    HttpLog, Httpv2Headers, IdleConnection, StreamContinuation,
 };
+pub use detector::{l4_protocol_bitmap, protocol_checks, DetectCounter, L7ProtocolDetector};
 pub use dns::{dns_check_protocol, DnsInfo, DnsLog};
+pub use industrial::{
+    modbus_check_protocol, s7_check_protocol, ModbusInfo, ModbusLog, S7Info, S7Log,
+};
 pub use mq::{
     kafka_check_protocol, mqtt, mqtt_check_protocol, KafkaInfo, KafkaLog, MqttInfo, MqttLog,
 };
-pub use parser::{AppProtoLogsParser, MetaAppProto};
+pub use ntp::{ntp_check_protocol, NtpHeader, NtpInfo, NtpLog};
+pub use parser::{check_protocol, AppLogs, AppProtoLogsParser, MetaAppProto};
+pub use radius::{radius_check_protocol, RadiusInfo, RadiusLog};
 pub use rpc::{dubbo_check_protocol, DubboHeader, DubboInfo, DubboLog};
+pub use sampling::L7LogSampler;
+pub use snmp::{snmp_check_protocol, SnmpHeader, SnmpInfo, SnmpLog};
 pub use sql::{
-    decode, mysql_check_protocol, redis_check_protocol, MysqlHeader, MysqlInfo, MysqlLog,
-    RedisInfo, RedisLog,
+    clickhouse_check_protocol, decode, decode_redirect, mysql_check_protocol, redis_check_protocol,
+    tds_check_protocol, ClickhouseInfo, ClickhouseLog, ClickhouseQuery, ClickhouseResponse,
+    MysqlHeader, MysqlInfo, MysqlLog, MysqlSessions, RedisInfo, RedisLog, TdsHeader, TdsInfo,
+    TdsLog, TdsResponse,
 };
+pub use tls::{client_hello_server_name, tls_check_protocol, TlsHeader, TlsInfo, TlsLog};
 
 use std::{
     fmt,
     mem::swap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
     time::Duration,
 };
 
@@ -49,15 +73,20 @@ use serde::{Serialize, Serializer};
 
 use crate::{
     common::{
+        business_label::{serialize_labels, to_pb_labels, BusinessLabels},
         enums::{IpProtocol, PacketDirection, TapType},
         flow::L7Protocol,
         meta_packet::MetaPacket,
-        tap_port::TapPort,
+        tap_port::{CapturePoint, TapPort},
     },
+    config::L7LogTruncateConfig,
     flow_generator::error::Result,
     metric::document::TapSide,
     proto::flow_log,
-    utils::net::MacAddr,
+    utils::{
+        bytes::{truncate_str, truncate_utf8},
+        net::MacAddr,
+    },
 };
 
 const NANOS_PER_MICRO: u64 = 1000;
@@ -70,6 +99,8 @@ pub enum L7ResponseStatus {
     NotExist,
     ServerError,
     ClientError,
+    // 重定向，如Redis集群的MOVED/ASK
+    Redirect,
 }
 
 impl Default for L7ResponseStatus {
@@ -115,10 +146,26 @@ pub struct AppProtoHead {
 
     #[serde(rename = "response_duration")]
     pub rrt: u64, // HTTP，DNS时延: response-request
+    // rrt中网络往返和服务端处理的近似拆分，单位us
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub network_time: u64,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub server_time: u64,
     #[serde(skip)]
     pub version: u8,
 }
 
+impl AppProtoHead {
+    // 采集点到服务端的TCP RTT视为rrt中的网络部分，其余为服务端处理时间
+    pub fn split_rrt(&mut self, server_rtt: u64) {
+        if self.rrt == 0 || server_rtt == 0 {
+            return;
+        }
+        self.network_time = server_rtt.min(self.rrt);
+        self.server_time = self.rrt - self.network_time;
+    }
+}
+
 impl From<AppProtoHead> for flow_log::AppProtoHead {
     fn from(f: AppProtoHead) -> Self {
         flow_log::AppProtoHead {
@@ -127,6 +174,8 @@ impl From<AppProtoHead> for flow_log::AppProtoHead {
             status: f.status as u32,
             code: f.code as u32,
             rrt: f.rrt * NANOS_PER_MICRO,
+            network_time: f.network_time * NANOS_PER_MICRO,
+            server_time: f.server_time * NANOS_PER_MICRO,
         }
     }
 }
@@ -140,6 +189,8 @@ pub struct AppProtoLogsBaseInfo {
     pub flow_id: u64,
     #[serde(serialize_with = "to_string_format")]
     pub tap_port: TapPort,
+    #[serde(serialize_with = "to_string_format")]
+    pub capture_point: CapturePoint,
     pub vtap_id: u16,
     pub tap_type: TapType,
     #[serde(skip)]
@@ -172,9 +223,9 @@ pub struct AppProtoLogsBaseInfo {
     #[serde(skip_serializing_if = "value_is_default")]
     pub process_id_1: u32,
     #[serde(skip_serializing_if = "value_is_default")]
-    pub process_kname_0: String,
+    pub process_kname_0: Arc<str>,
     #[serde(skip_serializing_if = "value_is_default")]
-    pub process_kname_1: String,
+    pub process_kname_1: Arc<str>,
     #[serde(skip_serializing_if = "value_is_default")]
     pub syscall_trace_id_request: u64,
     #[serde(skip_serializing_if = "value_is_default")]
@@ -187,12 +238,39 @@ pub struct AppProtoLogsBaseInfo {
     pub syscall_cap_seq_0: u64,
     #[serde(skip_serializing_if = "value_is_default")]
     pub syscall_cap_seq_1: u64,
+    // 按eBPF数据的cgroup id关联的进程所在容器及POD
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub container_id_0: Arc<str>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub container_id_1: Arc<str>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub pod_id_0: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub pod_id_1: u32,
 
     pub protocol: IpProtocol,
     #[serde(skip)]
     pub is_vip_interface_src: bool,
     #[serde(skip)]
     pub is_vip_interface_dst: bool,
+
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_labels"
+    )]
+    pub business_labels_src: Option<Arc<BusinessLabels>>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_labels"
+    )]
+    pub business_labels_dst: Option<Arc<BusinessLabels>>,
+    // 发送前根据DNS应答填充
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub server_domain: String,
+
+    // RRT超过配置上限，response_duration为截断后的值
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub rrt_outlier: bool,
 }
 
 pub fn duration_to_micros<S>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error>
@@ -262,14 +340,23 @@ impl From<AppProtoLogsBaseInfo> for flow_log::AppProtoLogsBaseInfo {
             resp_tcp_seq: f.resp_tcp_seq,
             process_id_0: f.process_id_0,
             process_id_1: f.process_id_1,
-            process_kname_0: f.process_kname_0,
-            process_kname_1: f.process_kname_1,
+            process_kname_0: f.process_kname_0.to_string(),
+            process_kname_1: f.process_kname_1.to_string(),
             syscall_trace_id_request: f.syscall_trace_id_request,
             syscall_trace_id_response: f.syscall_trace_id_response,
             syscall_trace_id_thread_0: f.syscall_trace_id_thread_0,
             syscall_trace_id_thread_1: f.syscall_trace_id_thread_1,
-            syscall_cap_seq_0: f.syscall_cap_seq_0 as u32,
-            syscall_cap_seq_1: f.syscall_cap_seq_1 as u32,
+            syscall_cap_seq_0: f.syscall_cap_seq_0,
+            syscall_cap_seq_1: f.syscall_cap_seq_1,
+            container_id_0: f.container_id_0.to_string(),
+            container_id_1: f.container_id_1.to_string(),
+            pod_id_0: f.pod_id_0,
+            pod_id_1: f.pod_id_1,
+            business_labels_src: to_pb_labels(f.business_labels_src),
+            business_labels_dst: to_pb_labels(f.business_labels_dst),
+            rrt_outlier: f.rrt_outlier,
+            server_domain: f.server_domain,
+            capture_point: Some(f.capture_point.or_from(f.tap_port).into()),
         }
     }
 }
@@ -289,6 +376,7 @@ impl AppProtoLogsBaseInfo {
             end_time: packet.lookup_key.timestamp,
             flow_id: packet.socket_id,
             tap_port: packet.tap_port,
+            capture_point: packet.capture_point,
             tap_type: TapType::Tor,
             is_ipv6: packet.lookup_key.dst_ip.is_ipv6(),
             tap_side: if is_src {
@@ -305,18 +393,39 @@ impl AppProtoLogsBaseInfo {
             port_dst: packet.lookup_key.dst_port,
             protocol: packet.lookup_key.proto,
 
-            process_id_0: if is_src { packet.process_id } else { 0 },
-            process_id_1: if !is_src { packet.process_id } else { 0 },
+            // unix socket可以从内核直接获取对端进程
+            process_id_0: if is_src {
+                packet.process_id
+            } else {
+                packet.peer_process_id
+            },
+            process_id_1: if !is_src {
+                packet.process_id
+            } else {
+                packet.peer_process_id
+            },
             process_kname_0: if is_src {
                 packet.process_name.clone()
             } else {
-                "".to_string()
+                Arc::default()
             },
             process_kname_1: if !is_src {
                 packet.process_name.clone()
             } else {
-                "".to_string()
+                Arc::default()
+            },
+            container_id_0: if is_src {
+                packet.container_id.clone()
+            } else {
+                Arc::default()
+            },
+            container_id_1: if !is_src {
+                packet.container_id.clone()
+            } else {
+                Arc::default()
             },
+            pod_id_0: if is_src { packet.pod_id } else { 0 },
+            pod_id_1: if !is_src { packet.pod_id } else { 0 },
 
             syscall_trace_id_request: if direction == PacketDirection::ClientToServer {
                 packet.syscall_trace_id
@@ -364,6 +473,10 @@ impl AppProtoLogsBaseInfo {
             l3_epc_id_dst: if is_src { remote_epc } else { local_epc },
             is_vip_interface_src: false,
             is_vip_interface_dst: false,
+            business_labels_src: None,
+            business_labels_dst: None,
+            server_domain: String::new(),
+            rrt_outlier: false,
         };
         if direction == PacketDirection::ServerToClient {
             swap(&mut info.mac_src, &mut info.mac_dst);
@@ -372,6 +485,8 @@ impl AppProtoLogsBaseInfo {
             swap(&mut info.port_src, &mut info.port_dst);
             swap(&mut info.process_id_0, &mut info.process_id_1);
             swap(&mut info.process_kname_0, &mut info.process_kname_1);
+            swap(&mut info.container_id_0, &mut info.container_id_1);
+            swap(&mut info.pod_id_0, &mut info.pod_id_1);
             info.tap_side = if info.tap_side == TapSide::ClientProcess {
                 TapSide::ServerProcess
             } else {
@@ -391,6 +506,14 @@ impl AppProtoLogsBaseInfo {
             self.process_id_1 = log.process_id_1;
             self.process_kname_1 = log.process_kname_1;
         }
+        if !log.container_id_0.is_empty() {
+            self.container_id_0 = log.container_id_0;
+            self.pod_id_0 = log.pod_id_0;
+        }
+        if !log.container_id_1.is_empty() {
+            self.container_id_1 = log.container_id_1;
+            self.pod_id_1 = log.pod_id_1;
+        }
         self.syscall_trace_id_thread_1 = log.syscall_trace_id_thread_1;
         self.syscall_cap_seq_1 = log.syscall_cap_seq_1;
         self.end_time = log.end_time.max(self.start_time);
@@ -400,6 +523,12 @@ impl AppProtoLogsBaseInfo {
         self.head.code = log.head.code;
         self.head.status = log.head.status;
         self.head.rrt = log.head.rrt;
+        // 拆分基于本条流的TCP握手，请求和响应都来自TCP时才有效
+        if self.req_tcp_seq > 0 && log.resp_tcp_seq > 0 {
+            self.head.network_time = log.head.network_time;
+            self.head.server_time = log.head.server_time;
+        }
+        self.rrt_outlier = log.rrt_outlier;
     }
 }
 
@@ -415,16 +544,78 @@ pub enum AppProtoLogsInfo {
     HttpV1(HttpInfo),
     HttpV2(HttpInfo),
     HttpV1TLS(HttpInfo),
+    Radius(RadiusInfo),
+    Modbus(ModbusInfo),
+    S7(S7Info),
+    Snmp(SnmpInfo),
+    Tds(TdsInfo),
+    Clickhouse(ClickhouseInfo),
+    Tls(TlsInfo),
+    Ntp(NtpInfo),
 }
 
 impl AppProtoLogsInfo {
     fn session_id(&self) -> Option<u32> {
+        let info: &dyn L7SessionId = match self {
+            Self::Dns(t) => t,
+            Self::Mysql(t) => t,
+            Self::Redis(t) => t,
+            Self::Kafka(t) => t,
+            Self::Mqtt(t) => t,
+            Self::Dubbo(t) => t,
+            Self::HttpV1(t) | Self::HttpV2(t) | Self::HttpV1TLS(t) => t,
+            Self::Radius(t) => t,
+            Self::Modbus(t) => t,
+            Self::S7(t) => t,
+            Self::Snmp(t) => t,
+            Self::Tds(t) => t,
+            Self::Clickhouse(t) => t,
+            Self::Tls(t) => t,
+            Self::Ntp(t) => t,
+        };
+        info.session_id()
+    }
+
+    pub fn trace_id(&self) -> Option<&str> {
+        let trace_id = match self {
+            AppProtoLogsInfo::HttpV1(t)
+            | AppProtoLogsInfo::HttpV2(t)
+            | AppProtoLogsInfo::HttpV1TLS(t) => &t.trace_id,
+            AppProtoLogsInfo::Dubbo(t) => &t.trace_id,
+            AppProtoLogsInfo::Mysql(t) => &t.trace_id,
+            _ => return None,
+        };
+        if trace_id.is_empty() {
+            None
+        } else {
+            Some(trace_id)
+        }
+    }
+
+    // 序列化前按字符边界截断较长的字段
+    pub fn truncate(&mut self, limits: &L7LogTruncateConfig) {
         match self {
-            AppProtoLogsInfo::Dns(t) if t.trans_id > 0 => Some(t.trans_id as u32),
-            AppProtoLogsInfo::Kafka(t) if t.correlation_id > 0 => Some(t.correlation_id),
-            AppProtoLogsInfo::Dubbo(t) if t.serial_id > 0 => Some(t.serial_id as u32),
-            AppProtoLogsInfo::HttpV2(t) if t.stream_id > 0 => Some(t.stream_id),
-            _ => None,
+            Self::HttpV1(t) | Self::HttpV2(t) | Self::HttpV1TLS(t) => {
+                truncate_str(&mut t.path, limits.http_url)
+            }
+            Self::Mysql(t) => {
+                truncate_str(&mut t.context, limits.sql);
+                truncate_str(&mut t.error_message, limits.sql);
+            }
+            Self::Clickhouse(t) => {
+                truncate_str(&mut t.query, limits.sql);
+                truncate_str(&mut t.exception_message, limits.sql);
+            }
+            Self::Tds(t) => {
+                truncate_str(&mut t.statement, limits.sql);
+                truncate_str(&mut t.error_message, limits.sql);
+            }
+            Self::Redis(t) => {
+                truncate_utf8(&mut t.request, limits.redis);
+                truncate_utf8(&mut t.response, limits.redis);
+                truncate_utf8(&mut t.error, limits.redis);
+            }
+            _ => (),
         }
     }
 
@@ -439,6 +630,14 @@ impl AppProtoLogsInfo {
             (Self::HttpV1(m), Self::HttpV1(o)) => m.merge(o),
             (Self::HttpV2(m), Self::HttpV2(o)) => m.merge(o),
             (Self::HttpV1TLS(m), Self::HttpV1TLS(o)) => m.merge(o),
+            (Self::Radius(m), Self::Radius(o)) => m.merge(o),
+            (Self::Modbus(m), Self::Modbus(o)) => m.merge(o),
+            (Self::S7(m), Self::S7(o)) => m.merge(o),
+            (Self::Snmp(m), Self::Snmp(o)) => m.merge(o),
+            (Self::Tds(m), Self::Tds(o)) => m.merge(o),
+            (Self::Clickhouse(m), Self::Clickhouse(o)) => m.merge(o),
+            (Self::Tls(m), Self::Tls(o)) => m.merge(o),
+            (Self::Ntp(m), Self::Ntp(o)) => m.merge(o),
             _ => unreachable!(),
         }
     }
@@ -456,6 +655,14 @@ impl fmt::Display for AppProtoLogsInfo {
             Self::HttpV1(l) => write!(f, "{:?}", l),
             Self::HttpV2(l) => write!(f, "{:?}", l),
             Self::HttpV1TLS(l) => write!(f, "{:?}", l),
+            Self::Radius(l) => write!(f, "{:?}", l),
+            Self::Modbus(l) => write!(f, "{:?}", l),
+            Self::S7(l) => write!(f, "{:?}", l),
+            Self::Snmp(l) => write!(f, "{:?}", l),
+            Self::Tds(l) => write!(f, "{:?}", l),
+            Self::Clickhouse(l) => write!(f, "{:?}", l),
+            Self::Tls(l) => write!(f, "{:?}", l),
+            Self::Ntp(l) => write!(f, "{:?}", l),
         }
     }
 }
@@ -498,6 +705,14 @@ impl AppProtoLogsData {
             AppProtoLogsInfo::HttpV1(t) => pb_proto_logs_data.http = Some(t.into()),
             AppProtoLogsInfo::HttpV2(t) => pb_proto_logs_data.http = Some(t.into()),
             AppProtoLogsInfo::HttpV1TLS(t) => pb_proto_logs_data.http = Some(t.into()),
+            AppProtoLogsInfo::Radius(t) => pb_proto_logs_data.radius = Some(t.into()),
+            AppProtoLogsInfo::Modbus(t) => pb_proto_logs_data.modbus = Some(t.into()),
+            AppProtoLogsInfo::S7(t) => pb_proto_logs_data.s7 = Some(t.into()),
+            AppProtoLogsInfo::Snmp(t) => pb_proto_logs_data.snmp = Some(t.into()),
+            AppProtoLogsInfo::Tds(t) => pb_proto_logs_data.tds = Some(t.into()),
+            AppProtoLogsInfo::Clickhouse(t) => pb_proto_logs_data.clickhouse = Some(t.into()),
+            AppProtoLogsInfo::Tls(t) => pb_proto_logs_data.tls = Some(t.into()),
+            AppProtoLogsInfo::Ntp(t) => pb_proto_logs_data.ntp = Some(t.into()),
         };
 
         pb_proto_logs_data
@@ -505,10 +720,13 @@ impl AppProtoLogsData {
             .map(|_| pb_proto_logs_data.encoded_len())
     }
 
-    pub fn ebpf_flow_session_id(&self) -> u64 {
+    fn ebpf_flow_id_part(&self) -> u64 {
         // 取flow_id(即ebpf底层的socket id)的高8位(cpu id)+低24位(socket id的变化增量), 作为聚合id的高32位
-        let flow_id_part =
-            (self.base_info.flow_id >> 56 << 56) | (self.base_info.flow_id << 40 >> 8);
+        (self.base_info.flow_id >> 56 << 56) | (self.base_info.flow_id << 40 >> 8)
+    }
+
+    pub fn ebpf_flow_session_id(&self) -> u64 {
+        let flow_id_part = self.ebpf_flow_id_part();
         if let Some(session_id) = self.special_info.session_id() {
             flow_id_part
                 | (self.base_info.head.proto as u64) << 24
@@ -525,6 +743,13 @@ impl AppProtoLogsData {
         }
     }
 
+    // 按请求的cap_seq计算聚合id，与该请求的ebpf_flow_session_id一致
+    pub fn ebpf_flow_session_id_by_request(&self, request_cap_seq: u64) -> u64 {
+        self.ebpf_flow_id_part()
+            | ((self.base_info.head.proto as u64) << 24)
+            | ((request_cap_seq + 1) & 0xffffff)
+    }
+
     pub fn session_merge(&mut self, log: AppProtoLogsData) {
         self.base_info.merge(log.base_info);
         self.special_info.merge(log.special_info);
@@ -593,6 +818,14 @@ pub trait L7LogParse: Send + Sync {
     fn info(&self) -> AppProtoLogsInfoEnum;
 }
 
+// eBPF数据按会话聚合请求和响应时使用的协议内请求标识，如DNS的transaction id
+// 各协议的Info在自己的模块中实现，没有请求标识的协议使用默认实现，按cap_seq聚合
+pub trait L7SessionId {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum AppProtoHeadEnum {
     Single(AppProtoHead),
@@ -639,3 +872,74 @@ impl IntoIterator for AppProtoLogsInfoEnum {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_id() {
+        let dns = AppProtoLogsInfo::Dns(DnsInfo {
+            trans_id: 0x1234,
+            ..Default::default()
+        });
+        assert_eq!(dns.session_id(), Some(0x1234));
+        let dns = AppProtoLogsInfo::Dns(DnsInfo::default());
+        assert_eq!(dns.session_id(), None);
+
+        let http = HttpInfo {
+            stream_id: 3,
+            ..Default::default()
+        };
+        assert_eq!(AppProtoLogsInfo::HttpV2(http).session_id(), Some(3));
+        let http = HttpInfo::default();
+        assert_eq!(AppProtoLogsInfo::HttpV1(http).session_id(), None);
+
+        let mysql = AppProtoLogsInfo::Mysql(MysqlInfo::default());
+        assert_eq!(mysql.session_id(), None);
+    }
+
+    #[test]
+    fn truncate() {
+        let limits = L7LogTruncateConfig {
+            http_url: 5,
+            sql: 0,
+            redis: 4,
+        };
+        let mut http = AppProtoLogsInfo::HttpV1(HttpInfo {
+            path: "/路径".into(),
+            ..Default::default()
+        });
+        http.truncate(&limits);
+        match http {
+            AppProtoLogsInfo::HttpV1(t) => assert_eq!(t.path, "/路"),
+            _ => unreachable!(),
+        }
+
+        let sql = "SELECT '中文'".to_owned();
+        let mut mysql = AppProtoLogsInfo::Mysql(MysqlInfo {
+            context: sql.clone(),
+            ..Default::default()
+        });
+        mysql.truncate(&limits);
+        match mysql {
+            AppProtoLogsInfo::Mysql(t) => assert_eq!(t.context, sql),
+            _ => unreachable!(),
+        }
+
+        // 被截断的payload结尾的不完整字符同样去掉
+        let mut redis = AppProtoLogsInfo::Redis(RedisInfo {
+            request: "GET 键".as_bytes().to_vec(),
+            response: "值".as_bytes()[..2].to_vec(),
+            ..Default::default()
+        });
+        redis.truncate(&limits);
+        match redis {
+            AppProtoLogsInfo::Redis(t) => {
+                assert_eq!(t.request, b"GET ");
+                assert!(t.response.is_empty());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
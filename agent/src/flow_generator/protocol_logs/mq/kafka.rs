@@ -13,22 +13,58 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
-use serde::Serialize;
+use std::sync::Arc;
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Serializer};
 
 use super::super::{
-    consts::KAFKA_REQ_HEADER_LEN, value_is_default, value_is_negative, AppProtoHead,
-    AppProtoLogsInfo, L7LogParse, L7Protocol, L7ResponseStatus, LogMessageType,
+    consts::{KAFKA_REQ_HEADER_LEN, KAFKA_RESP_HEADER_LEN},
+    value_is_default, value_is_negative, AppProtoHead, AppProtoLogsInfo, L7LogParse, L7Protocol,
+    L7ResponseStatus, L7SessionId, LogMessageType,
 };
 
+use super::kafka_error::{error_name, error_status};
 use crate::flow_generator::protocol_logs::{AppProtoHeadEnum, AppProtoLogsInfoEnum};
 use crate::proto::flow_log;
 use crate::{
     common::enums::{IpProtocol, PacketDirection},
     common::meta_packet::MetaPacket,
-    flow_generator::error::{Error, Result},
-    utils::bytes::{read_u16_be, read_u32_be},
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    flow_generator::golden,
+    utils::{
+        bytes::{read_u16_be, read_u32_be},
+        intern::intern,
+    },
 };
 
+const API_VERSIONS: u16 = 18;
+// ApiVersions从v3开始使用compact array和tagged fields
+const API_VERSIONS_FLEXIBLE_VERSION: u16 = 3;
+const MAX_PENDING_API_VERSIONS: usize = 1024;
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct KafkaApiVersion {
+    pub api_key: u16,
+    pub min_version: u16,
+    pub max_version: u16,
+}
+
+fn error_name_or_code(code: i16) -> String {
+    match error_name(code) {
+        Some(name) => name.to_owned(),
+        None => format!("UNKNOWN({})", code),
+    }
+}
+
+fn serialize_error_name<S: Serializer>(
+    code: &i16,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&error_name_or_code(*code))
+}
+
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct KafkaInfo {
     #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
@@ -37,16 +73,26 @@ pub struct KafkaInfo {
     // request
     #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
     pub req_msg_size: i32,
-    #[serde(skip)]
+    #[serde(rename = "request_version")]
     pub api_version: u16,
     #[serde(rename = "request_type")]
     pub api_key: u16,
     #[serde(skip)]
-    pub client_id: String,
+    pub client_id: Arc<str>,
 
     // reponse
     #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
     pub resp_msg_size: i32,
+    // 响应的错误码，序列化为错误名称，如NOT_LEADER_OR_FOLLOWER
+    #[serde(
+        rename = "error_name",
+        serialize_with = "serialize_error_name",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_code: i16,
+    // ApiVersions响应中服务端支持的API版本范围
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_versions: Vec<KafkaApiVersion>,
 }
 
 impl KafkaInfo {
@@ -54,6 +100,10 @@ impl KafkaInfo {
     const API_KEY_MAX: u16 = 67;
     pub fn merge(&mut self, other: Self) {
         self.resp_msg_size = other.resp_msg_size;
+        self.error_code = other.error_code;
+        if !other.api_versions.is_empty() {
+            self.api_versions = other.api_versions;
+        }
     }
     pub fn check(&self) -> bool {
         if self.api_key > Self::API_KEY_MAX {
@@ -63,6 +113,16 @@ impl KafkaInfo {
     }
 }
 
+impl L7SessionId for KafkaInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.correlation_id > 0 {
+            Some(self.correlation_id)
+        } else {
+            None
+        }
+    }
+}
+
 impl From<KafkaInfo> for flow_log::KafkaInfo {
     fn from(f: KafkaInfo) -> Self {
         flow_log::KafkaInfo {
@@ -70,8 +130,22 @@ impl From<KafkaInfo> for flow_log::KafkaInfo {
             req_msg_size: f.req_msg_size,
             api_version: f.api_version as u32,
             api_key: f.api_key as u32,
-            client_id: f.client_id,
+            client_id: f.client_id.to_string(),
             resp_msg_size: f.resp_msg_size,
+            error_name: if f.error_code != 0 {
+                error_name_or_code(f.error_code)
+            } else {
+                String::new()
+            },
+            api_versions: f
+                .api_versions
+                .into_iter()
+                .map(|v| flow_log::KafkaApiVersion {
+                    api_key: v.api_key as u32,
+                    min_version: v.min_version as u32,
+                    max_version: v.max_version as u32,
+                })
+                .collect(),
         }
     }
 }
@@ -82,6 +156,20 @@ pub struct KafkaLog {
     msg_type: LogMessageType,
     status: L7ResponseStatus,
     status_code: u16,
+    // correlation_id -> api_version，响应中只有correlation_id，需要请求中的版本解析ApiVersions响应
+    pending_api_versions: HashMap<u32, u16>,
+}
+
+// Kafka的unsigned varint，返回值和占用的字节数
+fn read_uvarint(bs: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, b) in bs.iter().take(5).enumerate() {
+        value |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
 }
 
 impl KafkaLog {
@@ -91,8 +179,10 @@ impl KafkaLog {
         self.info.req_msg_size = -1;
         self.info.api_version = 0;
         self.info.api_key = 0;
-        self.info.client_id = String::new();
+        self.info.client_id = Arc::default();
         self.info.resp_msg_size = -1;
+        self.info.error_code = 0;
+        self.info.api_versions.clear();
         self.status = L7ResponseStatus::Ok;
         self.status_code = 0;
     }
@@ -106,24 +196,40 @@ impl KafkaLog {
         let client_id_len = read_u16_be(&payload[12..]) as usize;
         if payload.len() < KAFKA_REQ_HEADER_LEN + client_id_len {
             self.reset_logs();
-            return Err(Error::KafkaLogParseFailed);
+            return Err(Error::KafkaLogParseFailed(
+                ParseContext::new(ParseStage::Request)
+                    .mismatch(KAFKA_REQ_HEADER_LEN + client_id_len, payload.len()),
+            ));
         }
 
         if strict && self.info.req_msg_size as usize != payload.len() - Self::MSG_LEN_SIZE {
             self.reset_logs();
-            return Err(Error::KafkaLogParseFailed);
+            return Err(Error::KafkaLogParseFailed(
+                ParseContext::new(ParseStage::Request).mismatch(
+                    payload.len() - Self::MSG_LEN_SIZE,
+                    self.info.req_msg_size as usize,
+                ),
+            ));
         }
 
         self.msg_type = LogMessageType::Request;
         self.info.api_key = read_u16_be(&payload[4..]);
         self.info.api_version = read_u16_be(&payload[6..]);
         self.info.correlation_id = read_u32_be(&payload[8..]);
-        self.info.client_id =
-            String::from_utf8_lossy(&payload[14..14 + client_id_len]).into_owned();
-
-        if !self.info.client_id.is_ascii() {
+        let client_id = String::from_utf8_lossy(&payload[14..14 + client_id_len]);
+        if !client_id.is_ascii() {
             self.reset_logs();
-            return Err(Error::KafkaLogParseFailed);
+            return Err(Error::KafkaLogParseFailed(
+                ParseContext::new(ParseStage::Request).at(14),
+            ));
+        }
+        self.info.client_id = intern(&client_id);
+        if self.info.api_key == API_VERSIONS {
+            if self.pending_api_versions.len() >= MAX_PENDING_API_VERSIONS {
+                self.pending_api_versions.clear();
+            }
+            self.pending_api_versions
+                .insert(self.info.correlation_id, self.info.api_version);
         }
 
         Ok(AppProtoHead {
@@ -133,6 +239,7 @@ impl KafkaLog {
             code: self.status_code,
             rrt: 0,
             version: 0,
+            ..Default::default()
         })
     }
 
@@ -140,16 +247,62 @@ impl KafkaLog {
         self.info.resp_msg_size = read_u32_be(payload) as i32;
         self.info.correlation_id = read_u32_be(&payload[4..]);
         self.msg_type = LogMessageType::Response;
+        if let Some(version) = self.pending_api_versions.remove(&self.info.correlation_id) {
+            self.parse_api_versions(&payload[KAFKA_RESP_HEADER_LEN..], version);
+        }
 
         Ok(AppProtoHead {
             proto: L7Protocol::Kafka,
             msg_type: self.msg_type,
-            status: L7ResponseStatus::Ok,
-            code: 0,
+            status: self.status,
+            code: self.status_code,
             rrt: 0,
             version: 0,
+            ..Default::default()
         })
     }
+
+    // ApiVersionsResponse => error_code [api_key min_version max_version] ...
+    // v3开始数组长度为unsigned varint(N+1)，每一项后跟tagged fields
+    fn parse_api_versions(&mut self, payload: &[u8], version: u16) {
+        if payload.len() < 2 {
+            return;
+        }
+        self.status_code = read_u16_be(payload);
+        self.info.error_code = self.status_code as i16;
+        self.status = error_status(self.info.error_code);
+
+        let flexible = version >= API_VERSIONS_FLEXIBLE_VERSION;
+        let (count, mut offset) = if flexible {
+            match read_uvarint(&payload[2..]) {
+                Some((n, size)) if n > 0 => (n as usize - 1, 2 + size),
+                _ => return,
+            }
+        } else {
+            if payload.len() < 6 {
+                return;
+            }
+            (read_u32_be(&payload[2..]) as usize, 6)
+        };
+        // 数据可能被截断，只保留完整的项
+        for _ in 0..count {
+            if offset + 6 > payload.len() {
+                break;
+            }
+            self.info.api_versions.push(KafkaApiVersion {
+                api_key: read_u16_be(&payload[offset..]),
+                min_version: read_u16_be(&payload[offset + 2..]),
+                max_version: read_u16_be(&payload[offset + 4..]),
+            });
+            offset += 6;
+            if flexible {
+                match read_uvarint(&payload[offset..]) {
+                    Some((0, size)) => offset += size,
+                    _ => break,
+                }
+            }
+        }
+    }
 }
 
 impl L7LogParse for KafkaLog {
@@ -164,7 +317,9 @@ impl L7LogParse for KafkaLog {
         }
         self.reset_logs();
         if payload.len() < KAFKA_REQ_HEADER_LEN {
-            return Err(Error::KafkaLogParseFailed);
+            return Err(Error::KafkaLogParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(KAFKA_REQ_HEADER_LEN, payload.len()),
+            ));
         }
         let head = match direction {
             PacketDirection::ClientToServer => self.request(payload, false),
@@ -201,6 +356,19 @@ pub fn kafka_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     return kafka.info.check();
 }
 
+impl KafkaLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let mut kafka = KafkaLog::default();
+            let _ = kafka.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_kafka = kafka_check_protocol(&mut bitmap, packet);
+            output.push_str(&format!("{:?} is_kafka: {}\r\n", kafka.info, is_kafka));
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -215,30 +383,53 @@ mod tests {
     fn run(name: &str) -> String {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
+        KafkaLog::golden_output(&mut packets)
+    }
+
+    #[test]
+    fn parse_api_versions() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("kafka.pcap"), None);
+        let packets = capture.as_meta_packets();
+        let mut kafka = KafkaLog::default();
+        let directions = [
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        ];
+        for (packet, direction) in packets.iter().zip(directions) {
+            let payload = packet.get_l4_payload().unwrap();
+            kafka
+                .parse(payload, packet.lookup_key.proto, direction)
+                .unwrap();
         }
+        assert_eq!(kafka.info.api_versions.len(), 59);
+        assert_eq!(
+            kafka.info.api_versions[18],
+            KafkaApiVersion {
+                api_key: 18,
+                min_version: 0,
+                max_version: 3
+            }
+        );
+        assert_eq!(kafka.status, L7ResponseStatus::Ok);
+    }
 
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
+    #[test]
+    fn parse_error_code() {
+        let mut kafka = KafkaLog::default();
+        // UNSUPPORTED_VERSION，空的api_versions数组
+        kafka.parse_api_versions(&[0, 35, 0, 0, 0, 0], 0);
+        assert_eq!(kafka.status, L7ResponseStatus::ClientError);
+        assert_eq!(kafka.info.error_code, 35);
+        let json = serde_json::to_string(&kafka.info).unwrap();
+        assert!(json.contains(r#""error_name":"UNSUPPORTED_VERSION""#));
 
-            let mut kafka = KafkaLog::default();
-            let _ = kafka.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_kafka = kafka_check_protocol(&mut bitmap, packet);
-            output.push_str(&format!("{:?} is_kafka: {}\r\n", kafka.info, is_kafka));
-        }
-        output
+        kafka.reset_logs();
+        let json = serde_json::to_string(&kafka.info).unwrap();
+        assert!(!json.contains("error_name"));
+
+        kafka.info.error_code = 1000;
+        let info: flow_log::KafkaInfo = kafka.info.clone().into();
+        assert_eq!(info.error_name, "UNKNOWN(1000)");
     }
 
     #[test]
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::super::L7ResponseStatus;
+
+const OK: L7ResponseStatus = L7ResponseStatus::Ok;
+// 请求本身的问题，如参数非法、鉴权失败、资源不存在，原样重试不会成功
+const C: L7ResponseStatus = L7ResponseStatus::ClientError;
+// broker或集群状态导致，如leader切换、副本不足、超时，通常可重试
+const S: L7ResponseStatus = L7ResponseStatus::ServerError;
+
+const UNKNOWN_SERVER_ERROR: i16 = -1;
+
+// https://kafka.apache.org/protocol.html#protocol_error_codes
+// 下标为错误码，覆盖到Kafka 3.7
+const ERRORS: [(&str, L7ResponseStatus); 120] = [
+    ("NONE", OK),                                 // 0
+    ("OFFSET_OUT_OF_RANGE", C),                   // 1
+    ("CORRUPT_MESSAGE", C),                       // 2
+    ("UNKNOWN_TOPIC_OR_PARTITION", C),            // 3
+    ("INVALID_FETCH_SIZE", C),                    // 4
+    ("LEADER_NOT_AVAILABLE", S),                  // 5
+    ("NOT_LEADER_OR_FOLLOWER", S),                // 6
+    ("REQUEST_TIMED_OUT", S),                     // 7
+    ("BROKER_NOT_AVAILABLE", S),                  // 8
+    ("REPLICA_NOT_AVAILABLE", S),                 // 9
+    ("MESSAGE_TOO_LARGE", C),                     // 10
+    ("STALE_CONTROLLER_EPOCH", S),                // 11
+    ("OFFSET_METADATA_TOO_LARGE", C),             // 12
+    ("NETWORK_EXCEPTION", S),                     // 13
+    ("COORDINATOR_LOAD_IN_PROGRESS", S),          // 14
+    ("COORDINATOR_NOT_AVAILABLE", S),             // 15
+    ("NOT_COORDINATOR", S),                       // 16
+    ("INVALID_TOPIC_EXCEPTION", C),               // 17
+    ("RECORD_LIST_TOO_LARGE", C),                 // 18
+    ("NOT_ENOUGH_REPLICAS", S),                   // 19
+    ("NOT_ENOUGH_REPLICAS_AFTER_APPEND", S),      // 20
+    ("INVALID_REQUIRED_ACKS", C),                 // 21
+    ("ILLEGAL_GENERATION", C),                    // 22
+    ("INCONSISTENT_GROUP_PROTOCOL", C),           // 23
+    ("INVALID_GROUP_ID", C),                      // 24
+    ("UNKNOWN_MEMBER_ID", C),                     // 25
+    ("INVALID_SESSION_TIMEOUT", C),               // 26
+    ("REBALANCE_IN_PROGRESS", S),                 // 27
+    ("INVALID_COMMIT_OFFSET_SIZE", C),            // 28
+    ("TOPIC_AUTHORIZATION_FAILED", C),            // 29
+    ("GROUP_AUTHORIZATION_FAILED", C),            // 30
+    ("CLUSTER_AUTHORIZATION_FAILED", C),          // 31
+    ("INVALID_TIMESTAMP", C),                     // 32
+    ("UNSUPPORTED_SASL_MECHANISM", C),            // 33
+    ("ILLEGAL_SASL_STATE", C),                    // 34
+    ("UNSUPPORTED_VERSION", C),                   // 35
+    ("TOPIC_ALREADY_EXISTS", C),                  // 36
+    ("INVALID_PARTITIONS", C),                    // 37
+    ("INVALID_REPLICATION_FACTOR", C),            // 38
+    ("INVALID_REPLICA_ASSIGNMENT", C),            // 39
+    ("INVALID_CONFIG", C),                        // 40
+    ("NOT_CONTROLLER", S),                        // 41
+    ("INVALID_REQUEST", C),                       // 42
+    ("UNSUPPORTED_FOR_MESSAGE_FORMAT", C),        // 43
+    ("POLICY_VIOLATION", C),                      // 44
+    ("OUT_OF_ORDER_SEQUENCE_NUMBER", C),          // 45
+    ("DUPLICATE_SEQUENCE_NUMBER", C),             // 46
+    ("INVALID_PRODUCER_EPOCH", C),                // 47
+    ("INVALID_TXN_STATE", C),                     // 48
+    ("INVALID_PRODUCER_ID_MAPPING", C),           // 49
+    ("INVALID_TRANSACTION_TIMEOUT", C),           // 50
+    ("CONCURRENT_TRANSACTIONS", S),               // 51
+    ("TRANSACTION_COORDINATOR_FENCED", S),        // 52
+    ("TRANSACTIONAL_ID_AUTHORIZATION_FAILED", C), // 53
+    ("SECURITY_DISABLED", C),                     // 54
+    ("OPERATION_NOT_ATTEMPTED", S),               // 55
+    ("KAFKA_STORAGE_ERROR", S),                   // 56
+    ("LOG_DIR_NOT_FOUND", C),                     // 57
+    ("SASL_AUTHENTICATION_FAILED", C),            // 58
+    ("UNKNOWN_PRODUCER_ID", C),                   // 59
+    ("REASSIGNMENT_IN_PROGRESS", S),              // 60
+    ("DELEGATION_TOKEN_AUTH_DISABLED", C),        // 61
+    ("DELEGATION_TOKEN_NOT_FOUND", C),            // 62
+    ("DELEGATION_TOKEN_OWNER_MISMATCH", C),       // 63
+    ("DELEGATION_TOKEN_REQUEST_NOT_ALLOWED", C),  // 64
+    ("DELEGATION_TOKEN_AUTHORIZATION_FAILED", C), // 65
+    ("DELEGATION_TOKEN_EXPIRED", C),              // 66
+    ("INVALID_PRINCIPAL_TYPE", C),                // 67
+    ("NON_EMPTY_GROUP", C),                       // 68
+    ("GROUP_ID_NOT_FOUND", C),                    // 69
+    ("FETCH_SESSION_ID_NOT_FOUND", C),            // 70
+    ("INVALID_FETCH_SESSION_EPOCH", C),           // 71
+    ("LISTENER_NOT_FOUND", S),                    // 72
+    ("TOPIC_DELETION_DISABLED", C),               // 73
+    ("FENCED_LEADER_EPOCH", S),                   // 74
+    ("UNKNOWN_LEADER_EPOCH", S),                  // 75
+    ("UNSUPPORTED_COMPRESSION_TYPE", C),          // 76
+    ("STALE_BROKER_EPOCH", S),                    // 77
+    ("OFFSET_NOT_AVAILABLE", S),                  // 78
+    ("MEMBER_ID_REQUIRED", C),                    // 79
+    ("PREFERRED_LEADER_NOT_AVAILABLE", S),        // 80
+    ("GROUP_MAX_SIZE_REACHED", C),                // 81
+    ("FENCED_INSTANCE_ID", C),                    // 82
+    ("ELIGIBLE_LEADERS_NOT_AVAILABLE", S),        // 83
+    ("ELECTION_NOT_NEEDED", C),                   // 84
+    ("NO_REASSIGNMENT_IN_PROGRESS", C),           // 85
+    ("GROUP_SUBSCRIBED_TO_TOPIC", C),             // 86
+    ("INVALID_RECORD", C),                        // 87
+    ("UNSTABLE_OFFSET_COMMIT", S),                // 88
+    ("THROTTLING_QUOTA_EXCEEDED", C),             // 89
+    ("PRODUCER_FENCED", C),                       // 90
+    ("RESOURCE_NOT_FOUND", C),                    // 91
+    ("DUPLICATE_RESOURCE", C),                    // 92
+    ("UNACCEPTABLE_CREDENTIAL", C),               // 93
+    ("INCONSISTENT_VOTER_SET", S),                // 94
+    ("INVALID_UPDATE_VERSION", C),                // 95
+    ("FEATURE_UPDATE_FAILED", S),                 // 96
+    ("PRINCIPAL_DESERIALIZATION_FAILURE", C),     // 97
+    ("SNAPSHOT_NOT_FOUND", S),                    // 98
+    ("POSITION_OUT_OF_RANGE", C),                 // 99
+    ("UNKNOWN_TOPIC_ID", C),                      // 100
+    ("DUPLICATE_BROKER_REGISTRATION", S),         // 101
+    ("BROKER_ID_NOT_REGISTERED", S),              // 102
+    ("INCONSISTENT_TOPIC_ID", S),                 // 103
+    ("INCONSISTENT_CLUSTER_ID", S),               // 104
+    ("TRANSACTIONAL_ID_NOT_FOUND", C),            // 105
+    ("FETCH_SESSION_TOPIC_ID_ERROR", S),          // 106
+    ("INELIGIBLE_REPLICA", S),                    // 107
+    ("NEW_LEADER_ELECTED", S),                    // 108
+    ("OFFSET_MOVED_TO_TIERED_STORAGE", S),        // 109
+    ("FENCED_MEMBER_EPOCH", C),                   // 110
+    ("UNRELEASED_INSTANCE_ID", C),                // 111
+    ("UNSUPPORTED_ASSIGNOR", C),                  // 112
+    ("STALE_MEMBER_EPOCH", C),                    // 113
+    ("MISMATCHED_ENDPOINT_TYPE", C),              // 114
+    ("UNSUPPORTED_ENDPOINT_TYPE", C),             // 115
+    ("UNKNOWN_CONTROLLER_ID", S),                 // 116
+    ("UNKNOWN_SUBSCRIPTION_ID", C),               // 117
+    ("TELEMETRY_TOO_LARGE", C),                   // 118
+    ("INVALID_REGISTRATION", C),                  // 119
+];
+
+pub fn error_name(code: i16) -> Option<&'static str> {
+    if code == UNKNOWN_SERVER_ERROR {
+        return Some("UNKNOWN_SERVER_ERROR");
+    }
+    ERRORS
+        .get(usize::try_from(code).ok()?)
+        .map(|(name, _)| *name)
+}
+
+// 未知的错误码按服务端错误处理
+pub fn error_status(code: i16) -> L7ResponseStatus {
+    match usize::try_from(code).ok().and_then(|c| ERRORS.get(c)) {
+        Some((_, status)) => *status,
+        None => L7ResponseStatus::ServerError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_code() {
+        assert_eq!(error_name(0), Some("NONE"));
+        assert_eq!(error_name(-1), Some("UNKNOWN_SERVER_ERROR"));
+        assert_eq!(error_name(6), Some("NOT_LEADER_OR_FOLLOWER"));
+        assert_eq!(error_name(119), Some("INVALID_REGISTRATION"));
+        assert_eq!(error_name(120), None);
+        assert_eq!(error_name(-2), None);
+
+        assert_eq!(error_status(0), L7ResponseStatus::Ok);
+        assert_eq!(error_status(-1), L7ResponseStatus::ServerError);
+        assert_eq!(error_status(6), L7ResponseStatus::ServerError);
+        assert_eq!(error_status(29), L7ResponseStatus::ClientError);
+        assert_eq!(error_status(1000), L7ResponseStatus::ServerError);
+    }
+}
@@ -15,6 +15,7 @@
  */
 
 mod kafka;
+mod kafka_error;
 pub mod mqtt;
 
 pub use kafka::{kafka_check_protocol, KafkaInfo, KafkaLog};
@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
 
 use log::{debug, warn};
 use nom::{
@@ -29,20 +29,31 @@ use serde::{Serialize, Serializer};
 use super::super::{
     value_is_default, value_is_negative, AppProtoHead, AppProtoHeadEnum, AppProtoLogsBaseInfo,
     AppProtoLogsData, AppProtoLogsInfo, AppProtoLogsInfoEnum, L7LogParse, L7Protocol,
-    L7ResponseStatus, LogMessageType,
+    L7ResponseStatus, L7SessionId, LogMessageType,
 };
 
 use crate::{
     common::enums::{IpProtocol, PacketDirection},
     common::meta_packet::MetaPacket,
-    flow_generator::error::{Error, Result},
-    proto::flow_log::{self, MqttTopic},
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    flow_generator::golden,
+    proto::flow_log::{self, MqttTopic, MqttTopicCount},
+    utils::intern::intern,
 };
 
+pub const MQTT_V5: u8 = 5;
+// v5 PUBLISH报文中的主题别名属性
+const MQTT_PROPERTY_TOPIC_ALIAS: u8 = 0x23;
+// 每个流最多记录的主题及主题别名数量，避免异常流量占用过多内存
+const MQTT_MAX_TOPICS_PER_FLOW: usize = 1024;
+// 会话中各主题消息数的汇总间隔，及汇总时输出的主题数
+const MQTT_TOPIC_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+const MQTT_TOPIC_SUMMARY_TOP_K: usize = 10;
+
 #[derive(Serialize, Clone, Debug)]
 pub struct MqttInfo {
     #[serde(rename = "request_domain", skip_serializing_if = "Option::is_none")]
-    pub client_id: Option<String>,
+    pub client_id: Option<Arc<str>>,
     #[serde(skip_serializing_if = "value_is_default")]
     pub version: u8,
     #[serde(rename = "request_type")]
@@ -58,9 +69,13 @@ pub struct MqttInfo {
     )]
     pub subscribe_topics: Option<Vec<MqttTopic>>,
     #[serde(skip)]
-    pub publish_topic: Option<String>,
+    pub publish_topic: Option<Arc<str>>,
     #[serde(skip)]
     pub code: u8, // connect_ack packet return code
+    #[serde(skip)]
+    pub topic_alias: u16, // v5 publish packet topic alias, 0 means not exist
+    #[serde(skip)]
+    pub topic_counts: Option<Vec<MqttTopicCount>>, // only for TopicSummary
 }
 
 pub fn topics_format<S>(t: &Option<Vec<MqttTopic>>, serializer: S) -> Result<S::Ok, S::Error>
@@ -83,6 +98,8 @@ impl Default for MqttInfo {
             subscribe_topics: None,
             publish_topic: None,
             code: 0,
+            topic_alias: 0,
+            topic_counts: None,
         }
     }
 }
@@ -102,13 +119,16 @@ impl MqttInfo {
     }
 }
 
+impl L7SessionId for MqttInfo {}
+
 impl From<MqttInfo> for flow_log::MqttInfo {
     fn from(f: MqttInfo) -> Self {
         let topics = match f.pkt_type {
             PacketKind::Publish { .. } => {
                 vec![MqttTopic {
-                    name: f.publish_topic.unwrap_or_default(),
+                    name: f.publish_topic.map(|t| t.to_string()).unwrap_or_default(),
                     qos: -1,
+                    wildcard: false,
                 }]
             }
             PacketKind::Unsubscribe | PacketKind::Subscribe => {
@@ -121,28 +141,114 @@ impl From<MqttInfo> for flow_log::MqttInfo {
             mqtt_type: f.pkt_type.to_string(),
             req_msg_size: f.req_msg_size,
             proto_version: f.version as u32,
-            client_id: f.client_id.unwrap_or_default(),
+            client_id: f.client_id.map(|c| c.to_string()).unwrap_or_default(),
             resp_msg_size: f.res_msg_size,
             topics,
+            topic_counts: f.topic_counts.unwrap_or_default(),
         }
     }
 }
 
+fn is_wildcard_topic(topic: &str) -> bool {
+    topic.contains(|c| c == '+' || c == '#')
+}
+
+// 主题消息数汇总，作为会话类型的日志输出
+fn topic_summary(
+    info: &MqttInfo,
+    topic_counts: Vec<MqttTopicCount>,
+    base_info: &AppProtoLogsBaseInfo,
+) -> Option<AppProtoLogsData> {
+    if topic_counts.is_empty() {
+        return None;
+    }
+    let mut base_info = base_info.clone();
+    base_info.head.msg_type = LogMessageType::Session;
+    base_info.head.status = L7ResponseStatus::Ok;
+    base_info.head.code = 0;
+    base_info.head.rrt = 0;
+    let summary = MqttInfo {
+        client_id: info.client_id.clone(),
+        version: info.version,
+        pkt_type: PacketKind::TopicSummary,
+        topic_counts: Some(topic_counts),
+        ..Default::default()
+    };
+    Some(AppProtoLogsData::new(
+        base_info,
+        AppProtoLogsInfo::Mqtt(summary),
+    ))
+}
+
+#[derive(Clone, Debug, Default)]
+struct MqttTopicStats {
+    // v5主题别名由发送方各自分配，按方向分别记录，下标0为客户端到服务端
+    aliases: [HashMap<u16, Arc<str>>; 2],
+    counts: HashMap<Arc<str>, u32>,
+    last_summary: Duration,
+}
+
+impl MqttTopicStats {
+    // 带主题名时记录别名映射，主题名为空时通过别名还原
+    fn resolve_alias(&mut self, direction: PacketDirection, info: &mut MqttInfo) {
+        if info.topic_alias == 0 {
+            return;
+        }
+        let aliases = &mut self.aliases[(direction != PacketDirection::ClientToServer) as usize];
+        match info.publish_topic.clone() {
+            Some(topic) if !topic.is_empty() => {
+                if aliases.len() < MQTT_MAX_TOPICS_PER_FLOW
+                    || aliases.contains_key(&info.topic_alias)
+                {
+                    aliases.insert(info.topic_alias, topic);
+                }
+            }
+            _ => info.publish_topic = aliases.get(&info.topic_alias).cloned(),
+        }
+    }
+
+    fn count(&mut self, topic: &Arc<str>) {
+        if let Some(count) = self.counts.get_mut(topic) {
+            *count += 1;
+        } else if self.counts.len() < MQTT_MAX_TOPICS_PER_FLOW {
+            self.counts.insert(topic.clone(), 1);
+        }
+    }
+
+    // 按消息数从大到小取前K个主题，取出后重新计数
+    fn take_top_k(&mut self) -> Vec<MqttTopicCount> {
+        let mut counts = self.counts.drain().collect::<Vec<_>>();
+        counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(MQTT_TOPIC_SUMMARY_TOP_K);
+        counts
+            .into_iter()
+            .map(|(name, count)| MqttTopicCount {
+                name: name.to_string(),
+                count,
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct MqttLog {
     info: Vec<MqttInfo>,
     msg_type: LogMessageType,
     status: L7ResponseStatus,
     version: u8,
-    client_map: HashMap<u64, String>,
+    client_map: HashMap<u64, Arc<str>>,
+    topic_stats: HashMap<u64, MqttTopicStats>,
 }
 
 impl MqttLog {
+    // 返回的日志中可能包含该流的主题消息数汇总
     pub fn amend_mqtt_proto_log_and_generate_log_data(
         &mut self,
         mut special_info: AppProtoLogsInfo,
         base_info: AppProtoLogsBaseInfo,
-    ) -> Result<AppProtoLogsData> {
+        direction: PacketDirection,
+    ) -> Result<Vec<AppProtoLogsData>> {
+        let mut summary = None;
         if let AppProtoLogsInfo::Mqtt(ref mut info) = special_info {
             let key = base_info.flow_id;
             match info.pkt_type {
@@ -158,28 +264,70 @@ impl MqttLog {
                             None => {
                                 debug!("client id not found, maybe four tuple(src_ip, dst_ip, src_port, dst_port) already changed, 
                                 or CONNECT packet not found, or treat other packets as MQTT packets.");
-                                return Err(Error::MqttLogParseFailed);
+                                return Err(Error::MqttLogParseFailed(ParseStage::Session.into()));
                             }
                         }
                     }
                 }
             }
+            summary = self.update_topic_stats(info, &base_info, direction);
+        }
+        let mut logs = vec![AppProtoLogsData::new(base_info, special_info)];
+        logs.extend(summary);
+        Ok(logs)
+    }
+
+    // 统计PUBLISH报文的主题，每隔MQTT_TOPIC_SUMMARY_INTERVAL及连接断开时输出汇总
+    fn update_topic_stats(
+        &mut self,
+        info: &mut MqttInfo,
+        base_info: &AppProtoLogsBaseInfo,
+        direction: PacketDirection,
+    ) -> Option<AppProtoLogsData> {
+        let key = base_info.flow_id;
+        let stats = match info.pkt_type {
+            PacketKind::Publish { .. } => {
+                self.topic_stats
+                    .entry(key)
+                    .or_insert_with(|| MqttTopicStats {
+                        last_summary: base_info.start_time,
+                        ..Default::default()
+                    })
+            }
+            PacketKind::Disconnect => {
+                let mut stats = self.topic_stats.remove(&key)?;
+                return topic_summary(info, stats.take_top_k(), base_info);
+            }
+            _ => return None,
+        };
+        stats.resolve_alias(direction, info);
+        if let Some(topic) = info.publish_topic.as_ref().filter(|t| !t.is_empty()) {
+            stats.count(topic);
+        }
+        if base_info.start_time < stats.last_summary + MQTT_TOPIC_SUMMARY_INTERVAL {
+            return None;
         }
-        Ok(AppProtoLogsData::new(base_info, special_info))
+        stats.last_summary = base_info.start_time;
+        topic_summary(info, stats.take_top_k(), base_info)
     }
 
     fn parse_mqtt_info(&mut self, mut payload: &[u8]) -> Result<Vec<AppProtoHead>> {
-        // 现在只支持MQTT 3.1.1解析，不支持v5.0
-        // Now only supports MQTT 3.1.1 parsing, not support v5.0
-        if self.version != 0 && self.version != 4 {
-            warn!("cannot parse packet, log parser only support to parse MQTT V3.1.1 packet");
-            return Err(Error::MqttLogParseFailed);
+        // 现在只支持MQTT 3.1.1及v5.0解析
+        // Now only supports MQTT 3.1.1 and v5.0 parsing
+        if self.version != 0 && self.version != 4 && self.version != MQTT_V5 {
+            warn!(
+                "cannot parse packet, log parser only support to parse MQTT V3.1.1 and V5.0 packet"
+            );
+            return Err(Error::MqttLogParseFailed(ParseStage::Session.into()));
         }
 
+        let total_len = payload.len();
         let mut app_proto_heads = vec![];
         loop {
-            let (input, header) =
-                mqtt_fixed_header(payload).map_err(|_| Error::MqttLogParseFailed)?;
+            let offset = total_len - payload.len();
+            let (input, header) = mqtt_fixed_header(payload).map_err(|_| {
+                Error::MqttLogParseFailed(ParseContext::new(ParseStage::Header).at(offset))
+            })?;
             let mut info = MqttInfo::default();
             match header.kind {
                 PacketKind::Connect => {
@@ -187,17 +335,24 @@ impl MqttLog {
                     let (_, (version, client_id)) = data
                         .and_then(parse_connect_packet)
                         .parse(input)
-                        .map_err(|_| Error::MqttLogParseFailed)?;
+                        .map_err(|_| {
+                            Error::MqttLogParseFailed(
+                                ParseContext::new(ParseStage::Request).at(offset),
+                            )
+                        })?;
                     info.version = version;
-                    info.client_id = Some(client_id.to_string());
+                    info.client_id = Some(intern(client_id));
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = header.remaining_length;
                     info.pkt_type = header.kind;
                     self.version = version;
                 }
                 PacketKind::Connack => {
-                    let (_, return_code) =
-                        parse_connack_packet(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, return_code) = parse_connack_packet(input).map_err(|_| {
+                        Error::MqttLogParseFailed(
+                            ParseContext::new(ParseStage::Response).at(offset),
+                        )
+                    })?;
                     info.code = return_code;
                     info.version = self.version;
                     self.msg_type = LogMessageType::Response;
@@ -206,11 +361,14 @@ impl MqttLog {
                     self.status = parse_status_code(return_code);
                 }
                 PacketKind::Publish { dup, qos, .. } => {
-                    let (_, topic_name) =
-                        mqtt_string(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (rest, topic_name) = mqtt_string(input).map_err(|_| {
+                        Error::MqttLogParseFailed(ParseContext::new(ParseStage::Payload).at(offset))
+                    })?;
                     if dup && qos == QualityOfService::AtMostOnce {
                         debug!("mqtt publish packet has invalid dup flags={}", dup);
-                        return Err(Error::MqttLogParseFailed);
+                        return Err(Error::MqttLogParseFailed(
+                            ParseContext::new(ParseStage::Payload).at(offset),
+                        ));
                     }
                     // QOS=1,2会有报文标识符
                     // QOS=1,2 there will be a message identifier
@@ -222,17 +380,26 @@ impl MqttLog {
                         self.msg_type = LogMessageType::Response;
                         info.res_msg_size = header.remaining_length;
                     };
-                    info.publish_topic.replace(topic_name.to_string());
+                    // v5使用主题别名时主题名为空，报文可能被截断，属性解析失败时忽略
+                    if self.version == MQTT_V5 {
+                        info.topic_alias = mqtt_publish_topic_alias(qos, rest)
+                            .map(|(_, alias)| alias)
+                            .unwrap_or_default();
+                    }
+                    info.publish_topic.replace(intern(topic_name));
                     info.pkt_type = header.kind;
                     info.version = self.version;
                 }
                 PacketKind::Subscribe => {
                     // 跳过解析报文标识符
                     // skip parsing packet identifier
-                    let (_, (_, result)) = mqtt_packet_identifier
-                        .and(mqtt_subscription_requests)
-                        .parse(input)
-                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, result) = mqtt_variable_header(self.version, input)
+                        .and_then(|(rest, _)| mqtt_subscription_requests(rest))
+                        .map_err(|_| {
+                            Error::MqttLogParseFailed(
+                                ParseContext::new(ParseStage::Request).at(offset),
+                            )
+                        })?;
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = header.remaining_length;
                     info.pkt_type = header.kind;
@@ -243,6 +410,7 @@ impl MqttLog {
                             .map(|(t, qos)| MqttTopic {
                                 name: t.to_string(),
                                 qos: qos as i32,
+                                wildcard: is_wildcard_topic(t),
                             })
                             .collect(),
                     );
@@ -254,10 +422,13 @@ impl MqttLog {
                     info.version = self.version;
                 }
                 PacketKind::Unsubscribe => {
-                    let (_, (_, reqs)) = mqtt_packet_identifier
-                        .and(mqtt_unsubscription_requests)
-                        .parse(input)
-                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let (_, reqs) = mqtt_variable_header(self.version, input)
+                        .and_then(|(rest, _)| mqtt_unsubscription_requests(rest))
+                        .map_err(|_| {
+                            Error::MqttLogParseFailed(
+                                ParseContext::new(ParseStage::Request).at(offset),
+                            )
+                        })?;
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = header.remaining_length;
                     info.pkt_type = header.kind;
@@ -267,6 +438,7 @@ impl MqttLog {
                             .map(|topic| MqttTopic {
                                 name: topic.to_string(),
                                 qos: -1,
+                                wildcard: is_wildcard_topic(topic),
                             })
                             .collect(),
                     );
@@ -293,6 +465,8 @@ impl MqttLog {
                     info.res_msg_size = header.remaining_length;
                     info.version = self.version;
                 }
+                // 不会从报文中解析出
+                PacketKind::TopicSummary => (),
             }
 
             app_proto_heads.push(AppProtoHead {
@@ -302,6 +476,7 @@ impl MqttLog {
                 code: info.code as u16,
                 rrt: 0,
                 version: info.version,
+                ..Default::default()
             });
             self.info.push(info);
 
@@ -312,7 +487,7 @@ impl MqttLog {
         }
 
         if app_proto_heads.is_empty() {
-            return Err(Error::MqttLogParseFailed);
+            return Err(Error::MqttLogParseFailed(ParseStage::Payload.into()));
         }
         Ok(app_proto_heads)
     }
@@ -419,6 +594,8 @@ pub enum PacketKind {
     Pingreq,
     Pingresp,
     Disconnect,
+    // 非MQTT报文，用于输出会话中各主题的消息数
+    TopicSummary,
 }
 
 impl fmt::Display for PacketKind {
@@ -438,6 +615,7 @@ impl fmt::Display for PacketKind {
             Self::Pingreq => write!(f, "PINGREQ"),
             Self::Pingresp => write!(f, "PINGRESP"),
             Self::Disconnect => write!(f, "DISCONNECT"),
+            Self::TopicSummary => write!(f, "TOPIC_SUMMARY"),
         }
     }
 }
@@ -526,9 +704,8 @@ fn decode_variable_length(bytes: &[u8]) -> u32 {
     output
 }
 
-pub fn mqtt_fixed_header(input: &[u8]) -> IResult<&[u8], PacketHeader> {
-    let (input, kind) = mqtt_packet_kind(input)?;
-    let (input, remaining_length) = map(
+fn mqtt_variable_length(input: &[u8]) -> IResult<&[u8], u32> {
+    map(
         recognize(
             number::complete::u8.and(bytes::complete::take_while_m_n(0, 3, |b| {
                 b & 0b1000_0000 != 0
@@ -536,7 +713,12 @@ pub fn mqtt_fixed_header(input: &[u8]) -> IResult<&[u8], PacketHeader> {
         ),
         decode_variable_length,
     )
-    .parse(input)?;
+    .parse(input)
+}
+
+pub fn mqtt_fixed_header(input: &[u8]) -> IResult<&[u8], PacketHeader> {
+    let (input, kind) = mqtt_packet_kind(input)?;
+    let (input, remaining_length) = mqtt_variable_length(input)?;
 
     Ok((
         input,
@@ -551,6 +733,68 @@ fn mqtt_packet_identifier(input: &[u8]) -> IResult<&[u8], u16> {
     number::complete::be_u16(input)
 }
 
+// v5属性，返回属性部分的数据
+fn mqtt_properties(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    mqtt_variable_length
+        .flat_map(bytes::complete::take)
+        .parse(input)
+}
+
+// SUBSCRIBE、UNSUBSCRIBE的可变报头，v5在报文标识符后有属性
+fn mqtt_variable_header(version: u8, input: &[u8]) -> IResult<&[u8], u16> {
+    let (input, identifier) = mqtt_packet_identifier(input)?;
+    if version == MQTT_V5 {
+        let (input, _) = mqtt_properties(input)?;
+        return Ok((input, identifier));
+    }
+    Ok((input, identifier))
+}
+
+fn mqtt_binary(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    number::complete::be_u16
+        .flat_map(bytes::complete::take)
+        .parse(input)
+}
+
+// 解析主题名之后的v5 PUBLISH可变报头，返回主题别名，不存在时为0
+fn mqtt_publish_topic_alias(qos: QualityOfService, input: &[u8]) -> IResult<&[u8], u16> {
+    let input = if qos == QualityOfService::AtMostOnce {
+        input
+    } else {
+        mqtt_packet_identifier(input)?.0
+    };
+    let (input, mut properties) = mqtt_properties(input)?;
+    let mut alias = 0;
+    // 按PUBLISH报文可能携带的属性类型跳过其他属性
+    while !properties.is_empty() {
+        let (rest, id) = number::complete::u8(properties)?;
+        properties = match id {
+            MQTT_PROPERTY_TOPIC_ALIAS => {
+                let (rest, value) = number::complete::be_u16(rest)?;
+                alias = value;
+                rest
+            }
+            // payload format indicator
+            0x01 => number::complete::u8(rest)?.0,
+            // message expiry interval
+            0x02 => number::complete::be_u32(rest)?.0,
+            // content type, response topic, correlation data
+            0x03 | 0x08 | 0x09 => mqtt_binary(rest)?.0,
+            // subscription identifier
+            0x0B => mqtt_variable_length(rest)?.0,
+            // user property
+            0x26 => mqtt_binary.and(mqtt_binary).parse(rest)?.0,
+            _ => {
+                return Err(nom::Err::Error(error::Error::new(
+                    rest,
+                    error::ErrorKind::Switch,
+                )))
+            }
+        };
+    }
+    Ok((input, alias))
+}
+
 fn mqtt_string(input: &[u8]) -> IResult<&[u8], &str> {
     fn control_characters(c: char) -> bool {
         ('\u{0001}'..='\u{001F}').contains(&c) || ('\u{007F}'..='\u{009F}').contains(&c)
@@ -584,6 +828,12 @@ pub fn parse_connect_packet(input: &[u8]) -> IResult<&[u8], (u8, &str)> {
 
     let (input, protocol_level) = number::complete::u8(input)?;
     let (input, _) = number::complete::be_u16(&input[1..])?;
+    // v5在保持连接时间之后有属性
+    let input = if protocol_level == MQTT_V5 {
+        mqtt_properties(input)?.0
+    } else {
+        input
+    };
     // Payload
     let (input, client_id) = mqtt_string(input)?;
     Ok((input, (protocol_level, client_id)))
@@ -628,7 +878,11 @@ pub fn parse_status_code(code: u8) -> L7ResponseStatus {
 fn mqtt_subscription_requests(input: &[u8]) -> IResult<&[u8], Vec<(&str, QualityOfService)>> {
     fn subscription_request(input: &[u8]) -> IResult<&[u8], (&str, QualityOfService)> {
         let (input, topic) = mqtt_string(input)?;
-        let (input, qos) = map_res(number::complete::u8, mqtt_quality_of_service).parse(input)?;
+        // v5中高位为其他订阅选项
+        let (input, qos) = map_res(number::complete::u8, |options| {
+            mqtt_quality_of_service(options & 0b11)
+        })
+        .parse(input)?;
         Ok((input, (topic, qos)))
     }
 
@@ -695,6 +949,21 @@ fn mqtt_unsubscription_requests(input: &[u8]) -> IResult<&[u8], Vec<&str>> {
     Ok((input, reqs))
 }
 
+impl MqttLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut mqtt = MqttLog::default();
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let _ = mqtt.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_mqtt = mqtt_check_protocol(&mut bitmap, packet);
+            for i in mqtt.info.iter() {
+                output.push_str(&format!("{:?} is_mqtt: {}\r\n", i, is_mqtt));
+            }
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -702,38 +971,14 @@ mod tests {
 
     use super::*;
 
-    use crate::{common::enums::PacketDirection, utils::test::Capture};
+    use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/mqtt";
 
     fn run(name: &str) -> String {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1024));
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
-
-        let mut mqtt = MqttLog::default();
-        let mut output: String = String::new();
-        let mut bitmap = 0;
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-            let _ = mqtt.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_mqtt = mqtt_check_protocol(&mut bitmap, packet);
-            for i in mqtt.info.iter() {
-                output.push_str(&format!("{:?} is_mqtt: {}\r\n", i, is_mqtt));
-            }
-        }
-        output
+        MqttLog::golden_output(&mut packets)
     }
 
     #[test]
@@ -882,6 +1127,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_v5_publish_properties() {
+        let input = &[
+            0, 1,  // Packet identifier
+            11, // Properties length
+            0x01, 0x01, // Payload format indicator
+            0x23, 0x00, 0x07, // Topic alias 7
+            0x26, 0, 1, b'k', 0, 0,    // User property
+            b'x', // Payload
+        ];
+        let (rest, alias) = mqtt_publish_topic_alias(QualityOfService::AtLeastOnce, input).unwrap();
+        assert_eq!(alias, 7);
+        assert_eq!(rest, &[b'x']);
+
+        mqtt_publish_topic_alias(QualityOfService::AtMostOnce, &[2, 0x7F, 0]).unwrap_err();
+    }
+
     #[test]
     fn check_simple_string() {
         let input = [0x00, 0x05, 0x41, 0xF0, 0xAA, 0x9B, 0x94];
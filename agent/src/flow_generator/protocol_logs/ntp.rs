@@ -0,0 +1,437 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseStage, Result},
+};
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct NtpInfo {
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: u8,
+    // 模式名称，如：client、server
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub mode: String,
+    // 请求的Transmit Timestamp即响应的Origin Timestamp，取其小数部分关联请求和响应
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub session_id: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub leap_indicator: u8,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub stratum: u8,
+    // stratum为0或1时为ASCII编码的kiss code或时钟源，否则为上级服务器的IPv4地址
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub reference_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub root_delay: u32, // us
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub root_dispersion: u32, // us
+    // 仅客户端模式的响应，服务端相对客户端的时钟偏差及往返时延，
+    // 客户端的接收时间取采集器收到响应的时间，采集器部署在客户端时准确
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub offset: i64, // us
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub delay: u32, // us
+}
+
+impl NtpInfo {
+    // 请求中只有版本和模式，以响应中的服务端状态为准
+    pub fn merge(&mut self, other: Self) {
+        self.leap_indicator = other.leap_indicator;
+        self.stratum = other.stratum;
+        self.reference_id = other.reference_id;
+        self.root_delay = other.root_delay;
+        self.root_dispersion = other.root_dispersion;
+        self.offset = other.offset;
+        self.delay = other.delay;
+    }
+}
+
+impl L7SessionId for NtpInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.session_id > 0 {
+            Some(self.session_id)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<NtpInfo> for flow_log::NtpInfo {
+    fn from(f: NtpInfo) -> Self {
+        flow_log::NtpInfo {
+            version: f.version as u32,
+            mode: f.mode,
+            session_id: f.session_id,
+            leap_indicator: f.leap_indicator as u32,
+            stratum: f.stratum as u32,
+            reference_id: f.reference_id,
+            root_delay: f.root_delay,
+            root_dispersion: f.root_dispersion,
+            offset: f.offset,
+            delay: f.delay,
+        }
+    }
+}
+
+// 64位NTP时间戳转换为UNIX时间，高32位为秒，低32位为秒的小数部分，全0表示未设置
+fn ntp_timestamp(buf: &[u8]) -> Duration {
+    let seconds = u32::from_be_bytes(buf[..4].try_into().unwrap()) as u64;
+    let fraction = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as u64;
+    if seconds == 0 && fraction == 0 {
+        return Duration::ZERO;
+    }
+    // 2036年后进入下一个NTP时代，秒数从0重新开始
+    let seconds = if seconds >= NTP_UNIX_EPOCH_OFFSET {
+        seconds - NTP_UNIX_EPOCH_OFFSET
+    } else {
+        seconds + (1 << 32) - NTP_UNIX_EPOCH_OFFSET
+    };
+    Duration::new(seconds, ((fraction * 1_000_000_000) >> 32) as u32)
+}
+
+// 16.16定点数表示的秒转换为us
+fn ntp_short(buf: &[u8]) -> u32 {
+    let value = u32::from_be_bytes(buf[..4].try_into().unwrap()) as u64;
+    ((value * 1_000_000) >> 16).min(u32::MAX as u64) as u32
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NtpHeader {
+    pub leap_indicator: u8,
+    pub version: u8,
+    pub mode: u8,
+    pub stratum: u8,
+    pub root_delay: u32,
+    pub root_dispersion: u32,
+    pub reference_id: [u8; 4],
+    pub origin_timestamp: Duration,
+    pub receive_timestamp: Duration,
+    pub transmit_timestamp: Duration,
+    // 请求的Transmit Timestamp或响应的Origin Timestamp的小数部分
+    pub session_id: u32,
+}
+
+impl NtpHeader {
+    // NTP包头格式参考RFC 5905，不解析扩展字段和MAC，不支持控制报文(mode 6)
+    pub fn parse_headers(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.len() < NTP_HEADER_LEN {
+            return Err(Error::NtpHeaderParseFailed(ParseStage::Header.into()));
+        }
+        let version = payload[0] >> 3 & 0x7;
+        let mode = payload[0] & 0x7;
+        if !(NTP_MIN_VERSION..=NTP_MAX_VERSION).contains(&version)
+            || !(NTP_MODE_SYMMETRIC_ACTIVE..=NTP_MODE_BROADCAST).contains(&mode)
+        {
+            return Err(Error::NtpHeaderParseFailed(ParseStage::Header.into()));
+        }
+        *self = NtpHeader {
+            leap_indicator: payload[0] >> 6,
+            version,
+            mode,
+            stratum: payload[1],
+            root_delay: ntp_short(&payload[4..8]),
+            root_dispersion: ntp_short(&payload[8..12]),
+            reference_id: payload[12..16].try_into().unwrap(),
+            origin_timestamp: ntp_timestamp(&payload[24..32]),
+            receive_timestamp: ntp_timestamp(&payload[32..40]),
+            transmit_timestamp: ntp_timestamp(&payload[40..48]),
+            session_id: 0,
+        };
+        let id_offset = match self.msg_type() {
+            LogMessageType::Request => 44,
+            LogMessageType::Response => 28,
+            _ => return Ok(()),
+        };
+        self.session_id = u32::from_be_bytes(payload[id_offset..id_offset + 4].try_into().unwrap());
+        Ok(())
+    }
+
+    pub fn msg_type(&self) -> LogMessageType {
+        match self.mode {
+            NTP_MODE_CLIENT | NTP_MODE_SYMMETRIC_ACTIVE => LogMessageType::Request,
+            NTP_MODE_SERVER | NTP_MODE_SYMMETRIC_PASSIVE => LogMessageType::Response,
+            // 广播没有请求
+            _ => LogMessageType::Session,
+        }
+    }
+
+    // 服务端时钟未同步或拒绝服务时为服务端异常
+    pub fn status(&self) -> L7ResponseStatus {
+        if self.leap_indicator == NTP_LEAP_ALARM || self.stratum == NTP_STRATUM_KISS_OF_DEATH {
+            L7ResponseStatus::ServerError
+        } else {
+            L7ResponseStatus::Ok
+        }
+    }
+
+    // 计算时钟偏差和往返时延(us)，received为客户端收到响应的时间
+    pub fn offset_and_delay(&self, received: Duration) -> Option<(i64, u32)> {
+        if self.mode != NTP_MODE_SERVER
+            || self.origin_timestamp.is_zero()
+            || received.is_zero()
+            || self.stratum == NTP_STRATUM_KISS_OF_DEATH
+        {
+            return None;
+        }
+        let us = |d: Duration| d.as_micros() as i64;
+        let (t1, t2, t3, t4) = (
+            us(self.origin_timestamp),
+            us(self.receive_timestamp),
+            us(self.transmit_timestamp),
+            us(received),
+        );
+        let offset = ((t2 - t1) + (t3 - t4)) / 2;
+        let delay = ((t4 - t1) - (t3 - t2)).clamp(0, u32::MAX as i64) as u32;
+        Some((offset, delay))
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            NTP_MODE_SYMMETRIC_ACTIVE => "symmetric active",
+            NTP_MODE_SYMMETRIC_PASSIVE => "symmetric passive",
+            NTP_MODE_CLIENT => "client",
+            NTP_MODE_SERVER => "server",
+            NTP_MODE_BROADCAST => "broadcast",
+            _ => "",
+        }
+    }
+
+    fn reference_name(&self) -> String {
+        if self.reference_id == [0; 4] {
+            return String::new();
+        }
+        if self.stratum <= NTP_STRATUM_PRIMARY {
+            return self
+                .reference_id
+                .iter()
+                .take_while(|b| **b != 0)
+                .filter(|b| b.is_ascii_graphic())
+                .map(|b| *b as char)
+                .collect();
+        }
+        Ipv4Addr::from(self.reference_id).to_string()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NtpLog {
+    info: NtpInfo,
+
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    // 包的采集时间，用于计算时钟偏差
+    timestamp: Duration,
+}
+
+impl NtpLog {
+    pub fn set_timestamp(&mut self, timestamp: Duration) {
+        self.timestamp = timestamp;
+    }
+
+    fn reset_logs(&mut self) {
+        self.info = NtpInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+    }
+
+    fn decode_payload(&mut self, payload: &[u8]) -> Result<AppProtoHead> {
+        let mut header = NtpHeader::default();
+        header.parse_headers(payload)?;
+
+        self.info = NtpInfo {
+            version: header.version,
+            mode: header.mode_name().to_string(),
+            session_id: header.session_id,
+            ..Default::default()
+        };
+        self.msg_type = header.msg_type();
+        if self.msg_type != LogMessageType::Request {
+            self.status = header.status();
+            self.info.leap_indicator = header.leap_indicator;
+            self.info.stratum = header.stratum;
+            self.info.reference_id = header.reference_name();
+            self.info.root_delay = header.root_delay;
+            self.info.root_dispersion = header.root_dispersion;
+            if let Some((offset, delay)) = header.offset_and_delay(self.timestamp) {
+                self.info.offset = offset;
+                self.info.delay = delay;
+            }
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Ntp,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: 0,
+            rrt: 0,
+            version: header.version,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for NtpLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        _direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Udp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        Ok(AppProtoHeadEnum::Single(self.decode_payload(payload)?))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Ntp(self.info.clone()))
+    }
+}
+
+// 通过发往123端口的请求识别NTP，采集器自身的时间同步也会被识别
+pub fn ntp_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Udp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Ntp));
+        return false;
+    }
+    if packet.lookup_key.dst_port != NTP_PORT {
+        if packet.lookup_key.src_port != NTP_PORT {
+            *bitmap &= !(1 << u8::from(L7Protocol::Ntp));
+        }
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    let mut header = NtpHeader::default();
+    if header.parse_headers(payload).is_err() {
+        *bitmap &= !(1 << u8::from(L7Protocol::Ntp));
+        return false;
+    }
+    header.msg_type() != LogMessageType::Response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2022-01-01 00:00:00 UTC
+    const UNIX_SECONDS: u64 = 1640995200;
+
+    fn timestamp(unix_ms: u64) -> [u8; 8] {
+        let seconds = (unix_ms / 1000 + NTP_UNIX_EPOCH_OFFSET) as u32;
+        let fraction = (((unix_ms % 1000) << 32) / 1000) as u32;
+        let mut buf = [0; 8];
+        buf[..4].copy_from_slice(&seconds.to_be_bytes());
+        buf[4..].copy_from_slice(&fraction.to_be_bytes());
+        buf
+    }
+
+    fn packet(li: u8, mode: u8, stratum: u8, origin: u64, receive: u64, transmit: u64) -> Vec<u8> {
+        let mut buf = vec![0; NTP_HEADER_LEN];
+        buf[0] = li << 6 | 4 << 3 | mode;
+        buf[1] = stratum;
+        // root delay 0.5s
+        buf[4..8].copy_from_slice(&0x8000u32.to_be_bytes());
+        buf[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        if origin > 0 {
+            buf[24..32].copy_from_slice(&timestamp(origin));
+        }
+        if receive > 0 {
+            buf[32..40].copy_from_slice(&timestamp(receive));
+        }
+        buf[40..48].copy_from_slice(&timestamp(transmit));
+        buf
+    }
+
+    #[test]
+    fn parse_client_server() {
+        // 时间均为1/8秒的整数倍，转换为NTP时间戳时没有误差
+        let t1 = UNIX_SECONDS * 1000 + 250;
+        let request = packet(0, NTP_MODE_CLIENT, 0, 0, 0, t1);
+        let mut ntp = NtpLog::default();
+        let head = ntp
+            .parse(&request, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(ntp.info.mode, "client");
+        assert_eq!(ntp.info.version, 4);
+        let request_id = ntp.info.session_id;
+        assert_ne!(request_id, 0);
+
+        // 服务端比客户端快1s，单程时延和服务端处理耗时均为125ms
+        let response = packet(0, NTP_MODE_SERVER, 2, t1, t1 + 1125, t1 + 1250);
+        ntp.set_timestamp(Duration::from_millis(t1 + 375));
+        let head = ntp
+            .parse(&response, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(head.status, L7ResponseStatus::Ok);
+        assert_eq!(
+            ntp.info,
+            NtpInfo {
+                version: 4,
+                mode: "server".into(),
+                session_id: request_id,
+                stratum: 2,
+                reference_id: "10.0.0.1".into(),
+                root_delay: 500000,
+                offset: 1000000,
+                delay: 250000,
+                ..Default::default()
+            }
+        );
+
+        // Kiss-o'-Death
+        let mut response = packet(NTP_LEAP_ALARM, NTP_MODE_SERVER, 0, t1, 0, 0);
+        response[12..16].copy_from_slice(b"RATE");
+        let head = ntp.decode_payload(&response).unwrap();
+        assert_eq!(head.status, L7ResponseStatus::ServerError);
+        assert_eq!(ntp.info.reference_id, "RATE");
+        assert_eq!(ntp.info.offset, 0);
+
+        // 控制报文
+        let mut control = request.clone();
+        control[0] = 2 << 3 | 6;
+        assert!(ntp.decode_payload(&control).is_err());
+        assert!(ntp.decode_payload(&request[..40]).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use log::debug;
+
+use super::{AppProtoLogsData, AppProtoLogsInfo, L7ResponseStatus, LogMessageType};
+use crate::common::flow::L7Protocol;
+use crate::metric::{
+    document::{Code, Document, Tagger},
+    meter::{AppMeter, Meter},
+};
+use crate::sender::SendItem;
+use crate::utils::queue::DebugSender;
+
+const MINUTE: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NtpMetricsKey {
+    vtap_id: u16,
+    client_epc: i32,
+    server_ip: IpAddr,
+    server_epc: i32,
+}
+
+#[derive(Default)]
+struct NtpStats {
+    meter: AppMeter,
+    // 用于计算偏差的标准差，平方和可能超出整数范围
+    offset_square_sum: f64,
+}
+
+impl NtpStats {
+    fn jitter(&self) -> u32 {
+        let ntp = &self.meter.ntp;
+        if ntp.offset_count < 2 {
+            return 0;
+        }
+        let count = ntp.offset_count as f64;
+        let mean = ntp.offset_sum as f64 / count;
+        let variance = self.offset_square_sum / count - mean * mean;
+        variance.max(0.0).sqrt().min(u32::MAX as f64) as u32
+    }
+}
+
+// 按(客户端EPC, NTP服务器)聚合NTP日志，生成分钟粒度的时钟偏差指标，
+// 同一服务器的客户端偏差普遍增大或离散时，说明存在大范围的时间漂移
+pub struct NtpMetrics {
+    // 会话聚合会延迟发送日志，一个分钟在超过该延迟后才输出
+    delay: Duration,
+    last_check: u64,
+    stash: BTreeMap<u64, HashMap<NtpMetricsKey, NtpStats>>,
+    output: DebugSender<SendItem>,
+}
+
+impl NtpMetrics {
+    pub fn new(delay: Duration, output: DebugSender<SendItem>) -> Self {
+        Self {
+            delay,
+            last_check: 0,
+            stash: BTreeMap::new(),
+            output,
+        }
+    }
+
+    // 日志已转换为客户端到服务端的方向
+    pub fn add(&mut self, log: &AppProtoLogsData) {
+        let base = &log.base_info;
+        let info = match &log.special_info {
+            AppProtoLogsInfo::Ntp(info) if base.head.proto == L7Protocol::Ntp => info,
+            _ => return,
+        };
+        let key = NtpMetricsKey {
+            vtap_id: base.vtap_id,
+            client_epc: base.l3_epc_id_src,
+            server_ip: base.ip_dst,
+            server_epc: base.l3_epc_id_dst,
+        };
+        let minute = base.start_time.as_secs() / MINUTE * MINUTE;
+        let stats = self
+            .stash
+            .entry(minute)
+            .or_default()
+            .entry(key)
+            .or_default();
+        let meter = &mut stats.meter;
+
+        match base.head.msg_type {
+            // 会话聚合结束时仍未匹配到响应的请求计为超时
+            LogMessageType::Request => {
+                meter.traffic.request += 1;
+                meter.anomaly.timeout += 1;
+                return;
+            }
+            LogMessageType::Response => meter.traffic.response += 1,
+            LogMessageType::Session => {
+                meter.traffic.request += 1;
+                meter.traffic.response += 1;
+            }
+            _ => return,
+        }
+        if base.head.status == L7ResponseStatus::ServerError {
+            meter.anomaly.server_error += 1;
+            meter.ntp.unsynchronized += 1;
+        }
+        if base.head.rrt > 0 {
+            let rrt = base.head.rrt.min(u32::MAX as u64) as u32;
+            meter.latency.rrt_max = meter.latency.rrt_max.max(rrt);
+            meter.latency.rrt_sum += base.head.rrt;
+            meter.latency.rrt_count += 1;
+        }
+        // 只有客户端模式的响应能计算偏差
+        if info.delay > 0 || info.offset != 0 {
+            meter.ntp.offset_sum += info.offset;
+            meter.ntp.offset_count += 1;
+            meter.ntp.offset_max = meter
+                .ntp
+                .offset_max
+                .max(info.offset.unsigned_abs().min(u32::MAX as u64) as u32);
+            stats.offset_square_sum += (info.offset as f64) * (info.offset as f64);
+        }
+    }
+
+    // 输出所有结束时间早于now - delay的分钟
+    pub fn flush(&mut self, now: Duration) {
+        if now.as_secs() == self.last_check {
+            return;
+        }
+        self.last_check = now.as_secs();
+        let deadline = now.saturating_sub(self.delay).as_secs();
+        while let Some((&minute, _)) = self.stash.iter().next() {
+            if minute + MINUTE > deadline {
+                break;
+            }
+            let stats = self.stash.remove(&minute).unwrap();
+            debug!("flush {} ntp metrics of minute {}", stats.len(), minute);
+            for (key, stats) in stats {
+                let _ = self
+                    .output
+                    .send(SendItem::Metrics(Box::new(Self::to_document(
+                        minute, key, stats,
+                    ))));
+            }
+        }
+    }
+
+    // 客户端只区分EPC，IP为全0
+    fn to_document(minute: u64, key: NtpMetricsKey, stats: NtpStats) -> Document {
+        let mut meter = stats.meter;
+        meter.ntp.jitter = stats.jitter();
+        let mut doc = Document::new(Meter::App(meter));
+        doc.timestamp = minute as u32;
+        doc.tagger = Tagger {
+            code: Code::IP_PATH | Code::L3_EPC_PATH | Code::VTAP_ID | Code::L7_PROTOCOL,
+            ip: if key.server_ip.is_ipv6() {
+                Ipv6Addr::UNSPECIFIED.into()
+            } else {
+                Ipv4Addr::UNSPECIFIED.into()
+            },
+            ip1: key.server_ip,
+            is_ipv6: key.server_ip.is_ipv6(),
+            l3_epc_id: key.client_epc as i16,
+            l3_epc_id1: key.server_epc as i16,
+            vtap_id: key.vtap_id,
+            l7_protocol: L7Protocol::Ntp,
+            ..Default::default()
+        };
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        meta_packet::MetaPacket,
+    };
+    use crate::debug::QueueDebugger;
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, NtpInfo};
+    use crate::utils::queue::bounded_with_debug;
+
+    fn log(timestamp: u64, msg_type: LogMessageType, offset: i64) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = "10.0.0.123".parse().unwrap();
+        packet.lookup_key.proto = IpProtocol::Udp;
+        packet.direction = PacketDirection::ClientToServer;
+        let head = AppProtoHead {
+            proto: L7Protocol::Ntp,
+            msg_type,
+            status: L7ResponseStatus::Ok,
+            rrt: 2000,
+            ..Default::default()
+        };
+        let base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        AppProtoLogsData::new(
+            base_info,
+            AppProtoLogsInfo::Ntp(NtpInfo {
+                offset,
+                delay: 2000,
+                ..Default::default()
+            }),
+        )
+    }
+
+    #[test]
+    fn offset_and_jitter() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut metrics = NtpMetrics::new(Duration::from_secs(60), s);
+
+        for offset in [-3000, 1000, 5000] {
+            metrics.add(&log(61, LogMessageType::Session, offset));
+        }
+        metrics.add(&log(62, LogMessageType::Request, 0));
+
+        metrics.flush(Duration::from_secs(181));
+        let doc = match r.recv(Some(Duration::from_millis(10))) {
+            Ok(SendItem::Metrics(doc)) => doc,
+            _ => panic!("no ntp metrics"),
+        };
+        assert_eq!(doc.tagger.l7_protocol, L7Protocol::Ntp);
+        assert_eq!(doc.tagger.ip1, "10.0.0.123".parse::<IpAddr>().unwrap());
+        match doc.meter {
+            Meter::App(m) => {
+                assert_eq!(m.traffic.request, 4);
+                assert_eq!(m.traffic.response, 3);
+                assert_eq!(m.anomaly.timeout, 1);
+                assert_eq!(m.ntp.offset_count, 3);
+                assert_eq!(m.ntp.offset_sum, 3000);
+                assert_eq!(m.ntp.offset_max, 5000);
+                // 偏差的均值为1000us，标准差为sqrt((16+0+16)/3)ms
+                assert_eq!(m.ntp.jitter, 3265);
+            }
+            _ => panic!("unexpected meter"),
+        }
+    }
+}
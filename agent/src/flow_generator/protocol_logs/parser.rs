@@ -30,19 +30,31 @@ use std::{
 use arc_swap::access::Access;
 use log::{debug, info, warn};
 
+use super::dns_metrics::DnsMetrics;
+use super::http_retry::HttpRetry;
+use super::ntp_metrics::NtpMetrics;
+use super::redis_metrics::RedisMetrics;
+use super::sampling::{new_sampler, L7LogSampler};
 use super::{
-    AppProtoHead, AppProtoLogsBaseInfo, AppProtoLogsData, AppProtoLogsInfo, DnsLog, DubboLog,
-    KafkaLog, LogMessageType, MqttLog, MysqlLog, RedisLog,
+    l4_protocol_bitmap, AppProtoHead, AppProtoHeadEnum, AppProtoLogsBaseInfo, AppProtoLogsData,
+    AppProtoLogsInfo, AppProtoLogsInfoEnum, ClickhouseLog, DetectCounter, DnsLog, DubboLog,
+    HttpInfo, IdleConnection, KafkaLog, L7ProtocolDetector, L7ResponseStatus, LogMessageType,
+    ModbusLog, MqttLog, MysqlLog, MysqlSessions, NtpLog, RadiusLog, RedisLog, S7Log, SnmpLog,
+    StreamContinuation, TdsLog, TlsLog,
 };
+#[cfg(target_os = "windows")]
+use crate::platform::ProcessSocketTable;
 use crate::{
     common::{
-        enums::{EthernetType, PacketDirection},
+        enums::{EthernetType, IpProtocol, PacketDirection},
         flow::{get_uniq_flow_id_in_one_minute, L7Protocol},
         MetaPacket, TaggedFlow,
     },
-    config::handler::LogParserAccess,
+    config::{handler::LogParserAccess, L7LogSamplingConfig, SessionPolicy},
+    debug::L7LogDebugger,
     flow_generator::{
-        error::Result,
+        error::{Error as LogError, Result},
+        perf::L7ParseErrorCounters,
         protocol_logs::{HttpLog, L7LogParse},
         FLOW_METRICS_PEER_DST, FLOW_METRICS_PEER_SRC,
     },
@@ -50,6 +62,7 @@ use crate::{
     sender::SendItem,
     utils::{
         net::MacAddr,
+        packet_pool::PacketBuffer,
         queue::{DebugSender, Error, Receiver},
         stats::{Counter, CounterType, CounterValue, RefCountable},
         LeakyBucket,
@@ -69,80 +82,109 @@ const THROTTLE_BUCKET: usize = 1 << THROTTLE_BUCKET_BITS; // 2^N。由于发送
 pub struct MetaAppProto {
     base_info: AppProtoLogsBaseInfo,
     direction: PacketDirection,
-    raw_proto_payload: Vec<u8>,
+    raw_proto_payload: PacketBuffer,
+    // 已生成的日志信息，不需要再解析payload，如空闲连接记录
+    info: Option<AppProtoLogsInfo>,
 }
 
 impl MetaAppProto {
-    pub fn new(
+    // 从流中获取的公共字段，IP和端口为流的方向
+    fn base_info(
         flow: &TaggedFlow,
-        meta_packet: &MetaPacket,
+        timestamp: Duration,
         head: AppProtoHead,
-        offset: u16,
-        packet_size: u16,
-    ) -> Option<Self> {
-        // 因metaPacket在logs处理时可能已经释放，需要copy metaPacket
-        // 此处，只拷贝待解析的协议payload部分, offset表示相对于协议payload的偏移
-        let raw_proto_payload = {
-            let payload = meta_packet.get_l4_payload()?;
-            let (offset, packet_size) = (offset as usize, packet_size as usize);
-            let max_payload_len = payload.len() - offset;
-            if max_payload_len > packet_size {
-                (&payload[offset..offset + packet_size]).to_vec()
-            } else {
-                (&payload[offset..offset + max_payload_len]).to_vec()
-            }
-        };
+        rrt_outlier: bool,
+    ) -> AppProtoLogsBaseInfo {
+        let flow_key = &flow.flow.flow_key;
         let mut base_info = AppProtoLogsBaseInfo {
-            start_time: meta_packet.lookup_key.timestamp,
-            end_time: meta_packet.lookup_key.timestamp,
+            start_time: timestamp,
+            end_time: timestamp,
             flow_id: flow.flow.flow_id,
-            vtap_id: flow.flow.flow_key.vtap_id,
-            tap_type: flow.flow.flow_key.tap_type,
-            tap_port: flow.flow.flow_key.tap_port,
+            vtap_id: flow_key.vtap_id,
+            tap_type: flow_key.tap_type,
+            tap_port: flow_key.tap_port,
+            capture_point: flow_key.capture_point,
             tap_side: flow.flow.tap_side,
             head,
-            protocol: meta_packet.lookup_key.proto,
+            protocol: flow_key.proto,
             is_vip_interface_src: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC]
                 .is_vip_interface,
             is_vip_interface_dst: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST]
                 .is_vip_interface,
             mac_src: MacAddr::ZERO,
             mac_dst: MacAddr::ZERO,
-            ip_src: meta_packet.lookup_key.src_ip,
-            ip_dst: meta_packet.lookup_key.dst_ip,
-            is_ipv6: meta_packet.lookup_key.eth_type == EthernetType::Ipv6,
-            port_src: meta_packet.lookup_key.src_port,
-            port_dst: meta_packet.lookup_key.dst_port,
-            l3_epc_id_src: 0,
-            l3_epc_id_dst: 0,
+            ip_src: flow_key.ip_src,
+            ip_dst: flow_key.ip_dst,
+            is_ipv6: flow_key.ip_src.is_ipv6(),
+            port_src: flow_key.port_src,
+            port_dst: flow_key.port_dst,
+            l3_epc_id_src: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].l3_epc_id,
+            l3_epc_id_dst: flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].l3_epc_id,
             req_tcp_seq: 0,
             resp_tcp_seq: 0,
             process_id_0: 0,
             process_id_1: 0,
-            process_kname_0: "".to_string(),
-            process_kname_1: "".to_string(),
+            process_kname_0: Arc::default(),
+            process_kname_1: Arc::default(),
             syscall_trace_id_request: 0,
             syscall_trace_id_response: 0,
             syscall_trace_id_thread_0: 0,
             syscall_trace_id_thread_1: 0,
             syscall_cap_seq_0: 0,
             syscall_cap_seq_1: 0,
+            container_id_0: Arc::default(),
+            container_id_1: Arc::default(),
+            pod_id_0: 0,
+            pod_id_1: 0,
+            business_labels_src: None,
+            business_labels_dst: None,
+            server_domain: String::new(),
+            rrt_outlier,
         };
         if flow.flow.tap_side == TapSide::Local {
-            base_info.mac_src = flow.flow.flow_key.mac_src;
-            base_info.mac_dst = flow.flow.flow_key.mac_dst;
+            base_info.mac_src = flow_key.mac_src;
+            base_info.mac_dst = flow_key.mac_dst;
         } else {
             if base_info.is_vip_interface_src {
-                base_info.mac_src = flow.flow.flow_key.mac_src;
+                base_info.mac_src = flow_key.mac_src;
             }
             if base_info.is_vip_interface_dst {
-                base_info.mac_dst = flow.flow.flow_key.mac_dst;
+                base_info.mac_dst = flow_key.mac_dst;
             }
         }
+        base_info
+    }
+
+    pub fn new(
+        flow: &TaggedFlow,
+        meta_packet: &MetaPacket,
+        head: AppProtoHead,
+        offset: u16,
+        packet_size: u16,
+        rrt_outlier: bool,
+    ) -> Option<Self> {
+        // 因metaPacket在logs处理时可能已经释放，需要copy metaPacket
+        // 此处，只拷贝待解析的协议payload部分, offset表示相对于协议payload的偏移
+        let raw_proto_payload = {
+            let payload = meta_packet.get_l4_payload()?;
+            let (offset, packet_size) = (offset as usize, packet_size as usize);
+            let max_payload_len = payload.len() - offset;
+            if max_payload_len > packet_size {
+                PacketBuffer::from_slice(&payload[offset..offset + packet_size])
+            } else {
+                PacketBuffer::from_slice(&payload[offset..offset + max_payload_len])
+            }
+        };
+        let lookup_key = &meta_packet.lookup_key;
+        let mut base_info = Self::base_info(flow, lookup_key.timestamp, head, rrt_outlier);
+        base_info.protocol = lookup_key.proto;
+        base_info.ip_src = lookup_key.src_ip;
+        base_info.ip_dst = lookup_key.dst_ip;
+        base_info.is_ipv6 = lookup_key.eth_type == EthernetType::Ipv6;
+        base_info.port_src = lookup_key.src_port;
+        base_info.port_dst = lookup_key.dst_port;
 
         if meta_packet.direction == PacketDirection::ClientToServer {
-            base_info.l3_epc_id_src = flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].l3_epc_id;
-            base_info.l3_epc_id_dst = flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].l3_epc_id;
             base_info.req_tcp_seq = meta_packet.tcp_data.seq;
         } else {
             base_info.l3_epc_id_src = flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].l3_epc_id;
@@ -154,8 +196,61 @@ impl MetaAppProto {
             base_info,
             direction: meta_packet.direction,
             raw_proto_payload,
+            info: None,
         })
     }
+
+    // HTTP/2空闲连接记录，不经过会话聚合直接输出
+    pub fn idle_connection(flow: &TaggedFlow, timestamp: Duration, idle: IdleConnection) -> Self {
+        let head = AppProtoHead {
+            proto: L7Protocol::Http2,
+            msg_type: LogMessageType::Session,
+            status: L7ResponseStatus::Ok,
+            code: 0,
+            rrt: 0,
+            version: 2,
+            ..Default::default()
+        };
+        Self {
+            base_info: Self::base_info(flow, timestamp, head, false),
+            direction: PacketDirection::ClientToServer,
+            raw_proto_payload: Default::default(),
+            info: Some(AppProtoLogsInfo::HttpV2(HttpInfo {
+                version: "2".into(),
+                idle_connection: Some(idle),
+                ..Default::default()
+            })),
+        }
+    }
+
+    // HTTP/1流式响应的持续记录，不经过会话聚合直接输出，IP和端口为流的方向
+    pub fn stream_continuation(
+        flow: &TaggedFlow,
+        timestamp: Duration,
+        code: u16,
+        stream: StreamContinuation,
+    ) -> Self {
+        let head = AppProtoHead {
+            proto: L7Protocol::Http1,
+            msg_type: LogMessageType::Session,
+            status: L7ResponseStatus::Ok,
+            code,
+            rrt: 0,
+            version: 1,
+            ..Default::default()
+        };
+        Self {
+            base_info: Self::base_info(flow, timestamp, head, false),
+            direction: PacketDirection::ClientToServer,
+            raw_proto_payload: Default::default(),
+            info: Some(AppProtoLogsInfo::HttpV1(HttpInfo {
+                version: "1.1".into(),
+                streaming: true,
+                stream_continuation: Some(stream),
+                ..Default::default()
+            })),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -165,6 +260,9 @@ pub struct SessionAggrCounter {
     merge: AtomicU64,
     cached: AtomicU64,
     throttle_drop: AtomicU64,
+    policy_drop: AtomicU64,
+    sampling_drop: AtomicU64,
+    pub parse_error: L7ParseErrorCounters,
 }
 
 // FIXME: counter not registered
@@ -196,6 +294,16 @@ impl RefCountable for SessionAggrCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.throttle_drop.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "policy-drop",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.policy_drop.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "sampling-drop",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.sampling_drop.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -212,6 +320,15 @@ struct SessionQueue {
     counter: Arc<SessionAggrCounter>,
     output_queue: DebugSender<SendItem>,
     config: LogParserAccess,
+    l7_log_debugger: Arc<L7LogDebugger>,
+
+    dns_metrics: DnsMetrics,
+    ntp_metrics: NtpMetrics,
+    redis_metrics: RedisMetrics,
+    http_retry: HttpRetry,
+    // 配置变化时重建采样器
+    sampling_config: L7LogSamplingConfig,
+    sampler: Option<Box<dyn L7LogSampler>>,
 }
 
 impl SessionQueue {
@@ -220,11 +337,23 @@ impl SessionQueue {
         output_queue: DebugSender<SendItem>,
         config: LogParserAccess,
         log_rate: Arc<LeakyBucket>,
+        l7_log_debugger: Arc<L7LogDebugger>,
+        metrics_output: DebugSender<SendItem>,
     ) -> Self {
         //l7_log_session_timeout 20s-300s ，window_size = 2-30，所以 SessionQueue.time_window 预分配内存
         let window_size =
             (config.load().l7_log_session_aggr_timeout.as_secs() / SLOT_WIDTH) as usize;
         let time_window = vec![HashMap::new(); window_size];
+        let dns_metrics = DnsMetrics::new(
+            config.load().l7_log_session_aggr_timeout,
+            metrics_output.clone(),
+        );
+        let ntp_metrics = NtpMetrics::new(
+            config.load().l7_log_session_aggr_timeout,
+            metrics_output.clone(),
+        );
+        let redis_metrics =
+            RedisMetrics::new(config.load().l7_log_session_aggr_timeout, metrics_output);
 
         Self {
             aggregate_start_time: Duration::ZERO,
@@ -237,9 +366,26 @@ impl SessionQueue {
 
             counter,
             output_queue,
+            l7_log_debugger,
+
+            dns_metrics,
+            ntp_metrics,
+            redis_metrics,
+            http_retry: HttpRetry::default(),
+            sampling_config: L7LogSamplingConfig::default(),
+            sampler: None,
         }
     }
 
+    fn flush_metrics(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        self.dns_metrics.flush(now);
+        self.ntp_metrics.flush(now);
+        self.redis_metrics.flush(now);
+    }
+
     fn flush_one_slot(&mut self) {
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -267,9 +413,27 @@ impl SessionQueue {
     //   - 收到响应，根据报文时间-RRT时间，找到对应的时间窗口，查找是否有匹配的请求
     //      - 若有， 则合并请求和响应(将响应的数据填入请求中，并修改请求的类型为会话)，释放当前响应，发送会话
     //      - 若没有, 则直接发送当前响应
-    fn aggregate_session_and_send(&mut self, item: AppProtoLogsData) {
+    fn aggregate_session_and_send(&mut self, mut item: AppProtoLogsData) {
         self.counter.receive.fetch_add(1, Ordering::Relaxed);
 
+        // 只需要请求或响应的协议不做合并，直接发送
+        let msg_type = item.base_info.head.msg_type;
+        match self.session_policy(item.base_info.head.proto) {
+            SessionPolicy::RequestOnly if msg_type == LogMessageType::Response => {
+                self.counter.policy_drop.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            SessionPolicy::ResponseOnly if msg_type == LogMessageType::Request => {
+                self.counter.policy_drop.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            SessionPolicy::RequestOnly | SessionPolicy::ResponseOnly => {
+                self.send(item);
+                return;
+            }
+            _ => (),
+        }
+
         let slot_time = if item.base_info.head.msg_type == LogMessageType::Response {
             // request = response - RRT
             (item.base_info.start_time - Duration::from_micros(item.base_info.head.rrt)).as_secs()
@@ -307,9 +471,14 @@ impl SessionQueue {
             slot = self.window_size - 1;
         }
 
+        let key = Self::calc_key(&item);
+        let retry_config = self.config.load().l7_log_http_retry.clone();
+        if retry_config.enabled {
+            self.http_retry
+                .on_request(&mut item, key, &mut time_window, retry_config.window);
+        }
         // 因为数组提前分配hashmap, slot < self.window_size 所以必然存在
         let map = time_window.get_mut(slot).unwrap();
-        let key = Self::calc_key(&item);
         match item.base_info.head.msg_type {
             LogMessageType::Request => {
                 // request，放入map
@@ -381,6 +550,11 @@ impl SessionQueue {
             AppProtoLogsInfo::HttpV1(h) => h.stream_id,
             AppProtoLogsInfo::HttpV2(h) => h.stream_id,
             AppProtoLogsInfo::Kafka(k) => k.correlation_id,
+            AppProtoLogsInfo::Radius(r) => r.identifier as u32,
+            AppProtoLogsInfo::Modbus(m) => m.transaction_id as u32,
+            AppProtoLogsInfo::S7(s) => s.pdu_ref as u32,
+            AppProtoLogsInfo::Snmp(s) => s.request_id,
+            AppProtoLogsInfo::Ntp(n) => n.session_id,
             _ => 0,
         };
         // key需保证流日志1分钟内唯一，由1分钟内唯一的flow_id和request_id组成
@@ -404,12 +578,120 @@ impl SessionQueue {
             Duration::from_secs(self.aggregate_start_time.as_secs() + n as u64 * SLOT_WIDTH);
     }
 
-    fn send(&mut self, item: AppProtoLogsData) {
+    // 目的端口为Elasticsearch的HTTP日志补充操作类型、索引名称及慢查询标记
+    fn amend_elasticsearch(&self, item: &mut AppProtoLogsData) {
+        let info = match &mut item.special_info {
+            AppProtoLogsInfo::HttpV1(h)
+            | AppProtoLogsInfo::HttpV2(h)
+            | AppProtoLogsInfo::HttpV1TLS(h) => h,
+            _ => return,
+        };
+        let config = self.config.load();
+        let es = &config.l7_log_elasticsearch;
+        if !es.ports.contains(&item.base_info.port_dst) {
+            return;
+        }
+        info.amend_elasticsearch(
+            Duration::from_micros(item.base_info.head.rrt),
+            es.slow_search_threshold,
+        );
+    }
+
+    fn amend_streaming(&self, item: &mut AppProtoLogsData) {
+        let info = match &mut item.special_info {
+            AppProtoLogsInfo::HttpV1(h)
+            | AppProtoLogsInfo::HttpV2(h)
+            | AppProtoLogsInfo::HttpV1TLS(h) => h,
+            _ => return,
+        };
+        info.amend_streaming(
+            Duration::from_micros(item.base_info.head.rrt),
+            self.config.load().l7_log_http_stream_interval,
+        );
+    }
+
+    fn session_policy(&self, proto: L7Protocol) -> SessionPolicy {
+        self.config
+            .load()
+            .l7_log_session_policies
+            .get(&proto)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // 未收到响应的请求视为超时
+    fn is_error(item: &AppProtoLogsData) -> bool {
+        let head = &item.base_info.head;
+        head.msg_type == LogMessageType::Request
+            || matches!(
+                head.status,
+                L7ResponseStatus::Error
+                    | L7ResponseStatus::ClientError
+                    | L7ResponseStatus::ServerError
+            )
+    }
+
+    fn sample(&mut self, item: &AppProtoLogsData) -> bool {
+        let config = self.config.load();
+        if self.sampling_config != config.l7_log_sampling {
+            self.sampling_config = config.l7_log_sampling.clone();
+            self.sampler = new_sampler(&self.sampling_config);
+        }
+        match self.sampler.as_mut() {
+            Some(sampler) => sampler.keep(item),
+            None => true,
+        }
+    }
+
+    fn send(&mut self, mut item: AppProtoLogsData) {
+        // 指标统计不受日志限速影响
+        if item.base_info.head.proto == L7Protocol::Dns {
+            let config = self.config.load();
+            if config.l7_log_dns.metrics_enabled {
+                self.dns_metrics.add(&item);
+                if config.l7_log_dns.metrics_only {
+                    return;
+                }
+            }
+        }
+        if item.base_info.head.proto == L7Protocol::Ntp {
+            self.ntp_metrics.add(&item);
+        }
+        if item.base_info.head.proto == L7Protocol::Redis {
+            let config = self.config.load();
+            if config.l7_log_redis.metrics_enabled {
+                self.redis_metrics
+                    .add(&item, config.l7_log_redis.max_key_patterns);
+                if config.l7_log_redis.metrics_only {
+                    return;
+                }
+            }
+        }
+        if self.config.load().l7_log_http_retry.enabled {
+            let key = Self::calc_key(&item);
+            self.http_retry.on_send(&mut item, key);
+        }
+        if self.session_policy(item.base_info.head.proto) == SessionPolicy::ErrorOnly
+            && !Self::is_error(&item)
+        {
+            self.counter.policy_drop.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if !self.sample(&item) {
+            self.counter.sampling_drop.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
         if !self.log_rate.acquire(1) {
             self.counter.throttle_drop.fetch_add(1, Ordering::Relaxed);
             return;
         }
 
+        self.amend_elasticsearch(&mut item);
+        self.amend_streaming(&mut item);
+        item.special_info
+            .truncate(&self.config.load().l7_log_truncate);
+
+        self.l7_log_debugger.record(&item);
         if let Err(Error::Terminated(..)) =
             self.output_queue.send(SendItem::L7FlowLog(Box::new(item)))
         {
@@ -424,30 +706,103 @@ impl SessionQueue {
     }
 }
 
+// 按detector的优先级依次尝试各应用协议，返回第一个识别成功的协议
+pub fn check_protocol(packet: &MetaPacket, detector: &L7ProtocolDetector) -> Option<L7Protocol> {
+    let mut bitmap = l4_protocol_bitmap(packet.lookup_key.proto);
+    detector.detect(&mut bitmap, packet, &mut DetectCounter::default())
+}
+
 #[derive(Default)]
-struct AppLogs {
+pub struct AppLogs {
     dns: DnsLog,
     http: HttpLog,
     mysql: MysqlLog,
+    mysql_sessions: MysqlSessions,
     redis: RedisLog,
     dubbo: DubboLog,
     kafka: KafkaLog,
     mqtt: MqttLog,
+    radius: RadiusLog,
+    modbus: ModbusLog,
+    s7: S7Log,
+    snmp: SnmpLog,
+    tds: TdsLog,
+    clickhouse: ClickhouseLog,
+    tls: TlsLog,
+    ntp: NtpLog,
 }
 
 impl AppLogs {
     pub fn new(config: &LogParserAccess) -> Self {
         Self {
+            dns: DnsLog::new(config),
             http: HttpLog::new(config, false),
             dubbo: DubboLog::new(config),
+            mysql: MysqlLog::new(config),
+            radius: RadiusLog::new(config),
             ..Default::default()
         }
     }
+
+    // 按应用协议解析payload，MQTT等一个包可能包含多个消息
+    pub fn parse(
+        &mut self,
+        protocol: L7Protocol,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<(AppProtoHeadEnum, AppProtoLogsInfoEnum)> {
+        let parser: &mut dyn L7LogParse = match protocol {
+            L7Protocol::Dns => &mut self.dns,
+            L7Protocol::Radius => &mut self.radius,
+            L7Protocol::Modbus => &mut self.modbus,
+            L7Protocol::S7 => &mut self.s7,
+            L7Protocol::Snmp => &mut self.snmp,
+            L7Protocol::Ntp => &mut self.ntp,
+            L7Protocol::Http1 | L7Protocol::Http2 => &mut self.http,
+            L7Protocol::Dubbo => &mut self.dubbo,
+            L7Protocol::Kafka => &mut self.kafka,
+            L7Protocol::Mqtt => &mut self.mqtt,
+            L7Protocol::Redis => &mut self.redis,
+            L7Protocol::Mysql => &mut self.mysql,
+            L7Protocol::Tds => &mut self.tds,
+            L7Protocol::Clickhouse => &mut self.clickhouse,
+            L7Protocol::Tls => &mut self.tls,
+            _ => return Err(LogError::L7ProtocolUnknown),
+        };
+        let head = parser.parse(payload, proto, direction)?;
+        Ok((head, parser.info()))
+    }
+
+    // MYSQL等解析依赖连接上的会话状态，解析前切换到flow_id对应的状态
+    pub fn parse_flow(
+        &mut self,
+        flow_id: u64,
+        timestamp: Duration,
+        protocol: L7Protocol,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<(AppProtoHeadEnum, AppProtoLogsInfoEnum)> {
+        if protocol == L7Protocol::Ntp {
+            // 时钟偏差的计算需要响应的采集时间
+            self.ntp.set_timestamp(timestamp);
+        }
+        if protocol != L7Protocol::Mysql {
+            return self.parse(protocol, payload, proto, direction);
+        }
+        self.mysql.set_session(self.mysql_sessions.take(flow_id));
+        self.mysql.set_timestamp(timestamp);
+        let result = self.parse(protocol, payload, proto, direction);
+        self.mysql_sessions.put(flow_id, self.mysql.take_session());
+        result
+    }
 }
 
 pub struct AppProtoLogsParser {
     input_queue: Arc<Receiver<Box<MetaAppProto>>>,
     output_queue: DebugSender<SendItem>,
+    metrics_output: DebugSender<SendItem>,
     id: u32,
     running: Arc<AtomicBool>,
     thread: Mutex<Option<JoinHandle<()>>>,
@@ -456,21 +811,28 @@ pub struct AppProtoLogsParser {
     config: LogParserAccess,
 
     log_rate: Arc<LeakyBucket>,
+    l7_log_debugger: Arc<L7LogDebugger>,
+
+    #[cfg(target_os = "windows")]
+    process_socket_table: Option<ProcessSocketTable>,
 }
 
 impl AppProtoLogsParser {
     pub fn new(
         input_queue: Receiver<Box<MetaAppProto>>,
         output_queue: DebugSender<SendItem>,
+        metrics_output: DebugSender<SendItem>,
         id: u32,
         config: LogParserAccess,
         log_rate: Arc<LeakyBucket>,
+        l7_log_debugger: Arc<L7LogDebugger>,
     ) -> (Self, Arc<SessionAggrCounter>) {
         let counter: Arc<SessionAggrCounter> = Default::default();
         (
             Self {
                 input_queue: Arc::new(input_queue),
                 output_queue,
+                metrics_output,
                 id,
                 running: Default::default(),
                 thread: Mutex::new(None),
@@ -478,11 +840,20 @@ impl AppProtoLogsParser {
                 l7_log_dynamic_is_updated: Arc::new(AtomicBool::new(false)),
                 config,
                 log_rate,
+                l7_log_debugger,
+                #[cfg(target_os = "windows")]
+                process_socket_table: None,
             },
             counter,
         )
     }
 
+    // Windows上没有eBPF，通过连接表为日志补充进程信息
+    #[cfg(target_os = "windows")]
+    pub fn set_process_socket_table(&mut self, table: ProcessSocketTable) {
+        self.process_socket_table = Some(table);
+    }
+
     pub fn l7_log_dynamic_config_updated(&self) {
         self.l7_log_dynamic_is_updated
             .store(true, Ordering::Relaxed);
@@ -494,6 +865,10 @@ impl AppProtoLogsParser {
         app_logs: &mut AppLogs,
     ) {
         if l7_log_dynamic_is_updated.swap(false, Ordering::Relaxed) {
+            info!(
+                "app proto logs parser apply l7 parser config version {}",
+                config.load().l7_parser.version
+            );
             app_logs.http.update_config(config);
             app_logs.dubbo.update_config(config);
         }
@@ -508,17 +883,28 @@ impl AppProtoLogsParser {
         let counter = self.counter.clone();
         let input_queue = self.input_queue.clone();
         let output_queue = self.output_queue.clone();
+        let metrics_output = self.metrics_output.clone();
 
         let config = self.config.clone();
         let l7_log_dynamic_is_updated = self.l7_log_dynamic_is_updated.clone();
         let log_rate = self.log_rate.clone();
+        let l7_log_debugger = self.l7_log_debugger.clone();
+        #[cfg(target_os = "windows")]
+        let process_socket_table = self.process_socket_table.clone();
 
         let thread = thread::spawn(move || {
-            let mut session_queue =
-                SessionQueue::new(counter, output_queue, config.clone(), log_rate);
+            let mut session_queue = SessionQueue::new(
+                counter.clone(),
+                output_queue,
+                config.clone(),
+                log_rate,
+                l7_log_debugger,
+                metrics_output,
+            );
             let mut app_logs = AppLogs::new(&config);
 
             while running.load(Ordering::Relaxed) {
+                session_queue.flush_metrics();
                 match input_queue.recv_n(QUEUE_BATCH_SIZE, Some(RCV_TIMEOUT)) {
                     Ok(app_protos) => {
                         Self::update_l7_log_dynamic_config(
@@ -531,11 +917,18 @@ impl AppProtoLogsParser {
                                 Ok(a) => a,
                                 Err(e) => {
                                     debug!("{}", e);
+                                    counter.parse_error.record(&e);
                                     continue;
                                 }
                             };
 
                             for proto_log in proto_logs {
+                                #[cfg(target_os = "windows")]
+                                let mut proto_log = proto_log;
+                                #[cfg(target_os = "windows")]
+                                if let Some(table) = process_socket_table.as_ref() {
+                                    table.fill(&mut proto_log.base_info);
+                                }
                                 session_queue.aggregate_session_and_send(proto_log);
                             }
                         }
@@ -567,6 +960,9 @@ impl AppProtoLogsParser {
         mut app_proto: MetaAppProto,
         app_logs: &mut AppLogs,
     ) -> Result<Vec<AppProtoLogsData>> {
+        if let Some(info) = app_proto.info.take() {
+            return Ok(vec![AppProtoLogsData::new(app_proto.base_info, info)]);
+        }
         // 应用流日志只存C2S方向,所以非C2S方向需要转换方向
         if app_proto.base_info.head.msg_type != LogMessageType::Request {
             let base_info = &mut app_proto.base_info;
@@ -574,96 +970,291 @@ impl AppProtoLogsParser {
             swap(&mut base_info.ip_src, &mut base_info.ip_dst);
             swap(&mut base_info.l3_epc_id_src, &mut base_info.l3_epc_id_dst);
         }
-        let proto_log = match app_proto.base_info.head.proto {
-            L7Protocol::Dns => {
-                app_logs.dns.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
+        let (_, special_info) = app_logs.parse_flow(
+            app_proto.base_info.flow_id,
+            app_proto.base_info.start_time,
+            app_proto.base_info.head.proto,
+            app_proto.raw_proto_payload.as_slice(),
+            app_proto.base_info.protocol,
+            app_proto.direction,
+        )?;
+        let base_info = app_proto.base_info;
+        let proto_log = if base_info.head.proto == L7Protocol::Mqtt {
+            let mut proto_log = vec![];
+            for v in special_info.into_iter() {
+                proto_log.extend(app_logs.mqtt.amend_mqtt_proto_log_and_generate_log_data(
+                    v,
+                    base_info.clone(),
                     app_proto.direction,
-                )?;
-                let special_info = app_logs.dns.info();
-                let base_info = app_proto.base_info;
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
+                )?);
             }
-            L7Protocol::Http1 | L7Protocol::Http2 => {
-                app_logs.http.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
-                let special_info = app_logs.http.info();
-                let base_info = app_proto.base_info;
+            proto_log
+        } else {
+            vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
+        };
 
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
-            }
-            L7Protocol::Dubbo => {
-                app_logs.dubbo.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
-                let special_info = app_logs.dubbo.info();
-                let base_info = app_proto.base_info;
+        Ok(proto_log)
+    }
+}
 
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
-            }
-            L7Protocol::Kafka => {
-                app_logs.kafka.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
-                let special_info = app_logs.kafka.info();
-                let base_info = app_proto.base_info;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
-            }
-            L7Protocol::Mqtt => {
-                app_logs.mqtt.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
+    use std::net::Ipv4Addr;
 
-                let special_info = app_logs.mqtt.info();
-                let base_info = app_proto.base_info;
+    use arc_swap::{access::Map, ArcSwap};
 
-                let result = special_info
-                    .into_iter()
-                    .map(|v| {
-                        app_logs
-                            .mqtt
-                            .amend_mqtt_proto_log_and_generate_log_data(v, base_info.clone())
-                    })
-                    .collect::<Result<Vec<_>>>()?;
+    use crate::config::handler::{LogParserConfig, ModuleConfig};
+    use crate::debug::QueueDebugger;
+    use crate::flow_generator::protocol_logs::DnsInfo;
+    use crate::utils::queue::bounded_with_debug;
 
-                result
-            }
-            L7Protocol::Redis => {
-                app_logs.redis.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
-                let special_info = app_logs.redis.info();
-                let base_info = app_proto.base_info;
+    #[test]
+    fn idle_connection() {
+        let mut flow = TaggedFlow::default();
+        let flow_key = &mut flow.flow.flow_key;
+        flow_key.ip_src = Ipv4Addr::new(10, 0, 0, 1).into();
+        flow_key.ip_dst = Ipv4Addr::new(10, 0, 0, 2).into();
+        flow_key.port_src = 34567;
+        flow_key.port_dst = 50051;
+        flow_key.proto = IpProtocol::Tcp;
+        flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].l3_epc_id = 1;
+        flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].l3_epc_id = 2;
 
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
-            }
-            L7Protocol::Mysql => {
-                app_logs.mysql.parse(
-                    app_proto.raw_proto_payload.as_slice(),
-                    app_proto.base_info.protocol,
-                    app_proto.direction,
-                )?;
-                let special_info = app_logs.mysql.info();
-                let base_info = app_proto.base_info;
+        let idle = IdleConnection {
+            connection_age: 600_000_000,
+            streams_opened: 3,
+            last_activity: 1_000_000,
+        };
+        let app_proto = MetaAppProto::idle_connection(&flow, Duration::from_secs(601), idle);
+        let logs = AppProtoLogsParser::parse_log(app_proto, &mut AppLogs::default()).unwrap();
+        assert_eq!(logs.len(), 1);
+        let base_info = &logs[0].base_info;
+        assert_eq!(base_info.head.msg_type, LogMessageType::Session);
+        assert_eq!(base_info.head.proto, L7Protocol::Http2);
+        assert_eq!(base_info.port_dst, 50051);
+        assert_eq!(base_info.l3_epc_id_src, 1);
+        assert_eq!(base_info.l3_epc_id_dst, 2);
+        match &logs[0].special_info {
+            AppProtoLogsInfo::HttpV2(info) => assert_eq!(info.idle_connection, Some(idle)),
+            _ => unreachable!(),
+        }
+    }
 
-                vec![AppProtoLogsData::new(base_info, special_info.into_inner())]
+    #[test]
+    fn mqtt_topic_summary() {
+        let mut flow = TaggedFlow::default();
+        flow.flow.flow_id = 1;
+        flow.flow.flow_key.proto = IpProtocol::Tcp;
+        let mqtt_proto = |seconds, msg_type, payload: &[u8]| {
+            let head = AppProtoHead {
+                proto: L7Protocol::Mqtt,
+                msg_type,
+                ..Default::default()
+            };
+            MetaAppProto {
+                base_info: MetaAppProto::base_info(
+                    &flow,
+                    Duration::from_secs(seconds),
+                    head,
+                    false,
+                ),
+                direction: PacketDirection::ClientToServer,
+                raw_proto_payload: PacketBuffer::from_slice(payload),
+                info: None,
             }
+        };
+        let mqtt_info = |log: &AppProtoLogsData| match &log.special_info {
+            AppProtoLogsInfo::Mqtt(info) => info.clone(),
             _ => unreachable!(),
         };
 
-        Ok(proto_log)
+        let mut app_logs = AppLogs::default();
+        let packets: [(u64, LogMessageType, &[u8]); 5] = [
+            // v5 CONNECT, client id "c1"
+            (
+                1,
+                LogMessageType::Request,
+                &[
+                    0x10, 15, 0, 4, b'M', b'Q', b'T', b'T', 5, 0x02, 0, 60, 0, 0, 2, b'c', b'1',
+                ],
+            ),
+            // SUBSCRIBE "a/#"
+            (
+                1,
+                LogMessageType::Request,
+                &[0x82, 9, 0, 1, 0, 0, 3, b'a', b'/', b'#', 0x01],
+            ),
+            // PUBLISH "a/b"并设置主题别名1
+            (
+                2,
+                LogMessageType::Response,
+                &[0x30, 10, 0, 3, b'a', b'/', b'b', 3, 0x23, 0, 1, b'x'],
+            ),
+            // 只带主题别名1的PUBLISH，超过汇总间隔
+            (
+                70,
+                LogMessageType::Response,
+                &[0x30, 7, 0, 0, 3, 0x23, 0, 1, b'y'],
+            ),
+            (71, LogMessageType::Session, &[0xE0, 0]),
+        ];
+        let mut logs = vec![];
+        for (seconds, msg_type, payload) in packets {
+            logs.push(
+                AppProtoLogsParser::parse_log(
+                    mqtt_proto(seconds, msg_type, payload),
+                    &mut app_logs,
+                )
+                .unwrap(),
+            );
+        }
+
+        let subscribe = mqtt_info(&logs[1][0]);
+        assert!(subscribe.subscribe_topics.unwrap()[0].wildcard);
+        assert_eq!(logs[2].len(), 1);
+        assert_eq!(mqtt_info(&logs[2][0]).topic_alias, 1);
+
+        assert_eq!(logs[3].len(), 2);
+        assert_eq!(mqtt_info(&logs[3][0]).publish_topic.as_deref(), Some("a/b"));
+        assert_eq!(logs[3][1].base_info.head.msg_type, LogMessageType::Session);
+        let summary = mqtt_info(&logs[3][1]);
+        assert_eq!(summary.pkt_type.to_string(), "TOPIC_SUMMARY");
+        assert_eq!(summary.client_id.as_deref(), Some("c1"));
+        let counts = summary.topic_counts.unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!((counts[0].name.as_str(), counts[0].count), ("a/b", 2));
+
+        // 汇总后没有新的PUBLISH，断开连接时不再输出
+        assert_eq!(logs[4].len(), 1);
+    }
+
+    #[test]
+    fn rrt_breakdown() {
+        let mut head = AppProtoHead {
+            msg_type: LogMessageType::Response,
+            rrt: 5000,
+            ..Default::default()
+        };
+        head.split_rrt(800);
+        assert_eq!((head.network_time, head.server_time), (800, 4200));
+
+        // RTT超过rrt时全部计为网络时延
+        let mut short = head.clone();
+        short.rrt = 500;
+        short.split_rrt(800);
+        assert_eq!((short.network_time, short.server_time), (500, 0));
+
+        let flow = TaggedFlow::default();
+        let request = AppProtoHead {
+            msg_type: LogMessageType::Request,
+            ..Default::default()
+        };
+        let mut session = MetaAppProto::base_info(&flow, Duration::from_secs(1), request, false);
+        session.req_tcp_seq = 100;
+        let mut response = MetaAppProto::base_info(&flow, Duration::from_secs(2), head, false);
+        response.resp_tcp_seq = 200;
+        session.merge(response);
+        assert_eq!(session.head.msg_type, LogMessageType::Session);
+        assert_eq!(session.head.rrt, 5000);
+        assert_eq!(session.head.network_time, 800);
+        assert_eq!(session.head.server_time, 4200);
+    }
+
+    fn session_queue(
+        policies: &[(L7Protocol, SessionPolicy)],
+    ) -> (SessionQueue, Receiver<SendItem>) {
+        let mut module_config = ModuleConfig::default();
+        module_config.log_parser.l7_log_session_aggr_timeout = Duration::from_secs(120);
+        module_config.log_parser.l7_log_session_policies = policies.iter().copied().collect();
+        let config: LogParserAccess = Map::new(
+            Arc::new(ArcSwap::from_pointee(module_config)),
+            |config| -> &LogParserConfig { &config.log_parser },
+        );
+        let queue_debugger = QueueDebugger::new();
+        let (output, receiver, _) = bounded_with_debug(16, "", &queue_debugger);
+        let (metrics_output, _, _) = bounded_with_debug(16, "", &queue_debugger);
+        let queue = SessionQueue::new(
+            Arc::default(),
+            output,
+            config,
+            Arc::new(LeakyBucket::new(None)),
+            Arc::new(L7LogDebugger::new(0)),
+            metrics_output,
+        );
+        (queue, receiver)
+    }
+
+    fn log(
+        flow_id: u64,
+        proto: L7Protocol,
+        msg_type: LogMessageType,
+        status: L7ResponseStatus,
+    ) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(1000);
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        let head = AppProtoHead {
+            proto,
+            msg_type,
+            status,
+            rrt: if msg_type == LogMessageType::Response {
+                100
+            } else {
+                0
+            },
+            ..Default::default()
+        };
+        let mut base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        base_info.flow_id = flow_id;
+        let info = match proto {
+            L7Protocol::Dns => AppProtoLogsInfo::Dns(DnsInfo::default()),
+            _ => AppProtoLogsInfo::HttpV1(HttpInfo::default()),
+        };
+        AppProtoLogsData::new(base_info, info)
+    }
+
+    fn sent(receiver: &Receiver<SendItem>) -> Vec<(L7Protocol, LogMessageType)> {
+        let mut logs = vec![];
+        while let Ok(item) = receiver.recv(Some(Duration::from_millis(10))) {
+            if let SendItem::L7FlowLog(log) = item {
+                logs.push((log.base_info.head.proto, log.base_info.head.msg_type));
+            }
+        }
+        logs
+    }
+
+    #[test]
+    fn session_policy() {
+        let (mut queue, receiver) = session_queue(&[
+            (L7Protocol::Dns, SessionPolicy::RequestOnly),
+            (L7Protocol::Http1, SessionPolicy::ErrorOnly),
+        ]);
+        let ok = L7ResponseStatus::Ok;
+
+        // 请求不缓存直接发送，响应丢弃
+        queue.aggregate_session_and_send(log(1, L7Protocol::Dns, LogMessageType::Request, ok));
+        queue.aggregate_session_and_send(log(1, L7Protocol::Dns, LogMessageType::Response, ok));
+        assert_eq!(
+            sent(&receiver),
+            vec![(L7Protocol::Dns, LogMessageType::Request)]
+        );
+
+        // 正常的会话丢弃，异常的会话发送
+        queue.aggregate_session_and_send(log(2, L7Protocol::Http1, LogMessageType::Request, ok));
+        queue.aggregate_session_and_send(log(2, L7Protocol::Http1, LogMessageType::Response, ok));
+        queue.aggregate_session_and_send(log(3, L7Protocol::Http1, LogMessageType::Request, ok));
+        queue.aggregate_session_and_send(log(
+            3,
+            L7Protocol::Http1,
+            LogMessageType::Response,
+            L7ResponseStatus::ServerError,
+        ));
+        assert_eq!(
+            sent(&receiver),
+            vec![(L7Protocol::Http1, LogMessageType::Session)]
+        );
+        assert_eq!(queue.counter.policy_drop.load(Ordering::Relaxed), 2);
     }
 }
@@ -0,0 +1,352 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use serde::Serialize;
+
+use super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::config::handler::LogParserAccess;
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::{bytes::read_u16_be, net::parse_ip_slice},
+};
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct RadiusInfo {
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub identifier: u8,
+    // 请求的Code名称，如：Access-Request
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub code: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub user_name: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub nas_ip: String,
+    // 响应的Code名称，如：Access-Accept
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub response: String,
+}
+
+impl RadiusInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.response = other.response;
+    }
+}
+
+impl L7SessionId for RadiusInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.identifier as u32)
+    }
+}
+
+impl From<RadiusInfo> for flow_log::RadiusInfo {
+    fn from(f: RadiusInfo) -> Self {
+        flow_log::RadiusInfo {
+            identifier: f.identifier as u32,
+            code: f.code,
+            user_name: f.user_name,
+            nas_ip: f.nas_ip,
+            response: f.response,
+        }
+    }
+}
+
+fn code_name(code: u8) -> &'static str {
+    match code {
+        RADIUS_ACCESS_REQUEST => "Access-Request",
+        RADIUS_ACCESS_ACCEPT => "Access-Accept",
+        RADIUS_ACCESS_REJECT => "Access-Reject",
+        RADIUS_ACCOUNTING_REQUEST => "Accounting-Request",
+        RADIUS_ACCOUNTING_RESPONSE => "Accounting-Response",
+        RADIUS_ACCESS_CHALLENGE => "Access-Challenge",
+        _ => "",
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RadiusLog {
+    info: RadiusInfo,
+    user_name_masked: bool,
+
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u8,
+}
+
+impl RadiusLog {
+    pub fn new(config: &LogParserAccess) -> Self {
+        Self {
+            user_name_masked: config.load().l7_log_user_name_masked,
+            ..Default::default()
+        }
+    }
+
+    fn reset_logs(&mut self) {
+        self.info = RadiusInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    // 仅保留首字符，避免日志中出现完整的用户名
+    fn mask_user_name(name: &str) -> String {
+        match name.chars().next() {
+            Some(c) => format!("{}***", c),
+            None => String::new(),
+        }
+    }
+
+    // payload可能因截取长度被截断，不完整的属性直接忽略
+    fn decode_attributes(&mut self, payload: &[u8]) {
+        let mut offset = 0;
+        while offset + RADIUS_ATTR_HEADER_LEN <= payload.len() {
+            let attr_type = payload[offset];
+            let attr_len = payload[offset + 1] as usize;
+            if attr_len < RADIUS_ATTR_HEADER_LEN || offset + attr_len > payload.len() {
+                return;
+            }
+            let value = &payload[offset + RADIUS_ATTR_HEADER_LEN..offset + attr_len];
+            match attr_type {
+                RADIUS_ATTR_USER_NAME => {
+                    let name = String::from_utf8_lossy(value);
+                    self.info.user_name = if self.user_name_masked {
+                        Self::mask_user_name(&name)
+                    } else {
+                        name.into_owned()
+                    };
+                }
+                RADIUS_ATTR_NAS_IP_ADDRESS | RADIUS_ATTR_NAS_IPV6_ADDRESS => {
+                    if let Some(ip) = parse_ip_slice(value) {
+                        self.info.nas_ip = ip.to_string();
+                    }
+                }
+                _ => (),
+            }
+            offset += attr_len;
+        }
+    }
+
+    fn decode_payload(&mut self, payload: &[u8]) -> Result<AppProtoHead> {
+        if payload.len() < RADIUS_HEADER_LEN {
+            let err_msg = format!("radius payload length too short: {}", payload.len());
+            return Err(Error::RadiusLogParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(RADIUS_HEADER_LEN, payload.len()),
+                err_msg,
+            ));
+        }
+        let code = payload[0];
+        let length = read_u16_be(&payload[RADIUS_LENGTH_OFFSET..]) as usize;
+        if length < RADIUS_HEADER_LEN || length > RADIUS_MAX_LEN {
+            let err_msg = format!("radius length invalid: {}", length);
+            return Err(Error::RadiusLogParseFailed(
+                ParseContext::new(ParseStage::Header).at(RADIUS_LENGTH_OFFSET),
+                err_msg,
+            ));
+        }
+
+        match code {
+            RADIUS_ACCESS_REQUEST | RADIUS_ACCOUNTING_REQUEST => {
+                self.msg_type = LogMessageType::Request;
+                self.info.code = code_name(code).to_string();
+            }
+            RADIUS_ACCESS_ACCEPT | RADIUS_ACCOUNTING_RESPONSE | RADIUS_ACCESS_CHALLENGE => {
+                self.msg_type = LogMessageType::Response;
+                self.status = L7ResponseStatus::Ok;
+                self.status_code = code;
+                self.info.response = code_name(code).to_string();
+            }
+            RADIUS_ACCESS_REJECT => {
+                self.msg_type = LogMessageType::Response;
+                self.status = L7ResponseStatus::ClientError;
+                self.status_code = code;
+                self.info.response = code_name(code).to_string();
+            }
+            _ => {
+                let err_msg = format!("radius code invalid: {}", code);
+                return Err(Error::RadiusLogParseFailed(
+                    ParseStage::Header.into(),
+                    err_msg,
+                ));
+            }
+        }
+        self.info.identifier = payload[RADIUS_IDENTIFIER_OFFSET];
+        self.decode_attributes(&payload[RADIUS_HEADER_LEN..length.min(payload.len())]);
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Radius,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code as u16,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for RadiusLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        _direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        self.reset_logs();
+        if proto != IpProtocol::Udp {
+            let err_msg = format!("radius ip protocol invalid: {:?}", proto);
+            return Err(Error::RadiusLogParseFailed(
+                ParseStage::Header.into(),
+                err_msg,
+            ));
+        }
+        Ok(AppProtoHeadEnum::Single(self.decode_payload(payload)?))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Radius(self.info.clone()))
+    }
+}
+
+// 通过请求来识别RADIUS，协议识别时payload未截断，要求长度字段与payload一致
+pub fn radius_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Udp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Radius));
+        return false;
+    }
+    let is_radius_port = |port| port == RADIUS_AUTH_PORT || port == RADIUS_ACCT_PORT;
+    if !is_radius_port(packet.lookup_key.dst_port) {
+        if !is_radius_port(packet.lookup_key.src_port) {
+            *bitmap &= !(1 << u8::from(L7Protocol::Radius));
+        }
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    if payload.len() < RADIUS_HEADER_LEN
+        || read_u16_be(&payload[RADIUS_LENGTH_OFFSET..]) as usize != payload.len()
+    {
+        *bitmap &= !(1 << u8::from(L7Protocol::Radius));
+        return false;
+    }
+
+    let mut radius = RadiusLog::default();
+    let ret = radius.parse(payload, packet.lookup_key.proto, packet.direction);
+    if ret.is_err() {
+        *bitmap &= !(1 << u8::from(L7Protocol::Radius));
+        return false;
+    }
+    radius.msg_type == LogMessageType::Request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(code: u8, identifier: u8, attrs: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut payload = vec![code, identifier, 0, 0];
+        payload.extend_from_slice(&[0; 16]);
+        for (t, v) in attrs {
+            payload.push(*t);
+            payload.push((v.len() + RADIUS_ATTR_HEADER_LEN) as u8);
+            payload.extend_from_slice(v);
+        }
+        let len = payload.len() as u16;
+        payload[RADIUS_LENGTH_OFFSET..RADIUS_LENGTH_OFFSET + 2].copy_from_slice(&len.to_be_bytes());
+        payload
+    }
+
+    #[test]
+    fn parse_access() {
+        let request = packet(
+            RADIUS_ACCESS_REQUEST,
+            7,
+            &[
+                (RADIUS_ATTR_USER_NAME, b"alice"),
+                (RADIUS_ATTR_NAS_IP_ADDRESS, &[10, 0, 0, 1]),
+            ],
+        );
+        let mut radius = RadiusLog::default();
+        let head = radius
+            .parse(&request, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap();
+        let head = head.into_iter().next().unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(
+            radius.info,
+            RadiusInfo {
+                identifier: 7,
+                code: "Access-Request".into(),
+                user_name: "alice".into(),
+                nas_ip: "10.0.0.1".into(),
+                ..Default::default()
+            }
+        );
+
+        radius.user_name_masked = true;
+        let _ = radius.parse(&request, IpProtocol::Udp, PacketDirection::ClientToServer);
+        assert_eq!(radius.info.user_name, "a***");
+
+        let reject = packet(RADIUS_ACCESS_REJECT, 7, &[]);
+        let head = radius
+            .parse(&reject, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap();
+        let head = head.into_iter().next().unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, RADIUS_ACCESS_REJECT as u16);
+        assert_eq!(radius.info.response, "Access-Reject");
+    }
+
+    #[test]
+    fn parse_truncated_and_invalid() {
+        let mut request = packet(
+            RADIUS_ACCOUNTING_REQUEST,
+            1,
+            &[(RADIUS_ATTR_NAS_IP_ADDRESS, &[10, 0, 0, 1])],
+        );
+        // 截断的属性被忽略
+        request.truncate(request.len() - 2);
+        let mut radius = RadiusLog::default();
+        assert!(radius
+            .parse(&request, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .is_ok());
+        assert_eq!(radius.info.code, "Accounting-Request");
+        assert_eq!(radius.info.nas_ip, "");
+
+        let unknown = packet(0xff, 1, &[]);
+        assert!(radius
+            .parse(&unknown, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .is_err());
+        assert!(radius
+            .parse(
+                &request[..10],
+                IpProtocol::Udp,
+                PacketDirection::ClientToServer
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,360 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use log::debug;
+
+use super::{
+    AppProtoLogsBaseInfo, AppProtoLogsData, AppProtoLogsInfo, L7ResponseStatus, LogMessageType,
+};
+use crate::common::{
+    enums::{IpProtocol, TapType},
+    flow::L7Protocol,
+    tap_port::TapPort,
+};
+use crate::metric::{
+    document::{Code, Direction, Document, Tagger},
+    meter::{AppMeter, Meter},
+};
+use crate::sender::SendItem;
+use crate::utils::queue::DebugSender;
+
+const MINUTE: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RedisMetricsKey {
+    vtap_id: u16,
+    tap_type: TapType,
+    tap_port: TapPort,
+    // 日志的采集位置，编码时转换为tap_side
+    direction: Direction,
+    protocol: IpProtocol,
+    client_epc: i32,
+    server_ip: IpAddr,
+    server_epc: i32,
+    server_port: u16,
+    // 超出模式数上限时为空
+    key_pattern: String,
+}
+
+// 按(客户端EPC, Redis服务端, key模式)聚合Redis日志，生成分钟粒度的请求数及时延指标，
+// 无需发送每条命令的日志即可发现热点key族
+pub struct RedisMetrics {
+    // 会话聚合会延迟发送日志，一个分钟在超过该延迟后才输出
+    delay: Duration,
+    last_check: u64,
+    stash: BTreeMap<u64, HashMap<RedisMetricsKey, AppMeter>>,
+    output: DebugSender<SendItem>,
+}
+
+impl RedisMetrics {
+    pub fn new(delay: Duration, output: DebugSender<SendItem>) -> Self {
+        Self {
+            delay,
+            last_check: 0,
+            stash: BTreeMap::new(),
+            output,
+        }
+    }
+
+    // 按日志方向确定服务端，只有响应的日志没有命令，不统计
+    fn server_side(base: &AppProtoLogsBaseInfo) -> Option<(IpAddr, i32, u16, i32)> {
+        match base.head.msg_type {
+            // 请求及合并后的会话已转换为客户端到服务端的方向
+            LogMessageType::Request | LogMessageType::Session => Some((
+                base.ip_dst,
+                base.l3_epc_id_dst,
+                base.port_dst,
+                base.l3_epc_id_src,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn add(&mut self, log: &AppProtoLogsData, max_key_patterns: usize) {
+        let base = &log.base_info;
+        let info = match &log.special_info {
+            AppProtoLogsInfo::Redis(info) if base.head.proto == L7Protocol::Redis => info,
+            _ => return,
+        };
+        let (server_ip, server_epc, server_port, client_epc) = match Self::server_side(base) {
+            Some(s) => s,
+            None => return,
+        };
+        let key_pattern = match info.key_pattern() {
+            Some(p) => p,
+            None => return,
+        };
+        let mut key = RedisMetricsKey {
+            vtap_id: base.vtap_id,
+            tap_type: base.tap_type,
+            tap_port: base.tap_port,
+            direction: Direction::from(base.tap_side),
+            protocol: base.protocol,
+            client_epc,
+            server_ip,
+            server_epc,
+            server_port,
+            key_pattern,
+        };
+        let minute = base.start_time.as_secs() / MINUTE * MINUTE;
+        let stats = self.stash.entry(minute).or_default();
+        if stats.len() >= max_key_patterns && !stats.contains_key(&key) {
+            key.key_pattern.clear();
+        }
+        let meter = stats.entry(key).or_default();
+
+        match base.head.msg_type {
+            // 会话聚合结束时仍未匹配到响应的请求计为超时
+            LogMessageType::Request => {
+                meter.traffic.request += 1;
+                meter.anomaly.timeout += 1;
+                return;
+            }
+            LogMessageType::Session => {
+                meter.traffic.request += 1;
+                meter.traffic.response += 1;
+            }
+            _ => return,
+        }
+        if base.head.status == L7ResponseStatus::ServerError {
+            meter.anomaly.server_error += 1;
+        }
+        if base.head.rrt > 0 {
+            let rrt = base.head.rrt.min(u32::MAX as u64) as u32;
+            meter.latency.rrt_max = meter.latency.rrt_max.max(rrt);
+            meter.latency.rrt_sum += base.head.rrt;
+            meter.latency.rrt_count += 1;
+        }
+    }
+
+    // 输出所有结束时间早于now - delay的分钟
+    pub fn flush(&mut self, now: Duration) {
+        if now.as_secs() == self.last_check {
+            return;
+        }
+        self.last_check = now.as_secs();
+        let deadline = now.saturating_sub(self.delay).as_secs();
+        while let Some((&minute, _)) = self.stash.iter().next() {
+            if minute + MINUTE > deadline {
+                break;
+            }
+            let stats = self.stash.remove(&minute).unwrap();
+            debug!("flush {} redis metrics of minute {}", stats.len(), minute);
+            for (key, meter) in stats {
+                let _ = self
+                    .output
+                    .send(SendItem::Metrics(Box::new(Self::to_document(
+                        minute, key, meter,
+                    ))));
+            }
+        }
+    }
+
+    // 客户端只区分EPC，IP为全0
+    fn to_document(minute: u64, key: RedisMetricsKey, meter: AppMeter) -> Document {
+        let mut doc = Document::new(Meter::App(meter));
+        doc.timestamp = minute as u32;
+        doc.tagger = Tagger {
+            code: Code::IP_PATH
+                | Code::L3_EPC_PATH
+                | Code::PROTOCOL
+                | Code::SERVER_PORT
+                | Code::DIRECTION
+                | Code::TAP_TYPE
+                | Code::TAP_PORT
+                | Code::VTAP_ID
+                | Code::L7_PROTOCOL
+                | Code::KEY_PATTERN,
+            ip: if key.server_ip.is_ipv6() {
+                Ipv6Addr::UNSPECIFIED.into()
+            } else {
+                Ipv4Addr::UNSPECIFIED.into()
+            },
+            ip1: key.server_ip,
+            is_ipv6: key.server_ip.is_ipv6(),
+            l3_epc_id: key.client_epc as i16,
+            l3_epc_id1: key.server_epc as i16,
+            protocol: key.protocol,
+            server_port: key.server_port,
+            direction: key.direction,
+            tap_type: key.tap_type,
+            tap_port: key.tap_port,
+            vtap_id: key.vtap_id,
+            l7_protocol: L7Protocol::Redis,
+            key_pattern: key.key_pattern,
+            ..Default::default()
+        };
+        doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        meta_packet::MetaPacket,
+    };
+    use crate::debug::QueueDebugger;
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, RedisInfo};
+    use crate::utils::queue::{bounded_with_debug, Receiver};
+
+    fn log(timestamp: u64, msg_type: LogMessageType, request: &str, rrt: u64) -> AppProtoLogsData {
+        log_with_direction(
+            timestamp,
+            msg_type,
+            request,
+            rrt,
+            PacketDirection::ClientToServer,
+        )
+    }
+
+    fn log_with_direction(
+        timestamp: u64,
+        msg_type: LogMessageType,
+        request: &str,
+        rrt: u64,
+        direction: PacketDirection,
+    ) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        let (client, server) = (("10.0.0.1", 50000), ("10.0.0.79", 6379));
+        let (src, dst) = if direction == PacketDirection::ClientToServer {
+            (client, server)
+        } else {
+            (server, client)
+        };
+        packet.lookup_key.src_ip = src.0.parse().unwrap();
+        packet.lookup_key.src_port = src.1;
+        packet.lookup_key.dst_ip = dst.0.parse().unwrap();
+        packet.lookup_key.dst_port = dst.1;
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.direction = direction;
+        let head = AppProtoHead {
+            proto: L7Protocol::Redis,
+            msg_type,
+            status: L7ResponseStatus::Ok,
+            rrt,
+            ..Default::default()
+        };
+        let base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        AppProtoLogsData::new(
+            base_info,
+            AppProtoLogsInfo::Redis(RedisInfo {
+                request: request.as_bytes().to_vec(),
+                request_type: request.split(' ').next().unwrap().as_bytes().to_vec(),
+                ..Default::default()
+            }),
+        )
+    }
+
+    fn recv_all(r: &Receiver<SendItem>) -> Vec<Document> {
+        let mut docs = vec![];
+        while let Ok(SendItem::Metrics(doc)) = r.recv(Some(Duration::from_millis(10))) {
+            docs.push(*doc);
+        }
+        docs.sort_by(|a, b| a.tagger.key_pattern.cmp(&b.tagger.key_pattern));
+        docs
+    }
+
+    #[test]
+    fn key_pattern_aggregation() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut metrics = RedisMetrics::new(Duration::from_secs(60), s);
+
+        for (id, rrt) in [(1001, 100), (1002, 300), (1003, 200)] {
+            let request = format!("GET user:{}:profile", id);
+            metrics.add(&log(61, LogMessageType::Session, &request, rrt), 2);
+        }
+        metrics.add(
+            &log(62, LogMessageType::Request, "GET user:1004:profile", 0),
+            2,
+        );
+        metrics.add(&log(62, LogMessageType::Session, "PING", 50), 2);
+        metrics.add(
+            &log(63, LogMessageType::Session, "SET order:9:state paid", 80),
+            2,
+        );
+        // 超出上限的模式计入空模式
+        metrics.add(&log(63, LogMessageType::Session, "DEL cart:7", 60), 2);
+
+        metrics.flush(Duration::from_secs(120));
+        assert!(r.recv(Some(Duration::from_millis(10))).is_err());
+        metrics.flush(Duration::from_secs(181));
+        let docs = recv_all(&r);
+        assert_eq!(docs.len(), 3);
+        assert_eq!(docs[0].tagger.key_pattern, "");
+        assert_eq!(docs[1].tagger.key_pattern, "order:*:state");
+        assert_eq!(docs[2].tagger.key_pattern, "user:*:profile");
+        assert_eq!(docs[2].tagger.server_port, 6379);
+        assert_eq!(docs[2].tagger.l7_protocol, L7Protocol::Redis);
+        match &docs[2].meter {
+            Meter::App(m) => {
+                assert_eq!(m.traffic.request, 4);
+                assert_eq!(m.traffic.response, 3);
+                assert_eq!(m.anomaly.timeout, 1);
+                assert_eq!(m.latency.rrt_max, 300);
+                assert_eq!(m.latency.rrt_sum, 600);
+                assert_eq!(m.latency.rrt_count, 3);
+            }
+            _ => panic!("unexpected meter"),
+        }
+    }
+
+    #[test]
+    fn server_from_log_direction() {
+        let queue_debugger = QueueDebugger::new();
+        let (s, r, _) = bounded_with_debug(16, "", &queue_debugger);
+        let mut metrics = RedisMetrics::new(Duration::from_secs(60), s);
+
+        // 服务端到客户端方向采集的会话
+        metrics.add(
+            &log_with_direction(
+                61,
+                LogMessageType::Session,
+                "GET user:1:profile",
+                100,
+                PacketDirection::ServerToClient,
+            ),
+            2,
+        );
+        // 只有响应的日志无法确定命令，不统计
+        metrics.add(
+            &log_with_direction(
+                61,
+                LogMessageType::Response,
+                "",
+                0,
+                PacketDirection::ServerToClient,
+            ),
+            2,
+        );
+        metrics.flush(Duration::from_secs(181));
+        let docs = recv_all(&r);
+        assert_eq!(docs.len(), 1);
+        let tagger = &docs[0].tagger;
+        assert_eq!(tagger.ip1, "10.0.0.79".parse::<IpAddr>().unwrap());
+        assert_eq!(tagger.server_port, 6379);
+        assert_eq!(tagger.protocol, IpProtocol::Tcp);
+        assert!(tagger.code.contains(Code::DIRECTION | Code::TAP_PORT));
+    }
+}
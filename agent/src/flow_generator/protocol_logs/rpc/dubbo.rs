@@ -20,14 +20,14 @@ use serde::Serialize;
 
 use super::super::{
     consts::*, value_is_default, value_is_negative, AppProtoHead, AppProtoLogsInfo, L7LogParse,
-    L7Protocol, L7ResponseStatus, LogMessageType,
+    L7Protocol, L7ResponseStatus, L7SessionId, LogMessageType,
 };
 
 use crate::common::enums::{IpProtocol, PacketDirection};
 use crate::common::meta_packet::MetaPacket;
 use crate::config::handler::{L7LogDynamicConfig, LogParserAccess};
-use crate::flow_generator::error::{Error, Result};
-use crate::flow_generator::{AppProtoHeadEnum, AppProtoLogsInfoEnum};
+use crate::flow_generator::error::{Error, ParseContext, ParseStage, Result};
+use crate::flow_generator::{golden, AppProtoHeadEnum, AppProtoLogsInfoEnum};
 use crate::proto::flow_log;
 use crate::utils::bytes::{read_u32_be, read_u64_be};
 
@@ -68,12 +68,22 @@ impl DubboInfo {
     }
 }
 
+impl L7SessionId for DubboInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.serial_id > 0 {
+            Some(self.serial_id as u32)
+        } else {
+            None
+        }
+    }
+}
+
 impl From<DubboInfo> for flow_log::DubboInfo {
     fn from(f: DubboInfo) -> Self {
         flow_log::DubboInfo {
             serial_id: f.serial_id as u32,
             r#type: f.data_type as u32,
-            id: f.request_id as u32,
+            id: f.request_id as u64,
             req_body_len: f.req_msg_size,
             version: f.dubbo_version,
             service_name: f.service_name,
@@ -277,6 +287,7 @@ impl L7LogParse for DubboLog {
             code: self.status_code as u16,
             rrt: 0,
             version: 0,
+            ..Default::default()
         }))
     }
 
@@ -307,10 +318,12 @@ impl DubboHeader {
     // +------------------------------------------------------------------------------------------------------------+
     pub fn parse_headers(&mut self, payload: &[u8]) -> Result<()> {
         if payload.len() < DUBBO_HEADER_LEN {
-            return Err(Error::DubboHeaderParseFailed);
+            return Err(Error::DubboHeaderParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(DUBBO_HEADER_LEN, payload.len()),
+            ));
         }
         if payload[0] != DUBBO_MAGIC_HIGH || payload[1] != DUBBO_MAGIC_LOW {
-            return Err(Error::DubboHeaderParseFailed);
+            return Err(Error::DubboHeaderParseFailed(ParseStage::Header.into()));
         }
 
         self.serial_id = payload[2] & 0x1f;
@@ -373,6 +386,19 @@ pub fn dubbo_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     return header.check();
 }
 
+impl DubboLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let mut dubbo = DubboLog::default();
+            let _ = dubbo.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_dubbo = dubbo_check_protocol(&mut bitmap, packet);
+            output.push_str(&format!("{:?} is_dubbo: {}\r\n", dubbo.info, is_dubbo));
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -380,42 +406,19 @@ mod tests {
 
     use super::*;
 
-    use crate::{common::enums::PacketDirection, utils::test::Capture};
+    use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/dubbo";
 
     fn run(name: &str) -> String {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
-
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-
-            let mut dubbo = DubboLog::default();
-            let _ = dubbo.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_dubbo = dubbo_check_protocol(&mut bitmap, packet);
-            output.push_str(&format!("{:?} is_dubbo: {}\r\n", dubbo.info, is_dubbo));
-        }
-        output
+        DubboLog::golden_output(&mut packets)
     }
 
     #[test]
     fn check() {
-        let files = vec![("dubbo_hessian2.pcap", "dubbo_hessian.result")];
+        let files = vec![("dubbo_hessian2.pcap", "dubbo_hessian2.result")];
 
         for item in files.iter() {
             let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
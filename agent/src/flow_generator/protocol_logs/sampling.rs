@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use rand::prelude::{Rng, SeedableRng, SmallRng};
+
+use super::{AppProtoLogsData, L7ResponseStatus, LogMessageType};
+use crate::config::{L7LogSamplingConfig, L7LogSamplingMode};
+
+const RATE_MAX: u32 = crate::config::L7_LOG_SAMPLING_RATE_MAX;
+
+// 会话聚合后决定每条L7日志是否发送
+pub trait L7LogSampler: Send {
+    // 返回false时丢弃该日志
+    fn keep(&mut self, log: &AppProtoLogsData) -> bool;
+}
+
+struct HeadBased {
+    rate: u32,
+    rng: SmallRng,
+}
+
+impl HeadBased {
+    fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    fn sample(&mut self) -> bool {
+        self.rng.gen_range(0..RATE_MAX) < self.rate
+    }
+}
+
+impl L7LogSampler for HeadBased {
+    fn keep(&mut self, _: &AppProtoLogsData) -> bool {
+        self.sample()
+    }
+}
+
+struct ErrorBiased(HeadBased);
+
+impl L7LogSampler for ErrorBiased {
+    fn keep(&mut self, log: &AppProtoLogsData) -> bool {
+        let head = &log.base_info.head;
+        let error = head.msg_type == LogMessageType::Request
+            || matches!(
+                head.status,
+                L7ResponseStatus::Error
+                    | L7ResponseStatus::ClientError
+                    | L7ResponseStatus::ServerError
+            );
+        error || self.0.sample()
+    }
+}
+
+// 各agent需要得到相同的哈希值，不能使用带随机种子的DefaultHasher
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    data.iter()
+        .fold(OFFSET_BASIS, |h, b| (h ^ *b as u64).wrapping_mul(PRIME))
+}
+
+struct TraceIdConsistent(HeadBased);
+
+impl L7LogSampler for TraceIdConsistent {
+    fn keep(&mut self, log: &AppProtoLogsData) -> bool {
+        match log.special_info.trace_id() {
+            Some(trace_id) => (fnv1a(trace_id.as_bytes()) % RATE_MAX as u64) < self.0.rate as u64,
+            None => self.0.sample(),
+        }
+    }
+}
+
+// 不采样时返回None
+pub fn new_sampler(config: &L7LogSamplingConfig) -> Option<Box<dyn L7LogSampler>> {
+    let rate = config.rate.min(RATE_MAX);
+    match config.mode {
+        L7LogSamplingMode::Disabled => None,
+        _ if rate == RATE_MAX => None,
+        L7LogSamplingMode::HeadBased => Some(Box::new(HeadBased::new(rate))),
+        L7LogSamplingMode::ErrorBiased => Some(Box::new(ErrorBiased(HeadBased::new(rate)))),
+        L7LogSamplingMode::TraceIdConsistent => {
+            Some(Box::new(TraceIdConsistent(HeadBased::new(rate))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    };
+    use crate::flow_generator::{
+        AppProtoHead, AppProtoLogsBaseInfo, AppProtoLogsInfo, DnsInfo, HttpInfo,
+    };
+
+    fn log(status: L7ResponseStatus, trace_id: Option<&str>) -> AppProtoLogsData {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(1);
+        packet.lookup_key.proto = IpProtocol::Tcp;
+        packet.direction = PacketDirection::ClientToServer;
+        let head = AppProtoHead {
+            proto: L7Protocol::Http1,
+            msg_type: LogMessageType::Session,
+            status,
+            ..Default::default()
+        };
+        let base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        let info = match trace_id {
+            Some(t) => AppProtoLogsInfo::HttpV1(HttpInfo {
+                trace_id: t.into(),
+                ..Default::default()
+            }),
+            None => AppProtoLogsInfo::Dns(DnsInfo::default()),
+        };
+        AppProtoLogsData::new(base_info, info)
+    }
+
+    fn config(mode: L7LogSamplingMode, rate: u32) -> L7LogSamplingConfig {
+        L7LogSamplingConfig { mode, rate }
+    }
+
+    #[test]
+    fn sampling_modes() {
+        assert!(new_sampler(&config(L7LogSamplingMode::Disabled, 0)).is_none());
+        assert!(new_sampler(&config(L7LogSamplingMode::HeadBased, RATE_MAX)).is_none());
+
+        let ok = log(L7ResponseStatus::Ok, None);
+        let error = log(L7ResponseStatus::ServerError, None);
+        let mut head_based = new_sampler(&config(L7LogSamplingMode::HeadBased, 0)).unwrap();
+        assert!(!head_based.keep(&ok));
+        assert!(!head_based.keep(&error));
+        let mut error_biased = new_sampler(&config(L7LogSamplingMode::ErrorBiased, 0)).unwrap();
+        assert!(!error_biased.keep(&ok));
+        assert!(error_biased.keep(&error));
+
+        // 两个独立的采样器对同一trace的取舍一致
+        let mut a = new_sampler(&config(L7LogSamplingMode::TraceIdConsistent, 500)).unwrap();
+        let mut b = new_sampler(&config(L7LogSamplingMode::TraceIdConsistent, 500)).unwrap();
+        let mut kept = 0;
+        for i in 0..1000 {
+            let trace_id = format!("{:032x}", i * 7919);
+            let keep = a.keep(&log(L7ResponseStatus::Ok, Some(&trace_id)));
+            assert_eq!(
+                keep,
+                b.keep(&log(L7ResponseStatus::ServerError, Some(&trace_id)))
+            );
+            if keep {
+                kept += 1;
+            }
+        }
+        assert!(kept > 400 && kept < 600, "{}", kept);
+    }
+}
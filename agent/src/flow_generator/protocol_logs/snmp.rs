@@ -0,0 +1,564 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use serde::Serialize;
+
+use super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseStage, Result},
+};
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct SnmpInfo {
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: String,
+    // PDU类型名称，如：GetRequest、SNMPv2-Trap，v3加密时为空
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub pdu_type: String,
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub request_id: u32,
+    #[serde(rename = "response_code", skip_serializing_if = "value_is_default")]
+    pub error_status: u8,
+    // 仅记录v1/v2c是否携带community，不记录其值
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub has_community: bool,
+}
+
+impl SnmpInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.error_status = other.error_status;
+    }
+}
+
+impl L7SessionId for SnmpInfo {
+    fn session_id(&self) -> Option<u32> {
+        if self.request_id > 0 {
+            Some(self.request_id)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<SnmpInfo> for flow_log::SnmpInfo {
+    fn from(f: SnmpInfo) -> Self {
+        flow_log::SnmpInfo {
+            version: f.version,
+            pdu_type: f.pdu_type,
+            request_id: f.request_id,
+            error_status: f.error_status as u32,
+            has_community: f.has_community,
+        }
+    }
+}
+
+// 读取BER编码的tag和length，返回(tag, value长度, 头部长度)
+fn ber_header(buf: &[u8]) -> Option<(u8, usize, usize)> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let tag = buf[0];
+    if buf[1] & 0x80 == 0 {
+        return Some((tag, buf[1] as usize, 2));
+    }
+    // 不支持不定长编码
+    let n = (buf[1] & 0x7f) as usize;
+    if n == 0 || n > 4 || buf.len() < 2 + n {
+        return None;
+    }
+    let len = buf[2..2 + n]
+        .iter()
+        .fold(0usize, |len, b| len << 8 | *b as usize);
+    Some((tag, len, 2 + n))
+}
+
+// 读取完整的TLV，返回value和剩余部分
+fn ber_read(buf: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (t, len, header_len) = ber_header(buf)?;
+    if t != tag || buf.len() < header_len + len {
+        return None;
+    }
+    Some((&buf[header_len..header_len + len], &buf[header_len + len..]))
+}
+
+// 进入构造类型，payload被截断时返回已有部分
+fn ber_enter(buf: &[u8], tag: u8) -> Option<&[u8]> {
+    let (t, len, header_len) = ber_header(buf)?;
+    if t != tag {
+        return None;
+    }
+    Some(&buf[header_len..buf.len().min(header_len + len)])
+}
+
+fn ber_integer(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let init = if value[0] & 0x80 != 0 { -1 } else { 0 };
+    Some(value.iter().fold(init, |v, b| v << 8 | *b as i64))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnmpHeader {
+    pub version: u8,
+    // v3加密时无法获取PDU类型，为0
+    pub pdu_type: u8,
+    pub request_id: u32,
+    pub error_status: u8,
+    pub has_community: bool,
+    // v3 msgFlags中的reportable标志
+    pub reportable: bool,
+}
+
+impl SnmpHeader {
+    // SNMP消息格式，参考RFC 1157、RFC 3416和RFC 3412
+    // v1/v2c: SEQUENCE { version, community, PDU }
+    // v3:     SEQUENCE { version, msgGlobalData, msgSecurityParameters, ScopedPDU | encryptedPDU }
+    // PDU:    [type] { request-id, error-status, error-index, variable-bindings }
+    // v1的Trap-PDU没有request-id和error-status
+    pub fn parse_headers(&mut self, payload: &[u8], strict: bool) -> Result<()> {
+        *self = Self::default();
+        self.decode(payload, strict)
+            .ok_or(Error::SnmpHeaderParseFailed(ParseStage::Header.into()))
+    }
+
+    // 协议识别时要求长度字段与payload一致，日志解析时payload可能被截断
+    fn decode(&mut self, payload: &[u8], strict: bool) -> Option<()> {
+        let (tag, len, header_len) = ber_header(payload)?;
+        if tag != BER_TAG_SEQUENCE
+            || len > SNMP_MAX_LEN
+            || (strict && header_len + len != payload.len())
+        {
+            return None;
+        }
+        let message = ber_enter(payload, BER_TAG_SEQUENCE)?;
+        let (version, message) = ber_read(message, BER_TAG_INTEGER)?;
+        self.version = u8::try_from(ber_integer(version)?).ok()?;
+        let pdu = match self.version {
+            SNMP_VERSION_1 | SNMP_VERSION_2C => {
+                let (community, pdu) = ber_read(message, BER_TAG_OCTET_STRING)?;
+                self.has_community = !community.is_empty();
+                pdu
+            }
+            SNMP_VERSION_3 => match self.decode_v3(message)? {
+                Some(pdu) => pdu,
+                None => return Some(()),
+            },
+            _ => return None,
+        };
+        self.decode_pdu(pdu)
+    }
+
+    // 返回ScopedPDU中的PDU，加密时返回None
+    fn decode_v3<'a>(&mut self, message: &'a [u8]) -> Option<Option<&'a [u8]>> {
+        let (global_data, message) = ber_read(message, BER_TAG_SEQUENCE)?;
+        let (msg_id, global_data) = ber_read(global_data, BER_TAG_INTEGER)?;
+        let (_, global_data) = ber_read(global_data, BER_TAG_INTEGER)?;
+        let (flags, _) = ber_read(global_data, BER_TAG_OCTET_STRING)?;
+        let flags = *flags.first()?;
+        // v3的响应会携带请求的msgID，加密时也能用于关联请求和响应
+        self.request_id = ber_integer(msg_id)? as u32;
+        self.reportable = flags & SNMP_V3_FLAG_REPORTABLE != 0;
+
+        let (_, message) = ber_read(message, BER_TAG_OCTET_STRING)?;
+        if flags & SNMP_V3_FLAG_PRIV != 0 {
+            ber_enter(message, BER_TAG_OCTET_STRING)?;
+            return Some(None);
+        }
+        let scoped_pdu = ber_enter(message, BER_TAG_SEQUENCE)?;
+        let (_, scoped_pdu) = ber_read(scoped_pdu, BER_TAG_OCTET_STRING)?;
+        let (_, scoped_pdu) = ber_read(scoped_pdu, BER_TAG_OCTET_STRING)?;
+        Some(Some(scoped_pdu))
+    }
+
+    fn decode_pdu(&mut self, buf: &[u8]) -> Option<()> {
+        let (tag, _, _) = ber_header(buf)?;
+        let valid = match self.version {
+            SNMP_VERSION_1 => (SNMP_PDU_GET_REQUEST..=SNMP_PDU_TRAP).contains(&tag),
+            _ => (SNMP_PDU_GET_REQUEST..=SNMP_PDU_REPORT).contains(&tag) && tag != SNMP_PDU_TRAP,
+        };
+        if !valid {
+            return None;
+        }
+        self.pdu_type = tag;
+        if tag == SNMP_PDU_TRAP {
+            return Some(());
+        }
+
+        let pdu = ber_enter(buf, tag)?;
+        let (request_id, pdu) = ber_read(pdu, BER_TAG_INTEGER)?;
+        let request_id = ber_integer(request_id)? as u32;
+        if self.version != SNMP_VERSION_3 {
+            self.request_id = request_id;
+        }
+        // GetBulkRequest的第二个字段为non-repeaters
+        if tag == SNMP_PDU_GET_BULK_REQUEST {
+            return Some(());
+        }
+        let (error_status, _) = ber_read(pdu, BER_TAG_INTEGER)?;
+        let error_status = ber_integer(error_status)?;
+        if !(0..=SNMP_ERROR_MAX).contains(&error_status) {
+            return None;
+        }
+        self.error_status = error_status as u8;
+        Some(())
+    }
+
+    pub fn msg_type(&self) -> LogMessageType {
+        match self.pdu_type {
+            SNMP_PDU_RESPONSE | SNMP_PDU_REPORT => LogMessageType::Response,
+            // Trap没有响应
+            SNMP_PDU_TRAP | SNMP_PDU_TRAP_V2 => LogMessageType::Session,
+            // v3加密时通过reportable标志区分，加密的Trap也会被当作响应
+            0 if !self.reportable => LogMessageType::Response,
+            _ => LogMessageType::Request,
+        }
+    }
+
+    pub fn status(&self) -> L7ResponseStatus {
+        match self.error_status {
+            0 => L7ResponseStatus::Ok,
+            SNMP_ERROR_TOO_BIG
+            | SNMP_ERROR_GEN_ERR
+            | SNMP_ERROR_RESOURCE_UNAVAILABLE
+            | SNMP_ERROR_COMMIT_FAILED
+            | SNMP_ERROR_UNDO_FAILED => L7ResponseStatus::ServerError,
+            _ => L7ResponseStatus::ClientError,
+        }
+    }
+
+    fn version_name(&self) -> &'static str {
+        match self.version {
+            SNMP_VERSION_1 => "v1",
+            SNMP_VERSION_2C => "v2c",
+            SNMP_VERSION_3 => "v3",
+            _ => "",
+        }
+    }
+
+    fn pdu_name(&self) -> &'static str {
+        match self.pdu_type {
+            SNMP_PDU_GET_REQUEST => "GetRequest",
+            SNMP_PDU_GET_NEXT_REQUEST => "GetNextRequest",
+            SNMP_PDU_RESPONSE => "Response",
+            SNMP_PDU_SET_REQUEST => "SetRequest",
+            SNMP_PDU_TRAP => "Trap",
+            SNMP_PDU_GET_BULK_REQUEST => "GetBulkRequest",
+            SNMP_PDU_INFORM_REQUEST => "InformRequest",
+            SNMP_PDU_TRAP_V2 => "SNMPv2-Trap",
+            SNMP_PDU_REPORT => "Report",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SnmpLog {
+    info: SnmpInfo,
+
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u8,
+}
+
+impl SnmpLog {
+    fn reset_logs(&mut self) {
+        self.info = SnmpInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    fn decode_payload(&mut self, payload: &[u8], strict: bool) -> Result<AppProtoHead> {
+        let mut header = SnmpHeader::default();
+        header.parse_headers(payload, strict)?;
+
+        self.info = SnmpInfo {
+            version: header.version_name().to_string(),
+            pdu_type: header.pdu_name().to_string(),
+            request_id: header.request_id,
+            error_status: header.error_status,
+            has_community: header.has_community,
+        };
+        self.msg_type = header.msg_type();
+        if self.msg_type == LogMessageType::Response {
+            self.status = header.status();
+            self.status_code = header.error_status;
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Snmp,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code as u16,
+            rrt: 0,
+            version: header.version,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for SnmpLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        _direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Udp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        Ok(AppProtoHeadEnum::Single(
+            self.decode_payload(payload, false)?,
+        ))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Snmp(self.info.clone()))
+    }
+}
+
+// 通过发往161/162端口的请求或Trap识别SNMP，要求长度字段与payload一致
+pub fn snmp_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Udp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Snmp));
+        return false;
+    }
+    let is_snmp_port = |port| port == SNMP_PORT || port == SNMP_TRAP_PORT;
+    if !is_snmp_port(packet.lookup_key.dst_port) {
+        if !is_snmp_port(packet.lookup_key.src_port) {
+            *bitmap &= !(1 << u8::from(L7Protocol::Snmp));
+        }
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    let mut header = SnmpHeader::default();
+    if header.parse_headers(payload, true).is_err() {
+        *bitmap &= !(1 << u8::from(L7Protocol::Snmp));
+        return false;
+    }
+    header.msg_type() != LogMessageType::Response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut buf = vec![tag];
+        if value.len() < 0x80 {
+            buf.push(value.len() as u8);
+        } else {
+            buf.push(0x82);
+            buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        }
+        buf.extend_from_slice(value);
+        buf
+    }
+
+    fn pdu(pdu_type: u8, request_id: &[u8], error_status: u8) -> Vec<u8> {
+        // sysDescr.0 = NULL
+        let varbind = [
+            tlv(0x06, &[0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00]),
+            tlv(0x05, &[]),
+        ]
+        .concat();
+        let varbinds = tlv(BER_TAG_SEQUENCE, &tlv(BER_TAG_SEQUENCE, &varbind));
+        let body = [
+            tlv(BER_TAG_INTEGER, request_id),
+            tlv(BER_TAG_INTEGER, &[error_status]),
+            tlv(BER_TAG_INTEGER, &[0]),
+            varbinds,
+        ]
+        .concat();
+        tlv(pdu_type, &body)
+    }
+
+    fn message(version: u8, community: &[u8], pdu: &[u8]) -> Vec<u8> {
+        let body = [
+            tlv(BER_TAG_INTEGER, &[version]),
+            tlv(BER_TAG_OCTET_STRING, community),
+            pdu.to_vec(),
+        ]
+        .concat();
+        tlv(BER_TAG_SEQUENCE, &body)
+    }
+
+    fn message_v3(msg_id: &[u8], flags: u8, data: &[u8]) -> Vec<u8> {
+        let global_data = [
+            tlv(BER_TAG_INTEGER, msg_id),
+            tlv(BER_TAG_INTEGER, &[0x05, 0xdc]),
+            tlv(BER_TAG_OCTET_STRING, &[flags]),
+            tlv(BER_TAG_INTEGER, &[3]),
+        ]
+        .concat();
+        let body = [
+            tlv(BER_TAG_INTEGER, &[SNMP_VERSION_3]),
+            tlv(BER_TAG_SEQUENCE, &global_data),
+            tlv(BER_TAG_OCTET_STRING, &[0x30, 0x00]),
+            data.to_vec(),
+        ]
+        .concat();
+        tlv(BER_TAG_SEQUENCE, &body)
+    }
+
+    #[test]
+    fn parse_v2c() {
+        let request = message(
+            SNMP_VERSION_2C,
+            b"public",
+            &pdu(SNMP_PDU_GET_REQUEST, &[0x12, 0x34, 0x56, 0x78], 0),
+        );
+        let mut snmp = SnmpLog::default();
+        let head = snmp
+            .parse(&request, IpProtocol::Udp, PacketDirection::ClientToServer)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(
+            snmp.info,
+            SnmpInfo {
+                version: "v2c".into(),
+                pdu_type: "GetRequest".into(),
+                request_id: 0x12345678,
+                error_status: 0,
+                has_community: true,
+            }
+        );
+        assert!(snmp.decode_payload(&request, true).is_ok());
+        // 长度字段与payload不一致
+        assert!(snmp
+            .decode_payload(&request[..request.len() - 2], true)
+            .is_err());
+
+        // noSuchName
+        let response = message(
+            SNMP_VERSION_2C,
+            b"",
+            &pdu(SNMP_PDU_RESPONSE, &[0x12, 0x34, 0x56, 0x78], 2),
+        );
+        let head = snmp
+            .parse(&response, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, 2);
+        assert!(!snmp.info.has_community);
+
+        // genErr
+        let response = message(
+            SNMP_VERSION_2C,
+            b"public",
+            &pdu(SNMP_PDU_RESPONSE, &[0x12, 0x34, 0x56, 0x78], 5),
+        );
+        let head = snmp
+            .parse(&response, IpProtocol::Udp, PacketDirection::ServerToClient)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn parse_trap() {
+        // v1 Trap-PDU: enterprise, agent-addr, generic-trap, specific-trap, time-stamp
+        let trap = [
+            tlv(0x06, &[0x2b, 0x06, 0x01, 0x04, 0x01]),
+            tlv(0x40, &[10, 0, 0, 1]),
+            tlv(BER_TAG_INTEGER, &[6]),
+            tlv(BER_TAG_INTEGER, &[1]),
+            tlv(0x43, &[0x01, 0x00]),
+            tlv(BER_TAG_SEQUENCE, &[]),
+        ]
+        .concat();
+        let trap = message(SNMP_VERSION_1, b"public", &tlv(SNMP_PDU_TRAP, &trap));
+        let mut snmp = SnmpLog::default();
+        let head = snmp.decode_payload(&trap, true).unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Session);
+        assert_eq!(snmp.info.version, "v1");
+        assert_eq!(snmp.info.pdu_type, "Trap");
+        assert_eq!(snmp.info.request_id, 0);
+
+        let trap = message(SNMP_VERSION_2C, b"public", &pdu(SNMP_PDU_TRAP_V2, &[7], 0));
+        let head = snmp.decode_payload(&trap, true).unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Session);
+        assert_eq!(snmp.info.pdu_type, "SNMPv2-Trap");
+        // v2c不允许使用v1的Trap-PDU
+        let trap = message(SNMP_VERSION_2C, b"public", &pdu(SNMP_PDU_TRAP, &[7], 0));
+        assert!(snmp.decode_payload(&trap, true).is_err());
+    }
+
+    #[test]
+    fn parse_v3() {
+        let scoped_pdu = [
+            tlv(BER_TAG_OCTET_STRING, &[0x80, 0x00, 0x1f, 0x88]),
+            tlv(BER_TAG_OCTET_STRING, b""),
+            pdu(SNMP_PDU_GET_BULK_REQUEST, &[0x01, 0x02], 10),
+        ]
+        .concat();
+        let request = message_v3(
+            &[0x00, 0x9a],
+            SNMP_V3_FLAG_REPORTABLE,
+            &tlv(BER_TAG_SEQUENCE, &scoped_pdu),
+        );
+        let mut snmp = SnmpLog::default();
+        let head = snmp.decode_payload(&request, true).unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(
+            snmp.info,
+            SnmpInfo {
+                version: "v3".into(),
+                pdu_type: "GetBulkRequest".into(),
+                request_id: 0x9a,
+                ..Default::default()
+            }
+        );
+
+        // 加密的响应
+        let response = message_v3(
+            &[0x00, 0x9a],
+            SNMP_V3_FLAG_PRIV | 0x01,
+            &tlv(BER_TAG_OCTET_STRING, &[0xde, 0xad, 0xbe, 0xef]),
+        );
+        let head = snmp.decode_payload(&response, true).unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(snmp.info.pdu_type, "");
+        assert_eq!(snmp.info.request_id, 0x9a);
+
+        let invalid = message(2, b"public", &pdu(SNMP_PDU_GET_REQUEST, &[1], 0));
+        assert!(snmp.decode_payload(&invalid, false).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str;
+
+use serde::Serialize;
+
+use super::super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::bytes::{read_u32_le, utf8_prefix},
+};
+
+// Query的格式与协商的协议版本相关，而版本只在连接建立时的Hello中出现，
+// 按从新到旧的顺序尝试会导致格式变化的版本
+const QUERY_REVISIONS: [u32; 8] = [
+    CLICKHOUSE_REVISION_PARALLEL_REPLICAS,
+    CLICKHOUSE_REVISION_INITIAL_QUERY_START_TIME,
+    CLICKHOUSE_REVISION_DISTRIBUTED_DEPTH,
+    CLICKHOUSE_REVISION_REFERER,
+    CLICKHOUSE_REVISION_X_FORWARDED_FOR,
+    CLICKHOUSE_REVISION_OPENTELEMETRY,
+    CLICKHOUSE_REVISION_INTERSERVER_SECRET,
+    CLICKHOUSE_REVISION_SETTINGS_AS_STRINGS,
+];
+
+// 服务端资源或网络导致的异常，其他异常视为客户端错误
+const SERVER_ERROR_CODES: [u32; 10] = [
+    49,  // LOGICAL_ERROR
+    159, // TIMEOUT_EXCEEDED
+    173, // CANNOT_ALLOCATE_MEMORY
+    202, // TOO_MANY_SIMULTANEOUS_QUERIES
+    209, // SOCKET_TIMEOUT
+    210, // NETWORK_ERROR
+    241, // MEMORY_LIMIT_EXCEEDED
+    243, // NOT_ENOUGH_SPACE
+    252, // TOO_MANY_PARTS
+    279, // ALL_CONNECTION_TRIES_FAILED
+];
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClickhouseInfo {
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub query_id: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub query: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub exception_code: u32,
+    #[serde(
+        rename = "response_execption",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception_message: String,
+}
+
+impl ClickhouseInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.exception_code = other.exception_code;
+        self.exception_message = other.exception_message;
+    }
+}
+
+impl L7SessionId for ClickhouseInfo {}
+
+impl From<ClickhouseInfo> for flow_log::ClickhouseInfo {
+    fn from(f: ClickhouseInfo) -> Self {
+        flow_log::ClickhouseInfo {
+            query_id: f.query_id,
+            query: f.query,
+            exception_code: f.exception_code,
+            exception_message: f.exception_message,
+        }
+    }
+}
+
+// 整数为LEB128编码，字符串为长度加内容
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.offset)?;
+        self.offset += 1;
+        Some(b)
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let b = self.data.get(self.offset..self.offset.checked_add(n)?)?;
+        self.offset += n;
+        Some(b)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..10 {
+            let b = self.u8()?;
+            value |= ((b & 0x7f) as u64) << (7 * i);
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.varint()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        str::from_utf8(self.bytes()?).ok()
+    }
+
+    // 超出payload的部分截断，用于较长的查询语句和异常信息
+    fn truncated_string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.varint()?).ok()?;
+        let rest = &self.data[self.offset..];
+        let b = &rest[..len.min(rest.len())];
+        self.offset += b.len();
+        Some(String::from_utf8_lossy(utf8_prefix(b, b.len())).into_owned())
+    }
+}
+
+fn is_printable(s: &str) -> bool {
+    s.chars().all(|c| !c.is_control())
+}
+
+// 连接建立时客户端和服务端的Hello均以名称和版本号开头，返回协议版本
+fn decode_hello(payload: &[u8]) -> Option<u64> {
+    let mut r = Reader::new(payload);
+    if r.u8()? != CLICKHOUSE_CLIENT_HELLO {
+        return None;
+    }
+    let name = r.string()?;
+    if name.is_empty() || !is_printable(name) {
+        return None;
+    }
+    r.varint()?;
+    r.varint()?;
+    let revision = r.varint()?;
+    if (CLICKHOUSE_MIN_REVISION..CLICKHOUSE_MAX_REVISION).contains(&revision) {
+        Some(revision)
+    } else {
+        None
+    }
+}
+
+// 跳过Query中的ClientInfo
+fn skip_client_info(r: &mut Reader, revision: u32) -> Option<()> {
+    let kind = r.u8()?;
+    if kind == CLICKHOUSE_QUERY_KIND_NONE {
+        return Some(());
+    }
+    if kind > CLICKHOUSE_QUERY_KIND_SECONDARY {
+        return None;
+    }
+    // initial_user, initial_query_id, initial_address
+    for _ in 0..3 {
+        r.bytes()?;
+    }
+    if revision >= CLICKHOUSE_REVISION_INITIAL_QUERY_START_TIME {
+        r.take(8)?;
+    }
+    let interface = r.u8()?;
+    match interface {
+        CLICKHOUSE_INTERFACE_TCP => {
+            // os_user, client_hostname, client_name, major, minor
+            for _ in 0..3 {
+                r.bytes()?;
+            }
+            r.varint()?;
+            r.varint()?;
+            // 协商的版本不会高于客户端版本
+            if r.varint()? < revision as u64 {
+                return None;
+            }
+        }
+        CLICKHOUSE_INTERFACE_HTTP => {
+            // http_method, user_agent
+            r.u8()?;
+            r.bytes()?;
+            if revision >= CLICKHOUSE_REVISION_X_FORWARDED_FOR {
+                r.bytes()?;
+            }
+            if revision >= CLICKHOUSE_REVISION_REFERER {
+                r.bytes()?;
+            }
+        }
+        _ => return None,
+    }
+    // quota_key
+    r.bytes()?;
+    if revision >= CLICKHOUSE_REVISION_DISTRIBUTED_DEPTH {
+        r.varint()?;
+    }
+    // version_patch
+    if interface == CLICKHOUSE_INTERFACE_TCP {
+        r.varint()?;
+    }
+    if revision >= CLICKHOUSE_REVISION_OPENTELEMETRY && r.u8()? == 1 {
+        // trace_id, span_id, tracestate, trace_flags
+        r.take(24)?;
+        r.bytes()?;
+        r.u8()?;
+    }
+    if revision >= CLICKHOUSE_REVISION_PARALLEL_REPLICAS {
+        for _ in 0..3 {
+            r.varint()?;
+        }
+    }
+    Some(())
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClickhouseQuery {
+    pub query_id: String,
+    pub query: String,
+}
+
+impl ClickhouseQuery {
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.first() != Some(&CLICKHOUSE_CLIENT_QUERY) {
+            return None;
+        }
+        QUERY_REVISIONS
+            .iter()
+            .find_map(|r| Self::decode_with_revision(&payload[1..], *r))
+    }
+
+    fn decode_with_revision(data: &[u8], revision: u32) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let query_id = r.string()?;
+        if query_id.len() > CLICKHOUSE_MAX_QUERY_ID_LEN || !is_printable(query_id) {
+            return None;
+        }
+        skip_client_info(&mut r, revision)?;
+        // 以字符串形式序列化的settings: name, flags, value，以空的name结束
+        loop {
+            if r.bytes()?.is_empty() {
+                break;
+            }
+            r.varint()?;
+            r.bytes()?;
+        }
+        if revision >= CLICKHOUSE_REVISION_INTERSERVER_SECRET {
+            r.bytes()?;
+        }
+        let stage = r.varint()?;
+        let compression = r.varint()?;
+        if stage > CLICKHOUSE_QUERY_STAGE_COMPLETE || compression > 1 {
+            return None;
+        }
+        let query = r.truncated_string()?;
+        if query.is_empty() {
+            return None;
+        }
+        Some(Self {
+            query_id: query_id.to_owned(),
+            query,
+        })
+    }
+}
+
+// 只解析响应的第一个包，Exception之外的Data、Progress等均视为正常响应
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClickhouseResponse {
+    pub exception_code: u32,
+    pub exception_message: String,
+}
+
+impl ClickhouseResponse {
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(payload);
+        match r.u8()? {
+            CLICKHOUSE_SERVER_EXCEPTION => {
+                // code(Int32), name, message, stack_trace, has_nested
+                let code = read_u32_le(r.take(4)?);
+                if code == 0 || code > i32::MAX as u32 {
+                    return None;
+                }
+                r.bytes()?;
+                Some(Self {
+                    exception_code: code,
+                    exception_message: r.truncated_string()?,
+                })
+            }
+            CLICKHOUSE_SERVER_DATA
+            | CLICKHOUSE_SERVER_PROGRESS
+            | CLICKHOUSE_SERVER_END_OF_STREAM
+            | CLICKHOUSE_SERVER_PROFILE_INFO
+            | CLICKHOUSE_SERVER_TOTALS
+            | CLICKHOUSE_SERVER_EXTREMES
+            | CLICKHOUSE_SERVER_LOG
+            | CLICKHOUSE_SERVER_TABLE_COLUMNS
+            | CLICKHOUSE_SERVER_PROFILE_EVENTS => Some(Self::default()),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> L7ResponseStatus {
+        if self.exception_code == 0 {
+            L7ResponseStatus::Ok
+        } else if SERVER_ERROR_CODES.contains(&self.exception_code) {
+            L7ResponseStatus::ServerError
+        } else {
+            L7ResponseStatus::ClientError
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        u16::try_from(self.exception_code).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClickhouseLog {
+    info: ClickhouseInfo,
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u16,
+}
+
+impl ClickhouseLog {
+    fn reset_logs(&mut self) {
+        self.info = ClickhouseInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    fn decode_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<AppProtoHead> {
+        if direction == PacketDirection::ClientToServer {
+            let query = ClickhouseQuery::decode(payload)
+                .ok_or(Error::ClickhouseLogParseFailed(ParseStage::Request.into()))?;
+            self.msg_type = LogMessageType::Request;
+            self.info.query_id = query.query_id;
+            self.info.query = query.query;
+        } else {
+            let response = ClickhouseResponse::decode(payload).ok_or(
+                Error::ClickhouseLogParseFailed(ParseContext::new(ParseStage::Response)),
+            )?;
+            self.msg_type = LogMessageType::Response;
+            self.status = response.status();
+            self.status_code = response.code();
+            self.info.exception_code = response.exception_code;
+            self.info.exception_message = response.exception_message;
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Clickhouse,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for ClickhouseLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        let head = self.decode_payload(payload, direction)?;
+        Ok(AppProtoHeadEnum::Single(head))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Clickhouse(self.info.clone()))
+    }
+}
+
+// 通过连接建立时的Hello或Query识别
+pub fn clickhouse_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Tcp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Clickhouse));
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    decode_hello(payload).is_some() || ClickhouseQuery::decode(payload).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.push(s.len() as u8);
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    // clickhouse-client 23.3发送的Query，协议版本54461
+    fn query(query_id: &str, sql: &str) -> Vec<u8> {
+        let mut buf = vec![CLICKHOUSE_CLIENT_QUERY];
+        string(&mut buf, query_id);
+        // ClientInfo
+        buf.push(1);
+        string(&mut buf, "default");
+        string(&mut buf, query_id);
+        string(&mut buf, "[::ffff:127.0.0.1]:0");
+        buf.extend_from_slice(&1680000000000000u64.to_le_bytes());
+        buf.push(CLICKHOUSE_INTERFACE_TCP);
+        string(&mut buf, "root");
+        string(&mut buf, "host-1");
+        string(&mut buf, "ClickHouse client");
+        buf.extend_from_slice(&[23, 3, 0xbd, 0xa9, 0x03]);
+        string(&mut buf, "");
+        buf.extend_from_slice(&[0, 5, 0, 0, 0, 0]);
+        // settings
+        string(&mut buf, "max_threads");
+        buf.push(1);
+        string(&mut buf, "4");
+        string(&mut buf, "");
+        // interserver secret, stage, compression
+        string(&mut buf, "");
+        buf.extend_from_slice(&[2, 1]);
+        string(&mut buf, sql);
+        // 紧跟的空Data包
+        buf.extend_from_slice(&[2, 0, 1, 0, 2, 0xff, 0xff, 0xff, 0xff, 0, 0, 0]);
+        buf
+    }
+
+    #[test]
+    fn parse_query() {
+        let payload = query("8f2e", "SELECT count() FROM system.tables");
+        let mut log = ClickhouseLog::default();
+        let head = log
+            .decode_payload(&payload, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(log.info.query_id, "8f2e");
+        assert_eq!(log.info.query, "SELECT count() FROM system.tables");
+
+        // 语句被截断
+        log.reset_logs();
+        log.decode_payload(
+            &payload[..payload.len() - 20],
+            PacketDirection::ClientToServer,
+        )
+        .unwrap();
+        assert_eq!(log.info.query, "SELECT count() FROM syste");
+    }
+
+    #[test]
+    fn parse_exception() {
+        let mut payload = vec![CLICKHOUSE_SERVER_EXCEPTION];
+        payload.extend_from_slice(&60u32.to_le_bytes());
+        string(&mut payload, "DB::Exception");
+        string(
+            &mut payload,
+            "DB::Exception: Table default.t doesn't exist. (UNKNOWN_TABLE)",
+        );
+        let mut log = ClickhouseLog::default();
+        let head = log
+            .decode_payload(&payload, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, 60);
+        assert_eq!(
+            log.info.exception_message,
+            "DB::Exception: Table default.t doesn't exist. (UNKNOWN_TABLE)"
+        );
+
+        payload[1] = 241;
+        assert_eq!(
+            ClickhouseResponse::decode(&payload).unwrap().status(),
+            L7ResponseStatus::ServerError
+        );
+        assert_eq!(
+            ClickhouseResponse::decode(&[CLICKHOUSE_SERVER_END_OF_STREAM]),
+            Some(ClickhouseResponse::default())
+        );
+        assert!(ClickhouseResponse::decode(&[0x20, 0x01]).is_none());
+    }
+
+    #[test]
+    fn hello() {
+        let mut payload = vec![CLICKHOUSE_CLIENT_HELLO];
+        string(&mut payload, "ClickHouse client");
+        payload.extend_from_slice(&[23, 3, 0xbd, 0xa9, 0x03]);
+        string(&mut payload, "default");
+        assert_eq!(decode_hello(&payload), Some(54461));
+        // php-fpm等同端口的其他协议
+        assert_eq!(decode_hello(b"\x01\x01\x00\x01\x00\x08\x00\x00"), None);
+    }
+}
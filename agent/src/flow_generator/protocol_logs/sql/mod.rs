@@ -14,10 +14,16 @@
  * limitations under the License.
  */
 
+mod clickhouse;
 mod mysql;
 mod redis;
+mod tds;
 
+pub use clickhouse::clickhouse_check_protocol;
+pub use clickhouse::{ClickhouseInfo, ClickhouseLog, ClickhouseQuery, ClickhouseResponse};
 pub use mysql::mysql_check_protocol;
-pub use mysql::{MysqlHeader, MysqlInfo, MysqlLog};
+pub use mysql::{MysqlHeader, MysqlInfo, MysqlLog, MysqlSessions};
 pub use redis::redis_check_protocol;
-pub use redis::{decode, RedisInfo, RedisLog};
+pub use redis::{decode, decode_redirect, RedisInfo, RedisLog};
+pub use tds::tds_check_protocol;
+pub use tds::{TdsHeader, TdsInfo, TdsLog, TdsResponse};
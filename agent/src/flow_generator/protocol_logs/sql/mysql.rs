@@ -14,20 +14,25 @@
  * limitations under the License.
  */
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use lru::LruCache;
 use serde::Serialize;
 
 use super::super::{
     consts::*, value_is_default, AppProtoHead, AppProtoLogsData, AppProtoLogsInfo, L7LogParse,
-    L7Protocol, L7ResponseStatus, LogMessageType,
+    L7Protocol, L7ResponseStatus, L7SessionId, LogMessageType,
 };
 
-use crate::flow_generator::{AppProtoHeadEnum, AppProtoLogsInfoEnum};
+use crate::config::handler::LogParserAccess;
+use crate::flow_generator::{golden, AppProtoHeadEnum, AppProtoLogsInfoEnum};
 use crate::proto::flow_log;
 use crate::{
     common::enums::{IpProtocol, PacketDirection},
     common::meta_packet::MetaPacket,
-    flow_generator::error::{Error, Result},
-    utils::bytes,
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::{bytes, intern::intern},
 };
 
 #[derive(Serialize, Debug, Default, Clone)]
@@ -36,7 +41,7 @@ pub struct MysqlInfo {
     #[serde(rename = "version", skip_serializing_if = "value_is_default")]
     pub protocol_version: u8,
     #[serde(skip)]
-    pub server_version: String,
+    pub server_version: Arc<str>,
     #[serde(skip)]
     pub server_thread_id: u32,
     // request
@@ -56,6 +61,20 @@ pub struct MysqlInfo {
         skip_serializing_if = "value_is_default"
     )]
     pub error_message: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub trace_id: String,
+    // 请求时所在的数据库，COM_INIT_DB等切换数据库的请求合并后为切换后的数据库
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub database: String,
+    // 所在事务在连接上的序号，从1开始，不在显式事务中时为0
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub transaction_id: u32,
+    // COMMIT/ROLLBACK的响应中记录事务从BEGIN到结束的时长，单位微秒
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub transaction_duration: u64,
+    // 多语句查询的语句数，context只记录第一条语句，单条语句时为0
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub statement_count: u32,
 }
 
 impl MysqlInfo {
@@ -64,14 +83,23 @@ impl MysqlInfo {
         self.affected_rows = other.affected_rows;
         self.error_code = other.error_code;
         self.error_message = other.error_message;
+        if !other.database.is_empty() {
+            self.database = other.database;
+        }
+        if other.transaction_id != 0 {
+            self.transaction_id = other.transaction_id;
+        }
+        self.transaction_duration = other.transaction_duration;
     }
 }
 
+impl L7SessionId for MysqlInfo {}
+
 impl From<MysqlInfo> for flow_log::MysqlInfo {
     fn from(f: MysqlInfo) -> Self {
         flow_log::MysqlInfo {
             protocol_version: f.protocol_version as u32,
-            server_version: f.server_version,
+            server_version: f.server_version.to_string(),
             server_thread_id: f.server_thread_id,
             command: f.command as u32,
             context: f.context,
@@ -79,13 +107,101 @@ impl From<MysqlInfo> for flow_log::MysqlInfo {
             affected_rows: f.affected_rows,
             error_code: f.error_code as u32,
             error_message: f.error_message,
+            trace_id: f.trace_id,
+            database: f.database,
+            transaction_id: f.transaction_id,
+            transaction_duration: f.transaction_duration,
+            statement_count: f.statement_count,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TransactionStatement {
+    Begin,
+    Commit,
+    Rollback,
+}
+
+impl TransactionStatement {
+    // 只识别显式事务语句，不处理autocommit=0时的隐式事务
+    fn parse(sql: &str) -> Option<Self> {
+        let mut sql = sql.trim_start();
+        // 跳过语句前的注释
+        while let Some(s) = sql.strip_prefix("/*") {
+            sql = s.split_once("*/")?.1.trim_start();
         }
+        let mut words = sql
+            .split(|c: char| c.is_ascii_whitespace() || c == ';')
+            .filter(|w| !w.is_empty());
+        let first = words.next()?;
+        let second = words.next();
+        let is = |w: &str, k: &str| w.eq_ignore_ascii_case(k);
+        if is(first, "BEGIN") && second.map(|w| is(w, "WORK")).unwrap_or(true) {
+            Some(Self::Begin)
+        } else if is(first, "START") && second.map(|w| is(w, "TRANSACTION")).unwrap_or(false) {
+            Some(Self::Begin)
+        } else if is(first, "COMMIT") {
+            Some(Self::Commit)
+        } else if is(first, "ROLLBACK") && !sql.to_ascii_uppercase().contains(" TO ") {
+            // ROLLBACK TO SAVEPOINT不结束事务
+            Some(Self::Rollback)
+        } else {
+            None
+        }
+    }
+}
+
+// 单条MYSQL连接的会话状态
+#[derive(Clone, Debug, Default)]
+pub struct MysqlSession {
+    database: String,
+    // 等待响应确认的COM_INIT_DB
+    pending_database: Option<String>,
+
+    // 连接上已开始的事务数，用作事务ID
+    transaction_count: u32,
+    // 当前事务的ID及BEGIN请求的时间
+    transaction: Option<(u32, Duration)>,
+    // 等待响应确认的事务语句及其请求时间
+    pending_transaction: Option<(TransactionStatement, Duration)>,
+
+    // 服务端已请求LOAD DATA LOCAL的文件，客户端随后发送的文件内容不解析
+    load_data: bool,
+    // 多语句查询只解析第一个响应，之后的结果集到下一个请求前跳过
+    multi_statement: bool,
+    skip_responses: bool,
+}
+
+const MYSQL_SESSIONS_CAPACITY: usize = 1 << 16;
+
+// AppLogs在多条流间共享同一个MysqlLog，按flow_id保存各流的会话状态
+pub struct MysqlSessions(LruCache<u64, MysqlSession>);
+
+impl Default for MysqlSessions {
+    fn default() -> Self {
+        Self(LruCache::new(MYSQL_SESSIONS_CAPACITY))
+    }
+}
+
+impl MysqlSessions {
+    pub fn take(&mut self, flow_id: u64) -> MysqlSession {
+        self.0.pop(&flow_id).unwrap_or_default()
+    }
+
+    pub fn put(&mut self, flow_id: u64, session: MysqlSession) {
+        self.0.put(flow_id, session);
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct MysqlLog {
     info: MysqlInfo,
+    session: MysqlSession,
+    // 为空时不解析SQL注释
+    comment_trace_key: String,
+    // 当前解析的包的时间，用于计算事务时长
+    timestamp: Duration,
 
     l7_proto: L7Protocol,
     msg_type: LogMessageType,
@@ -96,15 +212,191 @@ fn mysql_string(payload: &[u8]) -> String {
     if payload.len() > 2 && payload[0] == 0 && payload[1] == 1 {
         // MYSQL 8.0.26返回字符串前有0x0、0x1，MYSQL 8.0.21版本没有这个问题
         // https://gitlab.yunshan.net/platform/trident/-/merge_requests/2592#note_401425
-        String::from_utf8_lossy(&payload[2..]).into_owned()
+        String::from_utf8_lossy(bytes::utf8_prefix(&payload[2..], usize::MAX)).into_owned()
     } else {
-        String::from_utf8_lossy(payload).into_owned()
+        String::from_utf8_lossy(bytes::utf8_prefix(payload, usize::MAX)).into_owned()
+    }
+}
+
+// 按分号拆分多语句查询，忽略字符串、标识符及注释中的分号，
+// 返回第一条语句及语句数，只有注释或空白的部分不计数
+fn split_statements(sql: &str) -> (&str, u32) {
+    let bytes = sql.as_bytes();
+    let mut first = None;
+    let mut count = 0;
+    let mut start = 0;
+    let mut has_content = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            q @ (b'\'' | b'"' | b'`') => {
+                has_content = true;
+                i += 1;
+                while i < bytes.len() && bytes[i] != q {
+                    if bytes[i] == b'\\' && q != b'`' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = match sql[i + 2..].find("*/") {
+                    Some(end) => i + 2 + end + 1,
+                    None => bytes.len(),
+                };
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-')
+                && bytes
+                    .get(i + 2)
+                    .map(|c| c.is_ascii_whitespace())
+                    .unwrap_or(true) =>
+            {
+                i = sql[i..]
+                    .find('\n')
+                    .map(|end| i + end)
+                    .unwrap_or(bytes.len());
+            }
+            b'#' => {
+                i = sql[i..]
+                    .find('\n')
+                    .map(|end| i + end)
+                    .unwrap_or(bytes.len());
+            }
+            b';' => {
+                if has_content {
+                    count += 1;
+                    first.get_or_insert(&sql[start..i]);
+                }
+                has_content = false;
+                start = i + 1;
+            }
+            c if !c.is_ascii_whitespace() => has_content = true,
+            _ => (),
+        }
+        i += 1;
+    }
+    if has_content {
+        count += 1;
+        first.get_or_insert(&sql[start..]);
     }
+    (first.unwrap_or(sql).trim(), count)
+}
+
+// 提取SQL注释中指定key的值，如: /* traceparent='00-xxx-yyy-01' */
+// 返回值及去掉该注释后的SQL
+fn extract_comment_trace_id(sql: &str, key: &str) -> Option<(String, String)> {
+    let mut offset = 0;
+    while let Some(start) = sql[offset..].find("/*") {
+        let start = offset + start;
+        let end = start + 2 + sql[start + 2..].find("*/")?;
+        for kv in sql[start + 2..end].split(',') {
+            let (k, v) = match kv.split_once('=') {
+                Some(kv) => kv,
+                None => continue,
+            };
+            if !k.trim().eq_ignore_ascii_case(key) {
+                continue;
+            }
+            let mut value = v.trim().trim_matches(|c| c == '\'' || c == '"');
+            // traceparent格式为: version-trace_id-span_id-flags
+            if key.eq_ignore_ascii_case("traceparent") {
+                value = value.split('-').nth(1).unwrap_or(value);
+            }
+            if value.is_empty() {
+                return None;
+            }
+            let (head, tail) = (sql[..start].trim_end(), sql[end + 2..].trim_start());
+            let mut stripped = String::with_capacity(head.len() + tail.len() + 1);
+            stripped.push_str(head);
+            if !head.is_empty() && !tail.is_empty() {
+                stripped.push(' ');
+            }
+            stripped.push_str(tail);
+            return Some((value.to_string(), stripped));
+        }
+        offset = end + 2;
+    }
+    None
 }
 
 impl MysqlLog {
+    pub fn new(config: &LogParserAccess) -> Self {
+        Self {
+            comment_trace_key: config.load().l7_log_sql_comment_trace_key.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_session(&mut self, session: MysqlSession) {
+        self.session = session;
+    }
+
+    pub fn take_session(&mut self) -> MysqlSession {
+        std::mem::take(&mut self.session)
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Duration) {
+        self.timestamp = timestamp;
+    }
+
+    fn transaction_request(&mut self) {
+        let session = &mut self.session;
+        let statement = match self.info.command {
+            COM_QUERY => TransactionStatement::parse(&self.info.context),
+            _ => None,
+        };
+        self.info.transaction_id = match statement {
+            // 事务中再次BEGIN会隐式提交之前的事务，这里先标记为新事务的ID
+            Some(TransactionStatement::Begin) => session.transaction_count + 1,
+            _ => session.transaction.map(|(id, _)| id).unwrap_or_default(),
+        };
+        session.pending_transaction = statement.map(|s| (s, self.timestamp));
+    }
+
+    fn transaction_response(&mut self) {
+        let session = &mut self.session;
+        let pending = session.pending_transaction.take();
+        if self.info.response_code == MYSQL_RESPONSE_CODE_OK {
+            match pending {
+                Some((TransactionStatement::Begin, start)) => {
+                    session.transaction_count += 1;
+                    session.transaction = Some((session.transaction_count, start));
+                }
+                Some((_, _)) => {
+                    if let Some((id, start)) = session.transaction.take() {
+                        self.info.transaction_id = id;
+                        self.info.transaction_duration =
+                            self.timestamp.saturating_sub(start).as_micros() as u64;
+                        return;
+                    }
+                }
+                None => (),
+            }
+        }
+        self.info.transaction_id = session.transaction.map(|(id, _)| id).unwrap_or_default();
+    }
+
     fn request_string(&mut self, payload: &[u8]) {
-        self.info.context = mysql_string(payload);
+        let sql = mysql_string(payload);
+        if !self.comment_trace_key.is_empty() {
+            if let Some((trace_id, stripped)) =
+                extract_comment_trace_id(&sql, &self.comment_trace_key)
+            {
+                self.info.trace_id = trace_id;
+                self.info.context = stripped;
+                return;
+            }
+        }
+        self.info.context = sql;
+    }
+
+    fn query_string(&mut self, payload: &[u8]) {
+        self.request_string(payload);
+        let (first, count) = split_statements(&self.info.context);
+        if count > 1 {
+            self.info.context = first.to_owned();
+            self.info.statement_count = count;
+        }
     }
 
     fn reset_logs(&mut self) {
@@ -124,7 +416,9 @@ impl MysqlLog {
     fn greeting(&mut self, payload: &[u8]) -> Result<()> {
         let mut remain = payload.len();
         if remain < PROTOCOL_VERSION_LEN {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Response).mismatch(PROTOCOL_VERSION_LEN, remain),
+            ));
         }
         self.info.protocol_version = payload[PROTOCOL_VERSION_OFFSET];
         remain -= PROTOCOL_VERSION_LEN;
@@ -133,38 +427,111 @@ impl MysqlLog {
             .position(|&x| x == SERVER_VERSION_EOF)
             .unwrap_or_default();
         if server_version_pos <= 0 {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Response).at(SERVER_VERSION_OFFSET),
+            ));
         }
-        self.info.server_version = String::from_utf8_lossy(
+        self.info.server_version = intern(&String::from_utf8_lossy(
             &payload[SERVER_VERSION_OFFSET..SERVER_VERSION_OFFSET + server_version_pos],
-        )
-        .into_owned();
-        remain -= server_version_pos as usize;
+        ));
+        // server version及其结尾的0
+        remain -= server_version_pos as usize + 1;
+        let thread_id_offset = THREAD_ID_OFFSET_B + server_version_pos + 1;
         if remain < THREAD_ID_LEN {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Response)
+                    .at(thread_id_offset)
+                    .mismatch(THREAD_ID_LEN, remain),
+            ));
         }
-        let thread_id_offset = THREAD_ID_OFFSET_B + server_version_pos + 1;
         self.info.server_thread_id = bytes::read_u32_le(&payload[thread_id_offset..]);
         self.l7_proto = L7Protocol::Mysql;
+        // 新连接
+        self.session = MysqlSession::default();
         Ok(())
     }
 
     fn request(&mut self, payload: &[u8]) -> Result<()> {
         if payload.len() < COMMAND_LEN {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Request).mismatch(COMMAND_LEN, payload.len()),
+            ));
         }
         self.info.command = payload[COMMAND_OFFSET];
         match self.info.command {
             COM_QUIT | COM_FIELD_LIST | COM_STMT_EXECUTE | COM_STMT_CLOSE | COM_STMT_FETCH => (),
-            COM_INIT_DB | COM_QUERY | COM_STMT_PREPARE => {
+            COM_QUERY => self.query_string(&payload[COMMAND_OFFSET + COMMAND_LEN..]),
+            COM_INIT_DB | COM_STMT_PREPARE => {
                 self.request_string(&payload[COMMAND_OFFSET + COMMAND_LEN..]);
             }
-            _ => return Err(Error::MysqlLogParseFailed),
+            _ => {
+                return Err(Error::MysqlLogParseFailed(
+                    ParseContext::new(ParseStage::Request).at(COMMAND_OFFSET),
+                ))
+            }
+        }
+        if self.info.command == COM_INIT_DB {
+            self.session.pending_database = Some(self.info.context.clone());
         }
+        self.info.database = self.session.database.clone();
+        self.transaction_request();
+        self.session.multi_statement = self.info.statement_count > 1;
+        self.session.skip_responses = false;
         self.l7_proto = L7Protocol::Mysql;
         Ok(())
     }
 
+    // 返回length-encoded integer的值及其占用的字节数
+    fn decode_length_encoded_int(payload: &[u8]) -> Option<(u64, usize)> {
+        let (&flag, remain) = payload.split_first()?;
+        let len = match flag {
+            INT_FLAGS_2 => 2,
+            INT_FLAGS_3 => 3,
+            INT_FLAGS_8 => 8,
+            v if v < 0xfb => return Some((v as u64, INT_BASE_LEN)),
+            _ => return None,
+        };
+        let value = remain
+            .get(..len)?
+            .iter()
+            .rev()
+            .fold(0, |v, &b| (v << 8) | b as u64);
+        Some((value, INT_BASE_LEN + len))
+    }
+
+    // 返回length-encoded string及其占用的字节数
+    fn decode_length_encoded_string(payload: &[u8]) -> Option<(&[u8], usize)> {
+        let (len, offset) = Self::decode_length_encoded_int(payload)?;
+        let end = offset.checked_add(len as usize)?;
+        Some((payload.get(offset..end)?, end))
+    }
+
+    // 从OK包的session state info中解析切换后的数据库，需要客户端开启CLIENT_SESSION_TRACK
+    fn session_state_schema(payload: &[u8]) -> Option<String> {
+        let mut offset = AFFECTED_ROWS_OFFSET;
+        // affected rows和last insert id
+        for _ in 0..2 {
+            offset += Self::decode_length_encoded_int(payload.get(offset..)?)?.1;
+        }
+        let status_flags = bytes::read_u16_le(payload.get(offset..offset + STATUS_FLAGS_LEN)?);
+        if status_flags & SERVER_SESSION_STATE_CHANGED == 0 {
+            return None;
+        }
+        offset += STATUS_FLAGS_LEN + WARNINGS_LEN;
+        // info
+        offset += Self::decode_length_encoded_string(payload.get(offset..)?)?.1;
+        let (mut state, _) = Self::decode_length_encoded_string(payload.get(offset..)?)?;
+        while let Some((&track_type, remain)) = state.split_first() {
+            let (data, len) = Self::decode_length_encoded_string(remain)?;
+            if track_type == SESSION_TRACK_SCHEMA {
+                let (schema, _) = Self::decode_length_encoded_string(data)?;
+                return Some(String::from_utf8_lossy(schema).into_owned());
+            }
+            state = &remain[len..];
+        }
+        None
+    }
+
     fn decode_compress_int(payload: &[u8]) -> u64 {
         let remain = payload.len();
         if remain == 0 {
@@ -201,10 +568,13 @@ impl MysqlLog {
     fn response(&mut self, payload: &[u8]) -> Result<()> {
         let mut remain = payload.len();
         if remain < RESPONSE_CODE_LEN {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Response).mismatch(RESPONSE_CODE_LEN, remain),
+            ));
         }
         self.info.response_code = payload[RESPONSE_CODE_OFFSET];
         remain -= RESPONSE_CODE_LEN;
+        let pending_database = self.session.pending_database.take();
         match self.info.response_code {
             MYSQL_RESPONSE_CODE_ERR => {
                 if remain > ERROR_CODE_LEN {
@@ -218,16 +588,24 @@ impl MysqlLog {
                     } else {
                         SQL_STATE_OFFSET
                     };
-                self.info.error_message =
-                    String::from_utf8_lossy(&payload[error_message_offset..]).into_owned();
+                self.info.error_message = payload
+                    .get(error_message_offset..)
+                    .map(|m| String::from_utf8_lossy(m).into_owned())
+                    .unwrap_or_default();
             }
             MYSQL_RESPONSE_CODE_OK => {
                 self.status = L7ResponseStatus::Ok;
                 self.info.affected_rows =
                     MysqlLog::decode_compress_int(&payload[AFFECTED_ROWS_OFFSET..]);
+                if let Some(database) = Self::session_state_schema(payload).or(pending_database) {
+                    self.session.database = database;
+                }
             }
             _ => (),
         }
+        self.info.database = self.session.database.clone();
+        self.transaction_response();
+        self.session.skip_responses = self.session.multi_statement;
         Ok(())
     }
 }
@@ -244,21 +622,45 @@ impl L7LogParse for MysqlLog {
         }
         self.reset_logs();
 
+        let session = &mut self.session;
+        match direction {
+            PacketDirection::ClientToServer if session.load_data => {
+                return Err(Error::MysqlPayloadSkipped)
+            }
+            // 文件发送结束后服务端回复OK或ERR
+            PacketDirection::ServerToClient if session.load_data => session.load_data = false,
+            PacketDirection::ServerToClient if session.skip_responses => {
+                return Err(Error::MysqlPayloadSkipped)
+            }
+            PacketDirection::ServerToClient
+                if self.l7_proto == L7Protocol::Mysql
+                    && MysqlHeader::local_infile_request(payload) =>
+            {
+                session.load_data = true;
+                return Err(Error::MysqlPayloadSkipped);
+            }
+            _ => (),
+        }
+
         let mut header = MysqlHeader::default();
         let offset = header.decode(payload);
         if offset < 0 {
-            return Err(Error::MysqlLogParseFailed);
+            return Err(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Header).mismatch(HEADER_LEN + 1, payload.len()),
+            ));
         }
         let offset = offset as usize;
         let msg_type = header
             .check(direction, offset, payload, self.l7_proto)
-            .ok_or(Error::MysqlLogParseFailed)?;
+            .ok_or(Error::MysqlLogParseFailed(
+                ParseContext::new(ParseStage::Header).at(offset),
+            ))?;
 
         match msg_type {
             LogMessageType::Request => self.request(&payload[offset..])?,
             LogMessageType::Response => self.response(&payload[offset..])?,
             LogMessageType::Other => self.greeting(&payload[offset..])?,
-            _ => return Err(Error::MysqlLogParseFailed),
+            _ => return Err(Error::MysqlLogParseFailed(ParseStage::Session.into())),
         };
         self.msg_type = msg_type;
 
@@ -269,6 +671,7 @@ impl L7LogParse for MysqlLog {
             code: self.info.error_code,
             rrt: 0,
             version: 0,
+            ..Default::default()
         }))
     }
 
@@ -284,6 +687,14 @@ pub struct MysqlHeader {
 }
 
 impl MysqlHeader {
+    // 服务端请求LOAD DATA LOCAL的文件，为COM_QUERY响应的第一个包，内容为0xfb及文件名
+    pub fn local_infile_request(payload: &[u8]) -> bool {
+        payload.len() > HEADER_LEN + 1
+            && payload[NUMBER_OFFSET] == 1
+            && payload[HEADER_LEN] == MYSQL_RESPONSE_CODE_LOCAL_INFILE
+            && (bytes::read_u32_le(payload) & 0xffffff) as usize == payload.len() - HEADER_LEN
+    }
+
     pub fn decode(&mut self, payload: &[u8]) -> isize {
         if payload.len() < 5 {
             return -1;
@@ -364,7 +775,10 @@ pub fn mysql_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     }
     let offset = offset as usize;
 
-    if header.number != 0 || offset + header.length as usize > payload.len() {
+    if header.number != 0
+        || offset >= payload.len()
+        || offset + header.length as usize > payload.len()
+    {
         return false;
     }
 
@@ -379,6 +793,19 @@ pub fn mysql_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     return false;
 }
 
+impl MysqlLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut mysql = MysqlLog::default();
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let _ = mysql.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_mysql = mysql_check_protocol(&mut bitmap, packet);
+            output.push_str(&format!("{:?} is_mysql: {}\r\n", mysql.info, is_mysql));
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -391,32 +818,211 @@ mod tests {
     const FILE_DIR: &str = "resources/test/flow_generator/mysql";
 
     fn run(name: &str) -> String {
-        let pcap_file = Path::new(FILE_DIR).join(name);
-        let capture = Capture::load_pcap(pcap_file, Some(1400));
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1400));
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
+        MysqlLog::golden_output(&mut packets)
+    }
 
+    #[test]
+    fn comment_trace_id() {
+        let sql = "SELECT * FROM t /* traceparent='00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01',tracestate='x' */ WHERE id = 1";
+        assert_eq!(
+            extract_comment_trace_id(sql, "traceparent"),
+            Some((
+                "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+                "SELECT * FROM t WHERE id = 1".to_string()
+            ))
+        );
+        assert_eq!(
+            extract_comment_trace_id("/* hint */ /*request_id=abc*/ SELECT 1", "request_id"),
+            Some(("abc".to_string(), "/* hint */ SELECT 1".to_string()))
+        );
+        assert_eq!(
+            extract_comment_trace_id("/* hint */ SELECT 1", "request_id"),
+            None
+        );
+        assert_eq!(
+            extract_comment_trace_id("SELECT 1 /* request_id=abc", "request_id"),
+            None
+        );
+    }
+
+    #[test]
+    fn database_tracking() {
         let mut mysql = MysqlLog::default();
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-            let _ = mysql.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_mysql = mysql_check_protocol(&mut bitmap, packet);
-            output.push_str(&format!("{:?} is_mysql: {}\r\n", mysql.info, is_mysql));
-        }
-        output
+        let mut parse = |payload: &[u8], direction| {
+            mysql.parse(payload, IpProtocol::Tcp, direction).unwrap();
+            mysql.info.database.clone()
+        };
+        let use_db = [4, 0, 0, 0, COM_INIT_DB, b'd', b'b', b'1'];
+        let ok = [7, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0];
+        let query = [
+            9, 0, 0, 0, COM_QUERY, b's', b'e', b'l', b'e', b'c', b't', b' ', b'1',
+        ];
+        assert_eq!(parse(&use_db, PacketDirection::ClientToServer), "");
+        assert_eq!(parse(&ok, PacketDirection::ServerToClient), "db1");
+        assert_eq!(parse(&query, PacketDirection::ClientToServer), "db1");
+
+        // OK包session state中的schema变化
+        let mut ok = vec![
+            0x14, 0, 0, 1, 0, 0, 0, 2, 0x40, 0, 0, 0, 0x0b, 0x01, 0x09, 0x08,
+        ];
+        ok.extend_from_slice(b"yuanchao");
+        assert_eq!(
+            MysqlLog::session_state_schema(&ok[HEADER_LEN..]),
+            Some("yuanchao".to_string())
+        );
+        assert_eq!(parse(&ok, PacketDirection::ServerToClient), "yuanchao");
+        assert_eq!(parse(&query, PacketDirection::ClientToServer), "yuanchao");
+    }
+
+    #[test]
+    fn transaction_tracking() {
+        assert_eq!(
+            TransactionStatement::parse("/* app */ start transaction read only"),
+            Some(TransactionStatement::Begin)
+        );
+        assert_eq!(
+            TransactionStatement::parse("BEGIN;"),
+            Some(TransactionStatement::Begin)
+        );
+        assert_eq!(
+            TransactionStatement::parse("rollback work to savepoint s1"),
+            None
+        );
+        assert_eq!(TransactionStatement::parse("SELECT 'BEGIN'"), None);
+
+        let query = |sql: &[u8]| {
+            let mut payload = vec![sql.len() as u8 + 1, 0, 0, 0, COM_QUERY];
+            payload.extend_from_slice(sql);
+            payload
+        };
+        let ok = [7, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0];
+        let mut mysql = MysqlLog::default();
+        let mut parse = |payload: &[u8], direction, millis| {
+            mysql.set_timestamp(Duration::from_millis(millis));
+            mysql.parse(payload, IpProtocol::Tcp, direction).unwrap();
+            (mysql.info.transaction_id, mysql.info.transaction_duration)
+        };
+        let (c2s, s2c) = (
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(parse(&query(b"select 1"), c2s, 0), (0, 0));
+        assert_eq!(parse(&ok, s2c, 1), (0, 0));
+        assert_eq!(parse(&query(b"begin"), c2s, 10), (1, 0));
+        assert_eq!(parse(&ok, s2c, 11), (1, 0));
+        assert_eq!(parse(&query(b"update t set a = 1"), c2s, 20), (1, 0));
+        assert_eq!(parse(&ok, s2c, 21), (1, 0));
+        assert_eq!(parse(&query(b"commit"), c2s, 30), (1, 0));
+        assert_eq!(parse(&ok, s2c, 32), (1, 22000));
+        assert_eq!(parse(&query(b"select 1"), c2s, 40), (0, 0));
+        assert_eq!(parse(&ok, s2c, 41), (0, 0));
+
+        // BEGIN失败时不开始事务
+        let err = [3, 0, 0, 1, MYSQL_RESPONSE_CODE_ERR, 0x15, 0x04];
+        assert_eq!(parse(&query(b"START TRANSACTION"), c2s, 50), (2, 0));
+        assert_eq!(parse(&err, s2c, 51), (0, 0));
+        assert_eq!(parse(&query(b"START TRANSACTION"), c2s, 60), (2, 0));
+        assert_eq!(parse(&ok, s2c, 61), (2, 0));
+        assert_eq!(parse(&query(b"ROLLBACK"), c2s, 70), (2, 0));
+        assert_eq!(parse(&ok, s2c, 70), (2, 10000));
+    }
+
+    #[test]
+    fn multi_statement_and_load_data() {
+        assert_eq!(
+            split_statements("select ';' from t; -- c;\n update t set a = \"\\\";\";"),
+            ("select ';' from t", 2)
+        );
+        assert_eq!(
+            split_statements("select 1 /* ; */ ; # ;"),
+            ("select 1 /* ; */", 1)
+        );
+        assert_eq!(
+            split_statements("select `a;b` from t"),
+            ("select `a;b` from t", 1)
+        );
+
+        let query = |sql: &[u8]| {
+            let mut payload = vec![sql.len() as u8 + 1, 0, 0, 0, COM_QUERY];
+            payload.extend_from_slice(sql);
+            payload
+        };
+        let ok = |number: u8| [7, 0, 0, number, 0, 1, 0, 0, 0, 0, 0];
+        let (c2s, s2c) = (
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        );
+        let mut mysql = MysqlLog::default();
+
+        // 多语句查询只解析第一个响应
+        mysql
+            .parse(
+                &query(b"insert into t values (1); select 2"),
+                IpProtocol::Tcp,
+                c2s,
+            )
+            .unwrap();
+        assert_eq!(mysql.info.context, "insert into t values (1)");
+        assert_eq!(mysql.info.statement_count, 2);
+        mysql.parse(&ok(1), IpProtocol::Tcp, s2c).unwrap();
+        assert_eq!(mysql.info.affected_rows, 1);
+        assert!(matches!(
+            mysql.parse(&ok(2), IpProtocol::Tcp, s2c),
+            Err(Error::MysqlPayloadSkipped)
+        ));
+
+        // LOAD DATA LOCAL的文件内容不作为请求解析
+        mysql
+            .parse(
+                &query(b"LOAD DATA LOCAL INFILE 'a' INTO TABLE t"),
+                IpProtocol::Tcp,
+                c2s,
+            )
+            .unwrap();
+        assert_eq!(mysql.info.statement_count, 0);
+        let infile = [2, 0, 0, 1, MYSQL_RESPONSE_CODE_LOCAL_INFILE, b'a'];
+        assert!(mysql.parse(&infile, IpProtocol::Tcp, s2c).is_err());
+        let content = [4, 0, 0, 0, COM_QUERY, b'1', b',', b'2'];
+        assert!(matches!(
+            mysql.parse(&content, IpProtocol::Tcp, c2s),
+            Err(Error::MysqlPayloadSkipped)
+        ));
+        assert!(mysql.parse(&[0, 0, 0, 3], IpProtocol::Tcp, c2s).is_err());
+        let head = mysql.parse(&ok(4), IpProtocol::Tcp, s2c).unwrap();
+        assert!(matches!(
+            head,
+            AppProtoHeadEnum::Single(AppProtoHead {
+                msg_type: LogMessageType::Response,
+                ..
+            })
+        ));
+        mysql
+            .parse(&query(b"select 1"), IpProtocol::Tcp, c2s)
+            .unwrap();
+        assert_eq!(mysql.info.context, "select 1");
+    }
+
+    #[test]
+    fn malformed_payload() {
+        let mut mysql = MysqlLog::default();
+        // server version之后不足4字节的thread id
+        let greeting = [6, 0, 0, 0, PROTOCOL_VERSION, b'5', 0, 1, 2, 3];
+        assert!(mysql
+            .parse(&greeting, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_err());
+
+        let query = [2, 0, 0, 0, COM_QUERY, b'1'];
+        assert!(mysql
+            .parse(&query, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .is_ok());
+        // 截断的ERR包
+        let err = [2, 0, 0, 1, MYSQL_RESPONSE_CODE_ERR, 1];
+        assert!(mysql
+            .parse(&err, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_ok());
+        assert_eq!(mysql.info.error_message, "");
     }
 
     #[test]
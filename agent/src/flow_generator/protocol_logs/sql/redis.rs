@@ -20,16 +20,48 @@ use std::{fmt, str};
 
 use super::super::{
     value_is_default, AppProtoHead, AppProtoLogsInfo, L7LogParse, L7Protocol, L7ResponseStatus,
-    LogMessageType,
+    L7SessionId, LogMessageType,
 };
 
 use crate::common::enums::{IpProtocol, PacketDirection};
 use crate::common::meta_packet::MetaPacket;
-use crate::flow_generator::error::{Error, Result};
-use crate::flow_generator::{AppProtoHeadEnum, AppProtoLogsInfoEnum};
+use crate::flow_generator::error::{Error, ParseContext, ParseStage, Result};
+use crate::flow_generator::{golden, AppProtoHeadEnum, AppProtoLogsInfoEnum};
 use crate::proto::flow_log;
+use crate::utils::bytes;
 
 const SEPARATOR_SIZE: usize = 2;
+// key模式的最大长度，超出时截断
+const KEY_PATTERN_MAX_LEN: usize = 128;
+// 先按层级分隔符切分，再按词内分隔符切分，UUID含'-'须在第一层识别
+const KEY_SEPARATORS: [&[u8]; 2] = [b":/.|#={}", b"_-"];
+// 参数不是key的命令
+const KEYLESS_COMMANDS: [&[u8]; 24] = [
+    b"AUTH",
+    b"HELLO",
+    b"SELECT",
+    b"PING",
+    b"ECHO",
+    b"QUIT",
+    b"INFO",
+    b"CONFIG",
+    b"CLIENT",
+    b"CLUSTER",
+    b"COMMAND",
+    b"DBSIZE",
+    b"FLUSHDB",
+    b"FLUSHALL",
+    b"SLOWLOG",
+    b"SCAN",
+    b"KEYS",
+    b"MULTI",
+    b"EXEC",
+    b"DISCARD",
+    b"SCRIPT",
+    b"EVAL",
+    b"EVALSHA",
+    b"PUBLISH",
+];
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct RedisInfo {
@@ -58,6 +90,14 @@ pub struct RedisInfo {
         serialize_with = "vec_u8_to_string"
     )]
     pub error: Vec<u8>, // '-'
+
+    // 集群重定向：MOVED或ASK
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub redirect_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_slot: Option<u16>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub redirect_host: String,
 }
 
 pub fn vec_u8_to_string<S>(v: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error>
@@ -72,6 +112,26 @@ impl RedisInfo {
         self.response = other.response;
         self.status = other.status;
         self.error = other.error;
+        self.redirect_type = other.redirect_type;
+        self.redirect_slot = other.redirect_slot;
+        self.redirect_host = other.redirect_host;
+    }
+}
+
+impl RedisInfo {
+    // 请求中第一个key的模式，无key的命令返回None
+    pub fn key_pattern(&self) -> Option<String> {
+        if KEYLESS_COMMANDS
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&self.request_type))
+        {
+            return None;
+        }
+        let key = self.request.split(|b| *b == b' ').nth(1)?;
+        if key.is_empty() {
+            return None;
+        }
+        Some(key_pattern(key))
     }
 }
 
@@ -99,12 +159,19 @@ impl fmt::Display for RedisInfo {
         )?;
         write!(
             f,
-            "error: {:?} }}",
+            "error: {:?}, ",
             str::from_utf8(&self.error).unwrap_or_default()
+        )?;
+        write!(
+            f,
+            "redirect_type: {:?}, redirect_slot: {:?}, redirect_host: {:?} }}",
+            self.redirect_type, self.redirect_slot, self.redirect_host
         )
     }
 }
 
+impl L7SessionId for RedisInfo {}
+
 impl From<RedisInfo> for flow_log::RedisInfo {
     fn from(f: RedisInfo) -> Self {
         flow_log::RedisInfo {
@@ -113,6 +180,9 @@ impl From<RedisInfo> for flow_log::RedisInfo {
             response: f.response,
             status: f.status,
             error: f.error,
+            redirect_type: f.redirect_type,
+            redirect_slot: f.redirect_slot.unwrap_or_default() as u32,
+            redirect_host: f.redirect_host,
         }
     }
 }
@@ -149,6 +219,15 @@ impl RedisLog {
         match context[0] {
             b'+' => self.info.status = context,
             b'-' if error_response => {
+                // 集群重定向不是服务端错误
+                if let Some((redirect_type, slot, host)) = decode_redirect(&context) {
+                    self.info.redirect_type = redirect_type.to_string();
+                    self.info.redirect_slot = Some(slot);
+                    self.info.redirect_host = host.to_string();
+                    self.info.response = context;
+                    self.status = L7ResponseStatus::Redirect;
+                    return;
+                }
                 self.info.error = context;
                 self.status = L7ResponseStatus::ServerError;
             }
@@ -171,8 +250,15 @@ impl L7LogParse for RedisLog {
 
         self.reset();
         let (context, _, error_response) =
-            decode(payload, direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisLogParseFailed)?;
+            decode(payload, direction == PacketDirection::ClientToServer).ok_or(
+                Error::RedisLogParseFailed(ParseContext::new(
+                    if direction == PacketDirection::ClientToServer {
+                        ParseStage::Request
+                    } else {
+                        ParseStage::Response
+                    },
+                )),
+            )?;
         match direction {
             PacketDirection::ClientToServer => self.fill_request(context),
             PacketDirection::ServerToClient => self.fill_response(context, error_response),
@@ -184,6 +270,7 @@ impl L7LogParse for RedisLog {
             code: 0,
             rrt: 0,
             version: 0,
+            ..Default::default()
         }))
     }
 
@@ -342,6 +429,75 @@ pub fn decode_error_code(context: &[u8]) -> Option<&[u8]> {
     None
 }
 
+// 集群重定向回复，格式为"-MOVED 3999 127.0.0.1:6381"或"-ASK 3999 127.0.0.1:6381"
+pub fn decode_redirect(context: &[u8]) -> Option<(&str, u16, &str)> {
+    let context = str::from_utf8(context.strip_prefix(b"-")?).ok()?;
+    let mut fields = context.split(' ');
+    let redirect_type = match fields.next()? {
+        t @ ("MOVED" | "ASK") => t,
+        _ => return None,
+    };
+    let slot = fields.next()?.parse::<u16>().ok()?;
+    let host = fields.next()?;
+    if host.is_empty() || fields.next().is_some() {
+        return None;
+    }
+    Some((redirect_type, slot, host))
+}
+
+fn is_uuid(seg: &[u8]) -> bool {
+    seg.len() == 36
+        && seg.iter().enumerate().all(|(i, b)| match i {
+            8 | 13 | 18 | 23 => *b == b'-',
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+// 数字、UUID及较长的十六进制串(哈希、ObjectId等)
+fn is_variable_segment(seg: &[u8]) -> bool {
+    if seg.is_empty() {
+        return false;
+    }
+    seg.iter().all(u8::is_ascii_digit)
+        || is_uuid(seg)
+        || seg.len() >= 16
+            && seg.iter().all(u8::is_ascii_hexdigit)
+            && seg.iter().any(u8::is_ascii_digit)
+}
+
+fn push_key_pattern(pattern: &mut Vec<u8>, key: &[u8], separators: &[&[u8]]) {
+    let (outer, inner) = match separators.split_first() {
+        Some(s) => s,
+        None => {
+            pattern.extend_from_slice(key);
+            return;
+        }
+    };
+    let mut start = 0;
+    for i in 0..=key.len() {
+        if i < key.len() && !outer.contains(&key[i]) {
+            continue;
+        }
+        let seg = &key[start..i];
+        if is_variable_segment(seg) {
+            pattern.push(b'*');
+        } else {
+            push_key_pattern(pattern, seg, inner);
+        }
+        if i < key.len() {
+            pattern.push(key[i]);
+        }
+        start = i + 1;
+    }
+}
+
+// 将key中的数字、UUID等可变段替换为'*'，例如"user:1001:profile"为"user:*:profile"
+pub fn key_pattern(key: &[u8]) -> String {
+    let mut pattern = Vec::with_capacity(key.len());
+    push_key_pattern(&mut pattern, key, &KEY_SEPARATORS);
+    String::from_utf8_lossy(bytes::utf8_prefix(&pattern, KEY_PATTERN_MAX_LEN)).into_owned()
+}
+
 // 通过请求识别REDIS
 pub fn redis_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     if packet.lookup_key.proto != IpProtocol::Tcp {
@@ -361,6 +517,19 @@ pub fn redis_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
     return decode_asterisk(payload, true).is_some();
 }
 
+impl RedisLog {
+    // 逐包解析的输出，用于测试及golden文件生成
+    pub(crate) fn golden_output(packets: &mut [MetaPacket]) -> String {
+        let mut bitmap = 0;
+        golden::parse_payloads(packets, |payload, packet, output| {
+            let mut redis = RedisLog::default();
+            let _ = redis.parse(payload, packet.lookup_key.proto, packet.direction);
+            let is_redis = redis_check_protocol(&mut bitmap, packet);
+            output.push_str(&format!("{} is_redis: {}\r\n", redis.info, is_redis));
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -373,33 +542,9 @@ mod tests {
     const FILE_DIR: &str = "resources/test/flow_generator/redis";
 
     fn run(name: &str) -> String {
-        let pcap_file = Path::new(FILE_DIR).join(name);
-        let capture = Capture::load_pcap(pcap_file, None);
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
         let mut packets = capture.as_meta_packets();
-        if packets.is_empty() {
-            return "".to_string();
-        }
-
-        let mut output: String = String::new();
-        let first_dst_port = packets[0].lookup_key.dst_port;
-        let mut bitmap = 0;
-        for packet in packets.iter_mut() {
-            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
-                PacketDirection::ClientToServer
-            } else {
-                PacketDirection::ServerToClient
-            };
-            let payload = match packet.get_l4_payload() {
-                Some(p) => p,
-                None => continue,
-            };
-
-            let mut redis = RedisLog::default();
-            let _ = redis.parse(payload, packet.lookup_key.proto, packet.direction);
-            let is_redis = redis_check_protocol(&mut bitmap, packet);
-            output.push_str(&format!("{} is_redis: {}\r\n", redis.info, is_redis));
-        }
-        output
+        RedisLog::golden_output(&mut packets)
     }
 
     #[test]
@@ -471,4 +616,60 @@ mod tests {
         assert_eq!(n, 2);
         assert_eq!(e, true);
     }
+
+    #[test]
+    fn parse_redirect() {
+        assert_eq!(
+            decode_redirect(b"-MOVED 3999 127.0.0.1:6381"),
+            Some(("MOVED", 3999, "127.0.0.1:6381"))
+        );
+        assert_eq!(
+            decode_redirect(b"-ASK 3999 [::1]:6381"),
+            Some(("ASK", 3999, "[::1]:6381"))
+        );
+        assert_eq!(decode_redirect(b"-MOVED 99999 127.0.0.1:6381"), None);
+        assert_eq!(decode_redirect(b"-ERR unknown command"), None);
+
+        let mut redis = RedisLog::default();
+        let _ = redis.parse(
+            b"-MOVED 3999 127.0.0.1:6381\r\n",
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(redis.status, L7ResponseStatus::Redirect);
+        assert_eq!(redis.info.redirect_type, "MOVED");
+        assert_eq!(redis.info.redirect_slot, Some(3999));
+        assert_eq!(redis.info.redirect_host, "127.0.0.1:6381");
+        assert!(redis.info.error.is_empty());
+    }
+
+    #[test]
+    fn key_patterns() {
+        assert_eq!(key_pattern(b"user:1001:profile"), "user:*:profile");
+        assert_eq!(
+            key_pattern(b"session:550e8400-e29b-41d4-a716-446655440000"),
+            "session:*"
+        );
+        assert_eq!(key_pattern(b"order_12345"), "order_*");
+        assert_eq!(
+            key_pattern(b"cache/5d41402abc4b2a76b9719d911017c592"),
+            "cache/*"
+        );
+        assert_eq!(key_pattern(b"{shop-7}:cart"), "{shop-*}:cart");
+        assert_eq!(key_pattern(b"feed:deadbeef"), "feed:deadbeef");
+        assert_eq!(key_pattern(b"42"), "*");
+
+        let info = RedisInfo {
+            request: b"HGET user:1001:profile name".to_vec(),
+            request_type: b"HGET".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(info.key_pattern().as_deref(), Some("user:*:profile"));
+        let info = RedisInfo {
+            request: b"auth secret".to_vec(),
+            request_type: b"auth".to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(info.key_pattern(), None);
+    }
 }
@@ -0,0 +1,682 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use super::super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseContext, ParseStage, Result},
+    utils::bytes::{read_u16_be, read_u16_le, read_u32_le, read_u64_le},
+};
+
+// 按ProcID调用的系统存储过程，序号从1开始
+const RPC_PROC_NAMES: [&str; 15] = [
+    "sp_cursor",
+    "sp_cursoropen",
+    "sp_cursorprepare",
+    "sp_cursorexecute",
+    "sp_cursorprepexec",
+    "sp_cursorunprepare",
+    "sp_cursorfetch",
+    "sp_cursoroption",
+    "sp_cursorclose",
+    "sp_executesql",
+    "sp_prepare",
+    "sp_execute",
+    "sp_prepexec",
+    "sp_prepexecrpc",
+    "sp_unprepare",
+];
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct TdsInfo {
+    #[serde(rename = "request_type")]
+    pub packet_type: u8,
+    // SQL Batch的语句，或RPC调用sp_executesql等时的语句参数
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub statement: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub proc_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub error_number: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub error_class: u8,
+    #[serde(
+        rename = "response_execption",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "sql_affected_rows", skip_serializing_if = "value_is_default")]
+    pub affected_rows: u64,
+}
+
+impl TdsInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.error_number = other.error_number;
+        self.error_class = other.error_class;
+        self.error_message = other.error_message;
+        self.affected_rows = other.affected_rows;
+    }
+}
+
+impl L7SessionId for TdsInfo {}
+
+impl From<TdsInfo> for flow_log::TdsInfo {
+    fn from(f: TdsInfo) -> Self {
+        flow_log::TdsInfo {
+            packet_type: f.packet_type as u32,
+            statement: f.statement,
+            proc_name: f.proc_name,
+            error_number: f.error_number,
+            error_class: f.error_class as u32,
+            error_message: f.error_message,
+            affected_rows: f.affected_rows,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TdsHeader {
+    pub packet_type: u8,
+    pub status: u8,
+    pub length: u16,
+    pub packet_id: u8,
+}
+
+impl TdsHeader {
+    // 包头8字节: type, status, length(大端，含包头), spid, packet_id, window(固定为0)
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() < TDS_HEADER_LEN || payload[7] != 0 {
+            return None;
+        }
+        let header = Self {
+            packet_type: payload[0],
+            status: payload[1],
+            length: read_u16_be(&payload[2..]),
+            packet_id: payload[6],
+        };
+        if (header.length as usize) < TDS_HEADER_LEN || header.status & !TDS_STATUS_MASK != 0 {
+            return None;
+        }
+        Some(header)
+    }
+
+    // 一个消息可能拆分为多个TDS包，请求只解析首包，响应只解析带EOM标记的尾包
+    pub fn msg_type(&self, direction: PacketDirection) -> Option<LogMessageType> {
+        match (self.packet_type, direction) {
+            (TDS_PACKET_SQL_BATCH, PacketDirection::ClientToServer)
+            | (TDS_PACKET_RPC, PacketDirection::ClientToServer)
+            | (TDS_PACKET_ATTENTION, PacketDirection::ClientToServer)
+                if self.packet_id <= 1 =>
+            {
+                Some(LogMessageType::Request)
+            }
+            (TDS_PACKET_TABULAR_RESULT, PacketDirection::ServerToClient)
+                if self.status & TDS_STATUS_EOM != 0 =>
+            {
+                Some(LogMessageType::Response)
+            }
+            _ => None,
+        }
+    }
+}
+
+// 响应中的ERROR及DONE/DONEPROC/DONEINPROC token
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TdsResponse {
+    pub error_number: u32,
+    pub error_class: u8,
+    pub error_message: String,
+    pub affected_rows: u64,
+    pub done_error: bool,
+    done: bool,
+}
+
+impl TdsResponse {
+    // 依次遍历定长或带长度的token，遇到COLMETADATA、ROW等需要按列类型解析的token时只检查包末尾的DONE，
+    // 登录响应(LOGINACK)及不以DONE结尾的数据返回None
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut response = Self::default();
+        let mut offset = 0;
+        while offset < data.len() {
+            match data[offset] {
+                TDS_TOKEN_DONE | TDS_TOKEN_DONEPROC | TDS_TOKEN_DONEINPROC => {
+                    response.on_done(data.get(offset + 1..offset + TDS_DONE_TOKEN_LEN)?);
+                    offset += TDS_DONE_TOKEN_LEN;
+                }
+                TDS_TOKEN_RETURNSTATUS => offset += 5,
+                TDS_TOKEN_LOGINACK => return None,
+                TDS_TOKEN_ERROR | TDS_TOKEN_INFO | TDS_TOKEN_ENVCHANGE | TDS_TOKEN_ORDER => {
+                    let len = read_u16_le(data.get(offset + 1..offset + 3)?) as usize;
+                    let token = data.get(offset + 3..offset + 3 + len)?;
+                    // 只记录第一个错误
+                    if data[offset] == TDS_TOKEN_ERROR && response.error_number == 0 {
+                        response.on_error(token);
+                    }
+                    offset += 3 + len;
+                }
+                _ => {
+                    if data.len() >= offset + TDS_DONE_TOKEN_LEN {
+                        let tail = &data[data.len() - TDS_DONE_TOKEN_LEN..];
+                        if let TDS_TOKEN_DONE | TDS_TOKEN_DONEPROC | TDS_TOKEN_DONEINPROC = tail[0]
+                        {
+                            response.on_done(&tail[1..]);
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        if response.done {
+            Some(response)
+        } else {
+            None
+        }
+    }
+
+    // number(4), state(1), class(1), 消息长度(2, 字符数)及UCS-2消息
+    fn on_error(&mut self, token: &[u8]) {
+        if token.len() < 8 {
+            return;
+        }
+        self.error_number = read_u32_le(token);
+        self.error_class = token[5];
+        let end = (8 + read_u16_le(&token[6..]) as usize * 2).min(token.len());
+        self.error_message = ucs2_string(&token[8..end]);
+    }
+
+    fn on_done(&mut self, token: &[u8]) {
+        let status = read_u16_le(token);
+        if status & TDS_DONE_ERROR != 0 {
+            self.done_error = true;
+        }
+        if status & TDS_DONE_COUNT != 0 {
+            self.affected_rows = self.affected_rows.saturating_add(read_u64_le(&token[4..]));
+        }
+        self.done = true;
+    }
+
+    // 严重级别11-16为用户可纠正的错误
+    pub fn status(&self) -> L7ResponseStatus {
+        if self.error_number != 0 {
+            if self.error_class >= TDS_ERROR_CLASS_SERVER {
+                L7ResponseStatus::ServerError
+            } else {
+                L7ResponseStatus::ClientError
+            }
+        } else if self.done_error {
+            L7ResponseStatus::ServerError
+        } else {
+            L7ResponseStatus::Ok
+        }
+    }
+
+    // 超出u16的自定义错误号只记录在error_number中
+    pub fn code(&self) -> u16 {
+        u16::try_from(self.error_number).unwrap_or_default()
+    }
+}
+
+fn ucs2_string(data: &[u8]) -> String {
+    let chars = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars)
+}
+
+// TDS 7.2及以上的SQL Batch和RPC请求以ALL_HEADERS开头，首4字节为总长度，之后每个头部以4字节长度开头
+fn skip_all_headers(data: &[u8]) -> &[u8] {
+    if data.len() < 8 {
+        return data;
+    }
+    let total = read_u32_le(data) as usize;
+    let first = read_u32_le(&data[4..]) as usize;
+    if total <= data.len() && first >= 6 && first + 4 <= total {
+        &data[total..]
+    } else {
+        data
+    }
+}
+
+// 依次跳过RPC参数，返回第index个参数的NVARCHAR值，只支持INTN和NVARCHAR类型的参数
+fn rpc_nvarchar_param(mut data: &[u8], index: usize) -> Option<String> {
+    for i in 0..=index {
+        // 参数名(B_VARCHAR)及状态标志
+        let name_len = *data.first()? as usize * 2;
+        data = data.get(name_len + 2..)?;
+        match *data.first()? {
+            TDS_TYPE_INTN => {
+                // 1字节最大长度，值为1字节长度及数据
+                let len = *data.get(2)? as usize;
+                data = data.get(3 + len..)?;
+            }
+            TDS_TYPE_NVARCHAR => {
+                // 2字节最大长度及5字节collation
+                let max_len = read_u16_le(data.get(1..3)?);
+                data = data.get(8..)?;
+                let (value, rest) = if max_len == TDS_NVARCHAR_MAX {
+                    plp_value(data)?
+                } else {
+                    match read_u16_le(data.get(..2)?) {
+                        TDS_NULL_LEN => (vec![], &data[2..]),
+                        len => {
+                            let end = 2 + len as usize;
+                            (data.get(2..end)?.to_vec(), &data[end..])
+                        }
+                    }
+                };
+                if i == index {
+                    return Some(ucs2_string(&value));
+                }
+                data = rest;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+// PLP格式: 8字节总长度，之后为4字节长度开头的数据块，以长度为0的块结束
+fn plp_value(data: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    if read_u64_le(data.get(..8)?) == TDS_PLP_NULL {
+        return Some((vec![], &data[8..]));
+    }
+    let mut data = &data[8..];
+    let mut value = vec![];
+    loop {
+        let len = read_u32_le(data.get(..4)?) as usize;
+        data = &data[4..];
+        if len == 0 {
+            return Some((value, data));
+        }
+        value.extend_from_slice(data.get(..len)?);
+        data = &data[len..];
+    }
+}
+
+// 语句所在的参数序号
+fn rpc_statement_index(proc_id: u16) -> Option<usize> {
+    match proc_id {
+        TDS_RPC_SP_EXECUTESQL => Some(0),
+        TDS_RPC_SP_PREPARE | TDS_RPC_SP_PREPEXEC => Some(2),
+        _ => None,
+    }
+}
+
+fn is_text(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c != char::REPLACEMENT_CHARACTER && (!c.is_control() || c.is_whitespace()))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TdsLog {
+    info: TdsInfo,
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u16,
+}
+
+impl TdsLog {
+    fn reset_logs(&mut self) {
+        self.info = TdsInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    // 存储过程名长度为0xffff时后跟2字节ProcID，之后为2字节OptionFlags及参数
+    fn rpc_request(&mut self, data: &[u8]) -> Result<()> {
+        let data = skip_all_headers(data);
+        if data.len() < 2 {
+            return Err(Error::TdsLogParseFailed(
+                ParseContext::new(ParseStage::Request).mismatch(2, data.len()),
+            ));
+        }
+        let (proc_id, offset) = match read_u16_le(data) {
+            TDS_RPC_PROC_ID_SWITCH if data.len() >= 4 => {
+                let proc_id = read_u16_le(&data[2..]);
+                if let Some(name) = RPC_PROC_NAMES.get((proc_id as usize).wrapping_sub(1)) {
+                    self.info.proc_name = name.to_string();
+                }
+                (proc_id, 4)
+            }
+            TDS_RPC_PROC_ID_SWITCH => {
+                return Err(Error::TdsLogParseFailed(
+                    ParseContext::new(ParseStage::Request).mismatch(4, data.len()),
+                ))
+            }
+            len => {
+                let end = 2 + len as usize * 2;
+                let name = data.get(2..end).ok_or(Error::TdsLogParseFailed(
+                    ParseContext::new(ParseStage::Request).mismatch(end, data.len()),
+                ))?;
+                self.info.proc_name = ucs2_string(name);
+                (0, end)
+            }
+        };
+        if let (Some(index), Some(params)) = (rpc_statement_index(proc_id), data.get(offset + 2..))
+        {
+            if let Some(statement) = rpc_nvarchar_param(params, index) {
+                self.info.statement = statement;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<AppProtoHead> {
+        let header = TdsHeader::decode(payload)
+            .ok_or(Error::TdsLogParseFailed(ParseStage::Header.into()))?;
+        self.msg_type = header
+            .msg_type(direction)
+            .ok_or(Error::TdsLogParseFailed(ParseStage::Session.into()))?;
+        self.info.packet_type = header.packet_type;
+        let data = &payload[TDS_HEADER_LEN..(header.length as usize).min(payload.len())];
+
+        match header.packet_type {
+            TDS_PACKET_SQL_BATCH => self.info.statement = ucs2_string(skip_all_headers(data)),
+            TDS_PACKET_RPC => self.rpc_request(data)?,
+            TDS_PACKET_TABULAR_RESULT => {
+                let response = TdsResponse::decode(data).ok_or(Error::TdsLogParseFailed(
+                    ParseContext::new(ParseStage::Response).at(TDS_HEADER_LEN),
+                ))?;
+                self.status = response.status();
+                self.status_code = response.code();
+                self.info.error_number = response.error_number;
+                self.info.error_class = response.error_class;
+                self.info.error_message = response.error_message;
+                self.info.affected_rows = response.affected_rows;
+            }
+            _ => (),
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Tds,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for TdsLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        let head = self.decode_payload(payload, direction)?;
+        Ok(AppProtoHeadEnum::Single(head))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Tds(self.info.clone()))
+    }
+}
+
+// 通过SQL Batch或RPC请求识别，登录阶段的PRELOGIN、LOGIN7报文及分包时不清除bitmap，等待后续请求
+pub fn tds_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Tcp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Tds));
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    let header = match TdsHeader::decode(payload) {
+        Some(h) => h,
+        None => {
+            *bitmap &= !(1 << u8::from(L7Protocol::Tds));
+            return false;
+        }
+    };
+    if header.length as usize != payload.len() {
+        return false;
+    }
+
+    let mut tds = TdsLog::default();
+    if tds
+        .decode_payload(payload, PacketDirection::ClientToServer)
+        .is_err()
+    {
+        return false;
+    }
+    match header.packet_type {
+        TDS_PACKET_SQL_BATCH => is_text(&tds.info.statement),
+        TDS_PACKET_RPC => is_text(&tds.info.proc_name),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ucs2(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    fn packet(packet_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = vec![packet_type, TDS_STATUS_EOM];
+        packet.extend_from_slice(&((TDS_HEADER_LEN + body.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0x35, 1, 0]);
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    // 仅包含事务描述符的ALL_HEADERS
+    fn all_headers() -> Vec<u8> {
+        let mut headers = vec![];
+        headers.extend_from_slice(&22u32.to_le_bytes());
+        headers.extend_from_slice(&18u32.to_le_bytes());
+        headers.extend_from_slice(&2u16.to_le_bytes());
+        headers.extend_from_slice(&[0; 8]);
+        headers.extend_from_slice(&1u32.to_le_bytes());
+        headers
+    }
+
+    fn done(token: u8, status: u16, rows: u64) -> Vec<u8> {
+        let mut done = vec![token];
+        done.extend_from_slice(&status.to_le_bytes());
+        done.extend_from_slice(&[0xc1, 0x00]);
+        done.extend_from_slice(&rows.to_le_bytes());
+        done
+    }
+
+    #[test]
+    fn sql_batch() {
+        let mut body = all_headers();
+        body.extend(ucs2("select 1"));
+        let mut tds = TdsLog::default();
+        let head = tds
+            .decode_payload(
+                &packet(TDS_PACKET_SQL_BATCH, &body),
+                PacketDirection::ClientToServer,
+            )
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Request);
+        assert_eq!(tds.info.packet_type, TDS_PACKET_SQL_BATCH);
+        assert_eq!(tds.info.statement, "select 1");
+
+        // TDS 7.1及以前没有ALL_HEADERS
+        tds.reset_logs();
+        tds.decode_payload(
+            &packet(TDS_PACKET_SQL_BATCH, &ucs2("SELECT name FROM t")),
+            PacketDirection::ClientToServer,
+        )
+        .unwrap();
+        assert_eq!(tds.info.statement, "SELECT name FROM t");
+    }
+
+    #[test]
+    fn rpc() {
+        // sp_executesql N'select @p', N'@p int', @p=1
+        let mut body = all_headers();
+        body.extend_from_slice(&[0xff, 0xff, 10, 0, 0, 0]);
+        for s in ["select @p", "@p int"] {
+            body.extend_from_slice(&[0, 0, TDS_TYPE_NVARCHAR, 0x40, 0x1f]);
+            body.extend_from_slice(&[0x09, 0x04, 0xd0, 0x00, 0x34]);
+            body.extend_from_slice(&((s.len() * 2) as u16).to_le_bytes());
+            body.extend(ucs2(s));
+        }
+        body.extend_from_slice(&[2]);
+        body.extend(ucs2("@p"));
+        body.extend_from_slice(&[0, TDS_TYPE_INTN, 4, 4, 1, 0, 0, 0]);
+        let mut tds = TdsLog::default();
+        tds.decode_payload(
+            &packet(TDS_PACKET_RPC, &body),
+            PacketDirection::ClientToServer,
+        )
+        .unwrap();
+        assert_eq!(tds.info.proc_name, "sp_executesql");
+        assert_eq!(tds.info.statement, "select @p");
+
+        // 按名称调用存储过程
+        let mut body = all_headers();
+        body.extend_from_slice(&7u16.to_le_bytes());
+        body.extend(ucs2("dbo.foo"));
+        body.extend_from_slice(&[0, 0]);
+        tds.reset_logs();
+        tds.decode_payload(
+            &packet(TDS_PACKET_RPC, &body),
+            PacketDirection::ClientToServer,
+        )
+        .unwrap();
+        assert_eq!(tds.info.proc_name, "dbo.foo");
+        assert_eq!(tds.info.statement, "");
+    }
+
+    #[test]
+    fn plp_statement() {
+        // sp_prepexec @handle OUTPUT, N'@p int', N'select @p'(NVARCHAR(MAX))
+        let mut params = vec![0, 1, TDS_TYPE_INTN, 4, 0];
+        params.extend_from_slice(&[0, 0, TDS_TYPE_NVARCHAR, 0x40, 0x1f, 0, 0, 0, 0, 0]);
+        params.extend_from_slice(&12u16.to_le_bytes());
+        params.extend(ucs2("@p int"));
+        params.extend_from_slice(&[0, 0, TDS_TYPE_NVARCHAR, 0xff, 0xff, 0, 0, 0, 0, 0]);
+        params.extend_from_slice(&18u64.to_le_bytes());
+        for s in ["select", " @p"] {
+            params.extend_from_slice(&((s.len() * 2) as u32).to_le_bytes());
+            params.extend(ucs2(s));
+        }
+        params.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            rpc_nvarchar_param(&params, 2),
+            Some("select @p".to_string())
+        );
+        assert_eq!(rpc_nvarchar_param(&params, 1), Some("@p int".to_string()));
+        assert_eq!(rpc_nvarchar_param(&params[..30], 2), None);
+    }
+
+    #[test]
+    fn response() {
+        // ERROR 208 (Invalid object name) + DONE(ERROR)
+        let message = ucs2("Invalid object name 't'.");
+        let mut error = vec![];
+        error.extend_from_slice(&208u32.to_le_bytes());
+        error.extend_from_slice(&[1, 16]);
+        error.extend_from_slice(&((message.len() / 2) as u16).to_le_bytes());
+        error.extend(message);
+        error.extend_from_slice(&[0, 0, 1, 0, 0, 0]);
+        let mut body = vec![TDS_TOKEN_ERROR];
+        body.extend_from_slice(&(error.len() as u16).to_le_bytes());
+        body.extend(error);
+        body.extend(done(TDS_TOKEN_DONE, TDS_DONE_ERROR, 0));
+
+        let mut tds = TdsLog::default();
+        let head = tds
+            .decode_payload(
+                &packet(TDS_PACKET_TABULAR_RESULT, &body),
+                PacketDirection::ServerToClient,
+            )
+            .unwrap();
+        assert_eq!(head.msg_type, LogMessageType::Response);
+        assert_eq!(head.status, L7ResponseStatus::ClientError);
+        assert_eq!(head.code, 208);
+        assert_eq!(tds.info.error_class, 16);
+        assert_eq!(tds.info.error_message, "Invalid object name 't'.");
+
+        // COLMETADATA和ROW无法逐个跳过，从包末尾的DONE中取行数
+        let mut body = vec![
+            0x81, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x38, 0x00,
+        ];
+        body.extend_from_slice(&[0xd1, 0x01, 0x00, 0x00, 0x00]);
+        body.extend(done(TDS_TOKEN_DONE, TDS_DONE_COUNT, 1));
+        tds.reset_logs();
+        let head = tds
+            .decode_payload(
+                &packet(TDS_PACKET_TABULAR_RESULT, &body),
+                PacketDirection::ServerToClient,
+            )
+            .unwrap();
+        assert_eq!(head.status, L7ResponseStatus::Ok);
+        assert_eq!(tds.info.affected_rows, 1);
+
+        // 存储过程中多条语句的行数累加
+        let mut body = done(TDS_TOKEN_DONEINPROC, TDS_DONE_COUNT, 2);
+        body.extend_from_slice(&[TDS_TOKEN_RETURNSTATUS, 0, 0, 0, 0]);
+        body.extend(done(TDS_TOKEN_DONEPROC, TDS_DONE_COUNT, 3));
+        assert_eq!(TdsResponse::decode(&body).unwrap().affected_rows, 5);
+    }
+
+    #[test]
+    fn not_tds() {
+        let mut tds = TdsLog::default();
+        // 非EOM的响应包
+        let mut response = packet(TDS_PACKET_TABULAR_RESULT, &done(TDS_TOKEN_DONE, 0, 0));
+        response[1] = 0;
+        assert!(tds
+            .decode_payload(&response, PacketDirection::ServerToClient)
+            .is_err());
+        // PRELOGIN响应
+        let prelogin = packet(
+            TDS_PACKET_TABULAR_RESULT,
+            &[
+                0x00, 0x00, 0x06, 0x00, 0x06, 0xff, 0x0f, 0x00, 0x10, 0x00, 0x00, 0x00,
+            ],
+        );
+        assert!(tds
+            .decode_payload(&prelogin, PacketDirection::ServerToClient)
+            .is_err());
+        assert!(TdsHeader::decode(b"GET / HTTP/1.1\r\n").is_none());
+        assert!(!is_text(&ucs2_string(&[0x01, 0x00, 0x41, 0x00])));
+    }
+}
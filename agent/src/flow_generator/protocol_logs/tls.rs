@@ -0,0 +1,644 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use md5::{Digest, Md5};
+use ring::digest;
+use serde::Serialize;
+
+use super::{
+    consts::*, value_is_default, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, L7LogParse, L7ResponseStatus, L7SessionId, LogMessageType,
+};
+
+use crate::proto::flow_log;
+use crate::{
+    common::{
+        enums::{IpProtocol, PacketDirection},
+        flow::L7Protocol,
+        meta_packet::MetaPacket,
+    },
+    flow_generator::error::{Error, ParseStage, Result},
+    utils::bytes::read_u16_be,
+};
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    #[serde(rename = "request_type")]
+    pub handshake_type: u8,
+    // 请求中为ClientHello的版本，合并响应后为协商的版本
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: u16,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub server_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cipher_suite: u16,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3_hash: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3s: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3s_hash: String,
+    // 服务端证书链的SHA-256指纹，第一个为叶子证书，TLS 1.3中证书已加密
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub certificate_sha256: Vec<String>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub alert_description: u8,
+}
+
+impl TlsInfo {
+    pub fn merge(&mut self, other: Self) {
+        if other.version != 0 {
+            self.version = other.version;
+        }
+        self.cipher_suite = other.cipher_suite;
+        self.ja3s = other.ja3s;
+        self.ja3s_hash = other.ja3s_hash;
+        self.certificate_sha256 = other.certificate_sha256;
+        self.alert_description = other.alert_description;
+    }
+}
+
+impl L7SessionId for TlsInfo {}
+
+impl From<TlsInfo> for flow_log::TlsInfo {
+    fn from(f: TlsInfo) -> Self {
+        flow_log::TlsInfo {
+            handshake_type: f.handshake_type as u32,
+            version: f.version as u32,
+            server_name: f.server_name,
+            cipher_suite: f.cipher_suite as u32,
+            ja3: f.ja3,
+            ja3_hash: f.ja3_hash,
+            ja3s: f.ja3s,
+            ja3s_hash: f.ja3s_hash,
+            certificate_sha256: f.certificate_sha256,
+            alert_description: f.alert_description as u32,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TlsHeader {
+    pub content_type: u8,
+    pub version: u16,
+    pub length: u16,
+    // 握手记录的首个消息类型
+    pub handshake_type: u8,
+    pub alert_level: u8,
+    pub alert_description: u8,
+}
+
+impl TlsHeader {
+    // 记录头5字节: content type, version(2), length(2)
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() <= TLS_RECORD_HEADER_LEN
+            || payload[1] != TLS_MAJOR_VERSION
+            || payload[2] > TLS_MAX_MINOR_VERSION
+        {
+            return None;
+        }
+        let mut header = Self {
+            content_type: payload[0],
+            version: read_u16_be(&payload[1..]),
+            length: read_u16_be(&payload[3..]),
+            ..Default::default()
+        };
+        if header.length == 0 || header.length as usize > TLS_MAX_RECORD_LEN {
+            return None;
+        }
+        match header.content_type {
+            TLS_CONTENT_HANDSHAKE => header.handshake_type = payload[5],
+            TLS_CONTENT_ALERT => {
+                header.alert_level = payload[5];
+                header.alert_description = *payload.get(6)?;
+            }
+            TLS_CONTENT_CHANGE_CIPHER_SPEC | TLS_CONTENT_APPLICATION_DATA => (),
+            _ => return None,
+        }
+        Some(header)
+    }
+
+    // 只解析握手阶段，ClientHello为请求，ServerHello或服务端的明文告警为响应
+    pub fn msg_type(&self, direction: PacketDirection) -> Option<LogMessageType> {
+        match (self.content_type, direction) {
+            (TLS_CONTENT_HANDSHAKE, PacketDirection::ClientToServer)
+                if self.handshake_type == TLS_HANDSHAKE_CLIENT_HELLO =>
+            {
+                Some(LogMessageType::Request)
+            }
+            (TLS_CONTENT_HANDSHAKE, PacketDirection::ServerToClient)
+                if self.handshake_type == TLS_HANDSHAKE_SERVER_HELLO =>
+            {
+                Some(LogMessageType::Response)
+            }
+            (TLS_CONTENT_ALERT, PacketDirection::ServerToClient)
+                if self.length == TLS_ALERT_LEN =>
+            {
+                Some(LogMessageType::Response)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> L7ResponseStatus {
+        if self.content_type == TLS_CONTENT_ALERT && self.alert_level == TLS_ALERT_LEVEL_FATAL {
+            L7ResponseStatus::ServerError
+        } else {
+            L7ResponseStatus::Ok
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        if self.content_type == TLS_CONTENT_ALERT {
+            self.alert_description as u16
+        } else {
+            0
+        }
+    }
+}
+
+fn read_u24_be(bs: &[u8]) -> usize {
+    (bs[0] as usize) << 16 | (bs[1] as usize) << 8 | bs[2] as usize
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .fold(String::with_capacity(data.len() * 2), |s, c| {
+            s + &format!("{:02x}", c)
+        })
+}
+
+fn md5_hex(s: &str) -> String {
+    hex(&Md5::digest(s.as_bytes()))
+}
+
+// GREASE(RFC 8701)为0x0a0a、0x1a1a...0xfafa，计算JA3时忽略
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+// JA3中的列表，十进制数值以'-'连接
+fn ja3_list(values: impl Iterator<Item = u16>) -> String {
+    values
+        .filter(|v| !is_grease(*v))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn u16_values(data: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    data.chunks_exact(2).map(read_u16_be)
+}
+
+// 依次处理扩展的类型及内容，扩展不完整时返回false
+fn for_each_extension(mut data: &[u8], mut f: impl FnMut(u16, &[u8])) -> bool {
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return false;
+        }
+        let end = 4 + read_u16_be(&data[2..]) as usize;
+        let extension = match data.get(4..end) {
+            Some(e) => e,
+            None => return false,
+        };
+        f(read_u16_be(data), extension);
+        data = &data[end..];
+    }
+    true
+}
+
+// server_name_list长度(2)，之后为name_type(1)、长度(2)及域名，只取第一个
+fn server_name(extension: &[u8]) -> Option<String> {
+    let entry = extension.get(2..)?;
+    if *entry.first()? != TLS_SERVER_NAME_HOST {
+        return None;
+    }
+    let len = read_u16_be(entry.get(1..3)?) as usize;
+    let name = entry.get(3..3 + len)?;
+    std::str::from_utf8(name).ok().map(|s| s.to_owned())
+}
+
+// 拼接开头连续的握手记录，握手消息可能跨越多个记录，最后一个记录可能被截断
+fn handshake_fragments(mut payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![];
+    while payload.len() > TLS_RECORD_HEADER_LEN && payload[0] == TLS_CONTENT_HANDSHAKE {
+        let end = (TLS_RECORD_HEADER_LEN + read_u16_be(&payload[3..]) as usize).min(payload.len());
+        data.extend_from_slice(&payload[TLS_RECORD_HEADER_LEN..end]);
+        payload = &payload[end..];
+    }
+    data
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsLog {
+    info: TlsInfo,
+    msg_type: LogMessageType,
+    status: L7ResponseStatus,
+    status_code: u16,
+}
+
+impl TlsLog {
+    fn reset_logs(&mut self) {
+        self.info = TlsInfo::default();
+        self.msg_type = LogMessageType::default();
+        self.status = L7ResponseStatus::Ok;
+        self.status_code = 0;
+    }
+
+    // version(2), random(32), session_id, cipher_suites, compression_methods, extensions
+    // 完整解析时才计算JA3，消息被截断时仍记录已解析到的SNI
+    fn client_hello(&mut self, body: &[u8], complete: bool) -> Option<()> {
+        let version = read_u16_be(body.get(..2)?);
+        self.info.version = version;
+        let mut offset = 34;
+        offset += 1 + *body.get(offset)? as usize;
+        let len = read_u16_be(body.get(offset..offset + 2)?) as usize;
+        let ciphers = body.get(offset + 2..offset + 2 + len)?;
+        offset += 2 + len;
+        offset += 1 + *body.get(offset)? as usize;
+
+        let mut extensions = vec![];
+        let mut groups = String::new();
+        let mut point_formats = String::new();
+        let mut all_parsed = true;
+        if let Some(len) = body.get(offset..offset + 2) {
+            let end = offset + 2 + read_u16_be(len) as usize;
+            let parsed = for_each_extension(
+                &body[offset + 2..end.min(body.len())],
+                |extension_type, extension| {
+                    extensions.push(extension_type);
+                    match extension_type {
+                        TLS_EXTENSION_SERVER_NAME => {
+                            if let Some(name) = server_name(extension) {
+                                self.info.server_name = name;
+                            }
+                        }
+                        TLS_EXTENSION_SUPPORTED_GROUPS if extension.len() >= 2 => {
+                            groups = ja3_list(u16_values(&extension[2..]))
+                        }
+                        TLS_EXTENSION_EC_POINT_FORMATS if !extension.is_empty() => {
+                            point_formats = ja3_list(extension[1..].iter().map(|f| *f as u16))
+                        }
+                        _ => (),
+                    }
+                },
+            );
+            all_parsed = parsed && end <= body.len();
+        }
+        if complete && all_parsed {
+            self.info.ja3 = format!(
+                "{},{},{},{},{}",
+                version,
+                ja3_list(u16_values(ciphers)),
+                ja3_list(extensions.into_iter()),
+                groups,
+                point_formats
+            );
+            self.info.ja3_hash = md5_hex(&self.info.ja3);
+        }
+        Some(())
+    }
+
+    // version(2), random(32), session_id, cipher_suite(2), compression_method(1), extensions
+    fn server_hello(&mut self, body: &[u8], complete: bool) -> Option<()> {
+        let version = read_u16_be(body.get(..2)?);
+        self.info.version = version;
+        let mut offset = 34;
+        offset += 1 + *body.get(offset)? as usize;
+        let cipher_suite = read_u16_be(body.get(offset..offset + 2)?);
+        self.info.cipher_suite = cipher_suite;
+        offset += 3;
+
+        let mut extensions = vec![];
+        let mut all_parsed = true;
+        if let Some(len) = body.get(offset..offset + 2) {
+            let end = offset + 2 + read_u16_be(len) as usize;
+            let parsed = for_each_extension(
+                &body[offset + 2..end.min(body.len())],
+                |extension_type, extension| {
+                    extensions.push(extension_type);
+                    // TLS 1.3的ServerHello版本固定为TLS 1.2，实际版本在supported_versions扩展中
+                    if extension_type == TLS_EXTENSION_SUPPORTED_VERSIONS && extension.len() == 2 {
+                        self.info.version = read_u16_be(extension);
+                    }
+                },
+            );
+            all_parsed = parsed && end <= body.len();
+        }
+        if complete && all_parsed {
+            self.info.ja3s = format!(
+                "{},{},{}",
+                version,
+                cipher_suite,
+                ja3_list(extensions.into_iter())
+            );
+            self.info.ja3s_hash = md5_hex(&self.info.ja3s);
+        }
+        Some(())
+    }
+
+    // 证书列表长度(3)，之后每个证书为长度(3)及DER编码，证书链跨包时只计算完整证书的指纹
+    fn certificate(&mut self, body: &[u8]) {
+        let mut data = match body.get(3..) {
+            Some(d) => d,
+            None => return,
+        };
+        while data.len() >= 3 && self.info.certificate_sha256.len() < TLS_MAX_CERTIFICATES {
+            let end = 3 + read_u24_be(data);
+            let der = match data.get(3..end) {
+                Some(d) => d,
+                None => return,
+            };
+            self.info
+                .certificate_sha256
+                .push(hex(digest::digest(&digest::SHA256, der).as_ref()));
+            data = &data[end..];
+        }
+    }
+
+    // 一个记录可能包含多个握手消息，如ServerHello、Certificate及ServerHelloDone
+    fn handshake(&mut self, data: &[u8]) {
+        let mut offset = 0;
+        while let Some(header) = data.get(offset..offset + TLS_HANDSHAKE_HEADER_LEN) {
+            let start = offset + TLS_HANDSHAKE_HEADER_LEN;
+            let end = start + read_u24_be(&header[1..]);
+            let body = &data[start..end.min(data.len())];
+            let complete = end <= data.len();
+            match header[0] {
+                TLS_HANDSHAKE_CLIENT_HELLO => {
+                    let _ = self.client_hello(body, complete);
+                }
+                TLS_HANDSHAKE_SERVER_HELLO => {
+                    let _ = self.server_hello(body, complete);
+                }
+                TLS_HANDSHAKE_CERTIFICATE => self.certificate(body),
+                _ => (),
+            }
+            offset = end;
+        }
+    }
+
+    fn decode_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<AppProtoHead> {
+        let header = TlsHeader::decode(payload)
+            .ok_or(Error::TlsLogParseFailed(ParseStage::Header.into()))?;
+        self.msg_type = header
+            .msg_type(direction)
+            .ok_or(Error::TlsLogParseFailed(ParseStage::Session.into()))?;
+        self.status = header.status();
+        self.status_code = header.code();
+        if header.content_type == TLS_CONTENT_ALERT {
+            self.info.alert_description = header.alert_description;
+        } else {
+            self.info.handshake_type = header.handshake_type;
+            self.handshake(&handshake_fragments(payload));
+        }
+
+        Ok(AppProtoHead {
+            proto: L7Protocol::Tls,
+            msg_type: self.msg_type,
+            status: self.status,
+            code: self.status_code,
+            rrt: 0,
+            version: 0,
+            ..Default::default()
+        })
+    }
+}
+
+impl L7LogParse for TlsLog {
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        proto: IpProtocol,
+        direction: PacketDirection,
+    ) -> Result<AppProtoHeadEnum> {
+        if proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        self.reset_logs();
+        let head = self.decode_payload(payload, direction)?;
+        Ok(AppProtoHeadEnum::Single(head))
+    }
+
+    fn info(&self) -> AppProtoLogsInfoEnum {
+        AppProtoLogsInfoEnum::Single(AppProtoLogsInfo::Tls(self.info.clone()))
+    }
+}
+
+// 从ClientHello中提取SNI，不解密也能将后续流量归属到域名
+pub fn client_hello_server_name(payload: &[u8]) -> Option<String> {
+    let header = TlsHeader::decode(payload)?;
+    if header.content_type != TLS_CONTENT_HANDSHAKE
+        || header.handshake_type != TLS_HANDSHAKE_CLIENT_HELLO
+    {
+        return None;
+    }
+    let mut log = TlsLog::default();
+    log.handshake(&handshake_fragments(payload));
+    Some(log.info.server_name).filter(|name| !name.is_empty())
+}
+
+// 通过ClientHello识别，未以TLS记录开头的报文清除bitmap
+pub fn tls_check_protocol(bitmap: &mut u128, packet: &MetaPacket) -> bool {
+    if packet.lookup_key.proto != IpProtocol::Tcp {
+        *bitmap &= !(1 << u8::from(L7Protocol::Tls));
+        return false;
+    }
+
+    let payload = match packet.get_l4_payload() {
+        Some(p) => p,
+        None => return false,
+    };
+    match TlsHeader::decode(payload) {
+        Some(header) => {
+            header.content_type == TLS_CONTENT_HANDSHAKE
+                && header.handshake_type == TLS_HANDSHAKE_CLIENT_HELLO
+        }
+        None => {
+            *bitmap &= !(1 << u8::from(L7Protocol::Tls));
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(content_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, TLS_MAJOR_VERSION, 1];
+        record.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        record.extend_from_slice(body);
+        record
+    }
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![msg_type];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(body);
+        message
+    }
+
+    fn extension(extension_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut extension = extension_type.to_be_bytes().to_vec();
+        extension.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        extension.extend_from_slice(data);
+        extension
+    }
+
+    fn hello(version: u16, middle: &[u8], extensions: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x11; 32]);
+        body.extend_from_slice(&[4, 1, 2, 3, 4]);
+        body.extend_from_slice(middle);
+        let extensions = extensions.concat();
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+        body
+    }
+
+    fn client_hello() -> Vec<u8> {
+        let mut server_name = vec![0, 14, TLS_SERVER_NAME_HOST, 0, 11];
+        server_name.extend_from_slice(b"example.com");
+        let extensions = [
+            extension(0x1a1a, &[]),
+            extension(TLS_EXTENSION_SERVER_NAME, &server_name),
+            extension(
+                TLS_EXTENSION_SUPPORTED_GROUPS,
+                &[0, 6, 0x2a, 0x2a, 0, 29, 0, 23],
+            ),
+            extension(TLS_EXTENSION_EC_POINT_FORMATS, &[1, 0]),
+            extension(TLS_EXTENSION_SUPPORTED_VERSIONS, &[4, 3, 4, 3, 3]),
+        ];
+        // cipher suites含GREASE, compression methods只有null
+        let middle = [0, 6, 0x0a, 0x0a, 0x13, 0x01, 0xc0, 0x2f, 1, 0];
+        record(
+            TLS_CONTENT_HANDSHAKE,
+            &handshake(
+                TLS_HANDSHAKE_CLIENT_HELLO,
+                &hello(0x0303, &middle, &extensions),
+            ),
+        )
+    }
+
+    #[test]
+    fn ja3() {
+        let mut log = TlsLog::default();
+        let payload = client_hello();
+        let head = log
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ClientToServer)
+            .unwrap();
+        assert!(
+            matches!(head, AppProtoHeadEnum::Single(h) if h.msg_type == LogMessageType::Request)
+        );
+        assert_eq!(log.info.server_name, "example.com");
+        assert_eq!(log.info.version, 0x0303);
+        assert_eq!(log.info.ja3, "771,4865-49199,0-10-11-43,29-23,0");
+        assert_eq!(log.info.ja3_hash, "cefebb3c24208325c13a9ad9b14b83c9");
+
+        // 被截断时不计算JA3
+        log.parse(
+            &payload[..payload.len() - 10],
+            IpProtocol::Tcp,
+            PacketDirection::ClientToServer,
+        )
+        .unwrap();
+        assert_eq!(log.info.server_name, "example.com");
+        assert!(log.info.ja3.is_empty() && log.info.ja3_hash.is_empty());
+
+        assert_eq!(
+            client_hello_server_name(&payload[..payload.len() - 10]).as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(client_hello_server_name(&payload[5..]), None);
+
+        assert!(log
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_err());
+    }
+
+    #[test]
+    fn ja3s_and_certificates() {
+        let extensions = [extension(0xff01, &[0]), extension(11, &[1, 0])];
+        let server_hello = handshake(
+            TLS_HANDSHAKE_SERVER_HELLO,
+            &hello(0x0303, &[0xc0, 0x2f, 0], &extensions),
+        );
+        let mut certificates = vec![];
+        for der in [&b"leaf"[..], &b"intermediate"[..]] {
+            certificates.extend_from_slice(&(der.len() as u32).to_be_bytes()[1..]);
+            certificates.extend_from_slice(der);
+        }
+        let mut body = (certificates.len() as u32).to_be_bytes()[1..].to_vec();
+        body.extend_from_slice(&certificates);
+        let certificate = handshake(TLS_HANDSHAKE_CERTIFICATE, &body);
+
+        // Certificate消息跨越两个记录
+        let mut payload = record(TLS_CONTENT_HANDSHAKE, &server_hello);
+        payload.extend_from_slice(&record(TLS_CONTENT_HANDSHAKE, &certificate[..10]));
+        payload.extend_from_slice(&record(TLS_CONTENT_HANDSHAKE, &certificate[10..]));
+
+        let mut log = TlsLog::default();
+        log.parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(log.msg_type, LogMessageType::Response);
+        assert_eq!(log.info.cipher_suite, 0xc02f);
+        assert_eq!(log.info.ja3s, "771,49199,65281-11");
+        assert_eq!(log.info.ja3s_hash, "303951d4c50efb2e991652225a6f02b1");
+        assert_eq!(
+            log.info.certificate_sha256,
+            vec![
+                "9f91161f43433e49a6de6db680d79f60159f2e4ac9172621a12846428158440b".to_owned(),
+                "94e6f02a5aca51e44a2c057e9c0f3ced80814c198de8a84b4f5cab7e06ea2094".to_owned(),
+            ]
+        );
+
+        // 证书链被截断时只保留完整的叶子证书
+        log.parse(
+            &payload[..payload.len() - 4],
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+        )
+        .unwrap();
+        assert_eq!(log.info.certificate_sha256.len(), 1);
+    }
+
+    #[test]
+    fn alert() {
+        let mut log = TlsLog::default();
+        let payload = record(TLS_CONTENT_ALERT, &[TLS_ALERT_LEVEL_FATAL, 40]);
+        log.parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(log.status, L7ResponseStatus::ServerError);
+        assert_eq!(log.status_code, 40);
+        assert_eq!(log.info.alert_description, 40);
+
+        // 握手完成后的加密告警不解析
+        let payload = record(TLS_CONTENT_ALERT, &[0x5a; 26]);
+        assert!(log
+            .parse(&payload, IpProtocol::Tcp, PacketDirection::ServerToClient)
+            .is_err());
+        let payload = record(TLS_CONTENT_APPLICATION_DATA, &[0x5a; 32]);
+        assert!(TlsHeader::decode(&payload).is_some());
+        assert!(TlsHeader::decode(b"GET / HTTP/1.1\r\n").is_none());
+    }
+}
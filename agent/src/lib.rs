@@ -32,6 +32,7 @@ mod handler;
 mod integration_collector;
 mod metric;
 mod monitor;
+pub mod parser;
 mod pcap;
 mod platform;
 mod policy;
@@ -41,16 +42,24 @@ mod sender;
 pub mod trident;
 mod utils;
 
+pub use config::dump_config;
+
 // for benchmarks
 #[doc(hidden)]
 pub use {
     common::enums::TcpFlags as _TcpFlags,
+    common::flow::L7Protocol as _L7Protocol,
     common::lookup_key::LookupKey as _LookupKey,
     common::platform_data::{IpSubnet as _IpSubnet, PlatformData as _PlatformData},
     common::policy::Cidr as _Cidr,
     flow_generator::flow_map::{
         _new_flow_map_and_receiver, _new_meta_packet, _reverse_meta_packet,
     },
+    flow_generator::fuzz::{_l7_log_parse, _meta_packet_update},
+    flow_generator::golden::{
+        Anonymizer as _Anonymizer, GoldenParser as _GoldenParser, GOLDEN_DIR as _GOLDEN_DIR,
+        GOLDEN_PARSERS as _GOLDEN_PARSERS,
+    },
     flow_generator::perf::l7_rrt::L7RrtCache as _L7RrtCache,
     flow_generator::perf::tcp::{
         TcpPerf as _TcpPerf, _benchmark_report, _benchmark_session_peer_seq_no_assert,
@@ -53,6 +53,14 @@ struct Opts {
     /// grant capabilities including cap_net_admin, cap_net_raw,cap_net_bind_service
     #[clap(long)]
     add_cap: bool,
+
+    /// Dump effective config, including static config and the last config received from controller
+    #[clap(long)]
+    dump_config: bool,
+
+    /// Dump format, used with '--dump-config', yaml or json
+    #[clap(long, default_value = "yaml")]
+    dump_format: String,
 }
 
 #[cfg(unix)]
@@ -74,6 +82,10 @@ fn main() -> Result<()> {
         println!(env!("RUSTC_VERSION"));
         return Ok(());
     }
+    if opts.dump_config {
+        print!("{}", dump_config(&opts.config_file, &opts.dump_format)?);
+        return Ok(());
+    }
     let mut t =
         trident::Trident::start(&Path::new(&opts.config_file), env!("AGENT_NAME"), version)?;
     wait_on_signals();
@@ -107,6 +107,9 @@ bitflags! {
         const TAP_SIDE = 1<<48;
         const TAP_PORT = 1<<49;
         const L7_PROTOCOL = 1<<51;
+        const PROCESS_KNAME = 1<<52;
+        const SERVER_NAME = 1<<53;
+        const KEY_PATTERN = 1<<54;
 
         const TAG_TYPE = 1<<62;
         const TAG_VALUE = 1<<63;
@@ -125,7 +128,7 @@ impl Default for Code {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Direction {
     None,
@@ -221,6 +224,27 @@ impl From<Direction> for TapSide {
     }
 }
 
+impl From<TapSide> for Direction {
+    fn from(tap_side: TapSide) -> Self {
+        match tap_side {
+            TapSide::Rest => Direction::None,
+            TapSide::Client => Direction::ClientToServer,
+            TapSide::Server => Direction::ServerToClient,
+            TapSide::Local => Direction::LocalToLocal,
+            TapSide::ClientNode => Direction::ClientNodeToServer,
+            TapSide::ServerNode => Direction::ServerNodeToClient,
+            TapSide::ClientHypervisor => Direction::ClientHypervisorToServer,
+            TapSide::ServerHypervisor => Direction::ServerHypervisorToClient,
+            TapSide::ClientGatewayHypervisor => Direction::ClientGatewayHypervisorToServer,
+            TapSide::ServerGatewayHypervisor => Direction::ServerGatewayHypervisorToClient,
+            TapSide::ClientGateway => Direction::ClientGatewayToServer,
+            TapSide::ServerGateway => Direction::ServerGatewayToClient,
+            TapSide::ClientProcess => Direction::ClientProcessToServer,
+            TapSide::ServerProcess => Direction::ServerProcessToClient,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum TagType {
@@ -262,6 +286,19 @@ pub struct Tagger {
 
     pub tag_type: TagType,
     pub tag_value: u16,
+
+    // 双栈服务ID，开启dual-stack-merge-enabled时填写，不参与聚合
+    pub service_id: u32,
+    pub service_id1: u32,
+
+    // eBPF采集时的进程名，用于进程级别的应用指标
+    pub process_kname: String,
+
+    // TLS SNI，用于区分共享IP的HTTPS服务
+    pub server_name: String,
+
+    // Redis的key模式，用于发现热点key族
+    pub key_pattern: String,
 }
 
 impl Default for Tagger {
@@ -289,6 +326,15 @@ impl Default for Tagger {
 
             tag_type: TagType::default(),
             tag_value: 0,
+
+            service_id: 0,
+            service_id1: 0,
+
+            process_kname: String::new(),
+
+            server_name: String::new(),
+
+            key_pattern: String::new(),
         }
     }
 }
@@ -339,6 +385,11 @@ impl From<Tagger> for metric::MiniTag {
                 l7_protocol: t.l7_protocol as u32,
                 tag_type: t.tag_type as u32,
                 tag_value: t.tag_value as u32,
+                service_id: t.service_id,
+                service_id1: t.service_id1,
+                process_kname: t.process_kname,
+                server_name: t.server_name,
+                key_pattern: t.key_pattern,
             }),
         }
     }
@@ -349,6 +400,8 @@ mod tests {
     use super::*;
     use prost::Message;
 
+    use crate::metric::meter::Traffic;
+
     #[test]
     fn merge_reverse() {
         let mut doc1 = Document::new(Meter::new_flow());
@@ -369,6 +422,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sampled_traffic() {
+        let mut sampled = Traffic {
+            packet_tx: 2,
+            byte_tx: 200,
+            l3_byte_tx: 160,
+            ..Default::default()
+        };
+        sampled.scale(10);
+        assert!(sampled.is_sampled);
+        assert_eq!(sampled.packet_tx, 20);
+        assert_eq!(sampled.byte_tx, 2000);
+        assert_eq!(sampled.l3_byte_tx, 1600);
+        assert_eq!(sampled.raw_packet_tx, 2);
+        assert_eq!(sampled.raw_byte_tx, 200);
+
+        let mut traffic = Traffic {
+            packet_tx: 3,
+            byte_tx: 300,
+            ..Default::default()
+        };
+        traffic.scale(1);
+        assert!(!traffic.is_sampled);
+        traffic.sequential_merge(&sampled);
+        assert!(traffic.is_sampled);
+        assert_eq!(traffic.packet_tx, 23);
+        assert_eq!(traffic.raw_packet_tx, 5);
+        assert_eq!(traffic.raw_byte_tx, 500);
+        traffic.reverse();
+        assert_eq!(traffic.raw_packet_rx, 5);
+    }
+
     #[test]
     fn encode() {
         let mut doc = Document::new(Meter::new_flow());
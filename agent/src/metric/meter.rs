@@ -141,10 +141,59 @@ pub struct Traffic {
     pub l7_response: u32,
     pub syn: u32,
     pub synack: u32,
+
+    // 采样的流量按采样比例放大统计值，原始值保存在raw_*中，未采样时raw_*为0
+    pub is_sampled: bool,
+    pub raw_packet_tx: u64,
+    pub raw_packet_rx: u64,
+    pub raw_byte_tx: u64,
+    pub raw_byte_rx: u64,
 }
 
 impl Traffic {
+    pub fn scale(&mut self, ratio: u32) {
+        if ratio <= 1 {
+            return;
+        }
+        let ratio = ratio as u64;
+        self.is_sampled = true;
+        self.raw_packet_tx = self.packet_tx;
+        self.raw_packet_rx = self.packet_rx;
+        self.raw_byte_tx = self.byte_tx;
+        self.raw_byte_rx = self.byte_rx;
+        self.packet_tx = self.packet_tx.saturating_mul(ratio);
+        self.packet_rx = self.packet_rx.saturating_mul(ratio);
+        self.byte_tx = self.byte_tx.saturating_mul(ratio);
+        self.byte_rx = self.byte_rx.saturating_mul(ratio);
+        self.l3_byte_tx = self.l3_byte_tx.saturating_mul(ratio);
+        self.l3_byte_rx = self.l3_byte_rx.saturating_mul(ratio);
+        self.l4_byte_tx = self.l4_byte_tx.saturating_mul(ratio);
+        self.l4_byte_rx = self.l4_byte_rx.saturating_mul(ratio);
+    }
+
+    // 未采样时原始值即为统计值
+    fn raw_counts(&self) -> [u64; 4] {
+        if self.is_sampled {
+            [
+                self.raw_packet_tx,
+                self.raw_packet_rx,
+                self.raw_byte_tx,
+                self.raw_byte_rx,
+            ]
+        } else {
+            [self.packet_tx, self.packet_rx, self.byte_tx, self.byte_rx]
+        }
+    }
+
     pub fn sequential_merge(&mut self, other: &Traffic) {
+        if self.is_sampled || other.is_sampled {
+            let (raw, other_raw) = (self.raw_counts(), other.raw_counts());
+            self.raw_packet_tx = raw[0] + other_raw[0];
+            self.raw_packet_rx = raw[1] + other_raw[1];
+            self.raw_byte_tx = raw[2] + other_raw[2];
+            self.raw_byte_rx = raw[3] + other_raw[3];
+            self.is_sampled = true;
+        }
         self.packet_tx += other.packet_tx;
         self.packet_rx += other.packet_rx;
         self.byte_tx += other.byte_tx;
@@ -166,6 +215,8 @@ impl Traffic {
         swap(&mut self.byte_tx, &mut self.byte_rx);
         swap(&mut self.l3_byte_tx, &mut self.l3_byte_rx);
         swap(&mut self.l4_byte_tx, &mut self.l4_byte_rx);
+        swap(&mut self.raw_packet_tx, &mut self.raw_packet_rx);
+        swap(&mut self.raw_byte_tx, &mut self.raw_byte_rx);
 
         // flow, L7等其他统计,以客户端、服务端为视角，无需Reverse
     }
@@ -188,6 +239,11 @@ impl From<Traffic> for metric::Traffic {
             l7_response: m.l7_response,
             syn: m.syn,
             synack: m.synack,
+            is_sampled: m.is_sampled,
+            raw_packet_tx: m.raw_packet_tx,
+            raw_packet_rx: m.raw_packet_rx,
+            raw_byte_tx: m.raw_byte_tx,
+            raw_byte_rx: m.raw_byte_rx,
         }
     }
 }
@@ -420,6 +476,9 @@ pub struct AppMeter {
     pub traffic: AppTraffic,
     pub latency: AppLatency,
     pub anomaly: AppAnomaly,
+    pub connection: AppConnection,
+    pub dns: AppDns,
+    pub ntp: AppNtp,
 }
 
 impl AppMeter {
@@ -427,6 +486,9 @@ impl AppMeter {
         self.traffic.sequential_merge(&other.traffic);
         self.latency.sequential_merge(&other.latency);
         self.anomaly.sequential_merge(&other.anomaly);
+        self.connection.sequential_merge(&other.connection);
+        self.dns.sequential_merge(&other.dns);
+        self.ntp.sequential_merge(&other.ntp);
     }
     pub fn reverse(&mut self) {
         self.traffic.reverse()
@@ -439,6 +501,9 @@ impl From<AppMeter> for metric::AppMeter {
             traffic: Some(m.traffic.into()),
             latency: Some(m.latency.into()),
             anomaly: Some(m.anomaly.into()),
+            connection: Some(m.connection.into()),
+            dns: Some(m.dns.into()),
+            ntp: Some(m.ntp.into()),
         }
     }
 }
@@ -520,6 +585,105 @@ impl From<AppAnomaly> for metric::AppAnomaly {
     }
 }
 
+// HTTP连接的复用情况，只统计已关闭连接上的请求数，首个请求时延在包含首个请求的周期统计
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AppConnection {
+    pub closed: u32,
+    pub closed_request: u32,
+    pub single_request: u32,          // 只有一个请求即关闭的连接数
+    pub first_request_delay_max: u32, // us
+    pub first_request_delay_sum: u64, // us
+    pub first_request_delay_count: u32,
+}
+
+impl AppConnection {
+    pub fn sequential_merge(&mut self, other: &AppConnection) {
+        self.closed += other.closed;
+        self.closed_request += other.closed_request;
+        self.single_request += other.single_request;
+        if self.first_request_delay_max < other.first_request_delay_max {
+            self.first_request_delay_max = other.first_request_delay_max;
+        }
+        self.first_request_delay_sum += other.first_request_delay_sum;
+        self.first_request_delay_count += other.first_request_delay_count;
+    }
+}
+
+impl From<AppConnection> for metric::AppConnection {
+    fn from(m: AppConnection) -> Self {
+        metric::AppConnection {
+            closed: m.closed,
+            closed_request: m.closed_request,
+            single_request: m.single_request,
+            first_request_delay_max: m.first_request_delay_max,
+            first_request_delay_sum: m.first_request_delay_sum,
+            first_request_delay_count: m.first_request_delay_count,
+        }
+    }
+}
+
+// 由DNS日志聚合的解析器健康状况，查询数为traffic.request，超时数为anomaly.timeout
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AppDns {
+    pub nxdomain: u32,
+    pub servfail: u32,
+    pub rrt_p95: u32, // us
+}
+
+impl AppDns {
+    // 分位数无法合并，取较大值
+    pub fn sequential_merge(&mut self, other: &AppDns) {
+        self.nxdomain += other.nxdomain;
+        self.servfail += other.servfail;
+        if self.rrt_p95 < other.rrt_p95 {
+            self.rrt_p95 = other.rrt_p95;
+        }
+    }
+}
+
+impl From<AppDns> for metric::AppDns {
+    fn from(m: AppDns) -> Self {
+        metric::AppDns {
+            nxdomain: m.nxdomain,
+            servfail: m.servfail,
+            rrt_p95: m.rrt_p95,
+        }
+    }
+}
+
+// 由NTP日志聚合的时钟偏差，偏差为服务端相对客户端的时间差
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AppNtp {
+    pub offset_sum: i64, // us
+    pub offset_count: u32,
+    pub offset_max: u32,     // us，偏差绝对值的最大值
+    pub jitter: u32,         // us，偏差的标准差
+    pub unsynchronized: u32, // 服务端时钟未同步或拒绝服务的响应数
+}
+
+impl AppNtp {
+    // 标准差无法合并，取较大值
+    pub fn sequential_merge(&mut self, other: &AppNtp) {
+        self.offset_sum += other.offset_sum;
+        self.offset_count += other.offset_count;
+        self.offset_max = self.offset_max.max(other.offset_max);
+        self.jitter = self.jitter.max(other.jitter);
+        self.unsynchronized += other.unsynchronized;
+    }
+}
+
+impl From<AppNtp> for metric::AppNtp {
+    fn from(m: AppNtp) -> Self {
+        metric::AppNtp {
+            offset_sum: m.offset_sum,
+            offset_count: m.offset_count,
+            offset_max: m.offset_max,
+            jitter: m.jitter,
+            unsynchronized: m.unsynchronized,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct UsageMeter {
     pub packet_tx: u64,
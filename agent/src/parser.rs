@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Application protocol parsers usable without the agent runtime.
+//!
+//! ```ignore
+//! use deepflow_agent::parser::{
+//!     check_protocol, AppLogs, L7ProtocolDetector, MetaPacket, PacketDirection,
+//! };
+//!
+//! let detector = L7ProtocolDetector::default();
+//! let packet = MetaPacket::from_raw(&frame, timestamp)?;
+//! let protocol = check_protocol(&packet, &detector);
+//! if let (Some(protocol), Some(payload)) = (protocol, packet.get_l4_payload()) {
+//!     let mut logs = AppLogs::default();
+//!     let (head, info) = logs.parse(
+//!         protocol,
+//!         payload,
+//!         packet.lookup_key.proto,
+//!         PacketDirection::ClientToServer,
+//!     )?;
+//! }
+//! ```
+
+/// Packet decoding, built with [`MetaPacket::from_raw`] from an ethernet frame.
+pub use crate::common::{
+    enums::{IpProtocol, PacketDirection},
+    flow::L7Protocol,
+    meta_packet::MetaPacket,
+};
+/// Error returned when decoding a raw packet.
+pub use crate::error::{Error as PacketError, Result as PacketResult};
+/// Protocol detection with [`check_protocol`] in the order of an [`L7ProtocolDetector`] and
+/// payload parsing with [`AppLogs::parse`].
+pub use crate::flow_generator::{
+    check_protocol, AppLogs, AppProtoHead, AppProtoHeadEnum, AppProtoLogsInfo,
+    AppProtoLogsInfoEnum, ClickhouseInfo, ClickhouseLog, DnsInfo, DnsLog, DubboInfo, DubboLog,
+    Error, HttpInfo, HttpLog, KafkaInfo, KafkaLog, L7LogParse, L7ProtocolDetector,
+    L7ResponseStatus, LogMessageType, ModbusInfo, ModbusLog, MqttInfo, MqttLog, MysqlInfo,
+    MysqlLog, NtpInfo, NtpLog, RadiusInfo, RadiusLog, RedisInfo, RedisLog, Result, S7Info, S7Log,
+    SnmpInfo, SnmpLog, TdsInfo, TdsLog, TlsInfo, TlsLog,
+};
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    #[test]
+    fn parse_raw_http() {
+        let frames: Vec<Vec<u8>> =
+            Capture::load_pcap("resources/test/flow_generator/http/httpv1.pcap", Some(1500)).into();
+        let packet = frames
+            .iter()
+            .map(|f| MetaPacket::from_raw(f, Duration::ZERO).unwrap())
+            .find(|p| p.get_l4_payload().is_some())
+            .unwrap();
+        assert_eq!(
+            check_protocol(&packet, &L7ProtocolDetector::default()),
+            Some(L7Protocol::Http1)
+        );
+
+        let mut logs = AppLogs::default();
+        let (head, info) = logs
+            .parse(
+                L7Protocol::Http1,
+                packet.get_l4_payload().unwrap(),
+                packet.lookup_key.proto,
+                PacketDirection::ClientToServer,
+            )
+            .unwrap();
+        match head {
+            AppProtoHeadEnum::Single(h) => assert_eq!(h.msg_type, LogMessageType::Request),
+            _ => panic!("unexpected head"),
+        }
+        match info.into_inner() {
+            AppProtoLogsInfo::HttpV1(i) => assert_eq!(i.method, "POST"),
+            _ => panic!("unexpected info"),
+        }
+    }
+}
@@ -15,7 +15,7 @@
  */
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     os::unix::io::AsRawFd,
     path::PathBuf,
@@ -31,7 +31,9 @@ use log::{debug, info, log_enabled, warn, Level};
 use nix::errno::Errno;
 use nix::sched::{setns, CloneFlags};
 
-use super::{ls_ns_net, Poller};
+use super::{
+    get_pod_namespace, get_pod_uid, ls_ns_net, pod_namespace::PodNamespaceRefresher, Poller,
+};
 use crate::platform::InterfaceInfo;
 use crate::utils::net::{addr_list, link_list};
 
@@ -57,7 +59,9 @@ impl ActivePoller {
         }
     }
 
-    fn query(priv_logged: &mut bool) -> Option<Vec<InterfaceInfo>> {
+    // unresolved返回命名空间无法确定的POD UID
+    fn query(priv_logged: &mut bool, unresolved: &mut Vec<String>) -> Option<Vec<InterfaceInfo>> {
+        unresolved.clear();
         let netns = fs::File::open("/proc/self/ns/net");
         if netns.is_err() {
             warn!("get self net namespace failed: {:?}", netns.unwrap_err());
@@ -90,6 +94,14 @@ impl ActivePoller {
                 continue;
             }
 
+            // 同一POD的容器共享网络命名空间，pause容器未挂载ServiceAccount，需遍历所有进程
+            let pod_namespace = nss.iter().find_map(|&pid| get_pod_namespace(pid));
+            if pod_namespace.is_none() {
+                if let Some(uid) = nss.iter().find_map(|&pid| get_pod_uid(pid)) {
+                    unresolved.push(uid);
+                }
+            }
+            let pod_namespace = pod_namespace.unwrap_or_default();
             let mut current_ns_found = false;
             for &pid in nss.iter() {
                 let ns_id = Self::get_net_ns_by(pid);
@@ -151,6 +163,7 @@ impl ActivePoller {
                             .and_then(|p| p.to_str())
                             .map(|s| s.to_string())
                             .unwrap(),
+                        pod_namespace: pod_namespace.clone(),
                     };
                     new_interface_info.push(info);
                 }
@@ -194,6 +207,28 @@ impl ActivePoller {
         }
     }
 
+    // 存在命名空间未知的POD时通过K8s API刷新UID映射后重新查询，仍未知的POD每个只打印一次日志
+    fn poll(
+        priv_logged: &mut bool,
+        refresher: &mut PodNamespaceRefresher,
+        logged_pods: &mut HashSet<String>,
+    ) -> Option<Vec<InterfaceInfo>> {
+        let mut unresolved = vec![];
+        let mut interface_info = Self::query(priv_logged, &mut unresolved);
+        if !unresolved.is_empty() && refresher.refresh() {
+            interface_info = Self::query(priv_logged, &mut unresolved);
+        }
+        for uid in unresolved {
+            if logged_pods.insert(uid.clone()) {
+                warn!(
+                    "unable to resolve namespace of pod {}, its interfaces are excluded by capture-namespaces",
+                    uid
+                );
+            }
+        }
+        interface_info
+    }
+
     fn process(
         timer: Arc<Condvar>,
         running: Arc<Mutex<bool>>,
@@ -202,8 +237,10 @@ impl ActivePoller {
         timeout: Duration,
     ) {
         let mut priv_logged = false;
+        let mut refresher = PodNamespaceRefresher::default();
+        let mut logged_pods = HashSet::new();
         // 初始化
-        *entries.lock().unwrap() = Self::query(&mut priv_logged);
+        *entries.lock().unwrap() = Self::poll(&mut priv_logged, &mut refresher, &mut logged_pods);
         version.store(1, Ordering::SeqCst);
 
         loop {
@@ -217,7 +254,7 @@ impl ActivePoller {
             }
             drop(guard);
 
-            let new_interface_info = Self::query(&mut priv_logged);
+            let new_interface_info = Self::poll(&mut priv_logged, &mut refresher, &mut logged_pods);
             // compare two lists
             let mut old_interface_info = entries.lock().unwrap();
             if old_interface_info.eq(&new_interface_info) {
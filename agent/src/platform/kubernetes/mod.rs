@@ -14,18 +14,28 @@
  * limitations under the License.
  */
 
-use std::{collections::HashMap, fs, io, os::unix::io::AsRawFd, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    os::unix::io::AsRawFd,
+    time::Duration,
+};
 
 use enum_dispatch::enum_dispatch;
 use nix::sched::{setns, CloneFlags};
 
 mod active_poller;
 mod api_watcher;
+mod pod_namespace;
 mod resource_watcher;
 pub use active_poller::ActivePoller;
 pub use api_watcher::ApiWatcher;
+pub use pod_namespace::get_pod_uid;
 
 use super::InterfaceInfo;
+use crate::utils::net::Link;
+
+const SERVICE_ACCOUNT_NAMESPACE: &str = "var/run/secrets/kubernetes.io/serviceaccount/namespace";
 
 #[enum_dispatch]
 pub enum GenericPoller {
@@ -101,6 +111,37 @@ fn ls_ns_net() -> io::Result<Vec<Vec<u32>>> {
     Ok(ret)
 }
 
+// 获取进程所在POD的命名空间，优先读取容器挂载的ServiceAccount，
+// pause容器或设置了automountServiceAccountToken: false的POD没有该文件，
+// 此时通过cgroup中的POD UID在K8s API查询结果中查找，均失败时返回None
+pub fn get_pod_namespace(pid: u32) -> Option<String> {
+    let namespace = fs::read_to_string(format!("/proc/{}/root/{}", pid, SERVICE_ACCOUNT_NAMESPACE))
+        .ok()
+        .map(|ns| ns.trim().to_string())
+        .filter(|ns| !ns.is_empty());
+    if namespace.is_some() {
+        return namespace;
+    }
+    pod_namespace::lookup_pod_namespace(&get_pod_uid(pid)?)
+}
+
+// 仅保留属于指定命名空间POD的TAP口，即POD在宿主机侧的veth
+pub fn filter_links_by_namespace(
+    links: Vec<Link>,
+    interfaces: &[InterfaceInfo],
+    namespaces: &[String],
+) -> Vec<Link> {
+    let allowed = interfaces
+        .iter()
+        .filter(|info| namespaces.contains(&info.pod_namespace))
+        .map(|info| info.tap_idx)
+        .collect::<HashSet<_>>();
+    links
+        .into_iter()
+        .filter(|link| allowed.contains(&link.if_index))
+        .collect()
+}
+
 pub fn check_set_ns() -> bool {
     match fs::File::open("/proc/self/ns/net") {
         Ok(f) => setns(f.as_raw_fd(), CloneFlags::CLONE_NEWNET).is_ok(),
@@ -111,3 +152,35 @@ pub fn check_set_ns() -> bool {
 pub fn check_read_link_ns() -> bool {
     fs::read_link("/proc/1/ns/net").is_ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::utils::net::MacAddr;
+
+    #[test]
+    fn filter_links() {
+        let info = |tap_idx: u32, pod_namespace: &str| InterfaceInfo {
+            tap_idx,
+            mac: MacAddr::ZERO,
+            ips: vec![],
+            name: "eth0".to_string(),
+            device_id: "net:[4026532000]".to_string(),
+            pod_namespace: pod_namespace.to_string(),
+        };
+        let interfaces = vec![info(10, "default"), info(11, "kube-system"), info(12, "")];
+        let links = (9..=12)
+            .map(|if_index| Link {
+                if_index,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let filtered = filter_links_by_namespace(links, &interfaces, &["default".to_string()]);
+        assert_eq!(
+            filtered.iter().map(|l| l.if_index).collect::<Vec<_>>(),
+            vec![10]
+        );
+    }
+}
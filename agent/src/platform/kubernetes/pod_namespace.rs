@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{api::ListParams, Api, Client};
+use log::{info, warn};
+use tokio::runtime::Builder;
+
+// POD UID到命名空间的映射，由ActivePoller通过K8s API刷新，其他模块只读
+static POD_NAMESPACES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+// 两次API查询的最小间隔，避免未知POD频繁触发全量查询
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+// 从cgroup路径中解析POD UID，支持以下两种cgroup driver：
+//   cgroupfs: /kubepods/burstable/pod<uid>/<container_id>
+//   systemd:  /kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod<uid>.slice/...
+// systemd格式中UID的'-'被替换为'_'，统一还原为'-'
+pub fn parse_pod_uid(cgroup: &str) -> Option<String> {
+    for line in cgroup.lines() {
+        // 格式为 hierarchy-ID:controller-list:cgroup-path
+        let path = match line.splitn(3, ':').nth(2) {
+            Some(p) => p,
+            None => continue,
+        };
+        for segment in path.split('/') {
+            let segment = segment.trim_end_matches(".slice");
+            let uid = match segment.rfind("pod") {
+                Some(i) => &segment[i + 3..],
+                None => continue,
+            };
+            // UID固定为36个字符
+            if uid.len() == 36
+                && uid
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit() || c == '-' || c == '_')
+            {
+                return Some(uid.replace('_', "-"));
+            }
+        }
+    }
+    None
+}
+
+pub fn get_pod_uid(pid: u32) -> Option<String> {
+    parse_pod_uid(&fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?)
+}
+
+pub fn lookup_pod_namespace(uid: &str) -> Option<String> {
+    POD_NAMESPACES
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|m| m.get(uid).cloned())
+}
+
+// 通过K8s API查询所有POD的UID及命名空间，结果写入POD_NAMESPACES
+#[derive(Default)]
+pub struct PodNamespaceRefresher {
+    last_refresh: Option<Instant>,
+}
+
+impl PodNamespaceRefresher {
+    // 距上次查询不足REFRESH_INTERVAL时不查询，返回是否更新了映射
+    pub fn refresh(&mut self) -> bool {
+        if matches!(self.last_refresh, Some(t) if t.elapsed() < REFRESH_INTERVAL) {
+            return false;
+        }
+        self.last_refresh = Some(Instant::now());
+
+        let runtime = match Builder::new_current_thread().enable_all().build() {
+            Ok(r) => r,
+            Err(e) => {
+                warn!("create runtime for pod namespace query failed: {}", e);
+                return false;
+            }
+        };
+        match runtime.block_on(Self::list_pods()) {
+            Ok(pods) => {
+                info!("pod namespace mapping refreshed with {} pods", pods.len());
+                *POD_NAMESPACES.lock().unwrap() = Some(pods);
+                true
+            }
+            Err(e) => {
+                warn!("query pod namespaces from kubernetes api failed: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn list_pods() -> Result<HashMap<String, String>, kube::Error> {
+        let client = Client::try_default().await?;
+        let pods = Api::<Pod>::all(client).list(&ListParams::default()).await?;
+        Ok(pods
+            .into_iter()
+            .filter_map(|pod| Some((pod.metadata.uid?, pod.metadata.namespace?)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pod_uid() {
+        let cgroupfs = "12:memory:/kubepods/burstable/pod0b5a6d3e-1c2f-4a8b-9e7d-3f4c5b6a7d8e/7c1a2b3d4e5f\n\
+                        0::/kubepods/burstable/pod0b5a6d3e-1c2f-4a8b-9e7d-3f4c5b6a7d8e/7c1a2b3d4e5f\n";
+        assert_eq!(
+            parse_pod_uid(cgroupfs).as_deref(),
+            Some("0b5a6d3e-1c2f-4a8b-9e7d-3f4c5b6a7d8e")
+        );
+
+        let systemd = "0::/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod0b5a6d3e_1c2f_4a8b_9e7d_3f4c5b6a7d8e.slice/cri-containerd-7c1a2b3d4e5f.scope\n";
+        assert_eq!(
+            parse_pod_uid(systemd).as_deref(),
+            Some("0b5a6d3e-1c2f-4a8b-9e7d-3f4c5b6a7d8e")
+        );
+
+        assert_eq!(parse_pod_uid("0::/system.slice/sshd.service\n"), None);
+        assert_eq!(
+            parse_pod_uid("0::/kubepods.slice/kubepods-pod.slice\n"),
+            None
+        );
+    }
+}
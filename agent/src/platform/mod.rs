@@ -19,15 +19,22 @@ mod kubernetes;
 mod libvirt_xml_extractor;
 #[cfg(target_os = "linux")]
 mod platform_synchronizer;
+#[cfg(target_os = "windows")]
+mod process_socket_table;
 
 use std::fmt;
 use std::net::IpAddr;
 
 #[cfg(target_os = "linux")]
-pub use kubernetes::{ActivePoller, ApiWatcher, GenericPoller, Poller};
+pub use kubernetes::{
+    filter_links_by_namespace, get_pod_namespace, get_pod_uid, ActivePoller, ApiWatcher,
+    GenericPoller, Poller,
+};
 pub use libvirt_xml_extractor::LibvirtXmlExtractor;
 #[cfg(target_os = "linux")]
 pub use platform_synchronizer::PlatformSynchronizer;
+#[cfg(target_os = "windows")]
+pub use process_socket_table::ProcessSocketTable;
 
 use crate::utils::net::MacAddr;
 
@@ -46,6 +53,8 @@ pub struct InterfaceInfo {
     pub ips: Vec<IpAddr>,
     pub name: String,
     pub device_id: String,
+    // POD所在的Kubernetes命名空间，无法获取时为空
+    pub pod_namespace: String,
 }
 
 impl fmt::Display for InterfaceInfo {
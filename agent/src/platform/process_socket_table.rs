@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, RwLock,
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use crate::common::enums::IpProtocol;
+use crate::flow_generator::AppProtoLogsBaseInfo;
+use crate::utils::{
+    intern::intern,
+    process::{get_process_names, get_socket_owners, SocketOwner},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+type SocketKey = (u8, IpAddr, u16);
+
+// Windows上没有eBPF，周期性读取系统的TCP/UDP连接表，按本地IP和端口关联进程，
+// 仅能关联刷新时仍存在的连接
+#[derive(Clone, Default)]
+pub struct ProcessSocketTable {
+    sockets: Arc<RwLock<HashMap<SocketKey, (u32, Arc<str>)>>>,
+    running: Arc<AtomicBool>,
+    thread: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl ProcessSocketTable {
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let sockets = self.sockets.clone();
+        let running = self.running.clone();
+        let thread = thread::Builder::new()
+            .name("process-socket-table".to_owned())
+            .spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match Self::load() {
+                        Ok(table) => {
+                            debug!(
+                                "process socket table refreshed with {} sockets",
+                                table.len()
+                            );
+                            *sockets.write().unwrap() = table;
+                        }
+                        Err(e) => warn!("refresh process socket table failed: {}", e),
+                    }
+                    thread::sleep(REFRESH_INTERVAL);
+                }
+            })
+            .unwrap();
+        self.thread.lock().unwrap().replace(thread);
+        info!("process socket table started");
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        info!("process socket table stopped");
+    }
+
+    fn load() -> crate::error::Result<HashMap<SocketKey, (u32, Arc<str>)>> {
+        let owners = get_socket_owners()?;
+        let names = get_process_names()?;
+        Ok(Self::build(owners, &names))
+    }
+
+    fn build(
+        owners: Vec<SocketOwner>,
+        names: &HashMap<u32, String>,
+    ) -> HashMap<SocketKey, (u32, Arc<str>)> {
+        owners
+            .into_iter()
+            .map(|o| {
+                let name = names.get(&o.pid).map(|n| intern(n)).unwrap_or_default();
+                ((u8::from(o.protocol), o.ip, o.port), (o.pid, name))
+            })
+            .collect()
+    }
+
+    // 先精确匹配，再匹配监听在任意地址上的socket
+    fn lookup(&self, protocol: IpProtocol, ip: IpAddr, port: u16) -> Option<(u32, Arc<str>)> {
+        let sockets = self.sockets.read().unwrap();
+        let protocol = u8::from(protocol);
+        let any: IpAddr = if ip.is_ipv4() {
+            Ipv4Addr::UNSPECIFIED.into()
+        } else {
+            Ipv6Addr::UNSPECIFIED.into()
+        };
+        sockets
+            .get(&(protocol, ip, port))
+            .or_else(|| sockets.get(&(protocol, any, port)))
+            .cloned()
+    }
+
+    // 已由其他途径获取进程信息的一侧不修改
+    pub fn fill(&self, base_info: &mut AppProtoLogsBaseInfo) {
+        if base_info.process_id_0 == 0 {
+            if let Some((pid, name)) =
+                self.lookup(base_info.protocol, base_info.ip_src, base_info.port_src)
+            {
+                base_info.process_id_0 = pid;
+                base_info.process_kname_0 = name;
+            }
+        }
+        if base_info.process_id_1 == 0 {
+            if let Some((pid, name)) =
+                self.lookup(base_info.protocol, base_info.ip_dst, base_info.port_dst)
+            {
+                base_info.process_id_1 = pid;
+                base_info.process_kname_1 = name;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let table = ProcessSocketTable::default();
+        let owners = vec![
+            SocketOwner {
+                protocol: IpProtocol::Tcp,
+                ip: "10.1.1.1".parse().unwrap(),
+                port: 52000,
+                pid: 100,
+            },
+            SocketOwner {
+                protocol: IpProtocol::Tcp,
+                ip: Ipv4Addr::UNSPECIFIED.into(),
+                port: 80,
+                pid: 4,
+            },
+        ];
+        let names = [(100, "curl.exe".to_owned()), (4, "System".to_owned())]
+            .into_iter()
+            .collect();
+        *table.sockets.write().unwrap() = ProcessSocketTable::build(owners, &names);
+
+        let src = "10.1.1.1".parse().unwrap();
+        let dst = "10.1.1.2".parse().unwrap();
+        assert_eq!(
+            table.lookup(IpProtocol::Tcp, src, 52000),
+            Some((100, intern("curl.exe")))
+        );
+        assert_eq!(
+            table.lookup(IpProtocol::Tcp, dst, 80),
+            Some((4, intern("System")))
+        );
+        assert_eq!(table.lookup(IpProtocol::Udp, src, 52000), None);
+        assert_eq!(table.lookup(IpProtocol::Tcp, dst, 52000), None);
+    }
+}
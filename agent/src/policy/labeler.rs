@@ -683,6 +683,65 @@ mod tests {
         assert_eq!(real_ip, ip6.parse::<Ipv6Addr>().unwrap());
     }
 
+    #[test]
+    fn test_dual_stack_id() {
+        let mut labeler: Labeler = Default::default();
+        let dual_stack: PlatformData = PlatformData {
+            mac: 0x112233445566,
+            ips: vec![
+                IpSubnet {
+                    raw_ip: "192.168.10.100".parse().unwrap(),
+                    ..Default::default()
+                },
+                IpSubnet {
+                    raw_ip: "2002:2002::10".parse().unwrap(),
+                    netmask: 128,
+                    ..Default::default()
+                },
+            ],
+            epc_id: 1,
+            id: 10,
+            ..Default::default()
+        };
+        let single_stack: PlatformData = PlatformData {
+            mac: 0x112233445577,
+            ips: vec![IpSubnet {
+                raw_ip: "192.168.10.200".parse().unwrap(),
+                ..Default::default()
+            }],
+            epc_id: 1,
+            id: 20,
+            ..Default::default()
+        };
+        labeler.update_interface_table(&vec![Arc::new(dual_stack), Arc::new(single_stack)]);
+
+        let (v4, _) = labeler.get_endpoint_info(
+            0x112233445566,
+            "192.168.10.100".parse().unwrap(),
+            true,
+            true,
+            0,
+        );
+        let (v6, _) = labeler.get_endpoint_info(
+            0x112233445566,
+            "2002:2002::10".parse().unwrap(),
+            true,
+            true,
+            0,
+        );
+        assert_eq!(v4.dual_stack_id, 10);
+        assert_eq!(v4.dual_stack_id, v6.dual_stack_id);
+
+        let (single, _) = labeler.get_endpoint_info(
+            0x112233445577,
+            "192.168.10.200".parse().unwrap(),
+            true,
+            true,
+            0,
+        );
+        assert_eq!(single.dual_stack_id, 0);
+    }
+
     #[test]
     fn test_epc_ip_normal() {
         let mut labeler: Labeler = Default::default();
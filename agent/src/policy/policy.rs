@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::net::IpAddr;
 use std::sync::Arc;
 
@@ -38,6 +39,8 @@ pub struct Policy {
     labeler: Labeler,
     table: FirstPath,
     forward: Forward,
+    // 容器ID到POD ID的映射，来自平台数据
+    container_pods: HashMap<String, u32>,
 
     queue_count: usize,
     first_hit: usize,
@@ -55,6 +58,7 @@ impl Policy {
             labeler: Labeler::default(),
             table: FirstPath::new(queue_count, level, map_size, fast_disable),
             forward: Forward::new(queue_count),
+            container_pods: HashMap::new(),
             queue_count,
             first_hit: 0,
             fast_hit: 0,
@@ -150,6 +154,11 @@ impl Policy {
     ) {
         self.labeler.update_interface_table(ifaces);
         self.table.update_interfaces(ifaces);
+        self.container_pods = ifaces
+            .iter()
+            .filter(|i| i.pod_id > 0)
+            .flat_map(|i| i.container_ids.iter().map(move |c| (c.clone(), i.pod_id)))
+            .collect();
 
         // TODO: 后续需要添加监控本地网卡，如果网卡配置有变化应该也需要出发表更新
         let local_interfaces = datalink::interfaces();
@@ -157,6 +166,13 @@ impl Policy {
             .update_from_config(trident_type, ifaces, &local_interfaces);
     }
 
+    pub fn lookup_pod_by_container(&self, container_id: &str) -> u32 {
+        self.container_pods
+            .get(container_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
     pub fn update_ip_group(&mut self, groups: &Vec<Arc<IpGroupData>>) {
         self.table.update_ip_group(groups);
     }
@@ -225,6 +241,11 @@ impl PolicyGetter {
         self.policy()
             .lookup_all_by_epc(src, dst, l3_epc_id_src, l3_epc_id_dst)
     }
+
+    // 未找到时返回0
+    pub fn lookup_pod_by_container(&self, container_id: &str) -> u32 {
+        self.policy().lookup_pod_by_container(container_id)
+    }
 }
 
 impl From<*mut Policy> for PolicyGetter {
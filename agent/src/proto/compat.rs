@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// flow_log的pb转换自检：随机值经From转换、编码和解码后须能还原为源字段，
+// 用于发现pb字段比源字段窄导致的静默截断，失败用例由proptest收缩并记录复现
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use proptest::prelude::*;
+use prost::Message;
+
+use super::flow_log;
+use crate::common::{
+    enums::IpProtocol,
+    flow::{FlowKey, FlowMetricsPeer, L7ApiPerfStats, L7PerfStats, TcpPerfCountsPeer},
+    CapturePoint, TapPort,
+};
+use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, DubboInfo};
+use crate::metric::document::TapSide;
+use crate::utils::net::MacAddr;
+
+const ROUNDS: u32 = 1000;
+
+fn round_trip<T: Message + Default>(pb: T) -> T {
+    let mut buf = vec![];
+    pb.encode(&mut buf).unwrap();
+    T::decode(buf.as_slice()).unwrap()
+}
+
+fn check<S, P>(name: &str, src: S, pb: P) -> Result<(), TestCaseError>
+where
+    S: TryFrom<P> + PartialEq + Debug + Copy,
+    P: Debug + Copy,
+{
+    prop_assert_eq!(
+        S::try_from(pb).ok(),
+        Some(src),
+        "{} truncated: {:?} -> {:?}",
+        name,
+        src,
+        pb
+    );
+    Ok(())
+}
+
+prop_compose! {
+    fn arb_tcp_perf_counts_peer()(
+        (retrans_count, zero_win_count, zero_win_duration) in any::<(u32, u32, u32)>(),
+        (win_min, win_sum, win_count) in any::<(u32, u64, u32)>(),
+        (dup_ack_burst_count, ooo_count, spurious_retrans_count) in any::<(u32, u32, u32)>(),
+    ) -> TcpPerfCountsPeer {
+        TcpPerfCountsPeer {
+            retrans_count,
+            zero_win_count,
+            zero_win_duration,
+            win_min,
+            win_sum,
+            win_count,
+            dup_ack_burst_count,
+            ooo_count,
+            spurious_retrans_count,
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_flow_metrics_peer()(
+        (byte_count, l3_byte_count, l4_byte_count, packet_count) in any::<(u64, u64, u64, u64)>(),
+        (total_byte_count, total_packet_count) in any::<(u64, u64)>(),
+        (first, last) in any::<(u64, u64)>(),
+        (l3_epc_id, tcp_fingerprint) in any::<(i32, u32)>(),
+    ) -> FlowMetricsPeer {
+        FlowMetricsPeer {
+            byte_count,
+            l3_byte_count,
+            l4_byte_count,
+            packet_count,
+            total_byte_count,
+            total_packet_count,
+            first: Duration::from_nanos(first),
+            last: Duration::from_nanos(last),
+            l3_epc_id,
+            tcp_fingerprint,
+            ..Default::default()
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_l7_api_perf_stats()(
+        (api, request_count, response_count) in any::<(u16, u32, u32)>(),
+        (rrt_count, rrt_sum, rrt_max) in any::<(u32, u64, u32)>(),
+    ) -> L7ApiPerfStats {
+        L7ApiPerfStats {
+            api,
+            request_count,
+            response_count,
+            rrt_count,
+            rrt_sum,
+            rrt_max,
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_l7_perf_stats()(
+        (request_count, response_count) in any::<(u32, u32)>(),
+        (err_client_count, err_server_count, err_timeout) in any::<(u32, u32, u32)>(),
+        (rrt_count, rrt_sum, rrt_max, rrt_outlier_count) in any::<(u32, u64, u32, u32)>(),
+        (redirect_count, connection_request_count) in any::<(u32, u32)>(),
+        first_request_delay in any::<u32>(),
+        api in arb_l7_api_perf_stats(),
+    ) -> L7PerfStats {
+        L7PerfStats {
+            request_count,
+            response_count,
+            err_client_count,
+            err_server_count,
+            err_timeout,
+            rrt_count,
+            rrt_sum,
+            rrt_max,
+            rrt_outlier_count,
+            redirect_count,
+            connection_request_count,
+            first_request_delay,
+            api_stats: vec![api],
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_flow_key()(
+        vtap_id in any::<u16>(),
+        (ip_src, ip_dst) in any::<(u32, u32)>(),
+        (port_src, port_dst) in any::<(u16, u16)>(),
+    ) -> FlowKey {
+        FlowKey {
+            vtap_id,
+            ip_src: IpAddr::from(Ipv4Addr::from(ip_src)),
+            ip_dst: IpAddr::from(Ipv4Addr::from(ip_dst)),
+            port_src,
+            port_dst,
+            proto: IpProtocol::Tcp,
+            ..Default::default()
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_app_proto_logs_base_info()(
+        (start_time, end_time, flow_id, vtap_id) in any::<(u64, u64, u64, u16)>(),
+        // pb中rrt单位为ns
+        (code, rrt) in any::<(u16, u32)>(),
+        (ip_src, ip_dst, l3_epc_id_src, l3_epc_id_dst) in any::<(u32, u32, i32, i32)>(),
+        (port_src, port_dst, req_tcp_seq, resp_tcp_seq) in any::<(u16, u16, u32, u32)>(),
+        (process_id_0, process_id_1, pod_id_0, pod_id_1) in any::<(u32, u32, u32, u32)>(),
+        (syscall_trace_id_request, syscall_trace_id_response) in any::<(u64, u64)>(),
+        (syscall_trace_id_thread_0, syscall_trace_id_thread_1) in any::<(u32, u32)>(),
+        (syscall_cap_seq_0, syscall_cap_seq_1) in any::<(u64, u64)>(),
+    ) -> AppProtoLogsBaseInfo {
+        AppProtoLogsBaseInfo {
+            start_time: Duration::from_nanos(start_time),
+            end_time: Duration::from_nanos(end_time),
+            flow_id,
+            tap_port: TapPort::default(),
+            capture_point: CapturePoint::default(),
+            vtap_id,
+            tap_type: Default::default(),
+            is_ipv6: false,
+            tap_side: TapSide::Rest,
+            head: AppProtoHead {
+                code,
+                rrt: rrt as u64,
+                ..Default::default()
+            },
+            mac_src: MacAddr::ZERO,
+            mac_dst: MacAddr::ZERO,
+            ip_src: IpAddr::from(Ipv4Addr::from(ip_src)),
+            ip_dst: IpAddr::from(Ipv4Addr::from(ip_dst)),
+            l3_epc_id_src,
+            l3_epc_id_dst,
+            port_src,
+            port_dst,
+            req_tcp_seq,
+            resp_tcp_seq,
+            process_id_0,
+            process_id_1,
+            process_kname_0: Arc::default(),
+            process_kname_1: Arc::default(),
+            syscall_trace_id_request,
+            syscall_trace_id_response,
+            syscall_trace_id_thread_0,
+            syscall_trace_id_thread_1,
+            syscall_cap_seq_0,
+            syscall_cap_seq_1,
+            container_id_0: Arc::default(),
+            container_id_1: Arc::default(),
+            pod_id_0,
+            pod_id_1,
+            protocol: IpProtocol::Tcp,
+            is_vip_interface_src: false,
+            is_vip_interface_dst: false,
+            business_labels_src: None,
+            business_labels_dst: None,
+            server_domain: String::new(),
+            rrt_outlier: false,
+        }
+    }
+}
+
+prop_compose! {
+    fn arb_dubbo_info()(
+        (serial_id, data_type) in any::<(u8, u8)>(),
+        // 协议中为8字节的非负请求ID
+        request_id in 0..=i64::MAX,
+        (req_msg_size, resp_msg_size) in any::<(i32, i32)>(),
+    ) -> DubboInfo {
+        DubboInfo {
+            serial_id,
+            data_type,
+            request_id,
+            req_msg_size,
+            resp_msg_size,
+            ..Default::default()
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(ROUNDS))]
+
+    #[test]
+    fn tcp_perf_counts_peer(src in arb_tcp_perf_counts_peer()) {
+        let pb = round_trip(flow_log::TcpPerfCountsPeer::from(src));
+        check("retrans_count", src.retrans_count, pb.retrans_count)?;
+        check("zero_win_count", src.zero_win_count, pb.zero_win_count)?;
+        check("zero_win_duration", src.zero_win_duration, pb.zero_win_duration)?;
+        check("win_min", src.win_min, pb.win_min)?;
+        check("win_sum", src.win_sum, pb.win_sum)?;
+        check("win_count", src.win_count, pb.win_count)?;
+        check("dup_ack_burst_count", src.dup_ack_burst_count, pb.dup_ack_burst_count)?;
+        check("ooo_count", src.ooo_count, pb.ooo_count)?;
+        check("spurious_retrans_count", src.spurious_retrans_count, pb.spurious_retrans_count)?;
+    }
+
+    #[test]
+    fn flow_metrics_peer(src in arb_flow_metrics_peer()) {
+        let pb = round_trip(flow_log::FlowMetricsPeer::from(src));
+        check("byte_count", src.byte_count, pb.byte_count)?;
+        check("l3_byte_count", src.l3_byte_count, pb.l3_byte_count)?;
+        check("l4_byte_count", src.l4_byte_count, pb.l4_byte_count)?;
+        check("packet_count", src.packet_count, pb.packet_count)?;
+        check("total_byte_count", src.total_byte_count, pb.total_byte_count)?;
+        check("total_packet_count", src.total_packet_count, pb.total_packet_count)?;
+        check("first", src.first.as_nanos() as u64, pb.first)?;
+        check("last", src.last.as_nanos() as u64, pb.last)?;
+        check("l3_epc_id", src.l3_epc_id, pb.l3_epc_id)?;
+        check("tcp_fingerprint", src.tcp_fingerprint, pb.tcp_fingerprint)?;
+    }
+
+    #[test]
+    fn l7_perf_stats(src in arb_l7_perf_stats()) {
+        let pb = round_trip(flow_log::L7PerfStats::from(src.clone()));
+        check("request_count", src.request_count, pb.request_count)?;
+        check("response_count", src.response_count, pb.response_count)?;
+        check("err_client_count", src.err_client_count, pb.err_client_count)?;
+        check("err_server_count", src.err_server_count, pb.err_server_count)?;
+        check("err_timeout", src.err_timeout, pb.err_timeout)?;
+        check("rrt_count", src.rrt_count, pb.rrt_count)?;
+        check("rrt_sum", src.rrt_sum, pb.rrt_sum)?;
+        check("rrt_max", src.rrt_max, pb.rrt_max)?;
+        check("rrt_outlier_count", src.rrt_outlier_count, pb.rrt_outlier_count)?;
+        check("redirect_count", src.redirect_count, pb.redirect_count)?;
+        check(
+            "connection_request_count",
+            src.connection_request_count,
+            pb.connection_request_count,
+        )?;
+        check("first_request_delay", src.first_request_delay, pb.first_request_delay)?;
+
+        let (api, pb_api) = (&src.api_stats[0], &pb.api_stats[0]);
+        check("api", api.api, pb_api.api)?;
+        check("api.request_count", api.request_count, pb_api.request_count)?;
+        check("api.response_count", api.response_count, pb_api.response_count)?;
+        check("api.rrt_count", api.rrt_count, pb_api.rrt_count)?;
+        check("api.rrt_sum", api.rrt_sum, pb_api.rrt_sum)?;
+        check("api.rrt_max", api.rrt_max, pb_api.rrt_max)?;
+    }
+
+    #[test]
+    fn flow_key(src in arb_flow_key()) {
+        let pb = round_trip(flow_log::FlowKey::from(src.clone()));
+        check("vtap_id", src.vtap_id, pb.vtap_id)?;
+        check("port_src", src.port_src, pb.port_src)?;
+        check("port_dst", src.port_dst, pb.port_dst)?;
+        prop_assert_eq!(IpAddr::from(Ipv4Addr::from(pb.ip_src)), src.ip_src);
+        prop_assert_eq!(IpAddr::from(Ipv4Addr::from(pb.ip_dst)), src.ip_dst);
+    }
+
+    #[test]
+    fn app_proto_logs_base_info(src in arb_app_proto_logs_base_info()) {
+        let pb = round_trip(flow_log::AppProtoLogsBaseInfo::from(src.clone()));
+        check("start_time", src.start_time.as_nanos() as u64, pb.start_time)?;
+        check("end_time", src.end_time.as_nanos() as u64, pb.end_time)?;
+        check("flow_id", src.flow_id, pb.flow_id)?;
+        check("vtap_id", src.vtap_id, pb.vtap_id)?;
+        check("l3_epc_id_src", src.l3_epc_id_src, pb.l3_epc_id_src)?;
+        check("l3_epc_id_dst", src.l3_epc_id_dst, pb.l3_epc_id_dst)?;
+        check("port_src", src.port_src, pb.port_src)?;
+        check("port_dst", src.port_dst, pb.port_dst)?;
+        check("req_tcp_seq", src.req_tcp_seq, pb.req_tcp_seq)?;
+        check("resp_tcp_seq", src.resp_tcp_seq, pb.resp_tcp_seq)?;
+        check("process_id_0", src.process_id_0, pb.process_id_0)?;
+        check("process_id_1", src.process_id_1, pb.process_id_1)?;
+        check(
+            "syscall_trace_id_request",
+            src.syscall_trace_id_request,
+            pb.syscall_trace_id_request,
+        )?;
+        check(
+            "syscall_trace_id_response",
+            src.syscall_trace_id_response,
+            pb.syscall_trace_id_response,
+        )?;
+        check(
+            "syscall_trace_id_thread_0",
+            src.syscall_trace_id_thread_0,
+            pb.syscall_trace_id_thread_0,
+        )?;
+        check(
+            "syscall_trace_id_thread_1",
+            src.syscall_trace_id_thread_1,
+            pb.syscall_trace_id_thread_1,
+        )?;
+        check("syscall_cap_seq_0", src.syscall_cap_seq_0, pb.syscall_cap_seq_0)?;
+        check("syscall_cap_seq_1", src.syscall_cap_seq_1, pb.syscall_cap_seq_1)?;
+        check("pod_id_0", src.pod_id_0, pb.pod_id_0)?;
+        check("pod_id_1", src.pod_id_1, pb.pod_id_1)?;
+
+        let head = pb.head.unwrap();
+        check("head.code", src.head.code, head.code)?;
+        check("head.rrt", src.head.rrt * 1000, head.rrt)?;
+    }
+
+    #[test]
+    fn dubbo_info(src in arb_dubbo_info()) {
+        let pb = round_trip(flow_log::DubboInfo::from(src.clone()));
+        check("serial_id", src.serial_id, pb.serial_id)?;
+        check("type", src.data_type, pb.r#type)?;
+        check("id", src.request_id, pb.id)?;
+        check("req_body_len", src.req_msg_size, pb.req_body_len)?;
+        check("resp_body_len", src.resp_msg_size, pb.resp_body_len)?;
+    }
+}
@@ -14,6 +14,9 @@
  * limitations under the License.
  */
 
+#[cfg(test)]
+mod compat;
+
 pub(crate) mod common;
 pub(crate) mod flow_log;
 pub(crate) mod metric;
@@ -15,11 +15,17 @@
  */
 
 mod ntp;
+mod remote_exec;
 mod session;
 mod synchronizer;
+mod time_source;
 
+pub(crate) use remote_exec::RemoteExecutor;
 pub(crate) use session::{Session, DEFAULT_TIMEOUT};
 pub(crate) use synchronizer::{RunningConfig, StaticConfig, Status, Synchronizer};
+#[cfg(target_os = "linux")]
+pub(crate) use time_source::PhcClock;
+pub(crate) use time_source::TimeSource;
 
 use std::time::{Duration, SystemTime};
 
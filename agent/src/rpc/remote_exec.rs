@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use flexi_logger::LoggerHandle;
+use futures::channel::mpsc::{self, UnboundedSender};
+use log::{debug, info, warn};
+use parking_lot::{Mutex, RwLock};
+use tokio::runtime::Runtime;
+use tokio::task::{self, JoinHandle};
+use tokio::time;
+use tonic::Code;
+
+use super::{RunningConfig, Session, Status};
+use crate::flow_generator::{export_flow_snapshot, FlowSnapshot, SnapshotFormat};
+use crate::proto::trident::{self as tp, OutputFormat, RemoteCommand};
+use crate::utils::stats;
+
+const RPC_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+// 控制器不支持时很久才重试一次
+const UNIMPLEMENTED_RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+const FLOW_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(3);
+const CHUNK_SIZE: usize = 64 << 10;
+const DEFAULT_MAX_BYTES: usize = 16 << 20;
+
+// 命令的执行者，只实现白名单内的命令，不执行任意shell
+struct Commands {
+    status: Arc<RwLock<Status>>,
+    stats_collector: Arc<stats::Collector>,
+    flow_snapshot: Arc<FlowSnapshot>,
+    logger_handle: LoggerHandle,
+}
+
+impl Commands {
+    fn execute(
+        &self,
+        command: RemoteCommand,
+        format: OutputFormat,
+        max_bytes: usize,
+    ) -> Result<String, String> {
+        let mut output = String::new();
+        match command {
+            RemoteCommand::DumpQueueStats => {
+                for batch in self.stats_collector.latest_batches("queue") {
+                    let _ = writeln!(output, "{}", batch);
+                }
+            }
+            RemoteCommand::DumpPolicy => {
+                let status = self.status.read();
+                let _ = writeln!(
+                    output,
+                    "version platform_data: {} acls: {} groups: {}",
+                    status.version_platform_data, status.version_acls, status.version_groups
+                );
+                let _ = writeln!(
+                    output,
+                    "interfaces: {} cidrs: {} ip_groups: {} acls: {}",
+                    status.interfaces.len(),
+                    status.cidrs.len(),
+                    status.ip_groups.len(),
+                    status.acls.len()
+                );
+                for acl in status.acls.iter() {
+                    let _ = writeln!(output, "{:?}", acl);
+                }
+            }
+            RemoteCommand::FlowTableSnapshot => {
+                let format = match format {
+                    OutputFormat::Text => SnapshotFormat::Text,
+                    OutputFormat::Json => SnapshotFormat::Json,
+                    OutputFormat::Csv => SnapshotFormat::Csv,
+                };
+                output = export_flow_snapshot(
+                    &self.flow_snapshot.take(FLOW_SNAPSHOT_TIMEOUT)?,
+                    format,
+                    max_bytes,
+                );
+            }
+            RemoteCommand::RotateLog => self
+                .logger_handle
+                .trigger_rotation()
+                .map_err(|e| format!("rotate log failed: {}", e))?,
+        }
+        Ok(output)
+    }
+
+    fn handle(&self, request: tp::RemoteExecRequest) -> Vec<tp::RemoteExecResponse> {
+        let request_id = request.request_id;
+        // 未知的命令值在解码时被替换为默认值，这里需要先检查
+        let command = match request.command.and_then(RemoteCommand::from_i32) {
+            Some(c) => c,
+            None => {
+                return vec![tp::RemoteExecResponse {
+                    request_id,
+                    errmsg: Some(format!("unsupported command {:?}", request.command)),
+                    eof: Some(true),
+                    ..Default::default()
+                }]
+            }
+        };
+        let format = request
+            .format
+            .and_then(OutputFormat::from_i32)
+            .unwrap_or(OutputFormat::Text);
+        let max_bytes = match request.max_bytes {
+            Some(m) if m > 0 => m as usize,
+            _ => DEFAULT_MAX_BYTES,
+        };
+        info!(
+            "remote execute {:?} format {:?} request_id {:?}",
+            command, format, request_id
+        );
+        let output = match self.execute(command, format, max_bytes) {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("remote execute {:?} failed: {}", command, e);
+                return vec![tp::RemoteExecResponse {
+                    request_id,
+                    errmsg: Some(e),
+                    eof: Some(true),
+                    ..Default::default()
+                }];
+            }
+        };
+        let mut responses = output
+            .as_bytes()
+            .chunks(CHUNK_SIZE)
+            .map(|chunk| tp::RemoteExecResponse {
+                request_id,
+                content: Some(chunk.to_vec()),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        match responses.last_mut() {
+            Some(last) => last.eof = Some(true),
+            None => responses.push(tp::RemoteExecResponse {
+                request_id,
+                eof: Some(true),
+                ..Default::default()
+            }),
+        }
+        responses
+    }
+}
+
+// 与控制器保持一个双向流，控制器下发诊断命令，执行结果从同一个流返回，
+// 运维人员无需登录主机即可排查采集器的问题
+pub struct RemoteExecutor {
+    session: Arc<Session>,
+    running_config: Arc<RwLock<RunningConfig>>,
+    commands: Arc<Commands>,
+
+    running: Arc<AtomicBool>,
+    rt: Runtime,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl RemoteExecutor {
+    pub fn new(
+        session: Arc<Session>,
+        running_config: Arc<RwLock<RunningConfig>>,
+        status: Arc<RwLock<Status>>,
+        stats_collector: Arc<stats::Collector>,
+        flow_snapshot: Arc<FlowSnapshot>,
+        logger_handle: LoggerHandle,
+    ) -> Self {
+        Self {
+            session,
+            running_config,
+            commands: Arc::new(Commands {
+                status,
+                stats_collector,
+                flow_snapshot,
+                logger_handle,
+            }),
+            running: Arc::new(AtomicBool::new(false)),
+            rt: Runtime::new().unwrap(),
+            thread: Mutex::new(None),
+        }
+    }
+
+    async fn serve(
+        session: &Session,
+        running: &AtomicBool,
+        commands: &Arc<Commands>,
+        sender: UnboundedSender<tp::RemoteExecResponse>,
+        mut stream: tonic::Streaming<tp::RemoteExecRequest>,
+    ) -> Result<(), tonic::Status> {
+        let version = session.get_version();
+        while running.load(Ordering::SeqCst) {
+            let request = match stream.message().await? {
+                Some(r) => r,
+                None => {
+                    debug!("end of remote exec stream");
+                    return Ok(());
+                }
+            };
+            if session.get_version() != version {
+                info!("grpc server changed");
+                return Ok(());
+            }
+            let commands = commands.clone();
+            let request_id = request.request_id;
+            // 流表快照需要等待dispatcher，不阻塞异步线程
+            let responses = match task::spawn_blocking(move || commands.handle(request)).await {
+                Ok(r) => r,
+                // 命令执行panic时也要返回结果，否则控制器会一直等待
+                Err(e) => {
+                    warn!("remote execute request_id {:?} failed: {}", request_id, e);
+                    vec![tp::RemoteExecResponse {
+                        request_id,
+                        errmsg: Some(format!("command execution aborted: {}", e)),
+                        eof: Some(true),
+                        ..Default::default()
+                    }]
+                }
+            };
+            for r in responses {
+                if sender.unbounded_send(r).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let session = self.session.clone();
+        let running_config = self.running_config.clone();
+        let commands = self.commands.clone();
+        let running = self.running.clone();
+        self.thread.lock().replace(self.rt.spawn(async move {
+            while running.load(Ordering::SeqCst) {
+                session.update_current_server().await;
+                let client = match session.get_client() {
+                    Some(c) => c,
+                    None => {
+                        debug!("remote exec not running, client not connected");
+                        time::sleep(RPC_RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+                let mut client = tp::synchronizer_client::SynchronizerClient::new(client);
+
+                let (sender, receiver) = mpsc::unbounded();
+                // 首个消息用于控制器识别采集器
+                let (ctrl_ip, ctrl_mac) = {
+                    let c = running_config.read();
+                    (c.ctrl_ip.clone(), c.ctrl_mac.clone())
+                };
+                let _ = sender.unbounded_send(tp::RemoteExecResponse {
+                    ctrl_ip: Some(ctrl_ip),
+                    ctrl_mac: Some(ctrl_mac),
+                    ..Default::default()
+                });
+                let result = match client.remote_execute(receiver).await {
+                    Ok(response) => {
+                        info!("remote exec stream established");
+                        Self::serve(&session, &running, &commands, sender, response.into_inner())
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(_) => time::sleep(Duration::from_secs(1)).await,
+                    Err(e) if e.code() == Code::Unimplemented => {
+                        info!("remote exec not supported by controller");
+                        time::sleep(UNIMPLEMENTED_RETRY_INTERVAL).await;
+                    }
+                    Err(e) => {
+                        warn!("remote exec rpc error {:?}", e);
+                        time::sleep(RPC_RETRY_INTERVAL).await;
+                    }
+                }
+            }
+        }));
+        info!("remote executor started");
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // 等待命令的流可能长时间没有消息，直接取消
+        if let Some(t) = self.thread.lock().take() {
+            t.abort();
+        }
+        info!("remote executor stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flexi_logger::Logger;
+
+    fn commands() -> Commands {
+        Commands {
+            status: Default::default(),
+            stats_collector: Arc::new(stats::Collector::new(&vec![])),
+            flow_snapshot: Default::default(),
+            // 不设置为全局logger，各测试可以独立创建
+            logger_handle: Logger::try_with_str("info").unwrap().build().unwrap().1,
+        }
+    }
+
+    #[test]
+    fn unsupported_command() {
+        let responses = commands().handle(tp::RemoteExecRequest {
+            request_id: Some(1),
+            command: Some(100),
+            ..Default::default()
+        });
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].request_id, Some(1));
+        assert!(responses[0].errmsg.is_some());
+        assert_eq!(responses[0].eof, Some(true));
+    }
+
+    #[test]
+    fn dump_policy() {
+        let commands = commands();
+        commands.status.write().version_acls = 3;
+        let responses = commands.handle(tp::RemoteExecRequest {
+            request_id: Some(2),
+            command: Some(RemoteCommand::DumpPolicy as i32),
+            ..Default::default()
+        });
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].eof, Some(true));
+        let content = String::from_utf8(responses[0].content.clone().unwrap()).unwrap();
+        assert!(content.starts_with("version platform_data: 0 acls: 3 groups: 0\n"));
+    }
+
+    #[test]
+    fn flow_table_snapshot_json() {
+        let commands = commands();
+        let responses = commands.handle(tp::RemoteExecRequest {
+            request_id: Some(3),
+            command: Some(RemoteCommand::FlowTableSnapshot as i32),
+            format: Some(OutputFormat::Json as i32),
+            ..Default::default()
+        });
+        assert_eq!(responses.len(), 1);
+        let content = String::from_utf8(responses[0].content.clone().unwrap()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["flows"].as_array().unwrap().len(), 0);
+    }
+}
@@ -14,19 +14,25 @@
  * limitations under the License.
  */
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufWriter, Write};
+use std::io::{BufWriter, Read, Write};
 use std::mem;
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::process::{self, Command};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::sync::{self, Arc};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 #[cfg(unix)]
-use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+use std::{
+    fs::{OpenOptions, Permissions},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+};
 
+use flate2::read::GzDecoder;
 use log::{debug, error, info, warn};
 use md5::{Digest, Md5};
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
@@ -39,19 +45,25 @@ use tokio::task::JoinHandle;
 use tokio::time;
 
 use super::ntp::{NtpMode, NtpPacket, NtpTime};
+#[cfg(target_os = "linux")]
+use super::PhcClock;
+use super::TimeSource;
 
 use crate::common::policy::Acl;
 use crate::common::policy::{Cidr, IpGroupData, PeerConnection};
 use crate::common::{FlowAclListener, PlatformData as VInterface, DEFAULT_CONTROLLER_PORT};
-use crate::config::RuntimeConfig;
+use crate::config::{L7ParserConfig, RuntimeConfig, TimeSourceConfig, TimeSourceMode};
 use crate::exception::ExceptionHandler;
 use crate::policy::PolicySetter;
 use crate::proto::common::TridentType;
 use crate::proto::trident::{self as tp, Exception, TapMode};
 use crate::rpc::session::Session;
 use crate::trident::{self, TridentState};
+#[cfg(target_os = "linux")]
+use crate::utils::handoff;
 use crate::utils::{
     self,
+    drop_stats::DropStats,
     environment::{get_executable_path, is_tt_pod, running_in_container},
     net::{is_unicast_link_local, MacAddr},
 };
@@ -61,6 +73,8 @@ const RPC_RETRY_INTERVAL: Duration = Duration::from_secs(60);
 const NANOS_IN_SECOND: i64 = Duration::from_secs(1).as_nanos() as i64;
 const SECOND: Duration = Duration::from_secs(1);
 const NORMAL_EXIT_WITH_RESTART: i32 = 3;
+// 运行配置未变化时刷新缓存写入时间的间隔，用于判断缓存是否过期
+const RUNTIME_CONFIG_CACHE_REFRESH_INTERVAL: Duration = Duration::from_secs(600);
 
 pub struct StaticConfig {
     pub agent_ident: &'static str,
@@ -70,6 +84,9 @@ pub struct StaticConfig {
     pub tap_mode: tp::TapMode,
     pub vtap_group_id_request: String,
     pub kubernetes_cluster_id: String,
+    pub hot_upgrade: bool,
+    // 最近一次收到的运行配置写入该文件，供dump-config读取
+    pub runtime_config_cache: PathBuf,
 
     pub controller_ip: String,
 
@@ -85,6 +102,8 @@ impl Default for StaticConfig {
             tap_mode: Default::default(),
             vtap_group_id_request: Default::default(),
             kubernetes_cluster_id: Default::default(),
+            hot_upgrade: false,
+            runtime_config_cache: Default::default(),
             controller_ip: Default::default(),
             env: Default::default(),
         }
@@ -118,17 +137,24 @@ pub struct Status {
     pub proxy_port: u16,
     pub sync_interval: Duration,
     pub ntp_enabled: bool,
+    pub time_source: TimeSourceConfig,
 
     // GRPC数据
     pub version_platform_data: u64,
     pub version_acls: u64,
     pub version_groups: u64,
+    pub version_l7_parser_config: u64,
 
     pub interfaces: Vec<Arc<VInterface>>,
     pub peers: Vec<Arc<PeerConnection>>,
     pub cidrs: Vec<Arc<Cidr>>,
     pub ip_groups: Vec<Arc<IpGroupData>>,
     pub acls: Vec<Acl>,
+    pub l7_parser_config: L7ParserConfig,
+    // 采集socket和eBPF创建完成后才收紧权限，此前为空
+    pub hardening_profile: String,
+    // 已写入缓存文件的运行配置，未变化时只定期刷新写入时间
+    pub runtime_config_cache: tp::RuntimeConfigCache,
 }
 
 impl Default for Status {
@@ -146,15 +172,34 @@ impl Default for Status {
             proxy_port: DEFAULT_CONTROLLER_PORT,
             sync_interval: DEFAULT_SYNC_INTERVAL,
             ntp_enabled: false,
+            time_source: Default::default(),
 
             version_platform_data: 0,
             version_acls: 0,
             version_groups: 0,
+            version_l7_parser_config: 0,
             interfaces: Default::default(),
             peers: Default::default(),
             cidrs: Default::default(),
             ip_groups: Default::default(),
             acls: Default::default(),
+            l7_parser_config: Default::default(),
+            hardening_profile: Default::default(),
+            runtime_config_cache: Default::default(),
+        }
+    }
+}
+
+fn decompress(data: &[u8], gzipped: bool) -> Option<Cow<[u8]>> {
+    if !gzipped {
+        return Some(Cow::Borrowed(data));
+    }
+    let mut decompressed = vec![];
+    match GzDecoder::new(data).read_to_end(&mut decompressed) {
+        Ok(_) => Some(Cow::Owned(decompressed)),
+        Err(e) => {
+            warn!("decompress gzipped data failed: {}", e);
+            None
         }
     }
 }
@@ -188,6 +233,16 @@ impl Status {
         self.ip_groups = ip_groups;
     }
 
+    fn update_l7_parser_config(&mut self, version: u64, config: L7ParserConfig) {
+        info!(
+            "Update L7ParserConfig version {} to {}.",
+            self.version_l7_parser_config, version
+        );
+
+        self.version_l7_parser_config = version;
+        self.l7_parser_config = config;
+    }
+
     fn update_flow_acl(&mut self, version: u64, flow_acls: Vec<Acl>) {
         info!(
             "Update FlowAcls version {} to {}.",
@@ -214,10 +269,18 @@ impl Status {
             return false;
         }
 
+        if let Some(delta_compressed) = &resp.platform_data_delta {
+            let delta = decompress(delta_compressed, resp.gzipped())
+                .and_then(|d| tp::PlatformDataDelta::decode(d.as_ref()).ok());
+            if let Some(delta) = delta {
+                return self.apply_platform_delta(version, &delta);
+            }
+        }
+
         if let Some(platform_compressed) = &resp.platform_data {
-            let platform = tp::PlatformData::decode(platform_compressed.as_slice());
-            if platform.is_ok() {
-                let platform = platform.unwrap();
+            let platform = decompress(platform_compressed, resp.gzipped())
+                .and_then(|d| tp::PlatformData::decode(d.as_ref()).ok());
+            if let Some(platform) = platform {
                 let mut interfaces = Vec::new();
                 let mut peers = Vec::new();
                 let mut cidrs = Vec::new();
@@ -248,6 +311,82 @@ impl Status {
         return false;
     }
 
+    fn apply_platform_delta(&mut self, version: u64, delta: &tp::PlatformDataDelta) -> bool {
+        if delta.base_version() != self.version_platform_data {
+            // 版本不连续，清零版本号使下次同步请求全量数据
+            warn!(
+                "PlatformData delta base version {} mismatch current version {}, request full sync.",
+                delta.base_version(),
+                self.version_platform_data
+            );
+            self.version_platform_data = 0;
+            return false;
+        }
+
+        let mut interfaces = self
+            .interfaces
+            .iter()
+            .filter(|i| {
+                !delta.removed_interface_ids.contains(&i.id)
+                    && !delta.added_interfaces.iter().any(|a| a.id() == i.id)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for item in &delta.added_interfaces {
+            match VInterface::try_from(item) {
+                Ok(i) => interfaces.push(Arc::new(i)),
+                Err(e) => warn!("{:?}: {}", item, e),
+            }
+        }
+
+        let mut peers = self
+            .peers
+            .iter()
+            .filter(|p| {
+                !delta.removed_peer_connection_ids.contains(&p.id)
+                    && !delta.added_peer_connections.iter().any(|a| a.id() == p.id)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for item in &delta.added_peer_connections {
+            peers.push(Arc::new(PeerConnection::from(item)));
+        }
+
+        let removed_cidrs = delta
+            .removed_cidrs
+            .iter()
+            .filter_map(|c| Cidr::try_from(c).ok())
+            .collect::<Vec<_>>();
+        let mut cidrs = self
+            .cidrs
+            .iter()
+            .filter(|c| {
+                !removed_cidrs
+                    .iter()
+                    .any(|r| r.ip == c.ip && r.epc_id == c.epc_id)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for item in &delta.added_cidrs {
+            match Cidr::try_from(item) {
+                Ok(c) => cidrs.push(Arc::new(c)),
+                Err(e) => warn!("{:?}: {}", item, e),
+            }
+        }
+
+        debug!(
+            "PlatformData delta: +{}/-{} interfaces, +{}/-{} peers, +{}/-{} cidrs.",
+            delta.added_interfaces.len(),
+            delta.removed_interface_ids.len(),
+            delta.added_peer_connections.len(),
+            delta.removed_peer_connection_ids.len(),
+            delta.added_cidrs.len(),
+            delta.removed_cidrs.len()
+        );
+        self.update_platform_data(version, interfaces, peers, cidrs);
+        true
+    }
+
     fn modify_platform(&mut self, macs: &Vec<MacAddr>, config: &RuntimeConfig) {
         if config.yaml_config.tap_mode == TapMode::Analyzer {
             return;
@@ -297,8 +436,9 @@ impl Status {
         }
 
         if let Some(acls_commpressed) = &resp.flow_acls {
-            let acls = tp::FlowAcls::decode(acls_commpressed.as_slice());
-            if let Ok(acls) = acls {
+            let acls = decompress(acls_commpressed, resp.gzipped())
+                .and_then(|d| tp::FlowAcls::decode(d.as_ref()).ok());
+            if let Some(acls) = acls {
                 let flow_acls = acls
                     .flow_acl
                     .into_iter()
@@ -333,9 +473,9 @@ impl Status {
         }
 
         if let Some(groups_compressed) = &resp.groups {
-            let groups = tp::Groups::decode(groups_compressed.as_slice());
-            if groups.is_ok() {
-                let groups = groups.unwrap();
+            let groups = decompress(groups_compressed, resp.gzipped())
+                .and_then(|d| tp::Groups::decode(d.as_ref()).ok());
+            if let Some(groups) = groups {
                 let mut ip_groups = Vec::new();
                 for item in &groups.groups {
                     let result = IpGroupData::try_from(item);
@@ -352,6 +492,33 @@ impl Status {
         return false;
     }
 
+    pub fn get_l7_parser_config(&mut self, resp: &tp::SyncResponse) -> bool {
+        let version = resp.version_l7_parser_config.unwrap_or(0);
+        debug!(
+            "get grpc L7ParserConfig version: {} vs current version: {}.",
+            version, self.version_l7_parser_config
+        );
+        if version == 0 {
+            debug!("L7ParserConfig data in preparation.");
+            return false;
+        }
+        if self.version_l7_parser_config == version {
+            debug!("L7ParserConfig data same version.");
+            return false;
+        }
+
+        if let Some(config_compressed) = &resp.l7_parser_config {
+            match tp::L7ParserConfig::decode(config_compressed.as_slice()) {
+                Ok(config) => {
+                    self.update_l7_parser_config(version, L7ParserConfig::new(version, config));
+                    return true;
+                }
+                Err(e) => warn!("decode L7ParserConfig version {} failed: {}", version, e),
+            }
+        }
+        return false;
+    }
+
     pub fn get_blacklist(&mut self, resp: &tp::SyncResponse) -> Vec<u64> {
         return resp.skip_interface.iter().map(|i| i.mac.unwrap()).collect();
     }
@@ -387,6 +554,9 @@ pub struct Synchronizer {
 
     max_memory: Arc<AtomicU64>,
     ntp_diff: Arc<AtomicI64>,
+    // 单位ns，用于网卡硬件时间戳从TAI换算UTC
+    ptp_utc_offset: Arc<AtomicU64>,
+    drop_stats: DropStats,
 }
 
 impl Synchronizer {
@@ -400,6 +570,8 @@ impl Synchronizer {
         controller_ip: String,
         vtap_group_id_request: String,
         kubernetes_cluster_id: String,
+        hot_upgrade: bool,
+        runtime_config_cache: PathBuf,
         policy_setter: PolicySetter,
         exception_handler: ExceptionHandler,
     ) -> Synchronizer {
@@ -411,6 +583,8 @@ impl Synchronizer {
                 tap_mode: tp::TapMode::Local,
                 vtap_group_id_request,
                 kubernetes_cluster_id,
+                hot_upgrade,
+                runtime_config_cache,
                 controller_ip,
                 env: RuntimeEnvironment::new(),
             }),
@@ -426,6 +600,10 @@ impl Synchronizer {
 
             max_memory: Default::default(),
             ntp_diff: Default::default(),
+            ptp_utc_offset: Arc::new(AtomicU64::new(
+                TimeSourceConfig::default().ptp_utc_offset.as_nanos() as u64,
+            )),
+            drop_stats: Default::default(),
         }
     }
 
@@ -460,6 +638,7 @@ impl Synchronizer {
         status: &Arc<RwLock<Status>>,
         time_diff: i64,
         exception_handler: &ExceptionHandler,
+        drop_stats: &DropStats,
     ) -> tp::SyncRequest {
         let status = status.read();
 
@@ -482,6 +661,19 @@ impl Synchronizer {
         }
 
         let running_config = running_config.read();
+        // 上报上次同步以来的采集丢包，无采集数据时不上报
+        let drops = drop_stats.take_sync();
+        let capture_drop_stats = if drops.is_empty() {
+            None
+        } else {
+            Some(tp::CaptureDropStats {
+                kernel_packets: Some(drops.kernel_packets),
+                kernel_drops: Some(drops.kernel_drops),
+                ebpf_events: Some(drops.ebpf_events),
+                ebpf_lost: Some(drops.ebpf_lost),
+                drop_ratio: Some(drops.drop_ratio()),
+            })
+        };
 
         tp::SyncRequest {
             boot_time: Some(boot_time as u32),
@@ -489,6 +681,7 @@ impl Synchronizer {
             version_platform_data: Some(status.version_platform_data),
             version_acls: Some(status.version_acls),
             version_groups: Some(status.version_groups),
+            version_l7_parser_config: Some(status.version_l7_parser_config),
             state: Some(tp::State::Running.into()),
             revision: Some(static_config.revision.to_owned()),
             exception: Some(exception_handler.take()),
@@ -515,6 +708,10 @@ impl Synchronizer {
             kernel_version: Some(static_config.env.kernel_version.clone()),
             vtap_group_id_request: Some(static_config.vtap_group_id_request.clone()),
             kubernetes_cluster_id: Some(static_config.kubernetes_cluster_id.clone()),
+            hardening_profile: Some(status.hardening_profile.clone()),
+            platform_data_delta_supported: Some(true),
+            gzip_supported: Some(true),
+            capture_drop_stats,
 
             ..Default::default()
         }
@@ -578,6 +775,115 @@ impl Synchronizer {
         return (segments, macs);
     }
 
+    fn cache_runtime_config(path: &Path, status: &mut Status, config: tp::Config) {
+        if path.as_os_str().is_empty() {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let cache = tp::RuntimeConfigCache {
+            timestamp: Some(now),
+            config: Some(config),
+            version_platform_data: Some(status.version_platform_data),
+            version_acls: Some(status.version_acls),
+            version_groups: Some(status.version_groups),
+        };
+        let last = &status.runtime_config_cache;
+        if last.config == cache.config
+            && last.version_platform_data == cache.version_platform_data
+            && last.version_acls == cache.version_acls
+            && last.version_groups == cache.version_groups
+            && now < last.timestamp() + RUNTIME_CONFIG_CACHE_REFRESH_INTERVAL.as_secs()
+        {
+            return;
+        }
+        if let Err(e) = Self::write_private(path, &cache.encode_to_vec()) {
+            warn!(
+                "write runtime config cache {} failed: {}",
+                path.display(),
+                e
+            );
+        }
+        status.runtime_config_cache = cache;
+    }
+
+    // 运行配置中包含token等密钥，缓存文件只允许属主读写
+    #[cfg(unix)]
+    fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .mode(0o600)
+            .open(path)?;
+        // mode只对新建的文件生效，已有文件需要修改权限
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(contents)
+    }
+
+    #[cfg(not(unix))]
+    fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    // 启动时读取缓存的运行配置，缓存过期或无效时返回None，等待首次同步
+    pub fn load_runtime_config_cache(&self, max_age: Duration) -> Option<RuntimeConfig> {
+        let path = &self.static_config.runtime_config_cache;
+        if max_age.is_zero() || path.as_os_str().is_empty() {
+            return None;
+        }
+        let cache = match fs::read(path) {
+            Ok(content) => match tp::RuntimeConfigCache::decode(content.as_slice()) {
+                Ok(cache) => cache,
+                Err(e) => {
+                    warn!(
+                        "decode runtime config cache {} failed: {}",
+                        path.display(),
+                        e
+                    );
+                    return None;
+                }
+            },
+            Err(e) => {
+                debug!("read runtime config cache {} failed: {}", path.display(), e);
+                return None;
+            }
+        };
+        let cached_time = UNIX_EPOCH + Duration::from_secs(cache.timestamp());
+        match SystemTime::now().duration_since(cached_time) {
+            Ok(age) if age <= max_age => (),
+            _ => {
+                info!(
+                    "runtime config cache {} written at {:?} is stale, wait for controller",
+                    path.display(),
+                    cached_time
+                );
+                return None;
+            }
+        }
+        let runtime_config = match cache.config.clone().map(RuntimeConfig::try_from) {
+            Some(Ok(c)) if c.enabled => c,
+            Some(Err(e)) => {
+                warn!("invalid runtime config cache {}: {}", path.display(), e);
+                return None;
+            }
+            _ => return None,
+        };
+        info!(
+            "start with runtime config cached at {:?}, version platform data: {}, acls: {}, groups: {}",
+            cached_time,
+            cache.version_platform_data(),
+            cache.version_acls(),
+            cache.version_groups()
+        );
+        self.max_memory
+            .store(runtime_config.max_memory, Ordering::Relaxed);
+        self.status.write().runtime_config_cache = cache;
+        Some(runtime_config)
+    }
+
     fn on_response(
         remote: &IpAddr,
         mut resp: tp::SyncResponse,
@@ -606,7 +912,8 @@ impl Synchronizer {
             warn!("invalid response from {} without config", remote);
             return;
         }
-        let runtime_config = RuntimeConfig::try_from(config.unwrap());
+        let config = config.unwrap();
+        let runtime_config = RuntimeConfig::try_from(config.clone());
         if let Err(e) = runtime_config {
             warn!(
                 "invalid response from {} with invalid config: {}",
@@ -615,7 +922,7 @@ impl Synchronizer {
             exception_handler.set(Exception::InvalidConfiguration);
             return;
         }
-        let runtime_config = runtime_config.unwrap();
+        let mut runtime_config = runtime_config.unwrap();
         let yaml_config = &runtime_config.yaml_config;
 
         let _ = escape_tx.send(runtime_config.max_escape);
@@ -633,12 +940,16 @@ impl Synchronizer {
         status.proxy_port = runtime_config.proxy_controller_port;
         status.sync_interval = runtime_config.sync_interval;
         status.ntp_enabled = runtime_config.ntp_enabled;
+        status.time_source = yaml_config.time_source.clone();
         let updated_platform = status.get_platform_data(&resp);
         if updated_platform {
             status.modify_platform(&macs, &runtime_config);
         }
         let mut updated = status.get_ip_groups(&resp) || updated_platform;
         updated = status.get_flow_acls(&resp) || updated;
+        // 协议解析配置不随trident::Config下发，每次同步都需带上当前版本的配置
+        status.get_l7_parser_config(&resp);
+        runtime_config.l7_parser_config = status.l7_parser_config.clone();
         if updated {
             // 更新策略相关
             let last = SystemTime::now();
@@ -672,6 +983,7 @@ impl Synchronizer {
         // TODO: segments
         // TODO: modify platform
         let blacklist = status.get_blacklist(&resp);
+        Self::cache_runtime_config(&static_config.runtime_config_cache, &mut status, config);
         let (trident_state, cvar) = &**trident_state;
         if !runtime_config.enabled {
             *trident_state.lock().unwrap() = trident::State::Disabled;
@@ -693,6 +1005,7 @@ impl Synchronizer {
         let flow_acl_listener = self.flow_acl_listener.clone();
         let exception_handler = self.exception_handler.clone();
         let ntp_diff = self.ntp_diff.clone();
+        let drop_stats = self.drop_stats.clone();
         self.threads.lock().push(self.rt.spawn(async move {
             while running.load(Ordering::SeqCst) {
                 session.update_triggered_current_server().await;
@@ -712,6 +1025,7 @@ impl Synchronizer {
                         &status,
                         ntp_diff.load(Ordering::Relaxed),
                         &exception_handler,
+                        &drop_stats,
                     ))
                     .await;
                 if let Err(m) = response {
@@ -794,18 +1108,73 @@ impl Synchronizer {
         self.ntp_diff.clone()
     }
 
+    pub fn time_source(&self) -> TimeSource {
+        TimeSource::new(self.ntp_diff.clone(), self.ptp_utc_offset.clone())
+    }
+
+    pub fn set_hardening_profile(&self, profile: String) {
+        self.status.write().hardening_profile = profile;
+    }
+
+    pub fn drop_stats(&self) -> DropStats {
+        self.drop_stats.clone()
+    }
+
     fn run_ntp_sync(&self) {
         let running_config = self.running_config.clone();
         let session = self.session.clone();
         let status = self.status.clone();
         let running = self.running.clone();
         let ntp_diff = self.ntp_diff.clone();
+        let ptp_utc_offset = self.ptp_utc_offset.clone();
         self.rt.spawn(async move {
+            #[cfg(target_os = "linux")]
+            let mut phc: Option<PhcClock> = None;
             while running.load(Ordering::SeqCst) {
-                let (enabled, sync_interval) = {
+                let (enabled, sync_interval, time_source) = {
                     let reader = status.read();
-                    (reader.ntp_enabled, reader.sync_interval)
+                    (
+                        reader.ntp_enabled,
+                        reader.sync_interval,
+                        reader.time_source.clone(),
+                    )
                 };
+                ptp_utc_offset.store(
+                    time_source.ptp_utc_offset.as_nanos() as u64,
+                    Ordering::Relaxed,
+                );
+
+                // PHC不可用时回退到NTP
+                if time_source.mode == TimeSourceMode::Ptp {
+                    #[cfg(target_os = "linux")]
+                    if phc
+                        .as_ref()
+                        .map(|c| c.device() != time_source.ptp_device)
+                        .unwrap_or(true)
+                    {
+                        phc = match PhcClock::open(&time_source.ptp_device) {
+                            Ok(clock) => Some(clock),
+                            Err(e) => {
+                                warn!("open ptp clock {} failed: {}", time_source.ptp_device, e);
+                                None
+                            }
+                        };
+                    }
+                    #[cfg(target_os = "linux")]
+                    if let Some(clock) = phc.as_ref() {
+                        match clock.offset(time_source.ptp_utc_offset) {
+                            Ok(offset) => {
+                                ntp_diff.store(offset, Ordering::Relaxed);
+                                time::sleep(time_source.ptp_sync_interval).await;
+                                continue;
+                            }
+                            Err(e) => {
+                                warn!("read ptp clock {} failed: {}", clock.device(), e);
+                                phc = None;
+                            }
+                        }
+                    }
+                }
 
                 if !enabled {
                     ntp_diff.store(0, Ordering::Relaxed);
@@ -903,10 +1272,10 @@ impl Synchronizer {
         new_revision: &str,
         ctrl_ip: &str,
         ctrl_mac: &str,
-    ) -> Result<(), String> {
+    ) -> Result<Option<PathBuf>, String> {
         if running_in_container() {
             info!("running in a container, exit directly and try to recreate myself using a new version docker image...");
-            return Ok(());
+            return Ok(None);
         }
 
         session.update_current_server().await;
@@ -1039,7 +1408,7 @@ impl Synchronizer {
         // ignore failure as upgrade succeeded anyway
         let _ = fs::remove_file(backup_path);
 
-        Ok(())
+        Ok(Some(binary_path))
     }
 
     fn run(&self, escape_tx: UnboundedSender<Duration>) {
@@ -1054,6 +1423,7 @@ impl Synchronizer {
         let max_memory = self.max_memory.clone();
         let exception_handler = self.exception_handler.clone();
         let ntp_diff = self.ntp_diff.clone();
+        let drop_stats = self.drop_stats.clone();
         self.threads.lock().push(self.rt.spawn(async move {
             let mut client = None;
             let version = session.get_version();
@@ -1090,6 +1460,7 @@ impl Synchronizer {
                     &status,
                     ntp_diff.load(Ordering::Relaxed),
                     &exception_handler,
+                    &drop_stats,
                 );
                 debug!("grpc sync request: {:?}", request);
 
@@ -1158,7 +1529,16 @@ impl Synchronizer {
                         (running_config.ctrl_ip.clone(), running_config.ctrl_mac.clone())
                     };
                     match Self::upgrade(&running, &session, &revision, &ctrl_ip, &ctrl_mac).await {
-                        Ok(_) => {
+                        Ok(binary_path) => {
+                            // 仅停止dispatcher并交接采集socket及流表后exec，失败时按普通升级退出重启
+                            #[cfg(target_os = "linux")]
+                            if let Some(binary_path) = binary_path.filter(|_| static_config.hot_upgrade) {
+                                warn!("trident upgrade is successful, hot upgrade with capture sockets handed off...");
+                                let e = handoff::exec(&binary_path);
+                                error!("hot upgrade exec {} failed: {}", binary_path.display(), e);
+                            }
+                            #[cfg(target_os = "windows")]
+                            let _ = binary_path;
                             let (ts, cvar) = &*trident_state;
                             *ts.lock().unwrap() = trident::State::Terminated;
                             cvar.notify_one();
@@ -1265,3 +1645,83 @@ impl RuntimeEnvironment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    fn peer(id: u32, local_epc_id: u32) -> tp::PeerConnection {
+        tp::PeerConnection {
+            id: Some(id),
+            local_epc_id: Some(local_epc_id),
+            remote_epc_id: Some(100),
+        }
+    }
+
+    fn cidr(prefix: &str) -> tp::Cidr {
+        tp::Cidr {
+            prefix: Some(prefix.into()),
+            epc_id: Some(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn platform_data_delta() {
+        let mut status = Status::default();
+        let full = tp::PlatformData {
+            peer_connections: vec![peer(1, 1), peer(2, 2)],
+            cidrs: vec![cidr("10.0.0.0/8"), cidr("172.16.0.0/12")],
+            ..Default::default()
+        };
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&full.encode_to_vec()).unwrap();
+        let resp = tp::SyncResponse {
+            version_platform_data: Some(10),
+            platform_data: Some(encoder.finish().unwrap()),
+            gzipped: Some(true),
+            ..Default::default()
+        };
+        assert!(status.get_platform_data(&resp));
+        assert_eq!(status.peers.len(), 2);
+        assert_eq!(status.cidrs.len(), 2);
+
+        let delta = tp::PlatformDataDelta {
+            base_version: Some(10),
+            added_peer_connections: vec![peer(2, 3), peer(4, 4)],
+            removed_peer_connection_ids: vec![1],
+            added_cidrs: vec![cidr("192.168.0.0/16")],
+            removed_cidrs: vec![cidr("10.0.0.0/8")],
+            ..Default::default()
+        };
+        let mut resp = tp::SyncResponse {
+            version_platform_data: Some(11),
+            platform_data_delta: Some(delta.encode_to_vec()),
+            ..Default::default()
+        };
+        assert!(status.get_platform_data(&resp));
+        assert_eq!(status.version_platform_data, 11);
+        let mut peers = status
+            .peers
+            .iter()
+            .map(|p| (p.id, p.local_epc))
+            .collect::<Vec<_>>();
+        peers.sort();
+        assert_eq!(peers, vec![(2, 3), (4, 4)]);
+        let mut cidrs = status
+            .cidrs
+            .iter()
+            .map(|c| c.ip.to_string())
+            .collect::<Vec<_>>();
+        cidrs.sort();
+        assert_eq!(cidrs, vec!["172.16.0.0/12", "192.168.0.0/16"]);
+
+        // 版本不连续时清零版本号请求全量
+        resp.version_platform_data = Some(13);
+        assert!(!status.get_platform_data(&resp));
+        assert_eq!(status.version_platform_data, 0);
+        assert_eq!(status.peers.len(), 2);
+    }
+}
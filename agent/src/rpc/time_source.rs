@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use std::{
+    fs::File,
+    io,
+    os::unix::io::AsRawFd,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::get_timestamp;
+
+// 统一的时间源，系统时钟按时差校正，时差由Synchronizer根据NTP或PTP硬件时钟(PHC)更新
+#[derive(Clone, Debug, Default)]
+pub struct TimeSource {
+    diff: Arc<AtomicI64>,
+    // 网卡PHC(TAI)与UTC的偏移，单位ns，跟随配置的ptp_utc_offset更新
+    hw_utc_offset: Arc<AtomicU64>,
+}
+
+impl TimeSource {
+    pub fn new(diff: Arc<AtomicI64>, hw_utc_offset: Arc<AtomicU64>) -> Self {
+        Self {
+            diff,
+            hw_utc_offset,
+        }
+    }
+
+    // 单位ns
+    pub fn diff(&self) -> i64 {
+        self.diff.load(Ordering::Relaxed)
+    }
+
+    pub fn now(&self) -> Duration {
+        get_timestamp(self.diff())
+    }
+
+    // 按时差校正采集到的系统时钟时间戳
+    pub fn correct(&self, timestamp: Duration) -> Duration {
+        let diff = self.diff();
+        if diff >= 0 {
+            timestamp + Duration::from_nanos(diff as u64)
+        } else {
+            timestamp.saturating_sub(Duration::from_nanos(-diff as u64))
+        }
+    }
+
+    // 网卡硬件时间戳为PHC时间，换算为UTC
+    pub fn correct_hw(&self, timestamp: Duration) -> Duration {
+        timestamp.saturating_sub(Duration::from_nanos(
+            self.hw_utc_offset.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+// 网卡的PTP硬件时钟，由ptp4l同步到主时钟，通常为TAI时间
+#[cfg(target_os = "linux")]
+pub struct PhcClock {
+    device: String,
+    // 时钟有效期间须保持设备打开
+    file: File,
+}
+
+#[cfg(target_os = "linux")]
+impl PhcClock {
+    const CLOCKFD: libc::clockid_t = 3;
+
+    pub fn open(device: &str) -> io::Result<Self> {
+        Ok(Self {
+            device: device.to_owned(),
+            file: File::open(device)?,
+        })
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    // 同内核的FD_TO_CLOCKID
+    fn clock_id(&self) -> libc::clockid_t {
+        (!(self.file.as_raw_fd() as libc::clockid_t) << 3) | Self::CLOCKFD
+    }
+
+    pub fn now(&self) -> io::Result<Duration> {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { libc::clock_gettime(self.clock_id(), &mut ts) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+
+    // PHC换算为UTC后与系统时钟的时差，单位ns，取读PHC前后两次系统时间的中点以减小读取耗时的影响
+    pub fn offset(&self, utc_offset: Duration) -> io::Result<i64> {
+        let before = SystemTime::now();
+        let phc = self.now()?;
+        let after = SystemTime::now();
+        let (before, after) = match (
+            before.duration_since(UNIX_EPOCH),
+            after.duration_since(UNIX_EPOCH),
+        ) {
+            (Ok(b), Ok(a)) if a >= b => (b, a),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "system time went backwards",
+                ))
+            }
+        };
+        let system = before + (after - before) / 2;
+        Ok(phc.as_nanos() as i64 - utc_offset.as_nanos() as i64 - system.as_nanos() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correct() {
+        let diff = Arc::new(AtomicI64::new(0));
+        let source = TimeSource::new(diff.clone(), Default::default());
+        let ts = Duration::from_secs(100);
+        assert_eq!(source.correct(ts), ts);
+
+        diff.store(1_500, Ordering::Relaxed);
+        assert_eq!(source.correct(ts), ts + Duration::from_nanos(1_500));
+        diff.store(-1_500, Ordering::Relaxed);
+        assert_eq!(source.correct(ts), ts - Duration::from_nanos(1_500));
+        assert_eq!(source.correct(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn correct_hw() {
+        let diff = Arc::new(AtomicI64::new(1_500));
+        let offset = Arc::new(AtomicU64::new(37_000_000_000));
+        let source = TimeSource::new(diff, offset.clone());
+        let tai = Duration::from_secs(1_700_000_037);
+        // 不受系统时钟时差影响
+        assert_eq!(source.correct_hw(tai), Duration::from_secs(1_700_000_000));
+        offset.store(0, Ordering::Relaxed);
+        assert_eq!(source.correct_hw(tai), tai);
+        offset.store(37_000_000_000, Ordering::Relaxed);
+        assert_eq!(source.correct_hw(Duration::ZERO), Duration::ZERO);
+    }
+}
@@ -0,0 +1,283 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use ipnet::IpNet;
+use lru::LruCache;
+use ring::digest;
+
+use super::SendItem;
+use crate::config::{IpAnonymizationConfig, IpAnonymizationMode};
+use crate::flow_generator::AppProtoLogsInfo;
+
+const TRUNCATE_PREFIX_V4: u32 = 24;
+const TRUNCATE_PREFIX_V6: u32 = 48;
+const CACHE_SIZE: usize = 4096;
+
+// 在发送前按配置的cidrs对l4和l7流日志中的IP做匿名化
+pub struct IpAnonymizer {
+    config: IpAnonymizationConfig,
+    nets: Vec<IpNet>,
+    crypto_pan: CryptoPan,
+    cache: LruCache<IpAddr, IpAddr>,
+}
+
+impl IpAnonymizer {
+    pub fn new(config: &IpAnonymizationConfig) -> Self {
+        Self {
+            config: config.clone(),
+            // cidrs在加载配置时已校验
+            nets: config
+                .cidrs
+                .iter()
+                .filter_map(|c| c.parse::<IpNet>().ok())
+                .map(|n| n.trunc())
+                .collect(),
+            // Crypto-PAn需要32字节密钥，由配置的key经SHA256得到
+            crypto_pan: CryptoPan::from_secret(
+                digest::digest(&digest::SHA256, config.key.as_bytes())
+                    .as_ref()
+                    .try_into()
+                    .unwrap(),
+            ),
+            cache: LruCache::new(CACHE_SIZE),
+        }
+    }
+
+    pub fn anonymize_item(&mut self, send_item: &mut SendItem) {
+        if self.config.mode == IpAnonymizationMode::Disabled || self.nets.is_empty() {
+            return;
+        }
+        match send_item {
+            SendItem::L4FlowLog(l4) => {
+                let flow = &mut l4.flow;
+                flow.flow_key.ip_src = self.anonymize(flow.flow_key.ip_src);
+                flow.flow_key.ip_dst = self.anonymize(flow.flow_key.ip_dst);
+                for peer in flow.flow_metrics_peers.iter_mut() {
+                    peer.nat_real_ip = self.anonymize(peer.nat_real_ip);
+                }
+            }
+            SendItem::L7FlowLog(l7) => {
+                let base = &mut l7.base_info;
+                base.ip_src = self.anonymize(base.ip_src);
+                base.ip_dst = self.anonymize(base.ip_dst);
+                match &mut l7.special_info {
+                    AppProtoLogsInfo::HttpV1(h)
+                    | AppProtoLogsInfo::HttpV2(h)
+                    | AppProtoLogsInfo::HttpV1TLS(h) => self.anonymize_list(&mut h.client_ip),
+                    AppProtoLogsInfo::Dns(d) => {
+                        self.anonymize_list(&mut d.answers);
+                        for ip in d.answer_ips.iter_mut() {
+                            *ip = self.anonymize(*ip);
+                        }
+                    }
+                    AppProtoLogsInfo::Radius(r) => self.anonymize_list(&mut r.nas_ip),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // 替换以','或';'分隔的文本中的IP，如X-Forwarded-For和DNS应答，非IP的部分保持不变
+    fn anonymize_list(&mut self, s: &mut String) {
+        if s.is_empty() {
+            return;
+        }
+        let mut result = String::with_capacity(s.len());
+        let mut changed = false;
+        for segment in s.split_inclusive(&[',', ';'][..]) {
+            let token = segment.strip_suffix(&[',', ';'][..]).unwrap_or(segment);
+            let sep = &segment[token.len()..];
+            let trimmed = token.trim();
+            let start = token.len() - token.trim_start().len();
+            let replaced = if let Ok(ip) = trimmed.parse::<IpAddr>() {
+                Some(self.anonymize(ip).to_string())
+            } else if let Ok(addr) = trimmed.parse::<SocketAddr>() {
+                Some(SocketAddr::new(self.anonymize(addr.ip()), addr.port()).to_string())
+            } else {
+                None
+            };
+            match replaced {
+                Some(r) if r != trimmed => {
+                    changed = true;
+                    result.push_str(&token[..start]);
+                    result.push_str(&r);
+                    result.push_str(&token[start + trimmed.len()..]);
+                }
+                _ => result.push_str(token),
+            }
+            result.push_str(sep);
+        }
+        if changed {
+            *s = result;
+        }
+    }
+
+    fn anonymize(&mut self, ip: IpAddr) -> IpAddr {
+        if ip.is_unspecified() || !self.nets.iter().any(|n| n.contains(&ip)) {
+            return ip;
+        }
+        if let Some(anonymized) = self.cache.get(&ip) {
+            return *anonymized;
+        }
+        let anonymized = match self.config.mode {
+            IpAnonymizationMode::Truncate => match ip {
+                IpAddr::V4(ip4) => {
+                    Ipv4Addr::from(u32::from(ip4) & !(u32::MAX >> TRUNCATE_PREFIX_V4)).into()
+                }
+                IpAddr::V6(ip6) => {
+                    Ipv6Addr::from(u128::from(ip6) & !(u128::MAX >> TRUNCATE_PREFIX_V6)).into()
+                }
+            },
+            IpAnonymizationMode::PrefixPreserving => match ip {
+                IpAddr::V4(ip4) => {
+                    let addr = (u32::from(ip4) as u128) << 96;
+                    Ipv4Addr::from((self.crypto_pan.anonymize(addr, 32) >> 96) as u32).into()
+                }
+                IpAddr::V6(ip6) => {
+                    Ipv6Addr::from(self.crypto_pan.anonymize(u128::from(ip6), 128)).into()
+                }
+            },
+            IpAnonymizationMode::Disabled => ip,
+        };
+        self.cache.put(ip, anonymized);
+        anonymized
+    }
+}
+
+// Crypto-PAn (Xu et al.): 第i位的翻转与否由前i位经AES-128加密决定，因此共享前缀的IP映射后仍共享前缀
+// 与参考实现一致，32字节密钥的前16字节为AES密钥，后16字节加密后作为填充
+struct CryptoPan {
+    cipher: Aes128,
+    pad: u128,
+}
+
+impl CryptoPan {
+    fn from_secret(secret: &[u8; 32]) -> Self {
+        let cipher = Aes128::new(GenericArray::from_slice(&secret[..16]));
+        let mut pad = GenericArray::clone_from_slice(&secret[16..]);
+        cipher.encrypt_block(&mut pad);
+        Self {
+            cipher,
+            pad: u128::from_be_bytes(pad.into()),
+        }
+    }
+
+    // addr左对齐存放，IPv4为addr << 96且bits为32
+    fn anonymize(&self, addr: u128, bits: u32) -> u128 {
+        let mut otp = 0;
+        for i in 0..bits {
+            let mask = u128::MAX.checked_shl(128 - i).unwrap_or(0);
+            let mut block = GenericArray::from(((addr & mask) | (self.pad & !mask)).to_be_bytes());
+            self.cipher.encrypt_block(&mut block);
+            otp |= ((block[0] >> 7) as u128) << (127 - i);
+        }
+        addr ^ otp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anonymizer(mode: IpAnonymizationMode) -> IpAnonymizer {
+        IpAnonymizer::new(&IpAnonymizationConfig {
+            mode,
+            cidrs: vec!["10.0.0.0/8".into(), "2001:db8::/32".into()],
+            key: "deepflow".into(),
+        })
+    }
+
+    #[test]
+    fn truncate() {
+        let mut a = anonymizer(IpAnonymizationMode::Truncate);
+        assert_eq!(
+            a.anonymize("10.1.2.3".parse().unwrap()),
+            "10.1.2.0".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            a.anonymize("2001:db8:1:2::3".parse().unwrap()),
+            "2001:db8:1::".parse::<IpAddr>().unwrap()
+        );
+        // 不在cidrs中的IP保持不变
+        assert_eq!(
+            a.anonymize("192.168.1.1".parse().unwrap()),
+            "192.168.1.1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn prefix_preserving() {
+        let mut a = anonymizer(IpAnonymizationMode::PrefixPreserving);
+        let ip1: IpAddr = "10.1.2.3".parse().unwrap();
+        let ip2: IpAddr = "10.1.2.200".parse().unwrap();
+        let ip3: IpAddr = "10.129.2.3".parse().unwrap();
+        let (a1, a2, a3) = (a.anonymize(ip1), a.anonymize(ip2), a.anonymize(ip3));
+        assert_ne!(a1, ip1);
+        assert_eq!(a.anonymize(ip1), a1);
+
+        let common_prefix = |x: IpAddr, y: IpAddr| match (x, y) {
+            (IpAddr::V4(x), IpAddr::V4(y)) => (u32::from(x) ^ u32::from(y)).leading_zeros(),
+            _ => unreachable!(),
+        };
+        assert_eq!(common_prefix(a1, a2), common_prefix(ip1, ip2));
+        assert_eq!(common_prefix(a1, a3), common_prefix(ip1, ip3));
+
+        let ip6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_ne!(a.anonymize(ip6), ip6);
+    }
+
+    // 参考实现sample_trace_raw.txt与sample_trace_sanitized.txt中的数据
+    #[test]
+    fn crypto_pan_reference() {
+        let pan = CryptoPan::from_secret(&[
+            21, 34, 23, 141, 51, 164, 207, 128, 19, 10, 91, 22, 73, 144, 125, 16, 216, 152, 143,
+            131, 121, 121, 101, 39, 98, 87, 76, 45, 42, 132, 34, 2,
+        ]);
+        for (raw, sanitized) in [
+            ("128.11.68.132", "135.242.180.132"),
+            ("129.118.74.4", "134.136.186.123"),
+            ("130.132.252.244", "133.68.164.234"),
+            ("141.223.7.43", "141.167.8.160"),
+            ("141.233.145.108", "141.129.237.235"),
+            ("152.163.225.39", "151.140.114.167"),
+        ] {
+            let addr = (u32::from(raw.parse::<Ipv4Addr>().unwrap()) as u128) << 96;
+            assert_eq!(
+                Ipv4Addr::from((pan.anonymize(addr, 32) >> 96) as u32),
+                sanitized.parse::<Ipv4Addr>().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn anonymize_list() {
+        let mut a = anonymizer(IpAnonymizationMode::Truncate);
+        let mut xff = "10.1.2.3, 192.168.1.1,10.2.3.4:8080, unknown".to_string();
+        a.anonymize_list(&mut xff);
+        assert_eq!(xff, "10.1.2.0, 192.168.1.1,10.2.3.0:8080, unknown");
+
+        let mut answers = "example.com;10.3.4.5;2001:db8:1:2::3".to_string();
+        a.anonymize_list(&mut answers);
+        assert_eq!(answers, "example.com;10.3.4.0;2001:db8:1::");
+    }
+}
@@ -15,6 +15,8 @@
  */
 
 // NpbBandwidthWatcher NewFragmenterBuilder NewCompressorBuilder NewPCapBuilder NewUniformCollectSender
+mod ip_anonymizer;
+pub(crate) mod server_domain;
 mod tcp_packet;
 pub(crate) mod uniform_sender;
 
@@ -24,6 +26,7 @@ use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::collector::SecurityEvent;
 use crate::common::tagged_flow::TaggedFlow;
 use crate::flow_generator::AppProtoLogsData;
 use crate::integration_collector::{OpenTelemetry, PrometheusMetric, TelegrafMetric};
@@ -39,6 +42,7 @@ const OPEN_TELEMETRY: u32 = 20220607;
 const PROMETHEUS: u32 = 20220613;
 const TELEGRAF: u32 = 20220613;
 const PACKET_SEQUENCE_BLOCK: u32 = 20220712; // Enterprise Edition Feature: packet-sequence
+const SECURITY_EVENT: u32 = 20221016;
 
 const PRE_FILE_SUFFIX: &str = ".pre";
 const MAX_FILE_SIZE: usize = 1_000_000_000;
@@ -52,6 +56,7 @@ pub enum SendItem {
     ExternalTelegraf(TelegrafMetric),
     PacketSequenceBlock(Box<packet_sequence_block::PacketSequenceBlock>), // Enterprise Edition Feature: packet-sequence
     DeepflowStats(Arc<Batch>),
+    SecurityEvent(Box<SecurityEvent>),
 }
 
 impl SendItem {
@@ -65,6 +70,7 @@ impl SendItem {
             Self::ExternalTelegraf(p) => p.encode(buf),
             Self::PacketSequenceBlock(p) => p.encode(buf), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(b) => b.encode(buf),
+            Self::SecurityEvent(e) => e.encode(buf),
         }
     }
 
@@ -94,6 +100,7 @@ impl SendItem {
             Self::ExternalTelegraf(_) => SendMessageType::Telegraf,
             Self::PacketSequenceBlock(_) => SendMessageType::PacketSequenceBlock, // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(_) => SendMessageType::DeepflowStats,
+            Self::SecurityEvent(_) => SendMessageType::SecurityEvent,
         }
     }
 
@@ -106,6 +113,7 @@ impl SendItem {
             Self::ExternalProm(_) => PROMETHEUS,
             Self::ExternalTelegraf(_) => TELEGRAF,
             Self::PacketSequenceBlock(_) => PACKET_SEQUENCE_BLOCK, // Enterprise Edition Feature: packet-sequence
+            Self::SecurityEvent(_) => SECURITY_EVENT,
             _ => 0,
         }
     }
@@ -122,6 +130,7 @@ impl fmt::Display for SendItem {
             Self::ExternalTelegraf(p) => write!(f, "telegraf: {:?}", p),
             Self::PacketSequenceBlock(p) => write!(f, "packet_sequence_block: {:?}", p), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(s) => write!(f, "deepflow_stats: {:?}", s),
+            Self::SecurityEvent(e) => write!(f, "security_event: {:?}", e),
         }
     }
 }
@@ -137,6 +146,7 @@ impl fmt::Debug for SendItem {
             Self::ExternalTelegraf(p) => write!(f, "telegraf: {:?}", p),
             Self::PacketSequenceBlock(p) => write!(f, "packet_sequence_block: {:?}", p), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(s) => write!(f, "deepflow_stats: {:?}", s),
+            Self::SecurityEvent(e) => write!(f, "security_event: {:?}", e),
         }
     }
 }
@@ -155,6 +165,7 @@ pub enum SendMessageType {
     Telegraf = 8,
     PacketSequenceBlock = 9, // Enterprise Edition Feature: packet-sequence
     DeepflowStats = 10,
+    SecurityEvent = 11,
 }
 
 impl fmt::Display for SendMessageType {
@@ -171,6 +182,7 @@ impl fmt::Display for SendMessageType {
             Self::Telegraf => write!(f, "telegraf"),
             Self::PacketSequenceBlock => write!(f, "packet_sequence_block"), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats => write!(f, "deepflow_stats"),
+            Self::SecurityEvent => write!(f, "security_event"),
         }
     }
 }
@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use super::SendItem;
+use crate::common::flow::L7Protocol;
+use crate::config::ServerDomainConfig;
+use crate::flow_generator::{AppProtoLogsData, AppProtoLogsInfo, L7ResponseStatus, LogMessageType};
+
+// 从采集器自身的DNS日志中学习IP对应的域名，发送前为l4和l7流日志的服务端IP补充域名，
+// 不主动发起反向解析。l4和l7流日志由不同的发送线程处理，共享同一个缓存
+pub struct ServerDomainCache {
+    ttl: Duration,
+    // 值为域名及DNS应答的时间
+    cache: Mutex<LruCache<IpAddr, (String, Duration)>>,
+}
+
+impl ServerDomainCache {
+    pub fn new(config: &ServerDomainConfig) -> Self {
+        Self {
+            ttl: config.ttl,
+            cache: Mutex::new(LruCache::new(config.capacity)),
+        }
+    }
+
+    // 时间均使用日志中的时间戳，回放pcap时同样有效
+    fn learn(&self, log: &AppProtoLogsData) {
+        let base = &log.base_info;
+        if base.head.proto != L7Protocol::Dns
+            || base.head.msg_type == LogMessageType::Request
+            || base.head.status != L7ResponseStatus::Ok
+        {
+            return;
+        }
+        let info = match &log.special_info {
+            AppProtoLogsInfo::Dns(info) if !info.query_name.is_empty() => info,
+            _ => return,
+        };
+        let mut cache = self.cache.lock();
+        for ip in info.answer_ips.iter() {
+            cache.put(*ip, (info.query_name.clone(), base.end_time));
+        }
+    }
+
+    fn lookup(&self, ip: IpAddr, timestamp: Duration) -> Option<String> {
+        let mut cache = self.cache.lock();
+        match cache.get(&ip) {
+            Some((domain, learned)) if timestamp <= *learned + self.ttl => Some(domain.clone()),
+            Some(_) => {
+                cache.pop(&ip);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn process(&self, send_item: &mut SendItem) {
+        match send_item {
+            SendItem::L4FlowLog(l4) => {
+                let flow = &mut l4.flow;
+                if let Some(domain) = self.lookup(flow.flow_key.ip_dst, flow.end_time) {
+                    flow.server_domain = domain;
+                }
+            }
+            SendItem::L7FlowLog(l7) => {
+                self.learn(l7);
+                let base = &mut l7.base_info;
+                if let Some(domain) = self.lookup(base.ip_dst, base.end_time) {
+                    base.server_domain = domain;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::{
+        enums::{IpProtocol, PacketDirection},
+        meta_packet::MetaPacket,
+        tagged_flow::TaggedFlow,
+    };
+    use crate::flow_generator::{AppProtoHead, AppProtoLogsBaseInfo, DnsInfo, HttpInfo};
+
+    fn l7_log(timestamp: u64, server: &str, proto: L7Protocol, info: AppProtoLogsInfo) -> SendItem {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = Duration::from_secs(timestamp);
+        packet.lookup_key.src_ip = "10.0.0.1".parse().unwrap();
+        packet.lookup_key.dst_ip = server.parse().unwrap();
+        packet.lookup_key.proto = IpProtocol::Udp;
+        packet.direction = PacketDirection::ClientToServer;
+        let head = AppProtoHead {
+            proto,
+            msg_type: LogMessageType::Session,
+            status: L7ResponseStatus::Ok,
+            ..Default::default()
+        };
+        let base_info = AppProtoLogsBaseInfo::from_ebpf(&packet, head, 1, 0, 0);
+        SendItem::L7FlowLog(Box::new(AppProtoLogsData::new(base_info, info)))
+    }
+
+    #[test]
+    fn learn_from_dns() {
+        let cache = ServerDomainCache::new(&ServerDomainConfig {
+            enabled: true,
+            capacity: 2,
+            ttl: Duration::from_secs(60),
+        });
+        let mut dns = l7_log(
+            100,
+            "10.0.0.53",
+            L7Protocol::Dns,
+            AppProtoLogsInfo::Dns(DnsInfo {
+                query_name: "example.com".into(),
+                answer_ips: vec!["93.184.216.34".parse().unwrap()],
+                ..Default::default()
+            }),
+        );
+        cache.process(&mut dns);
+
+        let mut http = l7_log(
+            120,
+            "93.184.216.34",
+            L7Protocol::Http1,
+            AppProtoLogsInfo::HttpV1(HttpInfo::default()),
+        );
+        cache.process(&mut http);
+        match http {
+            SendItem::L7FlowLog(l7) => assert_eq!(l7.base_info.server_domain, "example.com"),
+            _ => unreachable!(),
+        }
+
+        let mut flow = TaggedFlow::default();
+        flow.flow.flow_key.ip_dst = "93.184.216.34".parse().unwrap();
+        flow.flow.end_time = Duration::from_secs(130);
+        let mut l4 = SendItem::L4FlowLog(Box::new(flow));
+        cache.process(&mut l4);
+        match &l4 {
+            SendItem::L4FlowLog(l4) => assert_eq!(l4.flow.server_domain, "example.com"),
+            _ => unreachable!(),
+        }
+
+        // 超过ttl后不再使用
+        let ip = "93.184.216.34".parse().unwrap();
+        assert_eq!(
+            cache.lookup(ip, Duration::from_secs(160)),
+            Some("example.com".into())
+        );
+        assert_eq!(cache.lookup(ip, Duration::from_secs(161)), None);
+        // 过期的记录已被删除
+        assert_eq!(cache.lookup(ip, Duration::from_secs(100)), None);
+    }
+}
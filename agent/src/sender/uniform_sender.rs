@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::VecDeque;
 use std::fs::{rename, File, OpenOptions};
 use std::io::{BufWriter, ErrorKind, Write};
 use std::net::{IpAddr, Shutdown, TcpStream};
@@ -28,7 +29,11 @@ use arc_swap::access::Access;
 use log::{debug, error, info, warn};
 use thread::JoinHandle;
 
-use super::{SendItem, SendMessageType, MAX_FILE_SIZE, PRE_FILE_SUFFIX};
+use super::{
+    ip_anonymizer::IpAnonymizer, server_domain::ServerDomainCache, SendItem, SendMessageType,
+    MAX_FILE_SIZE, PRE_FILE_SUFFIX,
+};
+use crate::common::business_label::BusinessLabeler;
 use crate::config::handler::SenderAccess;
 use crate::exception::ExceptionHandler;
 use crate::proto::trident::{Exception, SocketType};
@@ -43,6 +48,9 @@ pub struct SenderCounter {
     pub tx: AtomicU64,
     pub tx_bytes: AtomicU64,
     pub dropped: AtomicU64,
+    // 写入socket失败的frame区间数和frame数，不包括写入成功后在网络或服务端丢失的数据
+    pub write_failed_ranges: AtomicU64,
+    pub write_failed_frames: AtomicU64,
 }
 
 impl RefCountable for SenderCounter {
@@ -68,6 +76,16 @@ impl RefCountable for SenderCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "write-failed-ranges",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.write_failed_ranges.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "write-failed-frames",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.write_failed_frames.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -92,6 +110,48 @@ impl Header {
     }
 }
 
+// 记录frame序号写入socket的结果，写入失败的序号区间必然是服务端会观察到的序号空洞
+// 协议没有服务端确认，写入成功只表示数据进入了本地socket缓冲区，连接断开时仍可能丢失，
+// 这部分丢失只能由服务端receiver按Header.sequence检测(DropDetection，目前仅UDP接收的metrics)，采集器不维护确认区间
+struct WriteLog {
+    // 最近一次写入socket成功的序号
+    last_written: u64,
+    // 写入socket失败的序号区间[start, end]，按序号递增
+    unwritten: VecDeque<(u64, u64)>,
+}
+
+impl WriteLog {
+    const MAX_RANGES: usize = 64;
+
+    fn new() -> Self {
+        Self {
+            last_written: 0,
+            unwritten: VecDeque::new(),
+        }
+    }
+
+    // 返回是否产生了新的失败区间
+    fn write_failed(&mut self, sequence: u64) -> bool {
+        if let Some((_, end)) = self.unwritten.back_mut() {
+            if *end + 1 == sequence {
+                *end = sequence;
+                return false;
+            }
+        }
+        if self.unwritten.len() >= Self::MAX_RANGES {
+            self.unwritten.pop_front();
+        }
+        self.unwritten.push_back((sequence, sequence));
+        true
+    }
+
+    // 再次写入成功时返回此前写入失败的区间
+    fn written(&mut self, sequence: u64) -> Vec<(u64, u64)> {
+        self.last_written = sequence;
+        self.unwritten.drain(..).collect()
+    }
+}
+
 struct Encoder {
     id: usize,
     header: Header,
@@ -176,6 +236,9 @@ pub struct UniformSenderThread {
     id: usize,
     input: Arc<Receiver<SendItem>>,
     config: SenderAccess,
+    business_labeler: Option<Arc<BusinessLabeler>>,
+    server_domain_cache: Option<Arc<ServerDomainCache>>,
+    ip_anonymization_updated: Arc<AtomicBool>,
 
     thread_handle: Option<JoinHandle<()>>,
 
@@ -191,12 +254,17 @@ impl UniformSenderThread {
         config: SenderAccess,
         stats: Arc<Collector>,
         exception_handler: ExceptionHandler,
+        business_labeler: Option<Arc<BusinessLabeler>>,
+        server_domain_cache: Option<Arc<ServerDomainCache>>,
     ) -> Self {
         let running = Arc::new(AtomicBool::new(false));
         Self {
             id,
             input,
             config,
+            business_labeler,
+            server_domain_cache,
+            ip_anonymization_updated: Default::default(),
             thread_handle: None,
             running,
             stats,
@@ -220,11 +288,19 @@ impl UniformSenderThread {
             self.running.clone(),
             self.stats.clone(),
             self.exception_handler.clone(),
+            self.business_labeler.clone(),
+            self.server_domain_cache.clone(),
+            self.ip_anonymization_updated.clone(),
         );
         self.thread_handle = Some(thread::spawn(move || uniform_sender.process()));
         info!("uniform sender id: {} started", self.id);
     }
 
+    // 由发送线程在处理下一个数据时按新配置重建匿名化器
+    pub fn ip_anonymization_config_updated(&self) {
+        self.ip_anonymization_updated.store(true, Ordering::Relaxed);
+    }
+
     pub fn stop(&mut self) {
         if !self.running.swap(false, Ordering::Relaxed) {
             warn!(
@@ -262,6 +338,11 @@ pub struct UniformSender {
     file_path: String,
     pre_file_path: String,
     written_size: usize,
+    business_labeler: Option<Arc<BusinessLabeler>>,
+    server_domain_cache: Option<Arc<ServerDomainCache>>,
+    ip_anonymizer: IpAnonymizer,
+    ip_anonymization_updated: Arc<AtomicBool>,
+    write_log: WriteLog,
 }
 
 impl UniformSender {
@@ -275,6 +356,9 @@ impl UniformSender {
         running: Arc<AtomicBool>,
         stats: Arc<Collector>,
         exception_handler: ExceptionHandler,
+        business_labeler: Option<Arc<BusinessLabeler>>,
+        server_domain_cache: Option<Arc<ServerDomainCache>>,
+        ip_anonymization_updated: Arc<AtomicBool>,
     ) -> Self {
         Self {
             id,
@@ -295,7 +379,47 @@ impl UniformSender {
             file_path: String::new(),
             pre_file_path: String::new(),
             written_size: 0,
+            ip_anonymizer: IpAnonymizer::new(&config.load().ip_anonymization),
+            ip_anonymization_updated,
+            write_log: WriteLog::new(),
+            business_labeler,
+            server_domain_cache,
+        }
+    }
+
+    // 根据两端的IP和端口匹配业务标签
+    fn tag_business_labels(&self, send_item: &mut SendItem) {
+        let labeler = match self.business_labeler.as_ref() {
+            Some(l) => l,
+            None => return,
+        };
+        match send_item {
+            SendItem::L4FlowLog(l4) => {
+                let flow = &mut l4.flow;
+                let key = &flow.flow_key;
+                flow.business_labels_src = labeler.lookup(key.ip_src, key.port_src);
+                flow.business_labels_dst = labeler.lookup(key.ip_dst, key.port_dst);
+            }
+            SendItem::L7FlowLog(l7) => {
+                let base = &mut l7.base_info;
+                base.business_labels_src = labeler.lookup(base.ip_src, base.port_src);
+                base.business_labels_dst = labeler.lookup(base.ip_dst, base.port_dst);
+            }
+            _ => (),
+        }
+    }
+
+    // 业务标签需要按原始IP匹配，匿名化在其之后进行
+    fn anonymize_ips(&mut self, send_item: &mut SendItem) {
+        if self.ip_anonymization_updated.swap(false, Ordering::Relaxed) {
+            let config = self.config.load();
+            info!(
+                "update ip anonymization config to {:?}",
+                config.ip_anonymization
+            );
+            self.ip_anonymizer = IpAnonymizer::new(&config.ip_anonymization);
         }
+        self.ip_anonymizer.anonymize_item(send_item);
     }
 
     fn update_dst_ip_and_port(&mut self) {
@@ -323,12 +447,34 @@ impl UniformSender {
     fn flush_encoder(&mut self) {
         if self.encoder.buffer_len() > 0 {
             self.encoder.set_header_frame_size();
+            let sequence = self.encoder.header.sequence;
             let buffer = self.encoder.get_buffer();
-            self.send_buffer(buffer.as_slice());
+            if self.send_buffer(buffer.as_slice()) {
+                for (start, end) in self.write_log.written(sequence) {
+                    warn!(
+                        "sender id {} frames {}-{} failed to be written to {}:{}",
+                        self.id, start, end, self.dst_ip, self.dst_port
+                    );
+                }
+            } else {
+                if self.write_log.write_failed(sequence) {
+                    debug!(
+                        "sender id {} frame {} write failed, last written {}",
+                        self.id, sequence, self.write_log.last_written
+                    );
+                    self.counter
+                        .write_failed_ranges
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                self.counter
+                    .write_failed_frames
+                    .fetch_add(1, Ordering::Relaxed);
+            }
         }
     }
 
-    fn send_buffer(&mut self, buffer: &[u8]) {
+    // 返回frame是否完整写入socket
+    fn send_buffer(&mut self, buffer: &[u8]) -> bool {
         if self.reconnect || self.tcp_stream.is_none() {
             if let Some(t) = self.tcp_stream.take() {
                 if let Err(e) = t.shutdown(Shutdown::Both) {
@@ -342,7 +488,7 @@ impl UniformSender {
                 {
                     debug!("tcp stream set write timeout failed {}", e);
                     self.tcp_stream.take();
-                    return;
+                    return false;
                 }
                 self.reconnect = false;
             } else {
@@ -351,7 +497,7 @@ impl UniformSender {
                     error!("tcp connection to {}:{} failed", self.dst_ip, self.dst_port,);
                 }
                 self.counter.dropped.fetch_add(1, Ordering::Relaxed);
-                return;
+                return false;
             }
         }
 
@@ -368,7 +514,7 @@ impl UniformSender {
                         self.counter
                             .tx_bytes
                             .fetch_add(buffer.len() as u64, Ordering::Relaxed);
-                        break;
+                        return true;
                     }
                 }
                 Err(e) if e.kind() == ErrorKind::WouldBlock => {
@@ -385,7 +531,7 @@ impl UniformSender {
                     }
                     self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                     self.tcp_stream.take();
-                    break;
+                    return false;
                 }
             };
         }
@@ -409,23 +555,37 @@ impl UniformSender {
             let socket_type = self.config.load().collector_socket_type;
             match self
                 .input
-                .recv(Some(Duration::from_secs(Self::QUEUE_READ_TIMEOUT)))
+                .recv_batch(Some(Duration::from_secs(Self::QUEUE_READ_TIMEOUT)))
             {
-                Ok(send_item) => {
-                    let message_type = send_item.message_type();
-                    self.counter.rx.fetch_add(1, Ordering::Relaxed);
-                    debug!("send item {}: {}", message_type, send_item);
-                    let result = match socket_type {
-                        SocketType::File => self.handle_target_file(send_item, &mut kv_string),
-                        _ => self.handle_target_server(send_item),
-                    };
-                    if let Err(e) = result {
-                        if self.counter.dropped.load(Ordering::Relaxed) == 0 {
-                            warn!("send item {} failed {}", message_type, e);
-                            // reopen write file and overwritten
-                            let _ = self.buf_writer.take();
+                Ok(send_items) => {
+                    for mut send_item in send_items {
+                        if let Some(cache) = self.server_domain_cache.as_ref() {
+                            cache.process(&mut send_item);
+                        }
+                        self.tag_business_labels(&mut send_item);
+                        self.anonymize_ips(&mut send_item);
+                        let message_type = send_item.message_type();
+                        self.counter.rx.fetch_add(1, Ordering::Relaxed);
+                        debug!("send item {}: {}", message_type, send_item);
+                        let result = match socket_type {
+                            SocketType::File => self.handle_target_file(send_item, &mut kv_string),
+                            _ => self.handle_target_server(send_item),
+                        };
+                        if let Err(e) = result {
+                            if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                                warn!("send item {} failed {}", message_type, e);
+                                // reopen write file and overwritten
+                                let _ = self.buf_writer.take();
+                            }
+                            self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    // 启用批量读取时每批处理完即发送，低速率下不必等到读超时
+                    if self.input.batching_enabled() {
+                        match socket_type {
+                            SocketType::File => self.flush_writer(),
+                            _ => self.flush_encoder(),
                         }
-                        self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                     }
                 }
                 Err(Error::Timeout) => match socket_type {
@@ -504,3 +664,20 @@ impl UniformSender {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_log() {
+        let mut log = WriteLog::new();
+        assert!(log.written(1).is_empty());
+        assert!(log.write_failed(2));
+        assert!(!log.write_failed(3));
+        assert!(log.write_failed(5));
+        assert_eq!(log.written(6), vec![(2, 3), (5, 5)]);
+        assert_eq!(log.last_written, 6);
+        assert!(log.written(7).is_empty());
+    }
+}
@@ -17,7 +17,7 @@
 use std::env;
 use std::mem;
 use std::net::{IpAddr, Ipv4Addr};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
@@ -25,6 +25,8 @@ use std::sync::{
 };
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
+#[cfg(target_os = "linux")]
+use std::time::Instant;
 
 use anyhow::Result;
 use arc_swap::access::Access;
@@ -42,36 +44,51 @@ use crate::ebpf_collector::EbpfCollector;
 use crate::handler::PacketHandlerBuilder;
 use crate::integration_collector::MetricServer;
 use crate::pcap::WorkerManager;
+#[cfg(target_os = "windows")]
+use crate::platform::ProcessSocketTable;
 #[cfg(target_os = "linux")]
-use crate::platform::{ApiWatcher, PlatformSynchronizer};
+use crate::platform::{
+    filter_links_by_namespace, ApiWatcher, GenericPoller, PlatformSynchronizer, Poller,
+};
 #[cfg(target_os = "linux")]
 use crate::utils::cgroups::Cgroups;
+#[cfg(target_os = "windows")]
+use crate::utils::net::add_loopback_link;
+#[cfg(target_os = "linux")]
+use crate::utils::net::{Link, LinkEventSocket};
+#[cfg(target_os = "linux")]
+use crate::utils::numa::{self, NumaStats};
+#[cfg(target_os = "linux")]
+use crate::utils::profiler::Profiler;
+#[cfg(target_os = "linux")]
+use crate::utils::{handoff, hardening};
 use crate::{
     collector::Collector,
     collector::{
         flow_aggr::FlowAggrThread, quadruple_generator::QuadrupleGeneratorThread, CollectorThread,
-        MetricsType,
+        MetricsType, SecurityEventDetector,
     },
     common::{
-        enums::TapType, tagged_flow::TaggedFlow, tap_types::TapTyper, DropletMessageType,
-        DEFAULT_INGESTER_PORT, DEFAULT_LOG_RETENTION, FREE_SPACE_REQUIREMENT,
+        business_label::BusinessLabeler, enums::TapType, tagged_flow::TaggedFlow,
+        tap_types::TapTyper, DropletMessageType, DEFAULT_INGESTER_PORT, DEFAULT_LOG_RETENTION,
+        FREE_SPACE_REQUIREMENT,
     },
     config::{
         handler::{ConfigHandler, DispatcherConfig, ModuleConfig, PortAccess},
-        Config, ConfigError, RuntimeConfig, YamlConfig,
+        AfPacketFanoutMode, Config, ConfigError, RuntimeConfig, YamlConfig,
     },
     debug::{ConstructDebugCtx, Debugger, QueueDebugger},
     dispatcher::{
         self, recv_engine::bpf, BpfOptions, Dispatcher, DispatcherBuilder, DispatcherListener,
     },
     exception::ExceptionHandler,
-    flow_generator::{AppProtoLogsParser, PacketSequenceParser},
+    flow_generator::{AppProtoLogsParser, FlowSnapshot, PacketSequenceParser},
     monitor::Monitor,
     platform::LibvirtXmlExtractor,
     policy::{Policy, PolicyGetter},
     proto::trident::TapMode,
-    rpc::{Session, Synchronizer, DEFAULT_TIMEOUT},
-    sender::{uniform_sender::UniformSenderThread, SendItem},
+    rpc::{RemoteExecutor, Session, Synchronizer, DEFAULT_TIMEOUT},
+    sender::{server_domain::ServerDomainCache, uniform_sender::UniformSenderThread, SendItem},
     utils::{
         environment::{
             check, controller_ip_check, free_memory_check, free_space_checker, kernel_check,
@@ -125,13 +142,13 @@ impl Trident {
         let state = Arc::new((Mutex::new(State::Running), Condvar::new()));
         let state_thread = state.clone();
 
-        let config = match Config::load_from_file(config_path.as_ref()) {
-            Ok(conf) => conf,
+        let (config, config_path) = match Config::load_from_file(config_path.as_ref()) {
+            Ok(conf) => (conf, config_path.as_ref().to_path_buf()),
             Err(e) => {
                 if let ConfigError::YamlConfigInvalid(_) = e {
                     // try to load config file from trident.yaml to support upgrading from trident
                     if let Ok(conf) = Config::load_from_file(DEFAULT_TRIDENT_CONF_FILE) {
-                        conf
+                        (conf, PathBuf::from(DEFAULT_TRIDENT_CONF_FILE))
                     } else {
                         // return the original error instead of loading trident conf
                         return Err(e.into());
@@ -181,6 +198,9 @@ impl Trident {
         };
         let logger_handle = logger.start()?;
 
+        #[cfg(target_os = "linux")]
+        handoff::load_inherited();
+
         let stats_collector = Arc::new(stats::Collector::new(&config.controller_ips));
         stats_collector.start();
         stats_collector.register_countable(
@@ -194,6 +214,7 @@ impl Trident {
             if let Err(e) = Self::run(
                 state_thread,
                 config,
+                config_path,
                 agent_ident,
                 revision,
                 logger_handle,
@@ -211,6 +232,7 @@ impl Trident {
     fn run(
         state: TridentState,
         mut config: Config,
+        config_path: PathBuf,
         agent_ident: &'static str,
         revision: &'static str,
         logger_handle: LoggerHandle,
@@ -238,7 +260,10 @@ impl Trident {
             exception_handler.clone(),
         ));
 
-        if running_in_container() && config.kubernetes_cluster_id.is_empty() {
+        if !config.standalone.enabled
+            && running_in_container()
+            && config.kubernetes_cluster_id.is_empty()
+        {
             config.kubernetes_cluster_id = Config::get_k8s_cluster_id(&session);
         }
 
@@ -265,6 +290,8 @@ impl Trident {
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
         stats_sender.start();
 
@@ -278,10 +305,46 @@ impl Trident {
             config_handler.static_config.controller_ips[0].clone(),
             config_handler.static_config.vtap_group_id_request.clone(),
             config_handler.static_config.kubernetes_cluster_id.clone(),
+            config_handler.static_config.hot_upgrade,
+            config_handler.static_config.runtime_config_cache(),
             policy_setter,
             exception_handler.clone(),
         ));
-        synchronizer.start();
+        let flow_snapshot = Arc::new(FlowSnapshot::default());
+        let mut remote_executor = None;
+        if config_handler.static_config.standalone.enabled {
+            // 独立运行时不启动同步模块，直接应用本地配置
+            info!(
+                "running in standalone mode, config from {}",
+                config_path.display()
+            );
+            let runtime_config = config_handler
+                .static_config
+                .standalone
+                .runtime_config(&config_path)?;
+            *state.0.lock().unwrap() = State::ConfigChanged((runtime_config, vec![]));
+        } else {
+            // 使用缓存的运行配置先开始采集，首次同步后以控制器下发的配置为准
+            if let Some(runtime_config) = synchronizer.load_runtime_config_cache(
+                config_handler.static_config.runtime_config_cache_max_age,
+            ) {
+                *state.0.lock().unwrap() = State::ConfigChanged((runtime_config, vec![]));
+            }
+            synchronizer.start();
+
+            if config_handler.static_config.remote_exec_enabled {
+                let executor = RemoteExecutor::new(
+                    session.clone(),
+                    synchronizer.running_config.clone(),
+                    synchronizer.status.clone(),
+                    stats_collector.clone(),
+                    flow_snapshot.clone(),
+                    config_handler.logger_handle.clone(),
+                );
+                executor.start();
+                remote_executor = Some(executor);
+            }
+        }
 
         let log_dir = Path::new(config_handler.static_config.log_file.as_str());
         let log_dir = log_dir.parent().unwrap().to_str().unwrap();
@@ -292,6 +355,14 @@ impl Trident {
         );
         guard.start();
 
+        let drop_stats = synchronizer.drop_stats();
+        stats_collector.register_countable(
+            "capture_drop",
+            Countable::Owned(Box::new(drop_stats.clone())),
+            vec![],
+        );
+        drop_stats.start();
+
         let monitor = Monitor::new(stats_collector.clone(), log_dir.to_string())?;
         monitor.start();
 
@@ -299,6 +370,8 @@ impl Trident {
         let mut state_guard = state.lock().unwrap();
         let mut components: Option<Components> = None;
         let mut yaml_conf: Option<YamlConfig> = None;
+        #[cfg(target_os = "linux")]
+        let mut hardened = false;
 
         loop {
             match &*state_guard {
@@ -310,8 +383,12 @@ impl Trident {
                     if let Some(mut c) = components {
                         c.stop();
                         guard.stop();
+                        drop_stats.stop();
                         monitor.stop();
                     }
+                    if let Some(e) = remote_executor.as_ref() {
+                        e.stop();
+                    }
                     return Ok(());
                 }
                 State::Disabled => {
@@ -333,6 +410,10 @@ impl Trident {
                     if let Some(mut c) = components.take() {
                         c.stop();
                     }
+                    #[cfg(target_os = "linux")]
+                    if hardened && config_handler.static_config.hardening.enabled {
+                        warn!("components rebuilt after hardening, eBPF cannot be loaded again until restart");
+                    }
                 }
             }
             yaml_conf = Some(new_conf.yaml_config.clone());
@@ -348,8 +429,20 @@ impl Trident {
                         policy_getter,
                         exception_handler.clone(),
                         remote_log_config.clone(),
+                        &flow_snapshot,
                     )?;
                     comp.start();
+                    // 未被dispatcher复用的热升级socket需要关闭
+                    #[cfg(target_os = "linux")]
+                    handoff::close_unclaimed();
+                    // 采集socket和eBPF均已创建，此后收紧权限，仅在首次创建组件后执行
+                    #[cfg(target_os = "linux")]
+                    if !hardened {
+                        hardened = true;
+                        synchronizer.set_hardening_profile(hardening::apply(
+                            &config_handler.static_config.hardening,
+                        ));
+                    }
                     for callback in callbacks {
                         callback(&config_handler, &mut comp);
                     }
@@ -414,14 +507,247 @@ fn dispatcher_listener_callback(
                 links
             }
         };
+        #[cfg(target_os = "linux")]
+        let links = filter_tap_interfaces(
+            conf,
+            links,
+            &components.platform_synchronizer.clone_poller(),
+        );
+        #[cfg(target_os = "windows")]
+        let links = {
+            let mut links = links;
+            if conf.loopback_capture_enabled {
+                add_loopback_link(&mut links);
+            }
+            links
+        };
         for listener in components.dispatcher_listeners.iter() {
             listener.on_tap_interface_change(&links, if_mac_source, conf.trident_type, &blacklist);
         }
+        #[cfg(target_os = "linux")]
+        components
+            .tap_interface_watcher
+            .update(conf, &blacklist, &links);
     } else {
         todo!()
     }
 }
 
+// 按队列名设置发送队列的自适应批量读取，发送线程每批处理完即发送
+fn set_queue_batching<T>(receiver: &queue::Receiver<T>, name: &str, yaml_config: &YamlConfig) {
+    if let Some(batching) = yaml_config.queue_batching.get(name) {
+        info!(
+            "queue {} batching with size {} linger {:?}",
+            name, batching.batch_size, batching.linger
+        );
+        receiver.set_batching(batching.batch_size, batching.linger);
+    }
+}
+
+// 容器环境下配置了capture-namespaces时，仅采集属于这些命名空间POD的接口
+#[cfg(target_os = "linux")]
+fn filter_tap_interfaces(
+    conf: &DispatcherConfig,
+    links: Vec<Link>,
+    poller: &GenericPoller,
+) -> Vec<Link> {
+    if conf.capture_namespaces.is_empty() || !running_in_container() {
+        return links;
+    }
+    // 平台信息未就绪时暂不过滤，避免agent启动阶段丢失所有流量，就绪后由TapInterfaceWatcher在poller版本变化时重新过滤
+    let interfaces = match poller.get_interface_info() {
+        Some(interfaces) => interfaces,
+        None => {
+            info!(
+                "kubernetes poller not ready, capture all interfaces before filtering by capture-namespaces {:?}",
+                conf.capture_namespaces
+            );
+            return links;
+        }
+    };
+    let filtered = filter_links_by_namespace(links, &interfaces, &conf.capture_namespaces);
+    if filtered.is_empty() {
+        warn!(
+            "no interface belongs to capture-namespaces {:?}",
+            conf.capture_namespaces
+        );
+    }
+    filtered
+}
+
+#[cfg(target_os = "linux")]
+struct TapInterfaceContext {
+    config: DispatcherConfig,
+    blacklist: Vec<u64>,
+    links: Vec<Link>,
+}
+
+// 监听netlink接口增删事件，tap-interface-regex匹配的接口变化时即时更新各dispatcher的采集接口，
+// 不必等待下次配置同步
+#[cfg(target_os = "linux")]
+pub struct TapInterfaceWatcher {
+    listeners: Vec<DispatcherListener>,
+    poller: Arc<GenericPoller>,
+    context: Arc<Mutex<Option<TapInterfaceContext>>>,
+
+    thread_handler: Option<JoinHandle<()>>,
+    running: Arc<AtomicBool>,
+}
+
+#[cfg(target_os = "linux")]
+impl TapInterfaceWatcher {
+    const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+    // 接口创建后会连续产生多个通知（改名、设置MAC、UP等），无新通知后再统一处理
+    const MAX_DELAY: Duration = Duration::from_secs(5);
+
+    fn new(listeners: Vec<DispatcherListener>, poller: Arc<GenericPoller>) -> Self {
+        Self {
+            listeners,
+            poller,
+            context: Default::default(),
+            thread_handler: None,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn update(&self, config: &DispatcherConfig, blacklist: &Vec<u64>, links: &Vec<Link>) {
+        let mut links = links.clone();
+        links.sort();
+        *self.context.lock().unwrap() = Some(TapInterfaceContext {
+            config: config.clone(),
+            blacklist: blacklist.clone(),
+            links,
+        });
+    }
+
+    fn start(&mut self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let mut socket = match LinkEventSocket::new(Self::RECV_TIMEOUT) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("tap interface watcher subscribe link events failed: {}", e);
+                self.running.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+        let listeners = self.listeners.clone();
+        let poller = self.poller.clone();
+        let context = self.context.clone();
+        let running = self.running.clone();
+
+        self.thread_handler = Some(
+            thread::Builder::new()
+                .name("tap-if-watcher".to_owned())
+                .spawn(move || {
+                    let mut pending: Option<Instant> = None;
+                    let mut poller_version = poller.get_version();
+                    while running.load(Ordering::Relaxed) {
+                        let events = match socket.recv() {
+                            Ok(n) => n,
+                            Err(e) => {
+                                warn!("tap interface watcher receive link events failed: {}", e);
+                                thread::sleep(Self::RECV_TIMEOUT);
+                                continue;
+                            }
+                        };
+                        if events > 0 && pending.is_none() {
+                            pending = Some(Instant::now());
+                        }
+                        // POD命名空间信息更新后需按capture-namespaces重新过滤
+                        let version = poller.get_version();
+                        if version != poller_version {
+                            poller_version = version;
+                            Self::refresh(&context, &listeners, &poller);
+                        }
+                        match pending {
+                            Some(t) if events == 0 || t.elapsed() >= Self::MAX_DELAY => {
+                                pending = None;
+                                Self::refresh(&context, &listeners, &poller);
+                            }
+                            _ => (),
+                        }
+                    }
+                })
+                .unwrap(),
+        );
+        info!("tap interface watcher started");
+    }
+
+    fn stop(&mut self) {
+        if !self.running.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(handler) = self.thread_handler.take() {
+            let _ = handler.join();
+        }
+        info!("tap interface watcher stopped");
+    }
+
+    fn refresh(
+        context: &Mutex<Option<TapInterfaceContext>>,
+        listeners: &[DispatcherListener],
+        poller: &GenericPoller,
+    ) {
+        let mut context = context.lock().unwrap();
+        let context = match context.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        let links = match links_by_name_regex(&context.config.tap_interface_regex) {
+            Ok(links) => links,
+            Err(e) => {
+                warn!("get interfaces by name regex failed: {}", e);
+                return;
+            }
+        };
+        let mut links = filter_tap_interfaces(&context.config, links, poller);
+        links.sort();
+        // Link仅按if_index比较，MAC变化同样需要更新
+        if links.len() == context.links.len()
+            && links
+                .iter()
+                .zip(context.links.iter())
+                .all(|(a, b)| a.if_index == b.if_index && a.mac_addr == b.mac_addr)
+        {
+            return;
+        }
+
+        for link in links.iter() {
+            match context.links.binary_search(link) {
+                Ok(i) if context.links[i].mac_addr != link.mac_addr => info!(
+                    "Tap interface {} index {} mac changed from {} to {}",
+                    link.name, link.if_index, context.links[i].mac_addr, link.mac_addr
+                ),
+                Ok(_) => (),
+                Err(_) => info!(
+                    "Tap interface {} index {} mac {} added, attaching to dispatchers",
+                    link.name, link.if_index, link.mac_addr
+                ),
+            }
+        }
+        for link in context.links.iter() {
+            if links.binary_search(link).is_err() {
+                info!(
+                    "Tap interface {} index {} mac {} removed, detaching from dispatchers",
+                    link.name, link.if_index, link.mac_addr
+                );
+            }
+        }
+
+        for listener in listeners.iter() {
+            listener.on_tap_interface_change(
+                &links,
+                context.config.if_mac_source,
+                context.config.trident_type,
+                &context.blacklist,
+            );
+        }
+        context.links = links;
+    }
+}
+
 pub struct DomainNameListener {
     stats_collector: Arc<stats::Collector>,
     synchronizer: Arc<Synchronizer>,
@@ -554,8 +880,10 @@ pub struct Components {
     pub log_parsers: Vec<AppProtoLogsParser>,
     pub collectors: Vec<CollectorThread>,
     pub l4_flow_uniform_sender: UniformSenderThread,
-    pub metrics_uniform_sender: UniformSenderThread,
-    pub l7_flow_uniform_sender: UniformSenderThread,
+    pub metrics_uniform_sender: Option<UniformSenderThread>,
+    pub l7_flow_uniform_sender: Option<UniformSenderThread>,
+    pub security_event_uniform_sender: UniformSenderThread,
+    pub business_labeler: Option<Arc<BusinessLabeler>>,
     #[cfg(target_os = "linux")]
     pub platform_synchronizer: PlatformSynchronizer,
     #[cfg(target_os = "linux")]
@@ -576,6 +904,14 @@ pub struct Components {
     pub packet_sequence_uniform_sender: UniformSenderThread, // Enterprise Edition Feature: packet-sequence
     pub exception_handler: ExceptionHandler,
     pub domain_name_listener: DomainNameListener,
+    #[cfg(target_os = "linux")]
+    pub profiler: Profiler,
+    #[cfg(target_os = "linux")]
+    pub tap_interface_watcher: TapInterfaceWatcher,
+    #[cfg(target_os = "linux")]
+    pub numa_stats: Vec<Arc<NumaStats>>,
+    #[cfg(target_os = "windows")]
+    pub process_socket_table: ProcessSocketTable,
     max_memory: u64,
     tap_mode: TapMode,
 }
@@ -593,9 +929,17 @@ impl Components {
         #[cfg(target_os = "linux")]
         self.api_watcher.start();
         self.debugger.start();
-        self.metrics_uniform_sender.start();
-        self.l7_flow_uniform_sender.start();
+        if let Some(business_labeler) = self.business_labeler.as_ref() {
+            business_labeler.start();
+        }
+        if let Some(metrics_uniform_sender) = self.metrics_uniform_sender.as_mut() {
+            metrics_uniform_sender.start();
+        }
+        if let Some(l7_flow_uniform_sender) = self.l7_flow_uniform_sender.as_mut() {
+            l7_flow_uniform_sender.start();
+        }
         self.l4_flow_uniform_sender.start();
+        self.security_event_uniform_sender.start();
 
         // Enterprise Edition Feature: packet-sequence
         self.packet_sequence_uniform_sender.start();
@@ -637,6 +981,16 @@ impl Components {
             self.external_metrics_server.start();
         }
         self.domain_name_listener.start();
+        #[cfg(target_os = "linux")]
+        self.profiler.start();
+        #[cfg(target_os = "linux")]
+        if self.tap_mode == TapMode::Local {
+            self.tap_interface_watcher.start();
+        }
+        #[cfg(target_os = "windows")]
+        if self.tap_mode == TapMode::Local {
+            self.process_socket_table.start();
+        }
 
         info!("Started components.");
     }
@@ -649,6 +1003,7 @@ impl Components {
         policy_getter: PolicyGetter,
         exception_handler: ExceptionHandler,
         remote_log_config: RemoteLogConfig,
+        flow_snapshot: &Arc<FlowSnapshot>,
     ) -> Result<Self> {
         let static_config = &config_handler.static_config;
         let candidate_config = &config_handler.candidate_config;
@@ -657,6 +1012,8 @@ impl Components {
         let ctrl_mac = config_handler.ctrl_mac;
         let max_memory = config_handler.candidate_config.environment.max_memory;
         let tap_mode = config_handler.candidate_config.yaml_config.tap_mode;
+        let pipeline_mode = yaml_config.pipeline_mode;
+        flow_snapshot.set_tap_mode(tap_mode);
 
         trident_process_check();
         controller_ip_check(&static_config.controller_ips);
@@ -705,10 +1062,12 @@ impl Components {
             static_config: synchronizer.static_config.clone(),
             running_config: synchronizer.running_config.clone(),
             status: synchronizer.status.clone(),
+            flow_snapshot: flow_snapshot.clone(),
             config: config_handler.debug(),
         };
         let debugger = Debugger::new(context);
         let queue_debugger = debugger.clone_queue();
+        let l7_log_debugger = debugger.clone_l7_log();
 
         let (pcap_sender, pcap_receiver, _) = queue::bounded_with_debug(
             config_handler.candidate_config.pcap.queue_size as usize,
@@ -757,9 +1116,44 @@ impl Components {
             }
             Ok(links) => links,
         };
+        #[cfg(target_os = "linux")]
+        let tap_interfaces = filter_tap_interfaces(
+            &candidate_config.dispatcher,
+            tap_interfaces,
+            &platform_synchronizer.clone_poller(),
+        );
+        #[cfg(target_os = "windows")]
+        let tap_interfaces = {
+            let mut tap_interfaces = tap_interfaces;
+            if candidate_config.dispatcher.loopback_capture_enabled {
+                add_loopback_link(&mut tap_interfaces);
+            }
+            tap_interfaces
+        };
 
         // TODO: collector enabled
-        let dispatcher_num = yaml_config.src_interfaces.len().max(1);
+        let fanout_enabled = yaml_config.af_packet_fanout.mode != AfPacketFanoutMode::Disabled;
+        let dispatcher_num = if fanout_enabled {
+            yaml_config.af_packet_fanout.socket_count
+        } else {
+            yaml_config.src_interfaces.len().max(1)
+        };
+        #[cfg(target_os = "linux")]
+        let fanout_load = if fanout_enabled {
+            let load = Arc::new(dispatcher::recv_engine::af_packet::FanoutLoad::new(
+                dispatcher_num,
+                yaml_config.af_packet_fanout.imbalance_threshold,
+                exception_handler.clone(),
+            ));
+            stats_collector.register_countable(
+                "fanout",
+                Countable::Ref(Arc::downgrade(&load) as Weak<dyn RefCountable>),
+                vec![],
+            );
+            Some(load)
+        } else {
+            None
+        };
         let mut dispatchers = vec![];
         let mut dispatcher_listeners = vec![];
         let mut collectors = vec![];
@@ -785,12 +1179,31 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
+        let business_labeler = if yaml_config.business_label_file.is_empty() {
+            None
+        } else {
+            Some(Arc::new(BusinessLabeler::new(PathBuf::from(
+                &yaml_config.business_label_file,
+            ))))
+        };
+        let server_domain_cache = if yaml_config.server_domain.enabled {
+            Some(Arc::new(ServerDomainCache::new(&yaml_config.server_domain)))
+        } else {
+            None
+        };
+        set_queue_batching(
+            &l4_flow_aggr_receiver,
+            "3-flow-to-collector-sender",
+            yaml_config,
+        );
         let l4_flow_uniform_sender = UniformSenderThread::new(
             sender_id,
             Arc::new(l4_flow_aggr_receiver),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            business_labeler.clone(),
+            server_domain_cache.clone(),
         );
 
         let sender_id = 1usize;
@@ -807,35 +1220,82 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
-        let metrics_uniform_sender = UniformSenderThread::new(
-            sender_id,
-            Arc::new(metrics_receiver),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-        );
+        set_queue_batching(&metrics_receiver, "2-doc-to-collector-sender", yaml_config);
+        // 仅输出日志时不创建指标的发送线程，接收端释放后写入队列直接失败
+        let metrics_uniform_sender = if pipeline_mode.metrics_enabled() {
+            Some(UniformSenderThread::new(
+                sender_id,
+                Arc::new(metrics_receiver),
+                config_handler.sender(),
+                stats_collector.clone(),
+                exception_handler.clone(),
+                None,
+                None,
+            ))
+        } else {
+            drop(metrics_receiver);
+            None
+        };
 
         let sender_id = 2usize;
-        let (proto_log_sender, proto_log_receiver, counter) = queue::bounded_with_debug(
+        // 仅输出指标时不创建应用日志的队列和发送线程
+        let (mut proto_log_sender, mut l7_flow_uniform_sender) = (None, None);
+        if pipeline_mode.l7_log_enabled() {
+            let (sender, proto_log_receiver, counter) = queue::bounded_with_debug(
+                yaml_config.flow_sender_queue_size,
+                "3-protolog-to-collector-sender",
+                &queue_debugger,
+            );
+            stats_collector.register_countable(
+                "queue",
+                Countable::Owned(Box::new(counter)),
+                vec![
+                    StatsOption::Tag("module", "3-protolog-to-collector-sender".to_string()),
+                    StatsOption::Tag("index", "0".to_string()),
+                ],
+            );
+            set_queue_batching(
+                &proto_log_receiver,
+                "3-protolog-to-collector-sender",
+                yaml_config,
+            );
+            proto_log_sender = Some(sender);
+            l7_flow_uniform_sender = Some(UniformSenderThread::new(
+                sender_id,
+                Arc::new(proto_log_receiver),
+                config_handler.sender(),
+                stats_collector.clone(),
+                exception_handler.clone(),
+                business_labeler.clone(),
+                server_domain_cache.clone(),
+            ));
+        }
+
+        let sender_id = 7usize;
+        let (security_event_sender, security_event_receiver, counter) = queue::bounded_with_debug(
             yaml_config.flow_sender_queue_size,
-            "3-protolog-to-collector-sender",
+            "3-security-event-to-collector-sender",
             &queue_debugger,
         );
         stats_collector.register_countable(
             "queue",
             Countable::Owned(Box::new(counter)),
             vec![
-                StatsOption::Tag("module", "3-protolog-to-collector-sender".to_string()),
-                StatsOption::Tag("index", "0".to_string()),
+                StatsOption::Tag("module", "3-security-event-to-collector-sender".to_string()),
+                StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
-        let l7_flow_uniform_sender = UniformSenderThread::new(
+        let security_event_uniform_sender = UniformSenderThread::new(
             sender_id,
-            Arc::new(proto_log_receiver),
+            Arc::new(security_event_receiver),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
+        // 所有dispatcher的collector共享，同一扫描源的流可能分布在不同的dispatcher
+        let security_event = Arc::new(SecurityEventDetector::new(security_event_sender));
 
         // Dispatcher
         let source_ip = match get_route_src_ip(&candidate_config.dispatcher.analyzer_ip) {
@@ -883,12 +1343,19 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
+        set_queue_batching(
+            &packet_sequence_uniform_input,
+            "packet_sequence_block-to-sender",
+            yaml_config,
+        );
         let packet_sequence_uniform_sender = UniformSenderThread::new(
             sender_id,
             Arc::new(packet_sequence_uniform_input),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
 
         let bpf_options = Arc::new(Mutex::new(BpfOptions {
@@ -898,7 +1365,48 @@ impl Components {
             #[cfg(target_os = "windows")]
             bpf_syntax_str,
         }));
+        #[cfg(target_os = "windows")]
+        let process_socket_table = ProcessSocketTable::default();
+        #[cfg(target_os = "linux")]
+        let numa_local_memory = yaml_config.cpu_affinity.numa_local_memory;
+        #[cfg(target_os = "linux")]
+        let dispatcher_cores = numa::validate_cpus(&yaml_config.cpu_affinity.dispatcher_cores);
+        #[cfg(target_os = "linux")]
+        let numa_stats = if dispatcher_cores.is_empty() {
+            vec![]
+        } else {
+            numa::online_nodes()
+                .into_iter()
+                .map(|node| {
+                    let stats = Arc::new(NumaStats::new(node));
+                    stats_collector.register_countable(
+                        "numa",
+                        Countable::Ref(Arc::downgrade(&stats) as Weak<dyn RefCountable>),
+                        vec![StatsOption::Tag("node", node.to_string())],
+                    );
+                    stats
+                })
+                .collect()
+        };
         for i in 0..dispatcher_num {
+            #[cfg(target_os = "linux")]
+            let dispatcher_cpu = if dispatcher_cores.is_empty() {
+                None
+            } else {
+                let cpu = dispatcher_cores[i % dispatcher_cores.len()];
+                let node = numa::cpu_node(cpu);
+                info!(
+                    "dispatcher {} pinned to cpu {} numa node {:?}",
+                    i, cpu, node
+                );
+                // 创建该dispatcher的队列及抓包缓存期间，当前线程优先使用对应节点的内存
+                if numa_local_memory && node.is_some() {
+                    if let Err(e) = numa::set_preferred_node(node) {
+                        warn!("set preferred numa node {:?} failed: {}", node, e);
+                    }
+                }
+                Some(cpu)
+            };
             let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug(
                 yaml_config.flow_queue_size,
                 "1-tagged-flow-to-quadruple-generator",
@@ -914,33 +1422,57 @@ impl Components {
             );
 
             // create and start app proto logs
-            let (log_sender, log_receiver, counter) = queue::bounded_with_debug(
-                yaml_config.flow_queue_size,
-                "1-tagged-flow-to-app-protocol-logs",
-                &queue_debugger,
-            );
-            stats_collector.register_countable(
-                "queue",
-                Countable::Owned(Box::new(counter)),
-                vec![
-                    StatsOption::Tag("module", "1-tagged-flow-to-app-protocol-logs".to_string()),
-                    StatsOption::Tag("index", i.to_string()),
-                ],
-            );
-
-            let (app_proto_log_parser, counter) = AppProtoLogsParser::new(
-                log_receiver,
-                proto_log_sender.clone(),
-                i as u32,
-                config_handler.log_parser(),
-                l7_log_rate.clone(),
-            );
-            stats_collector.register_countable(
-                "l7_session_aggr",
-                Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
-                vec![StatsOption::Tag("index", i.to_string())],
-            );
-            log_parsers.push(app_proto_log_parser);
+            let mut log_sender = None;
+            if let Some(proto_log_sender) = proto_log_sender.as_ref() {
+                let (sender, log_receiver, counter) = queue::bounded_with_debug(
+                    yaml_config.flow_queue_size,
+                    "1-tagged-flow-to-app-protocol-logs",
+                    &queue_debugger,
+                );
+                stats_collector.register_countable(
+                    "queue",
+                    Countable::Owned(Box::new(counter)),
+                    vec![
+                        StatsOption::Tag(
+                            "module",
+                            "1-tagged-flow-to-app-protocol-logs".to_string(),
+                        ),
+                        StatsOption::Tag("index", i.to_string()),
+                    ],
+                );
+                log_sender = Some(sender);
+
+                #[allow(unused_mut)]
+                let (mut app_proto_log_parser, counter) = AppProtoLogsParser::new(
+                    log_receiver,
+                    proto_log_sender.clone(),
+                    metrics_sender.clone(),
+                    i as u32,
+                    config_handler.log_parser(),
+                    l7_log_rate.clone(),
+                    l7_log_debugger.clone(),
+                );
+                stats_collector.register_countable(
+                    "l7_session_aggr",
+                    Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                    vec![StatsOption::Tag("index", i.to_string())],
+                );
+                for (error, counter) in counter.parse_error.0.iter() {
+                    stats_collector.register_countable(
+                        "l7_log_parse_error",
+                        Countable::Ref(Arc::downgrade(counter) as Weak<dyn RefCountable>),
+                        vec![
+                            StatsOption::Tag("index", i.to_string()),
+                            StatsOption::Tag("error", error.to_string()),
+                        ],
+                    );
+                }
+                #[cfg(target_os = "windows")]
+                if tap_mode == TapMode::Local {
+                    app_proto_log_parser.set_process_socket_table(process_socket_table.clone());
+                }
+                log_parsers.push(app_proto_log_parser);
+            }
 
             // Enterprise Edition Feature: packet-sequence
             // create and start packet sequence
@@ -969,6 +1501,32 @@ impl Components {
             );
             packet_sequence_parsers.push(packet_sequence_parser);
 
+            // analyzer模式下未开启fanout时每个dispatcher采集一个src-interface，按网卡配置tap_type
+            let src_interface = if yaml_config.tap_mode == TapMode::Analyzer && !fanout_enabled {
+                yaml_config.src_interfaces.get(i)
+            } else {
+                None
+            };
+            let analyzer_tap_type = src_interface.and_then(|iface| {
+                yaml_config
+                    .analyzer_tap_types
+                    .iter()
+                    .find(|t| &t.interface == iface)
+            });
+            let default_tap_type = match analyzer_tap_type {
+                Some(t) if t.tap_type > 0 => t.tap_type,
+                _ => yaml_config.default_tap_type,
+            };
+            // 配置在加载时已校验
+            let vlan_tap_types = analyzer_tap_type
+                .map(|t| {
+                    t.vlan_tap_types
+                        .iter()
+                        .filter_map(|v| Some((v.vlan, (v.tap_type as u16).try_into().ok()?)))
+                        .collect()
+                })
+                .unwrap_or_default();
+
             let dispatcher_builder = DispatcherBuilder::new()
                 .id(i)
                 .ctrl_mac(ctrl_mac)
@@ -978,6 +1536,10 @@ impl Components {
                     af_packet_blocks: config_handler.candidate_config.dispatcher.af_packet_blocks,
                     #[cfg(target_os = "linux")]
                     af_packet_version: config_handler.candidate_config.dispatcher.af_packet_version,
+                    #[cfg(target_os = "linux")]
+                    af_packet_fanout_mode: yaml_config.af_packet_fanout.mode.into(),
+                    #[cfg(target_os = "linux")]
+                    af_packet_hardware_timestamp: yaml_config.af_packet_hardware_timestamp,
                     #[cfg(target_os = "windows")]
                     win_packet_blocks: config_handler.candidate_config.dispatcher.af_packet_blocks,
                     tap_mode: yaml_config.tap_mode,
@@ -986,6 +1548,8 @@ impl Components {
                     vxlan_port: yaml_config.vxlan_port,
                     controller_port: static_config.controller_port,
                     controller_tls_port: static_config.controller_tls_port,
+                    checksum_validation: yaml_config.checksum_validation.clone(),
+                    vlan_mode: yaml_config.vlan_mode,
                     snap_len: config_handler
                         .candidate_config
                         .dispatcher
@@ -994,27 +1558,43 @@ impl Components {
                     ..Default::default()
                 }))
                 .bpf_options(bpf_options.clone())
-                .default_tap_type(
-                    (yaml_config.default_tap_type as u16)
-                        .try_into()
-                        .unwrap_or(TapType::Tor),
-                )
+                .default_tap_type((default_tap_type as u16).try_into().unwrap_or(TapType::Tor))
                 .mirror_traffic_pcp(yaml_config.mirror_traffic_pcp)
                 .tap_typer(tap_typer.clone())
+                .vlan_tap_types(vlan_tap_types)
                 .analyzer_dedup_disabled(yaml_config.analyzer_dedup_disabled)
                 .libvirt_xml_extractor(libvirt_xml_extractor.clone())
                 .flow_output_queue(flow_sender)
-                .log_output_queue(log_sender)
                 .packet_sequence_output_queue(packet_sequence_sender) // Enterprise Edition Feature: packet-sequence
                 .stats_collector(stats_collector.clone())
                 .flow_map_config(config_handler.flow())
                 .policy_getter(policy_getter)
                 .exception_handler(exception_handler.clone())
-                .ntp_diff(synchronizer.ntp_diff());
+                .flow_snapshot(flow_snapshot.clone())
+                .time_source(synchronizer.time_source());
+            let dispatcher_builder = match log_sender {
+                Some(log_sender) => dispatcher_builder.log_output_queue(log_sender),
+                None => dispatcher_builder,
+            };
+            let dispatcher_builder = match src_interface {
+                Some(iface) => dispatcher_builder.src_interface(iface.clone()),
+                None => dispatcher_builder,
+            };
 
+            #[cfg(target_os = "linux")]
+            let dispatcher_builder = match fanout_load.as_ref() {
+                Some(load) => dispatcher_builder.fanout_load(load.clone()),
+                None => dispatcher_builder,
+            };
+            #[cfg(target_os = "linux")]
+            let dispatcher_builder = match dispatcher_cpu {
+                Some(cpu) => dispatcher_builder.cpu_affinity(cpu, numa_local_memory),
+                None => dispatcher_builder,
+            };
             #[cfg(target_os = "linux")]
             let dispatcher = dispatcher_builder
                 .platform_poller(platform_synchronizer.clone_poller())
+                .drop_stats(synchronizer.drop_stats())
                 .build()
                 .unwrap();
             #[cfg(target_os = "windows")]
@@ -1039,6 +1619,10 @@ impl Components {
 
             dispatchers.push(dispatcher);
             dispatcher_listeners.push(dispatcher_listener);
+            #[cfg(target_os = "linux")]
+            if dispatcher_cpu.is_some() && numa_local_memory {
+                let _ = numa::set_preferred_node(None);
+            }
 
             // create and start collector
             let collector = Self::new_collector(
@@ -1047,27 +1631,39 @@ impl Components {
                 flow_receiver,
                 l4_flow_aggr_sender.clone(),
                 metrics_sender.clone(),
-                MetricsType::SECOND | MetricsType::MINUTE,
+                if pipeline_mode.metrics_enabled() {
+                    MetricsType::SECOND | MetricsType::MINUTE
+                } else {
+                    MetricsType::empty()
+                },
                 config_handler,
                 &queue_debugger,
                 &synchronizer,
+                &exception_handler,
+                security_event.clone(),
             );
             collectors.push(collector);
         }
 
         #[cfg(target_os = "linux")]
-        let ebpf_collector = EbpfCollector::new(
-            synchronizer.ntp_diff(),
-            &config_handler.candidate_config.ebpf,
-            config_handler.log_parser(),
-            policy_getter,
-            l7_log_rate.clone(),
-            proto_log_sender,
-            &queue_debugger,
-        )
-        .ok();
+        let mut ebpf_collector = proto_log_sender.and_then(|proto_log_sender| {
+            EbpfCollector::new(
+                synchronizer.ntp_diff(),
+                &config_handler.candidate_config.ebpf,
+                config_handler.log_parser(),
+                policy_getter,
+                l7_log_rate.clone(),
+                proto_log_sender,
+                metrics_sender.clone(),
+                &queue_debugger,
+                l7_log_debugger,
+                exception_handler.clone(),
+            )
+            .ok()
+        });
         #[cfg(target_os = "linux")]
-        if let Some(collector) = &ebpf_collector {
+        if let Some(collector) = ebpf_collector.as_mut() {
+            collector.set_drop_stats(synchronizer.drop_stats());
             stats_collector.register_countable(
                 "ebpf-collector",
                 Countable::Owned(Box::new(collector.get_sync_counter())),
@@ -1091,12 +1687,15 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
+        set_queue_batching(&otel_receiver, "otel-to-sender", yaml_config);
         let otel_uniform_sender = UniformSenderThread::new(
             sender_id,
             Arc::new(otel_receiver),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
 
         let sender_id = 4;
@@ -1113,12 +1712,15 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
+        set_queue_batching(&prometheus_receiver, "prometheus-to-sender", yaml_config);
         let prometheus_uniform_sender = UniformSenderThread::new(
             sender_id,
             Arc::new(prometheus_receiver),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
 
         let sender_id = 5;
@@ -1135,12 +1737,15 @@ impl Components {
                 StatsOption::Tag("index", sender_id.to_string()),
             ],
         );
+        set_queue_batching(&telegraf_receiver, "telegraf-to-sender", yaml_config);
         let telegraf_uniform_sender = UniformSenderThread::new(
             sender_id,
             Arc::new(telegraf_receiver),
             config_handler.sender(),
             stats_collector.clone(),
             exception_handler.clone(),
+            None,
+            None,
         );
 
         let external_metrics_server = MetricServer::new(
@@ -1164,6 +1769,15 @@ impl Components {
             config_handler.port(),
         );
 
+        #[cfg(target_os = "linux")]
+        let profiler = Profiler::new(&yaml_config.profiler);
+
+        #[cfg(target_os = "linux")]
+        let tap_interface_watcher = TapInterfaceWatcher::new(
+            dispatcher_listeners.clone(),
+            platform_synchronizer.clone_poller(),
+        );
+
         Ok(Components {
             config: candidate_config.clone(),
             rx_leaky_bucket,
@@ -1176,6 +1790,8 @@ impl Components {
             l4_flow_uniform_sender,
             metrics_uniform_sender,
             l7_flow_uniform_sender,
+            security_event_uniform_sender,
+            business_labeler,
             #[cfg(target_os = "linux")]
             platform_synchronizer,
             #[cfg(target_os = "linux")]
@@ -1199,6 +1815,14 @@ impl Components {
             packet_sequence_uniform_sender, // Enterprise Edition Feature: packet-sequence
             packet_sequence_parsers,        // Enterprise Edition Feature: packet-sequence
             domain_name_listener,
+            #[cfg(target_os = "linux")]
+            profiler,
+            #[cfg(target_os = "linux")]
+            tap_interface_watcher,
+            #[cfg(target_os = "linux")]
+            numa_stats,
+            #[cfg(target_os = "windows")]
+            process_socket_table,
         })
     }
 
@@ -1212,40 +1836,53 @@ impl Components {
         config_handler: &ConfigHandler,
         queue_debugger: &QueueDebugger,
         synchronizer: &Arc<Synchronizer>,
+        exception_handler: &ExceptionHandler,
+        security_event: Arc<SecurityEventDetector>,
     ) -> CollectorThread {
         let yaml_config = &config_handler.candidate_config.yaml_config;
-        let (second_sender, second_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-second-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag(
-                    "module",
-                    "2-flow-with-meter-to-second-collector".to_string(),
-                ),
-                StatsOption::Tag("index", id.to_string()),
-            ],
-        );
-        let (minute_sender, minute_receiver, counter) = queue::bounded_with_debug(
-            yaml_config.quadruple_queue_size,
-            "2-flow-with-meter-to-minute-collector",
-            queue_debugger,
-        );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag(
-                    "module",
-                    "2-flow-with-meter-to-minute-collector".to_string(),
-                ),
-                StatsOption::Tag("index", id.to_string()),
-            ],
-        );
+        // 不生成的指标粒度不创建队列
+        let (mut second_sender, mut second_receiver) = (None, None);
+        if metrics_type.contains(MetricsType::SECOND) {
+            let (sender, receiver, counter) = queue::bounded_with_debug(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-second-collector",
+                queue_debugger,
+            );
+            stats_collector.register_countable(
+                "queue",
+                Countable::Owned(Box::new(counter)),
+                vec![
+                    StatsOption::Tag(
+                        "module",
+                        "2-flow-with-meter-to-second-collector".to_string(),
+                    ),
+                    StatsOption::Tag("index", id.to_string()),
+                ],
+            );
+            second_sender = Some(sender);
+            second_receiver = Some(receiver);
+        }
+        let (mut minute_sender, mut minute_receiver) = (None, None);
+        if metrics_type.contains(MetricsType::MINUTE) {
+            let (sender, receiver, counter) = queue::bounded_with_debug(
+                yaml_config.quadruple_queue_size,
+                "2-flow-with-meter-to-minute-collector",
+                queue_debugger,
+            );
+            stats_collector.register_countable(
+                "queue",
+                Countable::Owned(Box::new(counter)),
+                vec![
+                    StatsOption::Tag(
+                        "module",
+                        "2-flow-with-meter-to-minute-collector".to_string(),
+                    ),
+                    StatsOption::Tag("index", id.to_string()),
+                ],
+            );
+            minute_sender = Some(sender);
+            minute_receiver = Some(receiver);
+        }
 
         let (l4_log_sender, l4_log_receiver, counter) = queue::bounded_with_debug(
             yaml_config.flow.aggr_queue_size as usize,
@@ -1289,8 +1926,9 @@ impl Components {
             minute_quadruple_tolerable_delay,
             1 << 18, // possible_host_size
             config_handler.collector(),
-            synchronizer.ntp_diff(),
+            synchronizer.time_source(),
             stats_collector.clone(),
+            security_event,
         );
 
         let l4_flow_aggr = FlowAggrThread::new(
@@ -1301,7 +1939,7 @@ impl Components {
         );
 
         let (mut second_collector, mut minute_collector) = (None, None);
-        if metrics_type.contains(MetricsType::SECOND) {
+        if let Some(second_receiver) = second_receiver {
             second_collector = Some(Collector::new(
                 id as u32,
                 second_receiver,
@@ -1310,10 +1948,11 @@ impl Components {
                 second_quadruple_tolerable_delay as u32,
                 &stats_collector,
                 config_handler.collector(),
-                synchronizer.ntp_diff(),
+                synchronizer.time_source(),
+                exception_handler.clone(),
             ));
         }
-        if metrics_type.contains(MetricsType::MINUTE) {
+        if let Some(minute_receiver) = minute_receiver {
             minute_collector = Some(Collector::new(
                 id as u32,
                 minute_receiver,
@@ -1322,7 +1961,8 @@ impl Components {
                 minute_quadruple_tolerable_delay as u32,
                 &stats_collector,
                 config_handler.collector(),
-                synchronizer.ntp_diff(),
+                synchronizer.time_source(),
+                exception_handler.clone(),
             ));
         }
 
@@ -1359,8 +1999,16 @@ impl Components {
         }
 
         self.l4_flow_uniform_sender.stop();
-        self.metrics_uniform_sender.stop();
-        self.l7_flow_uniform_sender.stop();
+        if let Some(metrics_uniform_sender) = self.metrics_uniform_sender.as_mut() {
+            metrics_uniform_sender.stop();
+        }
+        if let Some(l7_flow_uniform_sender) = self.l7_flow_uniform_sender.as_mut() {
+            l7_flow_uniform_sender.stop();
+        }
+        self.security_event_uniform_sender.stop();
+        if let Some(business_labeler) = self.business_labeler.as_ref() {
+            business_labeler.stop();
+        }
 
         self.libvirt_xml_extractor.stop();
         self.pcap_manager.stop();
@@ -1385,6 +2033,12 @@ impl Components {
         self.telegraf_uniform_sender.stop();
         self.packet_sequence_uniform_sender.stop(); // Enterprise Edition Feature: packet-sequence
         self.domain_name_listener.stop();
+        #[cfg(target_os = "linux")]
+        self.profiler.stop();
+        #[cfg(target_os = "linux")]
+        self.tap_interface_watcher.stop();
+        #[cfg(target_os = "windows")]
+        self.process_socket_table.stop();
 
         info!("Stopped components.")
     }
@@ -53,3 +53,71 @@ pub fn write_u64_be(bs: &mut [u8], v: u64) {
     assert!(bs.len() >= 8);
     bs[0..8].copy_from_slice(v.to_be_bytes().as_slice())
 }
+
+// 不超过max_len字节的最长前缀，不拆分多字节字符，结尾不完整的UTF-8序列（如payload被截断）一并去掉
+pub fn utf8_prefix(bs: &[u8], max_len: usize) -> &[u8] {
+    let bs = &bs[..bs.len().min(max_len)];
+    // 从结尾向前找最后一个字符的首字节，UTF-8字符最长4字节
+    for i in 1..=bs.len().min(4) {
+        let width = match bs[bs.len() - i] {
+            0x80..=0xBF => continue,
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return bs,
+        };
+        return if width > i { &bs[..bs.len() - i] } else { bs };
+    }
+    bs
+}
+
+// 按字节数截断字符串，不拆分多字节字符，max_len为0时不截断
+pub fn truncate_str(s: &mut String, max_len: usize) {
+    if max_len == 0 || s.len() <= max_len {
+        return;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+}
+
+// 按字节数截断UTF-8内容，不拆分多字节字符，max_len为0时只去掉结尾不完整的字符
+pub fn truncate_utf8(bs: &mut Vec<u8>, max_len: usize) {
+    let len = utf8_prefix(bs, if max_len == 0 { bs.len() } else { max_len }).len();
+    bs.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_boundary() {
+        let s = "a中文";
+        let bs = s.as_bytes();
+        assert_eq!(utf8_prefix(bs, 10), bs);
+        assert_eq!(utf8_prefix(bs, 4), b"a\xe4\xb8\xad");
+        assert_eq!(utf8_prefix(bs, 3), b"a");
+        assert_eq!(utf8_prefix(bs, 0), b"");
+        // 结尾被截断的字符
+        assert_eq!(utf8_prefix(&bs[..6], 10), b"a\xe4\xb8\xad");
+        // 非法字节保留，由后续的lossy转换替换
+        assert_eq!(utf8_prefix(b"a\x80", 10), b"a\x80");
+        assert_eq!(utf8_prefix(b"\xff", 10), b"\xff");
+
+        let mut s = s.to_owned();
+        truncate_str(&mut s, 0);
+        assert_eq!(s, "a中文");
+        truncate_str(&mut s, 6);
+        assert_eq!(s, "a中");
+
+        let mut bs = "a中文".as_bytes()[..5].to_vec();
+        truncate_utf8(&mut bs, 0);
+        assert_eq!(bs, "a中".as_bytes());
+        truncate_utf8(&mut bs, 2);
+        assert_eq!(bs, b"a");
+    }
+}
@@ -0,0 +1,272 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::info;
+
+use super::stats::{Counter, CounterType, CounterValue, OwnedCountable};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// 丢包率单位为万分之一
+const DROP_RATIO_SCALE: u64 = 10000;
+
+// 内核采集socket及eBPF的丢包来源，每秒被轮询一次，将增量记录到DropStats
+pub trait DropSource: Send + Sync {
+    fn poll(&self);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureDrops {
+    pub kernel_packets: u64,
+    pub kernel_drops: u64,
+    pub ebpf_events: u64,
+    pub ebpf_lost: u64,
+}
+
+impl CaptureDrops {
+    // 返回万分之几，无数据时为0
+    pub fn drop_ratio(&self) -> u32 {
+        let total = self.kernel_packets + self.ebpf_events;
+        if total == 0 {
+            return 0;
+        }
+        ((self.kernel_drops + self.ebpf_lost) * DROP_RATIO_SCALE / total) as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kernel_packets == 0 && self.ebpf_events == 0
+    }
+}
+
+#[derive(Default)]
+struct DropCounter {
+    kernel_packets: AtomicU64,
+    kernel_drops: AtomicU64,
+    ebpf_events: AtomicU64,
+    ebpf_lost: AtomicU64,
+}
+
+impl DropCounter {
+    fn add_kernel(&self, packets: u64, drops: u64) {
+        self.kernel_packets.fetch_add(packets, Ordering::Relaxed);
+        self.kernel_drops.fetch_add(drops, Ordering::Relaxed);
+    }
+
+    fn add_ebpf(&self, events: u64, lost: u64) {
+        self.ebpf_events.fetch_add(events, Ordering::Relaxed);
+        self.ebpf_lost.fetch_add(lost, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> CaptureDrops {
+        CaptureDrops {
+            kernel_packets: self.kernel_packets.swap(0, Ordering::Relaxed),
+            kernel_drops: self.kernel_drops.swap(0, Ordering::Relaxed),
+            ebpf_events: self.ebpf_events.swap(0, Ordering::Relaxed),
+            ebpf_lost: self.ebpf_lost.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    sources: Mutex<Vec<Weak<dyn DropSource>>>,
+    // stats和同步请求各自取走增量，互不影响
+    stats: DropCounter,
+    sync: DropCounter,
+
+    thread: Mutex<Option<JoinHandle<()>>>,
+    running: (Mutex<bool>, Condvar),
+}
+
+// 汇总所有dispatcher及eBPF的丢包统计，通过stats和同步请求上报
+#[derive(Clone, Default)]
+pub struct DropStats(Arc<Inner>);
+
+impl DropStats {
+    pub fn register(&self, source: Weak<dyn DropSource>) {
+        self.0.sources.lock().unwrap().push(source);
+    }
+
+    pub fn record_kernel(&self, packets: u64, drops: u64) {
+        self.0.stats.add_kernel(packets, drops);
+        self.0.sync.add_kernel(packets, drops);
+    }
+
+    pub fn record_ebpf(&self, events: u64, lost: u64) {
+        self.0.stats.add_ebpf(events, lost);
+        self.0.sync.add_ebpf(events, lost);
+    }
+
+    // 已释放的来源（如重建的dispatcher）在轮询时移除
+    pub fn poll(&self) {
+        let sources = {
+            let mut sources = self.0.sources.lock().unwrap();
+            sources.retain(|s| s.strong_count() > 0);
+            sources
+                .iter()
+                .filter_map(|s| s.upgrade())
+                .collect::<Vec<_>>()
+        };
+        for source in sources {
+            source.poll();
+        }
+    }
+
+    // 返回上次同步以来的丢包统计
+    pub fn take_sync(&self) -> CaptureDrops {
+        self.0.sync.take()
+    }
+
+    pub fn start(&self) {
+        {
+            let (started, _) = &self.0.running;
+            let mut started = started.lock().unwrap();
+            if *started {
+                return;
+            }
+            *started = true;
+        }
+
+        let stats = self.clone();
+        let thread = thread::spawn(move || {
+            let (running, timer) = &stats.0.running;
+            loop {
+                stats.poll();
+
+                let mut running = running.lock().unwrap();
+                if !*running {
+                    break;
+                }
+                running = timer.wait_timeout(running, POLL_INTERVAL).unwrap().0;
+                if !*running {
+                    break;
+                }
+            }
+            info!("drop stats exited");
+        });
+
+        self.0.thread.lock().unwrap().replace(thread);
+        info!("drop stats started");
+    }
+
+    pub fn stop(&self) {
+        let (stopped, timer) = &self.0.running;
+        {
+            let mut stopped = stopped.lock().unwrap();
+            if !*stopped {
+                return;
+            }
+            *stopped = false;
+        }
+        timer.notify_one();
+
+        if let Some(thread) = self.0.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl OwnedCountable for DropStats {
+    fn get_counters(&self) -> Vec<Counter> {
+        let drops = self.0.stats.take();
+        vec![
+            (
+                "kernel_packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(drops.kernel_packets),
+            ),
+            (
+                "kernel_drops",
+                CounterType::Counted,
+                CounterValue::Unsigned(drops.kernel_drops),
+            ),
+            (
+                "ebpf_events",
+                CounterType::Counted,
+                CounterValue::Unsigned(drops.ebpf_events),
+            ),
+            (
+                "ebpf_lost",
+                CounterType::Counted,
+                CounterValue::Unsigned(drops.ebpf_lost),
+            ),
+            (
+                "drop_ratio",
+                CounterType::Gauged,
+                CounterValue::Unsigned(drops.drop_ratio() as u64),
+            ),
+        ]
+    }
+
+    fn closed(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeSource {
+        stats: DropStats,
+    }
+
+    impl DropSource for FakeSource {
+        fn poll(&self) {
+            self.stats.record_kernel(100, 2);
+        }
+    }
+
+    #[test]
+    fn poll_and_take() {
+        let stats = DropStats::default();
+        let source: Arc<dyn DropSource> = Arc::new(FakeSource {
+            stats: stats.clone(),
+        });
+        stats.register(Arc::downgrade(&source));
+        stats.poll();
+        stats.record_ebpf(100, 8);
+
+        let drops = stats.take_sync();
+        assert_eq!(
+            drops,
+            CaptureDrops {
+                kernel_packets: 100,
+                kernel_drops: 2,
+                ebpf_events: 100,
+                ebpf_lost: 8,
+            }
+        );
+        assert_eq!(drops.drop_ratio(), 500);
+        assert!(stats.take_sync().is_empty());
+        // stats的增量独立于同步请求
+        assert_eq!(stats.get_counters()[1].2, CounterValue::Unsigned(2));
+
+        // 来源释放后不再轮询
+        drop(source);
+        stats.poll();
+        assert!(stats.take_sync().is_empty());
+        assert!(stats.0.sources.lock().unwrap().is_empty());
+    }
+}
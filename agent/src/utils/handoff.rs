@@ -0,0 +1,396 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 热升级时保留af_packet采集socket：旧进程先停止dispatcher收包并导出流表，
+// 再清除socket的FD_CLOEXEC后原地exec新版本，PID不变（systemd及fanout组id不受影响），
+// exec期间内核继续向ring中写包，新进程通过环境变量取得socket及ring的读取位置，
+// 重新mmap后继续收包，并从交接文件中恢复流表中的流
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::net::IpAddr;
+use std::os::unix::{io::RawFd, process::CommandExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, Condvar, Mutex, Weak,
+};
+use std::time::{Duration, Instant};
+
+use libc::{c_int, close, fcntl, FD_CLOEXEC, F_GETFD, F_SETFD};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::flow_generator::FlowState;
+
+pub const HANDOFF_ENV: &str = "DEEPFLOW_AGENT_HANDOFF";
+pub const HANDOFF_FLOWS_ENV: &str = "DEEPFLOW_AGENT_HANDOFF_FLOWS";
+
+// dispatcher的收包循环间隔远小于该值
+const PARK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 新进程启动时未被dispatcher认领的socket在close_unclaimed中关闭
+static INHERITED: Mutex<Vec<CaptureSocket>> = Mutex::new(Vec::new());
+static INHERITED_FLOWS: Mutex<Vec<FlowHandoff>> = Mutex::new(Vec::new());
+static REGISTERED: Mutex<Vec<Weak<CaptureSocket>>> = Mutex::new(Vec::new());
+// 收包循环中会检查requested并调用park的dispatcher，exec时只等待这些dispatcher
+static PARKABLE: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+// exec前要求dispatcher停止收包，停止后的dispatcher在PARKED中登记id并提交流表
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static PARKED: (Mutex<Parked>, Condvar) = (
+    Mutex::new(Parked {
+        ids: Vec::new(),
+        flows: Vec::new(),
+    }),
+    Condvar::new(),
+);
+
+struct Parked {
+    ids: Vec<usize>,
+    flows: Vec<FlowHandoff>,
+}
+
+// 流表中一条流交接给新进程的状态，新进程中同一条流沿用flow_id、开始时间及累计统计量
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlowHandoff {
+    pub dispatcher_id: u32,
+    pub tap_port: u64,
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub port_src: u16,
+    pub port_dst: u16,
+    pub proto: u8,
+    pub flow_id: u64,
+    pub flow_uuid: u128,
+    pub start_time: Duration,
+    pub flow_state: FlowState,
+    // 按src、dst方向
+    pub total_packet_count: [u64; 2],
+    pub total_byte_count: [u64; 2],
+}
+
+// 采集socket及其ring的参数，参数与新配置不一致时不能复用
+#[derive(Debug, Default)]
+pub struct CaptureSocket {
+    pub id: usize,
+    pub iface: String,
+    pub fd: RawFd,
+    pub version: c_int,
+    pub frame_size: u32,
+    pub block_size: u32,
+    pub num_blocks: u32,
+    pub fanout: c_int,
+    // 正在读取的frame或block序号，由收包线程更新
+    pub offset: AtomicU32,
+}
+
+impl CaptureSocket {
+    fn encode(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            self.id,
+            self.iface,
+            self.fd,
+            self.version,
+            self.frame_size,
+            self.block_size,
+            self.num_blocks,
+            self.fanout,
+            self.offset.load(Ordering::Relaxed)
+        )
+    }
+
+    // 网卡名中不会出现':'
+    fn decode(s: &str) -> Option<Self> {
+        let fields = s.split(':').collect::<Vec<_>>();
+        if fields.len() != 9 {
+            return None;
+        }
+        Some(Self {
+            id: fields[0].parse().ok()?,
+            iface: fields[1].to_owned(),
+            fd: fields[2].parse().ok()?,
+            version: fields[3].parse().ok()?,
+            frame_size: fields[4].parse().ok()?,
+            block_size: fields[5].parse().ok()?,
+            num_blocks: fields[6].parse().ok()?,
+            fanout: fields[7].parse().ok()?,
+            offset: AtomicU32::new(fields[8].parse().ok()?),
+        })
+    }
+}
+
+fn decode_all(value: &str) -> Vec<CaptureSocket> {
+    value
+        .split(';')
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| {
+            let socket = CaptureSocket::decode(s);
+            if socket.is_none() {
+                warn!("ignore invalid handoff socket {}", s);
+            }
+            socket
+        })
+        .collect()
+}
+
+fn save_flows(flows: &[FlowHandoff]) -> io::Result<PathBuf> {
+    let path = env::temp_dir().join(format!("deepflow-agent-handoff-{}.json", process::id()));
+    let writer = BufWriter::new(File::create(&path)?);
+    serde_json::to_writer(writer, flows)?;
+    Ok(path)
+}
+
+fn load_flows(path: &Path) -> io::Result<Vec<FlowHandoff>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+// 启动时调用，读取旧进程交接的socket及流表并清除环境变量，避免传递给子进程
+pub fn load_inherited() {
+    if let Ok(path) = env::var(HANDOFF_FLOWS_ENV) {
+        env::remove_var(HANDOFF_FLOWS_ENV);
+        match load_flows(Path::new(&path)) {
+            Ok(flows) => {
+                info!("inherited {} flows from previous process", flows.len());
+                *INHERITED_FLOWS.lock().unwrap() = flows;
+            }
+            Err(e) => warn!("load handoff flows from {} failed: {}", path, e),
+        }
+        let _ = fs::remove_file(&path);
+    }
+    let value = match env::var(HANDOFF_ENV) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    env::remove_var(HANDOFF_ENV);
+    let sockets = decode_all(&value);
+    info!(
+        "inherited {} capture sockets from previous process",
+        sockets.len()
+    );
+    *INHERITED.lock().unwrap() = sockets;
+}
+
+pub fn take_inherited_flows(dispatcher_id: u32) -> Vec<FlowHandoff> {
+    let mut inherited = INHERITED_FLOWS.lock().unwrap();
+    let (taken, rest) = inherited
+        .drain(..)
+        .partition(|f| f.dispatcher_id == dispatcher_id);
+    *inherited = rest;
+    taken
+}
+
+pub fn take_inherited(id: usize, iface: &str) -> Option<CaptureSocket> {
+    let mut inherited = INHERITED.lock().unwrap();
+    let index = inherited
+        .iter()
+        .position(|s| s.id == id && s.iface == iface)?;
+    Some(inherited.swap_remove(index))
+}
+
+pub fn close_unclaimed() {
+    for socket in INHERITED.lock().unwrap().drain(..) {
+        info!(
+            "close unclaimed handoff socket {} of interface {:?}",
+            socket.fd, socket.iface
+        );
+        unsafe {
+            close(socket.fd);
+        }
+    }
+}
+
+pub fn register(socket: Weak<CaptureSocket>) {
+    let mut registered = REGISTERED.lock().unwrap();
+    registered.retain(|s| s.strong_count() > 0);
+    registered.push(socket);
+}
+
+// dispatcher进入收包循环前调用，表示会响应requested
+pub fn parkable(id: usize) {
+    let mut parkable = PARKABLE.lock().unwrap();
+    if !parkable.contains(&id) {
+        parkable.push(id);
+    }
+}
+
+// dispatcher在收包循环中检查，为true时调用park后退出
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+// dispatcher停止收包后调用，此后ring的读取位置不再变化
+pub fn park(id: usize, flows: Vec<FlowHandoff>) {
+    let (parked, cvar) = &PARKED;
+    let mut parked = parked.lock().unwrap();
+    parked.ids.push(id);
+    parked.flows.extend(flows);
+    cvar.notify_all();
+}
+
+// 等待各socket所属的dispatcher停止，返回已停止的dispatcher
+fn wait_parked(ids: &HashSet<usize>, timeout: Duration) -> (HashSet<usize>, Vec<FlowHandoff>) {
+    let (parked, cvar) = &PARKED;
+    let deadline = Instant::now() + timeout;
+    let mut guard = parked.lock().unwrap();
+    while !ids.iter().all(|id| guard.ids.contains(id)) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        guard = cvar.wait_timeout(guard, deadline - now).unwrap().0;
+    }
+    (
+        guard.ids.iter().copied().collect(),
+        std::mem::take(&mut guard.flows),
+    )
+}
+
+// 成功时不返回
+pub fn exec<P: AsRef<Path>>(binary: P) -> io::Error {
+    let sockets = REGISTERED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect::<Vec<Arc<CaptureSocket>>>();
+    // 先停止dispatcher，否则读取位置在exec前仍可能前进，新进程会重复处理部分包
+    REQUESTED.store(true, Ordering::Relaxed);
+    let parkable = PARKABLE.lock().unwrap().clone();
+    let (parked, flows) = wait_parked(
+        &sockets
+            .iter()
+            .map(|s| s.id)
+            .filter(|id| parkable.contains(id))
+            .collect(),
+        PARK_TIMEOUT,
+    );
+    let mut handoff = vec![];
+    for socket in sockets.iter() {
+        if !parkable.contains(&socket.id) {
+            warn!(
+                "dispatcher {} does not support handoff, socket {} is not handed off",
+                socket.id, socket.fd
+            );
+            continue;
+        }
+        if !parked.contains(&socket.id) {
+            warn!(
+                "dispatcher {} not stopped in {:?}, socket {} is not handed off",
+                socket.id, PARK_TIMEOUT, socket.fd
+            );
+            continue;
+        }
+        unsafe {
+            let flags = fcntl(socket.fd, F_GETFD);
+            if flags < 0 || fcntl(socket.fd, F_SETFD, flags & !FD_CLOEXEC) < 0 {
+                warn!(
+                    "keep socket {} across exec failed: {}",
+                    socket.fd,
+                    io::Error::last_os_error()
+                );
+                continue;
+            }
+        }
+        handoff.push(socket.encode());
+    }
+    let mut command = Command::new(binary.as_ref());
+    command
+        .args(env::args_os().skip(1))
+        .env(HANDOFF_ENV, handoff.join(";"));
+    match save_flows(&flows) {
+        Ok(path) => {
+            command.env(HANDOFF_FLOWS_ENV, path);
+        }
+        Err(e) => warn!("save {} handoff flows failed: {}", flows.len(), e),
+    }
+    info!(
+        "exec {} with {} capture sockets and {} flows handed off",
+        binary.as_ref().display(),
+        handoff.len(),
+        flows.len()
+    );
+    command.exec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let socket = CaptureSocket {
+            id: 1,
+            iface: "eth0".into(),
+            fd: 12,
+            version: 2,
+            frame_size: 65536,
+            block_size: 1048576,
+            num_blocks: 128,
+            fanout: 0x80001234u32 as c_int,
+            offset: AtomicU32::new(17),
+        };
+        let local = CaptureSocket {
+            fd: 13,
+            ..Default::default()
+        };
+        let value = format!("{};{};bad", socket.encode(), local.encode());
+        let sockets = decode_all(&value);
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[0].encode(), socket.encode());
+        assert_eq!(sockets[1].iface, "");
+        assert_eq!(sockets[1].fd, 13);
+    }
+
+    #[test]
+    fn save_and_load_flows() {
+        let flow = FlowHandoff {
+            dispatcher_id: 1,
+            tap_port: 0x10000001,
+            ip_src: "10.0.0.1".parse().unwrap(),
+            ip_dst: "fe80::1".parse().unwrap(),
+            port_src: 12345,
+            port_dst: 80,
+            proto: 6,
+            flow_id: u64::MAX,
+            flow_uuid: u128::MAX,
+            start_time: Duration::from_nanos(1_650_000_000_123_456_789),
+            flow_state: FlowState::Established,
+            total_packet_count: [3, 2],
+            total_byte_count: [300, 200],
+        };
+        let path = save_flows(&[flow.clone()]).unwrap();
+        let flows = load_flows(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(flows.unwrap(), vec![flow.clone()]);
+
+        *INHERITED_FLOWS.lock().unwrap() = vec![
+            flow.clone(),
+            FlowHandoff {
+                dispatcher_id: 2,
+                ..flow
+            },
+        ];
+        assert_eq!(take_inherited_flows(2).len(), 1);
+        assert!(take_inherited_flows(2).is_empty());
+        assert_eq!(take_inherited_flows(1).len(), 1);
+    }
+}
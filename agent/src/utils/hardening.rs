@@ -0,0 +1,648 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libc::{c_int, c_long, sock_filter, sock_fprog};
+use log::{info, warn};
+
+use crate::config::{HardeningConfig, SeccompMode};
+
+const CAPABILITIES: [&str; 41] = [
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+const CAP_LAST_CAP_PATH: &str = "/proc/sys/kernel/cap_last_cap";
+const TASK_PATH: &str = "/proc/self/task";
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+const SIGNAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 供信号处理函数读取，处理函数中不能分配内存或读文件
+static SIGNAL_KEEP: AtomicU64 = AtomicU64::new(0);
+static SIGNAL_LAST_CAP: AtomicU32 = AtomicU32::new(0);
+static SIGNAL_DONE: AtomicUsize = AtomicUsize::new(0);
+static SIGNAL_FAILED: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+const SECCOMP_SET_MODE_FILTER: c_long = 1;
+const SECCOMP_FILTER_FLAG_TSYNC: c_long = 1;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc0000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+// struct seccomp_data中nr和arch的偏移
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+
+// clone3在各架构上的调用号相同，glibc仅在ENOSYS时回退到clone
+const SYS_CLONE3: c_long = 435;
+
+const ALLOWED_SYSCALLS: &[c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_lseek,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_fchown,
+    libc::SYS_utimensat,
+    libc::SYS_truncate,
+    libc::SYS_ftruncate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_flock,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_getcwd,
+    libc::SYS_chdir,
+    libc::SYS_fchdir,
+    libc::SYS_umask,
+    libc::SYS_mmap,
+    libc::SYS_mprotect,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_msync,
+    libc::SYS_mincore,
+    libc::SYS_madvise,
+    libc::SYS_mlock,
+    libc::SYS_munlock,
+    libc::SYS_mlockall,
+    libc::SYS_brk,
+    libc::SYS_memfd_create,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_signalfd4,
+    libc::SYS_kill,
+    libc::SYS_tkill,
+    libc::SYS_tgkill,
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_shutdown,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvmmsg,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockopt,
+    libc::SYS_sendfile,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_timerfd_gettime,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+    libc::SYS_clone,
+    SYS_CLONE3,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_wait4,
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_get_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_restart_syscall,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_setaffinity,
+    libc::SYS_set_mempolicy,
+    libc::SYS_sched_getparam,
+    libc::SYS_sched_getscheduler,
+    libc::SYS_sched_setscheduler,
+    libc::SYS_getpriority,
+    libc::SYS_setpriority,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_getitimer,
+    libc::SYS_setitimer,
+    libc::SYS_times,
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getpgid,
+    libc::SYS_setpgid,
+    libc::SYS_getsid,
+    libc::SYS_setsid,
+    libc::SYS_getrlimit,
+    libc::SYS_setrlimit,
+    libc::SYS_prlimit64,
+    libc::SYS_getrusage,
+    libc::SYS_sysinfo,
+    libc::SYS_uname,
+    libc::SYS_prctl,
+    libc::SYS_capget,
+    libc::SYS_capset,
+    libc::SYS_getrandom,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_process_vm_readv,
+    libc::SYS_statx,
+];
+
+#[cfg(target_arch = "x86_64")]
+const ARCH_ALLOWED_SYSCALLS: &[c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_readlink,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_mkdir,
+    libc::SYS_rmdir,
+    libc::SYS_chmod,
+    libc::SYS_getdents,
+    libc::SYS_dup2,
+    libc::SYS_pipe,
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_epoll_create,
+    libc::SYS_epoll_wait,
+    libc::SYS_eventfd,
+    libc::SYS_fork,
+    libc::SYS_vfork,
+    libc::SYS_alarm,
+    libc::SYS_time,
+    libc::SYS_arch_prctl,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ARCH_ALLOWED_SYSCALLS: &[c_long] = &[];
+
+fn parse_capability(name: &str) -> Option<u32> {
+    let name = name.trim().to_ascii_uppercase();
+    let name = name.strip_prefix("CAP_").unwrap_or(&name);
+    CAPABILITIES
+        .iter()
+        .position(|c| *c == name)
+        .map(|i| i as u32)
+}
+
+fn cap_last_cap() -> u32 {
+    fs::read_to_string(CAP_LAST_CAP_PATH)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(CAPABILITIES.len() as u32 - 1)
+}
+
+// 只影响当前线程及之后由其创建的线程，其他线程通过drop_other_threads收缩
+fn drop_capabilities(keep: u64, last_cap: u32) -> io::Result<()> {
+    // 先收缩bounding set，该操作依赖CAP_SETPCAP
+    for cap in 0..=last_cap.min(63) {
+        if keep & (1 << cap) != 0 {
+            continue;
+        }
+        if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0) } != 0 {
+            let e = io::Error::last_os_error();
+            // 内核不认识的capability
+            if e.raw_os_error() != Some(libc::EINVAL) {
+                return Err(e);
+            }
+        }
+    }
+    unsafe {
+        libc::prctl(
+            libc::PR_CAP_AMBIENT,
+            libc::PR_CAP_AMBIENT_CLEAR_ALL as libc::c_ulong,
+            0,
+            0,
+            0,
+        );
+    }
+
+    let mut header = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for (i, d) in data.iter_mut().enumerate() {
+        let mask = (keep >> (32 * i)) as u32;
+        d.effective &= mask;
+        d.permitted &= mask;
+        d.inheritable &= mask;
+    }
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+extern "C" fn on_drop_signal(_: c_int) {
+    let errno = unsafe { *libc::__errno_location() };
+    let keep = SIGNAL_KEEP.load(Ordering::SeqCst);
+    if drop_capabilities(keep, SIGNAL_LAST_CAP.load(Ordering::SeqCst)).is_err() {
+        SIGNAL_FAILED.fetch_add(1, Ordering::SeqCst);
+    }
+    SIGNAL_DONE.fetch_add(1, Ordering::SeqCst);
+    unsafe {
+        *libc::__errno_location() = errno;
+    }
+}
+
+fn gettid() -> i32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+}
+
+fn thread_ids() -> io::Result<HashSet<i32>> {
+    Ok(fs::read_dir(TASK_PATH)?
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .collect())
+}
+
+// capset只作用于调用线程，而采集socket和eBPF创建完成时进程内已有多个工作线程，
+// 与glibc实现setuid相同，向其他线程发送信号使其在处理函数中各自收缩
+fn drop_other_threads(keep: u64, last_cap: u32) -> io::Result<()> {
+    SIGNAL_KEEP.store(keep, Ordering::SeqCst);
+    SIGNAL_LAST_CAP.store(last_cap, Ordering::SeqCst);
+    SIGNAL_DONE.store(0, Ordering::SeqCst);
+    SIGNAL_FAILED.store(0, Ordering::SeqCst);
+
+    let signal = libc::SIGRTMIN();
+    let mut old: libc::sigaction = unsafe { mem::zeroed() };
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_drop_signal as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        if libc::sigaction(signal, &action, &mut old) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let pid = unsafe { libc::getpid() };
+    let mut signaled = HashSet::from([gettid()]);
+    let mut sent = 0;
+    let deadline = Instant::now() + SIGNAL_TIMEOUT;
+    let result = (|| {
+        // 收缩期间新建的线程可能继承了未收缩的capability，重复直到没有新线程
+        loop {
+            let threads = thread_ids()?;
+            let pending = threads
+                .into_iter()
+                .filter(|tid| !signaled.contains(tid))
+                .collect::<Vec<_>>();
+            if pending.is_empty() {
+                break;
+            }
+            for tid in pending {
+                // 线程可能已经退出
+                if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) } == 0 {
+                    sent += 1;
+                }
+                signaled.insert(tid);
+            }
+            while SIGNAL_DONE.load(Ordering::SeqCst) < sent {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "{} of {} threads not responding",
+                            sent - SIGNAL_DONE.load(Ordering::SeqCst),
+                            sent
+                        ),
+                    ));
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        match SIGNAL_FAILED.load(Ordering::SeqCst) {
+            0 => Ok(()),
+            n => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{} of {} threads failed", n, sent),
+            )),
+        }
+    })();
+    unsafe {
+        libc::sigaction(signal, &old, ptr::null_mut());
+    }
+    result
+}
+
+fn bpf_stmt(code: u16, k: u32) -> sock_filter {
+    sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn bpf_jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code, jt, jf, k }
+}
+
+fn build_filter(default_action: u32) -> Vec<sock_filter> {
+    let mut filter = vec![
+        bpf_stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+        bpf_jump(BPF_JMP_JEQ_K, AUDIT_ARCH, 1, 0),
+        bpf_stmt(BPF_RET_K, default_action),
+        bpf_stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+    ];
+    for nr in ALLOWED_SYSCALLS.iter().chain(ARCH_ALLOWED_SYSCALLS.iter()) {
+        filter.push(bpf_jump(BPF_JMP_JEQ_K, *nr as u32, 0, 1));
+        filter.push(bpf_stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+    }
+    filter.push(bpf_stmt(BPF_RET_K, default_action));
+    filter
+}
+
+// 使用TSYNC同步到进程内所有线程
+fn apply_seccomp(mode: SeccompMode) -> io::Result<()> {
+    let default_action = match mode {
+        SeccompMode::Disabled => return Ok(()),
+        SeccompMode::Log => SECCOMP_RET_LOG,
+        SeccompMode::Enforce => SECCOMP_RET_ERRNO | libc::EPERM as u32,
+    };
+    let mut filter = build_filter(default_action);
+    let prog = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            SECCOMP_FILTER_FLAG_TSYNC,
+            &prog as *const sock_fprog,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// 在采集socket和eBPF创建完成后调用，返回实际生效的配置，随同步请求上报给控制器
+pub fn apply(config: &HardeningConfig) -> String {
+    if !config.enabled {
+        return "disabled".to_owned();
+    }
+
+    let mut keep = 0u64;
+    let mut kept = vec![];
+    for name in config.keep_capabilities.iter() {
+        match parse_capability(name) {
+            Some(cap) => {
+                keep |= 1 << cap;
+                kept.push(CAPABILITIES[cap as usize]);
+            }
+            None => warn!("ignore unknown capability {}", name),
+        }
+    }
+    let last_cap = cap_last_cap();
+    let capabilities = match drop_capabilities(keep, last_cap) {
+        Ok(_) => match drop_other_threads(keep, last_cap) {
+            Ok(_) => kept.join(","),
+            Err(e) => {
+                warn!("drop capabilities of other threads failed: {}", e);
+                "partial".to_owned()
+            }
+        },
+        Err(e) => {
+            warn!("drop capabilities failed: {}", e);
+            "unchanged".to_owned()
+        }
+    };
+
+    let seccomp = match apply_seccomp(config.seccomp) {
+        Ok(_) => format!("{:?}", config.seccomp).to_lowercase(),
+        Err(e) => {
+            warn!("apply seccomp filter failed: {}", e);
+            "failed".to_owned()
+        }
+    };
+
+    let profile = format!("capabilities={};seccomp={}", capabilities, seccomp);
+    info!("hardening profile applied: {}", profile);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process::Command;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    const CHILD_ENV: &str = "DEEPFLOW_HARDENING_TEST_CHILD";
+
+    fn effective_capabilities(tid: i32) -> u64 {
+        let status = fs::read_to_string(format!("{}/{}/status", TASK_PATH, tid)).unwrap();
+        let line = status.lines().find(|l| l.starts_with("CapEff:")).unwrap();
+        u64::from_str_radix(line["CapEff:".len()..].trim(), 16).unwrap()
+    }
+
+    #[test]
+    fn parse_and_build() {
+        assert_eq!(parse_capability("net_raw"), Some(13));
+        assert_eq!(parse_capability("CAP_BPF"), Some(39));
+        assert_eq!(parse_capability("NET_FOO"), None);
+
+        let filter = build_filter(SECCOMP_RET_LOG);
+        let n = ALLOWED_SYSCALLS.len() + ARCH_ALLOWED_SYSCALLS.len();
+        assert_eq!(filter.len(), 4 + 2 * n + 1);
+        assert_eq!(filter[2].k, SECCOMP_RET_LOG);
+        assert_eq!(filter.last().unwrap().k, SECCOMP_RET_LOG);
+    }
+
+    // 以默认配置在子进程中执行，避免影响同一进程中的其他测试，需要root权限
+    #[test]
+    fn apply_default_profile() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let status = Command::new(env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "utils::hardening::tests::apply_default_profile_child",
+                "--nocapture",
+            ])
+            .env(CHILD_ENV, "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn apply_default_profile_child() {
+        if env::var_os(CHILD_ENV).is_none() {
+            return;
+        }
+        let original = effective_capabilities(gettid());
+        // 模拟组件启动后已存在的工作线程
+        let (tid_sender, tid_receiver) = mpsc::channel();
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let worker = thread::spawn(move || {
+            tid_sender.send(gettid()).unwrap();
+            let _ = stop_receiver.recv();
+        });
+        let worker_tid = tid_receiver.recv().unwrap();
+
+        let config = HardeningConfig {
+            enabled: true,
+            seccomp: SeccompMode::Enforce,
+            ..Default::default()
+        };
+        let keep = config
+            .keep_capabilities
+            .iter()
+            .map(|c| 1 << parse_capability(c).unwrap())
+            .fold(0u64, |a, b| a | b);
+        assert_eq!(
+            apply(&config),
+            "capabilities=NET_ADMIN,NET_RAW,SYS_ADMIN;seccomp=enforce"
+        );
+        assert_eq!(effective_capabilities(gettid()), original & keep);
+        assert_eq!(effective_capabilities(worker_tid), original & keep);
+
+        // 重建采集socket仍然可行
+        if original & (1 << parse_capability("NET_RAW").unwrap()) != 0 {
+            let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, 0) };
+            assert!(fd >= 0);
+            unsafe {
+                libc::close(fd);
+            }
+        }
+        // 白名单外的调用返回EPERM
+        assert!(Command::new("/bin/true").status().is_err());
+        assert_eq!(unsafe { libc::syscall(libc::SYS_bpf, 0, 0, 0) }, -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+        assert_eq!(
+            unsafe { libc::syscall(libc::SYS_perf_event_open, 0, 0, -1, -1, 0) },
+            -1
+        );
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EPERM));
+
+        stop_sender.send(()).unwrap();
+        worker.join().unwrap();
+    }
+}
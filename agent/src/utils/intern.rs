@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const POOL_CAPACITY: usize = 4096;
+// 过长的字符串很少重复，不放入缓存
+const MAX_INTERN_LEN: usize = 256;
+
+// 进程名、数据库版本、消息队列主题等字符串在日志中大量重复，
+// 共享同一个Arc<str>可以减少内存分配和拷贝
+pub struct StringPool {
+    strings: HashSet<Arc<str>>,
+    capacity: usize,
+}
+
+impl StringPool {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            strings: HashSet::new(),
+            capacity,
+        }
+    }
+
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(v) = self.strings.get(s) {
+            return v.clone();
+        }
+        if s.len() > MAX_INTERN_LEN {
+            return Arc::from(s);
+        }
+        if self.strings.len() >= self.capacity {
+            self.evict();
+        }
+        let v: Arc<str> = Arc::from(s);
+        self.strings.insert(v.clone());
+        v
+    }
+
+    // 优先淘汰只被缓存引用的字符串，仍然过多时清空
+    fn evict(&mut self) {
+        self.strings.retain(|s| Arc::strong_count(s) > 1);
+        if self.strings.len() >= self.capacity / 2 {
+            self.strings.clear();
+        }
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<StringPool> = RefCell::new(StringPool::with_capacity(POOL_CAPACITY));
+}
+
+// 每个线程使用独立的缓存，避免加锁
+pub fn intern(s: &str) -> Arc<str> {
+    POOL.with(|pool| pool.borrow_mut().intern(s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_and_evict() {
+        let mut pool = StringPool::with_capacity(4);
+        let a = pool.intern("mysqld");
+        let b = pool.intern("mysqld");
+        assert!(Arc::ptr_eq(&a, &b));
+
+        let long = "x".repeat(MAX_INTERN_LEN + 1);
+        assert!(!Arc::ptr_eq(&pool.intern(&long), &pool.intern(&long)));
+
+        for s in ["a", "b", "c"] {
+            pool.intern(s);
+        }
+        // 缓存已满，只保留仍在使用的字符串
+        let d = pool.intern("d");
+        assert_eq!(pool.strings.len(), 2);
+        assert!(Arc::ptr_eq(&a, &pool.intern("mysqld")));
+        assert!(Arc::ptr_eq(&d, &pool.intern("d")));
+
+        assert!(Arc::ptr_eq(&intern("nginx"), &intern("nginx")));
+    }
+}
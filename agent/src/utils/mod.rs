@@ -18,15 +18,27 @@ pub(crate) mod bytes;
 #[cfg(target_os = "linux")]
 pub(crate) mod cgroups;
 pub(crate) mod command;
+pub(crate) mod drop_stats;
 pub(crate) mod environment;
 pub(crate) mod guard;
+#[cfg(target_os = "linux")]
+pub(crate) mod handoff;
+#[cfg(target_os = "linux")]
+pub(crate) mod hardening;
 pub(crate) mod hasher;
+pub(crate) mod intern;
 pub(crate) mod leaky_bucket;
 pub(crate) mod logger;
 pub(crate) mod lru;
 pub(crate) mod net;
+#[cfg(target_os = "linux")]
+pub(crate) mod numa;
+pub(crate) mod packet_pool;
+pub(crate) mod pcapng;
 pub(crate) mod possible_host;
 pub(crate) mod process;
+#[cfg(target_os = "linux")]
+pub(crate) mod profiler;
 pub(crate) mod queue;
 pub(crate) mod stats;
 
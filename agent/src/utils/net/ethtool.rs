@@ -20,6 +20,7 @@ use log::warn;
 use nix::errno::Errno;
 use nix::libc::ioctl;
 use nix::sys::socket::{socket, AddressFamily, SockFlag, SockType};
+use nix::unistd::close;
 
 use super::error::{Error, Result};
 
@@ -28,6 +29,15 @@ use super::error::{Error, Result};
 const SIOCETHTOOL: u64 = 0x8946;
 #[cfg(target_env = "musl")]
 const SIOCETHTOOL: i32 = 0x8946;
+#[cfg(target_env = "gnu")]
+const SIOCSHWTSTAMP: u64 = 0x89b0;
+#[cfg(target_env = "musl")]
+const SIOCSHWTSTAMP: i32 = 0x89b0;
+
+// linux/net_tstamp.h
+const HWTSTAMP_TX_OFF: i32 = 0;
+const HWTSTAMP_FILTER_NONE: i32 = 0;
+const HWTSTAMP_FILTER_ALL: i32 = 1;
 
 // ethtool stats related constants.
 const ETH_GSTRING_LEN: usize = 32;
@@ -79,6 +89,14 @@ struct Gfeatures {
     pub blocks: [GetFeaturesBlock; MAX_FEATURE_BLOCKS],
 }
 
+#[derive(Debug, Default)]
+#[repr(C)]
+struct HwtstampConfig {
+    flags: i32,
+    tx_type: i32,
+    rx_filter: i32,
+}
+
 #[derive(Debug)]
 #[repr(C)]
 struct IfReq {
@@ -177,6 +195,54 @@ pub fn get_link_features(if_name: &str) -> Result<Vec<(String, usize)>> {
     Ok(names)
 }
 
+/// enables hardware timestamping of all received packets on the given interface name.
+pub fn enable_rx_hw_timestamp(if_name: &str) -> Result<()> {
+    if if_name.len() > IFNAMSIZ {
+        return Err(Error::Ethtool(format!(
+            "enable hardware timestamp failed, because interface({}) name length({}) > IFNAMSIZ({})",
+            if_name,
+            if_name.len(),
+            IFNAMSIZ
+        )));
+    }
+    let mut req_name = [0u8; IFNAMSIZ];
+    req_name
+        .get_mut(..if_name.len())
+        .unwrap()
+        .copy_from_slice(if_name.as_bytes());
+
+    let fd = socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::empty(),
+        None,
+    )?;
+
+    let mut config = HwtstampConfig {
+        tx_type: HWTSTAMP_TX_OFF,
+        rx_filter: HWTSTAMP_FILTER_ALL,
+        ..Default::default()
+    };
+    let mut ifr = IfReq {
+        ifr_name: req_name,
+        ifr_data: &mut config as *mut HwtstampConfig as usize,
+    };
+    let code = unsafe { ioctl(fd, SIOCSHWTSTAMP, &mut ifr) };
+    let errno = Errno::last();
+    let _ = close(fd);
+    if code != 0 {
+        return Err(Error::Errno(errno));
+    }
+    // 驱动可能将过滤条件放宽或收窄，不支持时返回HWTSTAMP_FILTER_NONE
+    if config.rx_filter == HWTSTAMP_FILTER_NONE {
+        return Err(Error::Ethtool(format!(
+            "interface({}) does not support hardware timestamp",
+            if_name
+        )));
+    }
+    Ok(())
+}
+
 /// retrieves features only state is "on" of the given interface name.
 pub fn get_link_enabled_features(if_name: &str) -> Result<HashSet<String>> {
     let name_pairs = get_link_features(if_name)?;
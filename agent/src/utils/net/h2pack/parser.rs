@@ -44,6 +44,8 @@ bitflags! {
 
 pub struct Parser<'a> {
     decoder: Decoder<'a>,
+    // 引用了无法解出的dynamic table项，解析结果缺少部分头部
+    incomplete: bool,
 }
 
 fn parse_int(buf: &[u8], prefix: u8) -> Result<(usize, usize), ParseError> {
@@ -94,9 +96,15 @@ impl Parser<'_> {
     pub fn new() -> Parser<'static> {
         Parser {
             decoder: Decoder::new(),
+            incomplete: false,
         }
     }
 
+    // 从连接中途开始解析时dynamic table状态未知，对应的头部被跳过
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
     fn parse_kv_pair(
         &mut self,
         buf: &[u8],
@@ -117,6 +125,7 @@ impl Parser<'_> {
             // 静态表index从1到61，共60项。如果index大于61, 意味着这是一个dynamic table的
             // index，我们无法解出index对应的value，应该跳过对应的字节继续解析。
             if index > STATIC_INDEX_MAX {
+                self.incomplete = true;
                 return Ok((None, val_len));
             }
         } else {
@@ -158,6 +167,7 @@ impl Parser<'_> {
                 Err(_) => Err(ParseError::InvalidHuffmanCode),
             }
         } else {
+            self.incomplete |= index > STATIC_INDEX_MAX;
             Ok((None, index_len))
         }
     }
@@ -254,11 +264,14 @@ mod tests {
         assert_eq!(b":method", r1[0].0.as_slice());
         assert_eq!(b"GET", r1[0].1.as_slice());
 
+        assert!(!parser.is_incomplete());
+
         let buffer2 = [0xbf]; // dynamic table index
         let r2 = parser.parse(&buffer2).unwrap();
 
         assert!(r2.is_empty());
         assert_eq!(0, r2.len());
+        assert!(parser.is_incomplete());
     }
 
     #[test]
@@ -16,8 +16,11 @@
 
 use std::{
     ffi::{CStr, CString},
-    io::ErrorKind,
+    fs,
+    io::{self, ErrorKind},
+    mem,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::unix::io::RawFd,
     time::Duration,
 };
 
@@ -30,7 +33,7 @@ use neli::{
     socket::NlSocketHandle,
     types::{Buffer, RtBuffer},
 };
-use nix::libc::IFLA_INFO_KIND;
+use nix::libc::{self, IFLA_INFO_KIND};
 use pnet::{
     datalink::{self, DataLinkReceiver, DataLinkSender, NetworkInterface},
     packet::{
@@ -62,6 +65,11 @@ const RCV_TIMEOUT: Duration = Duration::from_millis(500);
 
 const NETLINK_ERROR_NOADDR: i32 = -19;
 
+const NLMSG_HDR_LEN: usize = 16;
+
+const SYS_CLASS_NET: &str = "/sys/class/net";
+const LINK_EVENT_BUFFER_SIZE: usize = 16384;
+
 /*
 * TODO
 *  BPF socket 构造
@@ -535,6 +543,111 @@ pub fn link_list() -> Result<Vec<Link>> {
     request_link_info(None)
 }
 
+// 订阅RTMGRP_LINK组播，接收内核的接口新增(RTM_NEWLINK)及删除(RTM_DELLINK)通知
+pub struct LinkEventSocket {
+    fd: RawFd,
+    buffer: Vec<u8>,
+}
+
+impl LinkEventSocket {
+    pub fn new(timeout: Duration) -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // 出错返回时由drop关闭fd
+        let socket = Self {
+            fd,
+            buffer: vec![0; LINK_EVENT_BUFFER_SIZE],
+        };
+
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = libc::RTMGRP_LINK as u32;
+        let ret = unsafe {
+            libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(socket)
+    }
+
+    // 返回收到的接口增删消息数，超时返回0
+    pub fn recv(&mut self) -> Result<usize> {
+        let n = unsafe {
+            libc::recv(
+                self.fd,
+                self.buffer.as_mut_ptr() as *mut libc::c_void,
+                self.buffer.len(),
+                0,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted => Ok(0),
+                // 接收缓冲区溢出丢失了通知，按有事件处理以便调用方重新获取接口
+                _ if e.raw_os_error() == Some(libc::ENOBUFS) => Ok(1),
+                _ => Err(e.into()),
+            };
+        }
+        Ok(count_link_events(&self.buffer[..n as usize]))
+    }
+}
+
+impl Drop for LinkEventSocket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn count_link_events(mut buf: &[u8]) -> usize {
+    let mut count = 0;
+    while buf.len() >= NLMSG_HDR_LEN {
+        let len = u32::from_ne_bytes(buf[..4].try_into().unwrap()) as usize;
+        if len < NLMSG_HDR_LEN || len > buf.len() {
+            break;
+        }
+        let msg_type = u16::from_ne_bytes(buf[4..6].try_into().unwrap());
+        if msg_type == libc::RTM_NEWLINK || msg_type == libc::RTM_DELLINK {
+            count += 1;
+        }
+        // 消息按4字节对齐
+        buf = &buf[((len + 3) & !3).min(buf.len())..];
+    }
+    count
+}
+
 pub fn addr_list() -> Result<Vec<Addr>> {
     let msg = Ifaddrmsg {
         ifa_family: RtAddrFamily::Unspecified,
@@ -687,6 +800,34 @@ fn get_route_src_ip_and_ifindex(dest: &IpAddr) -> Result<(IpAddr, u32)> {
     Ok((routes[0].src_ip, routes[0].oif_index))
 }
 
+// perm_mac为成员网卡的永久MAC，balance-tlb/alb模式下成员使用永久MAC发送报文
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BondMember {
+    pub name: String,
+    pub perm_mac: Option<MacAddr>,
+}
+
+// 从sysfs读取bond的成员接口，不是bond时返回空
+pub fn bond_members<S: AsRef<str>>(bond: S) -> Vec<BondMember> {
+    let path = format!("{}/{}/bonding/slaves", SYS_CLASS_NET, bond.as_ref());
+    let slaves = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
+    slaves
+        .split_whitespace()
+        .map(|name| BondMember {
+            name: name.to_owned(),
+            perm_mac: fs::read_to_string(format!(
+                "{}/{}/bonding_slave/perm_hwaddr",
+                SYS_CLASS_NET, name
+            ))
+            .ok()
+            .and_then(|s| s.trim().parse().ok()),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -716,6 +857,25 @@ mod tests {
         assert!(links_by_name_regex("***").is_err());
     }
 
+    #[test]
+    fn link_events() {
+        let mut buf = vec![];
+        for (msg_type, payload_len) in [
+            (libc::RTM_NEWLINK, 18),
+            (libc::RTM_NEWADDR, 8),
+            (libc::RTM_DELLINK, 16),
+        ] {
+            let len = NLMSG_HDR_LEN + payload_len;
+            buf.extend_from_slice(&(len as u32).to_ne_bytes());
+            buf.extend_from_slice(&msg_type.to_ne_bytes());
+            buf.resize(buf.len() + len - 6, 0);
+            buf.resize((buf.len() + 3) & !3, 0);
+        }
+        assert_eq!(count_link_events(&buf), 2);
+        // 截断的消息不计数
+        assert_eq!(count_link_events(&buf[..NLMSG_HDR_LEN + 4]), 0);
+    }
+
     #[test]
     fn get_nonexist_link() {
         match link_by_name("nonexist42") {
@@ -307,6 +307,47 @@ pub fn route_get(dest_addr: IpAddr) -> Result<Route> {
     }
 }
 
+// Npcap的回环抓包设备，没有对应的网卡，抓到的包为DLT_NULL格式
+// 回环采集没有使用WinDivert或pktmon:
+//   - Npcap回环设备本身基于WFP callout，与WinDivert的SNIFF模式在同一层采集，能看到相同的回环和本机流量，
+//     而Windows采集器已依赖Npcap，复用它不需要再分发和签名第二个内核驱动，也能沿用WinPacket和BPF过滤
+//   - pktmon需要Windows 10 2004/Server 2022及以上版本，输出为ETW事件，没有可供实时收包的接口
+// 回环设备与网卡上重复采集的包由dispatcher的HairpinDedup去除
+const NPCAP_LOOPBACK_DEVICE: &str = "\\Device\\NPF_Loopback";
+// Loopback Pseudo-Interface的if_index固定为1
+pub const LOOPBACK_IF_INDEX: u32 = 1;
+
+// 开启回环采集时在采集接口中加入Npcap回环设备
+pub fn add_loopback_link(links: &mut Vec<Link>) {
+    if links.iter().any(|l| l.if_index == LOOPBACK_IF_INDEX) {
+        return;
+    }
+    let device = match pcap::Device::list() {
+        Ok(devices) => devices
+            .into_iter()
+            .find(|d| d.name == NPCAP_LOOPBACK_DEVICE),
+        Err(e) => {
+            warn!("list pcap interfaces failed: {}", e);
+            return;
+        }
+    };
+    match device {
+        Some(device) => links.push(Link {
+            if_index: LOOPBACK_IF_INDEX,
+            mac_addr: MacAddr::ZERO,
+            adapter_id: "".to_string(),
+            device_name: device.name,
+            name: "Loopback".to_string(),
+            flags: LinkFlags::UP | LinkFlags::LOOPBACK,
+            parent_index: None,
+        }),
+        None => warn!(
+            "{} not found, npcap should be installed with loopback support",
+            NPCAP_LOOPBACK_DEVICE
+        ),
+    }
+}
+
 fn get_pcap_interfaces() -> Result<Vec<Link>> {
     let devices = pcap::Device::list()
         .map_err(|e| Error::Windows(format!("list pcap interfaces failed: {}", e)))?;
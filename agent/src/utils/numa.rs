@@ -0,0 +1,242 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 线程CPU亲和性及NUMA内存策略，CPU与NUMA节点的拓扑从sysfs获取
+
+use std::fs;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::Mutex;
+
+use libc::{c_ulong, cpu_set_t};
+use log::warn;
+
+use super::stats::{Counter, CounterType, CounterValue, RefCountable};
+
+const SYS_CPU_PATH: &str = "/sys/devices/system/cpu";
+const SYS_NODE_PATH: &str = "/sys/devices/system/node";
+
+const MPOL_DEFAULT: i32 = 0;
+const MPOL_PREFERRED: i32 = 1;
+// 节点掩码只使用一个c_ulong
+const MAX_NODES: u32 = 64;
+
+// 解析sysfs及配置中"0-3,8,10-11"形式的列表
+pub fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = vec![];
+    for part in s.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start = start.trim().parse::<usize>().ok()?;
+                let end = end.trim().parse::<usize>().ok()?;
+                if start > end {
+                    return None;
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(part.trim().parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+// 进程可用的CPU，受cgroup cpuset及taskset限制
+pub fn allowed_cpus() -> io::Result<Vec<usize>> {
+    unsafe {
+        let mut set: cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(0, mem::size_of::<cpu_set_t>(), &mut set) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| libc::CPU_ISSET(*cpu, &set))
+            .collect())
+    }
+}
+
+// 丢弃进程不可用的CPU，保留配置顺序
+pub fn validate_cpus(cpus: &[usize]) -> Vec<usize> {
+    if cpus.is_empty() {
+        return vec![];
+    }
+    let allowed = match allowed_cpus() {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            warn!("get cpu affinity failed: {}, cpu pinning disabled", e);
+            return vec![];
+        }
+    };
+    cpus.iter()
+        .filter(|cpu| {
+            if allowed.contains(*cpu) {
+                true
+            } else {
+                warn!("cpu {} is not available to deepflow-agent, ignored", cpu);
+                false
+            }
+        })
+        .copied()
+        .collect()
+}
+
+// 未开启NUMA的系统返回None
+pub fn cpu_node(cpu: usize) -> Option<u32> {
+    fs::read_dir(format!("{}/cpu{}", SYS_CPU_PATH, cpu))
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()
+        })
+}
+
+pub fn online_nodes() -> Vec<u32> {
+    fs::read_to_string(format!("{}/online", SYS_NODE_PATH))
+        .ok()
+        .and_then(|s| parse_cpu_list(&s))
+        .map(|nodes| nodes.into_iter().map(|n| n as u32).collect())
+        .unwrap_or_default()
+}
+
+// 将当前线程绑定到cpu，local_memory为true时优先从cpu所在NUMA节点分配内存
+pub fn pin_current_thread(cpu: usize, local_memory: bool) -> io::Result<()> {
+    unsafe {
+        let mut set: cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    if local_memory {
+        if let Some(node) = cpu_node(cpu) {
+            set_preferred_node(Some(node))?;
+        }
+    }
+    Ok(())
+}
+
+// 设置当前线程的内存分配策略，None恢复为默认的本地分配
+pub fn set_preferred_node(node: Option<u32>) -> io::Result<()> {
+    let ret = match node {
+        Some(node) if node >= MAX_NODES => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("numa node {} out of range", node),
+            ))
+        }
+        Some(node) => {
+            let mask: c_ulong = 1 << node;
+            unsafe {
+                libc::syscall(
+                    libc::SYS_set_mempolicy,
+                    MPOL_PREFERRED,
+                    &mask as *const c_ulong,
+                    (MAX_NODES + 1) as c_ulong,
+                )
+            }
+        }
+        None => unsafe {
+            libc::syscall(
+                libc::SYS_set_mempolicy,
+                MPOL_DEFAULT,
+                ptr::null::<c_ulong>(),
+                0 as c_ulong,
+            )
+        },
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+const NUMASTAT_FIELDS: [&str; 4] = ["numa_hit", "numa_miss", "numa_foreign", "other_node"];
+
+fn parse_numastat(s: &str) -> [u64; 4] {
+    let mut values = [0; 4];
+    for line in s.lines() {
+        if let Some((name, value)) = line.split_once(' ') {
+            if let Some(i) = NUMASTAT_FIELDS.iter().position(|f| *f == name) {
+                values[i] = value.trim().parse().unwrap_or_default();
+            }
+        }
+    }
+    values
+}
+
+// 节点的numastat为开机以来的累计页数，按差值上报
+pub struct NumaStats {
+    node: u32,
+    last: Mutex<Option<[u64; 4]>>,
+}
+
+impl NumaStats {
+    pub fn new(node: u32) -> Self {
+        Self {
+            node,
+            last: Mutex::new(None),
+        }
+    }
+}
+
+impl RefCountable for NumaStats {
+    fn get_counters(&self) -> Vec<Counter> {
+        let current =
+            match fs::read_to_string(format!("{}/node{}/numastat", SYS_NODE_PATH, self.node)) {
+                Ok(s) => parse_numastat(&s),
+                Err(_) => return vec![],
+            };
+        let last = self.last.lock().unwrap().replace(current);
+        let last = match last {
+            Some(last) => last,
+            None => return vec![],
+        };
+        let delta = |i: usize| CounterValue::Unsigned(current[i].saturating_sub(last[i]));
+        vec![
+            ("numa-hit", CounterType::Counted, delta(0)),
+            ("numa-miss", CounterType::Counted, delta(1)),
+            ("numa-foreign", CounterType::Counted, delta(2)),
+            ("other-node", CounterType::Counted, delta(3)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+    }
+
+    #[test]
+    fn numastat() {
+        let s = "numa_hit 1000\nnuma_miss 20\nnuma_foreign 30\ninterleave_hit 4\nlocal_node 990\nother_node 10\n";
+        assert_eq!(parse_numastat(s), [1000, 20, 30, 10]);
+    }
+}
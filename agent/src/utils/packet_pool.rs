@@ -0,0 +1,190 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 需要脱离采集缓冲区保存的报文内容（如eBPF数据、解析失败流的报文缓存、待解析的应用协议payload）
+// 使用PacketBuffer，释放时缓存归还到空闲列表，下次拷贝报文时复用，避免逐包分配Vec
+// Linux下dispatcher到flow_generator的MetaPacket仍借用af_packet环形缓冲区，不经过拷贝，也不使用PacketBuffer；
+// Windows采集引擎返回的报文仍是逐包分配的Vec
+//
+// 空闲列表按线程分片，每个线程固定使用一个分片，减少dispatcher等线程间的锁竞争；
+// 生产和释放常在不同线程，本分片为空时尝试从其他分片获取
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+const SHARDS: usize = 16;
+// 所有分片中空闲缓存的总容量上限
+const MAX_POOLED_BYTES: usize = 64 << 20;
+// 超过该容量的缓存直接释放，避免空闲列表长期占用大块内存
+const MAX_POOLED_CAPACITY: usize = 65536;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SHARD: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+static FREE_BUFFERS: [Mutex<Vec<Vec<u8>>>; SHARDS] = [EMPTY_SHARD; SHARDS];
+static POOLED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARDS;
+}
+
+fn take_buffer() -> Vec<u8> {
+    let home = SHARD.with(|s| *s);
+    for i in 0..SHARDS {
+        let shard = &FREE_BUFFERS[(home + i) % SHARDS];
+        // 其他分片只尝试加锁，不与其所属线程竞争
+        let free = if i == 0 {
+            Some(shard.lock().unwrap())
+        } else {
+            shard.try_lock().ok()
+        };
+        if let Some(buffer) = free.and_then(|mut f| f.pop()) {
+            POOLED_BYTES.fetch_sub(buffer.capacity(), Ordering::Relaxed);
+            return buffer;
+        }
+    }
+    Vec::new()
+}
+
+fn put_buffer(buffer: Vec<u8>) {
+    let capacity = buffer.capacity();
+    if POOLED_BYTES.fetch_add(capacity, Ordering::Relaxed) + capacity > MAX_POOLED_BYTES {
+        POOLED_BYTES.fetch_sub(capacity, Ordering::Relaxed);
+        return;
+    }
+    FREE_BUFFERS[SHARD.with(|s| *s)]
+        .lock()
+        .unwrap()
+        .push(buffer);
+}
+
+#[derive(Default, PartialEq, Eq)]
+pub struct PacketBuffer(Vec<u8>);
+
+impl PacketBuffer {
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut buffer = take_buffer();
+        buffer.extend_from_slice(data);
+        Self(buffer)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for PacketBuffer {
+    fn drop(&mut self) {
+        if self.0.capacity() == 0 || self.0.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        let mut buffer = std::mem::take(&mut self.0);
+        buffer.clear();
+        put_buffer(buffer);
+    }
+}
+
+impl Clone for PacketBuffer {
+    fn clone(&self) -> Self {
+        Self::from_slice(&self.0)
+    }
+}
+
+impl From<Vec<u8>> for PacketBuffer {
+    fn from(v: Vec<u8>) -> Self {
+        Self(v)
+    }
+}
+
+impl Deref for PacketBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for PacketBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for PacketBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for PacketBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycle() {
+        let buffer = PacketBuffer::from_slice(&[1; 100]);
+        let ptr = buffer.as_ptr();
+        assert_eq!(buffer.as_slice(), &[1; 100][..]);
+        drop(buffer);
+
+        // 其他测试线程可能同时使用空闲列表，只检查复用的缓存已清空
+        let buffer = PacketBuffer::from_slice(&[2; 10]);
+        assert_eq!(&buffer[..], &[2; 10][..]);
+        if buffer.as_ptr() == ptr {
+            assert!(buffer.0.capacity() >= 100);
+        }
+
+        let large = PacketBuffer::from(vec![0; MAX_POOLED_CAPACITY + 1]);
+        drop(large);
+        for shard in FREE_BUFFERS.iter() {
+            assert!(shard
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|b| b.is_empty() && b.capacity() <= MAX_POOLED_CAPACITY));
+        }
+    }
+
+    #[test]
+    fn bytes_limit() {
+        let buffers = (0..MAX_POOLED_BYTES / MAX_POOLED_CAPACITY + 16)
+            .map(|_| PacketBuffer::from(vec![0; MAX_POOLED_CAPACITY]))
+            .collect::<Vec<_>>();
+        drop(buffers);
+        assert!(POOLED_BYTES.load(Ordering::Relaxed) <= MAX_POOLED_BYTES);
+        let pooled = FREE_BUFFERS
+            .iter()
+            .map(|s| {
+                s.lock()
+                    .unwrap()
+                    .iter()
+                    .map(|b| b.capacity())
+                    .sum::<usize>()
+            })
+            .sum::<usize>();
+        assert!(pooled <= MAX_POOLED_BYTES);
+    }
+}
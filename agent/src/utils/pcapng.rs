@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// pcap-ng格式的抓包文件输出，参考draft-ietf-opsawg-pcapng
+// 相比pcap可以携带接口描述、注释及纳秒精度的时间戳
+
+use std::io::{Result, Write};
+use std::time::Duration;
+
+use crate::common::enums::LinkType;
+
+pub const BLOCK_SECTION_HEADER: u32 = 0x0A0D0D0A;
+pub const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x00000001;
+pub const BLOCK_ENHANCED_PACKET: u32 = 0x00000006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+const VERSION_MAJOR: u16 = 1;
+const VERSION_MINOR: u16 = 0;
+// 不指定section长度
+const SECTION_LENGTH_UNSPECIFIED: i64 = -1;
+const SNAP_LEN: u32 = 65535;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+// 时间戳单位为10^-9秒
+const TSRESOL_NANOS: u8 = 9;
+
+const USER_APPLICATION: &str = concat!("deepflow-agent ", env!("REV_COUNT"), "-", env!("REVISION"));
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+struct Options(Vec<u8>);
+
+impl Options {
+    fn new() -> Self {
+        Self(vec![])
+    }
+
+    fn push(&mut self, code: u16, value: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(&code.to_le_bytes());
+        self.0
+            .extend_from_slice(&(value.len() as u16).to_le_bytes());
+        self.0.extend_from_slice(value);
+        self.0.resize(self.0.len() + padding(value.len()), 0);
+        self
+    }
+
+    fn push_str(&mut self, code: u16, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(v) if !v.is_empty() => self.push(code, v.as_bytes()),
+            _ => self,
+        }
+    }
+
+    fn finish(&mut self) -> &[u8] {
+        if !self.0.is_empty() {
+            self.0.extend_from_slice(&OPT_END_OF_OPT.to_le_bytes());
+            self.0.extend_from_slice(&[0; 2]);
+        }
+        &self.0
+    }
+}
+
+// 所有块按小端序写出，section头中带agent版本及调用方提供的注释（如flow_id）
+pub struct PcapngWriter<W: Write> {
+    writer: W,
+    interfaces: u32,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    pub fn new(writer: W, comment: Option<&str>) -> Result<Self> {
+        let mut pcapng = Self {
+            writer,
+            interfaces: 0,
+        };
+        let mut body = vec![];
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+        body.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+        body.extend_from_slice(&SECTION_LENGTH_UNSPECIFIED.to_le_bytes());
+        let mut options = Options::new();
+        options
+            .push_str(OPT_COMMENT, comment)
+            .push_str(OPT_SHB_USERAPPL, Some(USER_APPLICATION));
+        pcapng.write_block(BLOCK_SECTION_HEADER, &body, options.finish())?;
+        Ok(pcapng)
+    }
+
+    // 返回接口序号，写包时使用
+    pub fn add_interface(&mut self, link_type: LinkType, name: Option<&str>) -> Result<u32> {
+        let mut body = vec![];
+        body.extend_from_slice(&(u8::from(link_type) as u16).to_le_bytes());
+        body.extend_from_slice(&[0; 2]);
+        body.extend_from_slice(&SNAP_LEN.to_le_bytes());
+        let mut options = Options::new();
+        options
+            .push_str(OPT_IF_NAME, name)
+            .push(OPT_IF_TSRESOL, &[TSRESOL_NANOS]);
+        self.write_block(BLOCK_INTERFACE_DESCRIPTION, &body, options.finish())?;
+        self.interfaces += 1;
+        Ok(self.interfaces - 1)
+    }
+
+    pub fn write_packet(
+        &mut self,
+        interface_id: u32,
+        timestamp: Duration,
+        raw: &[u8],
+        packet_len: u32,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let nanos = timestamp.as_nanos() as u64;
+        let mut body = Vec::with_capacity(20 + raw.len() + padding(raw.len()));
+        body.extend_from_slice(&interface_id.to_le_bytes());
+        body.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(nanos as u32).to_le_bytes());
+        body.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+        body.extend_from_slice(&packet_len.max(raw.len() as u32).to_le_bytes());
+        body.extend_from_slice(raw);
+        body.resize(body.len() + padding(raw.len()), 0);
+        let mut options = Options::new();
+        options.push_str(OPT_COMMENT, comment);
+        self.write_block(BLOCK_ENHANCED_PACKET, &body, options.finish())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8], options: &[u8]) -> Result<()> {
+        // 块类型及首尾两个块长度
+        let total_len = (12 + body.len() + options.len()) as u32;
+        self.writer.write_all(&block_type.to_le_bytes())?;
+        self.writer.write_all(&total_len.to_le_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(options)?;
+        self.writer.write_all(&total_len.to_le_bytes())
+    }
+}
+
+// 按块拆分pcap-ng数据，返回块类型及块内容（不含类型和长度）
+#[cfg(test)]
+pub fn read_blocks(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut blocks = vec![];
+    while data.len() >= 12 {
+        let block_type = u32::from_le_bytes(data[..4].try_into().unwrap());
+        let total_len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        assert_eq!(
+            &data[total_len - 4..total_len],
+            &data[4..8],
+            "block length mismatch"
+        );
+        blocks.push((block_type, &data[8..total_len - 4]));
+        data = &data[total_len..];
+    }
+    assert!(data.is_empty());
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_blocks() {
+        let mut pcapng = PcapngWriter::new(vec![], Some("flow_id=1")).unwrap();
+        assert_eq!(
+            pcapng
+                .add_interface(LinkType::Ethernet, Some("eth0"))
+                .unwrap(),
+            0
+        );
+        pcapng
+            .write_packet(0, Duration::new(1, 2), &[1; 5], 60, Some("l7 parse error"))
+            .unwrap();
+        let data = pcapng.writer;
+        assert_eq!(data.len() % 4, 0);
+
+        let blocks = read_blocks(&data);
+        assert_eq!(
+            blocks.iter().map(|b| b.0).collect::<Vec<_>>(),
+            vec![
+                BLOCK_SECTION_HEADER,
+                BLOCK_INTERFACE_DESCRIPTION,
+                BLOCK_ENHANCED_PACKET
+            ]
+        );
+        let shb = blocks[0].1;
+        assert_eq!(&shb[..4], &BYTE_ORDER_MAGIC.to_le_bytes());
+        // comment选项
+        assert_eq!(&shb[16..20], &[1, 0, 9, 0]);
+        assert_eq!(&shb[20..29], b"flow_id=1");
+
+        let idb = blocks[1].1;
+        assert_eq!(&idb[..2], &1u16.to_le_bytes());
+        assert_eq!(&idb[8..16], &[2, 0, 4, 0, b'e', b't', b'h', b'0']);
+        assert_eq!(&idb[16..21], &[9, 0, 1, 0, TSRESOL_NANOS]);
+
+        let epb = blocks[2].1;
+        let nanos = 1_000_000_002u64;
+        assert_eq!(&epb[4..8], &((nanos >> 32) as u32).to_le_bytes());
+        assert_eq!(&epb[8..12], &(nanos as u32).to_le_bytes());
+        assert_eq!(&epb[12..16], &5u32.to_le_bytes());
+        assert_eq!(&epb[16..20], &60u32.to_le_bytes());
+        assert_eq!(&epb[20..28], &[1, 1, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(&epb[28..32], &[1, 0, 14, 0]);
+    }
+}
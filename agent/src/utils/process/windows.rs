@@ -14,11 +14,26 @@
  * limitations under the License.
  */
 
-use std::{mem::size_of, path::PathBuf, process};
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    mem::size_of,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    process, ptr,
+};
 use sysinfo::{System, SystemExt};
 
 use ::windows::Win32::{
-    Foundation::{GetLastError, BOOL, CHAR, HINSTANCE, INVALID_HANDLE_VALUE, PWSTR},
+    Foundation::{
+        GetLastError, BOOL, CHAR, ERROR_INSUFFICIENT_BUFFER, HINSTANCE, INVALID_HANDLE_VALUE,
+        NO_ERROR, PWSTR,
+    },
+    NetworkManagement::IpHelper::{
+        GetExtendedTcpTable, GetExtendedUdpTable, AF_INET, AF_INET6, MIB_TCP6ROW_OWNER_PID,
+        MIB_TCPROW_OWNER_PID, MIB_UDP6ROW_OWNER_PID, MIB_UDPROW_OWNER_PID, TCP_TABLE_OWNER_PID_ALL,
+        UDP_TABLE_OWNER_PID,
+    },
     System::{
         Diagnostics::ToolHelp::{
             CreateToolhelp32Snapshot, Process32Next, PROCESSENTRY32, TH32CS_SNAPPROCESS,
@@ -32,6 +47,7 @@ use ::windows::Win32::{
 use log::debug;
 
 use crate::{
+    common::enums::IpProtocol,
     error::{Error, Result},
     utils::WIN_ERROR_CODE_STR,
 };
@@ -211,3 +227,151 @@ pub fn get_current_sys_free_memory_percentage() -> u32 {
     }
     0
 }
+
+// 本地socket及其所属进程
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketOwner {
+    pub protocol: IpProtocol,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub pid: u32,
+}
+
+// 返回的表结构为4字节的dwNumEntries后紧跟行数组，使用u32保证对齐
+fn get_extended_table(protocol: IpProtocol, family: u32) -> Result<Vec<u32>> {
+    let mut size = 0u32;
+    let mut table: Vec<u32> = vec![];
+    loop {
+        let ret_code = unsafe {
+            if protocol == IpProtocol::Tcp {
+                GetExtendedTcpTable(
+                    table.as_mut_ptr() as *mut c_void,
+                    &mut size,
+                    BOOL(0),
+                    family,
+                    TCP_TABLE_OWNER_PID_ALL,
+                    0,
+                )
+            } else {
+                GetExtendedUdpTable(
+                    table.as_mut_ptr() as *mut c_void,
+                    &mut size,
+                    BOOL(0),
+                    family,
+                    UDP_TABLE_OWNER_PID,
+                    0,
+                )
+            }
+        };
+        if ret_code == NO_ERROR {
+            return Ok(table);
+        }
+        // 两次调用之间socket增加时需要再次扩大
+        if ret_code != ERROR_INSUFFICIENT_BUFFER {
+            return Err(Error::Windows(format!(
+                "failed to get {:?} table because of win32 error code({}),\n{}",
+                protocol, ret_code, WIN_ERROR_CODE_STR
+            )));
+        }
+        table = vec![0u32; (size as usize + 3) / 4];
+    }
+}
+
+fn table_rows<T>(table: &[u32]) -> Vec<T> {
+    let count = match table.first() {
+        Some(n) => *n as usize,
+        None => return vec![],
+    };
+    let len = table.len() * 4;
+    let mut rows = vec![];
+    for i in 0..count {
+        let offset = 4 + i * size_of::<T>();
+        if offset + size_of::<T>() > len {
+            break;
+        }
+        rows.push(unsafe {
+            ptr::read_unaligned((table.as_ptr() as *const u8).add(offset) as *const T)
+        });
+    }
+    rows
+}
+
+// 端口为网络序，存放在u32的低16位
+fn port_from_row(port: u32) -> u16 {
+    u16::from_be(port as u16)
+}
+
+// 获取TCP及UDP的IPv4/IPv6 socket与进程ID的对应关系
+pub fn get_socket_owners() -> Result<Vec<SocketOwner>> {
+    let mut owners = vec![];
+    for row in table_rows::<MIB_TCPROW_OWNER_PID>(&get_extended_table(IpProtocol::Tcp, AF_INET)?) {
+        owners.push(SocketOwner {
+            protocol: IpProtocol::Tcp,
+            ip: Ipv4Addr::from(row.dwLocalAddr.to_ne_bytes()).into(),
+            port: port_from_row(row.dwLocalPort),
+            pid: row.dwOwningPid,
+        });
+    }
+    for row in table_rows::<MIB_TCP6ROW_OWNER_PID>(&get_extended_table(IpProtocol::Tcp, AF_INET6)?)
+    {
+        owners.push(SocketOwner {
+            protocol: IpProtocol::Tcp,
+            ip: Ipv6Addr::from(row.ucLocalAddr).into(),
+            port: port_from_row(row.dwLocalPort),
+            pid: row.dwOwningPid,
+        });
+    }
+    for row in table_rows::<MIB_UDPROW_OWNER_PID>(&get_extended_table(IpProtocol::Udp, AF_INET)?) {
+        owners.push(SocketOwner {
+            protocol: IpProtocol::Udp,
+            ip: Ipv4Addr::from(row.dwLocalAddr.to_ne_bytes()).into(),
+            port: port_from_row(row.dwLocalPort),
+            pid: row.dwOwningPid,
+        });
+    }
+    for row in table_rows::<MIB_UDP6ROW_OWNER_PID>(&get_extended_table(IpProtocol::Udp, AF_INET6)?)
+    {
+        owners.push(SocketOwner {
+            protocol: IpProtocol::Udp,
+            ip: Ipv6Addr::from(row.ucLocalAddr).into(),
+            port: port_from_row(row.dwLocalPort),
+            pid: row.dwOwningPid,
+        });
+    }
+    Ok(owners)
+}
+
+// 进程ID到可执行文件名的映射
+pub fn get_process_names() -> Result<HashMap<u32, String>> {
+    let snap = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snap == INVALID_HANDLE_VALUE {
+        let err_msg = format!(
+            "failed to run get_process_names function because of win32 error code({}),\n{}",
+            unsafe { GetLastError() },
+            WIN_ERROR_CODE_STR
+        );
+        return Err(Error::Windows(err_msg));
+    }
+
+    let mut names = HashMap::new();
+    loop {
+        let mut entry = PROCESSENTRY32::default();
+        entry.dwSize = size_of::<PROCESSENTRY32>() as u32;
+        if let Err(e) = unsafe { Process32Next(snap, &mut entry).ok() } {
+            debug!("failed to run Process32Next function error:{}", e);
+            break;
+        }
+
+        let name = entry
+            .szExeFile
+            .iter()
+            .take_while(|&&c| c != CHAR(0))
+            .map(|c| c.0)
+            .collect::<Vec<u8>>();
+        names.insert(
+            entry.th32ProcessID,
+            String::from_utf8_lossy(&name).into_owned(),
+        );
+    }
+    Ok(names)
+}
@@ -0,0 +1,341 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::Write;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::HeaderMap;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info, warn};
+use pprof::{protos::Message, ProfilerGuardBuilder};
+use ring::constant_time::verify_slices_are_equal;
+use tokio::{
+    runtime::{Builder, Runtime},
+    sync::oneshot,
+    task,
+};
+
+use crate::config::ProfilerConfig;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+const PROFILE_FREQUENCY: i32 = 99;
+const DEFAULT_PROFILE_SECONDS: u32 = 30;
+// CPU profile期间检查是否被取消的间隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// 这些库中的栈回溯可能导致死锁，参考pprof-rs的说明
+const PROFILE_BLOCKLIST: [&str; 4] = ["libc", "libgcc", "pthread", "vdso"];
+// 输出的/proc/self/status字段
+const STATUS_FIELDS: [&str; 9] = [
+    "VmPeak", "VmSize", "VmHWM", "VmRSS", "RssAnon", "RssFile", "VmData", "VmSwap", "Threads",
+];
+
+// pprof-rs的采样器是进程全局的，同一时刻只允许一个CPU profile
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let value = match headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        Some(v) => v,
+        None => return false,
+    };
+    match value.strip_prefix("Bearer ") {
+        Some(t) => verify_slices_are_equal(t.trim().as_bytes(), token.as_bytes()).is_ok(),
+        None => false,
+    }
+}
+
+fn profile_seconds(query: Option<&str>, max_seconds: u32) -> u32 {
+    query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .find_map(|kv| kv.strip_prefix("seconds="))
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_PROFILE_SECONDS)
+        .min(max_seconds)
+}
+
+// stop时取消正在进行的profile，避免阻塞trident主循环
+fn cpu_profile(seconds: u32, cancelled: &AtomicBool) -> Result<Vec<u8>, GenericError> {
+    let guard = ProfilerGuardBuilder::default()
+        .frequency(PROFILE_FREQUENCY)
+        .blocklist(&PROFILE_BLOCKLIST)
+        .build()?;
+    let deadline = Instant::now() + Duration::from_secs(seconds as u64);
+    while Instant::now() < deadline {
+        if cancelled.load(Ordering::Acquire) {
+            return Err("cpu profile cancelled".into());
+        }
+        thread::sleep(
+            CANCEL_CHECK_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+        );
+    }
+    let profile = guard.report().build()?.pprof()?;
+    let mut content = vec![];
+    profile.write_to_vec(&mut content)?;
+    Ok(content)
+}
+
+// musl下没有mallinfo，这里不是按分配统计的heap profile，只读取/proc/self/status和/proc/self/maps中的内存统计
+fn heap_snapshot(status: &str, maps: &str) -> String {
+    let mut snapshot = String::new();
+    for line in status.lines() {
+        if let Some((key, value)) = line.split_once(':') {
+            if STATUS_FIELDS.contains(&key) {
+                let _ = writeln!(snapshot, "{}: {}", key, value.trim());
+            }
+        }
+    }
+
+    let (mut heap, mut anonymous) = (0, 0);
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let size = match fields.next().and_then(|r| r.split_once('-')) {
+            Some((start, end)) => {
+                match (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16)) {
+                    (Ok(start), Ok(end)) => end.saturating_sub(start),
+                    _ => continue,
+                }
+            }
+            None => continue,
+        };
+        // 跳过perms, offset, dev, inode
+        match fields.nth(4) {
+            Some("[heap]") => heap += size,
+            None => anonymous += size,
+            _ => (),
+        }
+    }
+    let _ = writeln!(snapshot, "HeapMapping: {} kB", heap >> 10);
+    let _ = writeln!(snapshot, "AnonMapping: {} kB", anonymous >> 10);
+    snapshot
+}
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(body.into())
+        .unwrap()
+}
+
+async fn handler(
+    req: Request<Body>,
+    config: Arc<ProfilerConfig>,
+    cancelled: Arc<AtomicBool>,
+) -> Result<Response<Body>, GenericError> {
+    if !authorized(req.headers(), &config.token) {
+        return Ok(response(StatusCode::UNAUTHORIZED, "Unauthorized"));
+    }
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/debug/pprof/profile") => {
+            let seconds = profile_seconds(req.uri().query(), config.max_seconds);
+            if PROFILING.swap(true, Ordering::AcqRel) {
+                return Ok(response(
+                    StatusCode::CONFLICT,
+                    "another cpu profile is running",
+                ));
+            }
+            info!("cpu profile started, duration {}s", seconds);
+            // 客户端断开时请求会被丢弃，因此在阻塞任务内复位PROFILING
+            let result = task::spawn_blocking(move || {
+                let result = cpu_profile(seconds, &cancelled);
+                PROFILING.store(false, Ordering::Release);
+                result
+            })
+            .await?;
+            match result {
+                Ok(content) => Ok(Response::builder()
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .header(CONTENT_DISPOSITION, "attachment; filename=\"profile.pb\"")
+                    .body(content.into())?),
+                Err(e) => {
+                    warn!("cpu profile failed: {}", e);
+                    Ok(response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+                }
+            }
+        }
+        (&Method::GET, "/debug/pprof/heap") => {
+            match (
+                fs::read_to_string("/proc/self/status"),
+                fs::read_to_string("/proc/self/maps"),
+            ) {
+                (Ok(status), Ok(maps)) => {
+                    Ok(response(StatusCode::OK, heap_snapshot(&status, &maps)))
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    Ok(response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+                }
+            }
+        }
+        _ => Ok(response(StatusCode::NOT_FOUND, "Not Found")),
+    }
+}
+
+// 按需输出CPU profile(pprof格式)和/proc中的内存统计，用于现场排查高CPU和内存问题
+pub struct Profiler {
+    config: Arc<ProfilerConfig>,
+    // 仅在drop时取出，通过shutdown_background关闭，不等待进行中的profile
+    rt: Option<Runtime>,
+    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Profiler {
+    pub fn new(config: &ProfilerConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            rt: Some(
+                Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .enable_all()
+                    .thread_name("profiler")
+                    .build()
+                    .unwrap(),
+            ),
+            shutdown_tx: Default::default(),
+            cancelled: Default::default(),
+        }
+    }
+
+    pub fn start(&self) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut shutdown_tx = self.shutdown_tx.lock().unwrap();
+        if shutdown_tx.is_some() {
+            return;
+        }
+
+        // listen-address在加载配置时已校验
+        let addr = match self.config.listen_address.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(e) => {
+                warn!("profiler listen-address invalid: {}", e);
+                return;
+            }
+        };
+        let rt = self.rt.as_ref().unwrap();
+        let _guard = rt.enter();
+        let server_builder = match Server::try_bind(&addr) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("profiler bind {} failed: {}", addr, e);
+                return;
+            }
+        };
+
+        self.cancelled.store(false, Ordering::Release);
+        let config = self.config.clone();
+        let cancelled = self.cancelled.clone();
+        let service = make_service_fn(move |_| {
+            let config = config.clone();
+            let cancelled = cancelled.clone();
+            async move {
+                Ok::<_, GenericError>(service_fn(move |req| {
+                    handler(req, config.clone(), cancelled.clone())
+                }))
+            }
+        });
+        let (tx, rx) = oneshot::channel();
+        let server = server_builder.serve(service).with_graceful_shutdown(async {
+            let _ = rx.await;
+        });
+        rt.spawn(async move {
+            if let Err(e) = server.await {
+                error!("profiler error: {}", e);
+            }
+        });
+        shutdown_tx.replace(tx);
+        info!("profiler listening on http://{}", addr);
+    }
+
+    pub fn stop(&self) {
+        if let Some(tx) = self.shutdown_tx.lock().unwrap().take() {
+            self.cancelled.store(true, Ordering::Release);
+            let _ = tx.send(());
+            info!("profiler stopped");
+        }
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        self.stop();
+        // 默认drop Runtime会等待spawn_blocking中的profile结束
+        if let Some(rt) = self.rt.take() {
+            rt.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use http::HeaderValue;
+
+    #[test]
+    fn auth_and_query() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "secret"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        assert!(authorized(&headers, "secret"));
+        assert!(!authorized(&headers, "secret2"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic secret"));
+        assert!(!authorized(&headers, "secret"));
+
+        assert_eq!(profile_seconds(None, 300), DEFAULT_PROFILE_SECONDS);
+        assert_eq!(profile_seconds(Some("seconds=5"), 300), 5);
+        assert_eq!(profile_seconds(Some("debug=1&seconds=600"), 300), 300);
+        assert_eq!(
+            profile_seconds(Some("seconds=0"), 300),
+            DEFAULT_PROFILE_SECONDS
+        );
+    }
+
+    #[test]
+    fn cancel_profile() {
+        let cancelled = AtomicBool::new(true);
+        let start = Instant::now();
+        assert!(cpu_profile(10, &cancelled).is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn heap() {
+        let status =
+            "Name:\tdeepflow-agent\nVmPeak:\t  200000 kB\nVmRSS:\t   51200 kB\nThreads:\t32\n";
+        let maps = "\
+55d0c8a00000-55d0c8a21000 r-xp 00000000 08:01 1234 /usr/sbin/deepflow-agent
+55d0c8c00000-55d0c8d00000 rw-p 00000000 00:00 0 [heap]
+7f0000000000-7f0000100000 rw-p 00000000 00:00 0
+7f0000100000-7f0000101000 r--p 00000000 08:01 5678 /lib/ld-musl-x86_64.so.1
+";
+        assert_eq!(
+            heap_snapshot(status, maps),
+            "VmPeak: 200000 kB\nVmRSS: 51200 kB\nThreads: 32\nHeapMapping: 1024 kB\nAnonMapping: 1024 kB\n"
+        );
+    }
+}
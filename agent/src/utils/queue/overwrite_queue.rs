@@ -27,6 +27,9 @@ use std::time::{Duration, Instant};
 use super::Error;
 use crate::utils::stats;
 
+// 未启用自适应批量时recv_batch单次最多读取的数量
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
 pub fn bounded<T>(size: usize) -> (Sender<T>, Receiver<T>, StatsHandle<T>) {
     RefCounter::new(OverwriteQueue::with_capacity(size))
 }
@@ -36,6 +39,7 @@ pub struct Counter {
     pub input: AtomicU64,
     pub output: AtomicU64,
     pub overwritten: AtomicU64,
+    pub batches: AtomicU64,
 }
 
 // fixed size MPSC overwrite queue implemented with ring buffer
@@ -53,6 +57,10 @@ struct OverwriteQueue<T: Sized> {
 
     terminated: AtomicBool,
 
+    // recv_batch读到batch_size条或等待batch_linger后返回，batch_size为0时不等待
+    batch_size: AtomicUsize,
+    batch_linger: AtomicU64,
+
     counter: Counter,
 
     _marker: PhantomData<T>,
@@ -77,6 +85,8 @@ impl<T> OverwriteQueue<T> {
             writer_lock: Mutex::new(()),
             notify: Condvar::new(),
             terminated: AtomicBool::new(false),
+            batch_size: AtomicUsize::new(0),
+            batch_linger: AtomicU64::new(0),
             counter: Counter::default(),
             _marker: PhantomData,
         }
@@ -86,6 +96,14 @@ impl<T> OverwriteQueue<T> {
         self.terminated.load(Ordering::Relaxed)
     }
 
+    fn pending(&self, start: usize, end: usize) -> usize {
+        if end < start {
+            end + 2 * self.size - start
+        } else {
+            end - start
+        }
+    }
+
     unsafe fn raw_send(&self, msgs: *const T, count: usize) -> Result<(), Error<T>> {
         if self.terminated.load(Ordering::Acquire) {
             return Err(Error::Terminated(None, None));
@@ -153,6 +171,7 @@ impl<T> OverwriteQueue<T> {
     unsafe fn raw_recv_timeout(
         &self,
         timeout: Option<Duration>,
+        linger: Duration,
         buffer: *mut T,
         buf_size: usize,
     ) -> Result<usize, Error<T>> {
@@ -188,6 +207,19 @@ impl<T> OverwriteQueue<T> {
                 }
             }
         }
+        // 已有数据但不足一批时，最多再等待linger
+        if !linger.is_zero() {
+            let deadline = Instant::now() + linger;
+            while self.pending(start, end) < buf_size && !self.terminated.load(Ordering::Acquire) {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                guard = self.notify.wait_timeout(guard, deadline - now).unwrap().0;
+                start = self.start.load(Ordering::Acquire);
+                end = self.end.load(Ordering::Acquire);
+            }
+        }
         if end < start {
             end += 2 * self.size;
         }
@@ -213,6 +245,7 @@ impl<T> OverwriteQueue<T> {
         self.counter
             .output
             .fetch_add(recv_count as u64, Ordering::Relaxed);
+        self.counter.batches.fetch_add(1, Ordering::Relaxed);
         Ok(recv_count)
     }
 
@@ -362,11 +395,12 @@ impl<T> Receiver<T> {
     pub fn recv(&self, timeout: Option<Duration>) -> Result<T, Error<T>> {
         unsafe {
             let mut msg = MaybeUninit::<T>::uninit();
-            match self
-                .counter()
-                .queue
-                .raw_recv_timeout(timeout, msg.as_mut_ptr(), 1)
-            {
+            match self.counter().queue.raw_recv_timeout(
+                timeout,
+                Duration::ZERO,
+                msg.as_mut_ptr(),
+                1,
+            ) {
                 Ok(n) if n == 1 => Ok(msg.assume_init()),
                 Err(e) => Err(e),
                 _ => unreachable!(),
@@ -375,13 +409,48 @@ impl<T> Receiver<T> {
     }
 
     pub fn recv_n(&self, n: usize, timeout: Option<Duration>) -> Result<Vec<T>, Error<T>> {
+        self.recv_n_with_linger(n, timeout, Duration::ZERO)
+    }
+
+    pub fn set_batching(&self, batch_size: usize, linger: Duration) {
+        let queue = &self.counter().queue;
+        queue
+            .batch_linger
+            .store(linger.as_nanos() as u64, Ordering::Relaxed);
+        queue.batch_size.store(batch_size, Ordering::Relaxed);
+    }
+
+    pub fn batching_enabled(&self) -> bool {
+        self.counter().queue.batch_size.load(Ordering::Relaxed) > 0
+    }
+
+    // 读到配置的批量大小或等待linger后返回，低速率时延迟不超过linger，高速率时按批处理
+    // 未启用时等同于recv_n
+    pub fn recv_batch(&self, timeout: Option<Duration>) -> Result<Vec<T>, Error<T>> {
+        let queue = &self.counter().queue;
+        match queue.batch_size.load(Ordering::Relaxed) {
+            0 => self.recv_n(DEFAULT_BATCH_SIZE, timeout),
+            n => self.recv_n_with_linger(
+                n,
+                timeout,
+                Duration::from_nanos(queue.batch_linger.load(Ordering::Relaxed)),
+            ),
+        }
+    }
+
+    fn recv_n_with_linger(
+        &self,
+        n: usize,
+        timeout: Option<Duration>,
+        linger: Duration,
+    ) -> Result<Vec<T>, Error<T>> {
         assert!(n > 0);
         unsafe {
             let mut msgs = Vec::with_capacity(n);
             match self
                 .counter()
                 .queue
-                .raw_recv_timeout(timeout, msgs.as_mut_ptr(), n)
+                .raw_recv_timeout(timeout, linger, msgs.as_mut_ptr(), n)
             {
                 Ok(count) => {
                     msgs.set_len(count);
@@ -455,6 +524,8 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
         if end < start {
             end += 2 * queue.size;
         }
+        let output = queue.counter.output.swap(0, Ordering::Relaxed);
+        let batches = queue.counter.batches.swap(0, Ordering::Relaxed);
         vec![
             (
                 "in",
@@ -464,7 +535,7 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
             (
                 "out",
                 stats::CounterType::Counted,
-                stats::CounterValue::Unsigned(queue.counter.output.swap(0, Ordering::Relaxed)),
+                stats::CounterValue::Unsigned(output),
             ),
             (
                 "overwritten",
@@ -476,6 +547,17 @@ impl<T: Send> stats::OwnedCountable for StatsHandle<T> {
                 stats::CounterType::Gauged,
                 stats::CounterValue::Unsigned((end - start) as u64),
             ),
+            (
+                "batches",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(batches),
+            ),
+            // 统计周期内平均每次读取的数量
+            (
+                "batch-size",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(if batches > 0 { output / batches } else { 0 }),
+            ),
         ]
     }
 
@@ -710,4 +792,40 @@ mod tests {
         let c = c.load(Ordering::Acquire);
         assert_eq!(c, 0, "new/drop count mismatch: new - drop = {}", c);
     }
+
+    #[test]
+    fn adaptive_batching() {
+        use stats::OwnedCountable;
+
+        let (s, r, stats) = bounded(16);
+        assert_eq!(
+            r.recv_batch(Some(Duration::from_millis(1))),
+            Err(Error::Timeout)
+        );
+
+        // 不足一批时等待linger后返回
+        r.set_batching(4, Duration::from_millis(20));
+        assert!(r.batching_enabled());
+        s.send(1).unwrap();
+        let now = Instant::now();
+        assert_eq!(r.recv_batch(None).unwrap(), vec![1]);
+        assert!(now.elapsed() >= Duration::from_millis(20));
+
+        // 满一批时立即返回
+        let h = thread::spawn(move || {
+            let r = r;
+            let batch = r.recv_batch(None).unwrap();
+            (batch, r)
+        });
+        s.send_all(vec![2, 3]).unwrap();
+        s.send_all(vec![4, 5, 6]).unwrap();
+        let (batch, r) = h.join().unwrap();
+        assert!(batch.len() >= 2 && batch.len() <= 4, "batch: {:?}", batch);
+        r.recv_n(4, None).unwrap();
+
+        let counters = stats.get_counters();
+        assert_eq!(counters[1].2, stats::CounterValue::Unsigned(6));
+        assert_eq!(counters[4].2, stats::CounterValue::Unsigned(3));
+        assert_eq!(counters[5].2, stats::CounterValue::Unsigned(2));
+    }
 }
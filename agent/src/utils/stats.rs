@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
@@ -77,6 +78,24 @@ pub struct Batch {
     timestamp: SystemTime,
 }
 
+impl fmt::Display for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.module)?;
+        for (k, v) in self.tags.iter() {
+            write!(f, " {}={}", k, v)?;
+        }
+        write!(f, ":")?;
+        for (name, _, value) in self.points.iter() {
+            match value {
+                CounterValue::Signed(i) => write!(f, " {}={}", name, i)?,
+                CounterValue::Unsigned(u) => write!(f, " {}={}", name, u)?,
+                CounterValue::Float(v) => write!(f, " {}={}", name, v)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Batch {
     pub fn encode(&self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
         let pb_stats: stats::Stats = self.to_stats();
@@ -132,6 +151,8 @@ pub struct Collector {
     remotes: Arc<Mutex<Option<Vec<IpAddr>>>>,
     sources: Arc<Mutex<Vec<Source>>>,
     pre_hooks: Arc<Mutex<Vec<Box<dyn FnMut() + Send>>>>,
+    // 各source最近一次采集的结果，计数器读取后即清零，诊断时只能读取这里的值
+    latest: Arc<Mutex<HashMap<String, Arc<Batch>>>>,
 
     min_interval: Arc<AtomicU64>,
 
@@ -171,6 +192,7 @@ impl Collector {
             remotes: Arc::new(Mutex::new(Some(remotes))),
             sources: Arc::new(Mutex::new(vec![])),
             pre_hooks: Arc::new(Mutex::new(vec![])),
+            latest: Arc::new(Mutex::new(HashMap::new())),
             min_interval: Arc::new(AtomicU64::new(min_interval.as_secs())),
             running: Arc::new((Mutex::new(false), Condvar::new())),
             thread: Mutex::new(None),
@@ -249,6 +271,20 @@ impl Collector {
         self.pre_hooks.lock().unwrap().push(hook);
     }
 
+    // 返回module最近一次采集的结果，按tags排序
+    pub fn latest_batches(&self, module: &str) -> Vec<Arc<Batch>> {
+        let mut batches = self
+            .latest
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|b| b.module == module)
+            .cloned()
+            .collect::<Vec<_>>();
+        batches.sort_by(|a, b| a.tags.cmp(&b.tags));
+        batches
+    }
+
     pub fn set_remotes(&self, remotes: Vec<IpAddr>) {
         self.remotes.lock().unwrap().replace(remotes);
     }
@@ -300,6 +336,7 @@ impl Collector {
         let running = self.running.clone();
         let sources = self.sources.clone();
         let pre_hooks = self.pre_hooks.clone();
+        let latest = self.latest.clone();
         let hostname = self.hostname.clone();
         let min_interval = self.min_interval.clone();
         let sender = self.sender.clone();
@@ -320,7 +357,12 @@ impl Collector {
                         let mut sources = sources.lock().unwrap();
                         let min_interval_loaded = min_interval.load(Ordering::Relaxed);
                         // TODO: use Vec::retain_mut after stablize in rust 1.61.0
+                        let count = sources.len();
                         sources.retain(|s| !s.countable.closed());
+                        if sources.len() != count {
+                            let keys = sources.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+                            latest.lock().unwrap().retain(|k, _| keys.contains(k));
+                        }
                         for source in sources.iter_mut() {
                             source.skip -= 1;
                             if source.skip > 0 {
@@ -344,6 +386,10 @@ impl Collector {
                                         "stats to send queue failed because queue have terminated"
                                     );
                                 }
+                                latest
+                                    .lock()
+                                    .unwrap()
+                                    .insert(source.to_string(), batch.clone());
                                 batches.push(batch);
                             }
                         }
@@ -49,6 +49,7 @@ import (
 	_ "github.com/deepflowys/deepflow/server/controller/trisolaris/services/grpc/healthcheck"
 	_ "github.com/deepflowys/deepflow/server/controller/trisolaris/services/grpc/synchronize"
 	_ "github.com/deepflowys/deepflow/server/controller/trisolaris/services/http/cache"
+	_ "github.com/deepflowys/deepflow/server/controller/trisolaris/services/http/remoteexec"
 	_ "github.com/deepflowys/deepflow/server/controller/trisolaris/services/http/upgrade"
 )
 
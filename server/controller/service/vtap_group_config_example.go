@@ -168,6 +168,38 @@ npb_dedup_enabled: 1
 static_config:
   # kubernetes-namespace，当只有一个K8s命名空间权限时，填写此值
   kubernetes-namespace:
+  # 容器环境下限定采集的Kubernetes命名空间，为空时不限制
+  # 仅采集属于这些命名空间的POD在宿主机侧的网卡以及POD内进程的eBPF数据
+  # POD所属命名空间通过容器挂载的ServiceAccount获取
+  capture-namespaces: []
+  # 开启后eBPF采集unix domain socket的收发数据，并合成127.0.0.1地址的流
+  # 流的端口由两端socket生成，应用日志中会带上对端进程ID，仅支持CO-RE方式加载的eBPF程序
+  ebpf-unix-socket-enabled: false
+  # 同一进程的系统调用数据持续产生、但golang uprobe数据超过该时间未出现时，
+  # 认为uprobe已失效，重新扫描进程并attach；该时间内没有任何eBPF数据时打印告警
+  # 设置为0关闭检测，非0时最小值为10s。probe attach失败不受此配置影响，总是上报异常
+  ebpf-probe-stall-timeout: 60s
+  # 业务标签文件路径，为空时不开启，文件修改后自动重新加载
+  # 根据IP(CIDR)或IP:端口匹配流和应用日志的两端，将标签以key/value形式附加到数据中
+  # 文件以.yaml/.yml结尾时按YAML解析，否则按CSV解析，格式分别如下:
+  #   - target: 10.0.0.0/8
+  #     labels: {domain: payment, owner: team-a}
+  #   - target: 192.168.1.1:8080
+  #     labels: {domain: order}
+  # CSV每行一条，第一列为目标，之后为key=value:
+  #   10.0.0.0/8,domain=payment,owner=team-a
+  #   192.168.1.1:8080,domain=order
+  # IP:端口精确匹配优先于CIDR，多个CIDR时取最长前缀
+  business-label-file: ""
+  # 根据采集器自身采集到的DNS应答（Answer段的A/AAAA记录）学习IP对应的域名，
+  # 发送前为流日志及应用日志的服务端IP补充server_domain字段，不主动发起反向解析
+  # capacity: 缓存的IP数，超出时淘汰最久未使用的
+  # ttl: 应答超过该时间后不再使用
+  # 注意: 开启l7-log-dns的metrics-only后DNS日志不再发送，也无法学习域名
+  server-domain:
+    enabled: false
+    capacity: 65536
+    ttl: 10m
   # ingress的类型，填写为kubernetes or openshift，默认kubernetes
   ingress-flavour: kubernetes
   # 配置后会使用配置文件中的analyzer-ip分别替换控制器下发的analyzer-ip
@@ -181,12 +213,26 @@ static_config:
   afpacket-blocks-enabled: false
   # afpacket收包内存大小，单位是M，当ANALYZER模式或该值大于0时，使用该值
   afpacket-blocks: 0
+  # afpacket fanout配置，开启后创建socket-count个dispatcher，其socket加入同一个fanout组分担收包
+  afpacket-fanout:
+    # 可选值：disabled/hash/cpu/qm，hash模式按流分发，cpu/qm模式会将同一个流拆分到多个dispatcher
+    mode: disabled
+    # 开启fanout时dispatcher的数量，此时src-interfaces不再决定dispatcher数量
+    socket-count: 1
+    # 单个socket收包占比超过该百分比时上报异常，取值范围[50, 100]
+    imbalance-threshold: 80
+  # 开启后通过SO_TIMESTAMPING使用网卡硬件时间戳，网卡或驱动不支持时回退为软件时间戳
+  # 硬件时间戳不经NTP/PTP时差校正，按time-source.ptp-utc-offset从TAI换算为UTC，须保证网卡PTP时钟已由ptp4l同步
+  afpacket-hardware-timestamp: false
   # trident-ctl listen port
   debug-listen-port: 0
   # packet collector and sniffer stats
   enable-debug-stats: false
   # analyzer模式下tap-type=3采集流量去重开关
   analyzer-dedup-disabled: false
+  # 镜像源(如交换机)按1/N采样时配置为N，analyzer模式下流量的包数和字节数按该比例放大，
+  # 并标记为采样数据，原始值同时保留，默认1表示未采样
+  analyzer-sampling-ratio: 1
   # where packet is considered to come by default if packet has no qinq with outer vlan pcp == 7
   # # ISP: 1-2,4-255, TOR: 3, default value is 3
   default-tap-type: 3
@@ -211,8 +257,24 @@ static_config:
   tap-mode: 0
   # 是否为云网关镜像流量
   cloud-gateway-traffic: false
+  # Windows采集器是否采集回环流量，仅tap-mode为0时生效
+  # 需要安装支持回环抓包的Npcap，开启后额外采集\Device\NPF_Loopback设备
+  # 使用Npcap而非WinDivert/pktmon：复用已有的Npcap驱动和BPF过滤，pktmon不支持旧版本Windows且无实时收包接口
+  # 同时出现在网卡和回环设备上的包(如WinNAT/Hyper-V回环)会在10ms内按IP/TCP头部去重，计入hairpin_duplicates
+  windows-loopback-capture-enabled: false
   # mirror-traffic-pcp will only be used with analyzer-mode
   mirror-traffic-pcp: 0
+  # analyzer模式下按src-interfaces中的网卡指定tap_type，未开启afpacket-fanout时生效
+  # tap-type为0时使用default-tap-type，vlan-tap-types优先于控制器下发的vlan映射
+  #
+  # example:
+  # analyzer-tap-types:
+  #   - interface: eth1
+  #     tap-type: 3
+  #     vlan-tap-types:
+  #       - vlan: 100
+  #         tap-type: 4
+  analyzer-tap-types: []
   # the size of queue linking flow generator and quadruple generator, minimum 65536:
   #    - 1-tagged-flow-to-quadruple-generator
   #    - 1-tagged-flow-to-app-protocol-logs
@@ -233,6 +295,24 @@ static_config:
   flow-sender-queue-size: 65536
   # the number of encoders for raw flow sender
   flow-sender-queue-count: 1
+  # 发送队列的自适应批量读取，按队列名配置，默认不启用
+  # 发送线程读到batch-size条数据或有数据后等待linger即处理并发送一批，
+  # 低速率时日志延迟不超过linger，不必等待读超时(3s)，高速率时仍按批发送
+  # 支持的队列：
+  #    - 2-doc-to-collector-sender
+  #    - 3-flow-to-collector-sender
+  #    - 3-protolog-to-collector-sender
+  #    - packet_sequence_block-to-sender
+  #    - otel-to-sender
+  #    - prometheus-to-sender
+  #    - telegraf-to-sender
+  # batch-size最大65536，为0时不启用；linger最大1s，默认10ms
+  # 各队列实际的平均批量大小见agent的queue统计中的batch-size
+  queue-batching: {}
+  #queue-batching:
+  #  3-protolog-to-collector-sender:
+  #    batch-size: 1024
+  #    linger: 10ms
   # 该队列在ANALYZER模式下使用:
   #    - 0.1-bytes-to-parse
   #    - 0.2-packet-to-flowgenerator
@@ -319,6 +399,113 @@ static_config:
   grpc-buffer-size: 5
   # l7日志会话聚合的时间窗口应不小于20秒，不大于300秒. 单位为s，默认120s
   l7-log-session-aggr-timeout: 120
+  # HTTP/2(gRPC)连接上持续该时长无请求时输出一条空闲连接日志，用于发现只有keepalive的长连接
+  # 单位为s，默认0s表示不输出
+  l7-log-idle-connection-interval: 0s
+  # HTTP/1 SSE(text/event-stream)响应或持续时间超过该值的响应视为流式响应，流式传输期间
+  # 每隔该时长输出一条携带累计字节数的持续记录；响应首字节时延超过该值的会话（如长轮询）
+  # 标记为流式响应。单位为s，默认60s，0s表示关闭
+  l7-log-http-stream-interval: 60s
+  # 应用日志中的用户名（如RADIUS User-Name）是否脱敏，开启后仅保留首字符
+  l7-log-user-name-masked: false
+  # 是否解压HTTP/1.x中gzip/deflate编码的Body以估算解码后的Body长度，默认关闭
+  # 开启后会增加CPU消耗，仅对单个包内可见的Body做流式解压
+  l7-log-http-decompress: false
+  # 从MySQL请求的SQL注释中提取trace id的key，为空时不提取，例如traceparent
+  # 匹配/* traceparent='00-xxx-yyy-01' */形式的注释，提取后从SQL语句中去掉该注释
+  # key为traceparent时仅保留其中的trace id部分
+  l7-log-sql-comment-trace-key: ""
+  # HTTP日志脱敏，在解析时将以下查询参数的值及头部字段替换为mask
+  # 头部仅对通过http-log-x-request-id等配置采集的字段生效
+  l7-log-http-scrub:
+    query-params:
+    - access_token
+    - token
+    - api_key
+    - apikey
+    - password
+    - passwd
+    - secret
+    - client_secret
+    - signature
+    - sessionid
+    headers:
+    - authorization
+    - proxy-authorization
+    - cookie
+    - set-cookie
+    mask: "***"
+  # HTTP日志中采集以下请求和响应头部的值，对HTTP/1、HTTP/2及eBPF采集的HTTPS均生效
+  # 头部名称不区分大小写，值同样按l7-log-http-scrub脱敏
+  l7-log-http-captured-headers: []
+  # 除标准Method及WebDAV Method外，识别和解析HTTP/1请求时额外接受的Method，区分大小写
+  # 如: [MKCALENDAR, REPORT]
+  l7-log-http-custom-methods: []
+  # DNS日志解析，默认解析Answer和Authority段
+  # 开启additional-section后同时解析Additional段，如SRV目标及NS对应的A/AAAA记录
+  # answers中支持A、AAAA、NS、SOA、DNAME、MX、SRV、TXT等记录，多个记录以;分隔
+  # 超过max-answers-length的部分截断，最小为64
+  # 开启metrics-enabled后按(客户端EPC, 解析服务器IP)聚合DNS日志，每分钟输出查询数、NXDOMAIN、
+  # SERVFAIL、超时数及P95时延等指标；同时开启metrics-only时仅输出指标，不再发送DNS日志
+  # 开启idn-decode后将查询名称中的punycode（xn--）标签解码为Unicode，开启name-normalization后
+  # 查询名称转为小写并去除末尾的.，便于按域名分组；名称被改写时原始名称保存在raw_query_name中
+  l7-log-dns:
+    authority-section: true
+    additional-section: false
+    max-answers-length: 1024
+    metrics-enabled: false
+    metrics-only: false
+    idn-decode: false
+    name-normalization: false
+  # 开启metrics-enabled后将Redis命令第一个key中的数字、UUID、哈希等可变段替换为*（如user:1001:profile为user:*:profile），
+  # 按(客户端EPC, 服务端IP及端口, key模式)统计每分钟的请求数、错误数及时延，用于发现热点key族；
+  # 同时开启metrics-only时仅输出指标，不再发送Redis日志；每分钟超出max-key-patterns的模式合并统计为空模式
+  l7-log-redis:
+    metrics-enabled: false
+    metrics-only: false
+    max-key-patterns: 1024
+  # 目的端口为以下端口的HTTP日志按Elasticsearch REST API补充操作类型（search、bulk、doc_get等）及索引名称
+  # 响应时延不小于slow-search-threshold的search/msearch请求标记为慢查询，ports为空时不处理
+  l7-log-elasticsearch:
+    ports:
+    - 9200
+    slow-search-threshold: 1s
+  # 同一客户端（IP及EPC）在请求失败（5xx、408、429）或超时后window内重发的相同方法、域名及路径的HTTP请求，
+  # 标记为重试，并与失败的请求使用相同的retry_group_id，供下游去重；超时仅识别新连接上的重试
+  l7-log-http-retry:
+    enabled: false
+    window: 1s
+  # 应用日志中较长字段的最大长度，单位为字节，按UTF-8字符边界截断，避免输出被拆开的多字节字符，0表示不截断
+  # http-url: HTTP请求的path
+  # sql: MySQL、ClickHouse、SQL Server的语句及错误信息
+  # redis: Redis的命令及回复
+  l7-log-truncate:
+    http-url: 2048
+    sql: 4096
+    redis: 1024
+  # 按协议配置会话聚合策略，未配置的协议为full
+  # protocol: 如Http1、Dns，忽略大小写
+  # policy:
+  #   full: 请求和响应合并为会话
+  #   request-only: 只发送请求，不等待响应
+  #   response-only: 只发送响应
+  #   error-only: 正常合并，只发送异常及未收到响应的日志
+  # Example:
+  #   l7-log-session-policies:
+  #   - protocol: Dns
+  #     policy: error-only
+  l7-log-session-policies: []
+  # 会话聚合后对L7日志采样，不影响L7指标
+  # mode:
+  #   disabled: 不采样
+  #   head-based: 逐条按比例随机采样
+  #   error-biased: 异常及未收到响应的日志全部保留，其余按比例采样
+  #   trace-id-consistent: 按trace id的哈希采样，相同采样率的采集器对同一trace的取舍一致，
+  #     即保留的trace在各采集器上的日志都完整保留；没有trace id的日志随机采样
+  # rate: 保留比例，单位为千分之一，范围[0, 1000]
+  l7-log-sampling:
+    mode: disabled
+    rate: 1000
   # 通过该脚本获取采集接口对应的MAC地址，该选项需要如下条件才能生效：
   # 1. 采集器页面配置虚拟机MAC解析项为虚拟机XML
   # 2. tap-mode为0
@@ -334,4 +521,226 @@ static_config:
   l7-protocol-inference-max-fail-count: 5
   # 一个服务的应用层协议类型推断结果的有效期，单位为秒，超过有效期后会触发下一次推断
   l7-protocol-inference-ttl: 60
+  # 应用日志解析的payload截取长度配置
+  l7-snaplen:
+    # 按tap type配置截取长度，覆盖采集器页面配置的应用日志采集包长，例如：
+    # tap-types:
+    #   3: 1024
+    tap-types: {}
+    # 开启后，流的前adaptive-packet-count个应用层包按adaptive-packet-size截取，之后恢复
+    # 用于采集完整的HTTP/Kafka头部，会增加一定的CPU开销
+    adaptive-enabled: false
+    # 协议名称，可选Http1/Http2/Dubbo/Mysql/Redis/Kafka/Mqtt/Dns
+    adaptive-protocols:
+      - Http1
+      - Http2
+      - Kafka
+    adaptive-packet-size: 4096
+    adaptive-packet-count: 4
+  # 应用解析失败的流导出pcap，便于复现协议解析问题
+  l7-parse-error-pcap:
+    enabled: false
+    # 流首次解析失败后开始缓存最近的包，失败次数达到fail-count时导出为<flow_id>.pcapng
+    fail-count: 3
+    # 每条流缓存的包数，范围1-128
+    ring-size: 16
+    # 每小时最多导出的文件数
+    max-files-per-hour: 10
+    file-directory: /var/lib/pcap/l7-parse-error
+  # 应用协议解析耗时统计和熔断
+  l7-parse-budget:
+    # 每个协议每隔多少次解析采样一次耗时，为0时不采样
+    sample-interval: 64
+    # 单包解析耗时上限，为0时不熔断
+    packet-budget: 1ms
+    # 流的应用解析连续超过耗时上限的次数达到trip-count时，
+    # 在cooldown时间内停止解析该流的应用协议，仅统计字节数和L4性能
+    trip-count: 3
+    cooldown: 60s
+
+  ## L7 Protocol Detection
+  # 流未指定应用协议时按优先级逐个尝试识别，order中的协议优先识别，
+  # 如Http1、Mysql，忽略大小写，未配置的协议按默认顺序排在后面
+  # max-attempts: 每条流最多尝试识别的包数，超过后不再识别，为0时不限制
+  # content-hint: 按载荷开头的特征跳过不可能匹配的协议，如二进制载荷不尝试HTTP/1和Redis
+  # min-payload-len: 载荷小于该长度的包（如TCP keepalive、TLS告警）不参与识别，也不计入max-attempts，最小为1
+  # protocol-min-payload-lens: 单个协议的最小载荷长度，小于该长度时跳过该协议但不排除，后续较长的包仍可识别为该协议，
+  #   所有候选协议都被跳过的包不计入max-attempts
+  # 以上配置同时作用于eBPF采集的数据
+  # 跳过的包数及识别次数分别计入flow-perf统计的l7_small_payload_skipped和l7_protocol_small_payload_skipped
+  # Example:
+  #   protocol-min-payload-lens:
+  #   - protocol: Mysql
+  #     min-len: 8
+  l7-protocol-detection:
+    order: []
+    max-attempts: 5
+    content-hint: true
+    min-payload-len: 2
+    protocol-min-payload-lens: []
+
+  ## UDP Request/Response Performance
+  # 按服务端端口匹配UDP请求和响应，计算流的响应时延(RRT)、抖动及未响应的请求数，
+  # DNS按事务ID匹配，NTP、其它协议按请求的先后顺序匹配，QUIC仅匹配握手阶段的Initial包
+  udp-perf:
+    dns-ports: [53]
+    ntp-ports: [123]
+    quic-ports: [443]
+    generic-ports: []
+    # 超过该时间未收到响应的请求计为丢失
+    request-timeout: 3s
+
+  ## L7 Alert Rules
+  # 采集器本地按分钟评估服务端应用指标，超过阈值时上报L7_ALERT_TRIGGERED异常
+  # 字段说明：
+  #   name: 规则名称
+  #   server-ip: 服务端IP或CIDR，为空时匹配所有服务
+  #   server-port: 服务端端口，为0时匹配所有端口
+  #   l7-protocol: 应用协议，如Http1、Mysql，为空时匹配所有协议
+  #   metric: request/response/client-error/server-error/timeout/rrt-avg/rrt-max，时延单位为us
+  #   threshold: 阈值，一分钟内的指标值超过阈值时告警
+  # Example:
+  #   l7-alert-rules:
+  #   - name: payment-5xx
+  #     server-ip: 10.1.0.0/16
+  #     server-port: 8080
+  #     l7-protocol: Http1
+  #     metric: server-error
+  #     threshold: 10
+  l7-alert-rules: []
+
+  ## L4 Log Filter
+  # 开启后l4流日志仅导出满足任一条件的流，在l4-log-collect-nps-threshold限速之前执行
+  # close-types: CloseType取值，默认为客户端及服务端异常结束类型
+  #   2: 传输-服务端重置, 3: 连接超时, 7: 建连-客户端SYN结束, 8: 断连-服务端半关,
+  #   9: 传输-客户端重置, 10: 建连-服务端SYN结束, 11: 断连-客户端半关, 13: 建连-客户端端口复用,
+  #   15: 建连-服务端直接重置, 17: 传输-服务端队列溢出, 18: 建连-客户端其他重置, 19: 建连-服务端其他重置
+  # min-duration: 流持续时长不小于该值，为0时不生效
+  # min-bytes: 双向累计字节数不小于该值，为0时不生效
+  # min-retrans-permille: 统计周期内重传包数占总包数的千分比不小于该值，为0时不生效
+  l4-log-filter:
+    enabled: false
+    close-types: [2, 3, 7, 8, 9, 10, 11, 13, 15, 17, 18, 19]
+    min-duration: 0s
+    min-bytes: 0
+    min-retrans-permille: 0
+
+  ## Security Events
+  # 根据只有SYN、未完成握手即结束的TCP流检测扫描及SYN Flood，在每个检测窗口结束时上报事件，
+  # 事件包含只有SYN的流数、SYN包数、不同端口/主机/源的数量等证据计数
+  # port-scan-threshold: 同一源对同一主机扫描的不同端口数达到该值时上报端口扫描
+  # host-scan-threshold: 同一源对同一端口扫描的不同主机数达到该值时上报主机扫描
+  # syn-flood-threshold: 同一目标端口只有SYN的流数达到该值时上报SYN Flood
+  # 阈值为0时不检测对应事件
+  # capacity: 每个窗口最多跟踪的源及目标数，超出后新出现的源和目标不再统计
+  security-event:
+    enabled: false
+    window: 60s
+    port-scan-threshold: 100
+    host-scan-threshold: 100
+    syn-flood-threshold: 1000
+    capacity: 10000
+
+  ## CPU Affinity
+  # 多路服务器上将dispatcher线程绑定到指定CPU，避免跨NUMA节点访问内存，仅Linux生效
+  # dispatcher-cores: 按dispatcher序号依次绑定的CPU，不可用的CPU会被忽略，为空时不绑定
+  #   FlowMap在dispatcher线程内运行，随dispatcher一起绑定
+  # numa-local-memory: 优先从绑定CPU所在NUMA节点分配dispatcher的队列、FlowMap及抓包缓存
+  # 配置绑定后按NUMA节点上报numa-hit、numa-miss、numa-foreign、other-node统计
+  # Example:
+  #   cpu-affinity:
+  #     dispatcher-cores: [2, 3, 26, 27]
+  #     numa-local-memory: true
+  cpu-affinity:
+    dispatcher-cores: []
+    numa-local-memory: false
+
+  ## Checksum Validation
+  # 生成流和指标前校验IPv4头部及TCP/UDP校验和，仅local模式生效，被篡改的镜像流量会产生虚假的流
+  # 分片、截断及TSO合并的包无法校验，视为正确；校验失败的包计入dispatcher的checksum_errors统计
+  # drop-corrupted: 丢弃校验失败的包，关闭时仅统计
+  # tx-checksum-offload: 网卡开启发送校验和卸载时，本机发出的包采集时校验和尚未填写，不做校验
+  checksum-validation:
+    enabled: false
+    drop-corrupted: false
+    tx-checksum-offload: true
+
+  ## VLAN Mode
+  # 报文带两层VLAN(QinQ或802.1Q叠加)时流的VLAN取值:
+  #   inner: 使用内层VLAN
+  #   outer: 保留外层VLAN，适用于按运营商外层标签区分租户的Trunk
+  #   strip: 忽略VLAN，适用于同一业务跨多个VLAN的Trunk口
+  vlan-mode: inner
+
+  ## Server Connection Metrics
+  # 按(服务端IP, 端口, EPC)统计每分钟新建TCP连接数和并发连接数，用于发现连接速率饱和
+  server-connection-metrics-enabled: false
+
+  ## TLS SNI Metrics
+  # 按(服务端IP, 端口, EPC, TLS SNI)统计每分钟流量和时延，区分共享IP或负载均衡后的HTTPS服务
+  # SNI从ClientHello中获取，需开启应用性能统计，无论是否开启都会写入流日志的tls_sni字段
+  tls-sni-metrics-enabled: false
+
+  ## Pipeline Mode
+  # 资源受限的边缘节点可只运行部分处理流程，关闭的流程不创建对应的队列和线程
+  # 仅在启动时读取，运行中修改会被忽略并打印告警，需重启采集器生效
+  #   full: 同时生成指标和应用日志
+  #   logs-only: 不生成秒级和分钟级指标，L4流日志和应用日志照常发送
+  #   metrics-only: 不解析和发送应用日志，也不启动eBPF采集
+  pipeline-mode: full
+
+  ## Time Source
+  # 校正系统时钟的时间源，用于包时间戳、流和指标的时间
+  #   system: 系统时钟，开启NTP时按控制器校正到秒
+  #   ptp: 读取ptp4l同步的网卡硬件时钟(PHC)，按ns精度校正，不再使用NTP，PHC不可用时回退到NTP
+  time-source:
+    mode: system
+    ptp-device: /dev/ptp0
+    # PHC通常为TAI时间，减去该偏移得到UTC，同时用于afpacket硬件时间戳
+    ptp-utc-offset: 37s
+    # 读取PHC的间隔，取值范围[100ms, 60s]
+    ptp-sync-interval: 1s
+
+  ## IP Anonymization
+  # 发送l4和l7流日志前，对cidrs范围内的IP做匿名化，包括HTTP的client_ip(X-Forwarded-For)、
+  # DNS应答和RADIUS的nas_ip
+  # mode: disabled/truncate/prefix-preserving
+  #   truncate: IPv4保留/24前缀，IPv6保留/48前缀
+  #   prefix-preserving: Crypto-PAn(AES-128)前缀保持映射，需要配置key，
+  #     key经SHA256得到Crypto-PAn的32字节密钥，key为空时退化为truncate
+  # Example:
+  #   ip-anonymization:
+  #     mode: prefix-preserving
+  #     cidrs:
+  #     - 0.0.0.0/0
+  #     key: my-secret-key
+  ip-anonymization:
+    mode: disabled
+    cidrs: []
+    key: ""
+
+  ## Profiler
+  # 开启后在listen-address上提供按需采集的调试HTTP接口，仅Linux生效，默认只监听本地
+  # 请求需携带Header: Authorization: Bearer <token>，token为空时不开启
+  #   GET /debug/pprof/profile?seconds=30: CPU profile，pprof protobuf格式，可用go tool pprof查看，
+  #     时长不超过max-seconds，同一时刻只允许一个profile，采集器停止或重启时正在进行的profile被取消
+  #   GET /debug/pprof/heap: 并非按内存分配统计的heap profile，仅输出/proc/self/status和/proc/self/maps中的
+  #     内存统计(文本格式，不能用go tool pprof查看)
+  # Example:
+  #   curl -H "Authorization: Bearer my-token" -o profile.pb http://127.0.0.1:30037/debug/pprof/profile?seconds=30
+  profiler:
+    enabled: false
+    listen-address: 127.0.0.1:30037
+    token: ""
+    max-seconds: 300
+
+  ## L7 RRT Upper Bound
+  # 时钟跳变或响应错配会产生极端的RRT，超过upper-bound的RRT按upper-bound统计，
+  # 单独计入rrt_outlier_count，并在调用日志中标记rrt_outlier
+  # protocol: 如Http1、Mysql，忽略大小写
+  # Example:
+  #   l7-rrt-upper-bounds:
+  #   - protocol: Http1
+  #     upper-bound: 60s
+  l7-rrt-upper-bounds: []
 `)
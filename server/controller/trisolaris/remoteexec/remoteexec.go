@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+package remoteexec
+
+import (
+	"errors"
+	"fmt"
+	"sync"
+	"time"
+
+	api "github.com/deepflowys/deepflow/message/trident"
+	"github.com/op/go-logging"
+)
+
+var log = logging.MustGetLogger("trisolaris/remoteexec")
+
+var (
+	ErrNotConnected = errors.New("vtap is not connected to this controller")
+	ErrTimeout      = errors.New("remote execute timeout")
+)
+
+type result struct {
+	content []byte
+	errmsg  string
+	done    chan struct{}
+}
+
+// 一个采集器的命令流，采集器主动建立，请求和结果通过request_id对应
+type agentStream struct {
+	sync.Mutex
+	stream  api.Synchronizer_RemoteExecuteServer
+	results map[uint64]*result
+}
+
+type Manager struct {
+	sync.RWMutex
+	streams   map[string]*agentStream
+	requestID uint64
+}
+
+var manager = &Manager{streams: make(map[string]*agentStream)}
+
+func GetManager() *Manager {
+	return manager
+}
+
+// 处理采集器建立的流，首个消息携带ctrl_ip和ctrl_mac，流断开时返回
+func (m *Manager) Serve(in api.Synchronizer_RemoteExecuteServer) error {
+	first, err := in.Recv()
+	if err != nil {
+		return err
+	}
+	key := first.GetCtrlIp() + "-" + first.GetCtrlMac()
+	s := &agentStream{stream: in, results: make(map[uint64]*result)}
+	m.Lock()
+	m.streams[key] = s
+	m.Unlock()
+	log.Infof("vtap(%s) remote exec stream established", key)
+	defer func() {
+		m.Lock()
+		if m.streams[key] == s {
+			delete(m.streams, key)
+		}
+		m.Unlock()
+		log.Infof("vtap(%s) remote exec stream closed", key)
+	}()
+
+	for {
+		response, err := in.Recv()
+		if err != nil {
+			return err
+		}
+		s.Lock()
+		r, ok := s.results[response.GetRequestId()]
+		if ok {
+			r.content = append(r.content, response.GetContent()...)
+			if response.GetErrmsg() != "" {
+				r.errmsg = response.GetErrmsg()
+			}
+			if response.GetEof() {
+				delete(s.results, response.GetRequestId())
+				close(r.done)
+			}
+		}
+		s.Unlock()
+	}
+}
+
+// 向采集器下发命令并等待完整结果，maxBytes为0时使用采集器的默认上限
+func (m *Manager) Execute(key string, command api.RemoteCommand, format api.OutputFormat, maxBytes uint32, timeout time.Duration) ([]byte, error) {
+	m.Lock()
+	s, ok := m.streams[key]
+	m.requestID++
+	requestID := m.requestID
+	m.Unlock()
+	if !ok {
+		return nil, ErrNotConnected
+	}
+
+	r := &result{done: make(chan struct{})}
+	s.Lock()
+	s.results[requestID] = r
+	// grpc流不支持并发发送
+	err := s.stream.Send(&api.RemoteExecRequest{
+		RequestId: &requestID,
+		Command:   &command,
+		Format:    &format,
+		MaxBytes:  &maxBytes,
+	})
+	s.Unlock()
+	if err != nil {
+		s.Lock()
+		delete(s.results, requestID)
+		s.Unlock()
+		return nil, err
+	}
+	log.Infof("vtap(%s) remote execute %s format %s request_id %d", key, command, format, requestID)
+
+	select {
+	case <-r.done:
+	case <-time.After(timeout):
+		s.Lock()
+		delete(s.results, requestID)
+		s.Unlock()
+		return nil, ErrTimeout
+	}
+	if r.errmsg != "" {
+		return r.content, fmt.Errorf("remote execute failed: %s", r.errmsg)
+	}
+	return r.content, nil
+}
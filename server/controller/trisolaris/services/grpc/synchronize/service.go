@@ -25,6 +25,7 @@ import (
 
 	"github.com/deepflowys/deepflow/server/controller/genesis"
 	grpcserver "github.com/deepflowys/deepflow/server/controller/grpc"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris/remoteexec"
 )
 
 type service struct {
@@ -75,6 +76,10 @@ func (s *service) Upgrade(r *api.UpgradeRequest, in api.Synchronizer_UpgradeServ
 	return s.upgradeEvent.Upgrade(r, in)
 }
 
+func (s *service) RemoteExecute(in api.Synchronizer_RemoteExecuteServer) error {
+	return remoteexec.GetManager().Serve(in)
+}
+
 func (s *service) Query(ctx context.Context, in *api.NtpRequest) (*api.NtpResponse, error) {
 	return s.ntpEvent.Query(ctx, in)
 }
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+package remoteexec
+
+import (
+	"fmt"
+	"time"
+
+	"github.com/gin-gonic/gin"
+	"github.com/op/go-logging"
+
+	api "github.com/deepflowys/deepflow/message/trident"
+	models "github.com/deepflowys/deepflow/server/controller/db/mysql"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris/dbmgr"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris/remoteexec"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris/server/http"
+	"github.com/deepflowys/deepflow/server/controller/trisolaris/server/http/common"
+)
+
+var log = logging.MustGetLogger("trisolaris/remoteexec")
+
+const EXECUTE_TIMEOUT = 30 * time.Second
+
+func init() {
+	http.Register(NewRemoteExecService())
+}
+
+type RemoteExecService struct{}
+
+func NewRemoteExecService() *RemoteExecService {
+	return &RemoteExecService{}
+}
+
+type RemoteExecInfo struct {
+	// DUMP_QUEUE_STATS, DUMP_POLICY, FLOW_TABLE_SNAPSHOT, ROTATE_LOG
+	Command string `json:"command" binding:"required"`
+	// TEXT, JSON, CSV，默认为TEXT
+	Format   string `json:"format"`
+	MaxBytes uint32 `json:"max_bytes"`
+}
+
+func RemoteExec(c *gin.Context) {
+	lcuuid := c.Param("lcuuid")
+	if lcuuid == "" {
+		common.Response(c, nil, common.NewReponse("FAILED", "", nil, "not find lcuuid param"))
+		return
+	}
+	execInfo := RemoteExecInfo{}
+	err := c.BindJSON(&execInfo)
+	if err != nil {
+		log.Error(err)
+		common.Response(c, nil, common.NewReponse("FAILED", "", nil, fmt.Sprintf("%s", err)))
+		return
+	}
+	command, ok := api.RemoteCommand_value[execInfo.Command]
+	if !ok {
+		common.Response(c, nil, common.NewReponse("FAILED", "", nil, fmt.Sprintf("unsupported command %s", execInfo.Command)))
+		return
+	}
+	format := api.OutputFormat_TEXT
+	if execInfo.Format != "" {
+		f, ok := api.OutputFormat_value[execInfo.Format]
+		if !ok {
+			common.Response(c, nil, common.NewReponse("FAILED", "", nil, fmt.Sprintf("unsupported format %s", execInfo.Format)))
+			return
+		}
+		format = api.OutputFormat(f)
+	}
+	vtap, err := dbmgr.DBMgr[models.VTap](trisolaris.GetDB()).GetFromLcuuid(lcuuid)
+	if err != nil {
+		log.Error(err)
+		common.Response(c, nil, common.NewReponse("FAILED", "", nil, fmt.Sprintf("%s", err)))
+		return
+	}
+	key := vtap.CtrlIP + "-" + vtap.CtrlMac
+	content, err := remoteexec.GetManager().Execute(key, api.RemoteCommand(command), format, execInfo.MaxBytes, EXECUTE_TIMEOUT)
+	if err != nil {
+		log.Errorf("vtap(%s, %s) remote execute %s failed: %s", vtap.Name, key, execInfo.Command, err)
+		common.Response(c, nil, common.NewReponse("FAILED", "", string(content), fmt.Sprintf("%s", err)))
+		return
+	}
+	common.Response(c, nil, common.NewReponse("SUCCESS", "", string(content), ""))
+}
+
+func (*RemoteExecService) Register(mux *gin.Engine) {
+	mux.POST("v1/remote-exec/vtap/:lcuuid/", RemoteExec)
+}
@@ -364,6 +364,38 @@ var ColumnMod611 = []*ColumnMod{
 	},
 }
 
+// 采集器的syscall_cap_seq扩展为64位
+var ColumnMod613 = []*ColumnMod{
+	&ColumnMod{
+		Db:            "flow_log",
+		Table:         "l7_flow_log",
+		ColumnName:    "syscall_cap_seq_0",
+		NewColumnType: ckdb.UInt64,
+		DropIndex:     false,
+	},
+	&ColumnMod{
+		Db:            "flow_log",
+		Table:         "l7_flow_log",
+		ColumnName:    "syscall_cap_seq_1",
+		NewColumnType: ckdb.UInt64,
+		DropIndex:     false,
+	},
+	&ColumnMod{
+		Db:            "flow_log",
+		Table:         "l7_flow_log_local",
+		ColumnName:    "syscall_cap_seq_0",
+		NewColumnType: ckdb.UInt64,
+		DropIndex:     true,
+	},
+	&ColumnMod{
+		Db:            "flow_log",
+		Table:         "l7_flow_log_local",
+		ColumnName:    "syscall_cap_seq_1",
+		NewColumnType: ckdb.UInt64,
+		DropIndex:     true,
+	},
+}
+
 var u64ColumnNameAdd612 = []string{"syn_count", "synack_count", "retrans_syn", "retrans_synack", "cit_count"}
 var u32ColumnNameAdd612 = []string{"cit_max"}
 var f64ColumnNameAdd612 = []string{"cit_sum"}
@@ -413,6 +445,28 @@ var ColumnAdd612 = []*ColumnAdds{
 	},
 }
 
+var flowMetricsAppTableAdd613 = []string{
+	"vtap_app_port.1m", "vtap_app_port.1m_local",
+	"vtap_app_port.1s", "vtap_app_port.1s_local",
+	"vtap_app_edge_port.1m", "vtap_app_edge_port.1m_local",
+	"vtap_app_edge_port.1s", "vtap_app_edge_port.1s_local",
+}
+
+var ColumnAdd613 = []*ColumnAdds{
+	&ColumnAdds{
+		Dbs:         []string{"flow_metrics"},
+		Tables:      append(flowMetricsTableAdd612, flowMetricsAppTableAdd613...),
+		ColumnNames: []string{"server_name"},
+		ColumnType:  ckdb.LowCardinalityString,
+	},
+	&ColumnAdds{
+		Dbs:         []string{"flow_metrics"},
+		Tables:      flowMetricsAppTableAdd613,
+		ColumnNames: []string{"key_pattern"},
+		ColumnType:  ckdb.String,
+	},
+}
+
 func getTables(connect *sql.DB, db, tableName string) ([]string, error) {
 	sql := fmt.Sprintf("SHOW TABLES IN %s", db)
 	rows, err := connect.Query(sql)
@@ -636,12 +690,16 @@ func NewCKIssu(primaryAddr, secondaryAddr, username, password string) (*Issu, er
 		username:      username,
 		password:      password,
 		// columnRenames: ColumnRename572,
+		columnMods: ColumnMod613,
 	}
 
 	columnAdds := []*ColumnAdd{}
 	for _, adds := range ColumnAdd612 {
 		columnAdds = append(columnAdds, getColumnAdds(adds)...)
 	}
+	for _, adds := range ColumnAdd613 {
+		columnAdds = append(columnAdds, getColumnAdds(adds)...)
+	}
 	i.columnAdds = columnAdds
 
 	var err error
@@ -33,7 +33,8 @@ const (
 	EdgeCode    = zerodoc.IPPath | zerodoc.L3EpcIDPath
 	MainAddCode = zerodoc.RegionID | zerodoc.HostID | zerodoc.L3Device | zerodoc.SubnetID | zerodoc.PodNodeID | zerodoc.AZID | zerodoc.PodGroupID | zerodoc.PodNSID | zerodoc.PodID | zerodoc.PodClusterID | zerodoc.ServiceID | zerodoc.Resource
 	EdgeAddCode = zerodoc.RegionIDPath | zerodoc.HostIDPath | zerodoc.L3DevicePath | zerodoc.SubnetIDPath | zerodoc.PodNodeIDPath | zerodoc.AZIDPath | zerodoc.PodGroupIDPath | zerodoc.PodNSIDPath | zerodoc.PodIDPath | zerodoc.PodClusterIDPath | zerodoc.ServiceIDPath | zerodoc.ResourcePath
-	PortAddCode = zerodoc.IsKeyService
+	PortAddCode = zerodoc.IsKeyService | zerodoc.ServerName
+	AppAddCode  = zerodoc.KeyPattern
 )
 
 func DocumentExpand(doc *app.Document, platformData *grpc.PlatformInfoTable) error {
@@ -51,6 +52,10 @@ func DocumentExpand(doc *app.Document, platformData *grpc.PlatformInfoTable) err
 	if t.Code&zerodoc.ServerPort == zerodoc.ServerPort {
 		t.Code |= PortAddCode
 	}
+	// 未携带server_name/key_pattern的数据写入空字符串
+	if t.Code&zerodoc.L7Protocol == zerodoc.L7Protocol {
+		t.Code |= AppAddCode
+	}
 	if t.Code&EdgeCode == EdgeCode {
 		t.Code |= EdgeAddCode
 
@@ -77,8 +77,8 @@ type L7Base struct {
 	SyscallTraceIDResponse uint64
 	SyscallThread0         uint32
 	SyscallThread1         uint32
-	SyscallCapSeq0         uint32
-	SyscallCapSeq1         uint32
+	SyscallCapSeq0         uint64
+	SyscallCapSeq1         uint64
 }
 
 func L7BaseColumns() []*ckdb.Column {
@@ -121,8 +121,8 @@ func L7BaseColumns() []*ckdb.Column {
 		ckdb.NewColumn("syscall_trace_id_response", ckdb.UInt64).SetComment("SyscallTraceID-响应"),
 		ckdb.NewColumn("syscall_thread_0", ckdb.UInt32).SetComment("Syscall线程-请求"),
 		ckdb.NewColumn("syscall_thread_1", ckdb.UInt32).SetComment("Syscall线程-响应"),
-		ckdb.NewColumn("syscall_cap_seq_0", ckdb.UInt32).SetComment("Syscall序列号-请求"),
-		ckdb.NewColumn("syscall_cap_seq_1", ckdb.UInt32).SetComment("Syscall序列号-响应"),
+		ckdb.NewColumn("syscall_cap_seq_0", ckdb.UInt64).SetComment("Syscall序列号-请求"),
+		ckdb.NewColumn("syscall_cap_seq_1", ckdb.UInt64).SetComment("Syscall序列号-响应"),
 	)
 
 	return columns
@@ -231,10 +231,10 @@ func (f *L7Base) WriteBlock(block *ckdb.Block) error {
 	if err := block.WriteUInt32(f.SyscallThread1); err != nil {
 		return err
 	}
-	if err := block.WriteUInt32(f.SyscallCapSeq0); err != nil {
+	if err := block.WriteUInt64(f.SyscallCapSeq0); err != nil {
 		return err
 	}
-	if err := block.WriteUInt32(f.SyscallCapSeq1); err != nil {
+	if err := block.WriteUInt64(f.SyscallCapSeq1); err != nil {
 		return err
 	}
 
@@ -541,7 +541,7 @@ func (h *L7Logger) fillDubbo(l *pb.AppProtoLogsData) {
 	h.RequestDomain = info.ServiceName
 	h.RequestResource = info.MethodName
 	if info.Id != 0 {
-		h.requestId = uint64(info.Id)
+		h.requestId = info.Id
 		h.RequestId = &h.requestId
 	}
 
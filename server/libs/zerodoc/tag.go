@@ -84,6 +84,9 @@ const (
 	TAPPort
 	IsKeyService
 	L7Protocol
+	_          // 1<<52
+	ServerName // 1<<53
+	KeyPattern // 1<<54
 )
 
 const (
@@ -286,8 +289,15 @@ type Field struct {
 	IsKeyService uint8
 	L7Protocol   datatype.L7Protocol
 
+	ServerName string // TLS SNI等区分同一服务端口后的不同域名
+	KeyPattern string // Redis等的key模式
+
 	TagType  uint8
 	TagValue uint16
+
+	// 双栈服务ID，由agent按同一接口的IPv4和IPv6地址设置，0表示非双栈
+	DualStackID  uint32
+	DualStackID1 uint32
 }
 
 func newMetricsMinuteTable(id MetricsTableID, engine ckdb.EngineType, version string) *ckdb.Table {
@@ -427,12 +437,13 @@ func MetricsTableNameToID(name string) MetricsTableID {
 const (
 	BaseCode     = AZID | HostID | IP | L3Device | L3EpcID | PodClusterID | PodGroupID | PodID | PodNodeID | PodNSID | RegionID | SubnetID | TAPType | VTAPID | ServiceID | Resource
 	BasePathCode = AZIDPath | HostIDPath | IPPath | L3DevicePath | L3EpcIDPath | PodClusterIDPath | PodGroupIDPath | PodIDPath | PodNodeIDPath | PodNSIDPath | RegionIDPath | SubnetIDPath | TAPSide | TAPType | VTAPID | ServiceIDPath | ResourcePath
-	BasePortCode = Protocol | ServerPort | IsKeyService
+	BasePortCode = Protocol | ServerPort | IsKeyService | ServerName
+	BaseAppCode  = L7Protocol | KeyPattern
 
 	VTAP_FLOW_PORT      = BaseCode | BasePortCode | Direction
 	VTAP_FLOW_EDGE_PORT = BasePathCode | BasePortCode | TAPPort
-	VTAP_APP_PORT       = BaseCode | BasePortCode | Direction | L7Protocol
-	VTAP_APP_EDGE_PORT  = BasePathCode | BasePortCode | TAPPort | L7Protocol
+	VTAP_APP_PORT       = BaseCode | BasePortCode | Direction | BaseAppCode
+	VTAP_APP_EDGE_PORT  = BasePathCode | BasePortCode | TAPPort | BaseAppCode
 )
 
 var metricsTableCodes = []Code{
@@ -478,6 +489,13 @@ func marshalUint16WithSpecialID(v int16) string {
 	return strconv.FormatUint(uint64(v)&math.MaxUint16, 10)
 }
 
+// line protocol中tag值的逗号、空格、等号需要转义
+var tagValueEscaper = strings.NewReplacer(",", "\\,", " ", "\\ ", "=", "\\=")
+
+func escapeTagValue(s string) string {
+	return tagValueEscaper.Replace(s)
+}
+
 func unmarshalUint16WithSpecialID(s string) (int16, error) {
 	i, err := strconv.ParseInt(s, 10, 64)
 	if err != nil {
@@ -601,6 +619,11 @@ func (t *Tag) MarshalTo(b []byte) int {
 		}
 	}
 
+	if t.Code&KeyPattern != 0 {
+		offset += copy(b[offset:], ",key_pattern=")
+		offset += copy(b[offset:], escapeTagValue(t.KeyPattern))
+	}
+
 	if t.Code&L3Device != 0 {
 		offset += copy(b[offset:], ",l3_device_id=")
 		offset += copy(b[offset:], strconv.FormatUint(uint64(t.L3DeviceID), 10))
@@ -766,6 +789,11 @@ func (t *Tag) MarshalTo(b []byte) int {
 		offset += copy(b[offset:], strconv.FormatUint(uint64(t.ResourceGl2Type1), 10))
 	}
 
+	if t.Code&ServerName != 0 {
+		offset += copy(b[offset:], ",server_name=")
+		offset += copy(b[offset:], escapeTagValue(t.ServerName))
+	}
+
 	if t.Code&ServerPort != 0 {
 		offset += copy(b[offset:], ",server_port=")
 		offset += copy(b[offset:], strconv.FormatUint(uint64(t.ServerPort), 10))
@@ -901,6 +929,10 @@ func GenTagColumns(code Code) []*ckdb.Column {
 		columns = append(columns, ckdb.NewColumnWithGroupBy("is_key_service", ckdb.UInt8).SetComment("是否属于关键服务0: 否, 1: 是").SetIndex(ckdb.IndexMinmax))
 	}
 
+	if code&KeyPattern != 0 {
+		columns = append(columns, ckdb.NewColumnWithGroupBy("key_pattern", ckdb.String).SetComment("key模式, 数字等可变部分替换为*"))
+	}
+
 	if code&L3Device != 0 {
 		columns = append(columns, ckdb.NewColumnWithGroupBy("l3_device_id", ckdb.UInt32).SetComment("ip对应的资源ID"))
 		columns = append(columns, ckdb.NewColumnWithGroupBy("l3_device_type", ckdb.UInt8).SetComment("ip对应的资源类型"))
@@ -1024,6 +1056,10 @@ func GenTagColumns(code Code) []*ckdb.Column {
 		columns = append(columns, ckdb.NewColumnWithGroupBy("service_id_1", ckdb.UInt32).SetComment("ip4/6_1对应的服务ID"))
 	}
 
+	if code&ServerName != 0 {
+		columns = append(columns, ckdb.NewColumnWithGroupBy("server_name", ckdb.LowCardinalityString).SetComment("服务端域名, 如TLS SNI"))
+	}
+
 	if code&ServerPort != 0 {
 		columns = append(columns, ckdb.NewColumnWithGroupBy("server_port", ckdb.UInt16).SetIndex(ckdb.IndexSet).SetComment("服务端端口"))
 	}
@@ -1158,6 +1194,12 @@ func (t *Tag) WriteBlock(block *ckdb.Block, time uint32) error {
 		}
 	}
 
+	if code&KeyPattern != 0 {
+		if err := block.WriteString(t.KeyPattern); err != nil {
+			return err
+		}
+	}
+
 	if code&L3Device != 0 {
 		if err := block.WriteUInt32(t.L3DeviceID); err != nil {
 			return err
@@ -1377,6 +1419,12 @@ func (t *Tag) WriteBlock(block *ckdb.Block, time uint32) error {
 		}
 	}
 
+	if code&ServerName != 0 {
+		if err := block.WriteString(t.ServerName); err != nil {
+			return err
+		}
+	}
+
 	if code&ServerPort != 0 {
 		if err := block.WriteUInt16(t.ServerPort); err != nil {
 			return err
@@ -1501,8 +1549,12 @@ func (t *Tag) ReadFromPB(p *pb.MiniTag) {
 	t.TAPPort = datatype.TapPort(p.Field.TapPort)
 	t.TAPType = TAPTypeEnum(p.Field.TapType)
 	t.L7Protocol = datatype.L7Protocol(p.Field.L7Protocol)
+	t.ServerName = p.Field.ServerName
+	t.KeyPattern = p.Field.KeyPattern
 	t.TagType = uint8(p.Field.TagType)
 	t.TagValue = uint16(p.Field.TagValue)
+	t.DualStackID = p.Field.ServiceId
+	t.DualStackID1 = p.Field.ServiceId1
 }
 
 func (t *Tag) SetID(id string) {
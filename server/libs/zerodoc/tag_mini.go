@@ -32,7 +32,7 @@ import (
 
 const (
 	MINI_FIELD_FULL_CODES = IP | IPPath | L3EpcID | L3EpcIDPath | VTAPID | Protocol | ServerPort |
-		MAC | MACPath | Direction | TAPType | ACLGID | L7Protocol | TagType | TagValue
+		MAC | MACPath | Direction | TAPType | ACLGID | L7Protocol | TagType | TagValue | ServerName | KeyPattern
 )
 
 type MiniField struct {
@@ -61,6 +61,12 @@ type MiniField struct {
 
 	TagType  uint8 // (8B)
 	TagValue uint16
+
+	DualStackID  uint32
+	DualStackID1 uint32
+
+	ServerName string
+	KeyPattern string
 }
 
 func (f *MiniField) IP() net.IP {
@@ -102,6 +108,10 @@ func (f *MiniField) WriteToPB(p *pb.MiniField) {
 	p.L7Protocol = uint32(f.L7Protocol)
 	p.TagType = uint32(f.TagType)
 	p.TagValue = uint32(f.TagValue)
+	p.ServiceId = f.DualStackID
+	p.ServiceId1 = f.DualStackID1
+	p.ServerName = f.ServerName
+	p.KeyPattern = f.KeyPattern
 }
 
 func (f *MiniField) SetIP1(ip net.IP) {
@@ -164,6 +174,11 @@ func (t *MiniTag) MarshalTo(b []byte) int {
 		}
 	}
 
+	if t.Code&KeyPattern != 0 {
+		offset += copy(b[offset:], ",key_pattern=")
+		offset += copy(b[offset:], escapeTagValue(t.KeyPattern))
+	}
+
 	if t.Code&L3EpcID != 0 {
 		offset += copy(b[offset:], ",l3_epc_id=")
 		offset += copy(b[offset:], marshalUint16WithSpecialID(t.L3EpcID))
@@ -192,6 +207,11 @@ func (t *MiniTag) MarshalTo(b []byte) int {
 		offset += copy(b[offset:], strconv.FormatUint(uint64(t.Protocol), 10))
 	}
 
+	if t.Code&ServerName != 0 {
+		offset += copy(b[offset:], ",server_name=")
+		offset += copy(b[offset:], escapeTagValue(t.ServerName))
+	}
+
 	if t.Code&ServerPort != 0 {
 		offset += copy(b[offset:], ",server_port=")
 		offset += copy(b[offset:], strconv.FormatUint(uint64(t.ServerPort), 10))
@@ -243,6 +243,17 @@ func checkTagAndMiniTagEqual(tag *Tag, miniTag *MiniTag) bool {
 		}
 	}
 
+	if code&ServerName != 0 {
+		if tag.ServerName != miniTag.ServerName {
+			return false
+		}
+	}
+	if code&KeyPattern != 0 {
+		if tag.KeyPattern != miniTag.KeyPattern {
+			return false
+		}
+	}
+
 	if code&TagType != 0 {
 		if tag.TagType != miniTag.TagType {
 			return false
@@ -253,6 +264,9 @@ func checkTagAndMiniTagEqual(tag *Tag, miniTag *MiniTag) bool {
 			return false
 		}
 	}
+	if tag.DualStackID != miniTag.DualStackID || tag.DualStackID1 != miniTag.DualStackID1 {
+		return false
+	}
 	return true
 }
 
@@ -298,21 +312,25 @@ func TestEncodeMiniTag(t *testing.T) {
 
 	edgeMiniTag := &MiniTag{
 		MiniField: &MiniField{
-			IsIPv6:     1,
-			rawIP:      [net.IPv6len]byte{0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1},
-			rawIP1:     [net.IPv6len]byte{0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2},
-			L3EpcID:    15,
-			L3EpcID1:   16,
-			VTAPID:     24,
-			Protocol:   layers.IPProtocolTCP,
-			ServerPort: 5324,
-			Direction:  ClientToServer,
-			TAPType:    ToR,
-			ACLGID:     16,
-			TagType:    TAG_TYPE_TUNNEL_IP_ID,
-			TagValue:   18,
+			IsIPv6:       1,
+			rawIP:        [net.IPv6len]byte{0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1},
+			rawIP1:       [net.IPv6len]byte{0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2},
+			L3EpcID:      15,
+			L3EpcID1:     16,
+			VTAPID:       24,
+			Protocol:     layers.IPProtocolTCP,
+			ServerPort:   5324,
+			Direction:    ClientToServer,
+			TAPType:      ToR,
+			ACLGID:       16,
+			TagType:      TAG_TYPE_TUNNEL_IP_ID,
+			TagValue:     18,
+			ServerName:   "api.example.com",
+			KeyPattern:   "user:*:profile",
+			DualStackID:  10,
+			DualStackID1: 20,
 		},
-		Code: IPPath | L3EpcIDPath | VTAPID | Protocol | ServerPort | Direction | TAPType | ACLGID | TagType | TagValue,
+		Code: IPPath | L3EpcIDPath | VTAPID | Protocol | ServerPort | Direction | TAPType | ACLGID | TagType | TagValue | ServerName | KeyPattern,
 	}
 
 	pbMtagE.Reset()
@@ -330,6 +348,9 @@ func TestEncodeMiniTag(t *testing.T) {
 		t.Error("tag:     ", edgeTag)
 		t.Error("mini tag:", edgeMiniTag)
 	}
+	if edgeTag.DualStackID != 10 || edgeTag.DualStackID1 != 20 {
+		t.Errorf("dual stack id mismatch: %d %d", edgeTag.DualStackID, edgeTag.DualStackID1)
+	}
 
 	encoder.Reset()
 	edgeMiniTag.Direction = ServerToClient
@@ -351,6 +372,18 @@ func TestEncodeMiniTag(t *testing.T) {
 	}
 }
 
+func TestMarshalStringTags(t *testing.T) {
+	f := Field{ServerName: "api.example.com", KeyPattern: "order:*:state a=b,c"}
+	expected := ",key_pattern=order:*:state\\ a\\=b\\,c,server_name=api.example.com"
+	if s := f.NewTag(ServerName | KeyPattern).ToKVString(); s != expected {
+		t.Errorf("expected %s, actual %s", expected, s)
+	}
+	miniTag := &MiniTag{MiniField: &MiniField{ServerName: f.ServerName, KeyPattern: f.KeyPattern}, Code: ServerName | KeyPattern}
+	if s := miniTag.ToKVString(); s != expected {
+		t.Errorf("expected %s, actual %s", expected, s)
+	}
+}
+
 func TestDirectionEnum(t *testing.T) {
 	clients := []DirectionEnum{ClientToServer, ClientNodeToServer, ClientHypervisorToServer, ClientGatewayHypervisorToServer, ClientGatewayToServer}
 	for _, c := range clients {